@@ -1,9 +1,10 @@
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::marker::PhantomData;
-use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::fd::{AsRawFd, RawFd};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use monitor_layout_engine::{
@@ -13,9 +14,10 @@ use monitor_layout_engine::{
 use tab_client::{
 	InputEvent as TabInputEvent, MonitorEvent as TabMonitorEvent, RenderEvent as TabRenderEvent,
 };
-use tab_client::{TabClient, TabClientConfig, TabClientError, TabSwapchain};
+use tab_client::{SyncFence, TabClient, TabClientConfig, TabClientError, TabSwapchain};
 pub use tab_protocol::{
-	AxisOrientation, AxisPhase, AxisSource, SessionCreatedPayload, SessionInfo, SessionRole,
+	AxisOrientation, AxisPhase, AxisSource, MonitorId, SessionCreatedPayload, SessionId, SessionInfo,
+	SessionRole,
 };
 use tab_protocol::{BufferIndex, ButtonState, InputEventPayload, KeyState, TouchContact};
 use thiserror::Error;
@@ -47,7 +49,7 @@ impl Config {
 	pub fn from_token(token: impl Into<String>) -> Self {
 		Self {
 			token: token.into(),
-			socket_path: tab_protocol::DEFAULT_SOCKET_PATH.into(),
+			socket_path: tab_protocol::default_socket_path(),
 			render_node_path: None,
 			render_mode: RenderMode::Scheduled,
 			opengl_es_version: (3, 0),
@@ -124,14 +126,14 @@ pub enum FrameworkError {
 	#[error("poll failed: {0}")]
 	Poll(std::io::Error),
 	#[error("monitor not found: {0}")]
-	MonitorNotFound(String),
+	MonitorNotFound(MonitorId),
 }
 
 /// Logical monitor metadata exposed to applications.
 #[derive(Debug, Clone)]
 pub struct Monitor {
 	/// Stable monitor identifier used by the protocol.
-	pub id: String,
+	pub id: MonitorId,
 	/// Human-readable monitor name.
 	pub name: String,
 	/// Logical width in pixels.
@@ -151,13 +153,13 @@ pub struct Monitor {
 impl Monitor {
 	fn from_tab_monitor(state: &tab_client::MonitorState) -> Self {
 		Self {
-			id: state.info.id.clone(),
+			id: state.info.id,
 			name: state.info.name.clone(),
 			width: state.info.width,
 			height: state.info.height,
 			refresh_rate: state.info.refresh_rate,
-			x: 0,
-			y: 0,
+			x: state.info.position.x,
+			y: state.info.position.y,
 			scale: 1.0,
 		}
 	}
@@ -173,29 +175,31 @@ impl Monitor {
 	}
 }
 
-fn recompute_layout(monitors: &mut HashMap<String, MonitorRuntime>) {
+fn recompute_layout(monitors: &mut HashMap<MonitorId, MonitorRuntime>) {
 	let specs: Vec<_> = monitors
 		.values()
 		.map(|m| MonitorSpec {
-			id: m.monitor.id.clone(),
+			id: m.monitor.id.to_string(),
 			width: m.monitor.width,
 			height: m.monitor.height,
 		})
 		.collect();
 	let placements = layout_horizontal(&specs);
 	for p in placements {
-		if let Some(m) = monitors.get_mut(&p.id) {
+		if let Ok(id) = p.id.parse::<MonitorId>()
+			&& let Some(m) = monitors.get_mut(&id)
+		{
 			m.monitor.x = p.x;
 			m.monitor.y = p.y;
 		}
 	}
 }
 
-fn current_layout(monitors: &HashMap<String, MonitorRuntime>) -> Vec<MonitorPlacement> {
+fn current_layout(monitors: &HashMap<MonitorId, MonitorRuntime>) -> Vec<MonitorPlacement> {
 	monitors
 		.values()
 		.map(|m| MonitorPlacement {
-			id: m.monitor.id.clone(),
+			id: m.monitor.id.to_string(),
 			x: m.monitor.x,
 			y: m.monitor.y,
 			width: m.monitor.width,
@@ -208,7 +212,7 @@ fn current_layout(monitors: &HashMap<String, MonitorRuntime>) -> Vec<MonitorPlac
 #[derive(Debug, Clone)]
 pub struct RenderEvent {
 	/// Target monitor id.
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	/// Acquired swapchain buffer index.
 	pub buffer_index: BufferIndex,
 	/// DMA-BUF file descriptor for the render target.
@@ -229,7 +233,7 @@ pub struct RenderEvent {
 #[derive(Debug, Clone)]
 pub struct PresentEvent {
 	/// Target monitor id.
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	/// Buffer index that reached presentation completion.
 	pub buffer_index: BufferIndex,
 }
@@ -241,11 +245,19 @@ pub struct MonitorAddedEvent {
 	pub monitor: Monitor,
 }
 
+/// Emitted when a monitor changes size or refresh rate in place. After a size change the
+/// framework already replaced its swapchain and scheduled a frame at the new size.
+#[derive(Debug, Clone)]
+pub struct MonitorResizedEvent {
+	/// Monitor metadata with the new size and refresh rate.
+	pub monitor: Monitor,
+}
+
 /// Emitted when a monitor is removed.
 #[derive(Debug, Clone)]
 pub struct MonitorRemovedEvent {
 	/// Removed monitor id.
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	/// Removed monitor name.
 	pub name: String,
 }
@@ -553,6 +565,8 @@ pub trait Application: Sized + 'static {
 	fn on_monitor_added(&mut self, _ctx: &mut Context<Self>, _ev: MonitorAddedEvent) {}
 	/// Called when a monitor is removed.
 	fn on_monitor_removed(&mut self, _ctx: &mut Context<Self>, _ev: MonitorRemovedEvent) {}
+	/// Called when a monitor changes size or refresh rate.
+	fn on_monitor_resized(&mut self, _ctx: &mut Context<Self>, _ev: MonitorResizedEvent) {}
 	/// Called when session state changes.
 	fn on_session_state(&mut self, _ctx: &mut Context<Self>, _ev: SessionEvent) {}
 	/// Called for every raw input event.
@@ -588,10 +602,10 @@ pub trait Application: Sized + 'static {
 /// Mutable runtime context passed into application callbacks.
 pub struct Context<'a, A: Application> {
 	client: &'a mut TabClient,
-	monitors: &'a mut HashMap<String, MonitorRuntime>,
-	scheduled: &'a mut HashSet<String>,
+	monitors: &'a mut HashMap<MonitorId, MonitorRuntime>,
+	scheduled: &'a mut HashSet<MonitorId>,
 	watched_fds: &'a mut HashSet<RawFd>,
-	next_acquire_fence: &'a mut Option<OwnedFd>,
+	next_acquire_fence: &'a mut Option<SyncFence>,
 	cursor_position: &'a mut (f64, f64),
 	exiting: &'a mut bool,
 	_marker: PhantomData<A>,
@@ -599,13 +613,13 @@ pub struct Context<'a, A: Application> {
 
 impl<'a, A: Application> Context<'a, A> {
 	/// Schedules a frame for a specific monitor.
-	pub fn schedule_frame(&mut self, monitor_id: impl Into<String>) {
-		self.scheduled.insert(monitor_id.into());
+	pub fn schedule_frame(&mut self, monitor_id: MonitorId) {
+		self.scheduled.insert(monitor_id);
 	}
 
 	/// Schedules a frame for every known monitor.
 	pub fn schedule_all_frames(&mut self) {
-		self.scheduled.extend(self.monitors.keys().cloned());
+		self.scheduled.extend(self.monitors.keys().copied());
 	}
 
 	/// Returns an iterator over all known monitors.
@@ -614,32 +628,33 @@ impl<'a, A: Application> Context<'a, A> {
 	}
 
 	/// Returns a monitor by id.
-	pub fn monitor(&self, monitor_id: &str) -> Option<&Monitor> {
-		self.monitors.get(monitor_id).map(|m| &m.monitor)
+	pub fn monitor(&self, monitor_id: MonitorId) -> Option<&Monitor> {
+		self.monitors.get(&monitor_id).map(|m| &m.monitor)
 	}
 
 	/// Sets monitor position in global layout space.
 	///
-	/// The resulting layout must remain edge-contiguous and non-overlapping.
+	/// The resulting layout must remain edge-contiguous and non-overlapping. Only the
+	/// framework's copy changes; it follows Shift's layout again once monitors move there.
 	pub fn set_monitor_position(
 		&mut self,
-		monitor_id: &str,
+		monitor_id: MonitorId,
 		x: i32,
 		y: i32,
 	) -> Result<(), FrameworkError> {
 		let old = {
-			let Some(m) = self.monitors.get(monitor_id) else {
-				return Err(FrameworkError::MonitorNotFound(monitor_id.to_string()));
+			let Some(m) = self.monitors.get(&monitor_id) else {
+				return Err(FrameworkError::MonitorNotFound(monitor_id));
 			};
 			(m.monitor.x, m.monitor.y)
 		};
-		if let Some(m) = self.monitors.get_mut(monitor_id) {
+		if let Some(m) = self.monitors.get_mut(&monitor_id) {
 			m.monitor.x = x;
 			m.monitor.y = y;
 		}
 		let placements = current_layout(self.monitors);
 		if !is_valid_edge_contiguous_layout(&placements) {
-			if let Some(m) = self.monitors.get_mut(monitor_id) {
+			if let Some(m) = self.monitors.get_mut(&monitor_id) {
 				m.monitor.x = old.0;
 				m.monitor.y = old.1;
 			}
@@ -655,6 +670,8 @@ impl<'a, A: Application> Context<'a, A> {
 	}
 
 	/// Recomputes monitor positions using default horizontal packing.
+	///
+	/// Like [`Self::set_monitor_position`], this only lasts until monitors move in Shift's layout.
 	pub fn apply_horizontal_layout(&mut self) {
 		recompute_layout(self.monitors);
 		let placements = current_layout(self.monitors);
@@ -684,8 +701,8 @@ impl<'a, A: Application> Context<'a, A> {
 	}
 
 	/// Sets an acquire fence to be sent with the next buffer request.
-	pub fn set_next_acquire_fence(&mut self, fence_fd: OwnedFd) {
-		*self.next_acquire_fence = Some(fence_fd);
+	pub fn set_next_acquire_fence(&mut self, fence: impl Into<SyncFence>) {
+		*self.next_acquire_fence = Some(fence.into());
 	}
 
 	/// Returns current authenticated session information.
@@ -718,7 +735,7 @@ impl<'a, A: Application> Context<'a, A> {
 	/// Requests switching to another session.
 	pub fn switch_session(
 		&mut self,
-		session_id: &str,
+		session_id: SessionId,
 		animation: Option<String>,
 		duration: Duration,
 	) -> Result<(), FrameworkError> {
@@ -741,12 +758,12 @@ pub struct TabAppFramework<A: Application> {
 	app: A,
 	client: TabClient,
 	render_mode: RenderMode,
-	monitors: HashMap<String, MonitorRuntime>,
-	scheduled: HashSet<String>,
+	monitors: HashMap<MonitorId, MonitorRuntime>,
+	scheduled: HashSet<MonitorId>,
 	watched_fds: HashSet<RawFd>,
 	event_queue: Rc<RefCell<VecDeque<QueuedEvent>>>,
 	exiting: bool,
-	next_acquire_fence: Option<OwnedFd>,
+	next_acquire_fence: Option<SyncFence>,
 	stats: LoopStats,
 	cursor_position: (f64, f64),
 	touch_contacts: HashMap<i32, (f64, f64)>,
@@ -774,10 +791,9 @@ impl<A: Application> TabAppFramework<A> {
 		let mut monitors = HashMap::new();
 		for tab_monitor in client.monitors() {
 			let monitor = Monitor::from_tab_monitor(tab_monitor);
-			let swapchain = client.create_swapchain(&monitor.id)?;
-			monitors.insert(monitor.id.clone(), MonitorRuntime::new(monitor, swapchain));
+			let swapchain = client.create_swapchain(monitor.id)?;
+			monitors.insert(monitor.id, MonitorRuntime::new(monitor, swapchain));
 		}
-		recompute_layout(&mut monitors);
 		let initial_cursor = {
 			let placements = current_layout(&monitors);
 			let seed = placements
@@ -914,6 +930,42 @@ impl<A: Application> TabAppFramework<A> {
 		Ok((tab_ready, ready_fds))
 	}
 
+	/// Links a swapchain of the monitor's new size and schedules a frame with it.
+	fn resize_monitor(&mut self, state: &tab_client::MonitorState) -> Result<(), FrameworkError> {
+		let Some(runtime) = self.monitors.get(&state.info.id) else {
+			return Ok(());
+		};
+		let mut monitor = runtime.monitor.clone();
+		monitor.width = state.info.width;
+		monitor.height = state.info.height;
+		monitor.refresh_rate = state.info.refresh_rate;
+		monitor.x = state.info.position.x;
+		monitor.y = state.info.position.y;
+		let swapchain = self.client.create_swapchain(monitor.id)?;
+		self
+			.monitors
+			.insert(monitor.id, MonitorRuntime::new(monitor.clone(), swapchain));
+		let placements = current_layout(&self.monitors);
+		self.cursor_position =
+			clamp_point_to_layout(&placements, self.cursor_position.0, self.cursor_position.1);
+		// The old frame is only scaled until a buffer of the new size is presented.
+		self.scheduled.insert(monitor.id);
+		let monitor = self
+			.monitors
+			.get(&monitor.id)
+			.map(|m| m.monitor.clone())
+			.unwrap_or(monitor);
+		self.call_app(|app, ctx| {
+			app.on_monitor_resized(
+				ctx,
+				MonitorResizedEvent {
+					monitor: monitor.clone(),
+				},
+			)
+		});
+		Ok(())
+	}
+
 	fn drain_tab_events(&mut self) -> Result<(), FrameworkError> {
 		loop {
 			let maybe_event = self.event_queue.borrow_mut().pop_front();
@@ -924,15 +976,13 @@ impl<A: Application> TabAppFramework<A> {
 				QueuedEvent::Monitor(ev) => match ev {
 					TabMonitorEvent::Added(state) => {
 						let monitor = Monitor::from_tab_monitor(&state);
-						let swapchain = self.client.create_swapchain(&monitor.id)?;
+						let swapchain = self.client.create_swapchain(monitor.id)?;
 						if self.render_mode == RenderMode::Eager {
-							self.scheduled.insert(monitor.id.clone());
+							self.scheduled.insert(monitor.id);
 						}
-						self.monitors.insert(
-							monitor.id.clone(),
-							MonitorRuntime::new(monitor.clone(), swapchain),
-						);
-						recompute_layout(&mut self.monitors);
+						self
+							.monitors
+							.insert(monitor.id, MonitorRuntime::new(monitor.clone(), swapchain));
 						let placements = current_layout(&self.monitors);
 						self.cursor_position =
 							clamp_point_to_layout(&placements, self.cursor_position.0, self.cursor_position.1);
@@ -952,7 +1002,6 @@ impl<A: Application> TabAppFramework<A> {
 					}
 					TabMonitorEvent::Removed { monitor_id, name } => {
 						self.monitors.remove(&monitor_id);
-						recompute_layout(&mut self.monitors);
 						let placements = current_layout(&self.monitors);
 						self.cursor_position =
 							clamp_point_to_layout(&placements, self.cursor_position.0, self.cursor_position.1);
@@ -961,34 +1010,73 @@ impl<A: Application> TabAppFramework<A> {
 							app.on_monitor_removed(
 								ctx,
 								MonitorRemovedEvent {
-									monitor_id: monitor_id.clone(),
+									monitor_id,
 									name: name.clone(),
 								},
 							)
 						});
 					}
+					TabMonitorEvent::Resized { state, .. } => self.resize_monitor(&state)?,
+					TabMonitorEvent::ModeChanged(state) => {
+						let Some(runtime) = self.monitors.get_mut(&state.info.id) else {
+							continue;
+						};
+						let monitor = &runtime.monitor;
+						if (monitor.width, monitor.height) != (state.info.width, state.info.height) {
+							// The new size is normally announced with `Resized` first.
+							self.resize_monitor(&state)?;
+						} else if monitor.refresh_rate != state.info.refresh_rate {
+							runtime.monitor.refresh_rate = state.info.refresh_rate;
+							let monitor = runtime.monitor.clone();
+							self.call_app(|app, ctx| {
+								app.on_monitor_resized(
+									ctx,
+									MonitorResizedEvent {
+										monitor: monitor.clone(),
+									},
+								)
+							});
+						}
+					}
+					TabMonitorEvent::LayoutChanged(placements) => {
+						// Shift's layout is the one the pointer moves across.
+						for placement in placements {
+							if let Some(runtime) = self.monitors.get_mut(&placement.monitor_id) {
+								runtime.monitor.x = placement.position.x;
+								runtime.monitor.y = placement.position.y;
+							}
+						}
+						let placements = current_layout(&self.monitors);
+						self.cursor_position =
+							clamp_point_to_layout(&placements, self.cursor_position.0, self.cursor_position.1);
+					}
+					TabMonitorEvent::PrimaryChanged(_)
+					| TabMonitorEvent::ConnectorProperties(_)
+					| TabMonitorEvent::Captured { .. }
+					| TabMonitorEvent::VirtualCreated(_)
+					| TabMonitorEvent::ScreencastStarted(_)
+					| TabMonitorEvent::ScreencastStopped(_) => {}
 				},
 				QueuedEvent::Render(ev) => {
-					self.stats.buffer_release_events += 1;
 					let TabRenderEvent::BufferReleased {
 						monitor_id,
 						buffer,
-						release_fence_fd,
-					} = ev;
+						release_fence,
+					} = ev
+					else {
+						// Stats events are only meaningful to admin tooling.
+						continue;
+					};
+					self.stats.buffer_release_events += 1;
 					self.stats.instant_log(&format!(
 						"buffer_release event monitor={monitor_id} buffer={} fence={}",
 						buffer as u8,
-						if release_fence_fd.is_some() {
-							"yes"
-						} else {
-							"no"
-						}
+						if release_fence.is_some() { "yes" } else { "no" }
 					));
 					let mut should_emit_present = false;
 					if let Some(monitor) = self.monitors.get_mut(&monitor_id) {
-						if let Some(fd) = release_fence_fd {
-							monitor.pending_release_fences[buffer as usize] =
-								Some(unsafe { OwnedFd::from_raw_fd(fd) });
+						if let Some(fence) = release_fence {
+							monitor.pending_release_fences[buffer as usize] = Some(fence);
 						} else {
 							if monitor.pending_present[buffer as usize] {
 								monitor.pending_present[buffer as usize] = false;
@@ -996,7 +1084,7 @@ impl<A: Application> TabAppFramework<A> {
 							}
 							monitor.swapchain.mark_released(buffer);
 							if self.render_mode == RenderMode::Eager {
-								self.scheduled.insert(monitor_id.clone());
+								self.scheduled.insert(monitor_id);
 							}
 						}
 					}
@@ -1006,7 +1094,7 @@ impl<A: Application> TabAppFramework<A> {
 							app.on_present(
 								ctx,
 								PresentEvent {
-									monitor_id: monitor_id.clone(),
+									monitor_id,
 									buffer_index: buffer,
 								},
 							)
@@ -1443,7 +1531,7 @@ impl<A: Application> TabAppFramework<A> {
 				let (buffer, buffer_idx) = monitor_rt.swapchain.acquire_next()?;
 				self.stats.acquire_ok += 1;
 				let render_ev = RenderEvent {
-					monitor_id: monitor_id.clone(),
+					monitor_id,
 					buffer_index: buffer_idx,
 					dmabuf_fd: buffer.fd(),
 					width: buffer.width(),
@@ -1459,33 +1547,36 @@ impl<A: Application> TabAppFramework<A> {
 			};
 			self.next_acquire_fence = None;
 			self.call_app(|app, ctx| app.on_render(ctx, render_ev.clone()));
-			let acquire_fence = self.next_acquire_fence.as_ref().map(|fd| fd.as_raw_fd());
+			let acquire_fence = self.next_acquire_fence.take();
 			self.stats.instant_log(&format!(
 				"request_buffer send monitor={monitor_id} buffer={} fence={}",
 				buffer_idx as u8,
 				acquire_fence
-					.map(|fd| fd.to_string())
+					.as_ref()
+					.map(|fence| fence.as_raw_fd().to_string())
 					.unwrap_or_else(|| "none".to_string())
 			));
 
-			match self
-				.client
-				.request_buffer(&monitor_id, buffer_idx, acquire_fence)
-			{
+			let Some(monitor_rt) = self.monitors.get_mut(&monitor_id) else {
+				continue;
+			};
+			match self.client.present(
+				&mut monitor_rt.swapchain,
+				buffer_idx,
+				acquire_fence.as_ref(),
+				None,
+			) {
 				Ok(()) => {
 					self.stats.request_ok += 1;
 					self.stats.instant_log(&format!(
 						"request_buffer ack monitor={monitor_id} buffer={}",
 						buffer_idx as u8
 					));
-					if let Some(monitor_rt) = self.monitors.get_mut(&monitor_id) {
-						monitor_rt.swapchain.mark_busy(buffer_idx);
-						monitor_rt.pending_present[buffer_idx as usize] = true;
-					}
+					monitor_rt.pending_present[buffer_idx as usize] = true;
 					if self.render_mode == RenderMode::Eager {
 						// Keep requesting while another client-owned buffer exists.
 						// This avoids deadlocking on the first frame in double-buffering.
-						self.scheduled.insert(monitor_id.clone());
+						self.scheduled.insert(monitor_id);
 					}
 				}
 				Err(err) => {
@@ -1494,9 +1585,7 @@ impl<A: Application> TabAppFramework<A> {
 						"request_buffer err monitor={monitor_id} buffer={} err={}",
 						buffer_idx as u8, err
 					));
-					if let Some(monitor_rt) = self.monitors.get_mut(&monitor_id) {
-						monitor_rt.swapchain.rollback();
-					}
+					monitor_rt.swapchain.rollback();
 					if self.render_mode == RenderMode::Eager {
 						let err_text = err.to_string();
 						let ownership_related = err_text.contains("ownership_violation")
@@ -1504,7 +1593,7 @@ impl<A: Application> TabAppFramework<A> {
 							|| err_text.contains("session_sleeping")
 							|| err_text.contains("not client-owned");
 						if !ownership_related {
-							self.scheduled.insert(monitor_id.clone());
+							self.scheduled.insert(monitor_id);
 						}
 					}
 					let ferr: FrameworkError = err.into();
@@ -1524,10 +1613,10 @@ impl<A: Application> TabAppFramework<A> {
 				let Some(fence) = monitor_rt.pending_release_fences[buffer_idx].as_ref() else {
 					continue;
 				};
-				let signaled = match fd_readable_now(fence) {
+				let signaled = match fence.is_signaled() {
 					Ok(v) => v,
 					Err(err) => {
-						errors.push(err);
+						errors.push(FrameworkError::Poll(err));
 						true
 					}
 				};
@@ -1547,12 +1636,12 @@ impl<A: Application> TabAppFramework<A> {
 					if monitor_rt.pending_present[buffer_idx] {
 						monitor_rt.pending_present[buffer_idx] = false;
 						presents.push(PresentEvent {
-							monitor_id: monitor_rt.monitor.id.clone(),
+							monitor_id: monitor_rt.monitor.id,
 							buffer_index: buffer,
 						});
 					}
 					if self.render_mode == RenderMode::Eager {
-						ready_monitors.push(monitor_rt.monitor.id.clone());
+						ready_monitors.push(monitor_rt.monitor.id);
 					}
 				}
 			}
@@ -1736,7 +1825,7 @@ impl LoopStats {
 struct MonitorRuntime {
 	monitor: Monitor,
 	swapchain: TabSwapchain,
-	pending_release_fences: [Option<OwnedFd>; 2],
+	pending_release_fences: [Option<Arc<SyncFence>>; 2],
 	pending_present: [bool; 2],
 }
 
@@ -1758,27 +1847,3 @@ enum QueuedEvent {
 	Input(TabInputEvent),
 	Session(tab_client::SessionEvent),
 }
-
-fn fd_readable_now(fd: &OwnedFd) -> Result<bool, FrameworkError> {
-	let mut pfd = libc::pollfd {
-		fd: std::os::fd::AsRawFd::as_raw_fd(fd),
-		events: libc::POLLIN | libc::POLLERR | libc::POLLHUP,
-		revents: 0,
-	};
-	loop {
-		let rc = unsafe { libc::poll(&mut pfd as *mut libc::pollfd, 1, 0) };
-		if rc > 0 {
-			return Ok(
-				(pfd.revents & (libc::POLLIN | libc::POLLERR | libc::POLLHUP | libc::POLLNVAL)) != 0,
-			);
-		}
-		if rc == 0 {
-			return Ok(false);
-		}
-		let err = std::io::Error::last_os_error();
-		if err.kind() == std::io::ErrorKind::Interrupted {
-			continue;
-		}
-		return Err(FrameworkError::Poll(err));
-	}
-}
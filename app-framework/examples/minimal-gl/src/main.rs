@@ -46,7 +46,7 @@ impl GlApplication for App {
 			gl.clear(glow::COLOR_BUFFER_BIT);
 		}
 
-		let Some(monitor) = ctx.monitor(&ev.monitor_id) else {
+		let Some(monitor) = ctx.monitor(ev.monitor_id) else {
 			return;
 		};
 		let (cursor_x, cursor_y) = ctx.cursor_position();
@@ -34,6 +34,13 @@ pub trait GlApplication: Sized + 'static {
 		_ev: core::MonitorRemovedEvent,
 	) {
 	}
+	/// Called when a monitor changes size; its cached render targets are already released.
+	fn on_monitor_resized(
+		&mut self,
+		_ctx: &mut GlEventContext<'_, '_, Self>,
+		_ev: core::MonitorResizedEvent,
+	) {
+	}
 	/// Called when session state updates arrive.
 	fn on_session_state(&mut self, _ctx: &mut GlEventContext<'_, '_, Self>, _ev: core::SessionEvent) {
 	}
@@ -117,7 +124,7 @@ pub struct GlEventContext<'c, 'g, A: GlApplication> {
 
 impl<'c, 'g, A: GlApplication> GlEventContext<'c, 'g, A> {
 	/// Schedules a frame for a specific monitor.
-	pub fn schedule_frame(&mut self, monitor_id: impl Into<String>) {
+	pub fn schedule_frame(&mut self, monitor_id: core::MonitorId) {
 		self.core.schedule_frame(monitor_id);
 	}
 
@@ -152,14 +159,14 @@ impl<'c, 'g, A: GlApplication> GlEventContext<'c, 'g, A> {
 	}
 
 	/// Returns monitor metadata by id.
-	pub fn monitor(&self, monitor_id: &str) -> Option<&core::Monitor> {
+	pub fn monitor(&self, monitor_id: core::MonitorId) -> Option<&core::Monitor> {
 		self.core.monitor(monitor_id)
 	}
 
 	/// Sets monitor position in the global monitor layout.
 	pub fn set_monitor_position(
 		&mut self,
-		monitor_id: &str,
+		monitor_id: core::MonitorId,
 		x: i32,
 		y: i32,
 	) -> Result<(), core::FrameworkError> {
@@ -218,7 +225,7 @@ impl<'c, 'g, A: GlApplication> GlEventContext<'c, 'g, A> {
 	/// Requests switching to another session.
 	pub fn switch_session(
 		&mut self,
-		session_id: &str,
+		session_id: core::SessionId,
 		animation: Option<String>,
 		duration: Duration,
 	) -> Result<(), core::FrameworkError> {
@@ -308,7 +315,7 @@ impl<A: GlApplication> core::Application for GlBridge<A> {
 	}
 
 	fn on_monitor_removed(&mut self, ctx: &mut core::Context<Self>, ev: core::MonitorRemovedEvent) {
-		self.gl.release_monitor_targets(&ev.monitor_id);
+		self.gl.release_monitor_targets(ev.monitor_id);
 		let mut ctx = GlEventContext {
 			core: ctx,
 			gl: &mut self.gl,
@@ -316,6 +323,15 @@ impl<A: GlApplication> core::Application for GlBridge<A> {
 		self.app.on_monitor_removed(&mut ctx, ev);
 	}
 
+	fn on_monitor_resized(&mut self, ctx: &mut core::Context<Self>, ev: core::MonitorResizedEvent) {
+		self.gl.release_monitor_targets(ev.monitor.id);
+		let mut ctx = GlEventContext {
+			core: ctx,
+			gl: &mut self.gl,
+		};
+		self.app.on_monitor_resized(&mut ctx, ev);
+	}
+
 	fn on_session_state(&mut self, ctx: &mut core::Context<Self>, ev: core::SessionEvent) {
 		let mut ctx = GlEventContext {
 			core: ctx,
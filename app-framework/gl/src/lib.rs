@@ -17,7 +17,9 @@ use glow::HasContext;
 use thiserror::Error;
 
 pub use framework::{GlApplication, GlEventContext, GlInitContext, GlTabAppFramework};
-pub use tab_app_framework_core::{SessionCreatedPayload, SessionInfo, SessionRole};
+pub use tab_app_framework_core::{
+	MonitorId, SessionCreatedPayload, SessionId, SessionInfo, SessionRole,
+};
 
 /// Requested OpenGL ES version.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -313,7 +315,7 @@ impl GlContext {
 		&mut self,
 		ev: &tab_app_framework_core::RenderEvent,
 	) -> Result<(), GlError> {
-		let key = RenderTargetKey::new(&ev.monitor_id, ev.buffer_index as u8);
+		let key = RenderTargetKey::new(ev.monitor_id, ev.buffer_index as u8);
 		if !self.dmabuf_targets.contains_key(&key) {
 			let target = self.import_target(ev)?;
 			self.dmabuf_targets.insert(key.clone(), target);
@@ -333,7 +335,7 @@ impl GlContext {
 	}
 
 	/// Releases cached render targets for a monitor.
-	pub fn release_monitor_targets(&mut self, monitor_id: &str) {
+	pub fn release_monitor_targets(&mut self, monitor_id: MonitorId) {
 		let keys: Vec<_> = self
 			.dmabuf_targets
 			.keys()
@@ -699,14 +701,14 @@ fn load_symbol(lib: &libloading::Library, name: &str) -> Option<*const c_void> {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct RenderTargetKey {
-	monitor_id: String,
+	monitor_id: MonitorId,
 	buffer_index: u8,
 }
 
 impl RenderTargetKey {
-	fn new(monitor_id: &str, buffer_index: u8) -> Self {
+	fn new(monitor_id: MonitorId, buffer_index: u8) -> Self {
 		Self {
-			monitor_id: monitor_id.to_string(),
+			monitor_id,
 			buffer_index,
 		}
 	}
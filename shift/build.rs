@@ -22,10 +22,24 @@ fn main() {
 			"EGL_MESA_image_dma_buf_export",
 			"EGL_KHR_surfaceless_context",
 			"EGL_ANDROID_native_fence_sync",
+			"EGL_EXT_buffer_age",
 		],
 	)
 	.write_bindings(gl_generator::StructGenerator, &mut egl_file)
 	.unwrap();
+
+	// GLES bindings for what EasyDRM's GL bindings lack: debug output and context queries
+	let gles_path = out_dir.join("gles_bindings.rs");
+	let mut gles_file = File::create(&gles_path).unwrap();
+	Registry::new(
+		Api::Gles2,
+		(3, 2),
+		Profile::Core,
+		Fallbacks::All,
+		&["GL_KHR_debug", "GL_KHR_robustness"],
+	)
+	.write_bindings(gl_generator::StructGenerator, &mut gles_file)
+	.unwrap();
 	#[cfg(target_vendor = "ardos")]
 	{
 		println!("cargo::rustc-link-lib=png");
@@ -4,4 +4,6 @@ pub enum Error {
 	InvalidToken,
 	#[error("no session was found that matches the requested token")]
 	NotFound,
+	#[error("the session's role may not authenticate through this socket")]
+	RoleNotAdmitted,
 }
@@ -1,14 +1,19 @@
 use std::{
 	fmt::{Debug, Display},
-	os::{fd::AsRawFd, unix::net::UnixStream},
+	os::{
+		fd::{AsRawFd, FromRawFd, OwnedFd},
+		unix::net::UnixStream,
+	},
 	sync::Arc,
 };
 
 use tab_protocol::{
-	AuthErrorPayload, AuthOkPayload, ErrorPayload, MonitorAddedPayload, MonitorRemovedPayload,
-	SessionActivePayload, SessionAwakePayload, SessionCreatedPayload, SessionInfo,
-	SessionSleepPayload, SessionStatePayload, TabMessage, TabMessageFrame, TabMessageFrameReader,
-	message_header,
+	AuthErrorPayload, AuthOkPayload, BufferHashPayload, ErrorPayload, InputEventPayload,
+	MonitorAddedPayload, MonitorLayoutPayload, MonitorModeChangedPayload, MonitorRemovedPayload,
+	MonitorResizedPayload, PrimaryMonitorPayload, ProtocolError, ReleaseBuffersPayload,
+	RemoteControlStatePayload, SessionActivePayload, SessionAwakePayload, SessionCreatedPayload,
+	SessionInfo, SessionSleepPayload, SessionStatePayload, TabFrameEncoder, TabMessage,
+	TabMessageFrame, TabMessageFrameReader, message_header,
 };
 use tokio::{io::unix::AsyncFd, task::JoinHandle};
 use tracing::{Instrument, Span};
@@ -19,10 +24,14 @@ use crate::{
 	comms::{
 		client2server::{C2SMsg, C2STx},
 		server2client::S2CMsg,
+		trace::FrameId,
 	},
 	define_id_type,
-	monitor::{Monitor, MonitorId},
-	sessions::{Role, Session, SessionId},
+	monitor::Monitor,
+	sessions::{
+		Session, SessionId,
+		permissions::{self, Permission, SocketRestrictions},
+	},
 };
 pub type AsyncUnixStream = AsyncFd<UnixStream>;
 
@@ -30,26 +39,33 @@ pub struct Client {
 	id: ClientId,
 	socket: AsyncUnixStream,
 	frame_reader: TabMessageFrameReader,
+	/// Reused for input events, which arrive at up to the mouse's polling rate.
+	input_encoder: TabFrameEncoder,
 	channel_client_end: ChannelsClientEnd,
 	connected_session: Option<Arc<Session>>,
 	shutdown: bool,
 	initial_monitors: Vec<Monitor>,
+	/// Restrictions of the socket the client connected through.
+	socket_restrictions: Arc<SocketRestrictions>,
 }
 
 impl Client {
 	pub fn wrap_socket(
 		socket: AsyncUnixStream,
 		initial_monitors: Vec<Monitor>,
+		socket_restrictions: Arc<SocketRestrictions>,
 	) -> (Self, ClientView) {
 		let channels = client_view::Channels::new();
 		let client = Self {
 			socket,
 			frame_reader: TabMessageFrameReader::new(),
+			input_encoder: TabFrameEncoder::new(),
 			id: ClientId::rand(),
 			channel_client_end: channels.client_end,
 			connected_session: None,
 			shutdown: false,
 			initial_monitors,
+			socket_restrictions,
 		};
 		let client_view = ClientView::from_client(&client, channels.server_end);
 		(client, client_view)
@@ -59,22 +75,43 @@ impl Client {
 	}
 	#[tracing::instrument(level = "error", skip(self), fields(client.id = self.id().to_string()))]
 	async fn send_error(&self, code: &str, error: Option<impl Display + Debug>) {
-		tracing::warn!("sending error to the client");
-		let tab_message = TabMessageFrame::json(
-			message_header::ERROR,
-			ErrorPayload {
+		self
+			.send_error_payload(ErrorPayload {
 				code: code.into(),
-				message: error.as_ref().map(|e| e.to_string()),
-			},
-		);
+				message: error.map(|e| e.to_string()),
+				permission: None,
+			})
+			.await;
+	}
+	#[tracing::instrument(level = "error", skip(self), fields(client.id = self.id().to_string()))]
+	async fn send_error_payload(&self, payload: ErrorPayload) {
+		tracing::warn!("sending error to the client");
+		let message = payload.message.clone();
+		let tab_message = TabMessageFrame::json(message_header::ERROR, payload);
 		let result = tab_message.send_frame_to_async_fd(&self.socket).await;
 		if let Err(e) = result {
-			tracing::warn!(
-				"failed to send error message to client {:?}: {e}",
-				error.map(|e| e.to_string())
-			);
+			tracing::warn!("failed to send error message to client {message:?}: {e}");
 		}
 	}
+	async fn send_permission_denied(&self, permission: Permission) {
+		let message = match self.connected_session.as_deref() {
+			_ if !self.socket_restrictions.allows(permission) => {
+				format!("`{permission}` commands are not available through this socket")
+			}
+			Some(session) => format!(
+				"the {:?} role does not have the `{permission}` permission",
+				session.role()
+			),
+			None => format!("you need to authenticate before using `{permission}` commands"),
+		};
+		self
+			.send_error_payload(ErrorPayload {
+				code: "forbidden".into(),
+				message: Some(message),
+				permission: Some(permission.as_str().into()),
+			})
+			.await;
+	}
 	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
 	async fn send_auth_error(&mut self, cause: impl Display + Debug) {
 		let tab_message = TabMessageFrame::json(
@@ -100,43 +137,6 @@ impl Client {
 	}
 	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
 	async fn handle_packet(&mut self, tab_message: TabMessage) {
-		macro_rules! check_admin {
-			($action:literal) => {
-				if !self
-					.connected_session
-					.as_deref()
-					.is_some_and(|session| session.role() == Role::Admin)
-				{
-					self
-						.send_error(
-							"forbidden",
-							Some(format!(
-								"you need to authenticate as an admin client before being able to {}",
-								$action
-							)),
-						)
-						.await;
-					return;
-				};
-			};
-		}
-
-		macro_rules! check_session {
-			($action:literal, $var:ident) => {
-				let Some($var) = self.connected_session.as_deref() else {
-					self
-						.send_error(
-							"forbidden",
-							Some(format!(
-								"you need to authenticate before being able to {}",
-								$action
-							)),
-						)
-						.await;
-					return;
-				};
-			};
-		}
 		macro_rules! send_server_msg {
 			($send:expr) => {
 				let send_result = self.channel_client_end.to_server().send($send).await;
@@ -147,6 +147,16 @@ impl Client {
 				}
 			};
 		}
+		if let Some(permission) = permissions::required_for(&tab_message) {
+			let session = self
+				.connected_session
+				.as_deref()
+				.map(|session| (session.id(), session.role()));
+			if !permissions::check(self.id(), session, &self.socket_restrictions, permission) {
+				self.send_permission_denied(permission).await;
+				return;
+			}
+		}
 		match tab_message {
 			TabMessage::Auth(auth) => {
 				let token = auth.token.parse::<Token>();
@@ -162,35 +172,128 @@ impl Client {
 				send_server_msg!(C2SMsg::Auth(token));
 			}
 			TabMessage::SessionSwitch(session_switch_payload) => {
-				check_admin!("switch session");
 				send_server_msg!(C2SMsg::SwitchSession(session_switch_payload));
 			}
 			TabMessage::BufferRequest {
 				payload,
 				acquire_fence,
 			} => {
-				let monitor_id = payload.monitor_id.parse::<MonitorId>();
-				let monitor_id = match monitor_id {
-					Ok(monitor_id) => monitor_id,
-					Err(error) => {
-						return self
-							.send_error(
-								"unknown_monitor",
-								Some(format!("monitor id parse error: {error:?}")),
-							)
-							.await;
-					}
-				};
+				let frame_id = FrameId::next();
+				tracing::debug!(%frame_id, monitor_id = %payload.monitor_id, buffer = payload.buffer as u8, "buffer request");
 				send_server_msg!(C2SMsg::BufferRequest {
-					monitor_id: monitor_id,
+					monitor_id: payload.monitor_id,
 					buffer: payload.buffer,
 					acquire_fence,
+					present_at: payload.present_at,
+					frame_id,
 				});
 			}
 			TabMessage::SessionCreate(session_create_req) => {
-				check_admin!("create a session");
 				send_server_msg!(C2SMsg::CreateSession(session_create_req));
 			}
+			TabMessage::FrameTrace(frame_trace_payload) => {
+				send_server_msg!(C2SMsg::FrameTrace(frame_trace_payload));
+			}
+			TabMessage::ColorFilter(color_filter_payload) => {
+				send_server_msg!(C2SMsg::ColorFilter(color_filter_payload));
+			}
+			TabMessage::Magnifier(magnifier_payload) => {
+				send_server_msg!(C2SMsg::Magnifier(magnifier_payload));
+			}
+			TabMessage::DisplayAdjust(display_adjust_payload) => {
+				send_server_msg!(C2SMsg::DisplayAdjust(display_adjust_payload));
+			}
+			TabMessage::ScreenRecord(screen_record_payload) => {
+				send_server_msg!(C2SMsg::ScreenRecord(screen_record_payload));
+			}
+			TabMessage::Screenshot(screenshot_payload) => {
+				send_server_msg!(C2SMsg::Screenshot(screenshot_payload));
+			}
+			TabMessage::VirtualMonitorCreate(virtual_monitor_create_payload) => {
+				send_server_msg!(C2SMsg::VirtualMonitorCreate(virtual_monitor_create_payload));
+			}
+			TabMessage::VirtualMonitorRemove(virtual_monitor_remove_payload) => {
+				send_server_msg!(C2SMsg::VirtualMonitorRemove(virtual_monitor_remove_payload));
+			}
+			TabMessage::ScreencastStart(screencast_start_payload) => {
+				send_server_msg!(C2SMsg::ScreencastStart(screencast_start_payload));
+			}
+			TabMessage::ScreencastStop(screencast_stop_payload) => {
+				send_server_msg!(C2SMsg::ScreencastStop(screencast_stop_payload));
+			}
+			TabMessage::RemoteControl(remote_control_payload) => {
+				send_server_msg!(C2SMsg::RemoteControl(remote_control_payload));
+			}
+			TabMessage::InputInject(InputEventPayload::Unknown) => {
+				self
+					.send_error("invalid_payload", Some("unknown input event kind"))
+					.await;
+			}
+			TabMessage::InputInject(input_event_payload) => {
+				send_server_msg!(C2SMsg::InputInject(input_event_payload));
+			}
+			TabMessage::MonitorEnable(monitor_enable_payload) => {
+				send_server_msg!(C2SMsg::MonitorEnable(monitor_enable_payload));
+			}
+			TabMessage::SetPrimaryMonitor(set_primary_monitor_payload) => {
+				send_server_msg!(C2SMsg::SetPrimaryMonitor(set_primary_monitor_payload));
+			}
+			TabMessage::IdleInhibit(idle_inhibit_payload) => {
+				send_server_msg!(C2SMsg::IdleInhibit(idle_inhibit_payload));
+			}
+			TabMessage::RenderQuality(render_quality_payload) => {
+				send_server_msg!(C2SMsg::RenderQuality(render_quality_payload));
+			}
+			TabMessage::GetSessionMetadata(get_session_metadata_payload) => {
+				send_server_msg!(C2SMsg::GetSessionMetadata(get_session_metadata_payload));
+			}
+			TabMessage::GetConnectorProperties(get_connector_properties_payload) => {
+				send_server_msg!(C2SMsg::GetConnectorProperties(
+					get_connector_properties_payload
+				));
+			}
+			TabMessage::OverlayRegions(overlay_regions_payload) => {
+				send_server_msg!(C2SMsg::OverlayRegions(overlay_regions_payload));
+			}
+			TabMessage::MonitorProfile(monitor_profile_payload) => {
+				send_server_msg!(C2SMsg::MonitorProfile(monitor_profile_payload));
+			}
+			TabMessage::SetWallpaper(set_wallpaper_payload) => {
+				send_server_msg!(C2SMsg::SetWallpaper(set_wallpaper_payload));
+			}
+			TabMessage::SetColorProfile(set_color_profile_payload) => {
+				send_server_msg!(C2SMsg::SetColorProfile(set_color_profile_payload));
+			}
+			TabMessage::SetVrr(set_vrr_payload) => {
+				send_server_msg!(C2SMsg::SetVrr(set_vrr_payload));
+			}
+			TabMessage::SetOutputTransform(set_output_transform_payload) => {
+				send_server_msg!(C2SMsg::SetOutputTransform(set_output_transform_payload));
+			}
+			TabMessage::SetMonitorScale(set_monitor_scale_payload) => {
+				send_server_msg!(C2SMsg::SetMonitorScale(set_monitor_scale_payload));
+			}
+			TabMessage::SetMonitorMode(set_monitor_mode_payload) => {
+				send_server_msg!(C2SMsg::SetMonitorMode(set_monitor_mode_payload));
+			}
+			TabMessage::SetMonitorPosition(set_monitor_position_payload) => {
+				send_server_msg!(C2SMsg::SetMonitorPosition(set_monitor_position_payload));
+			}
+			TabMessage::SetColorTemperature(set_color_temperature_payload) => {
+				send_server_msg!(C2SMsg::SetColorTemperature(set_color_temperature_payload));
+			}
+			TabMessage::SetCursor { payload, pixels } => {
+				send_server_msg!(C2SMsg::SetCursor { payload, pixels });
+			}
+			TabMessage::LatencyMode(latency_mode_payload) => {
+				send_server_msg!(C2SMsg::LatencyMode(latency_mode_payload));
+			}
+			TabMessage::SessionUpdate(session_update_payload) => {
+				send_server_msg!(C2SMsg::SessionUpdate(session_update_payload));
+			}
+			TabMessage::AudioClients(audio_clients_payload) => {
+				send_server_msg!(C2SMsg::AudioClients(audio_clients_payload));
+			}
 			TabMessage::Ping => {
 				tracing::debug!("received ping");
 
@@ -201,13 +304,13 @@ impl Client {
 					tracing::warn!("failed to send pong message back: {e}");
 					return;
 				}
+				send_server_msg!(C2SMsg::Alive);
 			}
 			TabMessage::FramebufferLink {
 				payload: fb_info,
 				dma_bufs,
 			} => {
 				tracing::debug!(?fb_info, ?dma_bufs, "received link framebuffer request");
-				check_session!("link framebuffer", _session);
 				send_server_msg!(C2SMsg::FramebufferLink {
 					payload: fb_info,
 					dma_bufs
@@ -218,6 +321,10 @@ impl Client {
 			TabMessage::AuthOk(_auth_ok_payload) => self.handle_unknown_msg("AuthOk").await,
 			TabMessage::AuthError(_auth_error_payload) => self.handle_unknown_msg("AuthError").await,
 			TabMessage::BufferRelease { .. } => self.handle_unknown_msg("BufferRelease").await,
+			TabMessage::BufferHash(_) => self.handle_unknown_msg("BufferHash").await,
+			TabMessage::SessionFrameStats(_) => self.handle_unknown_msg("SessionFrameStats").await,
+			TabMessage::PresentationFeedback(_) => self.handle_unknown_msg("PresentationFeedback").await,
+			TabMessage::ChannelStats(_) => self.handle_unknown_msg("ChannelStats").await,
 			TabMessage::BufferRequestAck(_buffer_request_ack_payload) => {
 				self.handle_unknown_msg("BufferRequestAck").await
 			}
@@ -228,6 +335,15 @@ impl Client {
 			TabMessage::MonitorRemoved(_monitor_removed_payload) => {
 				self.handle_unknown_msg("MonitorRemoved").await
 			}
+			TabMessage::MonitorResized(_monitor_resized_payload) => {
+				self.handle_unknown_msg("MonitorResized").await
+			}
+			TabMessage::MonitorModeChanged(_monitor_mode_changed_payload) => {
+				self.handle_unknown_msg("MonitorModeChanged").await
+			}
+			TabMessage::MonitorLayout(_monitor_layout_payload) => {
+				self.handle_unknown_msg("MonitorLayout").await
+			}
 			TabMessage::SessionCreated(_session_created_payload) => {
 				self.handle_unknown_msg("SessionCreated").await
 			}
@@ -242,10 +358,36 @@ impl Client {
 			}
 			TabMessage::SessionAwake(_payload) => self.handle_unknown_msg("SessionAwake").await,
 			TabMessage::SessionSleep(_payload) => self.handle_unknown_msg("SessionSleep").await,
+			TabMessage::ReleaseBuffers(_payload) => self.handle_unknown_msg("ReleaseBuffers").await,
 			TabMessage::Error(_error_payload) => self.handle_unknown_msg("Error").await,
 			TabMessage::Pong => self.handle_unknown_msg("Pong").await,
+			TabMessage::FrameStats(_frame_stats_payload) => self.handle_unknown_msg("FrameStats").await,
+			TabMessage::RenderCacheStats(_render_cache_stats_payload) => {
+				self.handle_unknown_msg("RenderCacheStats").await
+			}
+			TabMessage::PrimaryMonitor(_primary_monitor_payload) => {
+				self.handle_unknown_msg("PrimaryMonitor").await
+			}
+			TabMessage::SessionMetadata(_session_metadata_payload) => {
+				self.handle_unknown_msg("SessionMetadata").await
+			}
+			TabMessage::RemoteControlState(_remote_control_state_payload) => {
+				self.handle_unknown_msg("RemoteControlState").await
+			}
+			TabMessage::ConnectorProperties(_connector_properties_payload) => {
+				self.handle_unknown_msg("ConnectorProperties").await
+			}
+			TabMessage::ScreenshotReady { .. } => self.handle_unknown_msg("ScreenshotReady").await,
+			TabMessage::VirtualMonitorCreated(_) => {
+				self.handle_unknown_msg("VirtualMonitorCreated").await
+			}
+			TabMessage::ScreencastStarted(_) => self.handle_unknown_msg("ScreencastStarted").await,
+			TabMessage::ScreencastStopped(_) => self.handle_unknown_msg("ScreencastStopped").await,
+			// Likely a request added by a newer client; it can carry on without it.
 			TabMessage::Unknown(tab_message_frame) => {
-				self.handle_unknown_msg(tab_message_frame.header.0).await
+				self
+					.send_error("unknown_message", Some(tab_message_frame.header.0))
+					.await
 			}
 		}
 	}
@@ -278,7 +420,7 @@ impl Client {
 							.collect(), // TODO: add monitors,
 						session: SessionInfo {
 							display_name: Some(session.display_name().to_string()),
-							id: session.id().to_string(),
+							id: session.id(),
 							role: session.role().into(),
 							state: if session.ready() {
 								tab_protocol::SessionLifecycle::Occupied
@@ -286,6 +428,8 @@ impl Client {
 								tab_protocol::SessionLifecycle::Loading
 							},
 						},
+						metadata: session.metadata().clone(),
+						render_node: crate::gpu::render_node(),
 					},
 				);
 				self.connected_session = Some(session);
@@ -307,7 +451,7 @@ impl Client {
 					SessionCreatedPayload {
 						session: SessionInfo {
 							display_name: session.display_name().map(String::from),
-							id: session.id().to_string(),
+							id: session.id(),
 							role: session.role().into(),
 							state: tab_protocol::SessionLifecycle::Pending,
 						},
@@ -331,8 +475,24 @@ impl Client {
 					self.schedule_client_shutdown().await;
 				}
 			}
+			S2CMsg::PermissionDenied { permission } => {
+				self.send_permission_denied(permission).await;
+			}
 			S2CMsg::BufferRelease { buffers } => {
 				for buffer in buffers {
+					if let Some(hash) = buffer.content_hash {
+						let payload = BufferHashPayload {
+							monitor_id: buffer.monitor_id,
+							buffer: buffer.buffer,
+							hash,
+						};
+						if let Err(e) = TabMessageFrame::json(message_header::BUFFER_HASH, payload)
+							.send_frame_to_async_fd(&self.socket)
+							.await
+						{
+							tracing::warn!(monitor_id = %buffer.monitor_id, buffer = buffer.buffer as u8, "failed to send buffer_hash: {e}");
+						}
+					}
 					let payload = format!("{} {}", buffer.monitor_id, buffer.buffer as u8);
 					let mut frame = TabMessageFrame::raw(message_header::BUFFER_RELEASE, payload);
 					if let Some(fd) = buffer.release_fence.as_ref() {
@@ -343,21 +503,24 @@ impl Client {
 						tracing::warn!(monitor_id = %buffer.monitor_id, buffer = buffer.buffer as u8, "failed to send buffer_release: {e}");
 						break;
 					}
+					tracing::debug!(frame_id = buffer.frame_id.map(tracing::field::display), monitor_id = %buffer.monitor_id, buffer = buffer.buffer as u8, "buffer released");
 				}
 			}
-			S2CMsg::BufferRequestAck { monitor_id, buffer } => {
+			S2CMsg::BufferRequestAck {
+				monitor_id,
+				buffer,
+				frame_id,
+			} => {
 				let payload = format!("{monitor_id} {}", buffer as u8);
 				if let Err(e) = TabMessageFrame::raw(message_header::BUFFER_REQUEST_ACK, payload)
 					.send_frame_to_async_fd(&self.socket)
 					.await
 				{
-					tracing::warn!(%monitor_id, buffer = buffer as u8, "failed to send buffer_request_ack: {e}");
+					tracing::warn!(%frame_id, %monitor_id, buffer = buffer as u8, "failed to send buffer_request_ack: {e}");
 				}
 			}
 			S2CMsg::SessionAwake { session_id } => {
-				let payload = SessionAwakePayload {
-					session_id: session_id.to_string(),
-				};
+				let payload = SessionAwakePayload { session_id };
 				if let Err(e) = TabMessageFrame::json(message_header::SESSION_AWAKE, payload)
 					.send_frame_to_async_fd(&self.socket)
 					.await
@@ -366,9 +529,7 @@ impl Client {
 				}
 			}
 			S2CMsg::SessionActive { session_id } => {
-				let payload = SessionActivePayload {
-					session_id: session_id.to_string(),
-				};
+				let payload = SessionActivePayload { session_id };
 				if let Err(e) = TabMessageFrame::json(message_header::SESSION_ACTIVE, payload)
 					.send_frame_to_async_fd(&self.socket)
 					.await
@@ -386,9 +547,7 @@ impl Client {
 				}
 			}
 			S2CMsg::SessionSleep { session_id } => {
-				let payload = SessionSleepPayload {
-					session_id: session_id.to_string(),
-				};
+				let payload = SessionSleepPayload { session_id };
 				if let Err(e) = TabMessageFrame::json(message_header::SESSION_SLEEP, payload)
 					.send_frame_to_async_fd(&self.socket)
 					.await
@@ -396,10 +555,20 @@ impl Client {
 					tracing::warn!("failed to send session sleep: {e}");
 				}
 			}
-			S2CMsg::InputEvent { event } => {
-				if let Err(e) = TabMessageFrame::json(message_header::INPUT_EVENT, event)
+			S2CMsg::ReleaseBuffers { session_id } => {
+				let payload = ReleaseBuffersPayload { session_id };
+				if let Err(e) = TabMessageFrame::json(message_header::RELEASE_BUFFERS, payload)
 					.send_frame_to_async_fd(&self.socket)
 					.await
+				{
+					tracing::warn!("failed to send release buffers: {e}");
+				}
+			}
+			S2CMsg::InputEvent { event } => {
+				if let Err(e) = self
+					.input_encoder
+					.send_json_to_async_fd(&self.socket, message_header::INPUT_EVENT, &event)
+					.await
 				{
 					tracing::warn!("failed to send input event: {e}");
 				}
@@ -417,7 +586,7 @@ impl Client {
 			}
 			S2CMsg::MonitorRemoved { monitor_id, name } => {
 				let payload = MonitorRemovedPayload {
-					monitor_id: monitor_id.to_string(),
+					monitor_id,
 					name: name.to_string(),
 				};
 				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_REMOVED, payload)
@@ -427,6 +596,150 @@ impl Client {
 					tracing::warn!("failed to send monitor removed: {e}");
 				}
 			}
+			S2CMsg::MonitorResized {
+				monitor,
+				relink_deadline,
+			} => {
+				let payload = MonitorResizedPayload {
+					monitor: monitor.to_protocol_info(),
+					relink_deadline_ms: relink_deadline.as_millis() as u64,
+				};
+				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_RESIZED, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send monitor resized: {e}");
+				}
+			}
+			S2CMsg::MonitorModeChanged { monitor } => {
+				let payload = MonitorModeChangedPayload {
+					monitor: monitor.to_protocol_info(),
+				};
+				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_MODE_CHANGED, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send monitor mode changed: {e}");
+				}
+			}
+			S2CMsg::MonitorLayout { monitors } => {
+				let payload = MonitorLayoutPayload { monitors };
+				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_LAYOUT, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send monitor layout: {e}");
+				}
+			}
+			S2CMsg::FrameStats { stats } => {
+				if let Err(e) = TabMessageFrame::json(message_header::FRAME_STATS, stats)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send frame stats: {e}");
+				}
+			}
+			S2CMsg::SessionFrameStats { stats } => {
+				if let Err(e) = TabMessageFrame::json(message_header::SESSION_FRAME_STATS, stats)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send session frame stats: {e}");
+				}
+			}
+			S2CMsg::PresentationFeedback { feedback } => {
+				if let Err(e) = TabMessageFrame::json(message_header::PRESENTATION_FEEDBACK, feedback)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send presentation feedback: {e}");
+				}
+			}
+			S2CMsg::ChannelStats { stats } => {
+				if let Err(e) = TabMessageFrame::json(message_header::CHANNEL_STATS, stats)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send channel stats: {e}");
+				}
+			}
+			S2CMsg::RenderCacheStats { stats } => {
+				if let Err(e) = TabMessageFrame::json(message_header::RENDER_CACHE_STATS, stats)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send render cache stats: {e}");
+				}
+			}
+			S2CMsg::SessionMetadata(payload) => {
+				if let Err(e) = TabMessageFrame::json(message_header::SESSION_METADATA, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send session metadata: {e}");
+				}
+			}
+			S2CMsg::ConnectorProperties(payload) => {
+				if let Err(e) = TabMessageFrame::json(message_header::CONNECTOR_PROPERTIES, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send connector properties: {e}");
+				}
+			}
+			S2CMsg::ScreenshotReady { payload, pixels } => {
+				let monitor_id = payload.monitor_id;
+				let mut frame = TabMessageFrame::json(message_header::SCREENSHOT_READY, payload);
+				frame.fds.push(pixels.as_raw_fd());
+				if let Err(e) = frame.send_frame_to_async_fd(&self.socket).await {
+					tracing::warn!(%monitor_id, "failed to send screenshot: {e}");
+				}
+			}
+			S2CMsg::VirtualMonitorCreated(payload) => {
+				if let Err(e) = TabMessageFrame::json(message_header::VIRTUAL_MONITOR_CREATED, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send virtual monitor: {e}");
+				}
+			}
+			S2CMsg::ScreencastStarted(payload) => {
+				if let Err(e) = TabMessageFrame::json(message_header::SCREENCAST_STARTED, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send screencast start: {e}");
+				}
+			}
+			S2CMsg::ScreencastStopped(payload) => {
+				if let Err(e) = TabMessageFrame::json(message_header::SCREENCAST_STOPPED, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send screencast stop: {e}");
+				}
+			}
+			S2CMsg::PrimaryMonitor { monitor_id } => {
+				let payload = PrimaryMonitorPayload { monitor_id };
+				if let Err(e) = TabMessageFrame::json(message_header::PRIMARY_MONITOR, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send primary monitor: {e}");
+				}
+			}
+			S2CMsg::RemoteControlState { monitor_id } => {
+				let payload = RemoteControlStatePayload {
+					active: monitor_id.is_some(),
+					monitor_id,
+				};
+				if let Err(e) = TabMessageFrame::json(message_header::REMOTE_CONTROL_STATE, payload)
+					.send_frame_to_async_fd(&self.socket)
+					.await
+				{
+					tracing::warn!("failed to send remote control state: {e}");
+				}
+			}
 		}
 	}
 	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
@@ -439,12 +752,32 @@ impl Client {
 			.await;
 		self.shutdown = true;
 	}
+	async fn handle_frame(&mut self, frame: TabMessageFrame) {
+		// Parsing owns the frame's fds only once it succeeds.
+		let fds = frame.fds.clone();
+		let error = match TabMessage::try_from(frame) {
+			Ok(packet) => return self.handle_packet(packet).await,
+			Err(error) => error,
+		};
+		for fd in fds {
+			// SAFETY: the fds arrived with the frame and nothing took ownership of them.
+			drop(unsafe { OwnedFd::from_raw_fd(fd) });
+		}
+		match error {
+			// The frame itself was fine, so the connection is still in sync.
+			ProtocolError::Json(e) => self.send_error("invalid_payload", Some(e)).await,
+			e => {
+				self.send_error("protocol_violation", Some(e)).await;
+				self.schedule_client_shutdown().await;
+			}
+		}
+	}
 	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
 	async fn run(mut self) {
 		loop {
 			tokio::select! {
-					read_frame_result = self.frame_reader.read_frame_from_async_fd(&self.socket) => match read_frame_result.and_then(TabMessage::try_from) {
-							Ok(packet) => self.handle_packet(packet).await,
+					read_frame_result = self.frame_reader.read_frame_from_async_fd(&self.socket) => match read_frame_result {
+							Ok(frame) => self.handle_frame(frame).await,
 							Err(e) => {
 									self.send_error("protocol_violation", Some(e)).await;
 									self.schedule_client_shutdown().await;
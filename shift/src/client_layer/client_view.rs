@@ -1,16 +1,23 @@
-use std::{rc::Rc, sync::Arc};
+use std::{os::fd::OwnedFd, rc::Rc, sync::Arc, time::Duration};
 
 use crate::{
 	auth::{self, Token},
 	client_layer::client::{Client, ClientId},
 	comms::{
+		channel::{self, ChannelKind},
 		client2server::{C2SMsg, C2SRx, C2STx, C2SWeakTx},
 		server2client::{BufferRelease, S2CMsg, S2CRx, S2CTx},
+		trace::FrameId,
 	},
 	monitor::{Monitor, MonitorId},
-	sessions::{PendingSession, Session, SessionId},
+	sessions::{PendingSession, Session, SessionId, permissions::Permission},
+};
+use tab_protocol::{
+	ChannelStatsPayload, ConnectorPropertiesPayload, FrameStatsPayload, InputEventPayload,
+	MonitorPlacement, PresentationFeedbackPayload, RenderCacheStatsPayload, ScreencastStartedPayload,
+	ScreencastStoppedPayload, ScreenshotReadyPayload, SessionFrameStatsPayload, SessionInfo,
+	SessionMetadataPayload, VirtualMonitorCreatedPayload,
 };
-use tab_protocol::{InputEventPayload, SessionInfo};
 
 #[derive(Debug)]
 pub struct ChannelsServerEnd(C2SRx, S2CTx);
@@ -40,9 +47,10 @@ pub struct Channels {
 	pub server_end: ChannelsServerEnd,
 }
 impl Channels {
+	/// Both directions use `SHIFT_CLIENT_CHANNEL_CAPACITY` and `SHIFT_CLIENT_CHANNEL_OVERFLOW`.
 	pub(super) fn new() -> Self {
-		let c2s = tokio::sync::mpsc::channel(5000);
-		let s2c = tokio::sync::mpsc::channel(5000);
+		let c2s = channel::channel(ChannelKind::Client);
+		let s2c = channel::channel(ChannelKind::Client);
 		Self {
 			client_end: ChannelsClientEnd(s2c.1, c2s.0),
 			server_end: ChannelsServerEnd(c2s.1, s2c.0),
@@ -138,11 +146,16 @@ impl ClientView {
 		&mut self,
 		monitor_id: MonitorId,
 		buffer: tab_protocol::BufferIndex,
+		frame_id: FrameId,
 	) -> bool {
 		self
 			.channels
 			.1
-			.send(S2CMsg::BufferRequestAck { monitor_id, buffer })
+			.send(S2CMsg::BufferRequestAck {
+				monitor_id,
+				buffer,
+				frame_id,
+			})
 			.await
 			.is_ok()
 	}
@@ -165,6 +178,183 @@ impl ClientView {
 			.is_ok()
 	}
 
+	pub async fn notify_monitor_resized(
+		&mut self,
+		monitor: Monitor,
+		relink_deadline: Duration,
+	) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::MonitorResized {
+				monitor,
+				relink_deadline,
+			})
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_monitor_mode_changed(&mut self, monitor: Monitor) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::MonitorModeChanged { monitor })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_monitor_layout(&mut self, monitors: Vec<MonitorPlacement>) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::MonitorLayout { monitors })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_frame_stats(&mut self, stats: FrameStatsPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::FrameStats { stats })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_session_frame_stats(&mut self, stats: SessionFrameStatsPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::SessionFrameStats { stats })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_presentation_feedback(
+		&mut self,
+		feedback: PresentationFeedbackPayload,
+	) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::PresentationFeedback { feedback })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_channel_stats(&mut self, stats: ChannelStatsPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ChannelStats { stats })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_render_cache_stats(&mut self, stats: RenderCacheStatsPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::RenderCacheStats { stats })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_permission_denied(&mut self, permission: Permission) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::PermissionDenied { permission })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_session_metadata(&mut self, payload: SessionMetadataPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::SessionMetadata(payload))
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_connector_properties(&mut self, payload: ConnectorPropertiesPayload) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ConnectorProperties(payload))
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_screenshot(
+		&mut self,
+		payload: ScreenshotReadyPayload,
+		pixels: OwnedFd,
+	) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ScreenshotReady { payload, pixels })
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_virtual_monitor_created(
+		&mut self,
+		payload: VirtualMonitorCreatedPayload,
+	) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::VirtualMonitorCreated(payload))
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_screencast_started(&mut self, monitor_id: MonitorId, node_id: u32) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ScreencastStarted(ScreencastStartedPayload {
+				monitor_id,
+				node_id,
+			}))
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_screencast_stopped(&mut self, monitor_id: MonitorId, reason: &str) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ScreencastStopped(ScreencastStoppedPayload {
+				monitor_id,
+				reason: reason.to_string(),
+			}))
+			.await
+			.is_ok()
+	}
+
+	pub async fn notify_primary_monitor(&mut self, monitor_id: Option<MonitorId>) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::PrimaryMonitor { monitor_id })
+			.await
+			.is_ok()
+	}
+
+	/// `monitor_id` is the remotely controlled monitor, `None` once remote control ends.
+	pub async fn notify_remote_control_state(&mut self, monitor_id: Option<MonitorId>) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::RemoteControlState { monitor_id })
+			.await
+			.is_ok()
+	}
+
 	pub async fn notify_session_awake(&mut self, session_id: SessionId) -> bool {
 		self
 			.channels
@@ -201,6 +391,15 @@ impl ClientView {
 			.is_ok()
 	}
 
+	pub async fn notify_release_buffers(&mut self, session_id: SessionId) -> bool {
+		self
+			.channels
+			.1
+			.send(S2CMsg::ReleaseBuffers { session_id })
+			.await
+			.is_ok()
+	}
+
 	pub async fn notify_input_event(&mut self, event: InputEventPayload) -> bool {
 		self
 			.channels
@@ -0,0 +1,673 @@
+//! Bounded channels between the layers, with a configurable overflow policy per channel kind.
+//!
+//! Every kind reads `SHIFT_<KIND>_CHANNEL_CAPACITY` and `SHIFT_<KIND>_CHANNEL_OVERFLOW` (`block`,
+//! `drop-oldest` or `coalesce`) once, where `<KIND>` is `CLIENT`, `INPUT` or `RENDER`. Sends that
+//! find their channel full bump the kind's [`ChannelCounters`], which [`snapshot`] exposes.
+//! `drop-oldest` is only accepted for input, and only drops messages marked
+//! [`droppable`](Coalesce::droppable); client and render messages carry acks and buffer
+//! releases that sessions wait for.
+//!
+//! The channels are written here rather than wrapping tokio's `mpsc` because both overflow
+//! policies act on messages that are already queued (merging into the newest, dropping the
+//! oldest), which `mpsc` doesn't give senders access to.
+
+use std::{
+	collections::VecDeque,
+	fmt,
+	pin::pin,
+	sync::{
+		Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, Weak,
+		atomic::{AtomicU64, Ordering},
+	},
+};
+
+use tokio::sync::Notify;
+
+/// What a send does when its channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+	/// Wait for the receiver to make room.
+	Block,
+	/// Drop the oldest queued [droppable](Coalesce::droppable) message to make room, and wait
+	/// for room when there is none. Only for [`ChannelKind::Input`].
+	DropOldest,
+	/// Fold the message into the newest queued one when [`Coalesce`] allows it, and wait for
+	/// room otherwise.
+	Coalesce,
+}
+
+impl OverflowPolicy {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			OverflowPolicy::Block => "block",
+			OverflowPolicy::DropOldest => "drop-oldest",
+			OverflowPolicy::Coalesce => "coalesce",
+		}
+	}
+}
+
+fn parse_policy(value: &str) -> Option<OverflowPolicy> {
+	match value.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+		"block" => Some(OverflowPolicy::Block),
+		"drop-oldest" => Some(OverflowPolicy::DropOldest),
+		"coalesce" => Some(OverflowPolicy::Coalesce),
+		_ => None,
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelConfig {
+	pub capacity: usize,
+	pub policy: OverflowPolicy,
+}
+
+/// The channels Shift tunes separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+	/// Both directions between the server and each connected client.
+	Client,
+	/// Input layer to server.
+	Input,
+	/// Renderer to server. Commands to the renderer use the same capacity but always block,
+	/// since their lanes are ordered against each other.
+	Render,
+}
+
+impl ChannelKind {
+	pub const ALL: [ChannelKind; 3] = [ChannelKind::Client, ChannelKind::Input, ChannelKind::Render];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			ChannelKind::Client => "client",
+			ChannelKind::Input => "input",
+			ChannelKind::Render => "render",
+		}
+	}
+
+	/// Whether channels of this kind may use `policy`.
+	pub fn allows(self, policy: OverflowPolicy) -> bool {
+		policy != OverflowPolicy::DropOldest || self == ChannelKind::Input
+	}
+
+	fn index(self) -> usize {
+		match self {
+			ChannelKind::Client => 0,
+			ChannelKind::Input => 1,
+			ChannelKind::Render => 2,
+		}
+	}
+
+	fn default_config(self) -> ChannelConfig {
+		let capacity = match self {
+			ChannelKind::Client => 5000,
+			ChannelKind::Input => 4096,
+			ChannelKind::Render => 5000,
+		};
+		ChannelConfig {
+			capacity,
+			policy: OverflowPolicy::Block,
+		}
+	}
+
+	/// The configuration for this kind, read from the environment on first use unless
+	/// [`configure`] ran before.
+	pub fn config(self) -> ChannelConfig {
+		CONFIGS.get_or_init(|| ChannelKind::ALL.map(ChannelKind::config_from_env))[self.index()]
+	}
+
+	fn config_from_env(self) -> ChannelConfig {
+		let prefix = self.name().to_ascii_uppercase();
+		let capacity_var = format!("SHIFT_{prefix}_CHANNEL_CAPACITY");
+		let policy_var = format!("SHIFT_{prefix}_CHANNEL_OVERFLOW");
+		let mut config = self.default_config();
+		if let Ok(v) = std::env::var(&capacity_var) {
+			match v.trim().parse::<usize>() {
+				Ok(capacity) if capacity > 0 => config.capacity = capacity,
+				_ => tracing::warn!(
+					name = capacity_var,
+					value = v,
+					"invalid channel capacity, ignoring"
+				),
+			}
+		}
+		if let Ok(v) = std::env::var(&policy_var) {
+			match parse_policy(&v) {
+				Some(policy) if self.allows(policy) => config.policy = policy,
+				Some(policy) => tracing::warn!(
+					name = policy_var,
+					policy = policy.as_str(),
+					"overflow policy would drop messages this channel must deliver, ignoring"
+				),
+				None => tracing::warn!(
+					name = policy_var,
+					value = v,
+					"invalid channel overflow policy, ignoring"
+				),
+			}
+		}
+		config
+	}
+
+	pub fn counters(self) -> &'static ChannelCounters {
+		static COUNTERS: [ChannelCounters; 3] = [
+			ChannelCounters::new(),
+			ChannelCounters::new(),
+			ChannelCounters::new(),
+		];
+		&COUNTERS[self.index()]
+	}
+}
+
+/// Overflow counters shared by every channel of one kind.
+#[derive(Debug, Default)]
+pub struct ChannelCounters {
+	/// Sends that found their channel full, whatever the policy did about it.
+	saturated: AtomicU64,
+	/// Messages dropped by [`OverflowPolicy::DropOldest`].
+	dropped: AtomicU64,
+	/// Messages folded into a queued one by [`OverflowPolicy::Coalesce`].
+	coalesced: AtomicU64,
+}
+
+impl ChannelCounters {
+	pub const fn new() -> Self {
+		Self {
+			saturated: AtomicU64::new(0),
+			dropped: AtomicU64::new(0),
+			coalesced: AtomicU64::new(0),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelSnapshot {
+	pub kind: ChannelKind,
+	pub config: ChannelConfig,
+	pub saturated: u64,
+	pub dropped: u64,
+	pub coalesced: u64,
+}
+
+/// Current configuration and counters of every channel kind.
+pub fn snapshot() -> [ChannelSnapshot; 3] {
+	ChannelKind::ALL.map(|kind| {
+		let counters = kind.counters();
+		ChannelSnapshot {
+			kind,
+			config: kind.config(),
+			saturated: counters.saturated.load(Ordering::Relaxed),
+			dropped: counters.dropped.load(Ordering::Relaxed),
+			coalesced: counters.coalesced.load(Ordering::Relaxed),
+		}
+	})
+}
+
+/// Messages that can be merged or dropped while their channel is full.
+pub trait Coalesce: Sized {
+	/// Folds `newer` into `self`, or hands it back when both have to be delivered.
+	fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+		Err(newer)
+	}
+
+	/// Whether [`OverflowPolicy::DropOldest`] may discard this message to make room.
+	fn droppable(&self) -> bool {
+		false
+	}
+}
+
+/// Returned when the receiving end is gone, with the message that couldn't be sent.
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SendError").finish_non_exhaustive()
+	}
+}
+
+impl<T> fmt::Display for SendError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("channel closed")
+	}
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+struct State<T> {
+	queue: VecDeque<T>,
+	senders: usize,
+	receiver_alive: bool,
+}
+
+struct Shared<T> {
+	state: Mutex<State<T>>,
+	config: ChannelConfig,
+	counters: &'static ChannelCounters,
+	/// Wakes the receiver when a message arrives or the last sender goes away.
+	readable: Notify,
+	/// Wakes async senders waiting for room.
+	writable: Notify,
+	/// Wakes blocking senders waiting for room.
+	writable_blocking: Condvar,
+}
+
+enum Push<T> {
+	Done,
+	Full(T),
+	Closed(T),
+}
+
+impl<T: Coalesce> Shared<T> {
+	fn lock(&self) -> MutexGuard<'_, State<T>> {
+		self.state.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Queues `msg` or applies the overflow policy. `saturated` makes sure a send that has to
+	/// wait several times is only counted once.
+	fn push(&self, state: &mut State<T>, msg: T, saturated: &mut bool) -> Push<T> {
+		if !state.receiver_alive {
+			return Push::Closed(msg);
+		}
+		if state.queue.len() < self.config.capacity {
+			state.queue.push_back(msg);
+			self.readable.notify_one();
+			return Push::Done;
+		}
+		if !std::mem::replace(saturated, true) {
+			self.counters.saturated.fetch_add(1, Ordering::Relaxed);
+		}
+		match self.config.policy {
+			OverflowPolicy::Block => Push::Full(msg),
+			OverflowPolicy::DropOldest => {
+				let Some(oldest) = state.queue.iter().position(T::droppable) else {
+					return Push::Full(msg);
+				};
+				state.queue.remove(oldest);
+				state.queue.push_back(msg);
+				self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+				self.readable.notify_one();
+				Push::Done
+			}
+			OverflowPolicy::Coalesce => {
+				let Some(newest) = state.queue.back_mut() else {
+					return Push::Full(msg);
+				};
+				match newest.coalesce(msg) {
+					Ok(()) => {
+						self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+						Push::Done
+					}
+					Err(msg) => Push::Full(msg),
+				}
+			}
+		}
+	}
+
+	fn wake_senders(&self) {
+		self.writable.notify_waiters();
+		self.writable_blocking.notify_all();
+	}
+}
+
+static CONFIGS: OnceLock<[ChannelConfig; 3]> = OnceLock::new();
+
+/// Overrides the environment for some kinds. Configuration is process-wide and fixed once a
+/// channel exists, so this returns `false` when it comes too late.
+pub fn configure(overrides: &[(ChannelKind, ChannelConfig)]) -> bool {
+	let configs = ChannelKind::ALL.map(|kind| {
+		overrides
+			.iter()
+			.rev()
+			.find(|(overridden, _)| *overridden == kind)
+			.map_or_else(|| kind.config_from_env(), |(_, config)| *config)
+	});
+	CONFIGS.set(configs).is_ok()
+		|| overrides
+			.iter()
+			.all(|(kind, config)| kind.config() == *config)
+}
+
+/// Creates a channel of `kind` with its configured capacity and policy.
+pub fn channel<T: Coalesce>(kind: ChannelKind) -> (Sender<T>, Receiver<T>) {
+	with_config(kind.config(), kind.counters())
+}
+
+pub fn with_config<T: Coalesce>(
+	config: ChannelConfig,
+	counters: &'static ChannelCounters,
+) -> (Sender<T>, Receiver<T>) {
+	let shared = Arc::new(Shared {
+		state: Mutex::new(State {
+			queue: VecDeque::new(),
+			senders: 1,
+			receiver_alive: true,
+		}),
+		config,
+		counters,
+		readable: Notify::new(),
+		writable: Notify::new(),
+		writable_blocking: Condvar::new(),
+	});
+	(
+		Sender {
+			shared: Arc::clone(&shared),
+		},
+		Receiver { shared },
+	)
+}
+
+pub struct Sender<T: Coalesce> {
+	shared: Arc<Shared<T>>,
+}
+
+impl<T: Coalesce> Sender<T> {
+	pub async fn send(&self, mut msg: T) -> Result<(), SendError<T>> {
+		let mut saturated = false;
+		loop {
+			let mut writable = pin!(self.shared.writable.notified());
+			writable.as_mut().enable();
+			let pushed = self
+				.shared
+				.push(&mut self.shared.lock(), msg, &mut saturated);
+			match pushed {
+				Push::Done => return Ok(()),
+				Push::Closed(m) => return Err(SendError(m)),
+				Push::Full(m) => msg = m,
+			}
+			writable.await;
+		}
+	}
+
+	/// Like [`Sender::send`], for threads outside the runtime.
+	pub fn blocking_send(&self, mut msg: T) -> Result<(), SendError<T>> {
+		let mut saturated = false;
+		let mut state = self.shared.lock();
+		loop {
+			match self.shared.push(&mut state, msg, &mut saturated) {
+				Push::Done => return Ok(()),
+				Push::Closed(m) => return Err(SendError(m)),
+				Push::Full(m) => msg = m,
+			}
+			state = self
+				.shared
+				.writable_blocking
+				.wait(state)
+				.unwrap_or_else(PoisonError::into_inner);
+		}
+	}
+
+	/// Whether the receiver is gone.
+	pub fn is_closed(&self) -> bool {
+		!self.shared.lock().receiver_alive
+	}
+
+	pub fn downgrade(&self) -> WeakSender<T> {
+		WeakSender {
+			shared: Arc::downgrade(&self.shared),
+		}
+	}
+}
+
+impl<T: Coalesce> Clone for Sender<T> {
+	fn clone(&self) -> Self {
+		self.shared.lock().senders += 1;
+		Self {
+			shared: Arc::clone(&self.shared),
+		}
+	}
+}
+
+impl<T: Coalesce> Drop for Sender<T> {
+	fn drop(&mut self) {
+		let mut state = self.shared.lock();
+		state.senders -= 1;
+		if state.senders == 0 {
+			self.shared.readable.notify_one();
+		}
+	}
+}
+
+impl<T: Coalesce> fmt::Debug for Sender<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Sender")
+			.field("config", &self.shared.config)
+			.finish_non_exhaustive()
+	}
+}
+
+/// A sender that doesn't keep the channel open.
+pub struct WeakSender<T: Coalesce> {
+	shared: Weak<Shared<T>>,
+}
+
+impl<T: Coalesce> WeakSender<T> {
+	/// Returns a sender while any other sender is still alive.
+	pub fn upgrade(&self) -> Option<Sender<T>> {
+		let shared = self.shared.upgrade()?;
+		{
+			let mut state = shared.lock();
+			if state.senders == 0 {
+				return None;
+			}
+			state.senders += 1;
+		}
+		Some(Sender { shared })
+	}
+}
+
+impl<T: Coalesce> Clone for WeakSender<T> {
+	fn clone(&self) -> Self {
+		Self {
+			shared: Weak::clone(&self.shared),
+		}
+	}
+}
+
+impl<T: Coalesce> fmt::Debug for WeakSender<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("WeakSender").finish_non_exhaustive()
+	}
+}
+
+pub struct Receiver<T: Coalesce> {
+	shared: Arc<Shared<T>>,
+}
+
+impl<T: Coalesce> Receiver<T> {
+	/// Waits for the next message; `None` once every sender is gone and the queue is drained.
+	pub async fn recv(&mut self) -> Option<T> {
+		loop {
+			let readable = self.shared.readable.notified();
+			{
+				let mut state = self.shared.lock();
+				if let Some(msg) = state.queue.pop_front() {
+					drop(state);
+					self.shared.wake_senders();
+					return Some(msg);
+				}
+				if state.senders == 0 {
+					return None;
+				}
+			}
+			readable.await;
+		}
+	}
+
+	/// Whether every sender is gone.
+	pub fn is_closed(&self) -> bool {
+		self.shared.lock().senders == 0
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.shared.lock().queue.is_empty()
+	}
+}
+
+impl<T: Coalesce> Drop for Receiver<T> {
+	fn drop(&mut self) {
+		let queued = {
+			let mut state = self.shared.lock();
+			state.receiver_alive = false;
+			std::mem::take(&mut state.queue)
+		};
+		self.shared.wake_senders();
+		drop(queued);
+	}
+}
+
+impl<T: Coalesce> fmt::Debug for Receiver<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Receiver")
+			.field("config", &self.shared.config)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, PartialEq)]
+	enum Msg {
+		Keep(u32),
+		Sum(u32),
+		Sample(u32),
+	}
+
+	impl Coalesce for Msg {
+		fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+			match (self, newer) {
+				(Msg::Sum(total), Msg::Sum(n)) => {
+					*total += n;
+					Ok(())
+				}
+				(_, newer) => Err(newer),
+			}
+		}
+
+		fn droppable(&self) -> bool {
+			matches!(self, Msg::Sample(_))
+		}
+	}
+
+	fn test_channel(
+		policy: OverflowPolicy,
+	) -> (Sender<Msg>, Receiver<Msg>, &'static ChannelCounters) {
+		let counters: &'static ChannelCounters = Box::leak(Box::default());
+		let (tx, rx) = with_config(
+			ChannelConfig {
+				capacity: 2,
+				policy,
+			},
+			counters,
+		);
+		(tx, rx, counters)
+	}
+
+	fn drain(rx: &mut Receiver<Msg>) -> Vec<Msg> {
+		let mut out = Vec::new();
+		while !rx.is_empty() {
+			out.push(rx.shared.lock().queue.pop_front().unwrap());
+		}
+		out
+	}
+
+	#[test]
+	fn parses_policies() {
+		assert_eq!(parse_policy("block"), Some(OverflowPolicy::Block));
+		assert_eq!(
+			parse_policy(" Drop_Oldest "),
+			Some(OverflowPolicy::DropOldest)
+		);
+		assert_eq!(
+			parse_policy("drop-oldest"),
+			Some(OverflowPolicy::DropOldest)
+		);
+		assert_eq!(parse_policy("coalesce"), Some(OverflowPolicy::Coalesce));
+		assert_eq!(parse_policy("newest"), None);
+	}
+
+	#[test]
+	fn drop_oldest_is_only_for_input() {
+		assert!(ChannelKind::Input.allows(OverflowPolicy::DropOldest));
+		assert!(!ChannelKind::Client.allows(OverflowPolicy::DropOldest));
+		assert!(!ChannelKind::Render.allows(OverflowPolicy::DropOldest));
+		for kind in ChannelKind::ALL {
+			assert!(kind.allows(OverflowPolicy::Block));
+			assert!(kind.allows(OverflowPolicy::Coalesce));
+		}
+	}
+
+	#[test]
+	fn drop_oldest_keeps_the_newest_samples() {
+		let (tx, mut rx, counters) = test_channel(OverflowPolicy::DropOldest);
+		for n in 0..4 {
+			tx.blocking_send(Msg::Sample(n)).unwrap();
+		}
+		assert_eq!(drain(&mut rx), vec![Msg::Sample(2), Msg::Sample(3)]);
+		assert_eq!(counters.saturated.load(Ordering::Relaxed), 2);
+		assert_eq!(counters.dropped.load(Ordering::Relaxed), 2);
+	}
+
+	#[test]
+	fn drop_oldest_never_drops_other_messages() {
+		let (tx, mut rx, counters) = test_channel(OverflowPolicy::DropOldest);
+		tx.blocking_send(Msg::Keep(0)).unwrap();
+		tx.blocking_send(Msg::Sample(1)).unwrap();
+		tx.blocking_send(Msg::Keep(2)).unwrap();
+		let full = tx
+			.shared
+			.push(&mut tx.shared.lock(), Msg::Keep(3), &mut false);
+		assert!(matches!(full, Push::Full(Msg::Keep(3))));
+		assert_eq!(drain(&mut rx), vec![Msg::Keep(0), Msg::Keep(2)]);
+		assert_eq!(counters.dropped.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	fn coalesce_merges_into_the_newest_message() {
+		let (tx, mut rx, counters) = test_channel(OverflowPolicy::Coalesce);
+		tx.blocking_send(Msg::Keep(0)).unwrap();
+		tx.blocking_send(Msg::Sum(1)).unwrap();
+		tx.blocking_send(Msg::Sum(2)).unwrap();
+		tx.blocking_send(Msg::Sum(3)).unwrap();
+		assert_eq!(drain(&mut rx), vec![Msg::Keep(0), Msg::Sum(6)]);
+		assert_eq!(counters.coalesced.load(Ordering::Relaxed), 2);
+	}
+
+	#[test]
+	fn coalesce_waits_when_messages_cannot_merge() {
+		let (tx, mut rx, counters) = test_channel(OverflowPolicy::Coalesce);
+		tx.blocking_send(Msg::Sum(1)).unwrap();
+		tx.blocking_send(Msg::Keep(0)).unwrap();
+		let sender = std::thread::spawn(move || tx.blocking_send(Msg::Sum(2)).is_ok());
+		while counters.saturated.load(Ordering::Relaxed) == 0 {
+			std::thread::yield_now();
+		}
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap();
+		assert_eq!(runtime.block_on(rx.recv()), Some(Msg::Sum(1)));
+		assert!(sender.join().unwrap());
+		assert_eq!(drain(&mut rx), vec![Msg::Keep(0), Msg::Sum(2)]);
+		assert_eq!(counters.coalesced.load(Ordering::Relaxed), 0);
+	}
+
+	#[test]
+	fn closes_when_either_end_goes_away() {
+		let (tx, mut rx, _) = test_channel(OverflowPolicy::Block);
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap();
+		runtime.block_on(tx.send(Msg::Keep(1))).unwrap();
+		let weak = tx.downgrade();
+		drop(tx);
+		assert!(weak.upgrade().is_none());
+		assert!(rx.is_closed());
+		assert_eq!(runtime.block_on(rx.recv()), Some(Msg::Keep(1)));
+		assert_eq!(runtime.block_on(rx.recv()), None);
+
+		let (tx, rx, _) = test_channel(OverflowPolicy::Block);
+		drop(rx);
+		assert!(tx.is_closed());
+		assert!(tx.blocking_send(Msg::Keep(1)).is_err());
+	}
+}
@@ -1,29 +1,88 @@
 use std::os::fd::OwnedFd;
 
 use tab_protocol::{
-	BufferIndex, FramebufferLinkPayload, SessionCreatePayload, SessionReadyPayload,
-	SessionSwitchPayload,
+	AudioClientsPayload, BufferIndex, ColorFilterPayload, DisplayAdjustPayload, FrameTracePayload,
+	FramebufferLinkPayload, GetConnectorPropertiesPayload, GetSessionMetadataPayload,
+	IdleInhibitPayload, InputEventPayload, LatencyModePayload, MagnifierPayload,
+	MonitorEnablePayload, MonitorProfilePayload, OverlayRegionsPayload, RemoteControlPayload,
+	RenderQualityPayload, ScreenRecordPayload, ScreencastStartPayload, ScreencastStopPayload,
+	ScreenshotPayload, SessionCreatePayload, SessionReadyPayload, SessionSwitchPayload,
+	SessionUpdatePayload, SetColorProfilePayload, SetColorTemperaturePayload, SetCursorPayload,
+	SetMonitorModePayload, SetMonitorPositionPayload, SetMonitorScalePayload,
+	SetOutputTransformPayload, SetPrimaryMonitorPayload, SetVrrPayload, SetWallpaperPayload,
+	VirtualMonitorCreatePayload, VirtualMonitorRemovePayload,
 };
 
+use super::channel::{self, Coalesce};
+use super::trace::FrameId;
 use crate::{auth::Token, monitor::MonitorId};
 #[derive(Debug)]
 pub enum C2SMsg {
 	Shutdown,
+	/// The client sent a `ping`, which the client layer answers itself.
+	Alive,
 	Auth(Token),
 	CreateSession(SessionCreatePayload),
 	SwitchSession(SessionSwitchPayload),
 	SessionReady(SessionReadyPayload),
+	FrameTrace(FrameTracePayload),
+	ColorFilter(ColorFilterPayload),
+	Magnifier(MagnifierPayload),
+	DisplayAdjust(DisplayAdjustPayload),
+	ScreenRecord(ScreenRecordPayload),
+	Screenshot(ScreenshotPayload),
+	VirtualMonitorCreate(VirtualMonitorCreatePayload),
+	VirtualMonitorRemove(VirtualMonitorRemovePayload),
+	ScreencastStart(ScreencastStartPayload),
+	ScreencastStop(ScreencastStopPayload),
+	RemoteControl(RemoteControlPayload),
+	InputInject(InputEventPayload),
+	IdleInhibit(IdleInhibitPayload),
+	MonitorEnable(MonitorEnablePayload),
+	SetPrimaryMonitor(SetPrimaryMonitorPayload),
+	RenderQuality(RenderQualityPayload),
+	LatencyMode(LatencyModePayload),
+	GetSessionMetadata(GetSessionMetadataPayload),
+	SessionUpdate(SessionUpdatePayload),
+	AudioClients(AudioClientsPayload),
+	GetConnectorProperties(GetConnectorPropertiesPayload),
+	OverlayRegions(OverlayRegionsPayload),
+	MonitorProfile(MonitorProfilePayload),
+	SetWallpaper(SetWallpaperPayload),
+	SetColorProfile(SetColorProfilePayload),
+	SetVrr(SetVrrPayload),
+	SetOutputTransform(SetOutputTransformPayload),
+	SetMonitorScale(SetMonitorScalePayload),
+	SetMonitorMode(SetMonitorModePayload),
+	SetMonitorPosition(SetMonitorPositionPayload),
+	SetColorTemperature(SetColorTemperaturePayload),
+	SetCursor {
+		payload: SetCursorPayload,
+		pixels: Option<OwnedFd>,
+	},
 	BufferRequest {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
 		acquire_fence: Option<OwnedFd>,
+		/// `CLOCK_MONOTONIC` nanoseconds.
+		present_at: Option<u64>,
+		frame_id: FrameId,
 	},
 	FramebufferLink {
 		payload: FramebufferLinkPayload,
-		dma_bufs: [OwnedFd; 2],
+		dma_bufs: [Vec<OwnedFd>; 2],
 	},
 }
 
-pub type C2SRx = tokio::sync::mpsc::Receiver<C2SMsg>;
-pub type C2STx = tokio::sync::mpsc::Sender<C2SMsg>;
-pub type C2SWeakTx = tokio::sync::mpsc::WeakSender<C2SMsg>;
+impl Coalesce for C2SMsg {
+	fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+		match (self, newer) {
+			(C2SMsg::Alive, C2SMsg::Alive) => Ok(()),
+			(_, newer) => Err(newer),
+		}
+	}
+}
+
+pub type C2SRx = channel::Receiver<C2SMsg>;
+pub type C2STx = channel::Sender<C2SMsg>;
+pub type C2SWeakTx = channel::WeakSender<C2SMsg>;
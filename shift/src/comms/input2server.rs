@@ -2,12 +2,131 @@ use std::sync::Arc;
 
 use tab_protocol::InputEventPayload;
 
+use super::channel::{self, Coalesce};
+
 #[derive(Debug, Clone)]
 pub enum InputEvt {
 	Event(InputEventPayload),
 	FatalError { reason: Arc<str> },
 }
 
-pub type InputEvtRx = tokio::sync::mpsc::Receiver<InputEvt>;
-pub type InputEvtTx = tokio::sync::mpsc::Sender<InputEvt>;
-pub type InputEvtWeakTx = tokio::sync::mpsc::WeakSender<InputEvt>;
+impl Coalesce for InputEvt {
+	fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+		match (self, newer) {
+			(InputEvt::Event(older), InputEvt::Event(newer)) => {
+				coalesce_motion(older, newer).map_err(|newer| InputEvt::Event(*newer))
+			}
+			(_, newer) => Err(newer),
+		}
+	}
+
+	/// Motion samples are superseded by the next one; anything else changes state.
+	fn droppable(&self) -> bool {
+		matches!(
+			self,
+			InputEvt::Event(
+				InputEventPayload::PointerMotion { .. }
+					| InputEventPayload::PointerMotionAbsolute { .. }
+					| InputEventPayload::TouchMotion { .. }
+					| InputEventPayload::TabletToolAxis { .. }
+			)
+		)
+	}
+}
+
+/// Folds pointer motion from one device into the previous motion: relative deltas add up,
+/// positions are taken from `newer`. Hands `newer` back when it can't be folded.
+pub fn coalesce_motion(
+	older: &mut InputEventPayload,
+	newer: InputEventPayload,
+) -> Result<(), Box<InputEventPayload>> {
+	match (older, newer) {
+		(
+			InputEventPayload::PointerMotion {
+				device,
+				time_usec,
+				x,
+				y,
+				dx,
+				dy,
+				unaccel_dx,
+				unaccel_dy,
+			},
+			InputEventPayload::PointerMotion {
+				device: newer_device,
+				time_usec: newer_time_usec,
+				x: newer_x,
+				y: newer_y,
+				dx: newer_dx,
+				dy: newer_dy,
+				unaccel_dx: newer_unaccel_dx,
+				unaccel_dy: newer_unaccel_dy,
+			},
+		) if *device == newer_device => {
+			*time_usec = newer_time_usec;
+			(*x, *y) = (newer_x, newer_y);
+			*dx += newer_dx;
+			*dy += newer_dy;
+			*unaccel_dx += newer_unaccel_dx;
+			*unaccel_dy += newer_unaccel_dy;
+			Ok(())
+		}
+		(
+			older @ InputEventPayload::PointerMotionAbsolute { .. },
+			newer @ InputEventPayload::PointerMotionAbsolute { .. },
+		) if motion_device(older) == motion_device(&newer) => {
+			*older = newer;
+			Ok(())
+		}
+		(_, newer) => Err(Box::new(newer)),
+	}
+}
+
+fn motion_device(event: &InputEventPayload) -> Option<u32> {
+	match event {
+		InputEventPayload::PointerMotion { device, .. }
+		| InputEventPayload::PointerMotionAbsolute { device, .. } => Some(*device),
+		_ => None,
+	}
+}
+
+pub type InputEvtRx = channel::Receiver<InputEvt>;
+pub type InputEvtTx = channel::Sender<InputEvt>;
+pub type InputEvtWeakTx = channel::WeakSender<InputEvt>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn motion(device: u32, x: f64, dx: f64) -> InputEventPayload {
+		InputEventPayload::PointerMotion {
+			device,
+			time_usec: x as u64,
+			x,
+			y: 0.0,
+			dx,
+			dy: 0.0,
+			unaccel_dx: dx,
+			unaccel_dy: 0.0,
+		}
+	}
+
+	#[test]
+	fn relative_motion_adds_up_deltas() {
+		let mut older = motion(1, 10.0, 2.0);
+		assert!(coalesce_motion(&mut older, motion(1, 13.0, 3.0)).is_ok());
+		let InputEventPayload::PointerMotion {
+			x, dx, unaccel_dx, ..
+		} = older
+		else {
+			panic!("motion changed kind");
+		};
+		assert_eq!((x, dx, unaccel_dx), (13.0, 5.0, 5.0));
+	}
+
+	#[test]
+	fn motion_from_other_devices_is_kept() {
+		let mut older = motion(1, 10.0, 2.0);
+		assert!(coalesce_motion(&mut older, motion(2, 13.0, 3.0)).is_err());
+	}
+}
@@ -1,5 +1,7 @@
+pub mod channel;
 pub mod client2server;
 pub mod input2server;
 pub mod render2server;
 pub mod server2client;
 pub mod server2render;
+pub mod trace;
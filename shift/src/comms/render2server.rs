@@ -3,11 +3,23 @@ use std::sync::Arc;
 
 use tab_protocol::BufferIndex;
 
+use super::channel::{self, Coalesce};
+use super::trace::FrameId;
 use crate::{
 	monitor::{Monitor, MonitorId},
 	sessions::SessionId,
 };
 
+/// A monitor's composed frame read back for `RenderCmd::Capture`: ARGB8888 pixels (`B, G, R,
+/// A` in memory), `stride` bytes per row, in a memfd.
+#[derive(Debug)]
+pub struct CapturedFrame {
+	pub width: u32,
+	pub height: u32,
+	pub stride: u32,
+	pub pixels: OwnedFd,
+}
+
 /// Events emitted by the rendering layer back into the server core.
 #[derive(Debug)]
 pub enum RenderEvt {
@@ -20,6 +32,19 @@ pub enum RenderEvt {
 	MonitorOnline { monitor: Monitor },
 	/// The user unplugged a monitor
 	MonitorOffline { monitor_id: MonitorId },
+	/// A connected monitor changed geometry (mode change, tile group formed or broke up). Its
+	/// linked buffers stay in place and are scaled until sessions link new ones.
+	MonitorResized { monitor: Monitor },
+	/// Outcome of `RenderCmd::SetMode`: the monitor in its new mode, announced after
+	/// `MonitorResized` when the size changed, or why the mode couldn't be set.
+	MonitorModeChanged {
+		monitor_id: MonitorId,
+		result: Result<Monitor, Arc<str>>,
+	},
+	/// The DRM device went away; all monitors are gone until `DeviceRestored`.
+	DeviceLost { reason: Arc<str> },
+	/// A DRM device was reopened after `DeviceLost`, with a fresh set of monitors.
+	DeviceRestored { monitors: Vec<Monitor> },
 	/// Rendering reported an unrecoverable condition.
 	FatalError { reason: Arc<str> },
 	/// Some monitors just page flipped and are ready to be commited to again
@@ -29,6 +54,18 @@ pub enum RenderEvt {
 		session_id: SessionId,
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		frame_id: FrameId,
+		/// Whether the buffer waits on its acquire fence or present time; `BufferLatched`
+		/// follows once it is shown. Present times already past don't wait.
+		deferred: bool,
+	},
+	/// A buffer that waited on its acquire fence or present time became the one shown.
+	BufferLatched {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		/// Request that presented the buffer; `None` when the renderer no longer knows it.
+		frame_id: Option<FrameId>,
 	},
 	/// Renderer switched to a newer buffer and no longer needs the previous one.
 	BufferConsumed {
@@ -36,16 +73,120 @@ pub enum RenderEvt {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
 		release_fence: Option<OwnedFd>,
+		/// Sampled content hash, only with `SHIFT_DEBUG_BUFFER_HASH=1`.
+		content_hash: Option<u64>,
+		/// Request that presented the buffer; `None` when the renderer no longer knows it.
+		frame_id: Option<FrameId>,
 	},
+	/// A latched buffer reached the screen with a monitor's flip.
+	PresentationFeedback {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		/// `CLOCK_MONOTONIC` nanoseconds.
+		timestamp_ns: u64,
+		sequence: u64,
+		refresh_ns: u64,
+		/// Request that presented the buffer; `None` when the renderer no longer knows it.
+		frame_id: Option<FrameId>,
+	},
+	/// Frame pacing for one monitor, aggregated over the last stats window.
+	FrameStats {
+		monitor_id: MonitorId,
+		fps: f32,
+		avg_frame_ms: f32,
+		p99_frame_ms: f32,
+		missed_vblanks: u32,
+		/// Refresh periods the renderer didn't commit because the scene was static.
+		skipped_commits: u32,
+	},
+	/// How a session kept up with a monitor's refresh, aggregated over the last stats window.
+	SessionFrameStats {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		presented_frames: u32,
+		missed_refreshes: u32,
+		longest_miss_streak: u32,
+		stale_presents: u32,
+	},
+	/// GPU resource cache usage, reported periodically.
+	ResourceCacheStats {
+		resource_count: u64,
+		resource_bytes: u64,
+		purgeable_bytes: u64,
+		budget_bytes: u64,
+	},
+	/// Answer to `RenderCmd::Capture`.
+	Captured {
+		monitor_id: MonitorId,
+		result: Result<CapturedFrame, Arc<str>>,
+	},
+	/// A screencast's PipeWire node is ready for consumers to connect to.
+	ScreencastStarted { cast_id: u64, node_id: u32 },
+	/// A screencast stopped on its own, or failed to start.
+	ScreencastEnded { cast_id: u64, reason: Arc<str> },
 	/// Renderer rejected a buffer request after inspecting local state.
 	BufferRequestRejected {
 		session_id: SessionId,
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
 		reason: Arc<str>,
+		frame_id: FrameId,
 	},
 }
 
-pub type RenderEvtRx = tokio::sync::mpsc::Receiver<RenderEvt>;
-pub type RenderEvtTx = tokio::sync::mpsc::Sender<RenderEvt>;
-pub type RenderEvtWeakTx = tokio::sync::mpsc::WeakSender<RenderEvt>;
+impl RenderEvt {
+	/// The buffer request the event is about, for correlating it in logs.
+	pub fn frame_id(&self) -> Option<FrameId> {
+		match self {
+			Self::BufferRequestAck { frame_id, .. } | Self::BufferRequestRejected { frame_id, .. } => {
+				Some(*frame_id)
+			}
+			Self::BufferLatched { frame_id, .. }
+			| Self::BufferConsumed { frame_id, .. }
+			| Self::PresentationFeedback { frame_id, .. } => *frame_id,
+			_ => None,
+		}
+	}
+}
+
+/// Page flips are merged and periodic statistics are replaced by the newer report.
+impl Coalesce for RenderEvt {
+	fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+		match (self, newer) {
+			(RenderEvt::PageFlip { monitors }, RenderEvt::PageFlip { monitors: newer }) => {
+				for monitor_id in newer {
+					if !monitors.contains(&monitor_id) {
+						monitors.push(monitor_id);
+					}
+				}
+				Ok(())
+			}
+			(older @ RenderEvt::FrameStats { .. }, newer @ RenderEvt::FrameStats { .. })
+				if stats_monitor(older) == stats_monitor(&newer) =>
+			{
+				*older = newer;
+				Ok(())
+			}
+			(
+				older @ RenderEvt::ResourceCacheStats { .. },
+				newer @ RenderEvt::ResourceCacheStats { .. },
+			) => {
+				*older = newer;
+				Ok(())
+			}
+			(_, newer) => Err(newer),
+		}
+	}
+}
+
+fn stats_monitor(event: &RenderEvt) -> Option<MonitorId> {
+	match event {
+		RenderEvt::FrameStats { monitor_id, .. } => Some(*monitor_id),
+		_ => None,
+	}
+}
+
+pub type RenderEvtRx = channel::Receiver<RenderEvt>;
+pub type RenderEvtTx = channel::Sender<RenderEvt>;
+pub type RenderEvtWeakTx = channel::WeakSender<RenderEvt>;
@@ -1,12 +1,23 @@
 use std::os::fd::OwnedFd;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tab_protocol::{BufferIndex, InputEventPayload, SessionInfo};
+use tab_protocol::{
+	BufferIndex, ChannelStatsPayload, ConnectorPropertiesPayload, FrameStatsPayload,
+	InputEventPayload, MonitorPlacement, PresentationFeedbackPayload, RenderCacheStatsPayload,
+	ScreencastStartedPayload, ScreencastStoppedPayload, ScreenshotReadyPayload,
+	SessionFrameStatsPayload, SessionInfo, SessionMetadataPayload, VirtualMonitorCreatedPayload,
+};
 
+use super::{
+	channel::{self, Coalesce},
+	input2server::coalesce_motion,
+	trace::FrameId,
+};
 use crate::{
 	auth::{self, Token},
 	monitor::{Monitor, MonitorId},
-	sessions::{PendingSession, Session, SessionId},
+	sessions::{PendingSession, Session, SessionId, permissions::Permission},
 };
 
 #[derive(Debug)]
@@ -14,6 +25,8 @@ pub struct BufferRelease {
 	pub monitor_id: MonitorId,
 	pub buffer: BufferIndex,
 	pub release_fence: Option<OwnedFd>,
+	pub content_hash: Option<u64>,
+	pub frame_id: Option<FrameId>,
 }
 
 #[derive(Debug)]
@@ -32,6 +45,7 @@ pub enum S2CMsg {
 	BufferRequestAck {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		frame_id: FrameId,
 	},
 	SessionActive {
 		session_id: SessionId,
@@ -45,6 +59,9 @@ pub enum S2CMsg {
 	SessionSleep {
 		session_id: SessionId,
 	},
+	ReleaseBuffers {
+		session_id: SessionId,
+	},
 	InputEvent {
 		event: InputEventPayload,
 	},
@@ -55,8 +72,82 @@ pub enum S2CMsg {
 		monitor_id: MonitorId,
 		name: Arc<str>,
 	},
+	MonitorResized {
+		monitor: Monitor,
+		relink_deadline: Duration,
+	},
+	MonitorModeChanged {
+		monitor: Monitor,
+	},
+	MonitorLayout {
+		monitors: Vec<MonitorPlacement>,
+	},
+	FrameStats {
+		stats: FrameStatsPayload,
+	},
+	RenderCacheStats {
+		stats: RenderCacheStatsPayload,
+	},
+	SessionFrameStats {
+		stats: SessionFrameStatsPayload,
+	},
+	PresentationFeedback {
+		feedback: PresentationFeedbackPayload,
+	},
+	ChannelStats {
+		stats: ChannelStatsPayload,
+	},
+	RemoteControlState {
+		monitor_id: Option<MonitorId>,
+	},
+	PrimaryMonitor {
+		monitor_id: Option<MonitorId>,
+	},
+	SessionMetadata(SessionMetadataPayload),
+	ConnectorProperties(ConnectorPropertiesPayload),
+	PermissionDenied {
+		permission: Permission,
+	},
+	ScreenshotReady {
+		payload: ScreenshotReadyPayload,
+		pixels: OwnedFd,
+	},
+	VirtualMonitorCreated(VirtualMonitorCreatedPayload),
+	ScreencastStarted(ScreencastStartedPayload),
+	ScreencastStopped(ScreencastStoppedPayload),
+}
+
+/// Releases are batched together, pointer motion is folded and statistics are replaced by the
+/// newer report for the same monitor.
+impl Coalesce for S2CMsg {
+	fn coalesce(&mut self, newer: Self) -> Result<(), Self> {
+		match (self, newer) {
+			(S2CMsg::BufferRelease { buffers }, S2CMsg::BufferRelease { buffers: newer }) => {
+				buffers.extend(newer);
+				Ok(())
+			}
+			(S2CMsg::InputEvent { event }, S2CMsg::InputEvent { event: newer }) => {
+				coalesce_motion(event, newer).map_err(|event| S2CMsg::InputEvent { event: *event })
+			}
+			(S2CMsg::FrameStats { stats }, S2CMsg::FrameStats { stats: newer })
+				if stats.monitor_id == newer.monitor_id =>
+			{
+				*stats = newer;
+				Ok(())
+			}
+			(S2CMsg::RenderCacheStats { stats }, S2CMsg::RenderCacheStats { stats: newer }) => {
+				*stats = newer;
+				Ok(())
+			}
+			(S2CMsg::ChannelStats { stats }, S2CMsg::ChannelStats { stats: newer }) => {
+				*stats = newer;
+				Ok(())
+			}
+			(_, newer) => Err(newer),
+		}
+	}
 }
 
-pub type S2CRx = tokio::sync::mpsc::Receiver<S2CMsg>;
-pub type S2CTx = tokio::sync::mpsc::Sender<S2CMsg>;
-pub type S2CWeakTx = tokio::sync::mpsc::WeakSender<S2CMsg>;
+pub type S2CRx = channel::Receiver<S2CMsg>;
+pub type S2CTx = channel::Sender<S2CMsg>;
+pub type S2CWeakTx = channel::WeakSender<S2CMsg>;
@@ -1,9 +1,22 @@
+use std::collections::VecDeque;
+use std::fmt;
 use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use tab_protocol::{BufferIndex, FramebufferLinkPayload};
+use tab_protocol::{
+	BufferIndex, ColorFilterMode, FramebufferLinkPayload, LatencyMode, MirrorScaling, MonitorScale,
+	NightLightSchedule, OutputTransform, OverlayRegion, RenderQualityPayload,
+};
 
-use crate::{monitor::MonitorId, sessions::SessionId};
+use tokio::sync::mpsc::{self, error::SendError};
+
+use super::trace::{CommandId, FrameId};
+use crate::{
+	monitor::{Monitor, MonitorId},
+	sessions::SessionId,
+};
 
 #[derive(Debug, Clone)]
 pub struct SessionTransition {
@@ -12,6 +25,100 @@ pub struct SessionTransition {
 	pub duration: Duration,
 }
 
+/// Output corner used to place overlays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScreenCorner {
+	TopLeft,
+	TopRight,
+	BottomLeft,
+	#[default]
+	BottomRight,
+}
+
+/// What the renderer draws where no session frame covers an output.
+#[derive(Clone, PartialEq, Eq)]
+pub enum WallpaperSource {
+	Color([u8; 3]),
+	/// Contents of an encoded image file, decoded by the renderer.
+	Image(Arc<[u8]>),
+}
+
+impl fmt::Debug for WallpaperSource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Color(rgb) => f.debug_tuple("Color").field(rgb).finish(),
+			Self::Image(bytes) => write!(f, "Image({} bytes)", bytes.len()),
+		}
+	}
+}
+
+/// Where the renderer takes a monitor's color profile from.
+#[derive(Clone, PartialEq, Eq)]
+pub enum ColorProfileSource {
+	/// Treat the monitor as sRGB and don't correct anything.
+	None,
+	/// Build a profile from the primaries and gamma in the monitor's EDID.
+	Edid,
+	/// Contents of an ICC profile, parsed by the renderer.
+	Icc(Arc<[u8]>),
+}
+
+impl fmt::Debug for ColorProfileSource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::None => f.write_str("None"),
+			Self::Edid => f.write_str("Edid"),
+			Self::Icc(bytes) => write!(f, "Icc({} bytes)", bytes.len()),
+		}
+	}
+}
+
+/// A session's pointer cursor: premultiplied ARGB8888 pixels (`B, G, R, A` in memory), rows
+/// packed without padding.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CursorImage {
+	pub width: u32,
+	pub height: u32,
+	/// The pixel at the pointer position.
+	pub hotspot: (u32, u32),
+	pub pixels: Arc<[u8]>,
+}
+
+impl fmt::Debug for CursorImage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CursorImage")
+			.field("width", &self.width)
+			.field("height", &self.height)
+			.field("hotspot", &self.hotspot)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Contents of the built-in recovery screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryScreen {
+	pub title: String,
+	pub message: String,
+	pub items: Vec<String>,
+	pub selected: usize,
+	/// Shown instead of the menu when set, oldest line first.
+	pub log_lines: Option<Vec<String>>,
+}
+
+/// One session listed by the built-in switcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitcherEntry {
+	pub session_id: SessionId,
+	pub name: String,
+}
+
+/// Contents of the built-in session switcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitcherView {
+	pub entries: Vec<SwitcherEntry>,
+	pub selected: usize,
+}
+
 #[derive(Debug)]
 pub enum RenderCmd {
 	/// Request the renderer to clean up and exit.
@@ -19,7 +126,7 @@ pub enum RenderCmd {
 	/// Ask the renderer to associate a client-provided framebuffer with internal GPU state.
 	FramebufferLink {
 		payload: FramebufferLinkPayload,
-		dma_bufs: [OwnedFd; 2],
+		dma_bufs: [Vec<OwnedFd>; 2],
 		session_id: SessionId,
 	},
 	/// Update which session should be displayed globally.
@@ -27,17 +134,465 @@ pub enum RenderCmd {
 		session_id: Option<SessionId>,
 		transition: Option<SessionTransition>,
 	},
+	/// Change the accessibility color filter of one monitor, or of every monitor when `None`.
+	SetColorFilter {
+		monitor_id: Option<MonitorId>,
+		mode: ColorFilterMode,
+	},
+	/// Set software brightness/contrast/gamma for one monitor.
+	SetDisplayAdjustment {
+		monitor_id: MonitorId,
+		brightness: f32,
+		contrast: f32,
+		gamma: f32,
+	},
+	/// Zoom one monitor (or every monitor when `None`) by `factor`; `1.0` disables the magnifier.
+	SetMagnifier {
+		monitor_id: Option<MonitorId>,
+		factor: f32,
+	},
+	/// Move the magnifier focus, in normalized monitor coordinates.
+	MagnifierFocus { x: f32, y: f32 },
+	/// Stamp `text` in `corner` of every output, or remove the watermark when `None`.
+	SetWatermark {
+		text: Option<String>,
+		corner: ScreenCorner,
+	},
+	/// Change the wallpaper of one monitor, or of every monitor when `None`.
+	SetWallpaper {
+		monitor_id: Option<MonitorId>,
+		wallpaper: WallpaperSource,
+	},
+	/// Change the color profile of one monitor, or of every monitor when `None`.
+	SetColorProfile {
+		monitor_id: Option<MonitorId>,
+		source: ColorProfileSource,
+	},
+	/// Turn VRR on or off for one monitor, or for every monitor when `None`. Only outputs that
+	/// report `vrr_capable` are affected.
+	SetVrr {
+		monitor_id: Option<MonitorId>,
+		enabled: bool,
+	},
+	/// Draw one monitor rotated or flipped by `transform`; its sessions render at the size
+	/// the transform gives it.
+	SetOutputTransform {
+		monitor_id: MonitorId,
+		transform: OutputTransform,
+	},
+	/// Sessions on `monitor_id` are told to draw at `scale`; buffers they link at another scale
+	/// are resized to match when composed.
+	SetMonitorScale {
+		monitor_id: MonitorId,
+		scale: MonitorScale,
+	},
+	/// Compose one monitor at `scale` times its mode's resolution and scale the result onto the
+	/// output: below 1x to spare the GPU, above to supersample.
+	SetRenderResolution {
+		monitor_id: MonitorId,
+		scale: MonitorScale,
+	},
+	/// Replaces the groups of monitors whose frames are committed together.
+	SetFlipGroups { groups: Vec<Vec<MonitorId>> },
+	/// Switch one monitor to the mode of its connector's that is `width`x`height` at `refresh`
+	/// Hz, answered with `RenderEvt::MonitorModeChanged`.
+	SetMode {
+		monitor_id: MonitorId,
+		width: i32,
+		height: i32,
+		refresh: u32,
+	},
+	/// Warm one monitor's colors, or every monitor's when `None`, to `kelvin`; only at night
+	/// when there is a `schedule`.
+	SetColorTemperature {
+		monitor_id: Option<MonitorId>,
+		kelvin: u32,
+		schedule: Option<NightLightSchedule>,
+	},
+	/// Show `image` as the pointer cursor on `monitor_id` only, or hide it when `None`.
+	SetCursor {
+		monitor_id: MonitorId,
+		image: Option<CursorImage>,
+	},
+	/// Move the cursor's hotspot to `(x, y)` (monitor pixels) on `monitor_id`.
+	MoveCursor {
+		monitor_id: MonitorId,
+		x: f64,
+		y: f64,
+	},
+	/// Read back the next frame composed for `monitor_id`; answered with `RenderEvt::Captured`.
+	Capture { monitor_id: MonitorId },
+	/// Compose `session_id` offscreen on the virtual monitor `monitor`, at its size and refresh
+	/// rate, for captures only. A mirror shows the session's frames on the `mirror` monitor
+	/// scaled to its size instead of buffers of its own.
+	AddVirtualMonitor {
+		monitor: Monitor,
+		session_id: SessionId,
+		mirror: Option<MonitorId>,
+		scaling: MirrorScaling,
+	},
+	/// Drop a virtual monitor and the buffers linked to it.
+	RemoveVirtualMonitor { monitor_id: MonitorId },
+	/// Stream every frame composed for `monitor_id` to a new PipeWire node, answered with
+	/// `RenderEvt::ScreencastStarted` or `RenderEvt::ScreencastEnded`.
+	StartScreencast { cast_id: u64, monitor_id: MonitorId },
+	/// Tear a screencast's stream down.
+	StopScreencast { cast_id: u64 },
+	/// Send black frames instead of the monitor's contents (`true`), or resume.
+	SetScreencastPaused { cast_id: u64, paused: bool },
+	/// Stop (`false`) or resume composing a monitor; disabled monitors show black.
+	SetMonitorEnabled {
+		monitor_id: MonitorId,
+		enabled: bool,
+	},
+	/// Show the session switcher on top of every output, or hide it when `None`.
+	SetSessionSwitcher { view: Option<SwitcherView> },
+	/// Replace every output's contents with the recovery screen, or go back to the sessions.
+	SetRecoveryScreen { screen: Option<RecoveryScreen> },
+	/// Blank every output (idle) or resume showing sessions.
+	SetBlanked { blanked: bool },
+	/// How to sample a session's buffers when composing it.
+	SetRenderQuality {
+		session_id: SessionId,
+		quality: RenderQualityPayload,
+	},
+	/// How to pace the monitors showing a session.
+	SetLatencyMode {
+		session_id: SessionId,
+		mode: LatencyMode,
+	},
+	/// Compose an overlay session on `monitor_id` inside `regions` (monitor pixels), above the
+	/// active session. Empty removes it from that monitor.
+	SetOverlayRegions {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		regions: Vec<OverlayRegion>,
+	},
+	/// Keep a copy of the frames a session shows as it goes to sleep, drawn in their place
+	/// until it presents again.
+	SnapshotSession { session_id: SessionId },
+	/// Release cached GPU resources nothing holds, e.g. after a session went to sleep.
+	PurgeResources,
+	/// Memory is short: release cached GPU resources and the copies of sleeping sessions' frames.
+	MemoryPressure,
 	/// Drop all GPU resources associated with a disconnected session.
 	SessionRemoved { session_id: SessionId },
+	/// Drop a session's buffers on one monitor, e.g. stale ones it didn't replace after the
+	/// monitor was resized.
+	DropSessionBuffers {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+	},
 	/// Present a framebuffer on a given monitor.
 	SwapBuffers {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
 		session_id: SessionId,
 		acquire_fence: Option<OwnedFd>,
+		/// Don't show the buffer before this `CLOCK_MONOTONIC` time, in nanoseconds.
+		present_at: Option<u64>,
+		frame_id: FrameId,
 	},
 }
 
-pub type RenderCmdRx = tokio::sync::mpsc::Receiver<RenderCmd>;
-pub type RenderCmdTx = tokio::sync::mpsc::Sender<RenderCmd>;
-pub type RenderCmdWeakTx = tokio::sync::mpsc::WeakSender<RenderCmd>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lane {
+	/// Small, frequent commands on the presentation path.
+	Frame,
+	/// Everything else, including slow ones like buffer imports.
+	Control,
+}
+
+impl RenderCmd {
+	fn lane(&self) -> Lane {
+		match self {
+			Self::SwapBuffers { .. } | Self::MagnifierFocus { .. } | Self::MoveCursor { .. } => {
+				Lane::Frame
+			}
+			_ => Lane::Control,
+		}
+	}
+
+	/// Session whose later frame commands must not overtake this command.
+	fn orders_frames_of(&self) -> Option<SessionId> {
+		match self {
+			Self::FramebufferLink { session_id, .. }
+			| Self::SessionRemoved { session_id }
+			| Self::DropSessionBuffers { session_id, .. } => Some(*session_id),
+			_ => None,
+		}
+	}
+
+	fn frame_session(&self) -> Option<SessionId> {
+		match self {
+			Self::SwapBuffers { session_id, .. } => Some(*session_id),
+			_ => None,
+		}
+	}
+
+	pub fn frame_id(&self) -> Option<FrameId> {
+		match self {
+			Self::SwapBuffers { frame_id, .. } => Some(*frame_id),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug)]
+struct Sequenced {
+	seq: u64,
+	cmd: RenderCmd,
+	/// Span the command was sent from.
+	span: tracing::Span,
+}
+
+/// A received command with what correlates it with the sender's logs.
+#[derive(Debug)]
+pub struct TracedCmd {
+	pub id: CommandId,
+	/// Span the command was sent from; the renderer handles the command in a child of it.
+	pub span: tracing::Span,
+	pub cmd: RenderCmd,
+}
+
+impl From<Sequenced> for TracedCmd {
+	fn from(sequenced: Sequenced) -> Self {
+		Self {
+			id: CommandId(sequenced.seq),
+			span: sequenced.span,
+			cmd: sequenced.cmd,
+		}
+	}
+}
+
+/// Sending half of the render command channel.
+///
+/// Commands travel on two lanes so a burst of buffer imports doesn't hold up swaps and the
+/// other way around. Sequence numbers keep a session's swaps behind the links, drops and
+/// removal sent before them; they follow send order as long as one task (the server) sends.
+/// They double as the [`CommandId`] of each command.
+#[derive(Debug, Clone)]
+pub struct RenderCmdTx {
+	frame: mpsc::Sender<Sequenced>,
+	control: mpsc::Sender<Sequenced>,
+	next_seq: Arc<AtomicU64>,
+}
+
+impl RenderCmdTx {
+	pub async fn send(&self, cmd: RenderCmd) -> Result<(), SendError<RenderCmd>> {
+		let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+		let lane = match cmd.lane() {
+			Lane::Frame => &self.frame,
+			Lane::Control => &self.control,
+		};
+		lane
+			.send(Sequenced {
+				seq,
+				cmd,
+				span: tracing::Span::current(),
+			})
+			.await
+			.map_err(|SendError(sequenced)| SendError(sequenced.cmd))
+	}
+}
+
+/// Receiving half of the render command channel; see [`RenderCmdTx`].
+#[derive(Debug)]
+pub struct RenderCmdRx {
+	frame: mpsc::Receiver<Sequenced>,
+	control: mpsc::Receiver<Sequenced>,
+	/// Next frame command, held back while an older control command of its session is pending.
+	frame_head: Option<Sequenced>,
+	/// Control commands taken off their lane to look past them, oldest first.
+	backlog: VecDeque<Sequenced>,
+	frame_closed: bool,
+	control_closed: bool,
+}
+
+/// Both halves of a render command channel holding up to `capacity` commands per lane.
+pub fn render_command_channel(capacity: usize) -> (RenderCmdTx, RenderCmdRx) {
+	let (frame_tx, frame_rx) = mpsc::channel(capacity);
+	let (control_tx, control_rx) = mpsc::channel(capacity);
+	let tx = RenderCmdTx {
+		frame: frame_tx,
+		control: control_tx,
+		next_seq: Arc::new(AtomicU64::new(0)),
+	};
+	let rx = RenderCmdRx {
+		frame: frame_rx,
+		control: control_rx,
+		frame_head: None,
+		backlog: VecDeque::new(),
+		frame_closed: false,
+		control_closed: false,
+	};
+	(tx, rx)
+}
+
+impl RenderCmdRx {
+	/// Next command, frame commands first. `None` once every sender is gone and both lanes are
+	/// drained. Cancel-safe, like [`Self::recv_traced`].
+	pub async fn recv(&mut self) -> Option<RenderCmd> {
+		self.recv_traced().await.map(|traced| traced.cmd)
+	}
+
+	/// [`Self::recv`] with the command's id and sender span. Cancel-safe: a command is only
+	/// taken off a lane when it is returned or kept in `self`.
+	pub async fn recv_traced(&mut self) -> Option<TracedCmd> {
+		loop {
+			if self.frame_head.is_none() {
+				self.frame_head = self.frame.try_recv().ok();
+			}
+			if let Some(frame) = &self.frame_head {
+				// Control commands sent before this one are already queued on their lane.
+				while self.backlog.back().is_none_or(|last| last.seq < frame.seq) {
+					match self.control.try_recv() {
+						Ok(cmd) => self.backlog.push_back(cmd),
+						Err(_) => break,
+					}
+				}
+				let blocked = frame.cmd.frame_session().is_some_and(|session_id| {
+					self
+						.backlog
+						.iter()
+						.any(|cmd| cmd.seq < frame.seq && cmd.cmd.orders_frames_of() == Some(session_id))
+				});
+				if !blocked {
+					return self.frame_head.take().map(TracedCmd::from);
+				}
+			}
+			if let Some(sequenced) = self.backlog.pop_front() {
+				return Some(sequenced.into());
+			}
+			tokio::select! {
+				biased;
+				frame = self.frame.recv(), if !self.frame_closed => match frame {
+					Some(frame) => self.frame_head = Some(frame),
+					None => self.frame_closed = true,
+				},
+				control = self.control.recv(), if !self.control_closed => match control {
+					Some(sequenced) => return Some(sequenced.into()),
+					None => self.control_closed = true,
+				},
+				else => return None,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	fn swap(n: u32) -> RenderCmd {
+		RenderCmd::SwapBuffers {
+			monitor_id: "mon_1".parse().expect("monitor id"),
+			buffer: BufferIndex::Zero,
+			session_id: session(n),
+			acquire_fence: None,
+			present_at: None,
+			frame_id: FrameId::next(),
+		}
+	}
+
+	fn drain(tx: RenderCmdTx, mut rx: RenderCmdRx) -> Vec<RenderCmd> {
+		drop(tx);
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async move {
+			let mut cmds = Vec::new();
+			while let Some(cmd) = rx.recv().await {
+				cmds.push(cmd);
+			}
+			cmds
+		})
+	}
+
+	fn send_all(tx: &RenderCmdTx, cmds: impl IntoIterator<Item = RenderCmd>) {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			for cmd in cmds {
+				tx.send(cmd).await.expect("receiver alive");
+			}
+		});
+	}
+
+	#[test]
+	fn swaps_overtake_control_commands_of_other_sessions() {
+		let (tx, rx) = render_command_channel(8);
+		send_all(
+			&tx,
+			[
+				RenderCmd::SetBlanked { blanked: false },
+				RenderCmd::SessionRemoved {
+					session_id: session(2),
+				},
+				swap(1),
+			],
+		);
+		let order: Vec<_> = drain(tx, rx)
+			.iter()
+			.map(|cmd| match cmd {
+				RenderCmd::SwapBuffers { .. } => "swap",
+				RenderCmd::SetBlanked { .. } => "blank",
+				RenderCmd::SessionRemoved { .. } => "removed",
+				_ => "other",
+			})
+			.collect();
+		assert_eq!(order, ["swap", "blank", "removed"]);
+	}
+
+	#[test]
+	fn swaps_wait_for_older_commands_of_their_session() {
+		let (tx, rx) = render_command_channel(8);
+		send_all(
+			&tx,
+			[
+				RenderCmd::SetBlanked { blanked: false },
+				RenderCmd::DropSessionBuffers {
+					session_id: session(1),
+					monitor_id: "mon_1".parse().expect("monitor id"),
+				},
+				swap(1),
+				RenderCmd::PurgeResources,
+			],
+		);
+		let order: Vec<_> = drain(tx, rx)
+			.iter()
+			.map(|cmd| match cmd {
+				RenderCmd::SwapBuffers { .. } => "swap",
+				RenderCmd::SetBlanked { .. } => "blank",
+				RenderCmd::DropSessionBuffers { .. } => "drop",
+				RenderCmd::PurgeResources => "purge",
+				_ => "other",
+			})
+			.collect();
+		assert_eq!(order, ["blank", "drop", "swap", "purge"]);
+	}
+
+	#[test]
+	fn command_ids_follow_send_order() {
+		let (tx, mut rx) = render_command_channel(8);
+		send_all(&tx, [RenderCmd::PurgeResources, swap(1)]);
+		drop(tx);
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.expect("runtime");
+		let received = runtime.block_on(async move {
+			let mut received = Vec::new();
+			while let Some(traced) = rx.recv_traced().await {
+				received.push((traced.id, traced.cmd.frame_id().is_some()));
+			}
+			received
+		});
+		// The swap overtakes the purge but keeps the id it was sent with.
+		assert_eq!(received, [(CommandId(1), true), (CommandId(0), false)]);
+	}
+}
@@ -0,0 +1,43 @@
+//! Correlation ids for following work across the client, server and rendering layers in logs.
+//!
+//! A [`FrameId`] names one `buffer_request`. The client layer assigns it when the request comes
+//! in and it travels with the swap to the renderer, which puts it on every event about that
+//! buffer (ack or rejection, latch after the acquire fence, release after the commit), and the
+//! server hands it back to the client layer with the ack and the release. Filtering the logs
+//! on one `frame_id` reconstructs that frame's journey.
+//!
+//! Every render command also gets a [`CommandId`] when it is sent, and the renderer handles it
+//! inside a child of the span it was sent from, so the client and session fields of the
+//! server's spans show up on the renderer's log lines too.
+
+use std::{
+	fmt,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrameId(u64);
+
+impl FrameId {
+	/// A process-wide unique id.
+	pub fn next() -> Self {
+		static NEXT: AtomicU64 = AtomicU64::new(1);
+		Self(NEXT.fetch_add(1, Ordering::Relaxed))
+	}
+}
+
+impl fmt::Display for FrameId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "fr_{}", self.0)
+	}
+}
+
+/// Position of a command in the order the server sent it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandId(pub(super) u64);
+
+impl fmt::Display for CommandId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "cmd_{}", self.0)
+	}
+}
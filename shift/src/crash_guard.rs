@@ -0,0 +1,109 @@
+//! Puts the machine back into a usable state when Shift crashes.
+//!
+//! Without this, a panic in the renderer leaves the last frame scanned out, the VT in graphics
+//! mode and the console keyboard unusable. On a panic (any thread) or a fatal signal, DRM
+//! master is dropped on every open DRM fd, the VT is switched back to text mode with a
+//! working keyboard and a visible cursor, and the process exits.
+//!
+//! Everything on the crash path is async-signal-safe: the VT fd is opened up front and DRM fds
+//! are found with `fstat`.
+
+use std::{
+	ffi::CStr,
+	sync::atomic::{AtomicI32, Ordering},
+};
+
+/// `DRM_IOCTL_DROP_MASTER`: `_IO('d', 0x1f)`.
+const DRM_IOCTL_DROP_MASTER: u32 = 0x641f;
+const DRM_MAJOR: u32 = 226;
+const KDSETMODE: u32 = 0x4B3A;
+const KD_TEXT: libc::c_int = 0x00;
+const KDSKBMODE: u32 = 0x4B45;
+const K_UNICODE: libc::c_int = 0x03;
+/// Highest fd checked for DRM devices.
+const MAX_FD: libc::c_int = 1024;
+const FATAL_SIGNALS: [libc::c_int; 5] = [
+	libc::SIGABRT,
+	libc::SIGSEGV,
+	libc::SIGBUS,
+	libc::SIGILL,
+	libc::SIGFPE,
+];
+
+static VT_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Installs the panic hook and fatal signal handlers. Call once, early in `main`.
+pub fn install() {
+	VT_FD.store(open_vt(), Ordering::Relaxed);
+
+	let previous = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		restore_console();
+		previous(info);
+		// A panicked render or server task would otherwise leave a frozen frame behind while
+		// the rest of the runtime keeps going.
+		std::process::exit(101);
+	}));
+
+	for signal in FATAL_SIGNALS {
+		// SAFETY: the handler only performs async-signal-safe calls.
+		unsafe {
+			let mut action: libc::sigaction = std::mem::zeroed();
+			action.sa_sigaction = on_fatal_signal as usize;
+			action.sa_flags = libc::SA_RESETHAND;
+			libc::sigemptyset(&mut action.sa_mask);
+			if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+				tracing::warn!(
+					signal,
+					"failed to install crash handler: {}",
+					std::io::Error::last_os_error()
+				);
+			}
+		}
+	}
+}
+
+/// The VT Shift runs on: stdin when it is a terminal, otherwise the foreground console.
+fn open_vt() -> libc::c_int {
+	// SAFETY: plain libc calls on a static path / stdin.
+	unsafe {
+		if libc::isatty(libc::STDIN_FILENO) == 1 {
+			return libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 3);
+		}
+		let path: &CStr = c"/dev/tty0";
+		libc::open(
+			path.as_ptr(),
+			libc::O_RDWR | libc::O_CLOEXEC | libc::O_NOCTTY,
+		)
+	}
+}
+
+extern "C" fn on_fatal_signal(signal: libc::c_int) {
+	restore_console();
+	// SA_RESETHAND restored the default action; re-raise to die with the original signal.
+	unsafe {
+		libc::raise(signal);
+	}
+}
+
+fn restore_console() {
+	// SAFETY: ioctl/fstat/write on fds this process owns; all async-signal-safe.
+	unsafe {
+		for fd in 0..MAX_FD {
+			let mut stat: libc::stat = std::mem::zeroed();
+			if libc::fstat(fd, &mut stat) != 0 || stat.st_mode & libc::S_IFMT != libc::S_IFCHR {
+				continue;
+			}
+			if libc::major(stat.st_rdev) == DRM_MAJOR {
+				libc::ioctl(fd, DRM_IOCTL_DROP_MASTER as _);
+			}
+		}
+		let vt = VT_FD.load(Ordering::Relaxed);
+		if vt >= 0 {
+			libc::ioctl(vt, KDSETMODE as _, KD_TEXT);
+			libc::ioctl(vt, KDSKBMODE as _, K_UNICODE);
+			let show_cursor = b"\x1b[?25h\x1b[0m";
+			libc::write(vt, show_cursor.as_ptr().cast(), show_cursor.len());
+		}
+	}
+}
@@ -0,0 +1,173 @@
+//! Direct access to the DRM cards, for the few connector/CRTC queries EasyDRM doesn't expose.
+
+use std::{
+	fs::{File, OpenOptions},
+	io,
+	os::fd::{AsFd, BorrowedFd},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use drm::control::{Device as ControlDevice, connector};
+
+/// The card EasyDRM scans out on, see [`locate_scanout`].
+static SCANOUT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub struct Card(File);
+
+impl AsFd for Card {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.0.as_fd()
+	}
+}
+
+impl drm::Device for Card {}
+impl ControlDevice for Card {}
+
+impl Card {
+	fn open(path: &Path) -> io::Result<Card> {
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+		Ok(Card(file))
+	}
+
+	/// Opens every `/dev/dri/card*` that can be opened, in path order.
+	pub fn open_all() -> Vec<(PathBuf, Card)> {
+		let Ok(entries) = std::fs::read_dir("/dev/dri") else {
+			return Vec::new();
+		};
+		let mut paths: Vec<PathBuf> = entries
+			.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+			.filter(|path| {
+				path
+					.file_name()
+					.and_then(|name| name.to_str())
+					.is_some_and(|name| name.starts_with("card"))
+			})
+			.collect();
+		paths.sort();
+		paths
+			.into_iter()
+			.filter_map(|path| {
+				let card = Card::open(&path).ok()?;
+				Some((path, card))
+			})
+			.collect()
+	}
+
+	/// Kernel name of `connector_id`, e.g. `DP-1`.
+	pub fn connector_name(&self, connector_id: u32) -> Option<String> {
+		let handle: connector::Handle = drm::control::from_u32(connector_id)?;
+		let info = self.get_connector(handle, false).ok()?;
+		Some(format!(
+			"{}-{}",
+			info.interface().as_str(),
+			info.interface_id()
+		))
+	}
+
+	/// The blob of the connector property called `name`, if it is set.
+	pub fn connector_blob(&self, connector_id: u32, name: &[u8]) -> Option<Vec<u8>> {
+		let handle: connector::Handle = drm::control::from_u32(connector_id)?;
+		let properties = self.get_properties(handle).ok()?;
+		let (ids, values) = properties.as_props_and_values();
+		ids.iter().zip(values).find_map(|(id, value)| {
+			let info = self.get_property(*id).ok()?;
+			if info.name().to_bytes() != name || *value == 0 {
+				return None;
+			}
+			self.get_property_blob(*value).ok()
+		})
+	}
+
+	/// Whether every connector in `connectors` is connected on this card, and whether each
+	/// also drives a CRTC; `None` if not, or if the card has no connectors at all.
+	fn drives(&self, connectors: &[connector::Handle]) -> Option<bool> {
+		if self.resource_handles().ok()?.connectors().is_empty() {
+			return None;
+		}
+		let mut driven = true;
+		for &handle in connectors {
+			let info = self.get_connector(handle, false).ok()?;
+			if info.state() != connector::State::Connected {
+				return None;
+			}
+			driven &= info
+				.current_encoder()
+				.and_then(|encoder| self.get_encoder(encoder).ok())
+				.and_then(|encoder| encoder.crtc())
+				.is_some();
+		}
+		Some(driven)
+	}
+}
+
+/// Finds the card EasyDRM scans out on from the connectors of its outputs, and remembers it
+/// for [`scanout_path`]. EasyDRM doesn't say which card it opened; this is the one card where
+/// all of them are connected, preferring one where they already drive CRTCs. Returns `None`,
+/// and forgets the previous card, when no card or more than one matches.
+pub fn locate_scanout(connectors: &[u32]) -> Option<PathBuf> {
+	let handles: Vec<connector::Handle> = connectors
+		.iter()
+		.filter_map(|&id| drm::control::from_u32(id))
+		.collect();
+	let mut candidates: Vec<(PathBuf, bool)> = Card::open_all()
+		.into_iter()
+		.filter_map(|(path, card)| Some((path, card.drives(&handles)?)))
+		.collect();
+	if candidates.iter().any(|(_, driven)| *driven) {
+		candidates.retain(|(_, driven)| *driven);
+	}
+	let found = match candidates.as_slice() {
+		[(path, _)] => Some(path.clone()),
+		_ => None,
+	};
+	if found.is_none() {
+		tracing::warn!(
+			candidates = candidates.len(),
+			"can't tell which DRM card the outputs are on"
+		);
+	}
+	*SCANOUT.lock().unwrap_or_else(|e| e.into_inner()) = found.clone();
+	found
+}
+
+/// The card last found by [`locate_scanout`], if any.
+pub fn scanout_path() -> Option<PathBuf> {
+	SCANOUT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Opens the card EasyDRM scans out on, if it is known. Connector, CRTC and property ids
+/// are per card, so queries about the outputs must go to this one.
+pub fn scanout() -> Option<Card> {
+	Card::open(&scanout_path()?).ok()
+}
+
+/// The blob of the connector property called `name` (`TILE`, `EDID`) on the scanout card, if
+/// it is set.
+pub fn connector_blob(connector_id: u32, name: &[u8]) -> Option<Vec<u8>> {
+	scanout()?.connector_blob(connector_id, name)
+}
+
+/// Whether the scanout card is gone: its device node disappeared or stopped answering
+/// (`ENODEV`). `false` while the card isn't known.
+pub fn scanout_removed() -> bool {
+	let Some(path) = scanout_path() else {
+		return false;
+	};
+	let gone =
+		|e: io::Error| e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(libc::ENODEV);
+	match Card::open(&path) {
+		Ok(card) => card.resource_handles().is_err_and(gone),
+		Err(e) => gone(e),
+	}
+}
+
+/// Kernel name of `connector_id` on the scanout card, e.g. `DP-1`.
+pub fn connector_name(connector_id: u32) -> Option<String> {
+	scanout()?.connector_name(connector_id)
+}
+
+/// The EDID blob of `connector_id`.
+pub fn read_edid(connector_id: u32) -> Option<Vec<u8>> {
+	connector_blob(connector_id, b"EDID")
+}
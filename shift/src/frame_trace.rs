@@ -0,0 +1,263 @@
+//! On-demand export of rendering spans in the Chrome trace event format.
+//!
+//! The layer is always installed but stays idle until an admin client starts a capture.
+//! While capturing, every rendering-layer span becomes a complete (`"X"`) event covering its
+//! whole lifetime, so waits show their real wall time. Stopping the capture writes a JSON file
+//! loadable in `chrome://tracing` or Perfetto.
+
+use std::{
+	cell::Cell,
+	path::{Path, PathBuf},
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::Instant,
+};
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{Subscriber, span};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+/// Spans outside of these module prefixes are ignored while capturing.
+const TRACED_TARGETS: &[&str] = &["shift::rendering_layer"];
+/// Hard cap on buffered events so a forgotten capture can't eat all memory.
+const MAX_EVENTS: usize = 1_000_000;
+
+#[derive(Debug, Error)]
+pub enum FrameTraceError {
+	#[error("a frame trace capture is already running")]
+	AlreadyRunning,
+	#[error("no frame trace capture is running")]
+	NotRunning,
+	#[error("failed to write trace file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("failed to encode trace: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("trace writer failed: {0}")]
+	Writer(#[from] tokio::task::JoinError),
+}
+
+#[derive(Serialize)]
+struct TraceEvent {
+	name: &'static str,
+	cat: &'static str,
+	ph: &'static str,
+	ts: u64,
+	dur: u64,
+	pid: u32,
+	tid: u64,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+	#[serde(rename = "traceEvents")]
+	trace_events: &'a [TraceEvent],
+	#[serde(rename = "displayTimeUnit")]
+	display_time_unit: &'static str,
+}
+
+struct Capture {
+	path: PathBuf,
+	started_at: Instant,
+	events: Vec<TraceEvent>,
+	dropped: u64,
+}
+
+impl Capture {
+	fn write(self) -> Result<PathBuf, FrameTraceError> {
+		let file = std::fs::File::create(&self.path)?;
+		serde_json::to_writer(
+			std::io::BufWriter::new(file),
+			&TraceFile {
+				trace_events: &self.events,
+				display_time_unit: "ms",
+			},
+		)?;
+		if self.dropped > 0 {
+			tracing::warn!(
+				dropped = self.dropped,
+				"frame trace hit the event cap, later spans were dropped"
+			);
+		}
+		Ok(self.path)
+	}
+}
+
+/// Shared control handle; cloned into the server so admins can start/stop captures.
+#[derive(Clone, Default)]
+pub struct FrameTraceHandle {
+	capture: Arc<Mutex<Option<Capture>>>,
+}
+
+impl FrameTraceHandle {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn layer(&self) -> FrameTraceLayer {
+		FrameTraceLayer {
+			capture: Arc::clone(&self.capture),
+		}
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.capture.lock().is_ok_and(|c| c.is_some())
+	}
+
+	pub fn start(&self, path: impl AsRef<Path>) -> Result<(), FrameTraceError> {
+		let mut capture = self.capture.lock().expect("frame trace mutex poisoned");
+		if capture.is_some() {
+			return Err(FrameTraceError::AlreadyRunning);
+		}
+		*capture = Some(Capture {
+			path: path.as_ref().to_path_buf(),
+			started_at: Instant::now(),
+			events: Vec::new(),
+			dropped: 0,
+		});
+		Ok(())
+	}
+
+	/// Stops the running capture and writes it to disk on a blocking thread, returning the
+	/// written path.
+	pub async fn stop(&self) -> Result<PathBuf, FrameTraceError> {
+		let capture = self
+			.capture
+			.lock()
+			.expect("frame trace mutex poisoned")
+			.take()
+			.ok_or(FrameTraceError::NotRunning)?;
+		tokio::task::spawn_blocking(move || capture.write()).await?
+	}
+}
+
+pub fn default_trace_path() -> PathBuf {
+	PathBuf::from(format!(
+		"/tmp/shift-frame-trace-{}.json",
+		chrono::Utc::now().format("%Y%m%d-%H%M%S")
+	))
+}
+
+/// Span creation time and the thread it was created on.
+struct OpenedAt(Instant, u64);
+
+pub struct FrameTraceLayer {
+	capture: Arc<Mutex<Option<Capture>>>,
+}
+
+fn current_tid() -> u64 {
+	static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+	thread_local! {
+		static TID: Cell<u64> = const { Cell::new(0) };
+	}
+	TID.with(|tid| {
+		if tid.get() == 0 {
+			tid.set(NEXT_TID.fetch_add(1, Ordering::Relaxed));
+		}
+		tid.get()
+	})
+}
+
+impl<S> Layer<S> for FrameTraceLayer
+where
+	S: Subscriber + for<'a> LookupSpan<'a>,
+{
+	fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+		if !self.capture.lock().is_ok_and(|c| c.is_some()) {
+			return;
+		}
+		let Some(span) = ctx.span(id) else {
+			return;
+		};
+		if !TRACED_TARGETS
+			.iter()
+			.any(|prefix| span.metadata().target().starts_with(prefix))
+		{
+			return;
+		}
+		span
+			.extensions_mut()
+			.insert(OpenedAt(Instant::now(), current_tid()));
+	}
+
+	fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+		let Some(span) = ctx.span(&id) else {
+			return;
+		};
+		let Some(OpenedAt(opened_at, tid)) = span.extensions_mut().remove::<OpenedAt>() else {
+			return;
+		};
+		let Ok(mut guard) = self.capture.lock() else {
+			return;
+		};
+		let Some(capture) = guard.as_mut() else {
+			return;
+		};
+		if capture.events.len() >= MAX_EVENTS {
+			capture.dropped += 1;
+			return;
+		}
+		let metadata = span.metadata();
+		capture.events.push(TraceEvent {
+			name: metadata.name(),
+			cat: metadata.target(),
+			ph: "X",
+			ts: opened_at
+				.saturating_duration_since(capture.started_at)
+				.as_micros() as u64,
+			dur: opened_at.elapsed().as_micros() as u64,
+			pid: std::process::id(),
+			tid,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tracing_subscriber::layer::SubscriberExt;
+
+	use super::*;
+
+	#[test]
+	fn capture_writes_rendering_spans() {
+		let handle = FrameTraceHandle::new();
+		let subscriber = tracing_subscriber::registry().with(handle.layer());
+		let path = std::env::temp_dir().join(format!(
+			"shift-frame-trace-test-{}.json",
+			std::process::id()
+		));
+		tracing::subscriber::with_default(subscriber, || {
+			tracing::info_span!(target: "shift::rendering_layer", "before_capture").in_scope(|| {});
+			handle.start(&path).expect("capture started");
+			assert!(matches!(
+				handle.start(&path),
+				Err(FrameTraceError::AlreadyRunning)
+			));
+			tracing::info_span!(target: "shift::rendering_layer::render_core", "compose").in_scope(|| {});
+			tracing::info_span!(target: "shift::server_layer", "untraced").in_scope(|| {});
+		});
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.expect("runtime");
+		let written = runtime.block_on(handle.stop()).expect("trace written");
+		assert_eq!(written, path);
+		assert!(!handle.is_running());
+		assert!(matches!(
+			runtime.block_on(handle.stop()),
+			Err(FrameTraceError::NotRunning)
+		));
+
+		let trace: serde_json::Value =
+			serde_json::from_slice(&std::fs::read(&path).expect("trace file")).expect("trace json");
+		std::fs::remove_file(&path).ok();
+		let names = trace["traceEvents"]
+			.as_array()
+			.expect("trace events")
+			.iter()
+			.map(|event| event["name"].as_str().expect("event name"))
+			.collect::<Vec<_>>();
+		assert_eq!(names, ["compose"]);
+	}
+}
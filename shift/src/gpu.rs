@@ -0,0 +1,171 @@
+//! The GPUs of the machine and the one clients render on.
+//!
+//! GPUs are read from sysfs: each `cardN` DRM device, its render node, kernel driver and
+//! whether firmware booted on it. `SHIFT_GPU` (or [`ShiftRuntimeBuilder::gpu`]) picks one by
+//! card or render node path, or by driver name (`i915`, `amdgpu`); otherwise the GPU firmware
+//! booted on is picked, then the first one. Its render node goes to clients in `auth_ok`, so
+//! they allocate buffers there.
+//!
+//! EasyDRM opens the device it scans out on by itself, which needn't be the picked one. Buffers
+//! from another GPU are imported across devices, and copied through a CPU mapping when the
+//! scanout GPU can't sample them, see [`DmaBufTexture::import_or_copy`].
+//!
+//! [`ShiftRuntimeBuilder::gpu`]: crate::ShiftRuntimeBuilder::gpu
+//! [`DmaBufTexture::import_or_copy`]: crate::rendering_layer::dmabuf_import::DmaBufTexture::import_or_copy
+
+use std::{
+	path::{Path, PathBuf},
+	sync::OnceLock,
+};
+
+const SYSFS_DRM: &str = "/sys/class/drm";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gpu {
+	/// `/dev/dri/cardN`.
+	pub card: PathBuf,
+	/// `/dev/dri/renderDN`; `None` for display-only devices.
+	pub render_node: Option<PathBuf>,
+	pub driver: Option<String>,
+	/// Whether firmware booted on this GPU.
+	pub boot_vga: bool,
+}
+
+impl Gpu {
+	/// Whether `selector` names this GPU, by path or driver.
+	fn matches(&self, selector: &str) -> bool {
+		let path = Path::new(selector);
+		self.card == path
+			|| self.render_node.as_deref() == Some(path)
+			|| self.driver.as_deref() == Some(selector)
+	}
+}
+
+static SELECTOR: OnceLock<String> = OnceLock::new();
+static SELECTED: OnceLock<Option<Gpu>> = OnceLock::new();
+
+/// Overrides `SHIFT_GPU`. The selection is process-wide and made once, so this returns `false`
+/// when it comes too late.
+pub fn configure(selector: String) -> bool {
+	SELECTED.get().is_none() && SELECTOR.set(selector).is_ok()
+}
+
+/// The GPU clients render on, enumerated and picked on first use.
+pub fn selected() -> Option<&'static Gpu> {
+	SELECTED
+		.get_or_init(|| {
+			let gpus = enumerate();
+			for gpu in &gpus {
+				tracing::info!(
+					card = %gpu.card.display(),
+					render_node = ?gpu.render_node,
+					driver = gpu.driver.as_deref(),
+					boot_vga = gpu.boot_vga,
+					"found GPU"
+				);
+			}
+			let selector = SELECTOR
+				.get()
+				.cloned()
+				.or_else(|| std::env::var("SHIFT_GPU").ok())
+				.filter(|selector| !selector.is_empty());
+			if let Some(selector) = &selector
+				&& !gpus.iter().any(|gpu| gpu.matches(selector))
+			{
+				tracing::warn!(
+					selector = selector.as_str(),
+					"no GPU matches the selection, picking the default one"
+				);
+			}
+			let gpu = select(&gpus, selector.as_deref()).cloned();
+			match &gpu {
+				Some(gpu) => tracing::info!(card = %gpu.card.display(), "clients render on this GPU"),
+				None => tracing::warn!("no GPU found"),
+			}
+			gpu
+		})
+		.as_ref()
+}
+
+/// The render node clients should allocate on, as advertised in `auth_ok`.
+pub fn render_node() -> Option<String> {
+	selected()?
+		.render_node
+		.as_deref()
+		.map(|path| path.display().to_string())
+}
+
+/// The GPU `selector` names, else the boot GPU, else the first one.
+fn select<'a>(gpus: &'a [Gpu], selector: Option<&str>) -> Option<&'a Gpu> {
+	selector
+		.and_then(|selector| gpus.iter().find(|gpu| gpu.matches(selector)))
+		.or_else(|| gpus.iter().find(|gpu| gpu.boot_vga))
+		.or_else(|| gpus.first())
+}
+
+/// Every DRM card in sysfs, in card order.
+pub fn enumerate() -> Vec<Gpu> {
+	let Ok(entries) = std::fs::read_dir(SYSFS_DRM) else {
+		return Vec::new();
+	};
+	let mut cards: Vec<(u32, String)> = entries
+		.filter_map(|entry| {
+			let name = entry.ok()?.file_name().into_string().ok()?;
+			// Connectors are listed as `cardN-<connector>` next to their card.
+			let index = name.strip_prefix("card")?.parse().ok()?;
+			Some((index, name))
+		})
+		.collect();
+	cards.sort();
+	cards
+		.into_iter()
+		.map(|(_, name)| {
+			let device = Path::new(SYSFS_DRM).join(&name).join("device");
+			Gpu {
+				card: Path::new("/dev/dri").join(&name),
+				render_node: render_node_of(&device),
+				driver: std::fs::read_link(device.join("driver"))
+					.ok()
+					.and_then(|driver| Some(driver.file_name()?.to_str()?.to_string())),
+				boot_vga: std::fs::read_to_string(device.join("boot_vga"))
+					.is_ok_and(|value| value.trim() == "1"),
+			}
+		})
+		.collect()
+}
+
+/// The render node among the DRM minors of `device`.
+fn render_node_of(device: &Path) -> Option<PathBuf> {
+	std::fs::read_dir(device.join("drm"))
+		.ok()?
+		.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+		.find(|name| name.starts_with("renderD"))
+		.map(|name| Path::new("/dev/dri").join(name))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn gpu(index: u32, driver: &str, boot_vga: bool) -> Gpu {
+		Gpu {
+			card: PathBuf::from(format!("/dev/dri/card{index}")),
+			render_node: Some(PathBuf::from(format!("/dev/dri/renderD{}", 128 + index))),
+			driver: Some(driver.to_string()),
+			boot_vga,
+		}
+	}
+
+	#[test]
+	fn selects_named_then_boot_then_first_gpu() {
+		let gpus = [gpu(0, "amdgpu", false), gpu(1, "i915", true)];
+		assert_eq!(select(&gpus, None), Some(&gpus[1]), "boot GPU");
+		assert_eq!(select(&gpus, Some("amdgpu")), Some(&gpus[0]));
+		assert_eq!(select(&gpus, Some("/dev/dri/renderD128")), Some(&gpus[0]));
+		assert_eq!(select(&gpus, Some("/dev/dri/card1")), Some(&gpus[1]));
+		assert_eq!(select(&gpus, Some("nouveau")), Some(&gpus[1]));
+		let headless = [gpu(0, "amdgpu", false), gpu(1, "i915", false)];
+		assert_eq!(select(&headless, None), Some(&headless[0]));
+		assert_eq!(select(&[], Some("i915")), None);
+	}
+}
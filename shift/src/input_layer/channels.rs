@@ -1,6 +1,7 @@
-use crate::comms::input2server::{InputEvtRx, InputEvtTx};
-
-const DEFAULT_CHANNEL_CAPACITY: usize = 4096;
+use crate::comms::{
+	channel::{self, ChannelKind},
+	input2server::{InputEvtRx, InputEvtTx},
+};
 
 #[derive(Debug)]
 pub struct ServerEnd {
@@ -38,12 +39,9 @@ pub struct Channels {
 }
 
 impl Channels {
+	/// Sized and bounded by `SHIFT_INPUT_CHANNEL_CAPACITY` and `SHIFT_INPUT_CHANNEL_OVERFLOW`.
 	pub fn new() -> Self {
-		Self::with_capacity(DEFAULT_CHANNEL_CAPACITY)
-	}
-
-	pub fn with_capacity(capacity: usize) -> Self {
-		let (evt_tx, evt_rx) = tokio::sync::mpsc::channel(capacity);
+		let (evt_tx, evt_rx) = channel::channel(ChannelKind::Input);
 		Self {
 			server_end: ServerEnd::new(evt_rx),
 			input_end: InputEnd::new(evt_tx),
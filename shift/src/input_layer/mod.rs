@@ -35,7 +35,10 @@ use tab_protocol::{
 };
 use thiserror::Error;
 
-use crate::comms::input2server::{InputEvt, InputEvtTx};
+use crate::{
+	comms::input2server::{InputEvt, InputEvtTx},
+	realtime::ThreadTuning,
+};
 
 #[derive(Debug, Error)]
 pub enum InputError {
@@ -52,6 +55,7 @@ pub struct InputLayer {
 	tap_drag: bool,
 	tap_drag_lock: bool,
 	tap_button_map: TapButtonMap,
+	tuning: ThreadTuning,
 }
 
 impl InputLayer {
@@ -76,6 +80,7 @@ impl InputLayer {
 			tap_drag,
 			tap_drag_lock,
 			tap_button_map,
+			tuning: ThreadTuning::from_env("INPUT"),
 		}
 	}
 
@@ -88,9 +93,13 @@ impl InputLayer {
 			tap_drag_lock: self.tap_drag_lock,
 			tap_button_map: self.tap_button_map,
 		};
-		tokio::task::spawn_blocking(move || run_blocking(tx, seat, input_config))
-			.await
-			.map_err(|e| io::Error::other(format!("input task join error: {e}")))?
+		let tuning = self.tuning;
+		tokio::task::spawn_blocking(move || {
+			tuning.apply_to_current_thread("input");
+			run_blocking(tx, seat, input_config)
+		})
+		.await
+		.map_err(|e| io::Error::other(format!("input task join error: {e}")))?
 	}
 }
 
@@ -102,7 +111,7 @@ struct InputConfig {
 	tap_button_map: TapButtonMap,
 }
 
-fn env_bool(name: &str, default: bool) -> bool {
+pub(crate) fn env_bool(name: &str, default: bool) -> bool {
 	match std::env::var(name) {
 		Ok(v) => !matches!(
 			v.trim().to_ascii_lowercase().as_str(),
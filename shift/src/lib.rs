@@ -0,0 +1,29 @@
+//! Shift, the Tab compositor. [`ShiftRuntime`] runs the same layers as the `shift` binary
+//! from any tokio runtime, on DRM or headless.
+
+mod auth;
+mod client_layer;
+mod comms;
+mod crash_guard;
+mod drm_card;
+mod frame_trace;
+mod gpu;
+mod ids;
+mod input_layer;
+mod log_tail;
+mod monitor;
+mod realtime;
+mod rendering_layer;
+mod runtime;
+mod server_layer;
+mod sessions;
+mod startup;
+
+pub use comms::channel::{ChannelConfig, ChannelKind, OverflowPolicy};
+pub use crash_guard::install as install_crash_guard;
+pub use frame_trace::FrameTraceHandle;
+pub use log_tail::LogTailHandle;
+pub use rendering_layer::{HeadlessMonitor, Vblank, VblankBuffer, VblankError, VblankHandle};
+pub use runtime::{Backend, RuntimeError, ShiftRuntime, ShiftRuntimeBuilder};
+pub use server_layer::ListenSocket;
+pub use startup::mark_process_start;
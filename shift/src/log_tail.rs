@@ -0,0 +1,96 @@
+//! Keeps the most recent log lines in memory so the recovery screen can show them without a
+//! journal or log file to read from.
+
+use std::{
+	collections::VecDeque,
+	fmt::Write,
+	sync::{Arc, Mutex},
+};
+
+use tracing::{Event, Level, Subscriber, field::Field};
+use tracing_subscriber::{Layer, layer::Context};
+
+const MAX_LINES: usize = 200;
+
+/// Shared view of the buffered lines; cloned into the server.
+#[derive(Clone, Default)]
+pub struct LogTailHandle {
+	lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogTailHandle {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn layer(&self) -> LogTailLayer {
+		LogTailLayer {
+			lines: Arc::clone(&self.lines),
+		}
+	}
+
+	/// Up to `count` most recent lines, oldest first.
+	pub fn recent(&self, count: usize) -> Vec<String> {
+		let Ok(lines) = self.lines.lock() else {
+			return Vec::new();
+		};
+		lines
+			.iter()
+			.skip(lines.len().saturating_sub(count))
+			.cloned()
+			.collect()
+	}
+}
+
+pub struct LogTailLayer {
+	lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+/// Formats the `message` field first, then the remaining fields as `name=value`.
+#[derive(Default)]
+struct LineVisitor {
+	message: String,
+	fields: String,
+}
+
+impl tracing::field::Visit for LineVisitor {
+	fn record_str(&mut self, field: &Field, value: &str) {
+		if field.name() == "message" {
+			self.message.push_str(value);
+		} else {
+			let _ = write!(self.fields, " {}={value}", field.name());
+		}
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+		if field.name() == "message" {
+			let _ = write!(self.message, "{value:?}");
+		} else {
+			let _ = write!(self.fields, " {}={value:?}", field.name());
+		}
+	}
+}
+
+impl<S: Subscriber> Layer<S> for LogTailLayer {
+	fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+		let level = *event.metadata().level();
+		if level > Level::INFO {
+			return;
+		}
+		let mut visitor = LineVisitor::default();
+		event.record(&mut visitor);
+		let line = format!(
+			"{} {level:>5} {}{}",
+			chrono::Local::now().format("%H:%M:%S"),
+			visitor.message,
+			visitor.fields
+		);
+		let Ok(mut lines) = self.lines.lock() else {
+			return;
+		};
+		if lines.len() == MAX_LINES {
+			lines.pop_front();
+		}
+		lines.push_back(line);
+	}
+}
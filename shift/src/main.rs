@@ -1,28 +1,17 @@
-use std::path::PathBuf;
-
+use shift::{FrameTraceHandle, LogTailHandle, ShiftRuntime};
 use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::{
-	input_layer::{InputLayer, channels::Channels as InputChannels},
-	rendering_layer::{RenderingLayer, channels::Channels as RenderChannels},
-	server_layer::ShiftServer,
-};
-
-mod auth;
-mod client_layer;
-mod comms;
-mod ids;
-mod input_layer;
-mod monitor;
-mod rendering_layer;
-mod server_layer;
-mod sessions;
 #[tokio::main]
 async fn main() {
+	shift::mark_process_start();
 	// ---- logging/tracing ----
 	let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
+	let frame_trace = FrameTraceHandle::new();
+	let log_tail = LogTailHandle::new();
 	Registry::default()
 		.with(env_filter)
+		.with(frame_trace.layer())
+		.with(log_tail.layer())
 		.with(
 			tracing_subscriber::fmt::layer()
 				.with_target(false)
@@ -30,48 +19,15 @@ async fn main() {
 		)
 		// .with(tracing_tracy::TracyLayer::new(tracing_tracy::DefaultConfig::default()))
 		.init();
+	shift::install_crash_guard();
 
-	// ---- socket path ----
-	let socket_path = std::env::var_os("SHIFT_SOCKET")
-		.map(PathBuf::from)
-		.unwrap_or_else(|| "/tmp/shift.sock".into());
-
-	// ---- create inter-layer channels ----
-	let render_channels = RenderChannels::new();
-	let (server_render_channels, rendering_render_channels) = render_channels.split();
-	let input_channels = InputChannels::new();
-	let (server_input_channels, input_layer_channels) = input_channels.split();
-
-	// ---- create server ----
-	let mut server = match ShiftServer::bind(
-		&socket_path,
-		server_render_channels,
-		server_input_channels.into_parts(),
-	)
-	.await
-	{
-		Ok(s) => s,
-		Err(e) => {
-			tracing::error!("failed to bind ShiftServer at {:?}: {e}", socket_path);
-			return;
-		}
-	};
-	tracing::info!("starting ShiftServer on {:?}", socket_path);
-
-	// ---- create rendering ----
-	let rendering = match RenderingLayer::init(rendering_render_channels) {
-		Ok(r) => r,
-		Err(e) => {
-			tracing::error!("failed to init rendering layer: {e}");
-			return;
-		}
-	};
-	let input = InputLayer::init(input_layer_channels);
-	let result = tokio::join!(server.start(), rendering.run(), input.run());
-	if let Err(e) = result.1 {
-		tracing::error!("rendering thread ended with error: {e}");
-	}
-	if let Err(e) = result.2 {
-		tracing::error!("input layer ended with error: {e}");
+	let runtime = ShiftRuntime::builder()
+		.frame_trace(frame_trace)
+		.log_tail(log_tail)
+		.build()
+		.await;
+	match runtime {
+		Ok(runtime) => runtime.run().await,
+		Err(e) => tracing::error!("{e}"),
 	}
 }
@@ -1,24 +1,94 @@
-use crate::define_id_type;
-use tab_protocol::MonitorInfo as ProtocolMonitorInfo;
+mod stable_id;
+mod transform;
+
+pub use stable_id::{read_stable_id, stable_id, unique_id};
+pub use tab_protocol::MonitorId;
+use tab_protocol::{
+	FormatModifiers, HdrEotf, MonitorInfo as ProtocolMonitorInfo, MonitorMode, MonitorPosition,
+	MonitorScale, OutputTransform,
+};
+pub use transform::{input_to_logical, oriented, to_output};
 
-define_id_type!(Monitor, "mon_");
 #[derive(Debug, Clone)]
 pub struct Monitor {
 	pub id: MonitorId,
+	/// Size sessions render at. The renderer reports the mode's size, which the server swaps for
+	/// monitors on their side, see [`oriented`].
 	pub width: i32,
 	pub height: i32,
 	pub refresh_rate: u32,
+	/// DRM connector backing this monitor; internal, not exposed over the protocol.
+	pub connector_id: u32,
 	pub name: String,
+	/// Maintained by the server; the renderer always reports `false`.
+	pub primary: bool,
+	/// Fourccs Shift can import from this monitor's sessions.
+	pub formats: Vec<u32>,
+	/// The subset of `formats` the monitor's primary plane can scan out.
+	pub scanout_formats: Vec<u32>,
+	/// Explicit modifiers Shift can import, for the `formats` that have any.
+	pub format_modifiers: Vec<FormatModifiers>,
+	/// HDR transfer functions the monitor can display, empty when it is SDR-only.
+	pub hdr_eotfs: Vec<HdrEotf>,
+	/// Whether the connector reports `vrr_capable`.
+	pub vrr_capable: bool,
+	/// Virtual monitor an admin created for one session; composed offscreen, never scanned out.
+	pub offscreen: bool,
+	/// Maintained by the server; the renderer always reports `Normal`.
+	pub transform: OutputTransform,
+	/// Maintained by the server; the renderer always reports 1x.
+	pub scale: MonitorScale,
+	/// Modes the connector offers, in its orientation; empty for virtual monitors.
+	pub modes: Vec<MonitorMode>,
+	/// Maintained by the server; the renderer always reports the origin.
+	pub position: MonitorPosition,
 }
 
 impl Monitor {
 	pub fn to_protocol_info(&self) -> ProtocolMonitorInfo {
 		ProtocolMonitorInfo {
-			id: self.id.to_string(),
+			id: self.id,
 			width: self.width,
 			height: self.height,
 			refresh_rate: self.refresh_rate as i32,
 			name: self.name.clone(),
+			primary: self.primary,
+			formats: self.formats.clone(),
+			scanout_formats: self.scanout_formats.clone(),
+			format_modifiers: self.format_modifiers.clone(),
+			hdr_eotfs: self.hdr_eotfs.clone(),
+			vrr_capable: self.vrr_capable,
+			offscreen: self.offscreen,
+			transform: self.transform,
+			scale: self.scale,
+			modes: self.modes.clone(),
+			position: self.position,
+		}
+	}
+}
+
+#[cfg(test)]
+impl Monitor {
+	/// A connected 1920x1080 monitor at 60 Hz, for tests to adjust.
+	pub fn fixture(id: &str, name: &str, connector_id: u32) -> Self {
+		Self {
+			id: id.parse().expect("monitor id"),
+			width: 1920,
+			height: 1080,
+			refresh_rate: 60,
+			connector_id,
+			name: name.to_string(),
+			primary: false,
+			formats: Vec::new(),
+			scanout_formats: Vec::new(),
+			format_modifiers: Vec::new(),
+			hdr_eotfs: Vec::new(),
+			vrr_capable: false,
+			offscreen: false,
+			transform: Default::default(),
+			scale: Default::default(),
+			modes: Vec::new(),
+			position: Default::default(),
 		}
 	}
 }
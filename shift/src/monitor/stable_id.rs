@@ -0,0 +1,87 @@
+//! Monitor ids that survive reconnects and restarts.
+//!
+//! A monitor's id is a 64-bit FNV-1a hash of its connector's name (`DP-1`) and its EDID, so the
+//! same screen on the same port gets the same id every time Shift sees it and clients can keep
+//! configuration against it. Screens without an EDID hash their connector alone.
+//!
+//! Should two connected screens hash alike, the one assigned later hashes its connector name
+//! with a `#2`, `#3`, … suffix until its id is free. A connected monitor keeps its id; only
+//! the newcomer is suffixed.
+
+use super::MonitorId;
+use crate::drm_card;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The id of the monitor on `connector` with `edid`.
+pub fn stable_id(connector: &str, edid: Option<&[u8]>) -> MonitorId {
+	let mut hash = FNV_OFFSET_BASIS;
+	// The separator keeps a connector name from running into the EDID bytes.
+	let bytes = connector
+		.bytes()
+		.chain([0])
+		.chain(edid.unwrap_or_default().iter().copied());
+	for byte in bytes {
+		hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+	}
+	MonitorId::from_raw(hash)
+}
+
+/// The id of the monitor on `connector` with `edid` that `taken` doesn't hold yet.
+pub fn unique_id(
+	connector: &str,
+	edid: Option<&[u8]>,
+	taken: impl Fn(MonitorId) -> bool,
+) -> MonitorId {
+	let id = stable_id(connector, edid);
+	if !taken(id) {
+		return id;
+	}
+	(2u32..)
+		.map(|n| stable_id(&format!("{connector}#{n}"), edid))
+		.find(|id| !taken(*id))
+		.expect("a free monitor id")
+}
+
+/// Reads the connector name and EDID of `connector_id` and derives its monitor's id, one
+/// `taken` doesn't hold.
+pub fn read_stable_id(connector_id: u32, taken: impl Fn(MonitorId) -> bool) -> MonitorId {
+	let connector =
+		drm_card::connector_name(connector_id).unwrap_or_else(|| format!("connector-{connector_id}"));
+	unique_id(
+		&connector,
+		drm_card::read_edid(connector_id).as_deref(),
+		taken,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ids_depend_on_connector_and_edid() {
+		let edid = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x10, 0xac];
+		let id = stable_id("DP-1", Some(&edid));
+		assert_eq!(id, stable_id("DP-1", Some(&edid)));
+		assert_ne!(id, stable_id("DP-2", Some(&edid)));
+		assert_ne!(id, stable_id("DP-1", Some(&edid[..8])));
+	}
+
+	#[test]
+	fn colliding_ids_get_a_suffix() {
+		let first = stable_id("DP-1", None);
+		assert_eq!(unique_id("DP-1", None, |_| false), first);
+		let second = unique_id("DP-1", None, |id| id == first);
+		assert_eq!(second, stable_id("DP-1#2", None));
+		let third = unique_id("DP-1", None, |id| id == first || id == second);
+		assert_eq!(third, stable_id("DP-1#3", None));
+	}
+
+	#[test]
+	fn ids_are_stable_across_builds() {
+		// Clients persist these, so the hash must never change.
+		assert_eq!(stable_id("DP-1", None).to_string(), "mon_c97d8a3417a6dcf5");
+	}
+}
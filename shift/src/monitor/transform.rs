@@ -0,0 +1,103 @@
+//! Geometry of [`OutputTransform`]s. The renderer reports monitors at their mode's size and
+//! draws in output pixels; the server sizes them for sessions ([`oriented`]) and maps absolute
+//! input from the panel to what sessions see ([`input_to_logical`]).
+
+use tab_protocol::OutputTransform;
+
+use super::Monitor;
+
+/// `monitor` as sessions see it under `transform`, whatever transform it had before.
+pub fn oriented(mut monitor: Monitor, transform: OutputTransform) -> Monitor {
+	if monitor.transform.swaps_axes() != transform.swaps_axes() {
+		std::mem::swap(&mut monitor.width, &mut monitor.height);
+	}
+	monitor.transform = transform;
+	monitor
+}
+
+/// Affine map `[scale_x, skew_x, trans_x, skew_y, scale_y, trans_y]` from a monitor's logical
+/// pixels, `logical_size` large, to its output pixels.
+pub fn to_output(transform: OutputTransform, logical_size: (f32, f32)) -> [f32; 6] {
+	let (w, h) = logical_size;
+	match transform {
+		OutputTransform::Normal => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+		OutputTransform::Rotate90 => [0.0, 1.0, 0.0, -1.0, 0.0, w],
+		OutputTransform::Rotate180 => [-1.0, 0.0, w, 0.0, -1.0, h],
+		OutputTransform::Rotate270 => [0.0, -1.0, h, 1.0, 0.0, 0.0],
+		OutputTransform::Flipped => [-1.0, 0.0, w, 0.0, 1.0, 0.0],
+		OutputTransform::Flipped90 => [0.0, 1.0, 0.0, 1.0, 0.0, 0.0],
+		OutputTransform::Flipped180 => [1.0, 0.0, 0.0, 0.0, -1.0, h],
+		OutputTransform::Flipped270 => [0.0, -1.0, h, -1.0, 0.0, w],
+	}
+}
+
+/// Maps an absolute position normalized to the panel, as touchscreens and tablets report it,
+/// to the same normalized position on the monitor sessions see.
+pub fn input_to_logical(transform: OutputTransform, x: f64, y: f64) -> (f64, f64) {
+	// Only the rotations by a quarter turn aren't their own inverse.
+	let inverse = match transform {
+		OutputTransform::Rotate90 => OutputTransform::Rotate270,
+		OutputTransform::Rotate270 => OutputTransform::Rotate90,
+		other => other,
+	};
+	let [sx, kx, tx, ky, sy, ty] = to_output(inverse, (1.0, 1.0)).map(f64::from);
+	(sx * x + kx * y + tx, ky * x + sy * y + ty)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ALL: [OutputTransform; 8] = [
+		OutputTransform::Normal,
+		OutputTransform::Rotate90,
+		OutputTransform::Rotate180,
+		OutputTransform::Rotate270,
+		OutputTransform::Flipped,
+		OutputTransform::Flipped90,
+		OutputTransform::Flipped180,
+		OutputTransform::Flipped270,
+	];
+
+	fn apply(map: [f32; 6], (x, y): (f32, f32)) -> (f32, f32) {
+		let [sx, kx, tx, ky, sy, ty] = map;
+		(sx * x + kx * y + tx, ky * x + sy * y + ty)
+	}
+
+	#[test]
+	fn input_follows_what_is_drawn() {
+		// A portrait monitor on a 1920x1080 panel, turned so its top is on the panel's left.
+		let top_left = apply(
+			to_output(OutputTransform::Rotate90, (1080.0, 1920.0)),
+			(0.0, 0.0),
+		);
+		assert_eq!(top_left, (0.0, 1080.0));
+		for transform in ALL {
+			let logical = if transform.swaps_axes() {
+				(1080.0, 1920.0)
+			} else {
+				(1920.0, 1080.0)
+			};
+			let output = if transform.swaps_axes() {
+				(logical.1, logical.0)
+			} else {
+				logical
+			};
+			let map = to_output(transform, logical);
+			for point in [(0.0, 0.0), (270.0, 480.0), (logical.0, logical.1)] {
+				let (x, y) = apply(map, point);
+				assert!((0.0..=output.0).contains(&x) && (0.0..=output.1).contains(&y));
+				let (lx, ly) =
+					input_to_logical(transform, f64::from(x / output.0), f64::from(y / output.1));
+				assert!(
+					(lx - f64::from(point.0 / logical.0)).abs() < 1e-6,
+					"{transform:?}"
+				);
+				assert!(
+					(ly - f64::from(point.1 / logical.1)).abs() < 1e-6,
+					"{transform:?}"
+				);
+			}
+		}
+	}
+}
@@ -0,0 +1,272 @@
+//! Scheduling policy and CPU affinity for the latency-critical threads.
+//!
+//! `SHIFT_RENDER_SCHED` / `SHIFT_INPUT_SCHED` pick the policy: `other` (default), `fifo:<prio>`
+//! or `deadline:<runtime_us>/<period_us>`. `SHIFT_RENDER_CPUS` / `SHIFT_INPUT_CPUS` pin the
+//! thread to a CPU list such as `2-3,6`. Privileged policies are checked against
+//! `CAP_SYS_NICE` and `RLIMIT_RTPRIO` first; anything the process may not do is logged and the
+//! thread keeps running with the normal scheduler.
+
+use std::io;
+
+/// `SCHED_DEADLINE` is only reachable through `sched_setattr`, which libc does not wrap.
+const SCHED_DEADLINE: u32 = 6;
+const CAP_SYS_NICE: u32 = 23;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedPolicy {
+	#[default]
+	Other,
+	Fifo {
+		priority: i32,
+	},
+	Deadline {
+		runtime_us: u64,
+		period_us: u64,
+	},
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadTuning {
+	pub policy: SchedPolicy,
+	pub cpus: Vec<usize>,
+}
+
+/// Mirrors the kernel's `struct sched_attr`.
+#[repr(C)]
+#[derive(Default)]
+struct SchedAttr {
+	size: u32,
+	sched_policy: u32,
+	sched_flags: u64,
+	sched_nice: i32,
+	sched_priority: u32,
+	sched_runtime: u64,
+	sched_deadline: u64,
+	sched_period: u64,
+}
+
+impl ThreadTuning {
+	/// Reads `SHIFT_<prefix>_SCHED` and `SHIFT_<prefix>_CPUS`.
+	pub fn from_env(prefix: &str) -> Self {
+		let sched_var = format!("SHIFT_{prefix}_SCHED");
+		let cpus_var = format!("SHIFT_{prefix}_CPUS");
+		let policy = match std::env::var(&sched_var) {
+			Ok(v) => parse_policy(&v).unwrap_or_else(|| {
+				tracing::warn!(
+					name = sched_var,
+					value = v,
+					"invalid scheduling policy, ignoring"
+				);
+				SchedPolicy::Other
+			}),
+			Err(_) => SchedPolicy::Other,
+		};
+		let cpus = match std::env::var(&cpus_var) {
+			Ok(v) => parse_cpu_list(&v).unwrap_or_else(|| {
+				tracing::warn!(name = cpus_var, value = v, "invalid CPU list, ignoring");
+				Vec::new()
+			}),
+			Err(_) => Vec::new(),
+		};
+		Self { policy, cpus }
+	}
+
+	/// Applies the tuning to the calling thread. Failures are logged, never fatal.
+	pub fn apply_to_current_thread(&self, thread: &'static str) {
+		// SCHED_DEADLINE tasks may not have a restricted affinity, so pin first and let the
+		// policy change report the conflict.
+		if !self.cpus.is_empty() {
+			match set_affinity(&self.cpus) {
+				Ok(()) => tracing::info!(thread, cpus = ?self.cpus, "pinned thread"),
+				Err(e) => tracing::warn!(thread, cpus = ?self.cpus, "failed to pin thread: {e}"),
+			}
+		}
+		let policy = match clamp_to_limits(self.policy) {
+			Ok(policy) => policy,
+			Err(reason) => {
+				tracing::warn!(thread, policy = ?self.policy, "keeping normal scheduling: {reason}");
+				return;
+			}
+		};
+		match set_policy(policy) {
+			Ok(()) if policy != SchedPolicy::Other => {
+				tracing::info!(thread, ?policy, "realtime scheduling enabled")
+			}
+			Ok(()) => {}
+			Err(e) => tracing::warn!(thread, ?policy, "keeping normal scheduling: {e}"),
+		}
+	}
+}
+
+/// Lowers a FIFO priority to `RLIMIT_RTPRIO` when the process lacks `CAP_SYS_NICE`.
+fn clamp_to_limits(policy: SchedPolicy) -> Result<SchedPolicy, String> {
+	if policy == SchedPolicy::Other || has_sys_nice() {
+		return Ok(policy);
+	}
+	match policy {
+		SchedPolicy::Fifo { priority } => {
+			let mut limit = libc::rlimit {
+				rlim_cur: 0,
+				rlim_max: 0,
+			};
+			// SAFETY: getrlimit writes into the struct we own.
+			if unsafe { libc::getrlimit(libc::RLIMIT_RTPRIO, &mut limit) } != 0 {
+				return Err(io::Error::last_os_error().to_string());
+			}
+			let allowed = i32::try_from(limit.rlim_cur).unwrap_or(i32::MAX);
+			if allowed < 1 {
+				return Err("no CAP_SYS_NICE and RLIMIT_RTPRIO is 0".into());
+			}
+			if priority > allowed {
+				tracing::warn!(
+					priority,
+					allowed,
+					"clamping realtime priority to RLIMIT_RTPRIO"
+				);
+			}
+			Ok(SchedPolicy::Fifo {
+				priority: priority.min(allowed),
+			})
+		}
+		SchedPolicy::Deadline { .. } => Err("SCHED_DEADLINE requires CAP_SYS_NICE".into()),
+		SchedPolicy::Other => Ok(policy),
+	}
+}
+
+fn has_sys_nice() -> bool {
+	let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+		return false;
+	};
+	status
+		.lines()
+		.find_map(|line| line.strip_prefix("CapEff:"))
+		.and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+		.is_some_and(|caps| caps & (1 << CAP_SYS_NICE) != 0)
+}
+
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+	// SAFETY: cpu_set_t is plain data; parse_cpu_list keeps every CPU below CPU_SETSIZE, which
+	// CPU_SET does not check.
+	unsafe {
+		let mut set: libc::cpu_set_t = std::mem::zeroed();
+		for &cpu in cpus {
+			libc::CPU_SET(cpu, &mut set);
+		}
+		if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+			return Err(io::Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+fn set_policy(policy: SchedPolicy) -> io::Result<()> {
+	let mut attr = SchedAttr {
+		size: std::mem::size_of::<SchedAttr>() as u32,
+		..Default::default()
+	};
+	match policy {
+		SchedPolicy::Other => attr.sched_policy = libc::SCHED_OTHER as u32,
+		SchedPolicy::Fifo { priority } => {
+			attr.sched_policy = libc::SCHED_FIFO as u32;
+			attr.sched_priority = priority as u32;
+		}
+		SchedPolicy::Deadline {
+			runtime_us,
+			period_us,
+		} => {
+			attr.sched_policy = SCHED_DEADLINE;
+			attr.sched_runtime = runtime_us * 1000;
+			attr.sched_deadline = period_us * 1000;
+			attr.sched_period = period_us * 1000;
+		}
+	}
+	// SAFETY: pid 0 is the calling thread; attr outlives the call.
+	if unsafe { libc::syscall(libc::SYS_sched_setattr, 0, &attr as *const SchedAttr, 0) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn parse_policy(value: &str) -> Option<SchedPolicy> {
+	let value = value.trim().to_ascii_lowercase();
+	let (kind, args) = value.split_once(':').unwrap_or((value.as_str(), ""));
+	match kind {
+		"other" | "" => Some(SchedPolicy::Other),
+		"fifo" => {
+			let priority: i32 = args.trim().parse().ok()?;
+			(1..=99)
+				.contains(&priority)
+				.then_some(SchedPolicy::Fifo { priority })
+		}
+		"deadline" => {
+			let (runtime, period) = args.split_once('/')?;
+			let runtime_us: u64 = runtime.trim().parse().ok()?;
+			let period_us: u64 = period.trim().parse().ok()?;
+			(runtime_us > 0 && runtime_us <= period_us).then_some(SchedPolicy::Deadline {
+				runtime_us,
+				period_us,
+			})
+		}
+		_ => None,
+	}
+}
+
+/// Parses a CPU list such as `2-3,6`. CPUs a `cpu_set_t` can't hold make the list invalid.
+fn parse_cpu_list(value: &str) -> Option<Vec<usize>> {
+	let fits = |cpu: usize| cpu < libc::CPU_SETSIZE as usize;
+	let mut cpus = Vec::new();
+	for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+		match part.split_once('-') {
+			Some((start, end)) => {
+				let start: usize = start.trim().parse().ok()?;
+				let end: usize = end.trim().parse().ok()?;
+				if start > end || !fits(end) {
+					return None;
+				}
+				cpus.extend(start..=end);
+			}
+			None => cpus.push(part.parse().ok().filter(|&cpu| fits(cpu))?),
+		}
+	}
+	Some(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_policies() {
+		assert_eq!(parse_policy("other"), Some(SchedPolicy::Other));
+		assert_eq!(
+			parse_policy("FIFO:10"),
+			Some(SchedPolicy::Fifo { priority: 10 })
+		);
+		assert_eq!(parse_policy("fifo:0"), None);
+		assert_eq!(
+			parse_policy("deadline:2000/16666"),
+			Some(SchedPolicy::Deadline {
+				runtime_us: 2000,
+				period_us: 16666
+			})
+		);
+		assert_eq!(parse_policy("deadline:20000/16666"), None);
+		assert_eq!(parse_policy("rr:5"), None);
+	}
+
+	#[test]
+	fn parses_cpu_lists() {
+		assert_eq!(parse_cpu_list("0-2, 5"), Some(vec![0, 1, 2, 5]));
+		assert_eq!(parse_cpu_list(""), Some(vec![]));
+		assert_eq!(parse_cpu_list("3-1"), None);
+		assert_eq!(parse_cpu_list("a"), None);
+	}
+
+	#[test]
+	fn rejects_cpus_beyond_the_cpu_set() {
+		let last = libc::CPU_SETSIZE as usize - 1;
+		assert_eq!(parse_cpu_list(&last.to_string()), Some(vec![last]));
+		assert_eq!(parse_cpu_list("1024"), None);
+		assert_eq!(parse_cpu_list("0-100000000"), None);
+	}
+}
@@ -34,6 +34,10 @@ impl AnimationRegistry {
 	pub fn get(&self, name: &str) -> Option<&dyn Animation> {
 		self.animations.get(name).map(|v| v.as_ref())
 	}
+
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.animations.keys().map(String::as_str)
+	}
 }
 
 #[derive(Default)]
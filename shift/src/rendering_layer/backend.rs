@@ -0,0 +1,248 @@
+//! What the rendering layer needs from the device it draws on.
+//!
+//! [`RenderingLayer`](super::RenderingLayer) composes with Skia over GL and imports client
+//! buffers through EGL, so a backend provides a current GL context per output, an EGL proc
+//! address resolver, and a commit that presents whatever was drawn. Everything else (command
+//! handling, buffer ownership, fences, pacing) stays in the layer.
+//!
+//! EasyDRM is the only GL backend. [`HeadlessRenderer`](super::HeadlessRenderer) has no GPU
+//! and keeps its own loop instead of implementing this trait.
+
+use std::{ffi::c_void, future::Future, os::fd::RawFd};
+
+use drm::control::Mode;
+use easydrm::{EasyDRM, EasyDRMError, Monitor};
+use thiserror::Error;
+
+use crate::{comms::server2render::CursorImage, drm_card};
+
+use super::scanout::{PlaneLayer, ScanoutBuffer};
+use super::surface_cache::MonitorRenderState;
+
+/// The KMS backend the `shift` binary renders with.
+pub type DrmBackend = EasyDRM<MonitorRenderState>;
+
+/// Resolves EGL and GL entry points. Owned, so it can be used while outputs are borrowed.
+pub type ProcLoader = Box<dyn Fn(&str) -> *const c_void>;
+
+/// A backend failure. The rendering layer retries the call unless
+/// [`RenderBackend::is_removed`] says the device went away.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct BackendError(String);
+
+impl BackendError {
+	pub fn new(message: impl Into<String>) -> Self {
+		Self(message.into())
+	}
+}
+
+/// Result of presenting a frame.
+pub struct Commit {
+	/// Whether any output took a new frame.
+	pub committed_any: bool,
+	/// Fence signalled once the GPU finished the committed frame, if the backend made one. It
+	/// stays open until the next commit.
+	pub render_fence: Option<RawFd>,
+}
+
+pub trait RenderBackend: Sized {
+	type Output: BackendOutput;
+
+	/// Opens a device and creates render state for each of its outputs.
+	fn open() -> Result<Self, BackendError>;
+
+	/// Makes the device's shared context current, for creating the Skia context.
+	fn make_current(&self) -> Result<(), BackendError>;
+
+	fn proc_loader(&self) -> ProcLoader;
+
+	fn outputs(&self) -> impl Iterator<Item = &Self::Output>;
+
+	fn outputs_mut(&mut self) -> impl Iterator<Item = &mut Self::Output>;
+
+	/// Waits for the device's next events (page flips, hotplug) and processes them.
+	fn poll_events(&mut self) -> impl Future<Output = Result<(), BackendError>>;
+
+	/// Presents what was drawn on every output since the last commit.
+	fn commit(&mut self) -> Result<Commit, BackendError>;
+
+	/// Whether the device is gone (unplugged, driver unbound), asked after a call failed.
+	fn is_removed(&self) -> bool;
+}
+
+pub trait BackendOutput {
+	fn context(&self) -> &MonitorRenderState;
+
+	fn context_mut(&mut self) -> &mut MonitorRenderState;
+
+	/// DRM connector the output scans out on; connector properties (tiles, HDR, plane formats)
+	/// are read through it.
+	fn connector_id(&self) -> u32;
+
+	/// Size of the active mode in pixels.
+	fn size(&self) -> (u32, u32);
+
+	/// In Hz.
+	fn refresh_rate(&self) -> u32;
+
+	/// Switches the output to `mode`, one its connector offers; its size may change. Returns
+	/// `false` when the backend can't change modes.
+	fn set_mode(&mut self, mode: Mode) -> Result<bool, BackendError>;
+
+	fn make_current(&self) -> Result<(), BackendError>;
+
+	/// Whether a frame drawn now would be presented, i.e. no flip is pending.
+	fn can_render(&self) -> bool;
+
+	/// Whether the output was drawn since the last commit.
+	fn was_drawn(&self) -> bool;
+
+	/// Puts `buffer` on the output's primary plane and each of `overlays` on an overlay plane
+	/// above it, in order, for the next commit instead of a drawn frame; the output then counts
+	/// as drawn. Returns `false` when the backend can't place all of them, and the frame is
+	/// composed as usual.
+	fn scan_out(
+		&mut self,
+		buffer: &ScanoutBuffer,
+		overlays: &[PlaneLayer<'_>],
+	) -> Result<bool, BackendError>;
+
+	/// Shows `cursor` on the output's cursor plane, or turns the plane off when `None`. Takes
+	/// effect without a commit. Returns `false` when the plane can't show the image, and the
+	/// cursor is composed instead.
+	fn set_cursor(&mut self, cursor: Option<&CursorImage>) -> Result<bool, BackendError>;
+
+	/// Moves the cursor plane so the image's top-left lands at `position`, in output pixels.
+	fn move_cursor(&mut self, position: (i32, i32)) -> Result<(), BackendError>;
+}
+
+impl From<EasyDRMError> for BackendError {
+	fn from(error: EasyDRMError) -> Self {
+		Self(error.to_string())
+	}
+}
+
+impl RenderBackend for DrmBackend {
+	type Output = Monitor<MonitorRenderState>;
+
+	fn open() -> Result<Self, BackendError> {
+		let backend =
+			EasyDRM::init(|req| MonitorRenderState::new(req).expect("MonitorRenderState::new failed"))?;
+		locate_scanout(&backend);
+		Ok(backend)
+	}
+
+	fn make_current(&self) -> Result<(), BackendError> {
+		EasyDRM::make_current(self).map_err(|e| BackendError::new(format!("{e:?}")))
+	}
+
+	fn proc_loader(&self) -> ProcLoader {
+		let egl_context = self.egl_context();
+		Box::new(move |symbol| {
+			egl_context
+				.lock()
+				.map(|ctx| ctx.get_proc_address(symbol))
+				.unwrap_or(std::ptr::null())
+		})
+	}
+
+	fn outputs(&self) -> impl Iterator<Item = &Self::Output> {
+		self.monitors()
+	}
+
+	fn outputs_mut(&mut self) -> impl Iterator<Item = &mut Self::Output> {
+		self.monitors_mut()
+	}
+
+	async fn poll_events(&mut self) -> Result<(), BackendError> {
+		let outputs = self.monitors().count();
+		self.poll_events_async().await?;
+		// Outputs that come up later may tell the card apart when those at open couldn't.
+		if self.monitors().count() != outputs && drm_card::scanout_path().is_none() {
+			locate_scanout(self);
+		}
+		Ok(())
+	}
+
+	fn commit(&mut self) -> Result<Commit, BackendError> {
+		let result = self.swap_buffers_with_result()?;
+		Ok(Commit {
+			committed_any: !result.committed_connectors.is_empty(),
+			render_fence: (result.render_fence >= 0).then_some(result.render_fence),
+		})
+	}
+
+	fn is_removed(&self) -> bool {
+		drm_card::scanout_removed()
+	}
+}
+
+fn locate_scanout(backend: &DrmBackend) {
+	let connectors: Vec<u32> = backend
+		.monitors()
+		.map(|mon| u32::from(mon.connector_id()))
+		.collect();
+	drm_card::locate_scanout(&connectors);
+}
+
+impl BackendOutput for Monitor<MonitorRenderState> {
+	fn context(&self) -> &MonitorRenderState {
+		Monitor::context(self)
+	}
+
+	fn context_mut(&mut self) -> &mut MonitorRenderState {
+		Monitor::context_mut(self)
+	}
+
+	fn connector_id(&self) -> u32 {
+		u32::from(Monitor::connector_id(self))
+	}
+
+	fn size(&self) -> (u32, u32) {
+		let (width, height) = Monitor::size(self);
+		(width as u32, height as u32)
+	}
+
+	fn refresh_rate(&self) -> u32 {
+		self.active_mode().vrefresh()
+	}
+
+	fn set_mode(&mut self, _mode: Mode) -> Result<bool, BackendError> {
+		// EasyDRM picks each output's mode when the connector comes up and can't change it
+		// afterwards.
+		Ok(false)
+	}
+
+	fn make_current(&self) -> Result<(), BackendError> {
+		Monitor::make_current(self).map_err(|e| BackendError::new(format!("{e:?}")))
+	}
+
+	fn can_render(&self) -> bool {
+		Monitor::can_render(self)
+	}
+
+	fn was_drawn(&self) -> bool {
+		Monitor::was_drawn(self)
+	}
+
+	fn scan_out(
+		&mut self,
+		_buffer: &ScanoutBuffer,
+		_overlays: &[PlaneLayer<'_>],
+	) -> Result<bool, BackendError> {
+		// EasyDRM commits the primary plane from its own GBM surface, exposes no overlay planes
+		// and can't take a foreign framebuffer yet, so every frame is composed.
+		Ok(false)
+	}
+
+	fn set_cursor(&mut self, cursor: Option<&CursorImage>) -> Result<bool, BackendError> {
+		let connector_id = BackendOutput::connector_id(self);
+		Ok(self.context_mut().cursor_plane.set(connector_id, cursor))
+	}
+
+	fn move_cursor(&mut self, position: (i32, i32)) -> Result<(), BackendError> {
+		self.context().cursor_plane.move_to(position);
+		Ok(())
+	}
+}
@@ -0,0 +1,150 @@
+//! Debug mode (`SHIFT_DEBUG_BUFFER_HASH=1`) hashing a sample of every client buffer Shift
+//! releases after showing it. The hash goes out with the release as `buffer_hash`, so client
+//! teams chasing tearing or stale-frame reports can tell which frame actually reached the
+//! screen. Buffers are read through a CPU mapping of the dmabuf, see
+//! [`tab_protocol::buffer_hash`]; that costs a mapping per release, so leave it off otherwise.
+
+use std::{
+	collections::HashMap,
+	io,
+	os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+};
+
+use super::state::SlotKey;
+
+/// `_IOW('b', 0, struct dma_buf_sync)`, see `linux/dma-buf.h`.
+const DMA_BUF_IOCTL_SYNC: u64 = 0x4008_6200;
+const DMA_BUF_SYNC_READ: u64 = 1 << 0;
+const DMA_BUF_SYNC_START: u64 = 0;
+const DMA_BUF_SYNC_END: u64 = 1 << 2;
+
+#[derive(Debug)]
+struct Linked {
+	fd: OwnedFd,
+	height: u32,
+	stride: u32,
+	offset: u32,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct BufferHashes {
+	enabled: bool,
+	linked: HashMap<SlotKey, Linked>,
+}
+
+impl BufferHashes {
+	pub fn from_env() -> Self {
+		Self {
+			enabled: std::env::var("SHIFT_DEBUG_BUFFER_HASH").is_ok_and(|v| v == "1"),
+			linked: HashMap::new(),
+		}
+	}
+
+	/// Keeps a handle on the buffer linked into `key`. Does nothing unless enabled.
+	pub fn link(&mut self, key: SlotKey, fd: BorrowedFd<'_>, height: i32, stride: i32, offset: i32) {
+		if !self.enabled {
+			return;
+		}
+		self.linked.remove(&key);
+		let (Ok(height), Ok(stride), Ok(offset)) = (
+			u32::try_from(height),
+			u32::try_from(stride),
+			u32::try_from(offset),
+		) else {
+			return;
+		};
+		match fd.try_clone_to_owned() {
+			Ok(fd) => {
+				self.linked.insert(
+					key,
+					Linked {
+						fd,
+						height,
+						stride,
+						offset,
+					},
+				);
+			}
+			Err(e) => tracing::warn!(?key, "cannot keep buffer for hashing: {e}"),
+		}
+	}
+
+	pub fn retain(&mut self, mut keep: impl FnMut(&SlotKey) -> bool) {
+		self.linked.retain(|key, _| keep(key));
+	}
+
+	/// The hash of the buffer in `key`; `None` when disabled or the buffer can't be read.
+	pub fn hash(&self, key: SlotKey) -> Option<u64> {
+		let linked = self.linked.get(&key)?;
+		match linked.hash() {
+			Ok(hash) => {
+				if hash.is_none() {
+					tracing::warn!(
+						?key,
+						"buffer is smaller than its linked layout, not hashing it"
+					);
+				}
+				hash
+			}
+			Err(e) => {
+				tracing::warn!(?key, "failed to map buffer for hashing: {e}");
+				None
+			}
+		}
+	}
+}
+
+impl Linked {
+	fn hash(&self) -> io::Result<Option<u64>> {
+		let hash = read_mapped(self.fd.as_fd(), |bytes| {
+			tab_protocol::buffer_hash::sample_hash(bytes, self.height, self.stride, self.offset)
+		})?;
+		Ok(hash.flatten())
+	}
+}
+
+/// Runs `read` on a CPU mapping of the whole dmabuf `fd`, bracketed for exporters that need
+/// cache maintenance. `None` for empty buffers.
+pub(super) fn read_mapped<R>(
+	fd: BorrowedFd<'_>,
+	read: impl FnOnce(&[u8]) -> R,
+) -> io::Result<Option<R>> {
+	let raw = fd.as_raw_fd();
+	let len = unsafe { libc::lseek(raw, 0, libc::SEEK_END) };
+	if len < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	if len == 0 {
+		return Ok(None);
+	}
+	let len = len as usize;
+	let data = unsafe {
+		libc::mmap(
+			std::ptr::null_mut(),
+			len,
+			libc::PROT_READ,
+			libc::MAP_SHARED,
+			raw,
+			0,
+		)
+	};
+	if data == libc::MAP_FAILED {
+		return Err(io::Error::last_os_error());
+	}
+	sync(fd, DMA_BUF_SYNC_START | DMA_BUF_SYNC_READ);
+	let bytes = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) };
+	let result = read(bytes);
+	sync(fd, DMA_BUF_SYNC_END | DMA_BUF_SYNC_READ);
+	unsafe {
+		libc::munmap(data, len);
+	}
+	Ok(Some(result))
+}
+
+/// Brackets CPU access for exporters that need cache maintenance; failing only means they
+/// don't.
+fn sync(fd: BorrowedFd<'_>, flags: u64) {
+	unsafe {
+		libc::ioctl(fd.as_raw_fd(), DMA_BUF_IOCTL_SYNC as _, &flags);
+	}
+}
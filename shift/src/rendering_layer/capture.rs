@@ -0,0 +1,252 @@
+//! Screenshots the server asks for with [`RenderCmd::Capture`].
+//!
+//! A capture waits for the next frame composed for its logical monitor and reads each of the
+//! monitor's outputs back right after their flush, every tile into its slice of the image.
+//! Outputs with a capture pending compose rather than scan a buffer out, so the readback is
+//! what the monitor shows.
+//!
+//! [`RenderCmd::Capture`]: crate::comms::server2render::RenderCmd::Capture
+
+use std::{
+	collections::HashMap,
+	fs::File,
+	io,
+	os::{
+		fd::{FromRawFd, OwnedFd},
+		unix::fs::FileExt,
+	},
+	sync::Arc,
+};
+
+use crate::{comms::render2server::CapturedFrame, monitor::MonitorId};
+
+#[derive(Debug, Default)]
+pub(super) struct Captures {
+	/// Keyed by logical monitor.
+	pending: HashMap<MonitorId, PendingCapture>,
+}
+
+#[derive(Debug)]
+struct PendingCapture {
+	size: (u32, u32),
+	pixels: Vec<u8>,
+	/// Connector-level outputs not read back yet.
+	remaining: Vec<MonitorId>,
+	failed: Option<Arc<str>>,
+}
+
+impl Captures {
+	/// Starts capturing `monitor_id`, `size` pixels large and shown on `outputs`. A capture
+	/// already pending for it is kept.
+	pub fn request(&mut self, monitor_id: MonitorId, size: (u32, u32), outputs: Vec<MonitorId>) {
+		self
+			.pending
+			.entry(monitor_id)
+			.or_insert_with(|| PendingCapture {
+				size,
+				pixels: vec![0; size.0 as usize * size.1 as usize * 4],
+				failed: outputs
+					.is_empty()
+					.then(|| Arc::from("monitor has no outputs")),
+				remaining: outputs,
+			});
+	}
+
+	/// Whether `output` (connector-level id) still has to be read back.
+	pub fn wants(&self, output: MonitorId) -> bool {
+		self
+			.pending
+			.values()
+			.any(|capture| capture.failed.is_none() && capture.remaining.contains(&output))
+	}
+
+	/// Reads `output`, `size` pixels at `offset` within `monitor_id`, into the capture.
+	/// `read` fills its slice as BGRA8888 with rows the given number of bytes apart.
+	pub fn read_output(
+		&mut self,
+		monitor_id: MonitorId,
+		output: MonitorId,
+		offset: (i32, i32),
+		size: (u32, u32),
+		read: impl FnOnce(&mut [u8], usize) -> bool,
+	) {
+		let Some(capture) = self.pending.get_mut(&monitor_id) else {
+			return;
+		};
+		let Some(index) = capture.remaining.iter().position(|id| *id == output) else {
+			return;
+		};
+		capture.remaining.swap_remove(index);
+		if capture.failed.is_some() {
+			return;
+		}
+		let Some(range) = slice_for(capture.size, offset, size) else {
+			capture.failed = Some("output lies outside its monitor".into());
+			return;
+		};
+		if !read(&mut capture.pixels[range], capture.size.0 as usize * 4) {
+			capture.failed = Some("failed to read the frame back".into());
+		}
+	}
+
+	/// Fails `monitor_id`'s capture, e.g. because the monitor doesn't compose.
+	pub fn fail(&mut self, monitor_id: MonitorId, reason: &str) {
+		if let Some(capture) = self.pending.get_mut(&monitor_id)
+			&& capture.failed.is_none()
+		{
+			capture.failed = Some(reason.into());
+		}
+	}
+
+	/// Captures that are complete or failed, ready to hand to the server.
+	pub fn take_finished(&mut self) -> Vec<(MonitorId, Result<CapturedFrame, Arc<str>>)> {
+		let finished: Vec<_> = self
+			.pending
+			.iter()
+			.filter(|(_, capture)| capture.failed.is_some() || capture.remaining.is_empty())
+			.map(|(monitor_id, _)| *monitor_id)
+			.collect();
+		finished
+			.into_iter()
+			.filter_map(|monitor_id| {
+				let capture = self.pending.remove(&monitor_id)?;
+				Some((monitor_id, capture.finish()))
+			})
+			.collect()
+	}
+
+	pub fn remove_output(&mut self, output: MonitorId) {
+		for capture in self.pending.values_mut() {
+			if let Some(index) = capture.remaining.iter().position(|id| *id == output) {
+				capture.remaining.swap_remove(index);
+				capture
+					.failed
+					.get_or_insert_with(|| "monitor disconnected".into());
+			}
+		}
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.pending.remove(&monitor_id);
+	}
+}
+
+impl PendingCapture {
+	fn finish(mut self) -> Result<CapturedFrame, Arc<str>> {
+		if let Some(reason) = self.failed {
+			return Err(reason);
+		}
+		// Framebuffer alpha isn't scanned out; don't let it make the image translucent.
+		for pixel in self.pixels.chunks_exact_mut(4) {
+			pixel[3] = 0xff;
+		}
+		let pixels =
+			memfd(&self.pixels).map_err(|e| Arc::from(format!("failed to share the frame: {e}")))?;
+		Ok(CapturedFrame {
+			width: self.size.0,
+			height: self.size.1,
+			stride: self.size.0 * 4,
+			pixels,
+		})
+	}
+}
+
+/// Bytes of a `monitor`-sized BGRA8888 image that an output of `size` at `offset` covers,
+/// from its first pixel to its last.
+fn slice_for(
+	monitor: (u32, u32),
+	offset: (i32, i32),
+	size: (u32, u32),
+) -> Option<std::ops::Range<usize>> {
+	let x = u32::try_from(offset.0).ok()?;
+	let y = u32::try_from(offset.1).ok()?;
+	if size.0 == 0 || size.1 == 0 || x + size.0 > monitor.0 || y + size.1 > monitor.1 {
+		return None;
+	}
+	let stride = monitor.0 as usize * 4;
+	let start = y as usize * stride + x as usize * 4;
+	let end = start + (size.1 as usize - 1) * stride + size.0 as usize * 4;
+	Some(start..end)
+}
+
+fn memfd(contents: &[u8]) -> io::Result<OwnedFd> {
+	// SAFETY: memfd_create returns a new fd we own, or -1 with errno set.
+	let fd = unsafe { libc::memfd_create(c"shift-screenshot".as_ptr(), libc::MFD_CLOEXEC) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	File::from(fd.try_clone()?).write_all_at(contents, 0)?;
+	Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ids() -> (MonitorId, MonitorId, MonitorId) {
+		(
+			"mon_1".parse().expect("monitor id"),
+			"mon_2".parse().expect("monitor id"),
+			"mon_3".parse().expect("monitor id"),
+		)
+	}
+
+	#[test]
+	fn tiles_fill_their_slice() {
+		let (monitor, left, right) = ids();
+		let mut captures = Captures::default();
+		captures.request(monitor, (4, 2), vec![left, right]);
+		captures.read_output(monitor, left, (0, 0), (2, 2), |dst, row_bytes| {
+			assert_eq!(row_bytes, 16);
+			dst[..8].fill(1);
+			dst[16..24].fill(1);
+			true
+		});
+		assert!(captures.take_finished().is_empty(), "waits for every tile");
+		assert!(captures.wants(right));
+		captures.read_output(monitor, right, (2, 0), (2, 2), |dst, _| {
+			assert_eq!(dst.len(), 24);
+			dst[..8].fill(2);
+			dst[16..].fill(2);
+			true
+		});
+		let [(id, Ok(frame))] = &captures.take_finished()[..] else {
+			panic!("capture did not finish");
+		};
+		assert_eq!(*id, monitor);
+		assert_eq!((frame.width, frame.height, frame.stride), (4, 2, 16));
+		let mut pixels = vec![0; 32];
+		File::from(frame.pixels.try_clone().expect("dup memfd"))
+			.read_exact_at(&mut pixels, 0)
+			.expect("read memfd");
+		assert_eq!(
+			&pixels[..12],
+			&[1, 1, 1, 0xff, 1, 1, 1, 0xff, 2, 2, 2, 0xff]
+		);
+		assert_eq!(&pixels[28..], &[2, 2, 2, 0xff]);
+	}
+
+	#[test]
+	fn failures_finish_the_capture() {
+		let (monitor, output, _) = ids();
+		let mut captures = Captures::default();
+		captures.request(monitor, (2, 2), vec![output]);
+		captures.fail(monitor, "monitor is disabled");
+		assert!(!captures.wants(output));
+		let [(_, Err(reason))] = &captures.take_finished()[..] else {
+			panic!("capture did not fail");
+		};
+		assert_eq!(&**reason, "monitor is disabled");
+		captures.request(monitor, (2, 2), Vec::new());
+		assert!(matches!(&captures.take_finished()[..], [(_, Err(_))]));
+	}
+
+	#[test]
+	fn outputs_must_lie_within_the_monitor() {
+		assert_eq!(slice_for((4, 2), (0, 0), (4, 2)), Some(0..32));
+		assert_eq!(slice_for((4, 2), (2, 1), (2, 1)), Some(24..32));
+		assert_eq!(slice_for((4, 2), (3, 0), (2, 2)), None);
+		assert_eq!(slice_for((4, 2), (-1, 0), (2, 2)), None);
+	}
+}
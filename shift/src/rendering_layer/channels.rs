@@ -1,10 +1,9 @@
 use crate::comms::{
+	channel::{self, ChannelKind},
 	render2server::{RenderEvtRx, RenderEvtTx},
-	server2render::{RenderCmdRx, RenderCmdTx},
+	server2render::{RenderCmdRx, RenderCmdTx, render_command_channel},
 };
 
-const DEFAULT_CHANNEL_CAPACITY: usize = 5000;
-
 #[derive(Debug)]
 pub struct ServerEnd {
 	render_events: RenderEvtRx,
@@ -62,13 +61,11 @@ pub struct Channels {
 }
 
 impl Channels {
+	/// Sized by `SHIFT_RENDER_CHANNEL_CAPACITY`. `SHIFT_RENDER_CHANNEL_OVERFLOW` only applies to
+	/// events; commands always wait for room.
 	pub fn new() -> Self {
-		Self::with_capacity(DEFAULT_CHANNEL_CAPACITY)
-	}
-
-	pub fn with_capacity(capacity: usize) -> Self {
-		let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(capacity);
-		let (evt_tx, evt_rx) = tokio::sync::mpsc::channel(capacity);
+		let (cmd_tx, cmd_rx) = render_command_channel(ChannelKind::Render.config().capacity);
+		let (evt_tx, evt_rx) = channel::channel(ChannelKind::Render);
 
 		Self {
 			server_end: ServerEnd::new(evt_rx, cmd_tx),
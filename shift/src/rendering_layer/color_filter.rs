@@ -0,0 +1,243 @@
+//! Color filters applied as a final per-monitor composition pass: accessibility color matrices
+//! followed by software brightness/contrast/gamma adjustments, then the monitor's color
+//! profile correction (see `color_profile`).
+//!
+//! Color-blindness modes daltonize the output: the deficiency is simulated with the Machado
+//! et al. (2009) matrices at full severity, and the color information lost in the simulation is
+//! shifted into channels the viewer can still distinguish.
+//!
+//! Display adjustments are baked into a per-channel lookup table and don't touch the hardware
+//! gamma ramp, so they work on outputs whose firmware controls are unreachable.
+
+use std::collections::HashMap;
+
+use skia_safe::{Canvas, ColorFilter, Paint, canvas::SaveLayerRec, color_filters};
+use tab_protocol::ColorFilterMode;
+
+use crate::monitor::MonitorId;
+
+type Mat3 = [[f32; 3]; 3];
+
+const PROTANOPIA_SIM: Mat3 = [
+	[0.152286, 1.052583, -0.204868],
+	[0.114503, 0.786281, 0.099216],
+	[-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA_SIM: Mat3 = [
+	[0.367322, 0.860646, -0.227968],
+	[0.280085, 0.672501, 0.047413],
+	[-0.011820, 0.042940, 0.968881],
+];
+const TRITANOPIA_SIM: Mat3 = [
+	[1.255528, -0.076749, -0.178779],
+	[-0.078411, 0.930809, 0.147602],
+	[0.004733, 0.691367, 0.303900],
+];
+/// How the simulation error is redistributed across channels (Fidaner et al.).
+const ERROR_SHIFT: Mat3 = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+/// Rec. 709 luma coefficients.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Daltonization matrix: `I + E * (I - S)`.
+fn correction_matrix(simulation: &Mat3) -> Mat3 {
+	let mut out = [[0.0; 3]; 3];
+	for (row, out_row) in out.iter_mut().enumerate() {
+		for (col, value) in out_row.iter_mut().enumerate() {
+			let error_term = (0..3)
+				.map(|k| {
+					let identity = if k == col { 1.0 } else { 0.0 };
+					ERROR_SHIFT[row][k] * (identity - simulation[k][col])
+				})
+				.sum::<f32>();
+			*value = if row == col { 1.0 } else { 0.0 } + error_term;
+		}
+	}
+	out
+}
+
+/// Row-major 4x5 matrix in the layout expected by Skia, or `None` for [`ColorFilterMode::None`].
+pub(super) fn color_matrix(mode: ColorFilterMode) -> Option<[f32; 20]> {
+	let rgb = match mode {
+		ColorFilterMode::None => return None,
+		ColorFilterMode::Grayscale => [LUMA; 3],
+		ColorFilterMode::Protanopia => correction_matrix(&PROTANOPIA_SIM),
+		ColorFilterMode::Deuteranopia => correction_matrix(&DEUTERANOPIA_SIM),
+		ColorFilterMode::Tritanopia => correction_matrix(&TRITANOPIA_SIM),
+	};
+	let mut matrix = [0.0; 20];
+	for (row, rgb_row) in rgb.iter().enumerate() {
+		matrix[row * 5..row * 5 + 3].copy_from_slice(rgb_row);
+	}
+	matrix[18] = 1.0;
+	Some(matrix)
+}
+
+/// Software brightness/contrast/gamma for one monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct DisplayAdjustment {
+	/// Added to every channel, in `-1.0..=1.0`.
+	pub brightness: f32,
+	/// Scale around mid-gray, in `0.0..=4.0`.
+	pub contrast: f32,
+	/// Output gamma, in `0.1..=10.0`; values above 1 brighten midtones.
+	pub gamma: f32,
+}
+
+impl Default for DisplayAdjustment {
+	fn default() -> Self {
+		Self {
+			brightness: 0.0,
+			contrast: 1.0,
+			gamma: 1.0,
+		}
+	}
+}
+
+impl DisplayAdjustment {
+	pub fn new(brightness: f32, contrast: f32, gamma: f32) -> Self {
+		Self {
+			brightness: brightness.clamp(-1.0, 1.0),
+			contrast: contrast.clamp(0.0, 4.0),
+			gamma: gamma.clamp(0.1, 10.0),
+		}
+	}
+
+	pub fn is_identity(&self) -> bool {
+		*self == Self::default()
+	}
+
+	fn lut(&self) -> [u8; 256] {
+		std::array::from_fn(|i| {
+			let value = (i as f32 / 255.0).powf(1.0 / self.gamma);
+			let value = (value - 0.5) * self.contrast + 0.5 + self.brightness;
+			(value.clamp(0.0, 1.0) * 255.0).round() as u8
+		})
+	}
+}
+
+/// Starts a layer that applies `filter` to everything drawn until the canvas is restored.
+/// Returns `false` (and leaves the canvas untouched) when there is no filter.
+pub(super) fn begin_filter_pass(canvas: &Canvas, filter: Option<ColorFilter>) -> bool {
+	let Some(filter) = filter else {
+		return false;
+	};
+	let mut paint = Paint::default();
+	paint.set_color_filter(filter);
+	canvas.save_layer(&SaveLayerRec::default().paint(&paint));
+	true
+}
+
+/// Filter selection: a global accessibility mode with per-monitor overrides, plus per-monitor
+/// display adjustments and color profile corrections.
+#[derive(Debug, Default)]
+pub(super) struct ColorFilters {
+	global: ColorFilterMode,
+	per_monitor: HashMap<MonitorId, ColorFilterMode>,
+	adjustments: HashMap<MonitorId, DisplayAdjustment>,
+	corrections: HashMap<MonitorId, ColorFilter>,
+}
+
+impl ColorFilters {
+	/// Sets the filter for one monitor, or for all monitors (dropping overrides) when `None`.
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, mode: ColorFilterMode) {
+		match monitor_id {
+			Some(monitor_id) => {
+				self.per_monitor.insert(monitor_id, mode);
+			}
+			None => {
+				self.global = mode;
+				self.per_monitor.clear();
+			}
+		}
+	}
+
+	pub fn for_monitor(&self, monitor_id: MonitorId) -> ColorFilterMode {
+		self
+			.per_monitor
+			.get(&monitor_id)
+			.copied()
+			.unwrap_or(self.global)
+	}
+
+	pub fn set_adjustment(&mut self, monitor_id: MonitorId, adjustment: DisplayAdjustment) {
+		if adjustment.is_identity() {
+			self.adjustments.remove(&monitor_id);
+		} else {
+			self.adjustments.insert(monitor_id, adjustment);
+		}
+	}
+
+	/// Sets the color profile correction of `monitor_id`; `None` leaves its colors as they are.
+	pub fn set_correction(&mut self, monitor_id: MonitorId, correction: Option<ColorFilter>) {
+		match correction {
+			Some(correction) => {
+				self.corrections.insert(monitor_id, correction);
+			}
+			None => {
+				self.corrections.remove(&monitor_id);
+			}
+		}
+	}
+
+	/// Combined filter for `monitor_id`: the accessibility matrix runs first, then the display
+	/// adjustment, and the color profile correction last.
+	pub fn filter_for(&self, monitor_id: MonitorId) -> Option<ColorFilter> {
+		let accessibility = color_matrix(self.for_monitor(monitor_id))
+			.map(|matrix| color_filters::matrix_row_major(&matrix, None));
+		let adjustment = self.adjustments.get(&monitor_id).and_then(|adjustment| {
+			let lut = adjustment.lut();
+			color_filters::table_argb(None, &lut, &lut, &lut)
+		});
+		let correction = self.corrections.get(&monitor_id).cloned();
+		[accessibility, adjustment, correction]
+			.into_iter()
+			.flatten()
+			.reduce(|inner, outer| outer.composed(inner).unwrap_or(outer))
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.per_monitor.remove(&monitor_id);
+		self.adjustments.remove(&monitor_id);
+		self.corrections.remove(&monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn apply(matrix: &[f32; 20], rgb: [f32; 3]) -> [f32; 3] {
+		std::array::from_fn(|row| (0..3).map(|col| matrix[row * 5 + col] * rgb[col]).sum())
+	}
+
+	#[test]
+	fn filters_keep_neutral_colors_neutral() {
+		for mode in [
+			ColorFilterMode::Grayscale,
+			ColorFilterMode::Protanopia,
+			ColorFilterMode::Deuteranopia,
+			ColorFilterMode::Tritanopia,
+		] {
+			let matrix = color_matrix(mode).expect("matrix for active mode");
+			for level in [0.0, 0.5, 1.0] {
+				let out = apply(&matrix, [level; 3]);
+				for channel in out {
+					assert!(
+						(channel - level).abs() < 0.01,
+						"{mode:?} maps gray {level} to {out:?}"
+					);
+				}
+			}
+		}
+		assert!(color_matrix(ColorFilterMode::None).is_none());
+	}
+
+	#[test]
+	fn default_adjustment_lut_is_identity() {
+		let lut = DisplayAdjustment::default().lut();
+		assert!(lut.iter().enumerate().all(|(i, v)| *v as usize == i));
+		let brighter = DisplayAdjustment::new(0.0, 1.0, 2.2).lut();
+		assert!(brighter[128] > 128);
+		assert_eq!((brighter[0], brighter[255]), (0, 255));
+	}
+}
@@ -0,0 +1,671 @@
+//! Per-monitor color management: session content is taken as sRGB and converted into each
+//! monitor's color space by a 3D LUT applied after every other color filter.
+//!
+//! A monitor's profile comes from an ICC display profile (matrix/TRC profiles only, the kind
+//! calibration tools produce for displays) or from the primaries, white point and gamma in its
+//! EDID. `SHIFT_COLOR_PROFILE` sets the default for every monitor, either `edid` or the path
+//! of an ICC file; monitors are left uncorrected when it is unset. Admins override it per
+//! monitor with `set_color_profile`. Conversion is relative colorimetric: white maps to the
+//! monitor's white and out-of-gamut colors are clipped.
+
+use std::collections::HashMap;
+
+use skia_safe::{
+	AlphaType, ColorFilter, ColorType, Data, FilterMode, ImageInfo, MipmapMode, RuntimeEffect,
+	SamplingOptions, TileMode, images, runtime_effect::ChildPtr,
+};
+use thiserror::Error;
+
+use crate::comms::server2render::ColorProfileSource;
+use crate::drm_card;
+use crate::monitor::MonitorId;
+
+use super::color_filter::ColorFilters;
+
+type Mat3 = [[f32; 3]; 3];
+
+/// Grid points per axis of the correction LUT.
+const LUT_SIZE: usize = 33;
+/// Samples of each channel's inverse tone curve.
+const INVERSE_SAMPLES: usize = 1024;
+
+/// sRGB primaries relative to the D50 connection space, as in the ICC sRGB profile.
+const SRGB_TO_XYZ_D50: Mat3 = [
+	[0.436_074_7, 0.385_064_9, 0.143_080_4],
+	[0.222_504_5, 0.716_878_6, 0.060_616_9],
+	[0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+const D50: [f32; 3] = [0.9642, 1.0, 0.8249];
+const BRADFORD: Mat3 = [
+	[0.8951, 0.2664, -0.1614],
+	[-0.7502, 1.7135, 0.0367],
+	[0.0389, -0.0685, 1.0296],
+];
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// Samples the LUT laid out as `size` slices of `size`×`size` texels, one per blue level, and
+/// blends between the two nearest slices.
+const LUT_SKSL: &str = "
+uniform shader lut;
+uniform float size;
+
+half4 main(half4 color) {
+	half alpha = color.a;
+	half3 rgb = alpha > 0 ? saturate(color.rgb / alpha) : half3(0);
+	float3 p = float3(rgb) * (size - 1);
+	float b0 = floor(p.b);
+	float b1 = min(b0 + 1, size - 1);
+	float2 rg = p.rg + 0.5;
+	half3 lo = lut.eval(float2(b0 * size + rg.r, rg.g)).rgb;
+	half3 hi = lut.eval(float2(b1 * size + rg.r, rg.g)).rgb;
+	return half4(mix(lo, hi, half(p.b - b0)) * alpha, alpha);
+}
+";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum IccError {
+	#[error("not an ICC profile")]
+	NotIcc,
+	#[error("only RGB profiles with an XYZ connection space are supported")]
+	UnsupportedColorSpace,
+	#[error("missing or unsupported `{0}` tag; only matrix/TRC profiles are supported")]
+	Tag(&'static str),
+}
+
+/// Tone response of one channel, from encoded value to linear light.
+#[derive(Debug, Clone, PartialEq)]
+enum ToneCurve {
+	/// ICC parametric curve: `(a·x + b)^g + e` above `d`, `c·x + f` below.
+	Parametric {
+		g: f32,
+		a: f32,
+		b: f32,
+		c: f32,
+		d: f32,
+		e: f32,
+		f: f32,
+	},
+	/// Evenly spaced samples, interpolated linearly.
+	Table(Vec<f32>),
+}
+
+impl ToneCurve {
+	fn gamma(g: f32) -> Self {
+		Self::Parametric {
+			g,
+			a: 1.0,
+			b: 0.0,
+			c: 0.0,
+			d: 0.0,
+			e: 0.0,
+			f: 0.0,
+		}
+	}
+
+	fn eval(&self, x: f32) -> f32 {
+		let x = x.clamp(0.0, 1.0);
+		match self {
+			Self::Parametric {
+				g,
+				a,
+				b,
+				c,
+				d,
+				e,
+				f,
+			} => {
+				if x >= *d {
+					(a * x + b).max(0.0).powf(*g) + e
+				} else {
+					c * x + f
+				}
+			}
+			Self::Table(samples) => {
+				let pos = x * (samples.len() - 1) as f32;
+				let i = (pos as usize).min(samples.len() - 2);
+				let t = pos - i as f32;
+				samples[i] + (samples[i + 1] - samples[i]) * t
+			}
+		}
+	}
+
+	/// Samples of the inverse curve over `0..=1`, found by bisection; the curve has to be
+	/// non-decreasing, which display curves are.
+	fn inverse_table(&self) -> Vec<f32> {
+		(0..INVERSE_SAMPLES)
+			.map(|i| {
+				let target = i as f32 / (INVERSE_SAMPLES - 1) as f32;
+				let (mut lo, mut hi) = (0.0f32, 1.0f32);
+				for _ in 0..24 {
+					let mid = (lo + hi) / 2.0;
+					if self.eval(mid) < target {
+						lo = mid;
+					} else {
+						hi = mid;
+					}
+				}
+				(lo + hi) / 2.0
+			})
+			.collect()
+	}
+}
+
+/// A display's color space: linear RGB to D50 XYZ, plus the tone curve of each channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayProfile {
+	to_xyz: Mat3,
+	curves: [ToneCurve; 3],
+}
+
+impl DisplayProfile {
+	pub fn from_icc(bytes: &[u8]) -> Result<Self, IccError> {
+		if bytes.len() < 132 || &bytes[36..40] != b"acsp" {
+			return Err(IccError::NotIcc);
+		}
+		if &bytes[16..20] != b"RGB " || &bytes[20..24] != b"XYZ " {
+			return Err(IccError::UnsupportedColorSpace);
+		}
+		let tag_count = read_u32(bytes, 128).ok_or(IccError::NotIcc)? as usize;
+		let tag = |signature: &'static str| {
+			(0..tag_count)
+				.find_map(|i| {
+					let entry = 132 + i * 12;
+					let tag = bytes.get(entry..entry + 12)?;
+					if &tag[..4] != signature.as_bytes() {
+						return None;
+					}
+					let offset = read_u32(tag, 4)? as usize;
+					let size = read_u32(tag, 8)? as usize;
+					bytes.get(offset..offset.checked_add(size)?)
+				})
+				.ok_or(IccError::Tag(signature))
+		};
+		let mut to_xyz = [[0.0; 3]; 3];
+		for (col, signature) in ["rXYZ", "gXYZ", "bXYZ"].into_iter().enumerate() {
+			let xyz = parse_xyz(tag(signature)?).ok_or(IccError::Tag(signature))?;
+			for (row, value) in xyz.into_iter().enumerate() {
+				to_xyz[row][col] = value;
+			}
+		}
+		let mut curves = Vec::with_capacity(3);
+		for signature in ["rTRC", "gTRC", "bTRC"] {
+			curves.push(parse_curve(tag(signature)?).ok_or(IccError::Tag(signature))?);
+		}
+		let curves: [ToneCurve; 3] = curves.try_into().expect("three curves");
+		invert(&to_xyz).ok_or(IccError::Tag("rXYZ"))?;
+		Ok(Self { to_xyz, curves })
+	}
+
+	/// `None` when the EDID has no usable chromaticities or says the monitor is sRGB.
+	pub fn from_edid(edid: &[u8]) -> Option<Self> {
+		if edid.len() < 35 || edid[..8] != EDID_HEADER {
+			return None;
+		}
+		// Feature support bit 2: sRGB is the default color space.
+		if edid[24] & 0x04 != 0 {
+			return None;
+		}
+		let low = |byte: u8, shift: u8| u16::from((byte >> shift) & 0x03);
+		let coord = |high: u8, low: u16| f32::from(u16::from(high) << 2 | low) / 1024.0;
+		let red = (
+			coord(edid[27], low(edid[25], 6)),
+			coord(edid[28], low(edid[25], 4)),
+		);
+		let green = (
+			coord(edid[29], low(edid[25], 2)),
+			coord(edid[30], low(edid[25], 0)),
+		);
+		let blue = (
+			coord(edid[31], low(edid[26], 6)),
+			coord(edid[32], low(edid[26], 4)),
+		);
+		let white = (
+			coord(edid[33], low(edid[26], 2)),
+			coord(edid[34], low(edid[26], 0)),
+		);
+		let to_xyz = rgb_to_xyz_d50([red, green, blue], white)?;
+		// 0xff defers the gamma to an extension block; assume the sRGB-like 2.2 then.
+		let gamma = match edid[23] {
+			0xff => 2.2,
+			value => (f32::from(value) + 100.0) / 100.0,
+		};
+		Some(Self {
+			to_xyz,
+			curves: std::array::from_fn(|_| ToneCurve::gamma(gamma)),
+		})
+	}
+
+	/// RGB bytes of the correction LUT in the layout `LUT_SKSL` samples, or `None` when the
+	/// profile is close enough to sRGB that correcting would change nothing.
+	fn correction_lut(&self) -> Option<Vec<u8>> {
+		let from_xyz = invert(&self.to_xyz)?;
+		let matrix = multiply(&from_xyz, &SRGB_TO_XYZ_D50);
+		let inverse = self.curves.each_ref().map(ToneCurve::inverse_table);
+		let grid = |i: usize| i as f32 / (LUT_SIZE - 1) as f32;
+		let mut lut = Vec::with_capacity(LUT_SIZE.pow(3) * 4);
+		let mut identity = true;
+		for g in 0..LUT_SIZE {
+			for b in 0..LUT_SIZE {
+				for r in 0..LUT_SIZE {
+					let linear = [grid(r), grid(g), grid(b)].map(srgb_to_linear);
+					let display = apply(&matrix, linear);
+					for (channel, value) in display.into_iter().enumerate() {
+						let encoded = sample(&inverse[channel], value.clamp(0.0, 1.0));
+						let byte = (encoded * 255.0).round() as u8;
+						let expected = (grid([r, g, b][channel]) * 255.0).round() as u8;
+						identity &= byte.abs_diff(expected) <= 1;
+						lut.push(byte);
+					}
+					lut.push(u8::MAX);
+				}
+			}
+		}
+		(!identity).then_some(lut)
+	}
+
+	/// Color filter converting sRGB into this profile.
+	fn correction_filter(&self) -> Option<ColorFilter> {
+		let lut = self.correction_lut()?;
+		let info = ImageInfo::new(
+			((LUT_SIZE * LUT_SIZE) as i32, LUT_SIZE as i32),
+			ColorType::RGBA8888,
+			AlphaType::Opaque,
+			None,
+		);
+		let image = images::raster_from_data(&info, Data::new_copy(&lut), LUT_SIZE * LUT_SIZE * 4)?;
+		let shader = image.to_shader(
+			(TileMode::Clamp, TileMode::Clamp),
+			SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+			None,
+		)?;
+		let effect = match RuntimeEffect::make_for_color_filter(LUT_SKSL, None) {
+			Ok(effect) => effect,
+			Err(e) => {
+				tracing::error!("failed to compile color correction shader: {e}");
+				return None;
+			}
+		};
+		let uniforms = Data::new_copy(&(LUT_SIZE as f32).to_ne_bytes());
+		effect.make_color_filter(uniforms, Some(&[ChildPtr::Shader(shader)][..]))
+	}
+}
+
+/// Profile sources per monitor and the corrections built from them.
+pub(super) struct ColorProfiles {
+	default: ColorProfileSource,
+	per_monitor: HashMap<MonitorId, ColorProfileSource>,
+	/// Monitors whose correction in [`ColorFilters`] matches their source, with their connector.
+	applied: HashMap<MonitorId, u32>,
+}
+
+impl ColorProfiles {
+	pub fn from_env() -> Self {
+		let mut profiles = Self {
+			default: ColorProfileSource::None,
+			per_monitor: HashMap::new(),
+			applied: HashMap::new(),
+		};
+		let Ok(value) = std::env::var("SHIFT_COLOR_PROFILE") else {
+			return profiles;
+		};
+		let value = value.trim();
+		profiles.default = match value {
+			"" | "none" => ColorProfileSource::None,
+			"edid" => ColorProfileSource::Edid,
+			path => match std::fs::read(path) {
+				Ok(bytes) => match DisplayProfile::from_icc(&bytes) {
+					Ok(_) => ColorProfileSource::Icc(bytes.into()),
+					Err(e) => {
+						tracing::warn!(path, "ignoring SHIFT_COLOR_PROFILE: {e}");
+						ColorProfileSource::None
+					}
+				},
+				Err(e) => {
+					tracing::warn!(path, "ignoring SHIFT_COLOR_PROFILE: {e}");
+					ColorProfileSource::None
+				}
+			},
+		};
+		profiles
+	}
+
+	/// Changes the profile of one monitor, or of every monitor when `None`. Takes effect on
+	/// the next [`Self::refresh`].
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, source: ColorProfileSource) {
+		match monitor_id {
+			Some(monitor_id) => {
+				self.per_monitor.insert(monitor_id, source);
+				self.applied.remove(&monitor_id);
+			}
+			None => {
+				self.default = source;
+				self.per_monitor.clear();
+				self.applied.clear();
+			}
+		}
+	}
+
+	/// Builds the corrections of `monitors`, given as `(monitor, connector)`, that are missing
+	/// or outdated, and forgets monitors that are gone.
+	pub fn refresh(&mut self, monitors: &[(MonitorId, u32)], filters: &mut ColorFilters) {
+		self.applied.retain(|monitor_id, connector_id| {
+			let connected = monitors.contains(&(*monitor_id, *connector_id));
+			if !connected {
+				filters.set_correction(*monitor_id, None);
+			}
+			connected
+		});
+		self
+			.per_monitor
+			.retain(|monitor_id, _| monitors.iter().any(|(id, _)| id == monitor_id));
+		for &(monitor_id, connector_id) in monitors {
+			if self.applied.contains_key(&monitor_id) {
+				continue;
+			}
+			let source = self.per_monitor.get(&monitor_id).unwrap_or(&self.default);
+			let profile = match source {
+				ColorProfileSource::None => None,
+				ColorProfileSource::Edid => {
+					drm_card::read_edid(connector_id).and_then(|edid| DisplayProfile::from_edid(&edid))
+				}
+				ColorProfileSource::Icc(bytes) => DisplayProfile::from_icc(bytes)
+					.inspect_err(|e| tracing::warn!(%monitor_id, "ignoring color profile: {e}"))
+					.ok(),
+			};
+			let correction = profile.and_then(|profile| profile.correction_filter());
+			tracing::debug!(%monitor_id, corrected = correction.is_some(), "color profile applied");
+			filters.set_correction(monitor_id, correction);
+			self.applied.insert(monitor_id, connector_id);
+		}
+	}
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(
+		bytes.get(offset..offset + 4)?.try_into().ok()?,
+	))
+}
+
+fn read_s15_16(bytes: &[u8], offset: usize) -> Option<f32> {
+	Some(read_u32(bytes, offset)? as i32 as f32 / 65536.0)
+}
+
+fn parse_xyz(tag: &[u8]) -> Option<[f32; 3]> {
+	if tag.get(..4)? != b"XYZ " {
+		return None;
+	}
+	Some([
+		read_s15_16(tag, 8)?,
+		read_s15_16(tag, 12)?,
+		read_s15_16(tag, 16)?,
+	])
+}
+
+fn parse_curve(tag: &[u8]) -> Option<ToneCurve> {
+	match tag.get(..4)? {
+		b"curv" => {
+			let count = read_u32(tag, 8)? as usize;
+			let entry = |i: usize| -> Option<u16> {
+				let at = 12 + i * 2;
+				Some(u16::from_be_bytes(tag.get(at..at + 2)?.try_into().ok()?))
+			};
+			match count {
+				0 => Some(ToneCurve::gamma(1.0)),
+				1 => Some(ToneCurve::gamma(f32::from(entry(0)?) / 256.0)),
+				_ => (0..count)
+					.map(|i| entry(i).map(|value| f32::from(value) / 65535.0))
+					.collect::<Option<Vec<_>>>()
+					.map(ToneCurve::Table),
+			}
+		}
+		b"para" => {
+			let function = u16::from_be_bytes(tag.get(8..10)?.try_into().ok()?);
+			let count = match function {
+				0 => 1,
+				1 => 3,
+				2 => 4,
+				3 => 5,
+				4 => 7,
+				_ => return None,
+			};
+			let params = (0..count)
+				.map(|i| read_s15_16(tag, 12 + i * 4))
+				.collect::<Option<Vec<_>>>()?;
+			let param = |i: usize| params.get(i).copied().unwrap_or(0.0);
+			let (g, a, b) = (param(0), if count > 1 { param(1) } else { 1.0 }, param(2));
+			// Types 1 and 2 cut off at the curve's root, below which they are constant.
+			let root = if a != 0.0 { -b / a } else { 0.0 };
+			Some(match function {
+				0 => ToneCurve::gamma(g),
+				1 => ToneCurve::Parametric {
+					g,
+					a,
+					b,
+					c: 0.0,
+					d: root,
+					e: 0.0,
+					f: 0.0,
+				},
+				2 => ToneCurve::Parametric {
+					g,
+					a,
+					b,
+					c: 0.0,
+					d: root,
+					e: param(3),
+					f: param(3),
+				},
+				3 => ToneCurve::Parametric {
+					g,
+					a,
+					b,
+					c: param(3),
+					d: param(4),
+					e: 0.0,
+					f: 0.0,
+				},
+				_ => ToneCurve::Parametric {
+					g,
+					a,
+					b,
+					c: param(3),
+					d: param(4),
+					e: param(5),
+					f: param(6),
+				},
+			})
+		}
+		_ => None,
+	}
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn sample(table: &[f32], value: f32) -> f32 {
+	let pos = value * (table.len() - 1) as f32;
+	let i = (pos as usize).min(table.len() - 2);
+	let t = pos - i as f32;
+	table[i] + (table[i + 1] - table[i]) * t
+}
+
+fn apply(matrix: &Mat3, v: [f32; 3]) -> [f32; 3] {
+	matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn multiply(a: &Mat3, b: &Mat3) -> Mat3 {
+	std::array::from_fn(|row| std::array::from_fn(|col| (0..3).map(|k| a[row][k] * b[k][col]).sum()))
+}
+
+fn invert(m: &Mat3) -> Option<Mat3> {
+	let cofactor =
+		|r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+	let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+		+ m[0][2] * cofactor(1, 2, 0, 1);
+	if det.abs() < 1e-9 {
+		return None;
+	}
+	let adjugate = [
+		[
+			cofactor(1, 2, 1, 2),
+			-cofactor(0, 2, 1, 2),
+			cofactor(0, 1, 1, 2),
+		],
+		[
+			-cofactor(1, 2, 0, 2),
+			cofactor(0, 2, 0, 2),
+			-cofactor(0, 1, 0, 2),
+		],
+		[
+			cofactor(1, 2, 0, 1),
+			-cofactor(0, 2, 0, 1),
+			cofactor(0, 1, 0, 1),
+		],
+	];
+	Some(adjugate.map(|row| row.map(|value| value / det)))
+}
+
+fn xy_to_xyz((x, y): (f32, f32)) -> Option<[f32; 3]> {
+	(y > 0.0).then(|| [x / y, 1.0, (1.0 - x - y) / y])
+}
+
+/// Linear RGB to XYZ for the given chromaticities, chromatically adapted from `white` to D50.
+fn rgb_to_xyz_d50(primaries: [(f32, f32); 3], white: (f32, f32)) -> Option<Mat3> {
+	let columns = [
+		xy_to_xyz(primaries[0])?,
+		xy_to_xyz(primaries[1])?,
+		xy_to_xyz(primaries[2])?,
+	];
+	let primaries: Mat3 = std::array::from_fn(|row| columns.map(|column| column[row]));
+	let white = xy_to_xyz(white)?;
+	let scale = apply(&invert(&primaries)?, white);
+	let to_xyz: Mat3 = primaries.map(|row| std::array::from_fn(|col| row[col] * scale[col]));
+	let source = apply(&BRADFORD, white);
+	let target = apply(&BRADFORD, D50);
+	let gain: Mat3 = std::array::from_fn(|row| {
+		std::array::from_fn(|col| {
+			if row == col {
+				target[row] / source[row]
+			} else {
+				0.0
+			}
+		})
+	});
+	let adapt = multiply(&invert(&BRADFORD)?, &multiply(&gain, &BRADFORD));
+	Some(multiply(&adapt, &to_xyz))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A matrix/TRC profile with sRGB's primaries and the given curve tag, in all three TRCs.
+	fn icc_profile(to_xyz: &Mat3, curve: &[u8]) -> Vec<u8> {
+		let mut bytes = vec![0u8; 132];
+		bytes[16..20].copy_from_slice(b"RGB ");
+		bytes[20..24].copy_from_slice(b"XYZ ");
+		bytes[36..40].copy_from_slice(b"acsp");
+		let mut tags: Vec<(&[u8; 4], Vec<u8>)> = Vec::new();
+		for (col, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+			let mut tag = b"XYZ \0\0\0\0".to_vec();
+			for row in to_xyz {
+				tag.extend_from_slice(&((row[col] * 65536.0).round() as i32).to_be_bytes());
+			}
+			tags.push((signature, tag));
+		}
+		for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+			tags.push((signature, curve.to_vec()));
+		}
+		bytes[128..132].copy_from_slice(&(tags.len() as u32).to_be_bytes());
+		let mut offset = 132 + tags.len() * 12;
+		let mut data = Vec::new();
+		for (signature, tag) in &tags {
+			bytes.extend_from_slice(*signature);
+			bytes.extend_from_slice(&(offset as u32).to_be_bytes());
+			bytes.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+			offset += tag.len();
+			data.extend_from_slice(tag);
+		}
+		bytes.extend_from_slice(&data);
+		bytes
+	}
+
+	fn srgb_curve() -> Vec<u8> {
+		let mut tag = b"para\0\0\0\0\0\x03\0\0".to_vec();
+		for value in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+			tag.extend_from_slice(&((value * 65536.0f32).round() as i32).to_be_bytes());
+		}
+		tag
+	}
+
+	#[test]
+	fn srgb_profile_needs_no_correction() {
+		let profile = DisplayProfile::from_icc(&icc_profile(&SRGB_TO_XYZ_D50, &srgb_curve()))
+			.expect("valid profile");
+		assert!(profile.correction_lut().is_none());
+	}
+
+	#[test]
+	fn gamma_profile_darkens_midtones() {
+		let curve = [b"curv".as_slice(), &[0, 0, 0, 0, 0, 0, 0, 1, 0x02, 0x80]].concat();
+		let profile =
+			DisplayProfile::from_icc(&icc_profile(&SRGB_TO_XYZ_D50, &curve)).expect("valid profile");
+		assert_eq!(profile.curves[0], ToneCurve::gamma(2.5));
+		let lut = profile
+			.correction_lut()
+			.expect("a gamma 2.5 display needs correcting");
+		let mid = LUT_SIZE / 2;
+		let texel = (mid * LUT_SIZE * LUT_SIZE + mid * LUT_SIZE + mid) * 4;
+		assert!(lut[texel] > 128, "{}", lut[texel]);
+		assert_eq!((lut[0], lut[lut.len() - 4]), (0, 255));
+	}
+
+	#[test]
+	fn rejects_lut_based_profiles() {
+		let mut bytes = icc_profile(&SRGB_TO_XYZ_D50, &srgb_curve());
+		bytes[132..136].copy_from_slice(b"A2B0");
+		assert_eq!(DisplayProfile::from_icc(&bytes), Err(IccError::Tag("rXYZ")));
+		assert_eq!(
+			DisplayProfile::from_icc(b"not a profile"),
+			Err(IccError::NotIcc)
+		);
+	}
+
+	#[test]
+	fn edid_primaries_match_srgb_matrix() {
+		let mut edid = vec![0u8; 128];
+		edid[..8].copy_from_slice(&EDID_HEADER);
+		edid[23] = 120;
+		// Rec. 709 primaries and D65, as 10-bit fractions split into high and low bits.
+		let coords = [
+			(0.640, 0.330),
+			(0.300, 0.600),
+			(0.150, 0.060),
+			(0.3127, 0.3290),
+		];
+		let bits: Vec<u16> = coords
+			.iter()
+			.flat_map(|(x, y)| [x, y])
+			.map(|value| (value * 1024.0).round() as u16)
+			.collect();
+		edid[25] = bits[..4].iter().fold(0, |acc, v| acc << 2 | (v & 3) as u8);
+		edid[26] = bits[4..].iter().fold(0, |acc, v| acc << 2 | (v & 3) as u8);
+		for (i, value) in bits.iter().enumerate() {
+			edid[27 + i] = (value >> 2) as u8;
+		}
+		let profile = DisplayProfile::from_edid(&edid).expect("chromaticities");
+		for (row, expected) in profile.to_xyz.iter().zip(SRGB_TO_XYZ_D50) {
+			for (value, expected) in row.iter().zip(expected) {
+				assert!((value - expected).abs() < 0.005, "{:?}", profile.to_xyz);
+			}
+		}
+		assert_eq!(profile.curves[0], ToneCurve::gamma(2.2));
+		edid[24] |= 0x04;
+		assert!(DisplayProfile::from_edid(&edid).is_none());
+	}
+}
@@ -1,116 +1,246 @@
 use std::{
-	os::fd::{FromRawFd, OwnedFd},
+	os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
 	sync::Arc,
+	time::Instant,
 };
 
-use crate::comms::server2render::RenderCmd;
+use crate::comms::server2render::{RenderCmd, TracedCmd};
 
-use super::dmabuf_import::{DmaBufTexture, ImportParams as DmaBufImportParams};
-use super::state::BufferSlot;
+use super::backend::{BackendOutput, RenderBackend};
+use super::color_filter::DisplayAdjustment;
+use super::dmabuf_import::{
+	DmaBufTexture, ImportParams as DmaBufImportParams, ImportPlane as DmaBufImportPlane,
+};
+use super::scanout::ScanoutBuffer;
+use super::state::{BufferSlot, DeferredRelease, SlotOwner};
 use super::{RenderError, RenderEvt, RenderingLayer, SlotKey};
+use super::{formats, modes, pixel_formats, present_queue};
 
-impl RenderingLayer {
+impl<B: RenderBackend> RenderingLayer<B> {
 	#[tracing::instrument(skip_all, fields(session_id = %session_id, monitor_id = %payload.monitor_id))]
 	pub(super) fn import_framebuffers(
 		&mut self,
 		payload: tab_protocol::FramebufferLinkPayload,
-		dma_bufs: [OwnedFd; 2],
+		dma_bufs: [Vec<OwnedFd>; 2],
 		session_id: crate::sessions::SessionId,
 	) {
-		let Ok(monitor_id) = payload.monitor_id.parse::<crate::monitor::MonitorId>() else {
-			tracing::warn!(monitor_id = %payload.monitor_id, "invalid monitor id in framebuffer link");
-			return;
-		};
+		let monitor_id = payload.monitor_id;
 
+		let unsupported_format = !pixel_formats::supported(payload.fourcc as u32);
+		let unsupported_modifier = payload.explicit_modifier().filter(|&modifier| {
+			!formats::modifier_supported(&self.importable_modifiers, payload.fourcc as u32, modifier)
+		});
 		let mut imported = Vec::new();
-		let mut found_monitor = false;
-		let egl_context = self.drm.egl_context();
-		for mon in self.drm.monitors_mut() {
-			if mon.context().id != monitor_id {
-				continue;
+		let proc_loader = self.backend.proc_loader();
+		// Virtual monitors import on the shared context they are composed on.
+		let current_gl = if self.virtual_monitors.contains(monitor_id) {
+			Some(self.virtual_monitors.make_current(&self.backend))
+		} else {
+			self
+				.backend
+				.outputs()
+				.find(|mon| mon.context().id == monitor_id)
+				.map(|mon| mon.make_current().map(|()| mon.context().gl.clone()))
+		};
+		let Some(current_gl) = current_gl else {
+			tracing::warn!(%monitor_id, "framebuffer link for unknown monitor");
+			return;
+		};
+		let gl = match current_gl {
+			_ if unsupported_format || unsupported_modifier.is_some() => None,
+			Ok(gl) => Some(gl),
+			Err(e) => {
+				tracing::warn!(%monitor_id, "failed to make monitor current: {e}");
+				None
 			}
-			found_monitor = true;
-			if let Err(e) = mon.make_current() {
-				tracing::warn!(%monitor_id, "failed to make monitor current: {e:?}");
-				break;
-			}
-			let gl = mon.context().gl.clone();
-			let proc_loader = |symbol: &str| {
-				egl_context
-					.lock()
-					.map(|ctx| ctx.get_proc_address(symbol))
-					.unwrap_or(std::ptr::null())
-			};
-			for (idx, fd) in dma_bufs.into_iter().enumerate() {
+		};
+		if unsupported_format {
+			tracing::warn!(
+				%monitor_id,
+				"cannot compose buffers in {}",
+				pixel_formats::name(payload.fourcc as u32)
+			);
+		} else if let Some(modifier) = unsupported_modifier {
+			tracing::warn!(%monitor_id, fourcc = payload.fourcc, "cannot import buffers with modifier {modifier:#x}");
+		}
+		if let Some(gl) = gl {
+			let layouts = std::iter::once((payload.stride, payload.offset)).chain(
+				payload
+					.extra_planes
+					.iter()
+					.map(|plane| (plane.stride, plane.offset)),
+			);
+			for (idx, fds) in dma_bufs.into_iter().enumerate() {
 				let Some(slot) = BufferSlot::from_index(idx) else {
 					continue;
 				};
+				if let Some(fd) = fds.first() {
+					self.buffer_hashes.link(
+						SlotKey::new(monitor_id, session_id, slot),
+						fd.as_fd(),
+						payload.height,
+						payload.stride,
+						payload.offset,
+					);
+				}
+				let scanout = self
+					.scanout
+					.enabled()
+					.then(|| ScanoutBuffer::from_link(&payload, &fds))
+					.flatten();
 				let params = DmaBufImportParams {
 					width: payload.width,
 					height: payload.height,
-					stride: payload.stride,
-					offset: payload.offset,
 					fourcc: payload.fourcc,
-					fd,
+					modifier: payload.explicit_modifier(),
+					planes: fds
+						.into_iter()
+						.zip(layouts.clone())
+						.map(|(fd, (stride, offset))| DmaBufImportPlane { fd, offset, stride })
+						.collect(),
 				};
-				match DmaBufTexture::import(&gl, &proc_loader, params).and_then(|texture| {
+				match DmaBufTexture::import_or_copy(&gl, &proc_loader, params).and_then(|texture| {
 					texture.to_skia(format!(
 						"session_{}_monitor_{}_buffer_{}",
 						session_id, monitor_id, idx
 					))
 				}) {
-					Ok(texture) => imported.push((slot, texture)),
+					Ok(texture) => {
+						imported.push((slot, texture.with_alpha_mode(payload.alpha_mode), scanout));
+					}
 					Err(e) => {
 						tracing::warn!(%monitor_id, ?slot, "failed to import dmabuf: {e:?}");
 					}
 				}
 			}
-			break;
 		}
 
-		if !found_monitor {
-			tracing::warn!(%monitor_id, "framebuffer link for unknown monitor");
-			return;
+		self
+			.hdr
+			.set_link(monitor_id, session_id, payload.hdr_metadata);
+		self
+			.monitor_scales
+			.set_link(monitor_id, session_id, payload.scale);
+		if let Some(shown) = self
+			.ownership
+			.current_slot_key_for_session(monitor_id, session_id)
+			.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
+			&& let Some(texture) = self.slots.remove(&shown)
+		{
+			self.retired_slots.insert((monitor_id, session_id), texture);
 		}
-
-		for (slot, texture) in imported {
+		// Buffers of the previous link are gone even when the new ones failed to import.
+		for slot in [BufferSlot::Zero, BufferSlot::One] {
+			let key = SlotKey::new(monitor_id, session_id, slot);
+			self.slots.remove(&key);
+			self.scanout.link(key, None);
+		}
+		let failure = if unsupported_format {
+			Some("unsupported_format")
+		} else if unsupported_modifier.is_some() {
+			Some("unsupported_modifier")
+		} else if imported.len() < 2 {
+			Some("import_failed")
+		} else {
+			None
+		};
+		match failure {
+			Some(reason) => {
+				self
+					.link_failures
+					.insert((monitor_id, session_id), reason.into());
+			}
+			None => {
+				self.link_failures.remove(&(monitor_id, session_id));
+			}
+		}
+		for (slot, texture, scanout) in imported {
 			let key = SlotKey::new(monitor_id, session_id, slot);
 			self.slots.insert(key, texture);
+			self.scanout.link(key, scanout);
 			self.ownership.mark_slot_client_owned(key);
 		}
 	}
 
-	pub(super) async fn process_deferred_releases(&mut self, release_fence: i32) {
+	/// Copies the buffer in `key` into its texture as it is latched, when it couldn't be
+	/// imported; see [`DmaBufTexture::import_or_copy`].
+	pub(super) fn refresh_copied(&mut self, key: SlotKey) {
+		if !self
+			.slots
+			.get(&key)
+			.is_some_and(|texture| texture.is_copy())
+		{
+			return;
+		}
+		if let Err(e) = self.virtual_monitors.make_current(&self.backend) {
+			tracing::warn!(?key, "failed to make the shared context current: {e}");
+			return;
+		}
+		if let Some(texture) = self.slots.get_mut(&key)
+			&& let Err(e) = texture.refresh()
+		{
+			tracing::warn!(?key, "failed to copy buffer: {e}");
+		}
+	}
+
+	pub(super) async fn process_deferred_releases(&mut self, release_fence: Option<BorrowedFd<'_>>) {
 		for item in self.ownership.take_deferred_releases() {
-			let key = SlotKey::new(item.monitor_id, item.session_id, item.buffer);
-			self.ownership.mark_slot_client_owned(key);
-			let release_fence = if release_fence >= 0 {
-				let dup_fd = unsafe { libc::dup(release_fence) };
-				if dup_fd >= 0 {
-					tracing::debug!(monitor_id = %item.monitor_id, buffer = ?item.buffer, release_fence = dup_fd, "deferring buffer release with fence");
-					Some(unsafe { OwnedFd::from_raw_fd(dup_fd) })
-				} else {
-					tracing::warn!(monitor_id = %item.monitor_id, buffer = ?item.buffer, "failed to duplicate release fence fd");
-					None
+			// A buffer the display still scans out is released after the flip that replaces it.
+			let Some(item) = self.scanout.hold(item) else {
+				continue;
+			};
+			let release_fence = if let Some(release_fence) = release_fence {
+				match release_fence.try_clone_to_owned() {
+					Ok(fd) => {
+						tracing::debug!(monitor_id = %item.monitor_id, buffer = ?item.buffer, release_fence = fd.as_raw_fd(), "deferring buffer release with fence");
+						Some(fd)
+					}
+					Err(e) => {
+						tracing::warn!(monitor_id = %item.monitor_id, buffer = ?item.buffer, "failed to duplicate release fence fd: {e}");
+						None
+					}
 				}
 			} else {
 				tracing::debug!(monitor_id = %item.monitor_id, buffer = ?item.buffer, "no release fence for deferred buffer release");
 				None
 			};
-			self
-				.emit_event(RenderEvt::BufferConsumed {
-					session_id: item.session_id,
-					monitor_id: item.monitor_id,
-					buffer: item.buffer.into(),
-					release_fence,
-				})
-				.await;
+			self.release_buffer(item, release_fence).await;
+		}
+	}
+
+	/// Releases the buffers that were scanned out until the page flip that just completed.
+	pub(super) async fn release_scanned_out(&mut self) {
+		for item in self.scanout.take_held() {
+			self.release_buffer(item, None).await;
 		}
 	}
 
-	#[tracing::instrument(skip_all)]
-	pub(super) async fn handle_command(&mut self, cmd: RenderCmd) -> Result<bool, RenderError> {
-		match cmd {
+	async fn release_buffer(&mut self, item: DeferredRelease, release_fence: Option<OwnedFd>) {
+		let key = SlotKey::new(item.monitor_id, item.session_id, item.buffer);
+		self.ownership.mark_slot_client_owned(key);
+		let content_hash = self.buffer_hashes.hash(key);
+		let frame_id = self.frame_ids.get(&key).copied();
+		tracing::debug!(
+			frame_id = frame_id.map(tracing::field::display),
+			?key,
+			"buffer released"
+		);
+		self
+			.emit_event(RenderEvt::BufferConsumed {
+				session_id: item.session_id,
+				monitor_id: item.monitor_id,
+				buffer: item.buffer.into(),
+				release_fence,
+				content_hash,
+				frame_id,
+			})
+			.await;
+	}
+
+	/// Handles `cmd` in a child of the span the server sent it from.
+	#[tracing::instrument(parent = &cmd.span, skip_all, fields(cmd_id = %cmd.id, frame_id = cmd.cmd.frame_id().map(tracing::field::display)))]
+	pub(super) async fn handle_command(&mut self, cmd: TracedCmd) -> Result<bool, RenderError> {
+		match cmd.cmd {
 			RenderCmd::Shutdown => {
 				tracing::warn!("received shutdown request from server");
 				return Ok(false);
@@ -126,7 +256,7 @@ impl RenderingLayer {
 				session_id,
 				transition,
 			} => {
-				self.active_transition = None;
+				self.end_transition();
 				if let Some(to_session_id) = session_id
 					&& let Some(transition) = transition
 				{
@@ -134,50 +264,281 @@ impl RenderingLayer {
 				}
 				self.ownership.set_current_session(session_id);
 			}
+			RenderCmd::SetColorFilter { monitor_id, mode } => {
+				self.color_filters.set(monitor_id, mode);
+			}
+			RenderCmd::SetDisplayAdjustment {
+				monitor_id,
+				brightness,
+				contrast,
+				gamma,
+			} => {
+				self.color_filters.set_adjustment(
+					monitor_id,
+					DisplayAdjustment::new(brightness, contrast, gamma),
+				);
+			}
+			RenderCmd::SetMagnifier { monitor_id, factor } => {
+				self.magnifier.set(monitor_id, factor);
+			}
+			RenderCmd::MagnifierFocus { x, y } => {
+				self.magnifier.set_focus(x, y);
+			}
+			RenderCmd::SetWatermark { text, corner } => {
+				self.watermark.set(text, corner);
+			}
+			RenderCmd::SetWallpaper {
+				monitor_id,
+				wallpaper,
+			} => {
+				self.wallpapers.set(monitor_id, &wallpaper);
+			}
+			RenderCmd::SetColorProfile { monitor_id, source } => {
+				self.color_profiles.set(monitor_id, source);
+				self.refresh_color_profiles();
+			}
+			RenderCmd::SetVrr {
+				monitor_id,
+				enabled,
+			} => {
+				self.vrr.set(monitor_id, enabled);
+			}
+			RenderCmd::SetColorTemperature {
+				monitor_id,
+				kelvin,
+				schedule,
+			} => {
+				self.night_light.set(monitor_id, kelvin, schedule);
+			}
+			RenderCmd::SetOutputTransform {
+				monitor_id,
+				transform,
+			} => {
+				if transform.is_normal() {
+					self.output_transforms.remove(&monitor_id);
+				} else {
+					self.output_transforms.insert(monitor_id, transform);
+				}
+				self.sync_cursor_planes();
+			}
+			RenderCmd::SetMonitorScale { monitor_id, scale } => {
+				self.monitor_scales.set(monitor_id, scale);
+			}
+			RenderCmd::SetFlipGroups { groups } => {
+				self.flip_groups.set(groups);
+			}
+			RenderCmd::SetRenderResolution { monitor_id, scale } => {
+				if scale.is_one() {
+					self.render_resolutions.remove(&monitor_id);
+				} else {
+					self.render_resolutions.insert(monitor_id, scale);
+				}
+			}
+			RenderCmd::SetMode {
+				monitor_id,
+				width,
+				height,
+				refresh,
+			} => {
+				let result = self.set_mode(monitor_id, width, height, refresh);
+				if result.is_ok() {
+					// Picks up the new size, which recreates the output's surfaces and announces
+					// `MonitorResized`.
+					self.sync_monitors().await;
+					self.commit_policy.damage();
+					self.idle.damage_all();
+				}
+				let result = result.and_then(|()| {
+					self
+						.known_monitors
+						.get(&monitor_id)
+						.cloned()
+						.ok_or_else(|| "unknown_monitor".into())
+				});
+				self
+					.emit_event(RenderEvt::MonitorModeChanged { monitor_id, result })
+					.await;
+			}
+			RenderCmd::SetCursor { monitor_id, image } => {
+				self.cursor.set(monitor_id, image);
+				self.sync_cursor_planes();
+			}
+			RenderCmd::MoveCursor { monitor_id, x, y } => {
+				self.cursor.queue_move(monitor_id, x, y);
+			}
+			RenderCmd::Capture { monitor_id } => {
+				self.request_capture(monitor_id);
+				self.emit_finished_captures().await;
+			}
+			RenderCmd::AddVirtualMonitor {
+				monitor,
+				session_id,
+				mirror,
+				scaling,
+			} => {
+				self
+					.virtual_monitors
+					.add(monitor, session_id, mirror, scaling);
+			}
+			RenderCmd::RemoveVirtualMonitor { monitor_id } => {
+				if self.virtual_monitors.remove(monitor_id) {
+					self.cleanup_monitor_slots(monitor_id);
+					self.frame_stats.remove_monitor(monitor_id);
+					self.frame_pacing.forget_monitor(monitor_id);
+				}
+				self.emit_finished_captures().await;
+			}
+			RenderCmd::StartScreencast {
+				cast_id,
+				monitor_id,
+			} => {
+				if let Err(reason) = self.start_screencast(cast_id, monitor_id) {
+					tracing::warn!(%monitor_id, %reason, "screencast failed to start");
+					self
+						.emit_event(RenderEvt::ScreencastEnded { cast_id, reason })
+						.await;
+				}
+			}
+			RenderCmd::StopScreencast { cast_id } => {
+				self.screencasts.stop(cast_id);
+			}
+			RenderCmd::SetScreencastPaused { cast_id, paused } => {
+				// Resumed casts continue with the monitor's next frame.
+				if let Some(monitor_id) = self.screencasts.set_paused(cast_id, paused)
+					&& !paused
+				{
+					self.virtual_monitors.damage(monitor_id);
+				}
+			}
+			RenderCmd::SetMonitorEnabled {
+				monitor_id,
+				enabled,
+			} => {
+				if enabled {
+					self.disabled_monitors.remove(&monitor_id);
+				} else {
+					self.disabled_monitors.insert(monitor_id);
+				}
+			}
+			RenderCmd::SetSessionSwitcher { view } => {
+				self.switcher.set(view);
+			}
+			RenderCmd::SetRecoveryScreen { screen } => {
+				self.recovery.set(screen);
+			}
+			RenderCmd::SetBlanked { blanked } => {
+				self.blanked = blanked;
+			}
+			RenderCmd::SnapshotSession { session_id } => {
+				self.snapshot_session(session_id);
+			}
+			RenderCmd::PurgeResources => {
+				self.resource_cache.purge(&mut self.skia);
+			}
+			RenderCmd::MemoryPressure => {
+				// Dropped first, so the purge also frees the targets they were drawn into.
+				self.session_snapshots.clear();
+				self.resource_cache.purge(&mut self.skia);
+			}
+			RenderCmd::SetLatencyMode { session_id, mode } => {
+				self.frame_pacing.set(session_id, mode);
+			}
+			RenderCmd::SetRenderQuality {
+				session_id,
+				quality,
+			} => {
+				self.render_quality.insert(session_id, quality);
+			}
+			RenderCmd::SetOverlayRegions {
+				session_id,
+				monitor_id,
+				regions,
+			} => {
+				self.overlays.set(session_id, monitor_id, &regions);
+			}
 			RenderCmd::SessionRemoved { session_id } => {
-				self.cleanup_session_slots(session_id);
+				self.remove_session(session_id);
+				self.overlays.remove_session(session_id);
 				if self.ownership.current_session() == Some(session_id) {
 					self.ownership.set_current_session(None);
 				}
 			}
+			RenderCmd::DropSessionBuffers {
+				session_id,
+				monitor_id,
+			} => {
+				self.cleanup_surface_slots(monitor_id, session_id);
+			}
 			RenderCmd::SwapBuffers {
 				monitor_id,
 				buffer,
 				session_id,
 				acquire_fence,
+				present_at,
+				frame_id,
 			} => {
 				let slot = BufferSlot::from(buffer);
-				let monitor_known = self.known_monitors.contains_key(&monitor_id);
+				let monitor_known = self.known_monitors.contains_key(&monitor_id)
+					|| self.virtual_monitors.contains(monitor_id);
 				let slot_key = SlotKey::new(monitor_id, session_id, slot);
 				let slot_known = self.slots.contains_key(&slot_key);
 				if !monitor_known || !slot_known {
 					let reason: Arc<str> = if !monitor_known {
-						"unknown_monitor"
+						"unknown_monitor".into()
 					} else {
-						"unlinked_buffer"
-					}
-					.into();
+						self
+							.link_failures
+							.get(&(monitor_id, session_id))
+							.cloned()
+							.unwrap_or_else(|| "unlinked_buffer".into())
+					};
 					self
 						.emit_event(RenderEvt::BufferRequestRejected {
 							session_id,
 							monitor_id,
 							buffer,
 							reason,
+							frame_id,
 						})
 						.await;
 				} else {
 					let has_acquire_fence = acquire_fence.is_some();
-					let transition =
+					let present_at = present_at.and_then(present_queue::instant_from_monotonic);
+					let deferred = has_acquire_fence || present_at.is_some();
+					let stale = self
+						.ownership
+						.current_slot_key_for_session(monitor_id, session_id)
+						== Some(slot_key);
+					if stale {
 						self
-							.ownership
-							.apply_swap_request(monitor_id, session_id, slot, has_acquire_fence);
+							.frame_stats
+							.record_stale_present(monitor_id, session_id, Instant::now());
+					} else if !deferred {
+						self
+							.frame_stats
+							.record_latch(monitor_id, session_id, Instant::now());
+						self.idle.damage(monitor_id);
+						self.virtual_monitors.presented(monitor_id, session_id);
+						self.session_snapshots.presented(monitor_id, session_id);
+						self.presentation_feedback.latched(slot_key);
+						self.refresh_copied(slot_key);
+					}
+					self.frame_ids.insert(slot_key, frame_id);
+					let transition = self
+						.ownership
+						.apply_swap_request(monitor_id, session_id, slot, deferred);
 					if let Some(pending) = transition.canceled_pending {
 						let pending_key = SlotKey::new(monitor_id, session_id, pending);
 						self.cancel_fence_wait(pending_key);
+						self.present_queue.cancel(pending_key);
 						self
 							.ownership
 							.queue_buffer_release(monitor_id, session_id, pending);
 					}
+					match present_at {
+						Some(at) => self.present_queue.queue(slot_key, at, has_acquire_fence),
+						None => self.present_queue.cancel(slot_key),
+					}
 					if let Some(fence_fd) = acquire_fence {
 						self.spawn_acquire_fence_waiter(slot_key, fence_fd);
 					} else {
@@ -193,6 +554,8 @@ impl RenderingLayer {
 							session_id,
 							monitor_id,
 							buffer,
+							frame_id,
+							deferred,
 						})
 						.await;
 				}
@@ -201,4 +564,42 @@ impl RenderingLayer {
 
 		Ok(true)
 	}
+	/// Switches `monitor_id` to the mode of its connector's that is `width`x`height` at `refresh`
+	/// Hz.
+	fn set_mode(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		width: i32,
+		height: i32,
+		refresh: u32,
+	) -> Result<(), Arc<str>> {
+		if self.tiles.placement(monitor_id).is_some() {
+			return Err("tiled_monitor".into());
+		}
+		let Some(output) = self
+			.backend
+			.outputs_mut()
+			.find(|mon| mon.context().id == monitor_id)
+		else {
+			return Err("unknown_monitor".into());
+		};
+		let Some(mode) = self
+			.connector_modes
+			.get(&output.connector_id())
+			.and_then(|connector_modes| modes::find(connector_modes, width, height, refresh))
+		else {
+			return Err("invalid_mode".into());
+		};
+		match output.set_mode(mode) {
+			Ok(true) => {
+				tracing::info!(%monitor_id, width, height, refresh, "monitor mode changed");
+				Ok(())
+			}
+			Ok(false) => Err("mode_change_unsupported".into()),
+			Err(e) => {
+				tracing::warn!(%monitor_id, "failed to set monitor mode: {e}");
+				Err("mode_set_failed".into())
+			}
+		}
+	}
 }
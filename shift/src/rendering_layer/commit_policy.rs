@@ -0,0 +1,149 @@
+//! Power-saving commit policy.
+//!
+//! The renderer normally recomposes and commits every frame. When nothing changed since the
+//! last commit that reached every output (no command, no newly signaled buffer, no hotplug, no
+//! running animation) it stops committing altogether, so panels with self-refresh (PSR) can
+//! power down the link and scan out from their own frame buffer. The first damage resumes
+//! commits. Between static periods, outputs with nothing new keep their frame while the
+//! others draw (see [`super::idle`]). `SHIFT_PSR=0` restores the always-commit behavior.
+//!
+//! A cursor move only asks for a frame; outputs then redraw just the rects the cursor covered
+//! (see [`super::damage`]). Any other damage redraws them whole.
+
+/// What the render loop should do this iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CommitDecision {
+	/// Compose and commit. `resumed` is set on the first commit after a static period.
+	Commit { resumed: bool },
+	/// Keep the outputs as they are. `entered` is set on the first skipped frame.
+	Skip { entered: bool },
+}
+
+#[derive(Debug)]
+pub(super) struct CommitPolicy {
+	enabled: bool,
+	damaged: bool,
+	/// Set by damage other than cursor moves, until the next frame takes it.
+	scene_damaged: bool,
+	is_static: bool,
+}
+
+impl CommitPolicy {
+	pub fn new(enabled: bool) -> Self {
+		Self {
+			enabled,
+			damaged: true,
+			scene_damaged: true,
+			is_static: false,
+		}
+	}
+
+	/// Reads `SHIFT_PSR` (default on).
+	pub fn from_env() -> Self {
+		Self::new(crate::input_layer::env_bool("SHIFT_PSR", true))
+	}
+
+	/// Something that affects the composed output changed.
+	pub fn damage(&mut self) {
+		self.damaged = true;
+		self.scene_damaged = true;
+	}
+
+	/// Only the composed cursor moved.
+	pub fn damage_cursor(&mut self) {
+		self.damaged = true;
+	}
+
+	/// Whether anything but the cursor changed since the last call, so outputs redraw whole.
+	pub fn take_scene_damage(&mut self) -> bool {
+		std::mem::take(&mut self.scene_damaged)
+	}
+
+	/// Whether commits stop when nothing changed; off with `SHIFT_PSR=0`.
+	pub fn enabled(&self) -> bool {
+		self.enabled
+	}
+
+	pub fn is_static(&self) -> bool {
+		self.is_static
+	}
+
+	pub fn next_frame(&mut self, animating: bool) -> CommitDecision {
+		if !self.enabled || self.damaged || animating {
+			let resumed = std::mem::take(&mut self.is_static);
+			return CommitDecision::Commit { resumed };
+		}
+		let entered = !std::mem::replace(&mut self.is_static, true);
+		CommitDecision::Skip { entered }
+	}
+
+	/// Records the outcome of a commit. Damage is only cleared once every output picked up
+	/// the new frame; outputs still waiting on a page flip are redrawn next time.
+	pub fn committed(&mut self, all_outputs_drawn: bool) {
+		self.damaged = !all_outputs_drawn;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn skips_static_frames_until_damaged() {
+		let mut policy = CommitPolicy::new(true);
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Commit { resumed: false }
+		);
+		policy.committed(false);
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Commit { resumed: false }
+		);
+		policy.committed(true);
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Skip { entered: true }
+		);
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Skip { entered: false }
+		);
+		assert!(policy.is_static());
+		policy.damage();
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Commit { resumed: true }
+		);
+		policy.committed(true);
+		assert_eq!(
+			policy.next_frame(true),
+			CommitDecision::Commit { resumed: false }
+		);
+	}
+
+	#[test]
+	fn cursor_moves_commit_without_scene_damage() {
+		let mut policy = CommitPolicy::new(true);
+		assert!(policy.take_scene_damage(), "the first frame is drawn whole");
+		policy.committed(true);
+		policy.damage_cursor();
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Commit { resumed: false }
+		);
+		assert!(!policy.take_scene_damage());
+		policy.damage();
+		assert!(policy.take_scene_damage());
+	}
+
+	#[test]
+	fn disabled_policy_always_commits() {
+		let mut policy = CommitPolicy::new(false);
+		policy.committed(true);
+		assert_eq!(
+			policy.next_frame(false),
+			CommitDecision::Commit { resumed: false }
+		);
+	}
+}
@@ -0,0 +1,507 @@
+//! The current session's pointer cursor.
+//!
+//! The server sends the image with [`RenderCmd::SetCursor`] and the pointer position with
+//! [`RenderCmd::MoveCursor`] as input comes in. Each output showing the cursor puts it on its
+//! cursor plane ([`BackendOutput::set_cursor`]), where a move takes effect at the next vblank
+//! without composing or committing a frame. Outputs whose backend has no usable cursor plane, or
+//! one too small for the image, compose the cursor on top of their frame instead, which also
+//! rules out direct scanout while it is shown there. So do rotated or flipped outputs, as the
+//! plane can't turn the image.
+//!
+//! The EasyDRM backend drives the plane with the legacy cursor ioctls on a card opened for the
+//! purpose ([`CursorPlane`]). Like `VRR_ENABLED`, only the DRM master may do that; a refusal is
+//! logged once and the output composes the cursor from then on.
+//!
+//! Pointer devices report far more often than displays refresh, so moves are resampled to the
+//! refresh of the cursor's monitor: a move due within a period of the last one waits until
+//! then, and only the newest position reaches the screen ([`Cursor::resample`]). A frame
+//! composed in between takes it along. `SHIFT_CURSOR_RESAMPLE=0` moves the cursor on every
+//! event instead. Clients still receive every motion event.
+//!
+//! A move on an output that composes the cursor only damages the rect the cursor left and the
+//! one it now covers ([`Cursor::take_damage`]), so the frame redraws just those.
+//!
+//! [`RenderCmd::SetCursor`]: crate::comms::server2render::RenderCmd::SetCursor
+//! [`RenderCmd::MoveCursor`]: crate::comms::server2render::RenderCmd::MoveCursor
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use drm::{
+	Device as _, DriverCapability,
+	buffer::{Buffer as _, DrmFourcc},
+	control::{Device as ControlDevice, connector, crtc, dumbbuffer::DumbBuffer},
+};
+use skia_safe::{AlphaType, Canvas, ColorType, Data, IRect, Image, ImageInfo, images};
+
+use super::backend::{BackendError, BackendOutput};
+use crate::{comms::server2render::CursorImage, drm_card::Card, monitor::MonitorId};
+
+/// Cursor plane size drivers that don't report one take.
+const DEFAULT_PLANE_SIZE: u64 = 64;
+
+#[derive(Debug, Default)]
+pub(super) struct Cursor {
+	image: Option<CursorImage>,
+	/// Logical monitor the pointer is on.
+	monitor_id: Option<MonitorId>,
+	/// Hotspot position in monitor pixels.
+	position: (f64, f64),
+	/// Bumped whenever the image changes, so outputs know to reprogram their plane.
+	generation: u64,
+	/// `image` as a Skia image, for outputs that compose it.
+	raster: Option<Image>,
+	/// Keyed by connector-level output id.
+	outputs: HashMap<MonitorId, OutputCursor>,
+	/// Newest pointer position not on screen yet.
+	pending_move: Option<(MonitorId, (f64, f64))>,
+	/// When the cursor last moved on screen.
+	moved_at: Option<Instant>,
+	/// Whether moves wait for the next refresh, see the module docs.
+	resample: bool,
+}
+
+#[derive(Debug, Default)]
+struct OutputCursor {
+	/// Image generation on the cursor plane; `None` when the plane is off.
+	on_plane: Option<u64>,
+	/// Image generation the plane refused, so it isn't offered again.
+	refused: Option<u64>,
+	/// Plane position last programmed, in output pixels.
+	plane_position: Option<(i32, i32)>,
+	/// Whether the output composes the cursor into its frames.
+	composed: bool,
+	/// Rect the composed cursor covers as of the last [`Cursor::take_damage`], in output pixels.
+	composed_rect: Option<IRect>,
+}
+
+impl Cursor {
+	pub fn from_env() -> Self {
+		Self {
+			resample: crate::input_layer::env_bool("SHIFT_CURSOR_RESAMPLE", true),
+			..Self::default()
+		}
+	}
+
+	pub fn set(&mut self, monitor_id: MonitorId, image: Option<CursorImage>) {
+		self.monitor_id = Some(monitor_id);
+		if self.image == image {
+			return;
+		}
+		self.raster = image.as_ref().and_then(raster_image);
+		self.image = image;
+		self.generation += 1;
+	}
+
+	pub fn move_to(&mut self, monitor_id: MonitorId, x: f64, y: f64) {
+		self.monitor_id = Some(monitor_id);
+		self.position = (x, y);
+	}
+
+	/// Records the pointer's newest position; it reaches the screen with [`Self::resample`].
+	pub fn queue_move(&mut self, monitor_id: MonitorId, x: f64, y: f64) {
+		self.pending_move = Some((monitor_id, (x, y)));
+	}
+
+	/// Logical monitor the cursor is on, or is about to move to.
+	pub fn monitor_id(&self) -> Option<MonitorId> {
+		self
+			.pending_move
+			.map(|(monitor_id, _)| monitor_id)
+			.or(self.monitor_id)
+	}
+
+	/// When the pending move is due: one refresh after the last move on a monitor refreshing
+	/// at `refresh_rate` Hz, right away when the cursor was still. `None` without a pending
+	/// move.
+	pub fn resample_at(&self, refresh_rate: u32) -> Option<Instant> {
+		self.pending_move?;
+		let period = match refresh_rate {
+			rate if self.resample && rate > 0 => Duration::from_secs_f64(1.0 / f64::from(rate)),
+			_ => Duration::ZERO,
+		};
+		Some(
+			self
+				.moved_at
+				.map_or_else(Instant::now, |moved_at| moved_at + period),
+		)
+	}
+
+	/// Moves the cursor to the newest position queued. Returns whether there was one.
+	pub fn resample(&mut self, now: Instant) -> bool {
+		let Some((monitor_id, (x, y))) = self.pending_move.take() else {
+			return false;
+		};
+		self.move_to(monitor_id, x, y);
+		self.moved_at = Some(now);
+		true
+	}
+
+	/// Brings `output`'s cursor plane in line with the cursor. `monitor_id` is the logical
+	/// monitor the output belongs to and `offset` where the output starts within it. Without
+	/// `plane` the output composes the cursor.
+	pub fn sync(
+		&mut self,
+		output: &mut impl BackendOutput,
+		monitor_id: MonitorId,
+		offset: (i32, i32),
+		plane: bool,
+	) -> Result<(), BackendError> {
+		let state = self.outputs.entry(output.context().id).or_default();
+		let image = self
+			.image
+			.as_ref()
+			.filter(|_| self.monitor_id == Some(monitor_id));
+		let Some(image) = image else {
+			if state.on_plane.take().is_some() {
+				output.set_cursor(None)?;
+			}
+			state.plane_position = None;
+			state.composed = false;
+			return Ok(());
+		};
+		if !plane {
+			if state.on_plane.take().is_some() {
+				output.set_cursor(None)?;
+			}
+			state.plane_position = None;
+		} else if state.on_plane != Some(self.generation) && state.refused != Some(self.generation) {
+			if output.set_cursor(Some(image))? {
+				state.on_plane = Some(self.generation);
+			} else {
+				state.refused = Some(self.generation);
+				if state.on_plane.take().is_some() {
+					output.set_cursor(None)?;
+				}
+			}
+		}
+		state.composed = state.on_plane.is_none();
+		if !state.composed {
+			let top_left = top_left(image, self.position, offset);
+			if state.plane_position != Some(top_left) {
+				output.move_cursor(top_left)?;
+				state.plane_position = Some(top_left);
+			}
+		}
+		Ok(())
+	}
+
+	/// Whether `output` (connector-level id) composes the cursor into its frames.
+	pub fn composed_on(&self, output: MonitorId) -> bool {
+		self
+			.outputs
+			.get(&output)
+			.is_some_and(|state| state.composed)
+	}
+
+	/// Whether a cursor move needs composing, i.e. some output can't show it on a plane.
+	pub fn composed_anywhere(&self) -> bool {
+		self.outputs.values().any(|state| state.composed)
+	}
+
+	/// Rects of `output` (connector-level id, at `offset` within its logical monitor) that
+	/// changed since the last call: where the composed cursor was then and where it is now.
+	pub fn take_damage(&mut self, output: MonitorId, offset: (i32, i32)) -> Vec<IRect> {
+		let rect = match (&self.image, self.composed_on(output)) {
+			(Some(image), true) => {
+				let (x, y) = top_left(image, self.position, offset);
+				Some(IRect::from_xywh(
+					x,
+					y,
+					image.width as i32,
+					image.height as i32,
+				))
+			}
+			_ => None,
+		};
+		let Some(state) = self.outputs.get_mut(&output) else {
+			return Vec::new();
+		};
+		let previous = std::mem::replace(&mut state.composed_rect, rect);
+		if previous == rect {
+			return Vec::new();
+		}
+		previous.into_iter().chain(rect).collect()
+	}
+
+	/// Draws the cursor on `canvas` of an output at `offset` within its logical monitor.
+	pub fn draw(&self, canvas: &Canvas, offset: (i32, i32)) {
+		let (Some(image), Some(raster)) = (&self.image, &self.raster) else {
+			return;
+		};
+		let (x, y) = top_left(image, self.position, offset);
+		canvas.draw_image(raster, (x as f32, y as f32), None);
+	}
+
+	pub fn remove_output(&mut self, output: MonitorId) {
+		self.outputs.remove(&output);
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		if self.monitor_id == Some(monitor_id) {
+			self.monitor_id = None;
+		}
+		if self
+			.pending_move
+			.is_some_and(|(pending, _)| pending == monitor_id)
+		{
+			self.pending_move = None;
+		}
+	}
+}
+
+/// Where the image's top-left lands on an output at `offset` within its logical monitor.
+pub(super) fn top_left(
+	image: &CursorImage,
+	position: (f64, f64),
+	offset: (i32, i32),
+) -> (i32, i32) {
+	(
+		position.0.floor() as i32 - image.hotspot.0 as i32 - offset.0,
+		position.1.floor() as i32 - image.hotspot.1 as i32 - offset.1,
+	)
+}
+
+pub(super) fn raster_image(image: &CursorImage) -> Option<Image> {
+	let info = ImageInfo::new(
+		(image.width as i32, image.height as i32),
+		ColorType::BGRA8888,
+		AlphaType::Premul,
+		None,
+	);
+	images::raster_from_data(
+		&info,
+		Data::new_copy(&image.pixels),
+		image.width as usize * 4,
+	)
+}
+
+/// An output's DRM cursor plane. EasyDRM owns the atomic state of the CRTC, so the plane is
+/// driven through the legacy cursor ioctls, which the kernel maps onto it.
+#[derive(Default)]
+pub struct CursorPlane {
+	device: Option<PlaneDevice>,
+	/// Set once the card refused the plane; it isn't tried again.
+	unavailable: bool,
+}
+
+struct PlaneDevice {
+	card: Card,
+	crtc: crtc::Handle,
+	size: (u32, u32),
+	buffer: DumbBuffer,
+}
+
+#[allow(deprecated)]
+impl CursorPlane {
+	/// Shows `image` on the plane of the CRTC driving `connector_id`, or turns the plane off.
+	/// Returns `false` when the plane can't show the image.
+	pub fn set(&mut self, connector_id: u32, image: Option<&CursorImage>) -> bool {
+		let Some(image) = image else {
+			if let Some(device) = &self.device
+				&& let Err(e) = device
+					.card
+					.set_cursor2(device.crtc, None::<&DumbBuffer>, (0, 0))
+			{
+				tracing::warn!(connector_id, "failed to turn the cursor plane off: {e}");
+			}
+			return true;
+		};
+		if self.unavailable {
+			return false;
+		}
+		if self.device.is_none() {
+			match PlaneDevice::open(connector_id) {
+				Ok(device) => self.device = Some(device),
+				Err(e) => {
+					tracing::warn!(connector_id, "no cursor plane, composing the cursor: {e}");
+					self.unavailable = true;
+					return false;
+				}
+			}
+		}
+		let Some(device) = &mut self.device else {
+			return false;
+		};
+		if image.width > device.size.0 || image.height > device.size.1 {
+			return false;
+		}
+		if let Err(e) = device.show(image) {
+			tracing::warn!(
+				connector_id,
+				"cursor plane refused the cursor, composing it: {e}"
+			);
+			self.device = None;
+			self.unavailable = true;
+			return false;
+		}
+		true
+	}
+
+	/// Moves the plane so the image's top-left lands at `position`, in output pixels.
+	pub fn move_to(&self, position: (i32, i32)) {
+		if let Some(device) = &self.device
+			&& let Err(e) = device.card.move_cursor(device.crtc, position)
+		{
+			tracing::warn!("failed to move the cursor plane: {e}");
+		}
+	}
+}
+
+#[allow(deprecated)]
+impl PlaneDevice {
+	fn open(connector_id: u32) -> std::io::Result<Self> {
+		let not_found = || {
+			std::io::Error::new(
+				std::io::ErrorKind::NotFound,
+				"no CRTC driving the connector",
+			)
+		};
+		let handle: connector::Handle = drm::control::from_u32(connector_id).ok_or_else(not_found)?;
+		for (_, card) in Card::open_all() {
+			let Some(crtc) = card
+				.get_connector(handle, false)
+				.ok()
+				.and_then(|info| info.current_encoder())
+				.and_then(|encoder| card.get_encoder(encoder).ok())
+				.and_then(|encoder| encoder.crtc())
+			else {
+				continue;
+			};
+			let capability = |cap| {
+				card
+					.get_driver_capability(cap)
+					.ok()
+					.filter(|size| *size > 0)
+					.unwrap_or(DEFAULT_PLANE_SIZE) as u32
+			};
+			let size = (
+				capability(DriverCapability::CursorWidth),
+				capability(DriverCapability::CursorHeight),
+			);
+			let buffer = card.create_dumb_buffer(size, DrmFourcc::Argb8888, 32)?;
+			return Ok(Self {
+				card,
+				crtc,
+				size,
+				buffer,
+			});
+		}
+		Err(not_found())
+	}
+
+	fn show(&mut self, image: &CursorImage) -> std::io::Result<()> {
+		let pitch = self.buffer.pitch() as usize;
+		let row = image.width as usize * 4;
+		let mut mapping = self.card.map_dumb_buffer(&mut self.buffer)?;
+		mapping.fill(0);
+		for (y, line) in image.pixels.chunks_exact(row).enumerate() {
+			mapping[y * pitch..y * pitch + row].copy_from_slice(line);
+		}
+		drop(mapping);
+		let hotspot = (image.hotspot.0 as i32, image.hotspot.1 as i32);
+		self
+			.card
+			.set_cursor2(self.crtc, Some(&self.buffer), hotspot)
+	}
+}
+
+#[allow(deprecated)]
+impl Drop for PlaneDevice {
+	fn drop(&mut self) {
+		let _ = self
+			.card
+			.set_cursor2(self.crtc, None::<&DumbBuffer>, (0, 0));
+		let _ = self.card.destroy_dumb_buffer(self.buffer);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn arrow() -> CursorImage {
+		CursorImage {
+			width: 16,
+			height: 16,
+			hotspot: (2, 3),
+			pixels: vec![0xff; 16 * 16 * 4].into(),
+		}
+	}
+
+	#[test]
+	fn the_hotspot_lands_on_the_pointer() {
+		assert_eq!(top_left(&arrow(), (100.7, 50.2), (0, 0)), (98, 47));
+		assert_eq!(
+			top_left(&arrow(), (2000.0, 50.0), (1920, 0)),
+			(78, 47),
+			"tiles place it relative to their slice"
+		);
+	}
+
+	#[test]
+	fn composed_moves_damage_both_rects() {
+		let mut cursor = Cursor::default();
+		let monitor = "mon_1".parse().expect("monitor id");
+		cursor.set(monitor, Some(arrow()));
+		cursor.outputs.insert(
+			monitor,
+			OutputCursor {
+				composed: true,
+				..OutputCursor::default()
+			},
+		);
+		cursor.move_to(monitor, 10.0, 10.0);
+		let first = IRect::from_xywh(8, 7, 16, 16);
+		assert_eq!(cursor.take_damage(monitor, (0, 0)), vec![first]);
+		assert!(
+			cursor.take_damage(monitor, (0, 0)).is_empty(),
+			"it didn't move"
+		);
+		cursor.move_to(monitor, 30.0, 10.0);
+		assert_eq!(
+			cursor.take_damage(monitor, (0, 0)),
+			vec![first, IRect::from_xywh(28, 7, 16, 16)]
+		);
+	}
+
+	#[test]
+	fn moves_within_a_refresh_are_resampled_to_the_newest() {
+		let mut cursor = Cursor {
+			resample: true,
+			..Cursor::default()
+		};
+		let monitor = "mon_1".parse().expect("monitor id");
+		let start = Instant::now();
+		cursor.queue_move(monitor, 10.0, 10.0);
+		assert!(
+			cursor
+				.resample_at(60)
+				.is_some_and(|at| at <= Instant::now()),
+			"a still cursor moves right away"
+		);
+		assert!(cursor.resample(start));
+		cursor.queue_move(monitor, 11.0, 10.0);
+		cursor.queue_move(monitor, 12.0, 10.0);
+		let period = Duration::from_secs_f64(1.0 / 60.0);
+		assert_eq!(cursor.resample_at(60), Some(start + period));
+		assert!(cursor.resample(start + period));
+		assert_eq!(cursor.position, (12.0, 10.0));
+		assert_eq!(cursor.resample_at(60), None);
+		assert!(!cursor.resample(start + period * 2));
+	}
+
+	#[test]
+	fn unchanged_images_keep_their_generation() {
+		let mut cursor = Cursor::default();
+		let monitor = "mon_1".parse().expect("monitor id");
+		cursor.set(monitor, Some(arrow()));
+		let generation = cursor.generation;
+		cursor.set(monitor, Some(arrow()));
+		assert_eq!(cursor.generation, generation);
+		cursor.set(monitor, None);
+		assert_ne!(cursor.generation, generation);
+	}
+}
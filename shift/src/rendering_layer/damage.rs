@@ -0,0 +1,252 @@
+//! Per-output damage, so a frame only redraws what changed since its framebuffer was drawn.
+//!
+//! Anything that changes the scene damages every output whole ([`CommitPolicy::damage`]); a
+//! cursor composed into the frame only damages the rect it left and the one it moved to
+//! ([`CommitPolicy::damage_cursor`]). An output composing a frame redraws the union of the damage
+//! its framebuffer missed, clipped to it, and keeps the rest of the framebuffer as it was. How
+//! many frames a framebuffer missed comes from `EGL_EXT_buffer_age` when the output draws to an
+//! EGL window surface, and from which framebuffer object is bound otherwise. When neither tells,
+//! the frame is redrawn whole.
+//!
+//! [`CommitPolicy::damage`]: super::commit_policy::CommitPolicy::damage
+//! [`CommitPolicy::damage_cursor`]: super::commit_policy::CommitPolicy::damage_cursor
+
+use std::{
+	collections::{HashMap, VecDeque},
+	ffi::c_void,
+};
+
+use skia_safe::IRect;
+
+use super::egl;
+use crate::monitor::MonitorId;
+
+/// Frames of damage kept per output; framebuffers that missed more are redrawn whole.
+const MAX_AGE: usize = 4;
+
+/// Part of an output to redraw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Region {
+	Empty,
+	Rect(IRect),
+	Full,
+}
+
+impl Region {
+	fn union(self, other: Region) -> Region {
+		match (self, other) {
+			(Region::Full, _) | (_, Region::Full) => Region::Full,
+			(Region::Empty, region) | (region, Region::Empty) => region,
+			(Region::Rect(a), Region::Rect(b)) => Region::Rect(IRect::from_ltrb(
+				a.left.min(b.left),
+				a.top.min(b.top),
+				a.right.max(b.right),
+				a.bottom.max(b.bottom),
+			)),
+		}
+	}
+
+	/// Clip to draw the frame with; `None` draws everything.
+	pub fn clip(self) -> Option<IRect> {
+		match self {
+			Region::Empty => Some(IRect::new_empty()),
+			Region::Rect(rect) => Some(rect),
+			Region::Full => None,
+		}
+	}
+}
+
+#[derive(Default)]
+pub(super) struct FrameDamage {
+	/// Queries the age of the current window surface's back buffer; `None` in tests.
+	egl: Option<egl::Egl>,
+	/// Keyed by connector-level output id.
+	outputs: HashMap<MonitorId, OutputDamage>,
+}
+
+struct OutputDamage {
+	size: (u32, u32),
+	/// Frames drawn so far.
+	frame: u64,
+	/// Frame each framebuffer object last received.
+	fbo_frames: HashMap<i32, u64>,
+	/// Damage of the last frames, newest first.
+	history: VecDeque<Region>,
+	/// Damage since the last frame.
+	pending: Region,
+}
+
+impl Default for OutputDamage {
+	fn default() -> Self {
+		Self {
+			size: (0, 0),
+			frame: 0,
+			fbo_frames: HashMap::new(),
+			history: VecDeque::new(),
+			pending: Region::Full,
+		}
+	}
+}
+
+impl FrameDamage {
+	pub fn load(resolver: impl Fn(&str) -> *const c_void) -> Self {
+		let egl = egl::Egl::load_with(|name| resolver(name));
+		Self {
+			egl: egl.QuerySurface.is_loaded().then_some(egl),
+			outputs: HashMap::new(),
+		}
+	}
+
+	/// The scene changed: every output redraws whole.
+	pub fn damage_all(&mut self) {
+		for output in self.outputs.values_mut() {
+			output.pending = Region::Full;
+		}
+	}
+
+	/// `rect` of `output`, in output pixels, changed.
+	pub fn damage_rect(&mut self, output: MonitorId, rect: IRect) {
+		let output = self.outputs.entry(output).or_default();
+		output.pending = output.pending.union(Region::Rect(rect));
+	}
+
+	/// Forgets what `output`'s framebuffers hold, e.g. after it scanned a buffer out or went
+	/// away; its next frame is redrawn whole.
+	pub fn forget(&mut self, output: MonitorId) {
+		self.outputs.remove(&output);
+	}
+
+	/// Starts a frame of `output`, `size` pixels large, into the framebuffer object `fbo` on
+	/// the current context, and returns what to redraw. `full` redraws everything regardless.
+	pub fn begin_frame(
+		&mut self,
+		output: MonitorId,
+		fbo: i32,
+		size: (u32, u32),
+		full: bool,
+	) -> Region {
+		let surface_age = self.surface_age();
+		let output = self.outputs.entry(output).or_default();
+		if full {
+			output.pending = Region::Full;
+		}
+		output.begin_frame(fbo, size, surface_age)
+	}
+
+	/// The back buffer's age on the current EGL window surface; `None` without one, and
+	/// `Some(None)` when EGL doesn't know.
+	fn surface_age(&self) -> Option<Option<u32>> {
+		let egl = self.egl.as_ref()?;
+		let display = unsafe { egl.GetCurrentDisplay() };
+		let surface = unsafe { egl.GetCurrentSurface(egl::DRAW as i32) };
+		if display.is_null() || surface.is_null() {
+			return None;
+		}
+		let mut age = 0;
+		let queried =
+			unsafe { egl.QuerySurface(display, surface, egl::BUFFER_AGE_EXT as i32, &mut age) };
+		Some((queried != 0 && age > 0).then_some(age as u32))
+	}
+}
+
+impl OutputDamage {
+	/// `surface_age` is the EGL buffer age, as [`FrameDamage::surface_age`] reports it.
+	fn begin_frame(
+		&mut self,
+		fbo: i32,
+		size: (u32, u32),
+		surface_age: Option<Option<u32>>,
+	) -> Region {
+		if self.size != size {
+			*self = Self {
+				size,
+				..Self::default()
+			};
+		}
+		self.frame += 1;
+		let age = match surface_age {
+			Some(age) => age,
+			None => self
+				.fbo_frames
+				.insert(fbo, self.frame)
+				.map(|last| (self.frame - last) as u32),
+		};
+		let pending = std::mem::replace(&mut self.pending, Region::Empty);
+		let region = match age {
+			Some(age) if age >= 1 && age as usize - 1 <= self.history.len() => self
+				.history
+				.iter()
+				.take(age as usize - 1)
+				.fold(pending, |region, damage| region.union(*damage)),
+			_ => Region::Full,
+		};
+		self.history.push_front(pending);
+		self.history.truncate(MAX_AGE);
+		region
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn framebuffers_redraw_the_damage_they_missed() {
+		let output = "mon_1".parse().expect("monitor id");
+		let mut damage = FrameDamage::default();
+		let size = (640, 480);
+		assert_eq!(damage.begin_frame(output, 1, size, false), Region::Full);
+		assert_eq!(
+			damage.begin_frame(output, 2, size, false),
+			Region::Full,
+			"a new framebuffer holds nothing"
+		);
+		let (left, right) = (
+			IRect::from_xywh(0, 0, 16, 16),
+			IRect::from_xywh(100, 0, 16, 16),
+		);
+		damage.damage_rect(output, left);
+		assert_eq!(
+			damage.begin_frame(output, 1, size, false),
+			Region::Rect(left)
+		);
+		damage.damage_rect(output, right);
+		assert_eq!(
+			damage.begin_frame(output, 2, size, false),
+			Region::Rect(IRect::from_ltrb(0, 0, 116, 16)),
+			"framebuffer 2 missed both moves"
+		);
+		assert_eq!(
+			damage.begin_frame(output, 2, size, false),
+			Region::Empty,
+			"nothing changed since framebuffer 2 was drawn"
+		);
+		damage.damage_all();
+		assert_eq!(damage.begin_frame(output, 2, size, false), Region::Full);
+		assert_eq!(
+			damage.begin_frame(output, 1, size, false),
+			Region::Full,
+			"framebuffer 1 missed a full frame"
+		);
+	}
+
+	#[test]
+	fn unknown_or_old_buffers_redraw_everything() {
+		let mut output = OutputDamage::default();
+		let size = (640, 480);
+		assert_eq!(output.begin_frame(0, size, Some(None)), Region::Full);
+		output.pending = Region::Rect(IRect::from_xywh(0, 0, 8, 8));
+		assert_eq!(
+			output.begin_frame(0, size, Some(Some(1))),
+			Region::Rect(IRect::from_xywh(0, 0, 8, 8))
+		);
+		assert_eq!(
+			output.begin_frame(0, size, Some(Some(MAX_AGE as u32 + 2))),
+			Region::Full
+		);
+		assert_eq!(
+			output.begin_frame(0, (800, 600), Some(Some(1))),
+			Region::Full
+		);
+	}
+}
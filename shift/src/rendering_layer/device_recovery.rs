@@ -0,0 +1,166 @@
+//! Recovery from the DRM device disappearing (eGPU unplug, driver reset).
+//!
+//! A failed commit or poll only counts as device loss when the device is gone (see
+//! [`RenderBackend::is_removed`]) or keeps failing; anything else is retried on the next frame.
+//! On loss, the renderer drops every GPU resource and keeps reopening a device with backoff.
+//! Whatever device the backend picks next (the same one once it returns, or another GPU)
+//! becomes the new render target. Commands that arrive in the meantime are
+//! answered without touching the GPU.
+//!
+//! Once the device has stayed away through the backoff, each further failed attempt also
+//! tries software rendering on any card that still shows a display (see [`SoftwareDisplay`]).
+//! A renderer that falls back stays in software until Shift restarts.
+
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use crate::comms::{
+	render2server::{RenderEvt, RenderEvtTx},
+	server2render::{RenderCmd, RenderCmdRx},
+};
+
+use super::{
+	RenderingLayer,
+	backend::RenderBackend,
+	color_filter::{ColorFilters, DisplayAdjustment},
+	software::SoftwareDisplay,
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// Failures in a row after which a device that is still there is given up on anyway.
+const MAX_CONSECUTIVE_FAILURES: u32 = 8;
+
+/// Counts backend failures in a row to tell transient ones (a rejected atomic commit, a busy
+/// CRTC) from a device that is gone.
+#[derive(Debug, Default)]
+pub(super) struct BackendFailures {
+	consecutive: u32,
+}
+
+impl BackendFailures {
+	pub(super) fn succeeded(&mut self) {
+		self.consecutive = 0;
+	}
+
+	/// Records a failure; returns whether the device counts as lost. `removed` is whether the
+	/// backend reports the device gone.
+	pub(super) fn failed(&mut self, removed: bool) -> bool {
+		self.consecutive += 1;
+		removed || self.consecutive >= MAX_CONSECUTIVE_FAILURES
+	}
+}
+
+/// What the renderer continues on after losing its device.
+pub(super) enum Recovery<B: RenderBackend> {
+	/// A reopened device.
+	Device(RenderingLayer<B>),
+	/// Software rendering, for good.
+	Software(SoftwareDisplay),
+}
+
+impl<B: RenderBackend> RenderingLayer<B> {
+	/// Releases GPU resources in an order that is safe when the device is already gone: imported
+	/// textures first, then Skia (abandoned, so it issues no further GL calls), then the backend.
+	pub(super) fn teardown(mut self) {
+		self.slots.clear();
+		self.session_snapshots.clear();
+		self.cancel_all_fences();
+		self.skia.abandon();
+	}
+
+	/// Waits until a DRM device can be opened again, or software rendering can take over.
+	/// Returns `None` if the server asked the renderer to shut down in the meantime.
+	/// Color filter changes are still applied to `color_filters`, which survive the device.
+	pub(super) async fn recover_device(
+		command_rx: &mut RenderCmdRx,
+		event_tx: RenderEvtTx,
+		color_filters: &mut ColorFilters,
+	) -> Option<Recovery<B>> {
+		let mut backoff = INITIAL_BACKOFF;
+		loop {
+			tokio::select! {
+				cmd = command_rx.recv() => {
+					match cmd {
+						None | Some(RenderCmd::Shutdown) => return None,
+						Some(RenderCmd::SwapBuffers { monitor_id, buffer, session_id, frame_id, .. }) => {
+							let _ = event_tx
+								.send(RenderEvt::BufferRequestRejected {
+									session_id,
+									monitor_id,
+									buffer,
+									reason: "device_lost".into(),
+									frame_id,
+								})
+								.await;
+						}
+						Some(RenderCmd::SetColorFilter { monitor_id, mode }) => {
+							color_filters.set(monitor_id, mode);
+						}
+						Some(RenderCmd::SetDisplayAdjustment { monitor_id, brightness, contrast, gamma }) => {
+							color_filters.set_adjustment(
+								monitor_id,
+								DisplayAdjustment::new(brightness, contrast, gamma),
+							);
+						}
+						// Links, session removals and active-session updates refer to GPU state that
+						// no longer exists; the server replays what it needs after `DeviceRestored`.
+						Some(cmd) => debug!(?cmd, "dropping render command while device is lost"),
+					}
+				}
+				_ = tokio::time::sleep(backoff) => {
+					match Self::open_device() {
+						Ok((backend, skia)) => {
+							info!("DRM device available again, resuming rendering");
+							return Some(Recovery::Device(Self::from_device(backend, skia, None, event_tx)));
+						}
+						Err(e) if backoff == MAX_BACKOFF => match SoftwareDisplay::open() {
+							Ok(display) => {
+								warn!("DRM device still unavailable ({e}), falling back to software rendering");
+								return Some(Recovery::Software(display));
+							}
+							Err(software_error) => {
+								warn!(retry_in = ?backoff, "DRM device still unavailable: {e}; software rendering: {software_error}");
+							}
+						},
+						Err(e) => {
+							warn!(retry_in = ?backoff, "DRM device still unavailable: {e}");
+							backoff = (backoff * 2).min(MAX_BACKOFF);
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn removed_device_is_lost_right_away() {
+		let mut failures = BackendFailures::default();
+		assert!(failures.failed(true));
+	}
+
+	#[test]
+	fn transient_failures_are_retried_until_they_persist() {
+		let mut failures = BackendFailures::default();
+		for _ in 1..MAX_CONSECUTIVE_FAILURES {
+			assert!(!failures.failed(false));
+		}
+		assert!(failures.failed(false));
+	}
+
+	#[test]
+	fn success_resets_the_count() {
+		let mut failures = BackendFailures::default();
+		for _ in 1..MAX_CONSECUTIVE_FAILURES {
+			failures.failed(false);
+		}
+		failures.succeeded();
+		assert!(!failures.failed(false));
+	}
+}
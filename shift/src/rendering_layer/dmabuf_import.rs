@@ -2,25 +2,83 @@
 
 use std::{
 	ffi::c_void,
-	os::fd::{IntoRawFd, OwnedFd},
+	io,
+	os::fd::{AsFd, AsRawFd, OwnedFd},
 };
 
 use easydrm::gl;
-use nix::unistd::close;
-use skia_safe::{Image, gpu};
+use skia_safe::{AlphaType, ColorType, Image, gpu};
+use tab_protocol::AlphaMode;
 use thiserror::Error;
 
-use crate::rendering_layer::egl;
+use crate::rendering_layer::{
+	buffer_hash::read_mapped,
+	egl,
+	pixel_formats::{self, PixelFormat},
+	skia_backend::SkiaContext,
+};
+
+/// `GL_TEXTURE_EXTERNAL_OES`; YUV images can only be sampled through it, with the driver
+/// converting to RGB.
+const TEXTURE_EXTERNAL_OES: gl::types::GLenum = 0x8D65;
+
+/// `DRM_FORMAT_MOD_LINEAR`.
+const MOD_LINEAR: u64 = 0;
+
+/// Attribute names for each plane's modifier, split into its low and high 32 bits.
+const PLANE_MODIFIER_ATTRIBS: [[i32; 2]; 3] = [
+	[
+		egl::DMA_BUF_PLANE0_MODIFIER_LO_EXT as i32,
+		egl::DMA_BUF_PLANE0_MODIFIER_HI_EXT as i32,
+	],
+	[
+		egl::DMA_BUF_PLANE1_MODIFIER_LO_EXT as i32,
+		egl::DMA_BUF_PLANE1_MODIFIER_HI_EXT as i32,
+	],
+	[
+		egl::DMA_BUF_PLANE2_MODIFIER_LO_EXT as i32,
+		egl::DMA_BUF_PLANE2_MODIFIER_HI_EXT as i32,
+	],
+];
+
+/// Attribute names for each plane: fd, offset and pitch.
+const PLANE_ATTRIBS: [[i32; 3]; 3] = [
+	[
+		egl::DMA_BUF_PLANE0_FD_EXT as i32,
+		egl::DMA_BUF_PLANE0_OFFSET_EXT as i32,
+		egl::DMA_BUF_PLANE0_PITCH_EXT as i32,
+	],
+	[
+		egl::DMA_BUF_PLANE1_FD_EXT as i32,
+		egl::DMA_BUF_PLANE1_OFFSET_EXT as i32,
+		egl::DMA_BUF_PLANE1_PITCH_EXT as i32,
+	],
+	[
+		egl::DMA_BUF_PLANE2_FD_EXT as i32,
+		egl::DMA_BUF_PLANE2_OFFSET_EXT as i32,
+		egl::DMA_BUF_PLANE2_PITCH_EXT as i32,
+	],
+];
+
+/// One plane of a client-provided dmabuf.
+#[derive(Debug)]
+pub struct ImportPlane {
+	pub fd: OwnedFd,
+	pub offset: i32,
+	pub stride: i32,
+}
 
 /// Metadata required to import a client-provided dmabuf as a GL texture.
 #[derive(Debug)]
 pub struct ImportParams {
 	pub width: i32,
 	pub height: i32,
-	pub stride: i32,
-	pub offset: i32,
 	pub fourcc: i32,
-	pub fd: OwnedFd,
+	/// Explicit DRM format modifier shared by all planes; `None` lets the driver assume the
+	/// layout it allocates by default.
+	pub modifier: Option<u64>,
+	/// In plane order; several planes may share one fd.
+	pub planes: Vec<ImportPlane>,
 }
 
 #[derive(Debug, Error)]
@@ -31,12 +89,31 @@ pub enum DmaBufImportError {
 	MissingDisplay,
 	#[error("no current EGL context")]
 	MissingContext,
+	#[error("cannot import a dmabuf with {0} planes")]
+	UnsupportedPlaneCount(usize),
+	#[error("unsupported pixel format {}", pixel_formats::name(*.0))]
+	UnsupportedFormat(u32),
 	#[error("eglCreateImageKHR failed (error={0:#X})")]
 	ImageCreationFailed(i32),
 	#[error("failed to create GL texture")]
 	TextureAllocationFailed,
 	#[error("glEGLImageTargetTexture2DOES failed (error={0:#X})")]
 	ImageBindFailed(u32),
+	#[error("failed to map the buffer for copying: {0}")]
+	MapFailed(io::Error),
+	#[error("buffer is smaller than its layout")]
+	BufferTooSmall,
+}
+
+/// A linear buffer EGL couldn't import, e.g. one allocated on another GPU. Its pixels are
+/// copied into a plain texture through a CPU mapping each time it is latched.
+struct CpuCopy {
+	fd: OwnedFd,
+	offset: usize,
+	stride: usize,
+	swap_red_blue: bool,
+	/// Tightly packed RGBA rows, reused between copies.
+	staging: Vec<u8>,
 }
 
 /// RAII wrapper owning the imported GL texture + EGL image.
@@ -46,9 +123,13 @@ pub struct DmaBufTexture {
 	display: egl::types::EGLDisplay,
 	image: egl::types::EGLImageKHR,
 	texture_id: gl::types::GLuint,
+	target: gl::types::GLenum,
 	pub width: i32,
 	pub height: i32,
 	pub fourcc: i32,
+	format: &'static PixelFormat,
+	/// Set for textures copied from the buffer rather than imported; `image` is then null.
+	copy: Option<CpuCopy>,
 }
 
 impl DmaBufTexture {
@@ -58,6 +139,8 @@ impl DmaBufTexture {
 		proc_resolver: &dyn Fn(&str) -> *const c_void,
 		params: ImportParams,
 	) -> Result<Self, DmaBufImportError> {
+		let format = pixel_formats::lookup(params.fourcc as u32)
+			.ok_or(DmaBufImportError::UnsupportedFormat(params.fourcc as u32))?;
 		let resolver = |name: &'static str| (proc_resolver)(name);
 		let egl = egl::Egl::load_with(|name| resolver(name));
 		if !(egl.CreateImageKHR.is_loaded() && egl.DestroyImageKHR.is_loaded()) {
@@ -72,22 +155,55 @@ impl DmaBufTexture {
 		if context.is_null() {
 			return Err(DmaBufImportError::MissingContext);
 		}
-		let raw_fd = params.fd.into_raw_fd();
-		let mut attrs = [
+		if params.planes.is_empty() || params.planes.len() > PLANE_ATTRIBS.len() {
+			return Err(DmaBufImportError::UnsupportedPlaneCount(
+				params.planes.len(),
+			));
+		}
+		let yuv = format.yuv;
+		let mut attrs = vec![
 			egl::LINUX_DRM_FOURCC_EXT as i32,
 			params.fourcc,
-			egl::DMA_BUF_PLANE0_FD_EXT as i32,
-			raw_fd,
-			egl::DMA_BUF_PLANE0_OFFSET_EXT as i32,
-			params.offset,
-			egl::DMA_BUF_PLANE0_PITCH_EXT as i32,
-			params.stride,
 			egl::WIDTH as i32,
 			params.width,
 			egl::HEIGHT as i32,
 			params.height,
-			egl::NONE as i32,
 		];
+		for (idx, (plane, [fd, offset, pitch])) in params.planes.iter().zip(PLANE_ATTRIBS).enumerate() {
+			attrs.extend([
+				fd,
+				plane.fd.as_raw_fd(),
+				offset,
+				plane.offset,
+				pitch,
+				plane.stride,
+			]);
+			if let Some(modifier) = params.modifier {
+				let [lo, hi] = PLANE_MODIFIER_ATTRIBS[idx];
+				attrs.extend([
+					lo,
+					modifier as u32 as i32,
+					hi,
+					(modifier >> 32) as u32 as i32,
+				]);
+			}
+		}
+		if yuv {
+			// Decoders don't say; HD and larger content is BT.709, SD is BT.601, both limited
+			// range.
+			let color_space = if params.height >= 720 {
+				egl::ITU_REC709_EXT
+			} else {
+				egl::ITU_REC601_EXT
+			};
+			attrs.extend([
+				egl::YUV_COLOR_SPACE_HINT_EXT as i32,
+				color_space as i32,
+				egl::SAMPLE_RANGE_HINT_EXT as i32,
+				egl::YUV_NARROW_RANGE_EXT as i32,
+			]);
+		}
+		attrs.push(egl::NONE as i32);
 
 		let image = unsafe {
 			egl.CreateImageKHR(
@@ -99,13 +215,19 @@ impl DmaBufTexture {
 			)
 		};
 
-		let _ = close(raw_fd);
+		// The image holds its own references to the buffers.
+		drop(params.planes);
 
 		if image.is_null() {
 			let egl_error = unsafe { egl.GetError() };
 			return Err(DmaBufImportError::ImageCreationFailed(egl_error));
 		}
 
+		let target = if yuv {
+			TEXTURE_EXTERNAL_OES
+		} else {
+			gl::TEXTURE_2D
+		};
 		let mut texture = 0;
 		unsafe {
 			gl.GenTextures(1, &mut texture);
@@ -118,28 +240,28 @@ impl DmaBufTexture {
 		}
 
 		unsafe {
-			gl.BindTexture(gl::TEXTURE_2D, texture);
+			gl.BindTexture(target, texture);
 			gl.TexParameteri(
-				gl::TEXTURE_2D,
+				target,
 				gl::TEXTURE_MIN_FILTER,
 				gl::LINEAR.try_into().unwrap(),
 			);
 			gl.TexParameteri(
-				gl::TEXTURE_2D,
+				target,
 				gl::TEXTURE_MAG_FILTER,
 				gl::LINEAR.try_into().unwrap(),
 			);
 			gl.TexParameteri(
-				gl::TEXTURE_2D,
+				target,
 				gl::TEXTURE_WRAP_S,
 				gl::CLAMP_TO_EDGE.try_into().unwrap(),
 			);
 			gl.TexParameteri(
-				gl::TEXTURE_2D,
+				target,
 				gl::TEXTURE_WRAP_T,
 				gl::CLAMP_TO_EDGE.try_into().unwrap(),
 			);
-			gl.EGLImageTargetTexture2DOES(gl::TEXTURE_2D, image.cast());
+			gl.EGLImageTargetTexture2DOES(target, image.cast());
 		}
 
 		let gl_error = unsafe { gl.GetError() };
@@ -156,16 +278,184 @@ impl DmaBufTexture {
 			display,
 			image,
 			texture_id: texture,
+			target,
 			width: params.width,
 			height: params.height,
 			fourcc: params.fourcc,
+			format,
+			copy: None,
 		})
 	}
+
+	/// Imports like [`Self::import`], and when that fails for a single-plane linear 8-bit RGB
+	/// buffer, uploads a copy of it instead; [`Self::refresh`] copies it again.
+	pub fn import_or_copy(
+		gl: &gl::Gles2,
+		proc_resolver: &dyn Fn(&str) -> *const c_void,
+		params: ImportParams,
+	) -> Result<Self, DmaBufImportError> {
+		let (width, height, fourcc) = (params.width, params.height, params.fourcc);
+		let copy = copy_layout(&params).and_then(|swap_red_blue| {
+			let plane = params.planes.first()?;
+			Some(CpuCopy {
+				fd: plane.fd.try_clone().ok()?,
+				offset: plane.offset as usize,
+				stride: plane.stride as usize,
+				swap_red_blue,
+				staging: Vec::new(),
+			})
+		});
+		match Self::import(gl, proc_resolver, params) {
+			Ok(texture) => Ok(texture),
+			Err(e) => {
+				let Some(copy) = copy else {
+					return Err(e);
+				};
+				tracing::info!("cannot import the buffer ({e}), copying it instead");
+				Self::copy(gl, proc_resolver, width, height, fourcc, copy)
+			}
+		}
+	}
+
+	fn copy(
+		gl: &gl::Gles2,
+		proc_resolver: &dyn Fn(&str) -> *const c_void,
+		width: i32,
+		height: i32,
+		fourcc: i32,
+		copy: CpuCopy,
+	) -> Result<Self, DmaBufImportError> {
+		let format = pixel_formats::lookup(fourcc as u32)
+			.ok_or(DmaBufImportError::UnsupportedFormat(fourcc as u32))?;
+		let egl = egl::Egl::load_with(|name| proc_resolver(name));
+		let display = if egl.GetCurrentDisplay.is_loaded() {
+			unsafe { egl.GetCurrentDisplay() }
+		} else {
+			std::ptr::null()
+		};
+		let mut texture = 0;
+		unsafe {
+			gl.GenTextures(1, &mut texture);
+		}
+		if texture == 0 {
+			return Err(DmaBufImportError::TextureAllocationFailed);
+		}
+		unsafe {
+			gl.BindTexture(gl::TEXTURE_2D, texture);
+			gl.TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_MIN_FILTER,
+				gl::LINEAR.try_into().unwrap(),
+			);
+			gl.TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_MAG_FILTER,
+				gl::LINEAR.try_into().unwrap(),
+			);
+			gl.TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_WRAP_S,
+				gl::CLAMP_TO_EDGE.try_into().unwrap(),
+			);
+			gl.TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_WRAP_T,
+				gl::CLAMP_TO_EDGE.try_into().unwrap(),
+			);
+			gl.TexImage2D(
+				gl::TEXTURE_2D,
+				0,
+				gl::RGBA8 as i32,
+				width,
+				height,
+				0,
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+				std::ptr::null(),
+			);
+		}
+		let gl_error = unsafe { gl.GetError() };
+		if gl_error != gl::NO_ERROR {
+			unsafe {
+				gl.DeleteTextures(1, &texture);
+			}
+			return Err(DmaBufImportError::TextureAllocationFailed);
+		}
+		let mut texture = Self {
+			gl: gl.clone(),
+			egl,
+			display,
+			image: std::ptr::null(),
+			texture_id: texture,
+			target: gl::TEXTURE_2D,
+			width,
+			height,
+			fourcc,
+			format,
+			copy: Some(copy),
+		};
+		texture.refresh()?;
+		Ok(texture)
+	}
+
+	/// Copies the buffer's current contents into a copied texture; imported textures already
+	/// show them. Expects a context sharing the texture current.
+	pub fn refresh(&mut self) -> Result<(), DmaBufImportError> {
+		let Some(copy) = &mut self.copy else {
+			return Ok(());
+		};
+		let (width, height) = (self.width as usize, self.height as usize);
+		let row = width * 4;
+		let needed = copy.offset + copy.stride * (height - 1) + row;
+		let (offset, stride, swap_red_blue) = (copy.offset, copy.stride, copy.swap_red_blue);
+		let staging = &mut copy.staging;
+		staging.resize(row * height, 0);
+		let copied = read_mapped(copy.fd.as_fd(), |bytes| {
+			if bytes.len() < needed {
+				return false;
+			}
+			for (y, dst) in staging.chunks_exact_mut(row).enumerate() {
+				let start = offset + y * stride;
+				dst.copy_from_slice(&bytes[start..start + row]);
+				if swap_red_blue {
+					for pixel in dst.chunks_exact_mut(4) {
+						pixel.swap(0, 2);
+					}
+				}
+			}
+			true
+		})
+		.map_err(DmaBufImportError::MapFailed)?;
+		if copied != Some(true) {
+			return Err(DmaBufImportError::BufferTooSmall);
+		}
+		unsafe {
+			let mut bound = 0;
+			self.gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut bound);
+			self.gl.BindTexture(gl::TEXTURE_2D, self.texture_id);
+			self.gl.TexSubImage2D(
+				gl::TEXTURE_2D,
+				0,
+				0,
+				0,
+				self.width,
+				self.height,
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+				copy.staging.as_ptr().cast(),
+			);
+			self
+				.gl
+				.BindTexture(gl::TEXTURE_2D, bound as gl::types::GLuint);
+		}
+		Ok(())
+	}
+
 	fn skia_tex_info(&self) -> gpu::gl::TextureInfo {
 		gpu::gl::TextureInfo {
-			target: gl::TEXTURE_2D as gpu::gl::Enum,
+			target: self.target as gpu::gl::Enum,
 			id: self.texture_id as gpu::gl::Enum,
-			format: gpu::gl::Format::RGBA8.into(),
+			format: self.format.gl_format.into(),
 			protected: gpu::Protected::No,
 		}
 	}
@@ -184,6 +474,7 @@ impl DmaBufTexture {
 			backend_texture,
 			source: self,
 			cached_image: None,
+			alpha_type: AlphaType::Opaque,
 		})
 	}
 }
@@ -199,11 +490,34 @@ impl Drop for DmaBufTexture {
 	}
 }
 
+/// Whether `params` can be copied when importing fails, and if so whether red and blue swap:
+/// copies are uploaded as R, G, B, A, and only 8-bit RGB layouts are copied.
+fn copy_layout(params: &ImportParams) -> Option<bool> {
+	let swap_red_blue = match pixel_formats::lookup(params.fourcc as u32)?.memory? {
+		ColorType::BGRA8888 => true,
+		ColorType::RGBA8888 => false,
+		_ => return None,
+	};
+	let [plane] = params.planes.as_slice() else {
+		return None;
+	};
+	let linear = params
+		.modifier
+		.is_none_or(|modifier| modifier == MOD_LINEAR);
+	(linear
+		&& params.width > 0
+		&& params.height > 0
+		&& plane.offset >= 0
+		&& plane.stride >= params.width * 4)
+		.then_some(swap_red_blue)
+}
+
 /// Helper struct that keeps the GL/EGL resources alive for as long as Skia needs them.
 pub struct SkiaDmaBufTexture {
 	pub backend_texture: gpu::BackendTexture,
 	source: DmaBufTexture,
 	cached_image: Option<Image>,
+	alpha_type: AlphaType,
 }
 
 impl SkiaDmaBufTexture {
@@ -211,15 +525,28 @@ impl SkiaDmaBufTexture {
 		&self.backend_texture
 	}
 
-	pub fn image<'a>(&'a mut self, gr: &mut gpu::DirectContext) -> Option<&'a Image> {
+	/// Reads the buffer's alpha as its link declared; it is ignored otherwise.
+	pub(super) fn with_alpha_mode(mut self, mode: Option<AlphaMode>) -> Self {
+		self.alpha_type = self.source.format.alpha_type(mode);
+		self
+	}
+
+	pub(super) fn alpha_type(&self) -> AlphaType {
+		self.alpha_type
+	}
+
+	/// Whether an overlay plane, which blends by the buffer's alpha as if premultiplied, shows
+	/// it the way composition does.
+	pub(super) fn blends_on_planes(&self) -> bool {
+		self.alpha_type == AlphaType::Premul || !self.source.format.alpha
+	}
+
+	pub(super) fn image<'a>(&'a mut self, skia: &mut SkiaContext) -> Option<&'a Image> {
 		if self.cached_image.is_none() {
-			self.cached_image = Image::from_texture(
-				gr,
+			self.cached_image = skia.texture_image(
 				&self.backend_texture,
-				gpu::SurfaceOrigin::TopLeft,
-				skia_safe::ColorType::RGBA8888,
-				skia_safe::AlphaType::Opaque,
-				None,
+				self.source.format.color_type,
+				self.alpha_type,
 			);
 		}
 		self.cached_image.as_ref()
@@ -247,4 +574,14 @@ impl SkiaDmaBufTexture {
 	pub fn gl_texture_id(&self) -> gl::types::GLuint {
 		self.source.texture_id
 	}
+
+	/// Whether the texture is a copy of the buffer, see [`DmaBufTexture::import_or_copy`].
+	pub fn is_copy(&self) -> bool {
+		self.source.copy.is_some()
+	}
+
+	/// See [`DmaBufTexture::refresh`].
+	pub fn refresh(&mut self) -> Result<(), DmaBufImportError> {
+		self.source.refresh()
+	}
 }
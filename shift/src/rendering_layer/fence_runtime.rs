@@ -1,8 +1,12 @@
 use std::os::fd::{AsFd, OwnedFd};
+use std::time::{Duration, Instant};
 
-use super::{FenceEvent, FenceWaitMode, RenderEvt, RenderingLayer, SlotKey};
+use super::backend::RenderBackend;
+use super::{FenceEvent, FenceTaskHandle, FenceWaitMode, RenderEvt, RenderingLayer, SlotKey};
 
-impl RenderingLayer {
+const DEFAULT_FENCE_DRAIN: Duration = Duration::from_millis(100);
+
+impl<B: RenderBackend> RenderingLayer<B> {
 	#[tracing::instrument(skip_all)]
 	pub(super) async fn emit_event(&self, event: RenderEvt) {
 		if let Err(e) = self.event_tx.send(event).await {
@@ -16,6 +20,44 @@ impl RenderingLayer {
 		}
 	}
 
+	/// Stops waiting on acquire fences at renderer shutdown. Fences that signal within
+	/// `SHIFT_FENCE_DRAIN_MS` (100 by default) still complete; the others are cancelled and
+	/// logged with their slot.
+	pub(super) async fn shutdown_fences(&mut self) {
+		let drain = std::env::var("SHIFT_FENCE_DRAIN_MS")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.map(Duration::from_millis)
+			.unwrap_or(DEFAULT_FENCE_DRAIN);
+		let in_flight = self.fence_scheduler.in_flight();
+		let report = self
+			.fence_scheduler
+			.shutdown(tokio::time::Instant::now() + drain)
+			.await;
+		tracing::debug!(
+			in_flight,
+			drained = report.drained,
+			"fence scheduler shut down"
+		);
+		self.report_unsignaled_fences(report.unsignaled);
+	}
+
+	/// Cancels every acquire fence wait without draining, e.g. when the device is lost.
+	pub(super) fn cancel_all_fences(&mut self) {
+		let unsignaled = self.fence_scheduler.cancel_all();
+		self.report_unsignaled_fences(unsignaled);
+	}
+
+	fn report_unsignaled_fences(&mut self, unsignaled: Vec<FenceTaskHandle>) {
+		for handle in unsignaled {
+			match self.fence_tasks.iter().find(|(_, task)| **task == handle) {
+				Some((key, _)) => tracing::warn!(?key, "acquire fence never signaled"),
+				None => tracing::warn!(?handle, "leaked fence wait without a slot"),
+			}
+		}
+		self.fence_tasks.clear();
+	}
+
 	pub(super) fn spawn_acquire_fence_waiter(&mut self, key: SlotKey, fence_fd: OwnedFd) {
 		if let Some(existing) = self.fence_tasks.get(&key).copied() {
 			if let Ok(cloned_fd) = fence_fd.as_fd().try_clone_to_owned()
@@ -43,12 +85,60 @@ impl RenderingLayer {
 		match event {
 			FenceEvent::Signaled { key } => {
 				self.fence_tasks.remove(&key);
-				if let Some(previous) = self.ownership.apply_acquire_fence_signaled(key) {
-					self
-						.ownership
-						.queue_buffer_release(key.monitor_id, key.session_id, previous);
+				if self.present_queue.fence_signaled(key, Instant::now()) {
+					self.show_pending(key).await;
 				}
 			}
 		}
 	}
+
+	/// Shows queued presents whose fence signaled and whose target time passed.
+	pub(super) async fn show_due_presents(&mut self, now: Instant) {
+		for key in self.present_queue.take_due(now) {
+			self.show_pending(key).await;
+		}
+	}
+
+	async fn show_pending(&mut self, key: SlotKey) {
+		let was_shown = self
+			.ownership
+			.current_slot_key_for_session(key.monitor_id, key.session_id)
+			== Some(key);
+		let Some(previous) = self.ownership.apply_acquire_fence_signaled(key) else {
+			return;
+		};
+		if !was_shown {
+			self
+				.frame_stats
+				.record_latch(key.monitor_id, key.session_id, Instant::now());
+			self.idle.damage(key.monitor_id);
+			self
+				.virtual_monitors
+				.presented(key.monitor_id, key.session_id);
+			self
+				.session_snapshots
+				.presented(key.monitor_id, key.session_id);
+			self.presentation_feedback.latched(key);
+			self.refresh_copied(key);
+		}
+		if let Some(previous) = previous {
+			self
+				.ownership
+				.queue_buffer_release(key.monitor_id, key.session_id, previous);
+		}
+		let frame_id = self.frame_ids.get(&key).copied();
+		tracing::debug!(
+			frame_id = frame_id.map(tracing::field::display),
+			?key,
+			"buffer latched"
+		);
+		self
+			.emit_event(RenderEvt::BufferLatched {
+				session_id: key.session_id,
+				monitor_id: key.monitor_id,
+				buffer: key.buffer.into(),
+				frame_id,
+			})
+			.await;
+	}
 }
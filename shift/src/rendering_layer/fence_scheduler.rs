@@ -1,12 +1,19 @@
 use std::{
 	collections::HashMap,
 	io::ErrorKind,
-	os::fd::{AsFd, AsRawFd, OwnedFd},
+	os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 
 use futures::future::{join_all, select_all};
-use tokio::{io::unix::AsyncFd, sync::mpsc, task::JoinHandle};
+use tokio::{io::unix::AsyncFd, sync::mpsc, task::JoinHandle, time::Instant};
+
+use super::sync_file;
+
+/// How long a fence may stay unsignaled before it is reported, unless `SHIFT_FENCE_STUCK_MS`
+/// says otherwise.
+const DEFAULT_STUCK_AFTER: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub(super) struct FenceTaskHandle(pub u64);
@@ -20,17 +27,51 @@ pub(super) enum FenceWaitMode {
 type TaskCallback = Box<dyn FnOnce() + Send + 'static>;
 type SharedCallback = Arc<Mutex<Option<TaskCallback>>>;
 
+/// Outstanding waits when the scheduler shut down.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct FenceShutdownReport {
+	/// Waits whose fences signaled while draining; their callbacks ran.
+	pub drained: usize,
+	/// Waits cancelled before their fences signaled; their callbacks never run.
+	pub unsignaled: Vec<FenceTaskHandle>,
+}
+
 struct CompletedTask {
 	handle: FenceTaskHandle,
+	batch: u64,
+	callback: SharedCallback,
+}
+
+struct Wait {
+	/// Task waiting on the fences, `None` until the next batch is spawned.
+	batch: Option<u64>,
 	callback: SharedCallback,
 }
 
+struct PendingWait {
+	handle: FenceTaskHandle,
+	fences: Vec<OwnedFd>,
+	mode: FenceWaitMode,
+}
+
+/// Runs callbacks once fences signal. Waits scheduled between two polls of
+/// [`recv_and_run`](Self::recv_and_run) share one task, so a client presenting on several
+/// monitors per frame costs one spawn rather than one per slot; each wait still completes on its
+/// own.
+///
+/// A fence still unsignaled after `SHIFT_FENCE_STUCK_MS` (1000 by default, `0` disables) is
+/// logged once with the driver, timeline and status of what it waits on, so reports of stuck
+/// buffers say which GPU work never finished.
 pub(super) struct FenceScheduler {
 	next_id: u64,
-	tasks: HashMap<FenceTaskHandle, JoinHandle<()>>,
-	callbacks: HashMap<FenceTaskHandle, SharedCallback>,
+	next_batch: u64,
+	pending: Vec<PendingWait>,
+	batches: HashMap<u64, JoinHandle<()>>,
+	waits: HashMap<FenceTaskHandle, Wait>,
 	tx: mpsc::UnboundedSender<CompletedTask>,
 	rx: mpsc::UnboundedReceiver<CompletedTask>,
+	/// Unsignaled time after which a fence is reported; `None` never reports.
+	stuck_after: Option<Duration>,
 }
 
 impl FenceScheduler {
@@ -38,13 +79,31 @@ impl FenceScheduler {
 		let (tx, rx) = mpsc::unbounded_channel();
 		Self {
 			next_id: 1,
-			tasks: HashMap::new(),
-			callbacks: HashMap::new(),
+			next_batch: 1,
+			pending: Vec::new(),
+			batches: HashMap::new(),
+			waits: HashMap::new(),
 			tx,
 			rx,
+			stuck_after: None,
 		}
 	}
 
+	pub fn from_env() -> Self {
+		let stuck_after = match std::env::var("SHIFT_FENCE_STUCK_MS") {
+			Ok(value) => value
+				.trim()
+				.parse::<u64>()
+				.ok()
+				.filter(|&ms| ms > 0)
+				.map(Duration::from_millis),
+			Err(_) => Some(DEFAULT_STUCK_AFTER),
+		};
+		let mut scheduler = Self::new();
+		scheduler.stuck_after = stuck_after;
+		scheduler
+	}
+
 	pub fn schedule(
 		&mut self,
 		fences: Vec<OwnedFd>,
@@ -53,10 +112,18 @@ impl FenceScheduler {
 	) -> FenceTaskHandle {
 		let handle = FenceTaskHandle(self.next_id);
 		self.next_id = self.next_id.saturating_add(1);
-		let callback = Arc::new(Mutex::new(Some(callback)));
-		let task = spawn_wait_task(handle, fences, mode, Arc::clone(&callback), self.tx.clone());
-		self.tasks.insert(handle, task);
-		self.callbacks.insert(handle, callback);
+		self.waits.insert(
+			handle,
+			Wait {
+				batch: None,
+				callback: Arc::new(Mutex::new(Some(callback))),
+			},
+		);
+		self.pending.push(PendingWait {
+			handle,
+			fences,
+			mode,
+		});
 		handle
 	}
 
@@ -66,30 +133,130 @@ impl FenceScheduler {
 		fences: Vec<OwnedFd>,
 		mode: FenceWaitMode,
 	) -> bool {
-		let Some(callback) = self.callbacks.get(&handle).cloned() else {
+		if !self.waits.contains_key(&handle) {
 			return false;
-		};
-		if let Some(task) = self.tasks.remove(&handle) {
-			task.abort();
 		}
-		let task = spawn_wait_task(handle, fences, mode, callback, self.tx.clone());
-		self.tasks.insert(handle, task);
+		self.detach(handle);
+		self.pending.push(PendingWait {
+			handle,
+			fences,
+			mode,
+		});
 		true
 	}
 
 	pub fn cancel(&mut self, handle: FenceTaskHandle) -> bool {
-		if let Some(task) = self.tasks.remove(&handle) {
+		self.detach(handle);
+		let Some(wait) = self.waits.remove(&handle) else {
+			return false;
+		};
+		if let Ok(mut guard) = wait.callback.lock() {
+			guard.take();
+		}
+		true
+	}
+
+	/// Stops waiting on the current fences of `handle`, aborting its batch once no other wait
+	/// needs it.
+	fn detach(&mut self, handle: FenceTaskHandle) {
+		self.pending.retain(|pending| pending.handle != handle);
+		let Some(batch) = self
+			.waits
+			.get_mut(&handle)
+			.and_then(|wait| wait.batch.take())
+		else {
+			return;
+		};
+		if !self.waits.values().any(|wait| wait.batch == Some(batch))
+			&& let Some(task) = self.batches.remove(&batch)
+		{
+			task.abort();
+		}
+	}
+
+	/// Spawns one task for every wait scheduled since the last call.
+	fn spawn_pending(&mut self) {
+		if self.pending.is_empty() {
+			return;
+		}
+		let batch = self.next_batch;
+		self.next_batch = self.next_batch.saturating_add(1);
+		let members = self
+			.pending
+			.drain(..)
+			.filter_map(|pending| {
+				let wait = self.waits.get_mut(&pending.handle)?;
+				wait.batch = Some(batch);
+				Some((pending, Arc::clone(&wait.callback)))
+			})
+			.collect();
+		let task = spawn_batch_task(batch, members, self.tx.clone(), self.stuck_after);
+		self.batches.insert(batch, task);
+	}
+
+	/// Number of waits whose callbacks haven't run yet.
+	pub fn in_flight(&self) -> usize {
+		self.waits.len()
+	}
+
+	/// Runs the callbacks of waits that complete before `deadline`, then cancels the rest.
+	pub async fn shutdown(&mut self, deadline: Instant) -> FenceShutdownReport {
+		let before = self.in_flight();
+		while self.in_flight() > 0 {
+			match tokio::time::timeout_at(deadline, self.recv_and_run()).await {
+				Ok(true) => {}
+				Ok(false) | Err(_) => break,
+			}
+		}
+		let drained = before - self.in_flight();
+		FenceShutdownReport {
+			drained,
+			unsignaled: self.cancel_all(),
+		}
+	}
+
+	/// Cancels every outstanding wait and returns their handles. No callback fires afterwards,
+	/// including those of waits that completed but weren't run yet.
+	pub fn cancel_all(&mut self) -> Vec<FenceTaskHandle> {
+		self.pending.clear();
+		for (_, task) in self.batches.drain() {
 			task.abort();
 		}
-		self.callbacks.remove(&handle).is_some()
+		let mut cancelled = Vec::with_capacity(self.waits.len());
+		for (handle, wait) in self.waits.drain() {
+			if let Ok(mut guard) = wait.callback.lock() {
+				guard.take();
+			}
+			cancelled.push(handle);
+		}
+		while self.rx.try_recv().is_ok() {}
+		cancelled.sort_by_key(|handle| handle.0);
+		cancelled
 	}
 
+	/// Spawns the waits scheduled since the last call, then runs the callback of the next wait
+	/// that completes. `false` once the completion channel closed.
 	pub async fn recv_and_run(&mut self) -> bool {
+		self.spawn_pending();
 		let Some(completed) = self.rx.recv().await else {
 			return false;
 		};
-		self.tasks.remove(&completed.handle);
-		self.callbacks.remove(&completed.handle);
+		// Completions of fences a wait was rescheduled away from are stale.
+		if self
+			.waits
+			.get(&completed.handle)
+			.is_none_or(|wait| wait.batch != Some(completed.batch))
+		{
+			return true;
+		}
+		self.waits.remove(&completed.handle);
+		if !self
+			.waits
+			.values()
+			.any(|wait| wait.batch == Some(completed.batch))
+		{
+			self.batches.remove(&completed.batch);
+		}
 		if let Ok(mut guard) = completed.callback.lock()
 			&& let Some(callback) = guard.take()
 		{
@@ -99,22 +266,49 @@ impl FenceScheduler {
 	}
 }
 
-fn spawn_wait_task(
-	handle: FenceTaskHandle,
-	fences: Vec<OwnedFd>,
-	mode: FenceWaitMode,
-	callback: SharedCallback,
+impl Drop for FenceScheduler {
+	fn drop(&mut self) {
+		self.cancel_all();
+	}
+}
+
+/// Waits on every member of a batch concurrently and reports each one as it completes.
+fn spawn_batch_task(
+	batch: u64,
+	members: Vec<(PendingWait, SharedCallback)>,
 	tx: mpsc::UnboundedSender<CompletedTask>,
+	stuck_after: Option<Duration>,
 ) -> JoinHandle<()> {
 	tokio::spawn(async move {
-		let wait_ok = wait_many_fences(fences, mode).await;
-		if wait_ok {
-			let _ = tx.send(CompletedTask { handle, callback });
+		let mut waiters = members
+			.into_iter()
+			.map(|(pending, callback)| {
+				Box::pin(async move {
+					let ok = wait_many_fences(pending.fences, pending.mode, stuck_after).await;
+					(pending.handle, callback, ok)
+				})
+			})
+			.collect::<Vec<_>>();
+		while !waiters.is_empty() {
+			let ((handle, callback, ok), _idx, rest) = select_all(waiters).await;
+			if ok {
+				let _ = tx.send(CompletedTask {
+					handle,
+					batch,
+					callback,
+				});
+			}
+			waiters = rest;
 		}
 	})
 }
 
-async fn wait_many_fences(fences: Vec<OwnedFd>, mode: FenceWaitMode) -> bool {
+#[tracing::instrument(skip_all, name = "fence_wait", fields(count = fences.len(), ?mode))]
+async fn wait_many_fences(
+	fences: Vec<OwnedFd>,
+	mode: FenceWaitMode,
+	stuck_after: Option<Duration>,
+) -> bool {
 	if fences.is_empty() {
 		return true;
 	}
@@ -123,19 +317,19 @@ async fn wait_many_fences(fences: Vec<OwnedFd>, mode: FenceWaitMode) -> bool {
 		FenceWaitMode::Any => {
 			let waiters = fences
 				.into_iter()
-				.map(|fd| Box::pin(wait_one_fence(fd)))
+				.map(|fd| Box::pin(wait_one_fence(fd, stuck_after)))
 				.collect::<Vec<_>>();
 			let (result, _idx, _rest) = select_all(waiters).await;
 			result
 		}
 		FenceWaitMode::All => {
-			let results = join_all(fences.into_iter().map(wait_one_fence)).await;
+			let results = join_all(fences.into_iter().map(|fd| wait_one_fence(fd, stuck_after))).await;
 			results.into_iter().all(|ok| ok)
 		}
 	}
 }
 
-async fn wait_one_fence(fd: OwnedFd) -> bool {
+async fn wait_one_fence(fd: OwnedFd, stuck_after: Option<Duration>) -> bool {
 	let raw_fd = fd.as_fd().as_raw_fd();
 	let afd = match AsyncFd::new(fd) {
 		Ok(afd) => {
@@ -147,7 +341,19 @@ async fn wait_one_fence(fd: OwnedFd) -> bool {
 			return false;
 		}
 	};
+	let Some(stuck_after) = stuck_after else {
+		return signaled(&afd).await;
+	};
+	match tokio::time::timeout(stuck_after, signaled(&afd)).await {
+		Ok(ok) => ok,
+		Err(_) => {
+			report_stuck(afd.get_ref().as_fd(), stuck_after);
+			signaled(&afd).await
+		}
+	}
+}
 
+async fn signaled(afd: &AsyncFd<OwnedFd>) -> bool {
 	loop {
 		let mut guard = match afd.readable().await {
 			Ok(guard) => guard,
@@ -179,3 +385,123 @@ async fn wait_one_fence(fd: OwnedFd) -> bool {
 		}
 	}
 }
+
+/// Logs what the fence `fd`, unsignaled for `waited`, waits on.
+fn report_stuck(fd: BorrowedFd<'_>, waited: Duration) {
+	let waited_ms = waited.as_millis() as u64;
+	match sync_file::info(fd) {
+		Ok(info) => {
+			let fences = info
+				.fences
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join(", ");
+			tracing::warn!(
+				waited_ms,
+				sync_file = info.name.as_str(),
+				status = %info.status,
+				fence_count = info.fence_count,
+				fences,
+				"fence still unsignaled"
+			);
+		}
+		Err(e) => tracing::warn!(
+			waited_ms,
+			"fence still unsignaled and can't be inspected: {e}"
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::Write,
+		os::unix::net::UnixStream,
+		sync::atomic::{AtomicUsize, Ordering},
+		time::Duration,
+	};
+
+	use super::*;
+
+	fn counting(counter: &Arc<AtomicUsize>) -> TaskCallback {
+		let counter = Arc::clone(counter);
+		Box::new(move || {
+			counter.fetch_add(1, Ordering::SeqCst);
+		})
+	}
+
+	#[test]
+	fn shutdown_drains_signaled_waits_and_cancels_the_rest() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			let (mut signaled_tx, signaled_rx) = UnixStream::pair().expect("socket pair");
+			let (_pending_tx, pending_rx) = UnixStream::pair().expect("socket pair");
+			let fired = Arc::new(AtomicUsize::new(0));
+			let mut scheduler = FenceScheduler::new();
+			scheduler.schedule(
+				vec![signaled_rx.into()],
+				FenceWaitMode::All,
+				counting(&fired),
+			);
+			let pending = scheduler.schedule(
+				vec![pending_rx.into()],
+				FenceWaitMode::All,
+				counting(&fired),
+			);
+			signaled_tx.write_all(&[1]).expect("signal");
+			let report = scheduler
+				.shutdown(Instant::now() + Duration::from_millis(200))
+				.await;
+			assert_eq!(
+				report,
+				FenceShutdownReport {
+					drained: 1,
+					unsignaled: vec![pending],
+				}
+			);
+			assert_eq!(scheduler.in_flight(), 0);
+			assert_eq!(fired.load(Ordering::SeqCst), 1);
+		});
+	}
+
+	#[test]
+	fn batched_waits_complete_and_cancel_independently() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			let (mut first_tx, first_rx) = UnixStream::pair().expect("socket pair");
+			let (mut second_tx, second_rx) = UnixStream::pair().expect("socket pair");
+			let first_fired = Arc::new(AtomicUsize::new(0));
+			let second_fired = Arc::new(AtomicUsize::new(0));
+			let mut scheduler = FenceScheduler::new();
+			scheduler.schedule(
+				vec![first_rx.into()],
+				FenceWaitMode::All,
+				counting(&first_fired),
+			);
+			let second = scheduler.schedule(
+				vec![second_rx.into()],
+				FenceWaitMode::All,
+				counting(&second_fired),
+			);
+			scheduler.spawn_pending();
+			assert_eq!(scheduler.batches.len(), 1, "one task for both waits");
+			assert!(scheduler.cancel(second));
+			assert_eq!(scheduler.batches.len(), 1, "first wait still needs it");
+			second_tx.write_all(&[1]).expect("signal");
+			first_tx.write_all(&[1]).expect("signal");
+			while scheduler.in_flight() > 0 {
+				assert!(scheduler.recv_and_run().await);
+			}
+			assert_eq!(first_fired.load(Ordering::SeqCst), 1);
+			assert_eq!(second_fired.load(Ordering::SeqCst), 0);
+			assert!(scheduler.batches.is_empty());
+		});
+	}
+}
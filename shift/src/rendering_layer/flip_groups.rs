@@ -0,0 +1,77 @@
+//! Monitors whose frames are committed together, for video walls.
+//!
+//! The outputs of a group only take a new frame once none of them still waits for a flip, so
+//! they are drawn in the same pass and presented by the same commit, flipping on the same
+//! vblank as closely as the hardware allows. Nothing shares a CRTC's timing with another, so
+//! panels whose clocks drift apart still drift by up to a refresh. VRR never holds the frame of
+//! a grouped output, which would let the group fall apart.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::monitor::MonitorId;
+
+#[derive(Debug, Default)]
+pub(super) struct FlipGroups {
+	/// Index of the group of each grouped logical monitor.
+	group_of: HashMap<MonitorId, usize>,
+}
+
+impl FlipGroups {
+	/// Replaces the groups; a monitor listed in several stays in the first.
+	pub fn set(&mut self, groups: Vec<Vec<MonitorId>>) {
+		self.group_of.clear();
+		for (index, members) in groups.into_iter().enumerate() {
+			for monitor_id in members {
+				self.group_of.entry(monitor_id).or_insert(index);
+			}
+		}
+	}
+
+	pub fn grouped(&self, monitor_id: MonitorId) -> bool {
+		self.group_of.contains_key(&monitor_id)
+	}
+
+	/// Whether `monitor_id` waits for a monitor of its group among `busy`, those whose last
+	/// frame hasn't flipped yet.
+	pub fn held(&self, monitor_id: MonitorId, busy: &HashSet<MonitorId>) -> bool {
+		let Some(group) = self.group_of.get(&monitor_id) else {
+			return false;
+		};
+		busy
+			.iter()
+			.any(|busy| self.group_of.get(busy) == Some(group))
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.group_of.remove(&monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn id(id: &str) -> MonitorId {
+		id.parse().expect("monitor id")
+	}
+
+	#[test]
+	fn a_group_waits_for_its_slowest_monitor() {
+		let mut groups = FlipGroups::default();
+		groups.set(vec![
+			vec![id("mon_1"), id("mon_2")],
+			vec![id("mon_3"), id("mon_4"), id("mon_1")],
+		]);
+		let busy = HashSet::from([id("mon_2")]);
+		assert!(groups.held(id("mon_1"), &busy));
+		assert!(!groups.held(id("mon_3"), &busy));
+		assert!(
+			!groups.held(id("mon_5"), &busy),
+			"ungrouped monitors never wait"
+		);
+		assert!(groups.grouped(id("mon_4")));
+
+		groups.remove_monitor(id("mon_2"));
+		assert!(!groups.held(id("mon_1"), &busy));
+	}
+}
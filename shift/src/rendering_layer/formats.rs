@@ -0,0 +1,262 @@
+//! Buffer formats reported to clients with each monitor.
+//!
+//! `formats` lists the fourccs EGL can import on the current device, so clients pick one Shift
+//! can compose instead of assuming XRGB8888. `scanout_formats` is the subset the monitor's
+//! primary plane can also scan out. When the driver can't be queried, both fall back to the
+//! formats every supported driver handles. `format_modifiers` lists the explicit modifiers
+//! (tiling, compression) EGL can import for each of them.
+//!
+//! The same plane information picks each monitor's own framebuffer format: 10 bits per channel
+//! where the primary plane scans it out, as allowed by `SHIFT_OUTPUT_DEPTH`.
+
+use std::{ffi::c_void, ptr};
+
+use drm::{
+	ClientCapability,
+	control::{Device as ControlDevice, connector, plane},
+};
+
+use tab_protocol::{FormatModifiers, FramebufferLinkPayload};
+
+use super::{egl, pixel_formats};
+use crate::drm_card::Card;
+
+const XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+const ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+pub(super) const XRGB2101010: u32 = u32::from_le_bytes(*b"XR30");
+pub(super) const FALLBACK_FORMATS: [u32; 2] = [XRGB8888, ARGB8888];
+/// `DRM_PLANE_TYPE_PRIMARY`.
+const PRIMARY_PLANE: u64 = 1;
+
+/// Fourccs `eglCreateImageKHR` accepts as dma-bufs on the current display, among those
+/// [`pixel_formats`] maps to a Skia color type.
+pub(super) fn query_importable(resolver: impl Fn(&str) -> *const c_void) -> Vec<u32> {
+	let egl = egl::Egl::load_with(|name| resolver(name));
+	if !egl.QueryDmaBufFormatsEXT.is_loaded() {
+		tracing::warn!(
+			"EGL_EXT_image_dma_buf_import_modifiers unavailable, reporting fallback formats"
+		);
+		return FALLBACK_FORMATS.to_vec();
+	}
+	let display = unsafe { egl.GetCurrentDisplay() };
+	if display.is_null() {
+		return FALLBACK_FORMATS.to_vec();
+	}
+	let mut count = 0;
+	if unsafe { egl.QueryDmaBufFormatsEXT(display, 0, ptr::null_mut(), &mut count) } == 0
+		|| count <= 0
+	{
+		return FALLBACK_FORMATS.to_vec();
+	}
+	let mut formats = vec![0i32; count as usize];
+	if unsafe { egl.QueryDmaBufFormatsEXT(display, count, formats.as_mut_ptr(), &mut count) } == 0 {
+		return FALLBACK_FORMATS.to_vec();
+	}
+	formats.truncate(count.max(0) as usize);
+	formats
+		.into_iter()
+		.map(|format| format as u32)
+		.filter(|&format| pixel_formats::supported(format))
+		.collect()
+}
+
+/// Explicit modifiers `eglCreateImageKHR` accepts for each of `formats`, skipping formats
+/// without any. Empty when the driver can't be queried, so clients stick to implicit layouts.
+pub(super) fn query_modifiers(
+	resolver: impl Fn(&str) -> *const c_void,
+	formats: &[u32],
+) -> Vec<FormatModifiers> {
+	let egl = egl::Egl::load_with(|name| resolver(name));
+	if !egl.QueryDmaBufModifiersEXT.is_loaded() {
+		return Vec::new();
+	}
+	let display = unsafe { egl.GetCurrentDisplay() };
+	if display.is_null() {
+		return Vec::new();
+	}
+	formats
+		.iter()
+		.filter_map(|&fourcc| {
+			let mut count = 0;
+			let queried = unsafe {
+				egl.QueryDmaBufModifiersEXT(
+					display,
+					fourcc as i32,
+					0,
+					ptr::null_mut(),
+					ptr::null_mut(),
+					&mut count,
+				)
+			};
+			if queried == 0 || count <= 0 {
+				return None;
+			}
+			let mut modifiers = vec![0u64; count as usize];
+			let queried = unsafe {
+				egl.QueryDmaBufModifiersEXT(
+					display,
+					fourcc as i32,
+					count,
+					modifiers.as_mut_ptr(),
+					ptr::null_mut(),
+					&mut count,
+				)
+			};
+			if queried == 0 {
+				return None;
+			}
+			modifiers.truncate(count.max(0) as usize);
+			Some(FormatModifiers { fourcc, modifiers })
+		})
+		.collect()
+}
+
+/// Whether buffers of `fourcc` laid out as `modifier` can be imported. Linear buffers always
+/// can; other explicit modifiers only when `supported` lists them.
+pub(super) fn modifier_supported(
+	supported: &[FormatModifiers],
+	fourcc: u32,
+	modifier: u64,
+) -> bool {
+	modifier == FramebufferLinkPayload::MOD_LINEAR
+		|| supported
+			.iter()
+			.any(|entry| entry.fourcc == fourcc && entry.modifiers.contains(&modifier))
+}
+
+/// Bits per channel of the monitors' own framebuffers, from `SHIFT_OUTPUT_DEPTH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(super) enum OutputDepth {
+	/// 10 bits where the primary plane is known to scan them out, 8 elsewhere.
+	#[default]
+	Auto,
+	Eight,
+	/// 10 bits unless the primary plane is known not to scan them out.
+	Ten,
+}
+
+impl OutputDepth {
+	/// `auto` (default), `8` or `10`.
+	pub fn from_env() -> Self {
+		match std::env::var("SHIFT_OUTPUT_DEPTH") {
+			Ok(v) => match v.trim() {
+				"auto" | "" => OutputDepth::Auto,
+				"8" => OutputDepth::Eight,
+				"10" => OutputDepth::Ten,
+				_ => {
+					tracing::warn!(value = v, "invalid SHIFT_OUTPUT_DEPTH, using auto");
+					OutputDepth::Auto
+				}
+			},
+			Err(_) => OutputDepth::Auto,
+		}
+	}
+}
+
+/// Framebuffer format for a monitor whose primary plane takes `scanout`, if known.
+pub(super) fn output_format(depth: OutputDepth, scanout: Option<&[u32]>) -> u32 {
+	let ten_bit = match (depth, scanout) {
+		(OutputDepth::Eight, _) => false,
+		(OutputDepth::Auto, None) => false,
+		(OutputDepth::Ten, None) => true,
+		(_, Some(scanout)) => scanout.contains(&XRGB2101010),
+	};
+	if ten_bit { XRGB2101010 } else { XRGB8888 }
+}
+
+/// Formats the primary plane of the CRTC driving `connector_id` can scan out.
+pub(super) fn read_scanout(connector_id: u32) -> Option<Vec<u32>> {
+	let handle: connector::Handle = drm::control::from_u32(connector_id)?;
+	Card::open_all().into_iter().find_map(|(_, card)| {
+		let crtc = card
+			.get_connector(handle, false)
+			.ok()?
+			.current_encoder()
+			.and_then(|encoder| card.get_encoder(encoder).ok())
+			.and_then(|encoder| encoder.crtc())?;
+		// Primary planes are only listed to clients that ask for universal planes.
+		card
+			.set_client_capability(ClientCapability::UniversalPlanes, true)
+			.ok()?;
+		let resources = card.resource_handles().ok()?;
+		card.plane_handles().ok()?.into_iter().find_map(|handle| {
+			let info = card.get_plane(handle).ok()?;
+			let drives_crtc = resources
+				.filter_crtcs(info.possible_crtcs())
+				.contains(&crtc);
+			(drives_crtc && is_primary(&card, handle)).then(|| info.formats().to_vec())
+		})
+	})
+}
+
+fn is_primary(card: &Card, handle: plane::Handle) -> bool {
+	let Ok(properties) = card.get_properties(handle) else {
+		return false;
+	};
+	let (ids, values) = properties.as_props_and_values();
+	ids.iter().zip(values).any(|(id, value)| {
+		card
+			.get_property(*id)
+			.is_ok_and(|info| info.name().to_bytes() == b"type" && *value == PRIMARY_PLANE)
+	})
+}
+
+/// Importable formats in EGL's order, and those of them `scanout` lists. Without plane
+/// information every importable format counts as scanout-capable.
+pub(super) fn monitor_formats(importable: &[u32], scanout: Option<&[u32]>) -> (Vec<u32>, Vec<u32>) {
+	let scanout_formats = match scanout {
+		Some(scanout) => importable
+			.iter()
+			.copied()
+			.filter(|format| scanout.contains(format))
+			.collect(),
+		None => importable.to_vec(),
+	};
+	(importable.to_vec(), scanout_formats)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scanout_formats_are_the_importable_ones_the_plane_takes() {
+		let nv12 = u32::from_le_bytes(*b"NV12");
+		let importable = [XRGB8888, nv12, ARGB8888];
+		let (formats, scanout) = monitor_formats(&importable, Some(&[ARGB8888, XRGB8888, 0x1234]));
+		assert_eq!(formats, importable);
+		assert_eq!(scanout, [XRGB8888, ARGB8888]);
+		let (_, scanout) = monitor_formats(&importable, None);
+		assert_eq!(scanout, importable);
+	}
+
+	#[test]
+	fn ten_bit_output_needs_a_plane_that_scans_it_out() {
+		let deep = [XRGB8888, XRGB2101010];
+		let shallow = [XRGB8888, ARGB8888];
+		assert_eq!(output_format(OutputDepth::Auto, Some(&deep)), XRGB2101010);
+		assert_eq!(output_format(OutputDepth::Auto, Some(&shallow)), XRGB8888);
+		assert_eq!(output_format(OutputDepth::Auto, None), XRGB8888);
+		assert_eq!(output_format(OutputDepth::Ten, None), XRGB2101010);
+		assert_eq!(output_format(OutputDepth::Ten, Some(&shallow)), XRGB8888);
+		assert_eq!(output_format(OutputDepth::Eight, Some(&deep)), XRGB8888);
+	}
+
+	#[test]
+	fn only_listed_modifiers_are_supported_besides_linear() {
+		const Y_TILED: u64 = 0x0100_0000_0000_0002;
+		const CCS: u64 = 0x0100_0000_0000_0004;
+		let supported = [FormatModifiers {
+			fourcc: XRGB8888,
+			modifiers: vec![Y_TILED],
+		}];
+		assert!(modifier_supported(&supported, XRGB8888, Y_TILED));
+		assert!(!modifier_supported(&supported, XRGB8888, CCS));
+		assert!(!modifier_supported(&supported, ARGB8888, Y_TILED));
+		assert!(modifier_supported(
+			&[],
+			ARGB8888,
+			FramebufferLinkPayload::MOD_LINEAR
+		));
+	}
+}
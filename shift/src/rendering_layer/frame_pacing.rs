@@ -0,0 +1,173 @@
+//! Per-monitor frame pacing driven by the sessions' `latency_mode` hints.
+//!
+//! A monitor follows the lowest-latency mode among the sessions shown on it:
+//! - `balanced` composes right after each page flip (render-ahead), the default;
+//! - `low` late-latches: after a flip the renderer waits until `SHIFT_LATE_LATCH_MARGIN_US`
+//!   (3000 by default) before the next vblank, so buffers presented meanwhile still make it;
+//! - `power_save` throttles the monitor to every other vblank.
+//!
+//! All monitors are composed in one pass, so a late-latching monitor delays the others' passes
+//! too; they still make their vblank as long as composing fits in the margin.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::LatencyMode;
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+const DEFAULT_LATCH_MARGIN: Duration = Duration::from_micros(3000);
+
+#[derive(Debug)]
+pub(super) struct FramePacing {
+	latch_margin: Duration,
+	sessions: HashMap<SessionId, LatencyMode>,
+	/// Last compose of each throttled output, keyed by connector-level monitor id.
+	last_drawn: HashMap<MonitorId, Instant>,
+	/// Shortest refresh interval among the late-latching monitors of the last pass.
+	latch_interval: Option<Duration>,
+}
+
+impl FramePacing {
+	pub fn from_env() -> Self {
+		let latch_margin = std::env::var("SHIFT_LATE_LATCH_MARGIN_US")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.map(Duration::from_micros)
+			.unwrap_or(DEFAULT_LATCH_MARGIN);
+		Self::new(latch_margin)
+	}
+
+	fn new(latch_margin: Duration) -> Self {
+		Self {
+			latch_margin,
+			sessions: HashMap::new(),
+			last_drawn: HashMap::new(),
+			latch_interval: None,
+		}
+	}
+
+	pub fn set(&mut self, session_id: SessionId, mode: LatencyMode) {
+		if mode == LatencyMode::default() {
+			self.sessions.remove(&session_id);
+		} else {
+			self.sessions.insert(session_id, mode);
+		}
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.sessions.remove(&session_id);
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.last_drawn.remove(&monitor_id);
+	}
+
+	/// Mode of a monitor showing `sessions`: the lowest-latency one they asked for.
+	pub fn monitor_mode(&self, sessions: impl IntoIterator<Item = SessionId>) -> LatencyMode {
+		sessions
+			.into_iter()
+			.map(|session_id| self.sessions.get(&session_id).copied().unwrap_or_default())
+			.min()
+			.unwrap_or_default()
+	}
+
+	/// Whether `output` skips this pass. Outputs in `power_save` are composed at most every
+	/// other vblank; the others are never skipped and forget their throttle state.
+	pub fn skip_output(
+		&mut self,
+		output: MonitorId,
+		mode: LatencyMode,
+		refresh_interval: Duration,
+		now: Instant,
+	) -> bool {
+		if mode != LatencyMode::PowerSave {
+			self.last_drawn.remove(&output);
+			return false;
+		}
+		// Half an interval of slack so jitter doesn't push the skip to a third vblank.
+		let next = self
+			.last_drawn
+			.get(&output)
+			.map(|last| *last + refresh_interval * 3 / 2);
+		if next.is_some_and(|next| now < next) {
+			return true;
+		}
+		self.last_drawn.insert(output, now);
+		false
+	}
+
+	/// Records the late-latching monitors composed in this pass.
+	pub fn set_latch_interval(&mut self, interval: Option<Duration>) {
+		self.latch_interval = interval;
+	}
+
+	/// How long to wait after a page flip before composing the next frame.
+	pub fn latch_delay(&self) -> Option<Duration> {
+		self
+			.latch_interval?
+			.checked_sub(self.latch_margin)
+			.filter(|delay| !delay.is_zero())
+	}
+}
+
+/// Duration of one refresh cycle at `refresh_hz`.
+pub(super) fn refresh_interval(refresh_hz: u32) -> Duration {
+	Duration::from_secs(1) / refresh_hz.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	#[test]
+	fn lowest_latency_session_decides() {
+		let mut pacing = FramePacing::new(DEFAULT_LATCH_MARGIN);
+		pacing.set(session(1), LatencyMode::PowerSave);
+		pacing.set(session(2), LatencyMode::Low);
+		assert_eq!(pacing.monitor_mode([session(1)]), LatencyMode::PowerSave);
+		assert_eq!(
+			pacing.monitor_mode([session(1), session(2)]),
+			LatencyMode::Low
+		);
+		assert_eq!(
+			pacing.monitor_mode([session(1), session(3)]),
+			LatencyMode::Balanced
+		);
+		assert_eq!(pacing.monitor_mode([]), LatencyMode::Balanced);
+	}
+
+	#[test]
+	fn power_save_skips_every_other_vblank() {
+		let mut pacing = FramePacing::new(DEFAULT_LATCH_MARGIN);
+		let output: MonitorId = "mon_1".parse().expect("monitor id");
+		let interval = refresh_interval(60);
+		let start = Instant::now();
+		let mode = LatencyMode::PowerSave;
+		assert!(!pacing.skip_output(output, mode, interval, start));
+		assert!(pacing.skip_output(output, mode, interval, start + interval));
+		assert!(!pacing.skip_output(output, mode, interval, start + interval * 2));
+		assert!(!pacing.skip_output(
+			output,
+			LatencyMode::Balanced,
+			interval,
+			start + interval * 3
+		));
+	}
+
+	#[test]
+	fn latches_margin_before_the_next_vblank() {
+		let mut pacing = FramePacing::new(Duration::from_millis(3));
+		assert_eq!(pacing.latch_delay(), None);
+		pacing.set_latch_interval(Some(Duration::from_millis(10)));
+		assert_eq!(pacing.latch_delay(), Some(Duration::from_millis(7)));
+		pacing.set_latch_interval(Some(Duration::from_millis(2)));
+		assert_eq!(pacing.latch_delay(), None);
+	}
+}
@@ -0,0 +1,470 @@
+//! Per-monitor frame pacing statistics, aggregated over fixed windows and reported to the
+//! server as [`RenderEvt::FrameStats`]. Alongside, every session shown on a monitor gets a
+//! [`RenderEvt::SessionFrameStats`] for windows in which it presented: how many refreshes it
+//! missed between two new buffers, and how often it re-presented the buffer already on screen.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use super::RenderEvt;
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+/// Longer gaps between two new buffers mean the session went idle, not that it dropped frames.
+const IDLE_REFRESHES: u32 = 8;
+
+#[derive(Debug)]
+struct MonitorWindow {
+	window_start: Instant,
+	last_frame: Option<Instant>,
+	frame_times_ms: Vec<f32>,
+	missed_vblanks: u32,
+	refresh_hz: u32,
+	/// Set while commits are paused on a static scene; refresh periods spent there are counted
+	/// as saved commits instead of missed vblanks.
+	static_since: Option<Instant>,
+	skipped_commits: u32,
+}
+
+impl MonitorWindow {
+	fn new(now: Instant) -> Self {
+		Self {
+			window_start: now,
+			last_frame: None,
+			frame_times_ms: Vec::new(),
+			missed_vblanks: 0,
+			refresh_hz: 0,
+			static_since: None,
+			skipped_commits: 0,
+		}
+	}
+
+	/// Moves the refresh periods spent static up to `now` into `skipped_commits`.
+	fn settle_static(&mut self, now: Instant) {
+		let Some(since) = self.static_since else {
+			return;
+		};
+		if self.refresh_hz == 0 {
+			return;
+		}
+		let hz = u128::from(self.refresh_hz);
+		let periods = now.saturating_duration_since(since).as_nanos() * hz / 1_000_000_000;
+		self.skipped_commits = self
+			.skipped_commits
+			.saturating_add(u32::try_from(periods).unwrap_or(u32::MAX));
+		// Keep the partial period so back-to-back windows don't lose time.
+		let counted =
+			Duration::from_nanos(u64::try_from(periods * 1_000_000_000 / hz).unwrap_or(u64::MAX));
+		self.static_since = Some(since + counted);
+	}
+}
+
+#[derive(Debug)]
+struct SessionWindow {
+	window_start: Instant,
+	/// A new buffer became current since the last refresh.
+	new_frame: bool,
+	/// Refreshes shown without a new buffer since the last one; `None` until the first.
+	refreshes_since_frame: Option<u32>,
+	presented_frames: u32,
+	missed_refreshes: u32,
+	longest_miss_streak: u32,
+	stale_presents: u32,
+}
+
+impl SessionWindow {
+	fn new(now: Instant) -> Self {
+		Self {
+			window_start: now,
+			new_frame: false,
+			refreshes_since_frame: None,
+			presented_frames: 0,
+			missed_refreshes: 0,
+			longest_miss_streak: 0,
+			stale_presents: 0,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub(super) struct FrameStatsTracker {
+	/// `None` disables aggregation entirely.
+	interval: Option<Duration>,
+	monitors: HashMap<MonitorId, MonitorWindow>,
+	sessions: HashMap<(MonitorId, SessionId), SessionWindow>,
+}
+
+impl FrameStatsTracker {
+	pub fn new(interval: Option<Duration>) -> Self {
+		Self {
+			interval,
+			monitors: HashMap::new(),
+			sessions: HashMap::new(),
+		}
+	}
+
+	/// Reads `SHIFT_FRAME_STATS_INTERVAL_MS` (default 1000, `0` disables reporting).
+	pub fn from_env() -> Self {
+		let interval = match std::env::var("SHIFT_FRAME_STATS_INTERVAL_MS") {
+			Ok(raw) => match raw.trim().parse::<u64>() {
+				Ok(0) => None,
+				Ok(ms) => Some(Duration::from_millis(ms)),
+				Err(e) => {
+					tracing::warn!(value = %raw, "invalid SHIFT_FRAME_STATS_INTERVAL_MS: {e}");
+					Some(DEFAULT_INTERVAL)
+				}
+			},
+			Err(_) => Some(DEFAULT_INTERVAL),
+		};
+		Self::new(interval)
+	}
+
+	/// Records a committed frame for `monitor_id`. Frame times are measured between consecutive
+	/// commits; every refresh period beyond the first counts as a missed vblank.
+	pub fn record_frame(&mut self, monitor_id: MonitorId, refresh_hz: u32, at: Instant) {
+		if self.interval.is_none() {
+			return;
+		}
+		let window = self
+			.monitors
+			.entry(monitor_id)
+			.or_insert_with(|| MonitorWindow::new(at));
+		window.refresh_hz = refresh_hz;
+		// Back from keeping its frame; the gap is not a frame time.
+		if window.static_since.is_some() {
+			window.settle_static(at);
+			window.static_since = None;
+			window.last_frame = None;
+		}
+		if let Some(last) = window.last_frame.replace(at) {
+			let frame_ms = at.saturating_duration_since(last).as_secs_f32() * 1000.0;
+			window.frame_times_ms.push(frame_ms);
+			if refresh_hz > 0 {
+				let period_ms = 1000.0 / refresh_hz as f32;
+				let vblanks = (frame_ms / period_ms).round() as u32;
+				window.missed_vblanks += vblanks.saturating_sub(1);
+			}
+		}
+	}
+
+	/// A new buffer of `session_id` became the one shown on `monitor_id`.
+	pub fn record_latch(&mut self, monitor_id: MonitorId, session_id: SessionId, at: Instant) {
+		if let Some(window) = self.session_window(monitor_id, session_id, at) {
+			window.new_frame = true;
+		}
+	}
+
+	/// `session_id` asked to present the buffer already shown on `monitor_id`.
+	pub fn record_stale_present(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		at: Instant,
+	) {
+		if let Some(window) = self.session_window(monitor_id, session_id, at) {
+			window.stale_presents += 1;
+		}
+	}
+
+	/// `monitor_id` refreshed while showing `session_id`. Refreshes without a new buffer count
+	/// as missed once the session presents again, unless it was idle in between.
+	pub fn record_refresh(&mut self, monitor_id: MonitorId, session_id: SessionId, at: Instant) {
+		let Some(window) = self.session_window(monitor_id, session_id, at) else {
+			return;
+		};
+		if std::mem::take(&mut window.new_frame) {
+			if let Some(missed) = window.refreshes_since_frame
+				&& missed <= IDLE_REFRESHES
+			{
+				window.missed_refreshes += missed;
+				window.longest_miss_streak = window.longest_miss_streak.max(missed);
+			}
+			window.refreshes_since_frame = Some(0);
+			window.presented_frames += 1;
+		} else if let Some(refreshes) = window.refreshes_since_frame.as_mut() {
+			*refreshes = refreshes.saturating_add(1);
+		}
+	}
+
+	fn session_window(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		at: Instant,
+	) -> Option<&mut SessionWindow> {
+		self.interval?;
+		Some(
+			self
+				.sessions
+				.entry((monitor_id, session_id))
+				.or_insert_with(|| SessionWindow::new(at)),
+		)
+	}
+
+	/// Commits stopped because the scene is static.
+	pub fn enter_static(&mut self, now: Instant) {
+		for window in self.monitors.values_mut() {
+			window.static_since = Some(now);
+		}
+	}
+
+	/// `monitor_id` kept its frame while others committed; it counts as static until its next
+	/// frame.
+	pub fn keep_frame(&mut self, monitor_id: MonitorId, now: Instant) {
+		if let Some(window) = self.monitors.get_mut(&monitor_id) {
+			window.static_since.get_or_insert(now);
+		}
+	}
+
+	/// Commits resumed. The static gap is not a frame time, so the next commit starts a new
+	/// measurement.
+	pub fn leave_static(&mut self, now: Instant) {
+		for window in self.monitors.values_mut() {
+			window.settle_static(now);
+			window.static_since = None;
+			window.last_frame = None;
+		}
+	}
+
+	/// Returns a `FrameStats` event for every monitor whose window has elapsed and starts a new
+	/// window for it.
+	pub fn take_due(&mut self, now: Instant) -> Vec<RenderEvt> {
+		let Some(interval) = self.interval else {
+			return Vec::new();
+		};
+		let mut due = Vec::new();
+		for (monitor_id, window) in &mut self.monitors {
+			let elapsed = now.saturating_duration_since(window.window_start);
+			if elapsed < interval {
+				continue;
+			}
+			window.settle_static(now);
+			let mut frame_times = std::mem::take(&mut window.frame_times_ms);
+			let frames = frame_times.len();
+			let (avg_frame_ms, p99_frame_ms) = if frames == 0 {
+				(0.0, 0.0)
+			} else {
+				frame_times.sort_by(f32::total_cmp);
+				let avg = frame_times.iter().sum::<f32>() / frames as f32;
+				let p99_index = ((frames as f32 * 0.99).ceil() as usize).clamp(1, frames) - 1;
+				(avg, frame_times[p99_index])
+			};
+			due.push(RenderEvt::FrameStats {
+				monitor_id: *monitor_id,
+				fps: frames as f32 / elapsed.as_secs_f32(),
+				avg_frame_ms,
+				p99_frame_ms,
+				missed_vblanks: std::mem::take(&mut window.missed_vblanks),
+				skipped_commits: std::mem::take(&mut window.skipped_commits),
+			});
+			window.window_start = now;
+		}
+		for ((monitor_id, session_id), window) in &mut self.sessions {
+			if now.saturating_duration_since(window.window_start) < interval {
+				continue;
+			}
+			window.window_start = now;
+			// Sessions that neither presented nor tried to have nothing to report.
+			if window.presented_frames == 0 && window.stale_presents == 0 {
+				continue;
+			}
+			due.push(RenderEvt::SessionFrameStats {
+				session_id: *session_id,
+				monitor_id: *monitor_id,
+				presented_frames: std::mem::take(&mut window.presented_frames),
+				missed_refreshes: std::mem::take(&mut window.missed_refreshes),
+				longest_miss_streak: std::mem::take(&mut window.longest_miss_streak),
+				stale_presents: std::mem::take(&mut window.stale_presents),
+			});
+		}
+		due
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.monitors.remove(&monitor_id);
+		self
+			.sessions
+			.retain(|(window_monitor, _), _| *window_monitor != monitor_id);
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self
+			.sessions
+			.retain(|(_, window_session), _| *window_session != session_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monitor_id() -> MonitorId {
+		"mon_1".parse().expect("monitor id")
+	}
+
+	fn session_id() -> SessionId {
+		"se_1".parse().expect("session id")
+	}
+
+	fn session_stats(due: &[RenderEvt]) -> (u32, u32, u32, u32) {
+		let Some(RenderEvt::SessionFrameStats {
+			presented_frames,
+			missed_refreshes,
+			longest_miss_streak,
+			stale_presents,
+			..
+		}) = due
+			.iter()
+			.find(|evt| matches!(evt, RenderEvt::SessionFrameStats { .. }))
+		else {
+			panic!("expected a SessionFrameStats event, got {due:?}");
+		};
+		(
+			*presented_frames,
+			*missed_refreshes,
+			*longest_miss_streak,
+			*stale_presents,
+		)
+	}
+
+	#[test]
+	fn reports_fps_and_percentiles_once_window_elapses() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		for frame in 0..=60u32 {
+			tracker.record_frame(
+				monitor_id(),
+				60,
+				start + Duration::from_micros(16_667) * frame,
+			);
+		}
+		assert!(
+			tracker
+				.take_due(start + Duration::from_millis(500))
+				.is_empty()
+		);
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		let [
+			RenderEvt::FrameStats {
+				fps,
+				avg_frame_ms,
+				p99_frame_ms,
+				missed_vblanks,
+				..
+			},
+		] = due.as_slice()
+		else {
+			panic!("expected a single FrameStats event, got {due:?}");
+		};
+		assert!((fps - 60.0).abs() < 0.5, "fps = {fps}");
+		assert!((avg_frame_ms - 16.667).abs() < 0.1, "avg = {avg_frame_ms}");
+		assert!((p99_frame_ms - 16.667).abs() < 0.1, "p99 = {p99_frame_ms}");
+		assert_eq!(*missed_vblanks, 0);
+	}
+
+	#[test]
+	fn counts_missed_vblanks_for_long_frames() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		let period = Duration::from_micros(16_667);
+		tracker.record_frame(monitor_id(), 60, start);
+		tracker.record_frame(monitor_id(), 60, start + period);
+		// Two refresh periods skipped before the next commit.
+		tracker.record_frame(monitor_id(), 60, start + period * 4);
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		let [
+			RenderEvt::FrameStats {
+				missed_vblanks,
+				p99_frame_ms,
+				..
+			},
+		] = due.as_slice()
+		else {
+			panic!("expected a single FrameStats event, got {due:?}");
+		};
+		assert_eq!(*missed_vblanks, 2);
+		assert!((p99_frame_ms - 50.0).abs() < 0.1, "p99 = {p99_frame_ms}");
+	}
+
+	#[test]
+	fn counts_refresh_periods_spent_static_as_skipped_commits() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		tracker.record_frame(monitor_id(), 60, start);
+		tracker.enter_static(start);
+		tracker.leave_static(start + Duration::from_millis(500));
+		tracker.record_frame(monitor_id(), 60, start + Duration::from_millis(500));
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		let [
+			RenderEvt::FrameStats {
+				missed_vblanks,
+				skipped_commits,
+				..
+			},
+		] = due.as_slice()
+		else {
+			panic!("expected a single FrameStats event, got {due:?}");
+		};
+		assert_eq!(*missed_vblanks, 0);
+		assert_eq!(*skipped_commits, 30);
+	}
+
+	#[test]
+	fn a_monitor_keeping_its_frame_misses_no_vblanks() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		tracker.record_frame(monitor_id(), 60, start);
+		tracker.keep_frame(monitor_id(), start + Duration::from_millis(16));
+		tracker.keep_frame(monitor_id(), start + Duration::from_millis(33));
+		tracker.record_frame(monitor_id(), 60, start + Duration::from_millis(516));
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		let [
+			RenderEvt::FrameStats {
+				missed_vblanks,
+				skipped_commits,
+				..
+			},
+		] = due.as_slice()
+		else {
+			panic!("expected a single FrameStats event, got {due:?}");
+		};
+		assert_eq!(*missed_vblanks, 0);
+		assert_eq!(*skipped_commits, 30);
+	}
+
+	#[test]
+	fn counts_refreshes_a_session_missed_between_frames() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		// Presents on refreshes 0, 1 and 4, so it missed 2 and 3.
+		for refresh in 0..5u32 {
+			let at = start + Duration::from_millis(16) * refresh;
+			if matches!(refresh, 0 | 1 | 4) {
+				tracker.record_latch(monitor_id(), session_id(), at);
+			}
+			tracker.record_refresh(monitor_id(), session_id(), at);
+		}
+		tracker.record_stale_present(monitor_id(), session_id(), start);
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		assert_eq!(session_stats(&due), (3, 2, 2, 1));
+		// Counters start over with the next window.
+		assert!(tracker.take_due(start + Duration::from_secs(2)).is_empty());
+	}
+
+	#[test]
+	fn idle_gaps_are_not_missed_refreshes() {
+		let mut tracker = FrameStatsTracker::new(Some(Duration::from_secs(1)));
+		let start = Instant::now();
+		tracker.record_latch(monitor_id(), session_id(), start);
+		// The first refresh shows the first frame, the rest go by without one.
+		for _ in 0..=IDLE_REFRESHES + 1 {
+			tracker.record_refresh(monitor_id(), session_id(), start);
+		}
+		tracker.record_latch(monitor_id(), session_id(), start);
+		tracker.record_refresh(monitor_id(), session_id(), start);
+		let due = tracker.take_due(start + Duration::from_secs(1));
+		assert_eq!(session_stats(&due), (2, 0, 0, 0));
+	}
+}
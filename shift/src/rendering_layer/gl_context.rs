@@ -0,0 +1,280 @@
+//! What the GL contexts are, and the driver's `GL_KHR_debug` output.
+//!
+//! `SHIFT_GL_CONTEXT` lists what the contexts should be, comma separated: `gles3` for OpenGL ES
+//! 3 or later, `robust` for robust buffer access that reports GPU resets, and `debug` for a
+//! debug context. EasyDRM creates the contexts and takes no attributes for them, so these can't
+//! be asked of the driver yet; the shared context's capabilities are logged when the device is
+//! opened ([`ContextInfo`]), with a warning for each request it doesn't meet.
+//!
+//! `debug` also turns on debug output on every context Shift draws with, and logs the driver's
+//! messages under the `shift::gl` target, at the level of their severity. Drivers send fewer
+//! messages to contexts not created for debugging.
+
+use std::{
+	collections::HashSet,
+	ffi::{CStr, c_char, c_void},
+};
+
+use super::{egl, gles};
+
+/// What `SHIFT_GL_CONTEXT` asks of the contexts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct ContextRequest {
+	pub gles3: bool,
+	pub robust: bool,
+	pub debug: bool,
+}
+
+impl ContextRequest {
+	pub fn from_env() -> Self {
+		std::env::var("SHIFT_GL_CONTEXT")
+			.map(|value| Self::parse(&value))
+			.unwrap_or_default()
+	}
+
+	fn parse(value: &str) -> Self {
+		let mut request = Self::default();
+		for attribute in value.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+			match attribute {
+				"gles3" => request.gles3 = true,
+				"robust" => request.robust = true,
+				"debug" => request.debug = true,
+				other => tracing::warn!(attribute = other, "unknown SHIFT_GL_CONTEXT attribute"),
+			}
+		}
+		request
+	}
+}
+
+/// Capabilities of a GL context, as queried on it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct ContextInfo {
+	pub version: String,
+	pub renderer: String,
+	pub vendor: String,
+	/// Major version of OpenGL ES; 0 when the version string doesn't say.
+	pub major: u32,
+	pub debug: bool,
+	pub robust_access: bool,
+	/// Whether a GPU reset is reported as a lost context, rather than not at all.
+	pub reset_notification: bool,
+	/// Whether the driver has `GL_KHR_debug`.
+	pub khr_debug: bool,
+}
+
+impl ContextInfo {
+	/// Queries the current context.
+	fn query(gl: &gles::Gles2) -> Self {
+		let version = string(gl, gles::VERSION);
+		let extensions = string(gl, gles::EXTENSIONS);
+		let has = |name: &str| extensions.split(' ').any(|extension| extension == name);
+		let flags = integer(gl, gles::CONTEXT_FLAGS) as u32;
+		let robustness = has("GL_KHR_robustness") || has("GL_EXT_robustness");
+		Self {
+			major: major_version(&version),
+			renderer: string(gl, gles::RENDERER),
+			vendor: string(gl, gles::VENDOR),
+			debug: flags & gles::CONTEXT_FLAG_DEBUG_BIT != 0,
+			robust_access: robustness && integer(gl, gles::CONTEXT_ROBUST_ACCESS) != 0,
+			reset_notification: robustness
+				&& integer(gl, gles::RESET_NOTIFICATION_STRATEGY) as u32 == gles::LOSE_CONTEXT_ON_RESET,
+			khr_debug: has("GL_KHR_debug"),
+			version,
+		}
+	}
+
+	/// Requested attributes the context doesn't have.
+	fn missing(&self, request: ContextRequest) -> Vec<&'static str> {
+		[
+			(request.gles3 && self.major < 3, "gles3"),
+			(
+				request.robust && !(self.robust_access && self.reset_notification),
+				"robust",
+			),
+			(request.debug && !self.debug, "debug"),
+		]
+		.into_iter()
+		.filter_map(|(missing, attribute)| missing.then_some(attribute))
+		.collect()
+	}
+}
+
+/// Debug output on the contexts Shift draws with.
+pub(super) struct GlDebug {
+	gl: gles::Gles2,
+	egl: egl::Egl,
+	enabled: bool,
+	/// Contexts debug output was turned on for, by EGL context handle.
+	attached: HashSet<usize>,
+}
+
+impl GlDebug {
+	/// Logs the capabilities of the current context, the shared one, and turns debug output on
+	/// there when asked to.
+	pub fn load(resolver: impl Fn(&str) -> *const c_void) -> Self {
+		let request = ContextRequest::from_env();
+		let gl = gles::Gles2::load_with(|name| resolver(name));
+		let info = ContextInfo::query(&gl);
+		tracing::info!(
+			version = %info.version,
+			renderer = %info.renderer,
+			vendor = %info.vendor,
+			debug = info.debug,
+			robust_access = info.robust_access,
+			reset_notification = info.reset_notification,
+			khr_debug = info.khr_debug,
+			"GL context"
+		);
+		for attribute in info.missing(request) {
+			tracing::warn!(
+				attribute,
+				"the GL context lacks a SHIFT_GL_CONTEXT attribute; EasyDRM takes none"
+			);
+		}
+		let enabled = request.debug && info.khr_debug && gl.DebugMessageCallbackKHR.is_loaded();
+		if request.debug && !enabled {
+			tracing::warn!("the driver has no GL_KHR_debug, so there is no GL debug output");
+		}
+		let mut debug = Self {
+			gl,
+			egl: egl::Egl::load_with(|name| resolver(name)),
+			enabled,
+			attached: HashSet::new(),
+		};
+		debug.attach();
+		debug
+	}
+
+	/// Turns debug output on for the current context, once per context.
+	pub fn attach(&mut self) {
+		if !self.enabled {
+			return;
+		}
+		let context = unsafe { self.egl.GetCurrentContext() } as usize;
+		if context == 0 || !self.attached.insert(context) {
+			return;
+		}
+		unsafe {
+			self.gl.Enable(gles::DEBUG_OUTPUT);
+			// Messages arrive on the thread and in the call that caused them.
+			self.gl.Enable(gles::DEBUG_OUTPUT_SYNCHRONOUS);
+			self
+				.gl
+				.DebugMessageCallbackKHR(Some(on_message), std::ptr::null());
+		}
+	}
+}
+
+extern "system" fn on_message(
+	source: gles::types::GLenum,
+	kind: gles::types::GLenum,
+	id: gles::types::GLuint,
+	severity: gles::types::GLenum,
+	length: gles::types::GLsizei,
+	message: *const c_char,
+	_user: *mut c_void,
+) {
+	if message.is_null() {
+		return;
+	}
+	let message = if length >= 0 {
+		let bytes = unsafe { std::slice::from_raw_parts(message.cast::<u8>(), length as usize) };
+		String::from_utf8_lossy(bytes).into_owned()
+	} else {
+		unsafe { CStr::from_ptr(message) }
+			.to_string_lossy()
+			.into_owned()
+	};
+	let (source, kind) = (source_name(source), type_name(kind));
+	match severity {
+		gles::DEBUG_SEVERITY_HIGH => {
+			tracing::error!(target: "shift::gl", source, kind, id, "{message}")
+		}
+		gles::DEBUG_SEVERITY_MEDIUM => {
+			tracing::warn!(target: "shift::gl", source, kind, id, "{message}")
+		}
+		gles::DEBUG_SEVERITY_LOW => tracing::info!(target: "shift::gl", source, kind, id, "{message}"),
+		_ => tracing::debug!(target: "shift::gl", source, kind, id, "{message}"),
+	}
+}
+
+fn source_name(source: gles::types::GLenum) -> &'static str {
+	match source {
+		gles::DEBUG_SOURCE_API => "api",
+		gles::DEBUG_SOURCE_WINDOW_SYSTEM => "window_system",
+		gles::DEBUG_SOURCE_SHADER_COMPILER => "shader_compiler",
+		gles::DEBUG_SOURCE_THIRD_PARTY => "third_party",
+		gles::DEBUG_SOURCE_APPLICATION => "application",
+		_ => "other",
+	}
+}
+
+fn type_name(kind: gles::types::GLenum) -> &'static str {
+	match kind {
+		gles::DEBUG_TYPE_ERROR => "error",
+		gles::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+		gles::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined_behavior",
+		gles::DEBUG_TYPE_PORTABILITY => "portability",
+		gles::DEBUG_TYPE_PERFORMANCE => "performance",
+		gles::DEBUG_TYPE_MARKER => "marker",
+		_ => "other",
+	}
+}
+
+fn string(gl: &gles::Gles2, name: gles::types::GLenum) -> String {
+	let value = unsafe { gl.GetString(name) };
+	if value.is_null() {
+		return String::new();
+	}
+	unsafe { CStr::from_ptr(value.cast()) }
+		.to_string_lossy()
+		.into_owned()
+}
+
+/// `name` of the current context; 0 when the context doesn't know it.
+fn integer(gl: &gles::Gles2, name: gles::types::GLenum) -> i32 {
+	let mut value = 0;
+	unsafe {
+		gl.GetIntegerv(name, &mut value);
+		// Older contexts reject queries they predate; clear the error for the next one.
+		gl.GetError();
+	}
+	value
+}
+
+/// Major version in an `OpenGL ES <major>.<minor> ...` version string.
+fn major_version(version: &str) -> u32 {
+	version
+		.strip_prefix("OpenGL ES ")
+		.and_then(|rest| rest.split('.').next())
+		.and_then(|major| major.parse().ok())
+		.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unmet_requests_are_reported() {
+		let request = ContextRequest::parse("gles3, debug,bogus");
+		assert_eq!(
+			request,
+			ContextRequest {
+				gles3: true,
+				robust: false,
+				debug: true,
+			}
+		);
+		let info = ContextInfo {
+			major: major_version("OpenGL ES 3.2 Mesa 24.1.0"),
+			..ContextInfo::default()
+		};
+		assert_eq!(info.major, 3);
+		assert_eq!(info.missing(request), ["debug"]);
+		assert_eq!(
+			ContextInfo::default().missing(ContextRequest::parse("gles3,robust")),
+			["gles3", "robust"]
+		);
+	}
+}
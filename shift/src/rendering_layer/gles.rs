@@ -0,0 +1,6 @@
+#[allow(clippy::all, warnings)]
+pub(crate) mod ffi {
+	include!(concat!(env!("OUT_DIR"), "/gles_bindings.rs"));
+}
+
+pub(crate) use ffi::*;
@@ -0,0 +1,219 @@
+//! Offscreen golden-image tests for the composition path.
+//!
+//! Every registered transition is rendered at fixed progress values into a CPU raster
+//! surface and compared against the PNGs in `shift/tests/golden`. Run with
+//! `SHIFT_UPDATE_GOLDENS=1` to (re)record them after an intended visual change and commit the
+//! result; a missing golden fails the test like a mismatched one.
+
+use std::path::PathBuf;
+
+use skia_safe::{
+	AlphaType, CachingHint, Color, ColorType, Image, ImageInfo, Paint, Rect, Surface, surfaces,
+};
+
+use tab_protocol::{RenderQualityPayload, SamplingFilter};
+
+use super::animation::AnimationRegistry;
+use super::render_core::{compose_fullscreen, sampling_options};
+
+const WIDTH: i32 = 64;
+const HEIGHT: i32 = 48;
+const PROGRESS_STEPS: [f64; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+/// Maximum per-channel difference before a pixel counts as mismatched.
+const CHANNEL_TOLERANCE: u8 = 4;
+/// Fraction of mismatched pixels tolerated before the comparison fails.
+const MAX_MISMATCH_RATIO: f64 = 0.005;
+
+fn golden_dir() -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn raster_surface() -> Surface {
+	surfaces::raster_n32_premul((WIDTH, HEIGHT)).expect("raster surface")
+}
+
+/// Red/blue checkerboard standing in for the outgoing session's buffer.
+fn old_session_texture() -> Image {
+	let mut surface = raster_surface();
+	let canvas = surface.canvas();
+	canvas.clear(Color::from_rgb(200, 30, 30));
+	let mut paint = Paint::default();
+	paint.set_color(Color::from_rgb(30, 30, 200));
+	for y in (0..HEIGHT).step_by(8) {
+		for x in (0..WIDTH).step_by(8) {
+			if (x / 8 + y / 8) % 2 == 0 {
+				canvas.draw_rect(Rect::from_xywh(x as f32, y as f32, 8.0, 8.0), &paint);
+			}
+		}
+	}
+	surface.image_snapshot()
+}
+
+/// Green horizontal bands standing in for the incoming session's buffer.
+fn new_session_texture() -> Image {
+	let mut surface = raster_surface();
+	let canvas = surface.canvas();
+	for band in 0..HEIGHT / 4 {
+		let mut paint = Paint::default();
+		paint.set_color(Color::from_rgb(20, (band * 20).min(255) as u8, 40));
+		canvas.draw_rect(
+			Rect::from_xywh(0.0, (band * 4) as f32, WIDTH as f32, 4.0),
+			&paint,
+		);
+	}
+	surface.image_snapshot()
+}
+
+fn read_rgba(surface: &mut Surface) -> Vec<u8> {
+	let info = ImageInfo::new(
+		(WIDTH, HEIGHT),
+		ColorType::RGBA8888,
+		AlphaType::Unpremul,
+		None,
+	);
+	let row_bytes = WIDTH as usize * 4;
+	let mut pixels = vec![0u8; row_bytes * HEIGHT as usize];
+	let ok = surface.image_snapshot().read_pixels(
+		&info,
+		pixels.as_mut_slice(),
+		row_bytes,
+		(0, 0),
+		CachingHint::Disallow,
+	);
+	assert!(ok, "failed to read back raster surface");
+	pixels
+}
+
+fn assert_matches_golden(name: &str, pixels: &[u8]) {
+	let path = golden_dir().join(format!("{name}.png"));
+	let update = std::env::var("SHIFT_UPDATE_GOLDENS").is_ok_and(|v| v == "1");
+	if update {
+		std::fs::create_dir_all(golden_dir()).expect("create golden dir");
+		image::save_buffer(
+			&path,
+			pixels,
+			WIDTH as u32,
+			HEIGHT as u32,
+			image::ColorType::Rgba8,
+		)
+		.expect("write golden image");
+		return;
+	}
+	let golden = image::open(&path)
+		.unwrap_or_else(|e| {
+			panic!(
+				"failed to open {}: {e}; record it with SHIFT_UPDATE_GOLDENS=1",
+				path.display()
+			)
+		})
+		.to_rgba8();
+	assert_eq!(
+		(golden.width(), golden.height()),
+		(WIDTH as u32, HEIGHT as u32),
+		"{name}: golden size mismatch"
+	);
+	let mismatched = golden
+		.as_raw()
+		.chunks_exact(4)
+		.zip(pixels.chunks_exact(4))
+		.filter(|(expected, actual)| {
+			expected
+				.iter()
+				.zip(actual.iter())
+				.any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE)
+		})
+		.count();
+	let ratio = mismatched as f64 / (WIDTH * HEIGHT) as f64;
+	assert!(
+		ratio <= MAX_MISMATCH_RATIO,
+		"{name}: {mismatched} pixels ({:.2}%) differ from {}",
+		ratio * 100.0,
+		path.display()
+	);
+}
+
+#[test]
+fn transitions_match_goldens() {
+	let registry = AnimationRegistry::new();
+	let old_image = old_session_texture();
+	let new_image = new_session_texture();
+	let mut names = registry.names().map(str::to_owned).collect::<Vec<_>>();
+	names.sort();
+	assert!(!names.is_empty(), "no transitions registered");
+	for name in names {
+		let animation = registry.get(&name).expect("registered animation");
+		for progress in PROGRESS_STEPS {
+			let mut surface = raster_surface();
+			surface.canvas().clear(Color::BLACK);
+			animation.draw(
+				surface.canvas(),
+				&old_image,
+				&new_image,
+				progress,
+				WIDTH as f32,
+				HEIGHT as f32,
+			);
+			let pixels = read_rgba(&mut surface);
+			assert_matches_golden(
+				&format!("transition_{name}_{:03}", (progress * 100.0) as u32),
+				&pixels,
+			);
+		}
+	}
+}
+
+#[test]
+fn fullscreen_composition_matches_golden() {
+	let mut surface = raster_surface();
+	surface.canvas().clear(Color::BLACK);
+	compose_fullscreen(
+		surface.canvas(),
+		&new_session_texture(),
+		WIDTH as f32,
+		HEIGHT as f32,
+		sampling_options(Default::default()),
+	);
+	let pixels = read_rgba(&mut surface);
+	assert_matches_golden("compose_fullscreen", &pixels);
+}
+
+#[test]
+fn fullscreen_composition_scales_non_native_buffers() {
+	let mut small = surfaces::raster_n32_premul((WIDTH / 2, HEIGHT / 2)).expect("raster surface");
+	small
+		.canvas()
+		.draw_image(old_session_texture(), (0, 0), None);
+	let mut surface = raster_surface();
+	surface.canvas().clear(Color::BLACK);
+	compose_fullscreen(
+		surface.canvas(),
+		&small.image_snapshot(),
+		WIDTH as f32,
+		HEIGHT as f32,
+		sampling_options(Default::default()),
+	);
+	let pixels = read_rgba(&mut surface);
+	assert_matches_golden("compose_fullscreen_scaled", &pixels);
+}
+
+#[test]
+fn fullscreen_composition_upscales_with_cubic_filter() {
+	let mut small = surfaces::raster_n32_premul((WIDTH / 4, HEIGHT / 4)).expect("raster surface");
+	small
+		.canvas()
+		.draw_image(new_session_texture(), (0, 0), None);
+	let mut surface = raster_surface();
+	surface.canvas().clear(Color::BLACK);
+	compose_fullscreen(
+		surface.canvas(),
+		&small.image_snapshot(),
+		WIDTH as f32,
+		HEIGHT as f32,
+		sampling_options(RenderQualityPayload {
+			filter: SamplingFilter::CatmullRom,
+			mipmaps: false,
+		}),
+	);
+	let pixels = read_rgba(&mut surface);
+	assert_matches_golden("compose_fullscreen_cubic", &pixels);
+}
@@ -0,0 +1,296 @@
+//! HDR static metadata passthrough.
+//!
+//! Sessions attach HDR metadata when linking their buffers. Whenever the session shown on an
+//! output changes, its metadata (or none, for SDR content) is written to the connector's
+//! `HDR_OUTPUT_METADATA` property so the panel switches to the matching HDR mode.
+//!
+//! An output counts as HDR-capable when its connector has `HDR_OUTPUT_METADATA` and the sink's
+//! EDID lists the transfer function in its HDR static metadata block. Metadata for a transfer
+//! function the output can't display is not written, so the session is shown as SDR.
+//!
+//! EasyDRM doesn't carry extra connector properties in its commits, so the property is set
+//! directly on the card. The kernel only accepts that from the DRM master; when it refuses, the
+//! failure is logged once per output and the output stays in its current mode.
+
+use std::collections::HashMap;
+
+use drm::control::{Device as ControlDevice, connector, property};
+use tab_protocol::{HdrEotf, HdrMetadata};
+
+use crate::{drm_card::Card, monitor::MonitorId, sessions::SessionId};
+
+/// `HDMI_STATIC_METADATA_TYPE1`.
+const STATIC_METADATA_TYPE1: u8 = 0;
+/// Size of the kernel's `struct hdr_output_metadata`, including tail padding.
+const BLOB_LEN: usize = 32;
+const EDID_BLOCK_LEN: usize = 128;
+/// CTA-861 extension block tag.
+const CTA_EXTENSION: u8 = 0x02;
+/// CTA data block tag announcing an extended tag in its first byte.
+const CTA_EXTENDED_TAG: u8 = 7;
+const CTA_HDR_STATIC_METADATA: u8 = 6;
+/// EOTF bits of the HDR static metadata data block.
+const EOTF_PQ: u8 = 1 << 2;
+const EOTF_HLG: u8 = 1 << 3;
+
+#[derive(Debug, Default)]
+pub(super) struct HdrOutputs {
+	linked: HashMap<(MonitorId, SessionId), HdrMetadata>,
+	/// Last metadata written per connector; `None` inside means cleared.
+	applied: HashMap<u32, Option<HdrMetadata>>,
+	/// Transfer functions each connector can display, empty for SDR-only outputs.
+	eotfs: HashMap<u32, Vec<HdrEotf>>,
+}
+
+impl HdrOutputs {
+	pub fn set_link(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		metadata: Option<HdrMetadata>,
+	) {
+		match metadata {
+			Some(metadata) => self.linked.insert((monitor_id, session_id), metadata),
+			None => self.linked.remove(&(monitor_id, session_id)),
+		};
+	}
+
+	/// Reads which transfer functions `connector_id` can display, once per connector.
+	pub fn probe(&mut self, connector_id: u32) {
+		self.eotfs.entry(connector_id).or_insert_with(|| {
+			let eotfs = read_capabilities(connector_id);
+			if !eotfs.is_empty() {
+				tracing::info!(connector_id, ?eotfs, "HDR-capable output");
+			}
+			eotfs
+		});
+	}
+
+	pub fn eotfs(&self, connector_id: u32) -> Vec<HdrEotf> {
+		self.eotfs.get(&connector_id).cloned().unwrap_or_default()
+	}
+
+	pub fn retain_connectors(&mut self, connected: impl Fn(u32) -> bool) {
+		self
+			.eotfs
+			.retain(|connector_id, _| connected(*connector_id));
+	}
+
+	/// Brings `connector_id` in line with the session shown on `monitor_id`. Returns whether the
+	/// output is now in an HDR mode.
+	pub fn sync(
+		&mut self,
+		connector_id: u32,
+		monitor_id: MonitorId,
+		shown: Option<SessionId>,
+	) -> bool {
+		let wanted = shown
+			.and_then(|session_id| self.linked.get(&(monitor_id, session_id)).copied())
+			.filter(|metadata| {
+				self
+					.eotfs
+					.get(&connector_id)
+					.is_some_and(|eotfs| eotfs.contains(&metadata.eotf))
+			});
+		match self.applied.get(&connector_id) {
+			Some(applied) if *applied == wanted => return wanted.is_some(),
+			// Never touched: leave whatever the output was brought up with.
+			None if wanted.is_none() => return false,
+			_ => {}
+		}
+		if let Err(e) = write_output_metadata(connector_id, wanted.as_ref()) {
+			tracing::warn!(connector_id, "failed to set HDR_OUTPUT_METADATA: {e}");
+		}
+		// Recorded even on failure so a refused property isn't retried every frame.
+		self.applied.insert(connector_id, wanted);
+		wanted.is_some()
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self
+			.linked
+			.retain(|(linked_monitor, _), _| *linked_monitor != monitor_id);
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self
+			.linked
+			.retain(|(_, linked_session), _| *linked_session != session_id);
+	}
+}
+
+/// Encodes `struct hdr_output_metadata` as the kernel expects it in the property blob.
+pub(super) fn output_metadata_blob(metadata: &HdrMetadata) -> [u8; BLOB_LEN] {
+	let mut blob = [0u8; BLOB_LEN];
+	// `metadata_type` (u32), then the type 1 infoframe.
+	blob[..4].copy_from_slice(&u32::from(STATIC_METADATA_TYPE1).to_ne_bytes());
+	blob[4] = match metadata.eotf {
+		HdrEotf::Pq => 2,
+		HdrEotf::Hlg => 3,
+	};
+	blob[5] = STATIC_METADATA_TYPE1;
+	let values = metadata
+		.display_primaries
+		.iter()
+		.flatten()
+		.chain(&metadata.white_point)
+		.chain(&[
+			metadata.max_mastering_luminance,
+			metadata.min_mastering_luminance,
+			metadata.max_cll,
+			metadata.max_fall,
+		])
+		.copied();
+	for (chunk, value) in blob[6..30].chunks_exact_mut(2).zip(values) {
+		chunk.copy_from_slice(&value.to_ne_bytes());
+	}
+	blob
+}
+
+/// Transfer functions the HDR static metadata block of `edid`'s CTA extensions lists.
+pub(super) fn edid_hdr_eotfs(edid: &[u8]) -> Vec<HdrEotf> {
+	let mut supported = 0u8;
+	for block in edid.chunks_exact(EDID_BLOCK_LEN).skip(1) {
+		if block[0] != CTA_EXTENSION {
+			continue;
+		}
+		// Data blocks run from byte 4 up to the detailed timings.
+		let end = usize::from(block[2]).clamp(4, EDID_BLOCK_LEN - 1);
+		let mut offset = 4;
+		while offset < end {
+			let header = block[offset];
+			let len = usize::from(header & 0x1f);
+			let payload = &block[(offset + 1).min(end)..(offset + 1 + len).min(end)];
+			if header >> 5 == CTA_EXTENDED_TAG
+				&& let [CTA_HDR_STATIC_METADATA, eotfs, ..] = payload
+			{
+				supported |= eotfs;
+			}
+			offset += 1 + len;
+		}
+	}
+	let mut eotfs = Vec::new();
+	if supported & EOTF_PQ != 0 {
+		eotfs.push(HdrEotf::Pq);
+	}
+	if supported & EOTF_HLG != 0 {
+		eotfs.push(HdrEotf::Hlg);
+	}
+	eotfs
+}
+
+/// HDR transfer functions `connector_id` can display: none unless the connector takes
+/// `HDR_OUTPUT_METADATA` and has an EDID advertising them.
+fn read_capabilities(connector_id: u32) -> Vec<HdrEotf> {
+	let Some(handle) = drm::control::from_u32::<connector::Handle>(connector_id) else {
+		return Vec::new();
+	};
+	Card::open_all()
+		.into_iter()
+		.find_map(|(_, card)| {
+			let properties = card.get_properties(handle).ok()?;
+			let (ids, values) = properties.as_props_and_values();
+			let mut has_metadata = false;
+			let mut edid = None;
+			for (id, value) in ids.iter().zip(values) {
+				let Ok(info) = card.get_property(*id) else {
+					continue;
+				};
+				match info.name().to_bytes() {
+					b"HDR_OUTPUT_METADATA" => has_metadata = true,
+					b"EDID" if *value != 0 => edid = card.get_property_blob(*value).ok(),
+					_ => {}
+				}
+			}
+			Some(if has_metadata {
+				edid.map(|edid| edid_hdr_eotfs(&edid)).unwrap_or_default()
+			} else {
+				Vec::new()
+			})
+		})
+		.unwrap_or_default()
+}
+
+fn write_output_metadata(connector_id: u32, metadata: Option<&HdrMetadata>) -> std::io::Result<()> {
+	let not_found = || {
+		std::io::Error::new(
+			std::io::ErrorKind::NotFound,
+			"no connector with HDR_OUTPUT_METADATA",
+		)
+	};
+	let handle: connector::Handle = drm::control::from_u32(connector_id).ok_or_else(not_found)?;
+	for (_, card) in Card::open_all() {
+		let Ok(properties) = card.get_properties(handle) else {
+			continue;
+		};
+		let (ids, _) = properties.as_props_and_values();
+		let Some(property_id) = ids.iter().copied().find(|id| {
+			card
+				.get_property(*id)
+				.is_ok_and(|info| info.name().to_bytes() == b"HDR_OUTPUT_METADATA")
+		}) else {
+			continue;
+		};
+		let value = match metadata {
+			Some(metadata) => match card.create_property_blob(&output_metadata_blob(metadata))? {
+				property::Value::Blob(id) => id,
+				_ => return Err(not_found()),
+			},
+			None => 0,
+		};
+		return card.set_property(handle, property_id, value);
+	}
+	Err(not_found())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encodes_kernel_hdr_output_metadata_layout() {
+		let metadata = HdrMetadata {
+			eotf: HdrEotf::Pq,
+			display_primaries: [[35400, 14600], [8500, 39850], [6550, 2300]],
+			white_point: [15635, 16450],
+			max_mastering_luminance: 1000,
+			min_mastering_luminance: 50,
+			max_cll: 1000,
+			max_fall: 400,
+		};
+		let blob = output_metadata_blob(&metadata);
+		assert_eq!(&blob[..6], &[0, 0, 0, 0, 2, 0]);
+		let u16_at = |offset: usize| u16::from_ne_bytes([blob[offset], blob[offset + 1]]);
+		assert_eq!(u16_at(6), 35400);
+		assert_eq!(u16_at(18), 15635);
+		assert_eq!(u16_at(22), 1000);
+		assert_eq!(u16_at(28), 400);
+		assert_eq!(&blob[30..], &[0, 0]);
+	}
+
+	fn edid_with_cta_blocks(data_blocks: &[u8]) -> Vec<u8> {
+		let mut edid = vec![0u8; EDID_BLOCK_LEN * 2];
+		edid[126] = 1;
+		let cta = &mut edid[EDID_BLOCK_LEN..];
+		cta[0] = CTA_EXTENSION;
+		cta[1] = 3;
+		cta[2] = (4 + data_blocks.len()) as u8;
+		cta[4..4 + data_blocks.len()].copy_from_slice(data_blocks);
+		edid
+	}
+
+	#[test]
+	fn reads_eotfs_from_the_hdr_static_metadata_block() {
+		// A 2-byte audio block, then the HDR block: SDR, PQ and HLG, static metadata type 1.
+		let edid = edid_with_cta_blocks(&[0x22, 0x09, 0x07, 0xe3, 0x06, 0x0d, 0x01]);
+		assert_eq!(edid_hdr_eotfs(&edid), [HdrEotf::Pq, HdrEotf::Hlg]);
+	}
+
+	#[test]
+	fn sdr_sinks_have_no_hdr_eotfs() {
+		// Colorimetry block only.
+		let edid = edid_with_cta_blocks(&[0xe3, 0x05, 0xc0, 0x00]);
+		assert!(edid_hdr_eotfs(&edid).is_empty());
+		assert!(edid_hdr_eotfs(&edid[..EDID_BLOCK_LEN]).is_empty());
+	}
+}
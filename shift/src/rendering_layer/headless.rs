@@ -0,0 +1,924 @@
+//! A renderer without a GPU, for embedding Shift, for tests, and for machines without one.
+//!
+//! It reports a fixed set of virtual monitors and keeps the DRM renderer's buffer
+//! bookkeeping: swaps of linked buffers are acknowledged right away, and each virtual refresh
+//! releases the buffers they replaced, reports a page flip and sends presentation feedback for
+//! the swapped buffers. Monitors the server adds with [`RenderCmd::AddVirtualMonitor`] are
+//! tracked the same way, on the same clock. Nothing is composed, so captures fail; acquire
+//! fences and present times are ignored.
+//!
+//! Built with [`HeadlessRenderer::software`], it reports the outputs of a
+//! [`SoftwareDisplay`] instead, copies each swapped frame out for it and shows the current
+//! session's frames on every refresh. Links the display can't read are rejected like failed
+//! imports; captures still fail.
+//!
+//! With [`HeadlessRenderer::after_device_loss`], it announces its monitors as a restored device,
+//! taking over from a DRM renderer whose device is gone.
+//!
+//! Given a [`VblankHandle`] with [`HeadlessRenderer::manual_vblank`], it only refreshes when
+//! the handle says so, for tests; see [`super::manual_vblank`].
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+
+use tab_protocol::MonitorMode;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{
+	comms::{
+		render2server::{RenderEvt, RenderEvtTx},
+		server2render::{RenderCmd, RenderCmdRx},
+		trace::FrameId,
+	},
+	monitor::{Monitor as ServerLayerMonitor, MonitorId},
+	sessions::SessionId,
+};
+
+use super::channels::RenderingEnd;
+use super::formats::FALLBACK_FORMATS;
+use super::manual_vblank::{Step, Vblank, VblankBuffer, VblankHandle};
+use super::ownership::OwnershipManager;
+use super::present_queue;
+use super::presentation_feedback::PresentationFeedback;
+use super::software::SoftwareDisplay;
+use super::state::{BufferSlot, SlotKey};
+
+/// Geometry of one virtual monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadlessMonitor {
+	pub width: i32,
+	pub height: i32,
+	/// In Hz; also sets how often the monitor flips.
+	pub refresh_rate: u32,
+}
+
+impl Default for HeadlessMonitor {
+	fn default() -> Self {
+		Self {
+			width: 1920,
+			height: 1080,
+			refresh_rate: 60,
+		}
+	}
+}
+
+pub struct HeadlessRenderer {
+	command_rx: RenderCmdRx,
+	event_tx: RenderEvtTx,
+	monitors: Vec<ServerLayerMonitor>,
+	/// Offscreen monitors the server added for single sessions.
+	offscreen_monitors: HashSet<MonitorId>,
+	/// Buffers sessions linked, per monitor.
+	slots: HashSet<SlotKey>,
+	ownership: OwnershipManager,
+	/// Request that last presented each buffer.
+	frame_ids: HashMap<SlotKey, FrameId>,
+	/// Monitors that took a swap since the last refresh.
+	pending_flips: Vec<MonitorId>,
+	presentation_feedback: PresentationFeedback,
+	/// Shows the frames when rendering in software.
+	display: Option<SoftwareDisplay>,
+	/// Why a session's buffers on a monitor couldn't be linked, for rejecting its swaps.
+	link_failures: HashMap<(MonitorId, SessionId), &'static str>,
+	/// Refreshes to run instead of the timer, when a test steps them.
+	steps: Option<UnboundedReceiver<Step>>,
+	/// Refreshes so far.
+	refreshes: u64,
+	/// Time of the last stepped refresh, for stepped refreshes to count from.
+	virtual_time_ns: u64,
+	/// Whether it takes over from a renderer that lost its device.
+	after_device_loss: bool,
+}
+
+impl HeadlessRenderer {
+	pub fn new(channels: RenderingEnd, monitors: &[HeadlessMonitor]) -> Self {
+		let (command_rx, event_tx) = channels.into_parts();
+		let monitors = monitors
+			.iter()
+			.enumerate()
+			.map(|(index, monitor)| ServerLayerMonitor {
+				id: crate::monitor::stable_id(&format!("HEADLESS-{}", index + 1), None),
+				width: monitor.width,
+				height: monitor.height,
+				refresh_rate: monitor.refresh_rate.max(1),
+				connector_id: 0,
+				name: format!("HEADLESS-{}", index + 1),
+				primary: false,
+				formats: FALLBACK_FORMATS.to_vec(),
+				scanout_formats: FALLBACK_FORMATS.to_vec(),
+				format_modifiers: Vec::new(),
+				hdr_eotfs: Vec::new(),
+				vrr_capable: false,
+				offscreen: false,
+				transform: Default::default(),
+				scale: Default::default(),
+				modes: vec![MonitorMode {
+					width: monitor.width,
+					height: monitor.height,
+					refresh_rate: monitor.refresh_rate.max(1),
+					preferred: true,
+					current: true,
+				}],
+				position: Default::default(),
+			})
+			.collect();
+		Self {
+			command_rx,
+			event_tx,
+			monitors,
+			offscreen_monitors: HashSet::new(),
+			slots: HashSet::new(),
+			ownership: OwnershipManager::new(),
+			frame_ids: HashMap::new(),
+			pending_flips: Vec::new(),
+			presentation_feedback: PresentationFeedback::default(),
+			display: None,
+			link_failures: HashMap::new(),
+			steps: None,
+			refreshes: 0,
+			virtual_time_ns: 0,
+			after_device_loss: false,
+		}
+	}
+
+	/// Renders in software on `display`'s outputs.
+	pub fn software(channels: RenderingEnd, display: SoftwareDisplay) -> Self {
+		let mut renderer = Self::new(channels, &[]);
+		renderer.monitors = display.monitors();
+		renderer.display = Some(display);
+		renderer
+	}
+
+	/// Refreshes only when `handle` steps it.
+	pub fn manual_vblank(mut self, handle: &VblankHandle) -> Self {
+		self.steps = handle.take_steps();
+		if self.steps.is_none() {
+			tracing::warn!("vblank handle already steps another renderer, refreshing on a timer");
+		}
+		self
+	}
+
+	/// Announces the monitors with `DeviceRestored` instead of `Started`, to take over from a
+	/// renderer after `DeviceLost`.
+	pub(super) fn after_device_loss(mut self) -> Self {
+		self.after_device_loss = true;
+		self
+	}
+
+	#[tracing::instrument(skip_all, fields(monitors = self.monitors.len()))]
+	pub async fn run(mut self) {
+		let monitors = self.monitors.clone();
+		let announcement = if self.after_device_loss {
+			RenderEvt::DeviceRestored { monitors }
+		} else {
+			RenderEvt::Started { monitors }
+		};
+		self.emit_event(announcement).await;
+		let mut refresh = tokio::time::interval(Duration::from_secs(1) / self.refresh_rate());
+		refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+		let mut steps = self.steps.take();
+		let stepped = steps.is_some();
+		self.virtual_time_ns = present_queue::monotonic_ns().unwrap_or_default();
+		loop {
+			tokio::select! {
+				// Commands already received go before a step.
+				biased;
+				cmd = self.command_rx.recv() => {
+					let Some(cmd) = cmd else { break };
+					if matches!(cmd, RenderCmd::Shutdown) {
+						break;
+					}
+					self.handle_command(cmd).await;
+				}
+				Some(step) = async {
+					match &mut steps {
+						Some(steps) => steps.recv().await,
+						None => std::future::pending().await,
+					}
+				} => {
+					let mut vblanks = Vec::with_capacity(step.frames as usize);
+					for _ in 0..step.frames {
+						self.virtual_time_ns += 1_000_000_000 / u64::from(self.refresh_rate());
+						vblanks.push(self.refresh(self.virtual_time_ns).await);
+					}
+					let _ = step.reply.send(vblanks);
+				}
+				_ = refresh.tick(), if !stepped => {
+					self.refresh(present_queue::monotonic_ns().unwrap_or_default()).await;
+				}
+			}
+		}
+	}
+
+	async fn handle_command(&mut self, cmd: RenderCmd) {
+		match cmd {
+			RenderCmd::FramebufferLink {
+				payload,
+				dma_bufs,
+				session_id,
+			} => {
+				let monitor_id = payload.monitor_id;
+				if !self.is_known(monitor_id) {
+					tracing::warn!(%monitor_id, "framebuffer link for an unknown monitor");
+					return;
+				}
+				self.drop_surface(monitor_id, session_id);
+				if let Some(display) = &mut self.display
+					&& display.shows(monitor_id)
+					&& let Err(reason) = display.link(session_id, &payload, dma_bufs)
+				{
+					tracing::warn!(%monitor_id, reason, "can't show the linked buffers in software");
+					self.link_failures.insert((monitor_id, session_id), reason);
+					return;
+				}
+				for slot in [BufferSlot::Zero, BufferSlot::One] {
+					let key = SlotKey::new(monitor_id, session_id, slot);
+					self.slots.insert(key);
+					self.ownership.mark_slot_client_owned(key);
+				}
+			}
+			RenderCmd::AddVirtualMonitor { monitor, .. } => {
+				self.offscreen_monitors.insert(monitor.id);
+			}
+			RenderCmd::RemoveVirtualMonitor { monitor_id }
+				if self.offscreen_monitors.remove(&monitor_id) =>
+			{
+				self.ownership.cleanup_monitor(monitor_id);
+				self.slots.retain(|key| key.monitor_id != monitor_id);
+				self.frame_ids.retain(|key, _| key.monitor_id != monitor_id);
+				self.pending_flips.retain(|pending| *pending != monitor_id);
+				self.presentation_feedback.remove_monitor(monitor_id);
+			}
+			RenderCmd::SetActiveSession { session_id, .. } => {
+				self.ownership.set_current_session(session_id);
+				if let Some(display) = &mut self.display {
+					display.damage_all();
+				}
+			}
+			RenderCmd::SessionRemoved { session_id } => {
+				self.ownership.cleanup_session(session_id);
+				self.presentation_feedback.remove_session(session_id);
+				self.slots.retain(|key| key.session_id != session_id);
+				self.frame_ids.retain(|key, _| key.session_id != session_id);
+				self
+					.link_failures
+					.retain(|(_, session), _| *session != session_id);
+				if let Some(display) = &mut self.display {
+					display.remove_session(session_id);
+				}
+				if self.ownership.current_session() == Some(session_id) {
+					self.ownership.set_current_session(None);
+				}
+			}
+			RenderCmd::DropSessionBuffers {
+				session_id,
+				monitor_id,
+			} => self.drop_surface(monitor_id, session_id),
+			RenderCmd::SwapBuffers {
+				monitor_id,
+				buffer,
+				session_id,
+				frame_id,
+				..
+			} => {
+				let slot = BufferSlot::from(buffer);
+				let reason = if !self.is_known(monitor_id) {
+					Some("unknown_monitor")
+				} else if !self
+					.slots
+					.contains(&SlotKey::new(monitor_id, session_id, slot))
+				{
+					Some(
+						self
+							.link_failures
+							.get(&(monitor_id, session_id))
+							.copied()
+							.unwrap_or("unlinked_buffer"),
+					)
+				} else {
+					None
+				};
+				if let Some(reason) = reason {
+					self
+						.emit_event(RenderEvt::BufferRequestRejected {
+							session_id,
+							monitor_id,
+							buffer,
+							reason: reason.into(),
+							frame_id,
+						})
+						.await;
+					return;
+				}
+				let key = SlotKey::new(monitor_id, session_id, slot);
+				self.frame_ids.insert(key, frame_id);
+				self.presentation_feedback.latched(key);
+				if let Some(display) = &mut self.display {
+					display.latch(key);
+				}
+				let transition = self
+					.ownership
+					.apply_swap_request(monitor_id, session_id, slot, false);
+				if let Some(previous) = transition.previous_to_release {
+					self
+						.ownership
+						.queue_buffer_release(monitor_id, session_id, previous);
+				}
+				if !self.pending_flips.contains(&monitor_id) {
+					self.pending_flips.push(monitor_id);
+				}
+				self
+					.emit_event(RenderEvt::BufferRequestAck {
+						session_id,
+						monitor_id,
+						buffer,
+						frame_id,
+						// Shown at the next refresh whatever its fence and present time say.
+						deferred: false,
+					})
+					.await;
+			}
+			RenderCmd::SetCursor { monitor_id, image } => {
+				if let Some(display) = &mut self.display {
+					display.set_cursor(monitor_id, image);
+				}
+			}
+			RenderCmd::MoveCursor { monitor_id, x, y } => {
+				if let Some(display) = &mut self.display {
+					display.move_cursor(monitor_id, x, y);
+				}
+			}
+			RenderCmd::SetOutputTransform {
+				monitor_id,
+				transform,
+			} => {
+				if let Some(display) = &mut self.display {
+					display.set_transform(monitor_id, transform);
+				}
+			}
+			RenderCmd::SetMonitorScale { monitor_id, scale } => {
+				if let Some(display) = &mut self.display {
+					display.set_scale(monitor_id, scale);
+				}
+			}
+			RenderCmd::SetMode {
+				monitor_id,
+				width,
+				height,
+				refresh,
+			} => {
+				let result = self.set_mode(monitor_id, width, height, refresh);
+				if let Ok((monitor, true)) = &result {
+					self
+						.emit_event(RenderEvt::MonitorResized {
+							monitor: monitor.clone(),
+						})
+						.await;
+				}
+				self
+					.emit_event(RenderEvt::MonitorModeChanged {
+						monitor_id,
+						result: result
+							.map(|(monitor, _)| monitor)
+							.map_err(|reason| reason.into()),
+					})
+					.await;
+			}
+			RenderCmd::Capture { monitor_id } => {
+				self
+					.emit_event(RenderEvt::Captured {
+						monitor_id,
+						result: Err(self.no_capture_reason().into()),
+					})
+					.await;
+			}
+			RenderCmd::StartScreencast { cast_id, .. } => {
+				self
+					.emit_event(RenderEvt::ScreencastEnded {
+						cast_id,
+						reason: self.no_capture_reason().into(),
+					})
+					.await;
+			}
+			_ => {}
+		}
+	}
+
+	/// Flips every monitor that took a swap, releasing the buffers the swaps replaced.
+	async fn refresh(&mut self, timestamp_ns: u64) -> Vblank {
+		self.refreshes += 1;
+		let mut vblank = Vblank {
+			sequence: self.refreshes,
+			timestamp_ns,
+			..Default::default()
+		};
+		if let Some(display) = &mut self.display {
+			display.present(self.ownership.current_session());
+		}
+		if self.pending_flips.is_empty() {
+			return vblank;
+		}
+		for release in self.ownership.take_deferred_releases() {
+			let key = SlotKey::new(release.monitor_id, release.session_id, release.buffer);
+			self.ownership.mark_slot_client_owned(key);
+			vblank.released.push(VblankBuffer {
+				monitor_id: release.monitor_id,
+				session_id: release.session_id,
+				buffer: release.buffer.into(),
+			});
+			self
+				.emit_event(RenderEvt::BufferConsumed {
+					session_id: release.session_id,
+					monitor_id: release.monitor_id,
+					buffer: release.buffer.into(),
+					release_fence: None,
+					content_hash: None,
+					frame_id: self.frame_ids.get(&key).copied(),
+				})
+				.await;
+		}
+		let monitors = std::mem::take(&mut self.pending_flips);
+		for monitor_id in &monitors {
+			self
+				.presentation_feedback
+				.committed(*monitor_id, self.refresh_rate());
+			for presented in self
+				.presentation_feedback
+				.flipped(*monitor_id, timestamp_ns)
+			{
+				vblank.presented.push(VblankBuffer {
+					monitor_id: presented.key.monitor_id,
+					session_id: presented.key.session_id,
+					buffer: presented.key.buffer.into(),
+				});
+				self
+					.emit_event(RenderEvt::PresentationFeedback {
+						session_id: presented.key.session_id,
+						monitor_id: presented.key.monitor_id,
+						buffer: presented.key.buffer.into(),
+						timestamp_ns: presented.timestamp_ns,
+						sequence: presented.sequence,
+						refresh_ns: presented.refresh_ns,
+						frame_id: self.frame_ids.get(&presented.key).copied(),
+					})
+					.await;
+			}
+		}
+		vblank.flipped = monitors.clone();
+		self.emit_event(RenderEvt::PageFlip { monitors }).await;
+		vblank
+	}
+
+	/// Switches `monitor_id` to one of its modes. Returns the monitor in its new mode and
+	/// whether its size changed.
+	fn set_mode(
+		&mut self,
+		monitor_id: MonitorId,
+		width: i32,
+		height: i32,
+		refresh: u32,
+	) -> Result<(ServerLayerMonitor, bool), &'static str> {
+		let Some(monitor) = self
+			.monitors
+			.iter_mut()
+			.find(|monitor| monitor.id == monitor_id)
+		else {
+			return Err("unknown_monitor");
+		};
+		let resized = (monitor.width, monitor.height) != (width, height);
+		match &mut self.display {
+			Some(display) => *monitor = display.set_mode(monitor_id, width, height, refresh)?,
+			None => {
+				// Virtual monitors only have the mode they were made with.
+				if !monitor
+					.modes
+					.iter()
+					.any(|mode| (mode.width, mode.height, mode.refresh_rate) == (width, height, refresh))
+				{
+					return Err("invalid_mode");
+				}
+			}
+		}
+		Ok((monitor.clone(), resized))
+	}
+
+	/// One clock for every monitor, at the fastest refresh rate.
+	fn refresh_rate(&self) -> u32 {
+		self
+			.monitors
+			.iter()
+			.map(|monitor| monitor.refresh_rate)
+			.max()
+			.unwrap_or(60)
+	}
+
+	fn no_capture_reason(&self) -> &'static str {
+		match self.display {
+			Some(_) => "the software renderer can't capture",
+			None => "the headless renderer composes nothing",
+		}
+	}
+
+	fn is_known(&self, monitor_id: MonitorId) -> bool {
+		self.monitors.iter().any(|monitor| monitor.id == monitor_id)
+			|| self.offscreen_monitors.contains(&monitor_id)
+	}
+
+	fn drop_surface(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self.ownership.cleanup_surface(monitor_id, session_id);
+		self
+			.presentation_feedback
+			.remove_surface(monitor_id, session_id);
+		self.link_failures.remove(&(monitor_id, session_id));
+		if let Some(display) = &mut self.display {
+			display.unlink(monitor_id, session_id);
+		}
+		self
+			.slots
+			.retain(|key| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self
+			.frame_ids
+			.retain(|key, _| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+	}
+
+	async fn emit_event(&self, event: RenderEvt) {
+		if let Err(e) = self.event_tx.send(event).await {
+			tracing::warn!("failed to send renderer event to server: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	//! Drives the renderer with random command sequences a session and the server could send,
+	//! checking after every command what the events and the bookkeeping say about each buffer.
+
+	use rand::{Rng, SeedableRng, rngs::StdRng};
+	use tab_protocol::FramebufferLinkPayload;
+
+	use super::*;
+	use crate::{
+		comms::render2server::RenderEvtRx,
+		rendering_layer::{channels::Channels, state::SlotOwner},
+	};
+
+	const SEEDS: u64 = 64;
+	const STEPS: usize = 400;
+
+	#[derive(Debug, Clone, Copy)]
+	enum Op {
+		Link(MonitorId, SessionId),
+		/// A swap, optionally with a present time that already passed.
+		Swap(MonitorId, SessionId, BufferSlot, bool),
+		Refresh,
+		Activate(Option<SessionId>),
+		Remove(SessionId),
+	}
+
+	/// What sessions were told about their buffers.
+	#[derive(Default)]
+	struct Model {
+		/// Every linked buffer, `true` while its session may draw into it.
+		client_owned: HashMap<SlotKey, bool>,
+		/// Request that swapped in each buffer last.
+		frames: HashMap<SlotKey, FrameId>,
+		/// Buffer each surface shows.
+		current: HashMap<(MonitorId, SessionId), BufferSlot>,
+		/// Monitors that took a swap since the last refresh.
+		swapped: HashSet<MonitorId>,
+	}
+
+	struct Harness {
+		renderer: HeadlessRenderer,
+		events: RenderEvtRx,
+		model: Model,
+		monitors: Vec<MonitorId>,
+		sessions: Vec<SessionId>,
+		refreshes: u64,
+	}
+
+	impl Harness {
+		fn new() -> Self {
+			let (server_end, rendering_end) = Channels::new().split();
+			let (events, _) = server_end.into_parts();
+			let renderer = HeadlessRenderer::new(
+				rendering_end,
+				&[HeadlessMonitor::default(), HeadlessMonitor::default()],
+			);
+			let mut monitors: Vec<_> = renderer.monitors.iter().map(|monitor| monitor.id).collect();
+			// Never known to the renderer.
+			monitors.push("mon_1".parse().expect("monitor id"));
+			Self {
+				renderer,
+				events,
+				model: Model::default(),
+				monitors,
+				sessions: (1..=3)
+					.map(|n| format!("se_{n}").parse().expect("session id"))
+					.collect(),
+				refreshes: 0,
+			}
+		}
+
+		/// A command, leaving out swaps of buffers their session doesn't hold.
+		fn random_op(&self, rng: &mut StdRng) -> Op {
+			let monitor_id = self.monitors[rng.random_range(0..self.monitors.len())];
+			let session_id = self.sessions[rng.random_range(0..self.sessions.len())];
+			match rng.random_range(0..100) {
+				0..45 => {
+					let slot = if rng.random_bool(0.5) {
+						BufferSlot::Zero
+					} else {
+						BufferSlot::One
+					};
+					let key = SlotKey::new(monitor_id, session_id, slot);
+					match self.model.client_owned.get(&key) {
+						Some(false) => Op::Refresh,
+						_ => Op::Swap(monitor_id, session_id, slot, rng.random_bool(0.2)),
+					}
+				}
+				45..70 => Op::Refresh,
+				70..85 => Op::Link(monitor_id, session_id),
+				85..93 => Op::Activate(rng.random_bool(0.8).then_some(session_id)),
+				_ => Op::Remove(session_id),
+			}
+		}
+
+		async fn apply(&mut self, op: Op) -> Vec<RenderEvt> {
+			match op {
+				Op::Link(monitor_id, session_id) => {
+					self
+						.renderer
+						.handle_command(RenderCmd::FramebufferLink {
+							payload: link_payload(monitor_id),
+							dma_bufs: [Vec::new(), Vec::new()],
+							session_id,
+						})
+						.await;
+				}
+				Op::Swap(monitor_id, session_id, slot, past) => {
+					self
+						.renderer
+						.handle_command(RenderCmd::SwapBuffers {
+							monitor_id,
+							buffer: slot.into(),
+							session_id,
+							acquire_fence: None,
+							present_at: past.then_some(1),
+							frame_id: FrameId::next(),
+						})
+						.await;
+				}
+				Op::Refresh => {
+					self.refreshes += 1;
+					self.renderer.refresh(self.refreshes).await;
+				}
+				Op::Activate(session_id) => {
+					self
+						.renderer
+						.handle_command(RenderCmd::SetActiveSession {
+							session_id,
+							transition: None,
+						})
+						.await;
+				}
+				Op::Remove(session_id) => {
+					self
+						.renderer
+						.handle_command(RenderCmd::SessionRemoved { session_id })
+						.await;
+				}
+			}
+			let mut events = Vec::new();
+			while !self.events.is_empty() {
+				events.extend(self.events.recv().await);
+			}
+			events
+		}
+
+		/// Checks the events `op` caused against the model and moves the model along.
+		fn check(&mut self, op: Op, events: Vec<RenderEvt>) {
+			let model = &mut self.model;
+			match op {
+				Op::Link(monitor_id, session_id) => {
+					assert!(events.is_empty(), "linking says nothing: {events:?}");
+					forget_surface(model, monitor_id, session_id);
+					if self.renderer.is_known(monitor_id) {
+						for slot in [BufferSlot::Zero, BufferSlot::One] {
+							let key = SlotKey::new(monitor_id, session_id, slot);
+							model.client_owned.insert(key, true);
+						}
+					}
+				}
+				Op::Swap(monitor_id, session_id, slot, _) => {
+					let key = SlotKey::new(monitor_id, session_id, slot);
+					let [event] = events.as_slice() else {
+						panic!("a swap is acked or rejected once: {events:?}");
+					};
+					match (event, model.client_owned.get(&key)) {
+						(
+							RenderEvt::BufferRequestAck {
+								frame_id, deferred, ..
+							},
+							Some(true),
+						) => {
+							// The server holds deferred buffers until `BufferLatched`, which a
+							// refresh never sends.
+							assert!(!deferred, "{key:?} acked as deferred");
+							model.client_owned.insert(key, false);
+							model.frames.insert(key, *frame_id);
+							model.current.insert((monitor_id, session_id), slot);
+							model.swapped.insert(monitor_id);
+						}
+						(RenderEvt::BufferRequestRejected { .. }, None) => {}
+						(event, owned) => panic!("{event:?} for a buffer owned {owned:?}"),
+					}
+				}
+				Op::Refresh => {
+					for event in events {
+						match event {
+							RenderEvt::BufferConsumed {
+								session_id,
+								monitor_id,
+								buffer,
+								frame_id,
+								..
+							} => {
+								let key = SlotKey::new(monitor_id, session_id, buffer.into());
+								assert_eq!(
+									model.client_owned.insert(key, true),
+									Some(false),
+									"released {key:?}, which its session held or didn't link"
+								);
+								assert_ne!(
+									model.current.get(&(monitor_id, session_id)),
+									Some(&key.buffer),
+									"released {key:?} while it is shown"
+								);
+								assert_eq!(frame_id, model.frames.get(&key).copied());
+							}
+							RenderEvt::PresentationFeedback {
+								session_id,
+								monitor_id,
+								buffer,
+								..
+							} => {
+								let key = SlotKey::new(monitor_id, session_id, buffer.into());
+								assert_eq!(
+									model.current.get(&(monitor_id, session_id)),
+									Some(&key.buffer),
+									"presented {key:?}, which it doesn't show"
+								);
+							}
+							RenderEvt::PageFlip { monitors } => {
+								for monitor_id in monitors {
+									assert!(
+										model.swapped.contains(&monitor_id),
+										"{monitor_id} flipped without a swap"
+									);
+								}
+							}
+							event => panic!("unexpected {event:?}"),
+						}
+					}
+					model.swapped.clear();
+					for (key, client_owned) in &model.client_owned {
+						let shown = model.current.get(&(key.monitor_id, key.session_id)) == Some(&key.buffer);
+						assert_eq!(
+							*client_owned, !shown,
+							"{key:?} is kept from its session after a refresh"
+						);
+					}
+				}
+				Op::Activate(session_id) => {
+					assert!(events.is_empty(), "{events:?}");
+					assert_eq!(self.renderer.ownership.current_session(), session_id);
+				}
+				Op::Remove(session_id) => {
+					assert!(events.is_empty(), "{events:?}");
+					for &monitor_id in &self.monitors {
+						forget_surface(model, monitor_id, session_id);
+					}
+				}
+			}
+			self.check_bookkeeping();
+		}
+
+		/// The renderer tracks the buffers the sessions were told about, and no others.
+		fn check_bookkeeping(&self) {
+			let renderer = &self.renderer;
+			let linked: HashSet<_> = self.model.client_owned.keys().copied().collect();
+			assert_eq!(renderer.slots, linked, "linked buffers");
+			assert!(
+				renderer.frame_ids.keys().all(|key| linked.contains(key)),
+				"frame ids of unlinked buffers"
+			);
+			for &monitor_id in &self.monitors {
+				for &session_id in &self.sessions {
+					assert_eq!(
+						renderer
+							.ownership
+							.current_slot_key_for_session(monitor_id, session_id)
+							.map(|key| key.buffer),
+						self.model.current.get(&(monitor_id, session_id)).copied(),
+						"buffer shown on {monitor_id} for {session_id}"
+					);
+					for slot in [BufferSlot::Zero, BufferSlot::One] {
+						let key = SlotKey::new(monitor_id, session_id, slot);
+						let expected = self.model.client_owned.get(&key).map(|client_owned| {
+							if *client_owned {
+								SlotOwner::ClientOwned
+							} else {
+								SlotOwner::ShiftOwned
+							}
+						});
+						assert_eq!(renderer.ownership.owner(key), expected, "owner of {key:?}");
+					}
+				}
+			}
+		}
+	}
+
+	fn forget_surface(model: &mut Model, monitor_id: MonitorId, session_id: SessionId) {
+		let other = |key: &SlotKey| (key.monitor_id, key.session_id) != (monitor_id, session_id);
+		model.client_owned.retain(|key, _| other(key));
+		model.frames.retain(|key, _| other(key));
+		model.current.remove(&(monitor_id, session_id));
+	}
+
+	fn link_payload(monitor_id: MonitorId) -> FramebufferLinkPayload {
+		FramebufferLinkPayload {
+			monitor_id,
+			width: 1920,
+			height: 1080,
+			stride: 1920 * 4,
+			offset: 0,
+			fourcc: 0x34325258,
+			hdr_metadata: None,
+			extra_planes: Vec::new(),
+			modifier: None,
+			scale: None,
+			alpha_mode: None,
+		}
+	}
+
+	#[test]
+	fn takes_over_as_a_restored_device() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_time()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			let (server_end, rendering_end) = Channels::new().split();
+			let (mut events, commands) = server_end.into_parts();
+			let renderer =
+				HeadlessRenderer::new(rendering_end, &[HeadlessMonitor::default()]).after_device_loss();
+			let task = tokio::spawn(renderer.run());
+			assert!(matches!(
+				events.recv().await,
+				Some(RenderEvt::DeviceRestored { monitors }) if monitors.len() == 1
+			));
+			commands
+				.send(RenderCmd::Shutdown)
+				.await
+				.expect("renderer running");
+			task.await.expect("renderer stopped");
+		});
+	}
+
+	#[test]
+	fn random_commands_keep_buffer_ownership_consistent() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			for seed in 0..SEEDS {
+				let mut rng = StdRng::seed_from_u64(seed);
+				let mut harness = Harness::new();
+				for step in 0..STEPS {
+					let op = harness.random_op(&mut rng);
+					let events = harness.apply(op).await;
+					let checked = std::panic::AssertUnwindSafe(|| harness.check(op, events));
+					if let Err(panic) = std::panic::catch_unwind(checked) {
+						eprintln!("seed {seed}, command {step}: {op:?}");
+						std::panic::resume_unwind(panic);
+					}
+				}
+
+				for session_id in harness.sessions.clone() {
+					let events = harness.apply(Op::Remove(session_id)).await;
+					harness.check(Op::Remove(session_id), events);
+				}
+				let events = harness.apply(Op::Refresh).await;
+				harness.check(Op::Refresh, events);
+				let renderer = &mut harness.renderer;
+				assert!(renderer.slots.is_empty());
+				assert!(renderer.frame_ids.is_empty());
+				assert!(renderer.link_failures.is_empty());
+				assert!(renderer.ownership.take_deferred_releases().is_empty());
+			}
+		});
+	}
+}
@@ -0,0 +1,100 @@
+//! Which outputs have nothing new to show.
+//!
+//! An output is idle from the frame it last drew until something on it changes: a session's
+//! buffer latched on its monitor, or damage that affects every output (commands, mode changes,
+//! a composed cursor moving). Idle outputs keep their frame instead of composing and committing
+//! the same one again, so a monitor whose session stopped presenting costs no GPU time while
+//! the others keep drawing. VRR outputs always hold their frame while idle; with `SHIFT_PSR=0`
+//! the others compose every frame as before. Running animations, captures, casts and flip
+//! groups draw regardless.
+
+use std::collections::HashMap;
+
+use crate::monitor::MonitorId;
+
+#[derive(Debug, Default)]
+pub(super) struct IdleOutputs {
+	/// Ticks on every damage and draw, so each can be ordered against the others.
+	clock: u64,
+	/// Last damage that affects every output.
+	damaged_all: u64,
+	/// Last buffer latched per logical monitor.
+	damaged: HashMap<MonitorId, u64>,
+	/// Last frame drawn by each output, keyed by connector-level monitor id.
+	drawn: HashMap<MonitorId, u64>,
+}
+
+impl IdleOutputs {
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	/// Something that affects every output changed.
+	pub fn damage_all(&mut self) {
+		self.damaged_all = self.tick();
+	}
+
+	/// A session's buffer was latched on `monitor_id`.
+	pub fn damage(&mut self, monitor_id: MonitorId) {
+		let now = self.tick();
+		self.damaged.insert(monitor_id, now);
+	}
+
+	/// `output` drew a frame, composed or scanned out.
+	pub fn drawn(&mut self, output: MonitorId) {
+		let now = self.tick();
+		self.drawn.insert(output, now);
+	}
+
+	/// No damage since the output's last frame. `output` is the connector-level id,
+	/// `monitor_id` the logical monitor it belongs to.
+	pub fn is_idle(&self, output: MonitorId, monitor_id: MonitorId) -> bool {
+		let Some(drawn) = self.drawn.get(&output) else {
+			return false;
+		};
+		let damaged = self.damaged.get(&monitor_id).copied().unwrap_or(0);
+		self.damaged_all.max(damaged) < *drawn
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.damaged.remove(&monitor_id);
+		self.drawn.remove(&monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn outputs_idle_until_damaged() {
+		let mut idle = IdleOutputs::default();
+		let (left, right) = (MonitorId::from_raw(1), MonitorId::from_raw(2));
+		assert!(!idle.is_idle(left, left), "never drawn");
+		idle.drawn(left);
+		idle.drawn(right);
+		assert!(idle.is_idle(left, left));
+		idle.damage(left);
+		assert!(!idle.is_idle(left, left));
+		assert!(idle.is_idle(right, right));
+		idle.drawn(left);
+		assert!(idle.is_idle(left, left));
+		idle.damage_all();
+		assert!(!idle.is_idle(left, left));
+		assert!(!idle.is_idle(right, right));
+	}
+
+	#[test]
+	fn tiles_follow_their_logical_monitor() {
+		let mut idle = IdleOutputs::default();
+		let (logical, tile) = (MonitorId::from_raw(1), MonitorId::from_raw(2));
+		idle.drawn(logical);
+		idle.drawn(tile);
+		idle.damage(logical);
+		assert!(!idle.is_idle(tile, logical));
+		idle.drawn(tile);
+		assert!(idle.is_idle(tile, logical));
+		assert!(!idle.is_idle(logical, logical));
+	}
+}
@@ -0,0 +1,95 @@
+//! Accessibility zoom applied as a canvas transform during composition.
+//!
+//! The point under the pointer stays fixed while everything around it is scaled, so the
+//! visible region follows the pointer. The rendered focus eases towards the last reported one
+//! to keep panning smooth even when pointer updates are bursty.
+
+use std::time::Instant;
+
+use skia_safe::Canvas;
+
+use crate::monitor::MonitorId;
+
+/// Time constant of the focus easing, in seconds.
+const PAN_TIME_CONSTANT: f32 = 0.08;
+
+#[derive(Debug)]
+pub(super) struct Magnifier {
+	/// `None` magnifies every monitor.
+	monitor_id: Option<MonitorId>,
+	factor: f32,
+	target: (f32, f32),
+	focus: (f32, f32),
+	last_update: Option<Instant>,
+}
+
+impl Default for Magnifier {
+	fn default() -> Self {
+		Self {
+			monitor_id: None,
+			factor: 1.0,
+			target: (0.5, 0.5),
+			focus: (0.5, 0.5),
+			last_update: None,
+		}
+	}
+}
+
+impl Magnifier {
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, factor: f32) {
+		if self.factor <= 1.0 {
+			// Start zooming where the pointer is instead of panning in from the old focus.
+			self.focus = self.target;
+		}
+		self.monitor_id = monitor_id;
+		self.factor = factor.max(1.0);
+	}
+
+	pub fn set_focus(&mut self, x: f32, y: f32) {
+		self.target = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+	}
+
+	/// Advances the easing; call once per composed frame.
+	pub fn tick(&mut self, now: Instant) {
+		let dt = self
+			.last_update
+			.replace(now)
+			.map(|last| now.saturating_duration_since(last).as_secs_f32())
+			.unwrap_or(0.0);
+		let alpha = 1.0 - (-dt / PAN_TIME_CONSTANT).exp();
+		self.focus.0 += (self.target.0 - self.focus.0) * alpha;
+		self.focus.1 += (self.target.1 - self.focus.1) * alpha;
+	}
+
+	/// `true` while the rendered focus is still easing towards the pointer.
+	pub fn is_panning(&self) -> bool {
+		self.factor > 1.0
+			&& ((self.target.0 - self.focus.0).abs() > 1e-4
+				|| (self.target.1 - self.focus.1).abs() > 1e-4)
+	}
+
+	pub fn magnifies(&self, monitor_id: MonitorId) -> bool {
+		self.factor > 1.0 && self.monitor_id.is_none_or(|id| id == monitor_id)
+	}
+
+	/// Applies the zoom transform for `monitor_id` to `canvas`. The caller restores the canvas
+	/// after drawing. Returns `false` when the monitor isn't magnified.
+	pub fn apply(&self, canvas: &Canvas, monitor_id: MonitorId, width: f32, height: f32) -> bool {
+		if !self.magnifies(monitor_id) {
+			return false;
+		}
+		let (fx, fy) = (self.focus.0 * width, self.focus.1 * height);
+		canvas.save();
+		canvas.translate((fx, fy));
+		canvas.scale((self.factor, self.factor));
+		canvas.translate((-fx, -fy));
+		true
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		if self.monitor_id == Some(monitor_id) {
+			self.factor = 1.0;
+			self.monitor_id = None;
+		}
+	}
+}
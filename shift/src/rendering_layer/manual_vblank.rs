@@ -0,0 +1,208 @@
+//! Refreshes of the headless renderer stepped by hand, for tests of the swap, fence and release
+//! handshake that must not depend on timing.
+//!
+//! Given a [`VblankHandle`] with `ShiftRuntimeBuilder::vblank`, the headless renderer no longer
+//! flips on a timer. Each [`VblankHandle::advance`] runs that many refreshes back to back and
+//! reports what every one of them released, presented and flipped, in the order the server was
+//! told. Commands the renderer received before a step are handled before it, and commands that
+//! arrive during one wait for the next; a swap is only sure to have arrived once its session got
+//! `buffer_request_ack`. Refresh times come from a clock that starts with the renderer and moves
+//! by one refresh interval per refresh, so presentation feedback is as regular as the steps.
+
+use std::sync::{Arc, Mutex};
+
+use tab_protocol::BufferIndex;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+/// One refresh of the headless renderer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vblank {
+	/// Counts refreshes from 1.
+	pub sequence: u64,
+	/// When the refresh happened on the renderer's clock, in `CLOCK_MONOTONIC` nanoseconds.
+	pub timestamp_ns: u64,
+	/// Buffers handed back to their sessions, replaced by a swap since the previous refresh.
+	pub released: Vec<VblankBuffer>,
+	/// Buffers on screen since this refresh.
+	pub presented: Vec<VblankBuffer>,
+	/// Monitors that flipped; empty when no swap came since the previous refresh.
+	pub flipped: Vec<MonitorId>,
+}
+
+/// A session's buffer on a monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VblankBuffer {
+	pub monitor_id: MonitorId,
+	pub session_id: SessionId,
+	pub buffer: BufferIndex,
+}
+
+#[derive(Debug, Error)]
+pub enum VblankError {
+	#[error("no headless renderer is stepped with this handle")]
+	Detached,
+	#[error("the headless renderer stopped")]
+	Stopped,
+}
+
+/// Request for `frames` refreshes.
+pub(super) struct Step {
+	pub frames: u32,
+	pub reply: oneshot::Sender<Vec<Vblank>>,
+}
+
+/// Steps the refreshes of the headless renderer it is given to; clones step the same one.
+#[derive(Clone)]
+pub struct VblankHandle {
+	steps: mpsc::UnboundedSender<Step>,
+	/// Taken by the renderer.
+	receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<Step>>>>,
+}
+
+impl Default for VblankHandle {
+	fn default() -> Self {
+		let (steps, receiver) = mpsc::unbounded_channel();
+		Self {
+			steps,
+			receiver: Arc::new(Mutex::new(Some(receiver))),
+		}
+	}
+}
+
+impl VblankHandle {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Runs `frames` refreshes and returns them in order.
+	pub async fn advance(&self, frames: u32) -> Result<Vec<Vblank>, VblankError> {
+		if self
+			.receiver
+			.lock()
+			.is_ok_and(|receiver| receiver.is_some())
+		{
+			return Err(VblankError::Detached);
+		}
+		let (reply, vblanks) = oneshot::channel();
+		self
+			.steps
+			.send(Step { frames, reply })
+			.map_err(|_| VblankError::Stopped)?;
+		vblanks.await.map_err(|_| VblankError::Stopped)
+	}
+
+	/// The steps to run, for the one renderer that gets them.
+	pub(super) fn take_steps(&self) -> Option<mpsc::UnboundedReceiver<Step>> {
+		self.receiver.lock().ok()?.take()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tab_protocol::FramebufferLinkPayload;
+
+	use super::*;
+	use crate::{
+		comms::{render2server::RenderEvt, server2render::RenderCmd, trace::FrameId},
+		rendering_layer::{HeadlessMonitor, HeadlessRenderer, channels::Channels},
+	};
+
+	#[test]
+	fn steps_report_releases_and_presents_in_order() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+			.expect("runtime");
+		runtime.block_on(async {
+			let (server_end, rendering_end) = Channels::new().split();
+			let (mut events, commands) = server_end.into_parts();
+			let vblank = VblankHandle::new();
+			assert!(matches!(
+				vblank.advance(1).await,
+				Err(VblankError::Detached)
+			));
+			let renderer =
+				HeadlessRenderer::new(rendering_end, &[HeadlessMonitor::default()]).manual_vblank(&vblank);
+			tokio::spawn(renderer.run());
+			let Some(RenderEvt::Started { monitors }) = events.recv().await else {
+				panic!("renderer didn't start");
+			};
+			let monitor_id = monitors[0].id;
+			let session_id: SessionId = "se_1".parse().expect("session id");
+			let buffer = |buffer| VblankBuffer {
+				monitor_id,
+				session_id,
+				buffer,
+			};
+			commands
+				.send(RenderCmd::FramebufferLink {
+					payload: FramebufferLinkPayload {
+						monitor_id,
+						width: 1920,
+						height: 1080,
+						stride: 1920 * 4,
+						offset: 0,
+						fourcc: 0x34325258,
+						hdr_metadata: None,
+						extra_planes: Vec::new(),
+						modifier: None,
+						scale: None,
+						alpha_mode: None,
+					},
+					dma_bufs: [Vec::new(), Vec::new()],
+					session_id,
+				})
+				.await
+				.expect("renderer alive");
+			let mut swap = async |index: BufferIndex| {
+				commands
+					.send(RenderCmd::SwapBuffers {
+						monitor_id,
+						buffer: index,
+						session_id,
+						acquire_fence: None,
+						present_at: None,
+						frame_id: FrameId::next(),
+					})
+					.await
+					.expect("renderer alive");
+				// Past the feedback and flips of earlier refreshes.
+				loop {
+					match events.recv().await {
+						Some(RenderEvt::BufferRequestAck { .. }) => break,
+						Some(RenderEvt::BufferRequestRejected { reason, .. }) => panic!("{reason}"),
+						Some(_) => {}
+						None => panic!("renderer stopped"),
+					}
+				}
+			};
+
+			swap(BufferIndex::Zero).await;
+			let first = vblank.advance(1).await.expect("stepped");
+			assert_eq!(first[0].presented, vec![buffer(BufferIndex::Zero)]);
+			assert!(first[0].released.is_empty());
+
+			swap(BufferIndex::One).await;
+			let next = vblank.advance(2).await.expect("stepped");
+			assert_eq!(next[0].released, vec![buffer(BufferIndex::Zero)]);
+			assert_eq!(next[0].presented, vec![buffer(BufferIndex::One)]);
+			assert_eq!(next[0].flipped, vec![monitor_id]);
+			assert_eq!(next[1].flipped, Vec::new(), "nothing was swapped");
+			assert_eq!(
+				next
+					.iter()
+					.map(|vblank| vblank.sequence)
+					.collect::<Vec<_>>(),
+				[2, 3]
+			);
+			assert_eq!(
+				next[1].timestamp_ns - first[0].timestamp_ns,
+				2 * (1_000_000_000 / 60),
+				"every refresh moves the clock by one refresh interval"
+			);
+		});
+	}
+}
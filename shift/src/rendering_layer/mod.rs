@@ -1,50 +1,137 @@
 #![allow(dead_code)]
 
 mod animation;
+mod backend;
+mod buffer_hash;
+mod capture;
 pub mod channels;
+mod color_filter;
+mod color_profile;
 mod commands;
+mod commit_policy;
+mod cursor;
+mod damage;
+mod device_recovery;
 pub mod dmabuf_import;
 mod egl;
 mod fence_runtime;
 mod fence_scheduler;
+mod flip_groups;
+mod formats;
+mod frame_pacing;
+mod frame_stats;
+mod gl_context;
+mod gles;
+#[cfg(test)]
+mod golden_tests;
+mod hdr;
+mod headless;
+mod idle;
+mod magnifier;
+mod manual_vblank;
+mod modes;
+mod monitor_scale;
+mod night_light;
 mod ownership;
+mod pixel_formats;
+mod present_queue;
+mod presentation_feedback;
+mod recovery;
 mod render_core;
+mod resource_cache;
+mod scanout;
+mod scene;
+mod screencast;
+mod skia_backend;
+mod snapshots;
+mod software;
 mod state;
+mod submit_fence;
 mod surface_cache;
+mod switcher;
+mod sync_file;
+mod tiling;
+mod virtual_monitors;
+mod vrr;
+mod wallpaper;
+mod watermark;
 
-use easydrm::EasyDRM;
-use skia_safe::gpu;
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
+	sync::Arc,
 	time::{Duration, Instant as StdInstant},
 };
 #[cfg(debug_assertions)]
 use std::{fs, time::Instant};
 use thiserror::Error;
 use tokio::sync::mpsc;
-use tracing::warn;
+use tracing::{Instrument, warn};
 
 use crate::comms::server2render::SessionTransition;
 use crate::{
 	comms::{
 		render2server::{RenderEvt, RenderEvtTx},
-		server2render::RenderCmdRx,
+		server2render::{RenderCmd, RenderCmdRx},
+		trace::FrameId,
 	},
 	monitor::{Monitor as ServerLayerMonitor, MonitorId},
 	sessions::SessionId,
 };
 use animation::AnimationRegistry;
+use backend::{BackendError, BackendOutput};
+use buffer_hash::BufferHashes;
+use capture::Captures;
 use channels::RenderingEnd;
+use color_filter::ColorFilters;
+use color_profile::ColorProfiles;
+use commit_policy::{CommitDecision, CommitPolicy};
+use cursor::Cursor;
+use damage::FrameDamage;
+use device_recovery::{BackendFailures, Recovery};
 use dmabuf_import::SkiaDmaBufTexture;
 use fence_scheduler::{FenceScheduler, FenceTaskHandle, FenceWaitMode};
+use flip_groups::FlipGroups;
+use frame_pacing::FramePacing;
+use frame_stats::FrameStatsTracker;
+use gl_context::GlDebug;
+use hdr::HdrOutputs;
+use idle::IdleOutputs;
+use magnifier::Magnifier;
+use monitor_scale::MonitorScales;
+use night_light::NightLight;
 use ownership::OwnershipManager;
-use state::{FenceEvent, SlotKey};
+use present_queue::PresentQueue;
+use presentation_feedback::PresentationFeedback;
+use recovery::RecoveryUi;
+use resource_cache::ResourceCache;
+use scanout::DirectScanout;
+use scene::OverlayRegions;
+use screencast::Screencasts;
+use skia_backend::SkiaContext;
+use snapshots::SessionSnapshots;
+use state::{FenceEvent, SlotKey, SlotOwner};
+use submit_fence::SubmitFences;
 use surface_cache::{MonitorRenderState, current_framebuffer_binding};
+use switcher::SessionSwitcher;
+use tiling::TileLayout;
+use virtual_monitors::VirtualMonitors;
+use vrr::VrrOutputs;
+use wallpaper::Wallpapers;
+use watermark::Watermark;
+
+pub use backend::{DrmBackend, RenderBackend};
+pub use color_profile::DisplayProfile;
+pub use headless::{HeadlessMonitor, HeadlessRenderer};
+pub use manual_vblank::{Vblank, VblankBuffer, VblankError, VblankHandle};
+pub use software::SoftwareDisplay;
+
+/// How often a static renderer wakes up to report frame stats.
+const STATIC_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Error)]
 pub enum RenderError {
-	#[error("easydrm error: {0}")]
-	EasyDrmError(#[from] easydrm::EasyDRMError),
+	#[error("render backend error: {0}")]
+	Backend(#[from] BackendError),
 
 	#[error("skia GL interface creation failed")]
 	SkiaGlInterface,
@@ -60,26 +147,112 @@ pub enum RenderError {
 	OpenFdGuardExceeded { count: usize, limit: usize },
 }
 
-pub struct RenderingLayer {
-	drm: EasyDRM<MonitorRenderState>,
-	gr: gpu::DirectContext,
+/// A GPU opened with [`RenderingLayer::open`], with its Skia context.
+pub struct GpuDevice<B: RenderBackend> {
+	backend: B,
+	skia: SkiaContext,
+}
+
+pub struct RenderingLayer<B: RenderBackend = DrmBackend> {
+	backend: B,
+	skia: SkiaContext,
 	command_rx: Option<RenderCmdRx>,
 	event_tx: RenderEvtTx,
 	known_monitors: HashMap<MonitorId, ServerLayerMonitor>,
 	ownership: OwnershipManager,
 	slots: HashMap<SlotKey, SkiaDmaBufTexture>,
+	buffer_hashes: BufferHashes,
+	/// Linked buffers kept for direct scanout, and the releases waiting on a page flip.
+	scanout: DirectScanout,
+	/// Request that last presented each buffer, reported with its latch and release.
+	frame_ids: HashMap<SlotKey, FrameId>,
+	/// Last presented buffer of a session that linked new buffers, shown until one of the new
+	/// buffers is presented so a relink (e.g. after a resize) doesn't flash black.
+	retired_slots: HashMap<(MonitorId, SessionId), SkiaDmaBufTexture>,
+	/// Copies of the frames sessions showed when they went to sleep, drawn when their buffers
+	/// can't be.
+	session_snapshots: SessionSnapshots,
+	/// Session removed while the running transition started from it; its last frames stay in
+	/// `retired_slots` until the transition ends.
+	departed_session: Option<SessionId>,
+	/// Why the last framebuffer link of a surface failed, given as the reason when the session
+	/// requests one of its buffers.
+	link_failures: HashMap<(MonitorId, SessionId), Arc<str>>,
 	fence_event_tx: mpsc::UnboundedSender<FenceEvent>,
 	fence_event_rx: mpsc::UnboundedReceiver<FenceEvent>,
 	fence_scheduler: FenceScheduler,
 	fence_tasks: HashMap<SlotKey, FenceTaskHandle>,
+	/// Pending buffers that must not be shown before their target time.
+	present_queue: PresentQueue,
+	/// Latched buffers waiting for the flip that shows them, reported to their sessions.
+	presentation_feedback: PresentationFeedback,
 	animations: AnimationRegistry,
 	active_transition: Option<ActiveTransition>,
+	frame_stats: FrameStatsTracker,
+	gl_debug: GlDebug,
+	frame_pacing: FramePacing,
+	color_filters: ColorFilters,
+	color_profiles: ColorProfiles,
+	magnifier: Magnifier,
+	/// Outputs show black instead of sessions while the server reports idle.
+	blanked: bool,
+	/// Connected monitors the server asked not to compose.
+	disabled_monitors: HashSet<MonitorId>,
+	/// Logical monitors drawn rotated or flipped. Tiled monitors are drawn upright.
+	output_transforms: HashMap<MonitorId, tab_protocol::OutputTransform>,
+	/// Monitors composed at another resolution than their mode's, relative to it.
+	render_resolutions: HashMap<MonitorId, tab_protocol::MonitorScale>,
+	monitor_scales: MonitorScales,
+	watermark: Watermark,
+	wallpapers: Wallpapers,
+	recovery: RecoveryUi,
+	switcher: SessionSwitcher,
+	cursor: Cursor,
+	captures: Captures,
+	/// Offscreen monitors composed for one session each; not among `known_monitors`.
+	virtual_monitors: VirtualMonitors,
+	/// PipeWire streams of monitors the server started casting.
+	screencasts: Screencasts,
+	/// Stable monitor id per connector, assigned once when it appears and kept while it stays
+	/// connected.
+	stable_ids: HashMap<u32, MonitorId>,
+	/// Connectors grouped into tiled logical monitors, refreshed on every monitor sync.
+	tiles: TileLayout,
+	commit_policy: CommitPolicy,
+	/// Outputs with nothing new to show, which keep their frame.
+	idle: IdleOutputs,
+	/// What each output's framebuffers missed, so frames redraw only that.
+	frame_damage: FrameDamage,
+	hdr: HdrOutputs,
+	vrr: VrrOutputs,
+	flip_groups: FlipGroups,
+	night_light: NightLight,
+	render_quality: HashMap<SessionId, tab_protocol::RenderQualityPayload>,
+	overlays: OverlayRegions,
+	submit_fences: SubmitFences,
+	/// Fourccs EGL imports on this device, reported with every monitor.
+	importable_formats: Vec<u32>,
+	/// Explicit modifiers EGL imports for those formats, reported with every monitor.
+	importable_modifiers: Vec<tab_protocol::FormatModifiers>,
+	/// Primary plane formats per connector-level monitor, read once when it appears.
+	plane_formats: HashMap<MonitorId, Option<Vec<u32>>>,
+	/// Modes each connector offers, read once when it appears.
+	connector_modes: HashMap<u32, Vec<drm::control::Mode>>,
+	output_depth: formats::OutputDepth,
+	resource_cache: ResourceCache,
+	/// Sync files exported after each monitor's submit in the current frame.
+	frame_fences: Vec<std::os::fd::OwnedFd>,
 	#[cfg(debug_assertions)]
 	fd_guard_limit: usize,
 	#[cfg(debug_assertions)]
 	fd_guard_last_check: Instant,
 }
 
+enum DeviceExit {
+	Shutdown,
+	Lost(BackendError),
+}
+
 #[derive(Debug, Clone)]
 struct ActiveTransition {
 	from_session_id: SessionId,
@@ -112,35 +285,106 @@ impl ActiveTransition {
 	}
 }
 
-impl RenderingLayer {
+impl<B: RenderBackend> RenderingLayer<B> {
 	#[tracing::instrument(skip_all)]
-	pub fn init(channels: RenderingEnd) -> Result<Self, RenderError> {
+	/// Opens the GPU to render on. Split from [`Self::start`] so that when this fails, the
+	/// channels are left for the software renderer.
+	pub fn open() -> Result<GpuDevice<B>, RenderError> {
+		// Enumerate GPUs now, so the log shows them next to the device EasyDRM opens rather
+		// than at the first client's `auth_ok`.
+		crate::gpu::selected();
+		let (backend, skia) = Self::open_device()?;
+		Ok(GpuDevice { backend, skia })
+	}
+
+	pub fn start(device: GpuDevice<B>, channels: RenderingEnd) -> Self {
 		let (command_rx, event_tx) = channels.into_parts();
-		let drm =
-			EasyDRM::init(|req| MonitorRenderState::new(req).expect("MonitorRenderState::new failed"))?;
-		drm
+		Self::from_device(device.backend, device.skia, Some(command_rx), event_tx)
+	}
+
+	fn open_device() -> Result<(B, SkiaContext), RenderError> {
+		let backend = B::open()?;
+		backend
 			.make_current()
 			.map_err(|_| RenderError::SkiaGlInterface)?;
-		let interface = gpu::gl::Interface::new_load_with(|s| drm.get_proc_address(s))
-			.ok_or(RenderError::SkiaGlInterface)?;
-		let gr =
-			gpu::direct_contexts::make_gl(interface, None).ok_or(RenderError::SkiaDirectContext)?;
+		let skia = SkiaContext::open(&backend.proc_loader())?;
+		Ok((backend, skia))
+	}
+
+	fn from_device(
+		backend: B,
+		mut skia: SkiaContext,
+		command_rx: Option<RenderCmdRx>,
+		event_tx: RenderEvtTx,
+	) -> Self {
 		let (fence_event_tx, fence_event_rx) = mpsc::unbounded_channel();
+		let resource_cache = ResourceCache::configure(&mut skia);
+		let proc_loader = backend.proc_loader();
+		let submit_fences = SubmitFences::load(&proc_loader);
+		let importable_formats = formats::query_importable(&proc_loader);
+		let importable_modifiers = formats::query_modifiers(&proc_loader, &importable_formats);
 
-		Ok(Self {
-			drm,
-			gr,
-			command_rx: Some(command_rx),
+		Self {
+			backend,
+			skia,
+			command_rx,
 			event_tx,
 			known_monitors: HashMap::new(),
 			ownership: OwnershipManager::new(),
 			slots: HashMap::new(),
+			buffer_hashes: BufferHashes::from_env(),
+			scanout: DirectScanout::from_env(),
+			frame_ids: HashMap::new(),
+			retired_slots: HashMap::new(),
+			session_snapshots: SessionSnapshots::from_env(),
+			departed_session: None,
+			link_failures: HashMap::new(),
 			fence_event_tx,
 			fence_event_rx,
-			fence_scheduler: FenceScheduler::new(),
+			fence_scheduler: FenceScheduler::from_env(),
 			fence_tasks: HashMap::new(),
+			present_queue: PresentQueue::default(),
+			presentation_feedback: PresentationFeedback::default(),
 			animations: AnimationRegistry::new(),
 			active_transition: None,
+			frame_stats: FrameStatsTracker::from_env(),
+			gl_debug: GlDebug::load(&proc_loader),
+			frame_pacing: FramePacing::from_env(),
+			color_filters: ColorFilters::default(),
+			color_profiles: ColorProfiles::from_env(),
+			magnifier: Magnifier::default(),
+			blanked: false,
+			disabled_monitors: HashSet::new(),
+			output_transforms: HashMap::new(),
+			render_resolutions: HashMap::new(),
+			monitor_scales: MonitorScales::default(),
+			watermark: Watermark::default(),
+			wallpapers: Wallpapers::from_env(),
+			recovery: RecoveryUi::default(),
+			switcher: SessionSwitcher::default(),
+			cursor: Cursor::from_env(),
+			captures: Captures::default(),
+			virtual_monitors: VirtualMonitors::default(),
+			screencasts: Screencasts::default(),
+			stable_ids: HashMap::new(),
+			tiles: TileLayout::default(),
+			commit_policy: CommitPolicy::from_env(),
+			idle: IdleOutputs::default(),
+			frame_damage: FrameDamage::load(&proc_loader),
+			hdr: HdrOutputs::default(),
+			vrr: VrrOutputs::from_env(),
+			flip_groups: FlipGroups::default(),
+			night_light: NightLight::from_env(),
+			render_quality: HashMap::new(),
+			overlays: OverlayRegions::default(),
+			submit_fences,
+			importable_formats,
+			importable_modifiers,
+			plane_formats: HashMap::new(),
+			connector_modes: HashMap::new(),
+			output_depth: formats::OutputDepth::from_env(),
+			resource_cache,
+			frame_fences: Vec::new(),
 			#[cfg(debug_assertions)]
 			fd_guard_limit: std::env::var("SHIFT_MAX_OPEN_FDS")
 				.ok()
@@ -148,7 +392,7 @@ impl RenderingLayer {
 				.unwrap_or(4096),
 			#[cfg(debug_assertions)]
 			fd_guard_last_check: Instant::now(),
-		})
+		}
 	}
 
 	#[tracing::instrument(skip_all)]
@@ -157,39 +401,169 @@ impl RenderingLayer {
 			.command_rx
 			.take()
 			.expect("render command channel missing");
-		let current = self.collect_monitors();
-		self
-			.emit_event(RenderEvt::Started {
-				monitors: current.clone(),
-			})
-			.await;
-		self.known_monitors = current.into_iter().map(|m| (m.id, m)).collect();
+		let monitors = self.publish_monitors();
+		self.emit_event(RenderEvt::Started { monitors }).await;
 
-		'e: loop {
+		loop {
+			match self.run_device(&mut command_rx).await? {
+				DeviceExit::Shutdown => {
+					self.shutdown_fences().await;
+					break;
+				}
+				DeviceExit::Lost(error) => {
+					warn!("DRM device lost, tearing down renderer: {error}");
+					let event_tx = self.event_tx.clone();
+					let mut color_filters = std::mem::take(&mut self.color_filters);
+					self.teardown();
+					let _ = event_tx
+						.send(RenderEvt::DeviceLost {
+							reason: error.to_string().into(),
+						})
+						.await;
+					let recovery =
+						Self::recover_device(&mut command_rx, event_tx.clone(), &mut color_filters).await;
+					let restored = match recovery {
+						Some(Recovery::Device(restored)) => restored,
+						Some(Recovery::Software(display)) => {
+							HeadlessRenderer::software(RenderingEnd::new(command_rx, event_tx), display)
+								.after_device_loss()
+								.run()
+								.await;
+							break;
+						}
+						None => break,
+					};
+					self = restored;
+					self.color_filters = color_filters;
+					let monitors = self.publish_monitors();
+					self
+						.emit_event(RenderEvt::DeviceRestored { monitors })
+						.await;
+				}
+			}
+		}
+
+		warn!("shutting down renderer");
+		Ok(())
+	}
+
+	/// Drives the current device until shutdown or until the device is lost. Backend errors
+	/// while the device is still there are logged and retried.
+	async fn run_device(&mut self, command_rx: &mut RenderCmdRx) -> Result<DeviceExit, RenderError> {
+		let mut failures = BackendFailures::default();
+		loop {
 			#[cfg(debug_assertions)]
 			self.check_open_fd_guard()?;
-			let committed_any = self.render_and_commit().await?;
+			self.sync_night_light();
+			// Stream buffers come back on PipeWire's schedule; a static renderer sends the
+			// frames waiting for them on its next poll.
+			if self.screencasts.waiting() {
+				self.commit_policy.damage();
+			}
+			let animating = self.active_transition.is_some() || self.magnifier.is_panning();
+			let committed_any = match self.commit_policy.next_frame(animating) {
+				CommitDecision::Commit { resumed } => {
+					if resumed {
+						tracing::debug!("output damaged, resuming commits");
+						self.frame_stats.leave_static(StdInstant::now());
+					}
+					match self.render_and_commit().await {
+						Ok(committed_any) => {
+							failures.succeeded();
+							committed_any
+						}
+						Err(RenderError::Backend(e)) => {
+							if failures.failed(self.backend.is_removed()) {
+								return Ok(DeviceExit::Lost(e));
+							}
+							warn!("commit failed, retrying: {e}");
+							self.commit_policy.damage();
+							false
+						}
+						Err(e) => return Err(e),
+					}
+				}
+				CommitDecision::Skip { entered } => {
+					let now = StdInstant::now();
+					if entered {
+						tracing::debug!("scene static, pausing commits");
+						self.frame_stats.enter_static(now);
+					}
+					self.emit_due_frame_stats(now).await;
+					false
+				}
+			};
+			let idle_wait = if self.commit_policy.is_static() {
+				STATIC_POLL_INTERVAL
+			} else {
+				Duration::from_millis(2)
+			};
+			// Covers only the wait for the device's events, not the commands handled meanwhile.
+			let page_flip_wait = tracing::info_span!("page_flip_wait", committed_any);
 
+			// Set after a page flip while late-latching; composing waits until then.
+			let mut latch_at = None;
 			'l: loop {
+				let present_deadline = self.present_queue.next_deadline();
+				let cursor_due = self.cursor.resample_at(self.cursor_refresh_rate());
 				tokio::select! {
-					cmd = command_rx.recv() => {
+					cmd = command_rx.recv_traced() => {
 						if let Some(cmd) = cmd {
+							// Buffer swaps damage only their monitor, when the buffer is latched.
+							let swap = matches!(cmd.cmd, RenderCmd::SwapBuffers { .. });
+							let cursor_moved = matches!(cmd.cmd, RenderCmd::MoveCursor { .. });
 							if !self.handle_command(cmd).await? {
-								break 'e;
+								return Ok(DeviceExit::Shutdown);
+							}
+							if cursor_moved {
+								if !self.move_due_cursor() {
+									continue 'l;
+								}
+							} else {
+								self.commit_policy.damage();
+								if !swap {
+									self.idle.damage_all();
+								}
+							}
+							if self.compose_on_damage(latch_at.is_some()) {
+								break 'l;
 							}
 						} else {
 							warn!("server→renderer channel closed, shutting down renderer");
-							break 'e;
+							return Ok(DeviceExit::Shutdown);
 						}
 					}
-					result = self.drm.poll_events_async() => {
-						result?;
+					result = self.backend.poll_events().instrument(page_flip_wait.clone()), if latch_at.is_none() => {
+						if let Err(e) = result {
+							if failures.failed(self.backend.is_removed()) {
+								return Ok(DeviceExit::Lost(e));
+							}
+							warn!("polling device events failed, retrying: {e}");
+							break 'l;
+						}
+						self.release_scanned_out().await;
+						self.emit_presentation_feedback().await;
 						self.sync_monitors().await;
+						match self.frame_pacing.latch_delay() {
+							Some(delay) if committed_any => latch_at = Some(StdInstant::now() + delay),
+							_ => break 'l,
+						}
+					}
+					_ = async {
+						match latch_at {
+							Some(at) => tokio::time::sleep_until(tokio::time::Instant::from_std(at)).await,
+							None => std::future::pending::<()>().await,
+						}
+					} => {
 						break 'l;
 					}
 					fence_evt = self.fence_event_rx.recv() => {
 						if let Some(fence_evt) = fence_evt {
 							self.handle_fence_event(fence_evt).await;
+							self.commit_policy.damage();
+							if self.compose_on_damage(latch_at.is_some()) {
+								break 'l;
+							}
 						}
 					}
 					scheduler_ok = self.fence_scheduler.recv_and_run() => {
@@ -197,15 +571,69 @@ impl RenderingLayer {
 							warn!("fence scheduler channel closed");
 						}
 					}
-					_ = tokio::time::sleep(Duration::from_millis(2)), if !committed_any => {
+					_ = async {
+						match present_deadline {
+							Some(deadline) => {
+								tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await
+							}
+							None => std::future::pending::<()>().await,
+						}
+					} => {
+						self.show_due_presents(StdInstant::now()).await;
+						self.commit_policy.damage();
+						if self.compose_on_damage(latch_at.is_some()) {
+							break 'l;
+						}
+					}
+					_ = async {
+						match cursor_due {
+							Some(at) => tokio::time::sleep_until(tokio::time::Instant::from_std(at)).await,
+							None => std::future::pending::<()>().await,
+						}
+					} => {
+						if self.move_due_cursor() && self.compose_on_damage(latch_at.is_some()) {
+							break 'l;
+						}
+					}
+					_ = tokio::time::sleep(idle_wait), if !committed_any => {
 						break 'l;
 					}
 				}
 			}
 		}
+	}
 
-		warn!("shutting down renderer");
-		Ok(())
+	/// Brings every output's night light up to date, when it changed or its schedule may have
+	/// moved on.
+	fn sync_night_light(&mut self) {
+		if !self.night_light.due(StdInstant::now()) {
+			return;
+		}
+		let minute = night_light::local_minute();
+		for mon in self.backend.outputs() {
+			let monitor_id = self.tiles.logical_id(mon.context().id);
+			self
+				.night_light
+				.sync(mon.connector_id(), monitor_id, minute);
+		}
+	}
+
+	/// Whether damage is composed right away instead of after the next page flip: while static
+	/// no flip is coming, and VRR outputs flip as soon as their content changes.
+	fn compose_on_damage(&self, latching: bool) -> bool {
+		self.commit_policy.is_static() || (!latching && self.vrr.any_enabled())
+	}
+
+	/// Records the device's monitors as known and returns them for announcing to the server.
+	fn publish_monitors(&mut self) -> Vec<ServerLayerMonitor> {
+		self.refresh_monitor_ids();
+		self.refresh_tiles();
+		self.refresh_plane_formats();
+		self.refresh_output_capabilities();
+		let current = self.collect_monitors();
+		self.known_monitors = current.iter().map(|m| (m.id, m.clone())).collect();
+		self.refresh_color_profiles();
+		current
 	}
 
 	#[cfg(debug_assertions)]
@@ -234,28 +662,161 @@ impl RenderingLayer {
 		Ok(())
 	}
 
-	pub fn drm(&self) -> &EasyDRM<MonitorRenderState> {
-		&self.drm
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+
+	/// Gives outputs their stable ids in place of the random ones they were created with. Should
+	/// a new output derive the id of a connected one, it gets a suffixed id instead.
+	fn refresh_monitor_ids(&mut self) {
+		let connected: HashSet<u32> = self
+			.backend
+			.outputs()
+			.map(|mon| mon.connector_id())
+			.collect();
+		self
+			.stable_ids
+			.retain(|connector_id, _| connected.contains(connector_id));
+		// Connectors that appear together are assigned in a fixed order, so when two screens
+		// would share an id the same one gets the suffixed id every time.
+		let mut appeared: Vec<u32> = connected
+			.into_iter()
+			.filter(|connector_id| !self.stable_ids.contains_key(connector_id))
+			.collect();
+		appeared.sort_unstable();
+		for connector_id in appeared {
+			let taken: HashSet<MonitorId> = self.stable_ids.values().copied().collect();
+			let id = crate::monitor::read_stable_id(connector_id, |id| taken.contains(&id));
+			self.stable_ids.insert(connector_id, id);
+		}
+		for mon in self.backend.outputs_mut() {
+			let connector_id = mon.connector_id();
+			let id = self.stable_ids[&connector_id];
+			let context = mon.context_mut();
+			if context.id != id {
+				tracing::debug!(monitor_id = %id, runtime_id = %context.runtime_id, connector_id, "assigned monitor id");
+				context.id = id;
+			}
+		}
 	}
 
-	pub fn drm_mut(&mut self) -> &mut EasyDRM<MonitorRenderState> {
-		&mut self.drm
+	/// Re-reads the connectors' `TILE` properties. A tile only counts while it runs at its
+	/// native tile mode; anything else is composed as an ordinary monitor.
+	fn refresh_tiles(&mut self) {
+		let tiles = self.backend.outputs().filter_map(|mon| {
+			let tile = tiling::read_tile_info(mon.connector_id())?;
+			let (width, height) = mon.size();
+			(width == tile.tile_width && height == tile.tile_height).then_some((mon.context().id, tile))
+		});
+		self.tiles = TileLayout::build(tiles.collect::<Vec<_>>());
 	}
 
+	fn refresh_plane_formats(&mut self) {
+		let connected: HashMap<_, _> = self
+			.backend
+			.outputs()
+			.map(|mon| (mon.context().id, mon.connector_id()))
+			.collect();
+		self
+			.plane_formats
+			.retain(|monitor_id, _| connected.contains_key(monitor_id));
+		for (monitor_id, connector_id) in connected {
+			self
+				.plane_formats
+				.entry(monitor_id)
+				.or_insert_with(|| formats::read_scanout(connector_id));
+		}
+		for mon in self.backend.outputs_mut() {
+			let scanout = self.plane_formats.get(&mon.context().id).cloned().flatten();
+			let format = formats::output_format(self.output_depth, scanout.as_deref());
+			mon.context_mut().set_output_format(format);
+		}
+	}
+
+	fn refresh_output_capabilities(&mut self) {
+		let connected: HashSet<u32> = self
+			.backend
+			.outputs()
+			.map(|mon| mon.connector_id())
+			.collect();
+		self
+			.hdr
+			.retain_connectors(|connector_id| connected.contains(&connector_id));
+		self
+			.vrr
+			.retain_connectors(|connector_id| connected.contains(&connector_id));
+		self
+			.night_light
+			.retain_connectors(|connector_id| connected.contains(&connector_id));
+		self
+			.connector_modes
+			.retain(|connector_id, _| connected.contains(connector_id));
+		for connector_id in connected {
+			self.hdr.probe(connector_id);
+			self.vrr.probe(connector_id);
+			self
+				.connector_modes
+				.entry(connector_id)
+				.or_insert_with(|| modes::connector_modes(connector_id));
+		}
+	}
+
+	/// Monitors as the server sees them: secondary tiles are folded into their group's
+	/// top-left monitor, which takes the combined size.
 	fn collect_monitors(&self) -> Vec<ServerLayerMonitor> {
 		self
-			.drm
-			.monitors()
-			.map(MonitorRenderState::get_server_layer_monitor)
+			.backend
+			.outputs()
+			.filter(|mon| !self.tiles.is_secondary_tile(mon.context().id))
+			.map(|mon| {
+				let mut monitor = MonitorRenderState::get_server_layer_monitor(mon);
+				if let Some(placement) = self.tiles.placement(monitor.id) {
+					(monitor.width, monitor.height) = placement.logical_size;
+				} else if let Some(connector_modes) = self.connector_modes.get(&monitor.connector_id) {
+					// Tiles of a group switch modes together, which isn't offered.
+					let current = (monitor.width, monitor.height, monitor.refresh_rate);
+					monitor.modes = modes::describe(connector_modes, current);
+				}
+				let scanout = self.plane_formats.get(&monitor.id).cloned().flatten();
+				(monitor.formats, monitor.scanout_formats) =
+					formats::monitor_formats(&self.importable_formats, scanout.as_deref());
+				monitor.format_modifiers = self.importable_modifiers.clone();
+				monitor.hdr_eotfs = self.hdr.eotfs(monitor.connector_id);
+				monitor.vrr_capable = self.vrr.capable(monitor.connector_id);
+				monitor
+			})
 			.collect()
 	}
 
 	#[tracing::instrument(skip_all)]
 	async fn sync_monitors(&mut self) {
+		self.refresh_monitor_ids();
+		self.refresh_tiles();
+		self.refresh_plane_formats();
+		self.refresh_output_capabilities();
 		let current_list = self.collect_monitors();
 		let mut current_map = HashMap::new();
 		for monitor in current_list {
-			if !self.known_monitors.contains_key(&monitor.id) {
+			let resized = self
+				.known_monitors
+				.get(&monitor.id)
+				.is_some_and(|known| (known.width, known.height) != (monitor.width, monitor.height));
+			if resized {
+				// The mode changed, or a tile group formed or broke up around this monitor. Keep
+				// the linked buffers, scaled, until sessions link buffers of the new size.
+				self.commit_policy.damage();
+				self.idle.damage_all();
+				self
+					.emit_event(RenderEvt::MonitorResized {
+						monitor: monitor.clone(),
+					})
+					.await;
+			} else if !self.known_monitors.contains_key(&monitor.id) {
+				self.commit_policy.damage();
 				self
 					.emit_event(RenderEvt::MonitorOnline {
 						monitor: monitor.clone(),
@@ -271,19 +832,71 @@ impl RenderingLayer {
 			.copied()
 			.collect::<Vec<_>>();
 		for removed_id in removed_ids {
-			self
-				.emit_event(RenderEvt::MonitorOffline {
-					monitor_id: removed_id,
-				})
-				.await;
-			self.cleanup_monitor_slots(removed_id);
+			self.forget_monitor(removed_id).await;
 		}
 		self.known_monitors = current_map;
+		self.refresh_color_profiles();
+	}
+
+	/// Builds the color corrections of monitors that are new or whose profile changed.
+	fn refresh_color_profiles(&mut self) {
+		let monitors: Vec<_> = self
+			.known_monitors
+			.values()
+			.map(|monitor| (monitor.id, monitor.connector_id))
+			.collect();
+		self
+			.color_profiles
+			.refresh(&monitors, &mut self.color_filters);
+	}
+
+	async fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.commit_policy.damage();
+		self
+			.emit_event(RenderEvt::MonitorOffline { monitor_id })
+			.await;
+		self.cleanup_monitor_slots(monitor_id);
+		self.frame_stats.remove_monitor(monitor_id);
+		self.frame_pacing.forget_monitor(monitor_id);
+		self.color_filters.remove_monitor(monitor_id);
+		self.magnifier.remove_monitor(monitor_id);
+		self.wallpapers.remove_monitor(monitor_id);
+		self.cursor.remove_monitor(monitor_id);
+		self.captures.remove_monitor(monitor_id);
+		self.disabled_monitors.remove(&monitor_id);
+		self.output_transforms.remove(&monitor_id);
+		self.render_resolutions.remove(&monitor_id);
+		self.monitor_scales.remove_monitor(monitor_id);
+		self.vrr.remove_monitor(monitor_id);
+		self.idle.remove_monitor(monitor_id);
+		self.flip_groups.remove_monitor(monitor_id);
+		self.night_light.remove_monitor(monitor_id);
+		self.session_snapshots.remove_monitor(monitor_id);
+		self.presentation_feedback.remove_monitor(monitor_id);
 	}
 
 	fn cleanup_monitor_slots(&mut self, monitor_id: MonitorId) {
 		self.slots.retain(|key, _| key.monitor_id != monitor_id);
+		self
+			.buffer_hashes
+			.retain(|key| key.monitor_id != monitor_id);
+		self.scanout.retain(|key| key.monitor_id != monitor_id);
+		self.scanout.remove_output(monitor_id);
+		self.cursor.remove_output(monitor_id);
+		self.frame_damage.forget(monitor_id);
+		self.captures.remove_output(monitor_id);
+		self.frame_ids.retain(|key, _| key.monitor_id != monitor_id);
+		self
+			.retired_slots
+			.retain(|(retired_monitor, _), _| *retired_monitor != monitor_id);
+		self
+			.link_failures
+			.retain(|(failed_monitor, _), _| *failed_monitor != monitor_id);
 		self.ownership.cleanup_monitor(monitor_id);
+		self.hdr.remove_monitor(monitor_id);
+		self
+			.present_queue
+			.retain(|key| key.monitor_id != monitor_id);
 		let remove = self
 			.fence_tasks
 			.keys()
@@ -295,9 +908,96 @@ impl RenderingLayer {
 		}
 	}
 
+	/// Removes `session_id`, keeping the frames it last showed when the running transition
+	/// starts from it.
+	fn remove_session(&mut self, session_id: SessionId) {
+		let departing = self
+			.active_transition
+			.as_ref()
+			.is_some_and(|transition| transition.from_session_id == session_id);
+		let mut last_frames = Vec::new();
+		if departing {
+			for &monitor_id in self.known_monitors.keys() {
+				let shown = self
+					.ownership
+					.current_slot_key_for_session(monitor_id, session_id)
+					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
+					.and_then(|key| self.slots.remove(&key));
+				if let Some(texture) =
+					shown.or_else(|| self.retired_slots.remove(&(monitor_id, session_id)))
+				{
+					last_frames.push(((monitor_id, session_id), texture));
+				}
+			}
+		}
+		self.cleanup_session_slots(session_id);
+		if departing {
+			self.retired_slots.extend(last_frames);
+			self.departed_session = Some(session_id);
+		}
+	}
+
+	/// Copies the frames `session_id` shows, as it goes to sleep.
+	fn snapshot_session(&mut self, session_id: SessionId) {
+		if !self.session_snapshots.enabled() {
+			return;
+		}
+		if let Err(e) = self.virtual_monitors.make_current(&self.backend) {
+			tracing::warn!(%session_id, "failed to make the shared context current: {e}");
+			return;
+		}
+		let monitor_ids: Vec<_> = self.known_monitors.keys().copied().collect();
+		for monitor_id in monitor_ids {
+			if let Some(image) = Self::session_image(
+				&mut self.slots,
+				&mut self.retired_slots,
+				&self.session_snapshots,
+				&self.ownership,
+				&mut self.skia,
+				monitor_id,
+				session_id,
+			) {
+				self
+					.session_snapshots
+					.take(&mut self.skia, monitor_id, session_id, &image);
+			}
+		}
+	}
+
+	/// Ends the running transition, dropping the last frames of a session removed during it.
+	fn end_transition(&mut self) {
+		self.active_transition = None;
+		if let Some(session_id) = self.departed_session.take() {
+			self
+				.retired_slots
+				.retain(|(_, retired_session), _| *retired_session != session_id);
+		}
+	}
+
 	fn cleanup_session_slots(&mut self, session_id: SessionId) {
 		self.slots.retain(|key, _| key.session_id != session_id);
+		self
+			.buffer_hashes
+			.retain(|key| key.session_id != session_id);
+		self.scanout.retain(|key| key.session_id != session_id);
+		self.frame_ids.retain(|key, _| key.session_id != session_id);
+		self
+			.retired_slots
+			.retain(|(_, retired_session), _| *retired_session != session_id);
+		self
+			.link_failures
+			.retain(|(_, failed_session), _| *failed_session != session_id);
 		self.ownership.cleanup_session(session_id);
+		self.hdr.remove_session(session_id);
+		self.monitor_scales.remove_session(session_id);
+		self.session_snapshots.remove_session(session_id);
+		self.presentation_feedback.remove_session(session_id);
+		self
+			.present_queue
+			.retain(|key| key.session_id != session_id);
+		self.render_quality.remove(&session_id);
+		self.frame_pacing.forget_session(session_id);
+		self.frame_stats.remove_session(session_id);
 		let remove = self
 			.fence_tasks
 			.keys()
@@ -308,4 +1008,37 @@ impl RenderingLayer {
 			self.cancel_fence_wait(key);
 		}
 	}
+
+	fn cleanup_surface_slots(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self
+			.slots
+			.retain(|key, _| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self
+			.buffer_hashes
+			.retain(|key| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self
+			.scanout
+			.retain(|key| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self
+			.frame_ids
+			.retain(|key, _| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self.retired_slots.remove(&(monitor_id, session_id));
+		self.link_failures.remove(&(monitor_id, session_id));
+		self.ownership.cleanup_surface(monitor_id, session_id);
+		self
+			.presentation_feedback
+			.remove_surface(monitor_id, session_id);
+		self
+			.present_queue
+			.retain(|key| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		let remove = self
+			.fence_tasks
+			.keys()
+			.filter(|key| (key.monitor_id, key.session_id) == (monitor_id, session_id))
+			.copied()
+			.collect::<Vec<_>>();
+		for key in remove {
+			self.cancel_fence_wait(key);
+		}
+	}
 }
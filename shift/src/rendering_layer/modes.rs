@@ -0,0 +1,117 @@
+//! Display modes a connector offers, as monitors list them to clients.
+//!
+//! Connectors list a mode once per timing; modes only differing in timings Shift doesn't expose
+//! (sync polarities, blanking) are listed once, keeping the preferred one. Admins pick a mode
+//! by size and refresh rate with `set_monitor_mode`, and the renderer switches the output to the
+//! first connector mode that matches.
+
+use drm::control::{Device as ControlDevice, Mode, ModeTypeFlags, connector};
+use tab_protocol::MonitorMode;
+
+use crate::drm_card::Card;
+
+/// Size and refresh rate of `mode`.
+pub(super) fn key(mode: &Mode) -> (i32, i32, u32) {
+	let (width, height) = mode.size();
+	(width.into(), height.into(), mode.vrefresh())
+}
+
+/// `modes` as clients see them, `current` being the size and refresh rate the output runs at.
+pub(super) fn describe(modes: &[Mode], current: (i32, i32, u32)) -> Vec<MonitorMode> {
+	list(
+		modes.iter().map(|mode| {
+			let (width, height, refresh_rate) = key(mode);
+			MonitorMode {
+				width,
+				height,
+				refresh_rate,
+				preferred: mode.mode_type().contains(ModeTypeFlags::PREFERRED),
+				current: false,
+			}
+		}),
+		current,
+	)
+}
+
+fn list(
+	modes: impl IntoIterator<Item = MonitorMode>,
+	current: (i32, i32, u32),
+) -> Vec<MonitorMode> {
+	let mut listed: Vec<MonitorMode> = Vec::new();
+	for mut mode in modes {
+		mode.current = (mode.width, mode.height, mode.refresh_rate) == current;
+		match listed.iter_mut().find(|listed| same_mode(listed, &mode)) {
+			Some(listed) => listed.preferred |= mode.preferred,
+			None => listed.push(mode),
+		}
+	}
+	listed
+}
+
+fn same_mode(a: &MonitorMode, b: &MonitorMode) -> bool {
+	(a.width, a.height, a.refresh_rate) == (b.width, b.height, b.refresh_rate)
+}
+
+/// The connector mode that is `width`x`height` at `refresh` Hz, preferring the preferred one.
+pub(super) fn find(modes: &[Mode], width: i32, height: i32, refresh: u32) -> Option<Mode> {
+	let matching = || {
+		modes
+			.iter()
+			.filter(move |mode| key(mode) == (width, height, refresh))
+	};
+	matching()
+		.find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+		.or_else(|| matching().next())
+		.copied()
+}
+
+/// Modes `connector_id` reports, read from whichever card has it.
+pub(super) fn connector_modes(connector_id: u32) -> Vec<Mode> {
+	let Some(handle) = drm::control::from_u32::<connector::Handle>(connector_id) else {
+		return Vec::new();
+	};
+	Card::open_all()
+		.into_iter()
+		.find_map(|(_, card)| card.get_connector(handle, false).ok())
+		.map(|info| info.modes().to_vec())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mode(width: i32, height: i32, refresh_rate: u32, preferred: bool) -> MonitorMode {
+		MonitorMode {
+			width,
+			height,
+			refresh_rate,
+			preferred,
+			current: false,
+		}
+	}
+
+	#[test]
+	fn timings_of_one_mode_are_listed_once() {
+		let listed = list(
+			[
+				mode(1920, 1080, 60, false),
+				mode(1920, 1080, 60, true),
+				mode(1920, 1080, 144, false),
+				mode(1280, 720, 60, false),
+			],
+			(1920, 1080, 144),
+		);
+		assert_eq!(
+			listed,
+			vec![
+				mode(1920, 1080, 60, true),
+				MonitorMode {
+					current: true,
+					..mode(1920, 1080, 144, false)
+				},
+				mode(1280, 720, 60, false),
+			]
+		);
+	}
+}
@@ -0,0 +1,96 @@
+//! Fractional scaling. Sessions are asked to draw at their monitor's scale; buffers linked at
+//! another one, as `FramebufferLinkPayload::scale` says, are drawn resized by the ratio of the
+//! two from the monitor's top left instead of being stretched over it, so their content keeps
+//! the size it would have at the monitor's scale.
+
+use std::collections::HashMap;
+
+use tab_protocol::MonitorScale;
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+#[derive(Debug, Default)]
+pub(super) struct MonitorScales {
+	/// Monitors not at 1x.
+	monitors: HashMap<MonitorId, MonitorScale>,
+	/// Scales sessions linked their buffers at, when they declared one.
+	links: HashMap<(MonitorId, SessionId), MonitorScale>,
+}
+
+impl MonitorScales {
+	pub fn set(&mut self, monitor_id: MonitorId, scale: MonitorScale) {
+		if scale.is_one() {
+			self.monitors.remove(&monitor_id);
+		} else {
+			self.monitors.insert(monitor_id, scale);
+		}
+	}
+
+	pub fn set_link(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		scale: Option<MonitorScale>,
+	) {
+		match scale {
+			Some(scale) => self.links.insert((monitor_id, session_id), scale.clamped()),
+			None => self.links.remove(&(monitor_id, session_id)),
+		};
+	}
+
+	pub fn scale(&self, monitor_id: MonitorId) -> MonitorScale {
+		self.monitors.get(&monitor_id).copied().unwrap_or_default()
+	}
+
+	/// How much `session_id`'s buffers on `monitor_id` are resized by when composed; `None`
+	/// when they are drawn at the monitor's scale and fill it.
+	pub fn buffer_ratio(&self, monitor_id: MonitorId, session_id: SessionId) -> Option<f32> {
+		buffer_ratio(
+			self.scale(monitor_id),
+			self.links.get(&(monitor_id, session_id)).copied(),
+		)
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.monitors.remove(&monitor_id);
+		self
+			.links
+			.retain(|(linked_monitor, _), _| *linked_monitor != monitor_id);
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self
+			.links
+			.retain(|(_, linked_session), _| *linked_session != session_id);
+	}
+}
+
+/// Ratio of `monitor` to `buffer`, unless the buffer is at the monitor's scale.
+pub(super) fn buffer_ratio(monitor: MonitorScale, buffer: Option<MonitorScale>) -> Option<f32> {
+	let buffer = buffer.filter(|buffer| *buffer != monitor)?;
+	Some(monitor.0 as f32 / buffer.0 as f32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn buffers_at_another_scale_are_resized() {
+		let monitor: MonitorId = "mon_1".parse().expect("monitor id");
+		let session: SessionId = "se_1".parse().expect("session id");
+		let mut scales = MonitorScales::default();
+		scales.set(monitor, MonitorScale(180));
+		assert_eq!(scales.buffer_ratio(monitor, session), None);
+		// A legacy client drawing at 1x on a 1.5x monitor.
+		scales.set_link(monitor, session, Some(MonitorScale::ONE));
+		assert_eq!(scales.buffer_ratio(monitor, session), Some(1.5));
+		scales.set_link(monitor, session, Some(MonitorScale(180)));
+		assert_eq!(scales.buffer_ratio(monitor, session), None);
+		scales.set_link(monitor, session, Some(MonitorScale(360)));
+		scales.set(monitor, MonitorScale::ONE);
+		assert_eq!(scales.buffer_ratio(monitor, session), Some(1.0 / 3.0));
+		scales.remove_session(session);
+		assert_eq!(scales.buffer_ratio(monitor, session), None);
+	}
+}
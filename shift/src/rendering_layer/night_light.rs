@@ -0,0 +1,367 @@
+//! Night light: warmer colors by lowering an output's color temperature.
+//!
+//! The warmth is applied by the display hardware, after composition, so it costs no GPU time
+//! and also covers directly scanned out buffers. Each output's CRTC gets a `GAMMA_LUT` scaling
+//! its channels towards the white point of the color temperature, or a diagonal `CTM` on
+//! hardware without a gamma LUT. At [`NightLightSchedule::NEUTRAL_KELVIN`] the property is
+//! cleared again.
+//!
+//! `SHIFT_NIGHT_LIGHT` sets the default, either `<kelvin>` or `<kelvin>@<sunset>-<sunrise>` with
+//! local `HH:MM` times, e.g. `3400@20:30-07:00`; admins change it per monitor with
+//! `set_color_temperature`. Scheduled warmth fades in over [`FADE_MINUTES`] after sunset and
+//! out over as long before sunrise. Like `VRR_ENABLED`, the properties are set directly on the
+//! card, which only the DRM master may do; a refusal is logged and not retried until the
+//! wanted temperature changes.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use chrono::Timelike;
+use drm::control::{Device as ControlDevice, connector, property};
+use tab_protocol::NightLightSchedule;
+
+use crate::{drm_card::Card, monitor::MonitorId};
+
+const NEUTRAL_KELVIN: u32 = NightLightSchedule::NEUTRAL_KELVIN;
+const FADE_MINUTES: f64 = 30.0;
+const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+/// Temperatures are rounded to this many kelvin, small enough that a step isn't noticed and
+/// large enough that a fade doesn't rewrite the LUT on every check.
+const KELVIN_STEP: f64 = 50.0;
+/// How often scheduled temperatures are re-evaluated.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Size of the kernel's `struct drm_color_lut`.
+const LUT_ENTRY_LEN: usize = 8;
+/// Size of the kernel's `struct drm_color_ctm`.
+const CTM_LEN: usize = 9 * 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Setting {
+	kelvin: u32,
+	schedule: Option<NightLightSchedule>,
+}
+
+impl Setting {
+	const OFF: Self = Self {
+		kelvin: NEUTRAL_KELVIN,
+		schedule: None,
+	};
+
+	fn new(kelvin: u32, schedule: Option<NightLightSchedule>) -> Self {
+		Self {
+			kelvin: kelvin.clamp(NightLightSchedule::MIN_KELVIN, NEUTRAL_KELVIN),
+			schedule,
+		}
+	}
+
+	/// The temperature to show `minute` minutes after local midnight.
+	fn kelvin_at(&self, minute: f64) -> u32 {
+		let warmth = self
+			.schedule
+			.map_or(1.0, |schedule| night_progress(schedule, minute));
+		let neutral = f64::from(NEUTRAL_KELVIN);
+		let kelvin = neutral - (neutral - f64::from(self.kelvin)) * warmth;
+		((kelvin / KELVIN_STEP).round() * KELVIN_STEP) as u32
+	}
+}
+
+#[derive(Debug)]
+pub(super) struct NightLight {
+	default: Setting,
+	/// Per-monitor choices that take precedence over `default`.
+	overrides: HashMap<MonitorId, Setting>,
+	/// Last temperature written per connector.
+	applied: HashMap<u32, u32>,
+	next_check: Option<Instant>,
+}
+
+impl NightLight {
+	/// Reads `SHIFT_NIGHT_LIGHT` (default off).
+	pub fn from_env() -> Self {
+		let default = std::env::var("SHIFT_NIGHT_LIGHT")
+			.ok()
+			.and_then(|value| parse(&value))
+			.unwrap_or(Setting::OFF);
+		Self::new(default)
+	}
+
+	fn new(default: Setting) -> Self {
+		Self {
+			default,
+			overrides: HashMap::new(),
+			applied: HashMap::new(),
+			next_check: None,
+		}
+	}
+
+	/// Sets the temperature of `monitor_id`, or of every monitor when `None`, which also drops
+	/// the per-monitor choices.
+	pub fn set(
+		&mut self,
+		monitor_id: Option<MonitorId>,
+		kelvin: u32,
+		schedule: Option<NightLightSchedule>,
+	) {
+		let setting = Setting::new(kelvin, schedule);
+		match monitor_id {
+			Some(monitor_id) => {
+				self.overrides.insert(monitor_id, setting);
+			}
+			None => {
+				self.default = setting;
+				self.overrides.clear();
+			}
+		}
+		self.next_check = None;
+	}
+
+	/// Whether it's time to sync the outputs again: right after a change, then every
+	/// [`CHECK_INTERVAL`] so schedules advance.
+	pub fn due(&mut self, now: Instant) -> bool {
+		if self.next_check.is_some_and(|at| now < at) {
+			return false;
+		}
+		self.next_check = Some(now + CHECK_INTERVAL);
+		true
+	}
+
+	fn wanted(&self, monitor_id: MonitorId, minute: f64) -> u32 {
+		self
+			.overrides
+			.get(&monitor_id)
+			.unwrap_or(&self.default)
+			.kelvin_at(minute)
+	}
+
+	/// Brings `connector_id`'s CRTC in line with the temperature for `monitor_id` at `minute`
+	/// minutes after local midnight.
+	pub fn sync(&mut self, connector_id: u32, monitor_id: MonitorId, minute: f64) {
+		let wanted = self.wanted(monitor_id, minute);
+		match self.applied.get(&connector_id) {
+			Some(applied) if *applied == wanted => return,
+			// Never touched: leave whatever the output was brought up with.
+			None if wanted == NEUTRAL_KELVIN => return,
+			_ => {}
+		}
+		match write_color_temperature(connector_id, wanted) {
+			Ok(()) => tracing::debug!(connector_id, kelvin = wanted, "night light updated"),
+			Err(e) => tracing::warn!(connector_id, "failed to set night light: {e}"),
+		}
+		// Recorded even on failure so a refused property isn't retried every check.
+		self.applied.insert(connector_id, wanted);
+	}
+
+	pub fn retain_connectors(&mut self, connected: impl Fn(u32) -> bool) {
+		self
+			.applied
+			.retain(|connector_id, _| connected(*connector_id));
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.overrides.remove(&monitor_id);
+	}
+}
+
+/// Minutes since local midnight.
+pub(super) fn local_minute() -> f64 {
+	let now = chrono::Local::now();
+	f64::from(now.hour() * 60 + now.minute()) + f64::from(now.second()) / 60.0
+}
+
+/// How far into the night `minute` is: 0 by day, 1 at full warmth, in between while fading.
+fn night_progress(schedule: NightLightSchedule, minute: f64) -> f64 {
+	let sunset = f64::from(schedule.sunset);
+	let night = (f64::from(schedule.sunrise) - sunset).rem_euclid(MINUTES_PER_DAY);
+	let since_sunset = (minute - sunset).rem_euclid(MINUTES_PER_DAY);
+	if night == 0.0 || since_sunset >= night {
+		return 0.0;
+	}
+	let fade = FADE_MINUTES.min(night / 2.0);
+	(since_sunset / fade)
+		.min((night - since_sunset) / fade)
+		.min(1.0)
+}
+
+/// Parses `<kelvin>` or `<kelvin>@HH:MM-HH:MM`.
+fn parse(value: &str) -> Option<Setting> {
+	let value = value.trim();
+	if value.is_empty() {
+		return None;
+	}
+	let (kelvin, schedule) = match value.split_once('@') {
+		Some((kelvin, schedule)) => (kelvin, Some(schedule)),
+		None => (value, None),
+	};
+	let kelvin = kelvin.trim().parse::<u32>().ok();
+	let schedule = schedule.map(|schedule| {
+		let (sunset, sunrise) = schedule.split_once('-')?;
+		Some(NightLightSchedule {
+			sunset: parse_time(sunset)?,
+			sunrise: parse_time(sunrise)?,
+		})
+	});
+	match (kelvin, schedule) {
+		(Some(kelvin), None) => Some(Setting::new(kelvin, None)),
+		(Some(kelvin), Some(Some(schedule))) => Some(Setting::new(kelvin, Some(schedule))),
+		_ => {
+			tracing::warn!(value, "invalid SHIFT_NIGHT_LIGHT, night light off");
+			None
+		}
+	}
+}
+
+/// `HH:MM` as minutes after midnight.
+fn parse_time(time: &str) -> Option<u16> {
+	let (hours, minutes) = time.trim().split_once(':')?;
+	let hours = hours.parse::<u16>().ok().filter(|h| *h < 24)?;
+	let minutes = minutes.parse::<u16>().ok().filter(|m| *m < 60)?;
+	Some(hours * 60 + minutes)
+}
+
+/// Channel gains giving white the color of a black body at `kelvin`, relative to
+/// [`NEUTRAL_KELVIN`], after Tanner Helland's fit of the black body colors.
+pub(super) fn white_point(kelvin: u32) -> [f64; 3] {
+	fn rgb(kelvin: u32) -> [f64; 3] {
+		let t = f64::from(kelvin) / 100.0;
+		let red = if t <= 66.0 {
+			255.0
+		} else {
+			329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+		};
+		let green = if t <= 66.0 {
+			99.470_802_586_1 * t.ln() - 161.119_568_166_1
+		} else {
+			288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+		};
+		let blue = if t >= 66.0 {
+			255.0
+		} else if t <= 19.0 {
+			0.0
+		} else {
+			138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+		};
+		[red, green, blue].map(|channel| channel.clamp(0.0, 255.0))
+	}
+	let white = rgb(kelvin);
+	let neutral = rgb(NEUTRAL_KELVIN);
+	[0, 1, 2].map(|i| (white[i] / neutral[i]).clamp(0.0, 1.0))
+}
+
+/// A `GAMMA_LUT` blob of `size` `struct drm_color_lut` entries ramping each channel up to
+/// `gains`.
+pub(super) fn gamma_lut_blob(size: usize, gains: [f64; 3]) -> Vec<u8> {
+	let mut blob = Vec::with_capacity(size * LUT_ENTRY_LEN);
+	let last = size.saturating_sub(1).max(1) as f64;
+	for i in 0..size {
+		let level = i as f64 / last;
+		for gain in gains {
+			let value = (level * gain * f64::from(u16::MAX)).round() as u16;
+			blob.extend_from_slice(&value.to_ne_bytes());
+		}
+		// `reserved`.
+		blob.extend_from_slice(&0u16.to_ne_bytes());
+	}
+	blob
+}
+
+/// A `CTM` blob: `struct drm_color_ctm`, a row-major 3x3 matrix of S31.32 sign-magnitude
+/// values, here scaling each channel by its gain.
+pub(super) fn ctm_blob(gains: [f64; 3]) -> [u8; CTM_LEN] {
+	let mut blob = [0u8; CTM_LEN];
+	for (channel, gain) in gains.into_iter().enumerate() {
+		let value = (gain * (1u64 << 32) as f64).round() as u64;
+		let offset = (channel * 4) * 8;
+		blob[offset..offset + 8].copy_from_slice(&value.to_ne_bytes());
+	}
+	blob
+}
+
+/// Writes `kelvin` to the CRTC currently driving `connector_id`, through `GAMMA_LUT` when it
+/// has one and `CTM` otherwise.
+fn write_color_temperature(connector_id: u32, kelvin: u32) -> std::io::Result<()> {
+	let not_found = || {
+		std::io::Error::new(
+			std::io::ErrorKind::NotFound,
+			"no CRTC with GAMMA_LUT or CTM driving the connector",
+		)
+	};
+	let handle: connector::Handle = drm::control::from_u32(connector_id).ok_or_else(not_found)?;
+	let gains = white_point(kelvin);
+	for (_, card) in Card::open_all() {
+		let Some(crtc) = card
+			.get_connector(handle, false)
+			.ok()
+			.and_then(|info| info.current_encoder())
+			.and_then(|encoder| card.get_encoder(encoder).ok())
+			.and_then(|encoder| encoder.crtc())
+		else {
+			continue;
+		};
+		let Ok(properties) = card.get_properties(crtc) else {
+			continue;
+		};
+		let (ids, values) = properties.as_props_and_values();
+		let mut gamma_lut = None;
+		let mut lut_size = 0;
+		let mut ctm = None;
+		for (id, value) in ids.iter().zip(values) {
+			let Ok(info) = card.get_property(*id) else {
+				continue;
+			};
+			match info.name().to_bytes() {
+				b"GAMMA_LUT" => gamma_lut = Some(*id),
+				b"GAMMA_LUT_SIZE" => lut_size = *value as usize,
+				b"CTM" => ctm = Some(*id),
+				_ => {}
+			}
+		}
+		let (property_id, blob) = match (gamma_lut, ctm) {
+			(Some(property_id), _) if lut_size > 0 => (property_id, gamma_lut_blob(lut_size, gains)),
+			(_, Some(property_id)) => (property_id, ctm_blob(gains).to_vec()),
+			_ => continue,
+		};
+		let value = if kelvin == NEUTRAL_KELVIN {
+			0
+		} else {
+			match card.create_property_blob(&blob)? {
+				property::Value::Blob(id) => id,
+				_ => return Err(not_found()),
+			}
+		};
+		return card.set_property(crtc, property_id, value);
+	}
+	Err(not_found())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn schedules_fade_across_midnight() {
+		let setting = parse("3400@20:30-07:00").expect("setting");
+		let at = |hours: f64| setting.kelvin_at(hours * 60.0);
+		assert_eq!(at(12.0), NEUTRAL_KELVIN);
+		assert_eq!(at(20.75), 4950, "halfway through the fade");
+		assert_eq!(at(23.0), 3400);
+		assert_eq!(at(3.0), 3400);
+		assert_eq!(at(6.75), 4950);
+		assert_eq!(at(7.0), NEUTRAL_KELVIN);
+		assert_eq!(parse("2000").map(|s| s.kelvin_at(720.0)), Some(2000));
+		assert_eq!(parse("500").map(|s| s.kelvin), Some(1000));
+		assert_eq!(parse("3400@25:00-07:00"), None);
+
+		let gains = white_point(3400);
+		assert_eq!(white_point(NEUTRAL_KELVIN), [1.0; 3]);
+		assert!(gains[0] == 1.0 && gains[1] < 1.0 && gains[2] < gains[1]);
+		let lut = gamma_lut_blob(256, gains);
+		assert_eq!(lut.len(), 256 * LUT_ENTRY_LEN);
+		assert_eq!(lut[255 * LUT_ENTRY_LEN..][..2], u16::MAX.to_ne_bytes());
+		let ctm = ctm_blob([1.0, 0.5, 0.0]);
+		assert_eq!(ctm[..8], (1u64 << 32).to_ne_bytes());
+		assert_eq!(ctm[32..40], (1u64 << 31).to_ne_bytes());
+		assert_eq!(ctm[64..], [0; 8]);
+	}
+}
@@ -45,11 +45,6 @@ impl OwnershipManager {
 		}
 	}
 
-	pub fn current_slot_key(&self, monitor_id: MonitorId) -> Option<SlotKey> {
-		let session_id = self.current_session?;
-		self.current_slot_key_for_session(monitor_id, session_id)
-	}
-
 	pub fn current_slot_key_for_session(
 		&self,
 		monitor_id: MonitorId,
@@ -91,12 +86,14 @@ impl OwnershipManager {
 		self.slot_ownership.insert(key, SlotOwner::ShiftOwned);
 	}
 
+	/// `deferred` keeps the buffer pending until [`Self::apply_acquire_fence_signaled`], for
+	/// acquire fences and queued presents.
 	pub fn apply_swap_request(
 		&mut self,
 		monitor_id: MonitorId,
 		session_id: SessionId,
 		slot: BufferSlot,
-		has_acquire_fence: bool,
+		deferred: bool,
 	) -> SwapApplyResult {
 		let canceled_pending = self
 			.monitor_state
@@ -110,7 +107,7 @@ impl OwnershipManager {
 		let previous = state.current_buffer;
 		state.pending_buffer = Some(slot);
 
-		let previous_to_release = if has_acquire_fence {
+		let previous_to_release = if deferred {
 			None
 		} else {
 			state.current_buffer = Some(slot);
@@ -124,7 +121,9 @@ impl OwnershipManager {
 		}
 	}
 
-	pub fn apply_acquire_fence_signaled(&mut self, key: SlotKey) -> Option<BufferSlot> {
+	/// Makes the pending buffer current. `None` when `key` wasn't pending; otherwise the
+	/// previous buffer to release, if any.
+	pub fn apply_acquire_fence_signaled(&mut self, key: SlotKey) -> Option<Option<BufferSlot>> {
 		let state = self.state_mut(key.monitor_id, key.session_id)?;
 		if state.pending_buffer != Some(key.buffer) {
 			return None;
@@ -132,7 +131,7 @@ impl OwnershipManager {
 		let previous = state.current_buffer;
 		state.current_buffer = Some(key.buffer);
 		state.pending_buffer = None;
-		previous.filter(|prev| *prev != key.buffer)
+		Some(previous.filter(|prev| *prev != key.buffer))
 	}
 
 	pub fn queue_buffer_release(
@@ -167,6 +166,16 @@ impl OwnershipManager {
 		self.monitor_state.retain(|(mon, _), _| *mon != monitor_id);
 	}
 
+	pub fn cleanup_surface(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self
+			.slot_ownership
+			.retain(|key, _| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		self.monitor_state.remove(&(monitor_id, session_id));
+		self
+			.deferred_releases
+			.retain(|item| (item.monitor_id, item.session_id) != (monitor_id, session_id));
+	}
+
 	pub fn cleanup_session(&mut self, session_id: SessionId) {
 		self
 			.slot_ownership
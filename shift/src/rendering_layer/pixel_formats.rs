@@ -0,0 +1,230 @@
+//! The buffer formats Shift composes, and how Skia sees each of them.
+//!
+//! Every fourcc a session may link is listed in [`PIXEL_FORMATS`], with the Skia color type its
+//! imported texture is sampled as, the GL format backing that texture and, for formats a CPU
+//! can read, the Skia color type of its bytes in memory. EGL samples imported RGB buffers in R,
+//! G, B, A order whatever their memory order, so BGRA-native buffers (`XR24`, `AR30`) need no
+//! swizzle once imported; copies made through a CPU mapping go by the memory order instead.
+//! `X` formats sample as Skia's `x` color types, so their padding never reaches blending.
+//! Formats with alpha are opaque too unless their link gives an `alpha_mode`, which picks
+//! whether Skia samples them as premultiplied or straight.
+//!
+//! Formats missing from the table are neither reported to clients nor imported: linking one
+//! fails with `unsupported_format` instead of showing wrong channels.
+
+use skia_safe::{AlphaType, ColorType, gpu::gl::Format as GlFormat};
+use tab_protocol::AlphaMode;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+	u32::from_le_bytes(*code)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct PixelFormat {
+	pub fourcc: u32,
+	/// How Skia samples the imported texture.
+	pub color_type: ColorType,
+	/// Format Skia is told backs the imported texture.
+	pub gl_format: GlFormat,
+	/// Color type of the bytes in memory, for formats a CPU can read.
+	pub memory: Option<ColorType>,
+	/// Sampled through `GL_TEXTURE_EXTERNAL_OES`, with the driver converting to RGB.
+	pub yuv: bool,
+	/// Has an alpha channel.
+	pub alpha: bool,
+}
+
+impl PixelFormat {
+	/// How Skia reads the alpha of buffers in this format linked with `mode`.
+	pub fn alpha_type(&self, mode: Option<AlphaMode>) -> AlphaType {
+		match mode {
+			Some(AlphaMode::Premultiplied) if self.alpha => AlphaType::Premul,
+			Some(AlphaMode::Straight) if self.alpha => AlphaType::Unpremul,
+			_ => AlphaType::Opaque,
+		}
+	}
+}
+
+const fn rgb(
+	code: &[u8; 4],
+	color_type: ColorType,
+	gl_format: GlFormat,
+	memory: Option<ColorType>,
+) -> PixelFormat {
+	PixelFormat {
+		fourcc: fourcc(code),
+		color_type,
+		gl_format,
+		memory,
+		yuv: false,
+		// DRM names formats with alpha `A…` or `…A` (`AR24`, `RA24`).
+		alpha: code[0] == b'A' || code[1] == b'A',
+	}
+}
+
+const fn yuv(code: &[u8; 4]) -> PixelFormat {
+	PixelFormat {
+		fourcc: fourcc(code),
+		color_type: ColorType::RGBA8888,
+		gl_format: GlFormat::RGBA8,
+		memory: None,
+		yuv: true,
+		alpha: false,
+	}
+}
+
+pub(super) const PIXEL_FORMATS: [PixelFormat; 25] = [
+	// 8 bits per channel. DRM names list channels from the most significant bits, so on
+	// little-endian `XRGB8888` is B, G, R, X in memory.
+	rgb(
+		b"XR24",
+		ColorType::RGB888x,
+		GlFormat::RGBA8,
+		Some(ColorType::BGRA8888),
+	),
+	rgb(
+		b"AR24",
+		ColorType::RGBA8888,
+		GlFormat::RGBA8,
+		Some(ColorType::BGRA8888),
+	),
+	rgb(
+		b"XB24",
+		ColorType::RGB888x,
+		GlFormat::RGBA8,
+		Some(ColorType::RGBA8888),
+	),
+	rgb(
+		b"AB24",
+		ColorType::RGBA8888,
+		GlFormat::RGBA8,
+		Some(ColorType::RGBA8888),
+	),
+	rgb(b"RX24", ColorType::RGB888x, GlFormat::RGBA8, None),
+	rgb(b"BX24", ColorType::RGB888x, GlFormat::RGBA8, None),
+	rgb(b"RA24", ColorType::RGBA8888, GlFormat::RGBA8, None),
+	rgb(b"BA24", ColorType::RGBA8888, GlFormat::RGBA8, None),
+	// Packed 24-bit `RGB888` and `BGR888`.
+	rgb(b"RG24", ColorType::RGB888x, GlFormat::RGB8, None),
+	rgb(b"BG24", ColorType::RGB888x, GlFormat::RGB8, None),
+	rgb(
+		b"RG16",
+		ColorType::RGB565,
+		GlFormat::RGB565,
+		Some(ColorType::RGB565),
+	),
+	// 10 bits per channel, as HDR clients render PQ or HLG content.
+	rgb(
+		b"XR30",
+		ColorType::RGB101010x,
+		GlFormat::RGB10_A2,
+		Some(ColorType::BGR101010x),
+	),
+	rgb(
+		b"AR30",
+		ColorType::RGBA1010102,
+		GlFormat::RGB10_A2,
+		Some(ColorType::BGRA1010102),
+	),
+	rgb(
+		b"XB30",
+		ColorType::RGB101010x,
+		GlFormat::RGB10_A2,
+		Some(ColorType::RGB101010x),
+	),
+	rgb(
+		b"AB30",
+		ColorType::RGBA1010102,
+		GlFormat::RGB10_A2,
+		Some(ColorType::RGBA1010102),
+	),
+	// Half float per channel; Skia has no BGR order for it.
+	rgb(b"XR4H", ColorType::RGBAF16, GlFormat::RGBA16F, None),
+	rgb(b"AR4H", ColorType::RGBAF16, GlFormat::RGBA16F, None),
+	rgb(
+		b"XB4H",
+		ColorType::RGBAF16,
+		GlFormat::RGBA16F,
+		Some(ColorType::RGBAF16),
+	),
+	rgb(
+		b"AB4H",
+		ColorType::RGBAF16,
+		GlFormat::RGBA16F,
+		Some(ColorType::RGBAF16),
+	),
+	// YUV layouts decoders hand out: semi-planar NV12/NV21/P010, planar YUV420/YVU420 and
+	// packed YUYV.
+	yuv(b"NV12"),
+	yuv(b"NV21"),
+	yuv(b"P010"),
+	yuv(b"YU12"),
+	yuv(b"YV12"),
+	yuv(b"YUYV"),
+];
+
+/// The entry for `fourcc`, if Shift composes it.
+pub(super) fn lookup(fourcc: u32) -> Option<&'static PixelFormat> {
+	PIXEL_FORMATS.iter().find(|format| format.fourcc == fourcc)
+}
+
+pub(super) fn supported(fourcc: u32) -> bool {
+	lookup(fourcc).is_some()
+}
+
+/// `fourcc` as its four characters, for logs and errors.
+pub(super) fn name(fourcc: u32) -> String {
+	let bytes = fourcc.to_le_bytes();
+	if bytes
+		.iter()
+		.all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+	{
+		String::from_utf8_lossy(&bytes).trim_end().to_string()
+	} else {
+		format!("{fourcc:#010x}")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bgra_native_formats_sample_as_rgba_and_copy_as_bgra() {
+		let xrgb = lookup(fourcc(b"XR24")).expect("XRGB8888");
+		assert_eq!(xrgb.color_type, ColorType::RGB888x);
+		assert_eq!(xrgb.memory, Some(ColorType::BGRA8888));
+		let abgr = lookup(fourcc(b"AB24")).expect("ABGR8888");
+		assert_eq!(abgr.memory, Some(ColorType::RGBA8888));
+		assert_eq!(
+			lookup(fourcc(b"AR30")).map(|format| format.color_type),
+			Some(ColorType::RGBA1010102)
+		);
+		assert!(lookup(fourcc(b"YUYV")).is_some_and(|format| format.yuv));
+		assert!(!supported(fourcc(b"C8  ")));
+		assert_eq!(name(fourcc(b"C8  ")), "C8");
+		assert_eq!(name(0x0102_0304), "0x01020304");
+	}
+
+	#[test]
+	fn alpha_is_only_read_from_formats_that_have_it() {
+		let argb = lookup(fourcc(b"AR24")).expect("ARGB8888");
+		assert_eq!(argb.alpha_type(None), AlphaType::Opaque);
+		assert_eq!(
+			argb.alpha_type(Some(AlphaMode::Straight)),
+			AlphaType::Unpremul
+		);
+		let rgba = lookup(fourcc(b"RA24")).expect("RGBA8888");
+		assert_eq!(
+			rgba.alpha_type(Some(AlphaMode::Premultiplied)),
+			AlphaType::Premul
+		);
+		for opaque in [b"XR24", b"RG16", b"XR4H", b"NV12"] {
+			let format = lookup(fourcc(opaque)).expect("format");
+			assert_eq!(
+				format.alpha_type(Some(AlphaMode::Premultiplied)),
+				AlphaType::Opaque
+			);
+		}
+	}
+}
@@ -0,0 +1,144 @@
+//! Queued presents: buffers a session asked not to show before a given time, so video players
+//! can schedule frames against their audio clock instead of showing them on submit.
+//!
+//! A queued buffer stays pending, like one waiting on its acquire fence, until both its fence
+//! signaled and its target time passed. It is then composed into the next frame, which reaches
+//! the screen on the following vblank.
+
+use std::{collections::HashMap, time::Duration, time::Instant};
+
+use super::state::SlotKey;
+
+#[derive(Debug, Clone, Copy)]
+struct QueuedPresent {
+	at: Instant,
+	fence_pending: bool,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct PresentQueue {
+	queued: HashMap<SlotKey, QueuedPresent>,
+}
+
+impl PresentQueue {
+	pub fn queue(&mut self, key: SlotKey, at: Instant, fence_pending: bool) {
+		self.queued.insert(key, QueuedPresent { at, fence_pending });
+	}
+
+	pub fn cancel(&mut self, key: SlotKey) {
+		self.queued.remove(&key);
+	}
+
+	/// The acquire fence of `key` signaled; `true` when the buffer may be shown right away.
+	pub fn fence_signaled(&mut self, key: SlotKey, now: Instant) -> bool {
+		let Some(queued) = self.queued.get_mut(&key) else {
+			return true;
+		};
+		if queued.at <= now {
+			self.queued.remove(&key);
+			return true;
+		}
+		queued.fence_pending = false;
+		false
+	}
+
+	/// When the next buffer whose fence already signaled becomes due.
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self
+			.queued
+			.values()
+			.filter(|queued| !queued.fence_pending)
+			.map(|queued| queued.at)
+			.min()
+	}
+
+	/// Removes and returns the buffers that may be shown now.
+	pub fn take_due(&mut self, now: Instant) -> Vec<SlotKey> {
+		let due: Vec<_> = self
+			.queued
+			.iter()
+			.filter(|(_, queued)| !queued.fence_pending && queued.at <= now)
+			.map(|(key, _)| *key)
+			.collect();
+		for key in &due {
+			self.queued.remove(key);
+		}
+		due
+	}
+
+	pub fn retain(&mut self, mut keep: impl FnMut(&SlotKey) -> bool) {
+		self.queued.retain(|key, _| keep(key));
+	}
+}
+
+/// `CLOCK_MONOTONIC` nanoseconds as an [`Instant`], or `None` when that time already passed.
+pub(super) fn instant_from_monotonic(target_ns: u64) -> Option<Instant> {
+	until(target_ns, monotonic_ns()?, Instant::now())
+}
+
+/// The current `CLOCK_MONOTONIC` time in nanoseconds, the clock the protocol's times use.
+pub(super) fn monotonic_ns() -> Option<u64> {
+	let mut now = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+	// SAFETY: `now` is a valid timespec to write to.
+	if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) } != 0 {
+		return None;
+	}
+	Some(now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64)
+}
+
+fn until(target_ns: u64, now_ns: u64, now: Instant) -> Option<Instant> {
+	target_ns
+		.checked_sub(now_ns)
+		.filter(|ahead| *ahead > 0)
+		.map(|ahead| now + Duration::from_nanos(ahead))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rendering_layer::state::BufferSlot;
+
+	fn key() -> SlotKey {
+		SlotKey::new(
+			"mon_1".parse().expect("monitor id"),
+			"se_1".parse().expect("session id"),
+			BufferSlot::Zero,
+		)
+	}
+
+	#[test]
+	fn waits_for_both_fence_and_target_time() {
+		let now = Instant::now();
+		let at = now + Duration::from_millis(20);
+		let mut queue = PresentQueue::default();
+		queue.queue(key(), at, true);
+		assert_eq!(queue.next_deadline(), None, "fence still pending");
+		assert!(!queue.fence_signaled(key(), now));
+		assert_eq!(queue.next_deadline(), Some(at));
+		assert!(queue.take_due(now).is_empty());
+		assert_eq!(queue.take_due(at), vec![key()]);
+		assert!(queue.fence_signaled(key(), at), "no longer queued");
+	}
+
+	#[test]
+	fn converts_monotonic_targets() {
+		let now = Instant::now();
+		assert_eq!(
+			until(1_500, 1_000, now),
+			Some(now + Duration::from_nanos(500))
+		);
+		assert_eq!(until(1_000, 1_000, now), None);
+		assert_eq!(until(900, 1_000, now), None);
+	}
+
+	#[test]
+	fn past_targets_are_not_queued() {
+		// Swaps with these present right away, and the renderer acks them as not deferred.
+		assert_eq!(instant_from_monotonic(1), None);
+		let soon = monotonic_ns().expect("monotonic clock") + 1_000_000_000;
+		assert!(instant_from_monotonic(soon).is_some());
+	}
+}
@@ -0,0 +1,150 @@
+//! When presented buffers reached the screen.
+//!
+//! A buffer a session presented is shown by the first commit after it was latched, and is on
+//! screen once that commit's flip completed. Sessions get `presentation_feedback` for it then:
+//! the flip's time, a per-monitor flip sequence and the refresh interval, so they can measure
+//! their real latency and time animations to scanout. EasyDRM doesn't hand out the kernel's
+//! flip timestamps, so the time is when the renderer saw the flip complete; offscreen monitors
+//! have no flip and report the commit that rendered them.
+
+use std::collections::HashMap;
+
+use super::state::SlotKey;
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+/// A buffer on screen since a flip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Presented {
+	pub key: SlotKey,
+	pub sequence: u64,
+	/// `CLOCK_MONOTONIC` nanoseconds.
+	pub timestamp_ns: u64,
+	pub refresh_ns: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct PresentationFeedback {
+	/// Buffers latched since their monitor's last commit, one per session and monitor.
+	latched: Vec<SlotKey>,
+	/// Refresh interval and buffers of each monitor's committed frame, until it flips.
+	in_flight: HashMap<MonitorId, (u64, Vec<SlotKey>)>,
+	/// Flips each monitor completed.
+	sequences: HashMap<MonitorId, u64>,
+}
+
+impl PresentationFeedback {
+	/// `key` became the buffer its session shows; one latched before it was never shown.
+	pub fn latched(&mut self, key: SlotKey) {
+		self.latched.retain(|latched| {
+			(latched.monitor_id, latched.session_id) != (key.monitor_id, key.session_id)
+		});
+		self.latched.push(key);
+	}
+
+	/// `monitor_id` committed a frame, which shows the buffers latched on it since its last one.
+	pub fn committed(&mut self, monitor_id: MonitorId, refresh_hz: u32) {
+		let (buffers, rest) = std::mem::take(&mut self.latched)
+			.into_iter()
+			.partition(|key| key.monitor_id == monitor_id);
+		self.latched = rest;
+		let (refresh_ns, in_flight) = self.in_flight.entry(monitor_id).or_default();
+		*refresh_ns = 1_000_000_000 / u64::from(refresh_hz.max(1));
+		in_flight.extend(buffers);
+	}
+
+	/// Monitors whose committed frame hasn't flipped yet.
+	pub fn pending(&self) -> impl Iterator<Item = MonitorId> + '_ {
+		self.in_flight.keys().copied()
+	}
+
+	/// `monitor_id`'s committed frame is on screen since `timestamp_ns`.
+	pub fn flipped(&mut self, monitor_id: MonitorId, timestamp_ns: u64) -> Vec<Presented> {
+		let Some((refresh_ns, buffers)) = self.in_flight.remove(&monitor_id) else {
+			return Vec::new();
+		};
+		let sequence = self.sequences.entry(monitor_id).or_default();
+		*sequence += 1;
+		buffers
+			.into_iter()
+			.map(|key| Presented {
+				key,
+				sequence: *sequence,
+				timestamp_ns,
+				refresh_ns,
+			})
+			.collect()
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.latched.retain(|key| key.monitor_id != monitor_id);
+		self.in_flight.remove(&monitor_id);
+		self.sequences.remove(&monitor_id);
+	}
+
+	/// Forgets the buffers of a session's surface on `monitor_id`, which were dropped or
+	/// relinked before they were shown.
+	pub fn remove_surface(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		let other = |key: &SlotKey| (key.monitor_id, key.session_id) != (monitor_id, session_id);
+		self.latched.retain(other);
+		if let Some((_, buffers)) = self.in_flight.get_mut(&monitor_id) {
+			buffers.retain(other);
+		}
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self.latched.retain(|key| key.session_id != session_id);
+		for (_, buffers) in self.in_flight.values_mut() {
+			buffers.retain(|key| key.session_id != session_id);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rendering_layer::state::BufferSlot;
+
+	fn key(monitor: &str, buffer: BufferSlot) -> SlotKey {
+		SlotKey::new(
+			monitor.parse().expect("monitor id"),
+			"se_1".parse().expect("session id"),
+			buffer,
+		)
+	}
+
+	#[test]
+	fn buffers_are_reported_with_the_flip_that_showed_them() {
+		let monitor: MonitorId = "mon_1".parse().expect("monitor id");
+		let mut feedback = PresentationFeedback::default();
+		feedback.latched(key("mon_1", BufferSlot::Zero));
+		feedback.latched(key("mon_1", BufferSlot::One));
+		feedback.latched(key("mon_2", BufferSlot::Zero));
+		assert!(feedback.flipped(monitor, 1).is_empty(), "nothing committed");
+
+		feedback.committed(monitor, 60);
+		assert_eq!(feedback.pending().collect::<Vec<_>>(), vec![monitor]);
+		assert_eq!(
+			feedback.flipped(monitor, 5),
+			vec![Presented {
+				key: key("mon_1", BufferSlot::One),
+				sequence: 1,
+				timestamp_ns: 5,
+				refresh_ns: 16_666_666,
+			}],
+			"only the last latch of the session was shown"
+		);
+
+		feedback.committed(monitor, 120);
+		assert!(feedback.flipped(monitor, 9).is_empty());
+		feedback.committed(monitor, 120);
+		feedback.latched(key("mon_1", BufferSlot::Zero));
+		feedback.committed(monitor, 120);
+		let presented = feedback.flipped(monitor, 13);
+		assert_eq!(presented.len(), 1);
+		assert_eq!(
+			(presented[0].sequence, presented[0].refresh_ns),
+			(3, 8_333_333)
+		);
+		assert_eq!(feedback.pending().count(), 0);
+	}
+}
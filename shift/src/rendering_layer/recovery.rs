@@ -0,0 +1,107 @@
+//! Built-in recovery screen drawn over every output while the server is in safe mode. It is
+//! opaque and replaces the composed sessions, the magnifier and color filters included.
+
+use skia_safe::{Canvas, Color, Font, FontMgr, FontStyle, Paint, RRect, Rect};
+
+use crate::comms::server2render::RecoveryScreen;
+
+#[derive(Default)]
+pub(super) struct RecoveryUi {
+	screen: Option<RecoveryScreen>,
+	/// Resolved lazily; `None` inside means no system font could be loaded.
+	font_mgr: Option<Option<FontMgr>>,
+}
+
+impl RecoveryUi {
+	pub fn set(&mut self, screen: Option<RecoveryScreen>) {
+		self.screen = screen;
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.screen.is_some()
+	}
+
+	pub fn draw(&mut self, canvas: &Canvas, width: f32, height: f32) {
+		let Some(screen) = self.screen.as_ref() else {
+			return;
+		};
+		canvas.clear(Color::from_rgb(24, 26, 32));
+		let font_mgr = self.font_mgr.get_or_insert_with(|| {
+			let font_mgr = FontMgr::new();
+			if font_mgr.count_families() == 0 {
+				tracing::warn!("no system fonts available, recovery screen shows no text");
+				return None;
+			}
+			Some(font_mgr)
+		});
+		let Some(font_mgr) = font_mgr.as_ref() else {
+			return;
+		};
+		let (Some(bold), Some(regular)) = (
+			font_mgr.legacy_make_typeface(None, FontStyle::bold()),
+			font_mgr.legacy_make_typeface(None, FontStyle::normal()),
+		) else {
+			return;
+		};
+		let size = (height * 0.03).clamp(14.0, 40.0);
+		let margin = size * 2.0;
+		let title_font = Font::from_typeface(bold, size * 1.5);
+		let body_font = Font::from_typeface(regular.clone(), size);
+
+		let mut text = Paint::default();
+		text.set_anti_alias(true);
+		text.set_color(Color::from_rgb(235, 235, 235));
+		let mut dim = text.clone();
+		dim.set_color(Color::from_rgb(160, 164, 172));
+
+		let mut y = margin + size * 1.5;
+		canvas.draw_str(&screen.title, (margin, y), &title_font, &text);
+		y += size * 1.8;
+		canvas.draw_str(&screen.message, (margin, y), &body_font, &dim);
+		y += size * 2.0;
+
+		if let Some(lines) = &screen.log_lines {
+			let log_size = (size * 0.6).max(11.0);
+			let log_font = Font::from_typeface(regular, log_size);
+			let line_height = log_size * 1.4;
+			let bottom = height - margin - size * 1.5;
+			let fit = ((bottom - y) / line_height).max(0.0) as usize;
+			for line in lines.iter().skip(lines.len().saturating_sub(fit)) {
+				y += line_height;
+				canvas.draw_str(line, (margin, y), &log_font, &text);
+			}
+			canvas.draw_str(
+				"Press Enter or Esc to go back",
+				(margin, height - margin),
+				&body_font,
+				&dim,
+			);
+			return;
+		}
+
+		let mut highlight = Paint::default();
+		highlight.set_anti_alias(true);
+		highlight.set_color(Color::from_rgb(58, 96, 160));
+		let item_height = size * 2.0;
+		let item_width = (width - margin * 2.0).min(size * 16.0);
+		for (index, item) in screen.items.iter().enumerate() {
+			if index == screen.selected {
+				let rect = Rect::from_xywh(margin, y, item_width, item_height);
+				canvas.draw_rrect(RRect::new_rect_xy(rect, size * 0.3, size * 0.3), &highlight);
+			}
+			canvas.draw_str(
+				item,
+				(margin + size * 0.6, y + item_height * 0.5 + size * 0.35),
+				&body_font,
+				&text,
+			);
+			y += item_height + size * 0.4;
+		}
+		canvas.draw_str(
+			"Up/Down to choose, Enter to confirm",
+			(margin, height - margin),
+			&body_font,
+			&dim,
+		);
+	}
+}
@@ -1,83 +1,572 @@
 use easydrm::gl::{COLOR_BUFFER_BIT, DEPTH_BUFFER_BIT};
-use skia_safe::{FilterMode, MipmapMode, Paint, SamplingOptions};
-use std::collections::HashMap;
+use skia_safe::{
+	AlphaType, BlendMode, ColorType, CubicResampler, FilterMode, IRect, ImageInfo, MipmapMode, Paint,
+	SamplingOptions,
+};
+use std::{
+	collections::{HashMap, HashSet},
+	os::fd::{AsFd, BorrowedFd},
+	sync::Arc,
+};
+use tab_protocol::{LatencyMode, OutputTransform, RenderQualityPayload, SamplingFilter};
 use tracing::warn;
 
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+use super::backend::{BackendError, BackendOutput, RenderBackend};
+use super::damage::Region;
+use super::frame_pacing;
+use super::ownership::OwnershipManager;
+use super::scanout::PlaneLayer;
+use super::scene::{MonitorScene, OVERLAY_Z, SceneLayer};
+use super::skia_backend::SkiaContext;
+use super::snapshots::SessionSnapshots;
 use super::state::SlotOwner;
+use super::tiling::TileLayout;
 use super::{RenderError, RenderEvt, RenderingLayer, current_framebuffer_binding};
 use super::{SkiaDmaBufTexture, SlotKey};
+use super::{color_filter, present_queue, submit_fence};
 
-impl RenderingLayer {
+impl<B: RenderBackend> RenderingLayer<B> {
 	fn slot_image(
 		slots: &mut HashMap<SlotKey, SkiaDmaBufTexture>,
-		gr: &mut skia_safe::gpu::DirectContext,
+		skia: &mut SkiaContext,
 		key: SlotKey,
 	) -> Option<skia_safe::Image> {
 		let texture = slots.get_mut(&key)?;
-		texture.image(gr).cloned()
+		texture.image(skia).cloned()
+	}
+
+	/// The frame `session_id` last presented on `monitor_id`: its current buffer once Shift
+	/// owns it, otherwise the buffer retired by a relink.
+	fn session_image(
+		slots: &mut HashMap<SlotKey, SkiaDmaBufTexture>,
+		retired_slots: &mut HashMap<(MonitorId, SessionId), SkiaDmaBufTexture>,
+		snapshots: &SessionSnapshots,
+		ownership: &OwnershipManager,
+		skia: &mut SkiaContext,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+	) -> Option<skia_safe::Image> {
+		let current = ownership
+			.current_slot_key_for_session(monitor_id, session_id)
+			.filter(|key| ownership.owner(*key) == Some(SlotOwner::ShiftOwned));
+		match current {
+			Some(key) => Self::slot_image(slots, skia, key),
+			None => retired_slots
+				.get_mut(&(monitor_id, session_id))
+				.and_then(|texture| texture.image(skia).cloned()),
+		}
+		.or_else(|| snapshots.get(monitor_id, session_id))
+	}
+
+	fn session_sampling(&self, session_id: SessionId) -> SamplingOptions {
+		sampling_options(
+			self
+				.render_quality
+				.get(&session_id)
+				.copied()
+				.unwrap_or_default(),
+		)
 	}
 
-	fn draw_image_fullscreen(context: &mut super::MonitorRenderState, image: &skia_safe::Image) {
-		let rect = skia_safe::Rect::from_wh(context.width as f32, context.height as f32);
-		let sampling = SamplingOptions::new(FilterMode::Nearest, MipmapMode::Nearest);
-		let mut paint = Paint::default();
-		paint.set_argb(255, 255, 255, 255);
-		context
-			.canvas()
-			.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
+	/// Layers composed on `monitor_id` outside of session transitions.
+	fn monitor_scene(&self, monitor_id: MonitorId) -> MonitorScene {
+		let mut scene = MonitorScene::default();
+		if let Some(session_id) = self.ownership.current_session() {
+			let mut layer = SceneLayer::fullscreen(session_id, 0);
+			layer.sampling = self.session_sampling(session_id);
+			layer.scale = self.monitor_scales.buffer_ratio(monitor_id, session_id);
+			scene.push(layer);
+		}
+		for mut layer in self.overlays.layers(monitor_id) {
+			layer.sampling = self.session_sampling(layer.session_id);
+			layer.scale = self
+				.monitor_scales
+				.buffer_ratio(monitor_id, layer.session_id);
+			scene.push(layer);
+		}
+		scene
+	}
+
+	/// The buffer `monitor_id` can scan out instead of being composed: its scene is a single
+	/// opaque fullscreen layer and nothing else is drawn over or under it.
+	fn scanout_layers(
+		&self,
+		monitor_id: MonitorId,
+		scene: &MonitorScene,
+	) -> Option<(SlotKey, Vec<(SlotKey, IRect)>)> {
+		let decorated = self.active_transition.is_some()
+			|| self.blanked
+			|| self.switcher.is_visible()
+			|| self.recovery.is_active()
+			|| self.watermark.is_visible();
+		if !self.scanout.enabled()
+			|| decorated
+			|| self.disabled_monitors.contains(&monitor_id)
+			|| self.magnifier.magnifies(monitor_id)
+			|| self.color_filters.filter_for(monitor_id).is_some()
+		{
+			return None;
+		}
+		let (primary, overlays) = scene.layers().split_first()?;
+		if !primary.covers_output() {
+			return None;
+		}
+		let slot = |session_id| {
+			self
+				.ownership
+				.current_slot_key_for_session(monitor_id, session_id)
+				.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
+				.filter(|key| self.scanout.has_buffer(*key))
+		};
+		// A plane crops to one rectangle, so only single-region overlays go on planes.
+		let overlays = overlays
+			.iter()
+			.map(|layer| {
+				let [region] = layer.clip.as_slice() else {
+					return None;
+				};
+				if layer.z < OVERLAY_Z
+					|| layer.opacity < 1.0
+					|| !layer.transform.is_identity()
+					|| layer.scale.is_some()
+				{
+					return None;
+				}
+				let key = slot(layer.session_id).filter(|key| {
+					self
+						.slots
+						.get(key)
+						.is_some_and(|texture| texture.blends_on_planes())
+				})?;
+				Some((key, region.round()))
+			})
+			.collect::<Option<Vec<_>>>()?;
+		// Translucent sessions show the wallpaper through.
+		let primary = slot(primary.session_id).filter(|key| {
+			self
+				.slots
+				.get(key)
+				.is_some_and(|texture| texture.alpha_type() == AlphaType::Opaque)
+		})?;
+		Some((primary, overlays))
 	}
 
+	/// How `output_id` is drawn: as its logical monitor's transform says, except for tiles,
+	/// which are always upright.
+	fn output_transform(
+		tiles: &TileLayout,
+		transforms: &HashMap<MonitorId, OutputTransform>,
+		output_id: MonitorId,
+	) -> OutputTransform {
+		if tiles.placement(output_id).is_some() {
+			return OutputTransform::Normal;
+		}
+		transforms
+			.get(&tiles.logical_id(output_id))
+			.copied()
+			.unwrap_or_default()
+	}
+
+	/// Programs every output's cursor plane for the current cursor. Transformed outputs
+	/// compose it instead, as the plane shows it upright.
+	fn sync_cursor(&mut self) -> Result<(), BackendError> {
+		for mon in self.backend.outputs_mut() {
+			let output_id = mon.context().id;
+			let offset = self
+				.tiles
+				.placement(output_id)
+				.map_or((0, 0), |placement| placement.offset);
+			let plane =
+				Self::output_transform(&self.tiles, &self.output_transforms, output_id).is_normal();
+			self
+				.cursor
+				.sync(mon, self.tiles.logical_id(output_id), offset, plane)?;
+		}
+		Ok(())
+	}
+
+	/// [`Self::sync_cursor`] right as a cursor command comes in. A failing output is left to
+	/// the next frame, which reports it as a device loss.
+	pub(super) fn sync_cursor_planes(&mut self) {
+		if let Err(e) = self.sync_cursor() {
+			warn!("failed to program cursor planes: {e}");
+		}
+	}
+
+	/// Refresh rate of the monitor the cursor is on, in Hz; the fastest of its tiles.
+	pub(super) fn cursor_refresh_rate(&self) -> u32 {
+		let Some(monitor_id) = self.cursor.monitor_id() else {
+			return 0;
+		};
+		if let Some(target) = self.virtual_monitors.get(monitor_id) {
+			return target.refresh_rate();
+		}
+		self
+			.backend
+			.outputs()
+			.filter(|mon| self.tiles.logical_id(mon.context().id) == monitor_id)
+			.map(|mon| mon.refresh_rate())
+			.max()
+			.unwrap_or(0)
+	}
+
+	/// Whether `output` (connector-level id) keeps its frame instead of drawing a new one: idle
+	/// outputs do, only VRR ones under `SHIFT_PSR=0`. Animations, captures, casts and flip
+	/// groups always draw.
+	fn keeps_frame(&self, connector_id: u32, output: MonitorId) -> bool {
+		let monitor_id = self.tiles.logical_id(output);
+		self.active_transition.is_none()
+			&& !self.magnifier.is_panning()
+			&& (self.commit_policy.enabled() || self.vrr.enabled(connector_id))
+			&& self.idle.is_idle(output, monitor_id)
+			&& !self.flip_groups.grouped(monitor_id)
+			&& !self.captures.wants(output)
+			&& !self.screencasts.casting(monitor_id)
+	}
+
+	/// Puts the pointer's newest position on screen once its move is due. Cursor planes move
+	/// without a new frame, and a composed cursor only damages the rects it moved between.
+	/// Returns whether that damaged anything.
+	pub(super) fn move_due_cursor(&mut self) -> bool {
+		let now = std::time::Instant::now();
+		if !self
+			.cursor
+			.resample_at(self.cursor_refresh_rate())
+			.is_some_and(|at| at <= now)
+		{
+			return false;
+		}
+		self.cursor.resample(now);
+		self.sync_cursor_planes();
+		if !self.cursor.composed_anywhere() {
+			return false;
+		}
+		self.commit_policy.damage_cursor();
+		self.idle.damage_all();
+		true
+	}
+
+	/// Starts a capture of the logical monitor `monitor_id` from the outputs showing it.
+	pub(super) fn request_capture(&mut self, monitor_id: MonitorId) {
+		if let Some(target) = self.virtual_monitors.get(monitor_id) {
+			let size = target.size();
+			self.captures.request(monitor_id, size, vec![monitor_id]);
+			self.virtual_monitors.damage(monitor_id);
+			return;
+		}
+		let mut size = (0, 0);
+		let mut outputs = Vec::new();
+		for mon in self.backend.outputs() {
+			let output_id = mon.context().id;
+			if self.tiles.logical_id(output_id) != monitor_id {
+				continue;
+			}
+			size = match self.tiles.placement(output_id) {
+				Some(placement) => (
+					placement.logical_size.0 as u32,
+					placement.logical_size.1 as u32,
+				),
+				None => mon.size(),
+			};
+			outputs.push(output_id);
+		}
+		self.captures.request(monitor_id, size, outputs);
+	}
+
+	/// Starts streaming the logical or virtual monitor `monitor_id` at its current size.
+	pub(super) fn start_screencast(
+		&mut self,
+		cast_id: u64,
+		monitor_id: MonitorId,
+	) -> Result<(), Arc<str>> {
+		let target = match self.virtual_monitors.get(monitor_id) {
+			Some(target) => Some((target.size(), target.refresh_rate())),
+			None => self
+				.backend
+				.outputs()
+				.filter(|mon| self.tiles.logical_id(mon.context().id) == monitor_id)
+				.map(|mon| {
+					let size = match self.tiles.placement(mon.context().id) {
+						Some(placement) => (
+							placement.logical_size.0 as u32,
+							placement.logical_size.1 as u32,
+						),
+						None => mon.size(),
+					};
+					(size, mon.refresh_rate())
+				})
+				.last(),
+		};
+		let Some((size, refresh_rate)) = target else {
+			return Err("unknown monitor".into());
+		};
+		self
+			.screencasts
+			.start(cast_id, monitor_id, size, refresh_rate, &self.event_tx)?;
+		// A new cast wants a first frame.
+		self.virtual_monitors.damage(monitor_id);
+		Ok(())
+	}
+
+	pub(super) async fn emit_finished_captures(&mut self) {
+		for (monitor_id, result) in self.captures.take_finished() {
+			if let Err(reason) = &result {
+				warn!(%monitor_id, %reason, "screenshot failed");
+			}
+			self
+				.emit_event(RenderEvt::Captured { monitor_id, result })
+				.await;
+		}
+	}
+
+	#[tracing::instrument(skip_all)]
 	pub(super) fn draw_ready_monitors(&mut self) -> Result<(), RenderError> {
-		let monitor_ids: Vec<_> = self.drm.monitors().map(|mon| mon.context().id).collect();
+		// Tiles of one logical monitor share its session buffers, so ownership, scenes and
+		// per-monitor effects are all keyed by the logical id.
+		let mut monitor_ids: Vec<_> = self
+			.backend
+			.outputs()
+			.map(|mon| self.tiles.logical_id(mon.context().id))
+			.collect();
+		let mut seen = HashSet::new();
+		monitor_ids.retain(|monitor_id| seen.insert(*monitor_id));
 		self.ownership.ensure_current_session_monitors(&monitor_ids);
+		let ownership = &self.ownership;
+		self.retired_slots.retain(|(monitor_id, session_id), _| {
+			ownership
+				.current_slot_key_for_session(*monitor_id, *session_id)
+				.is_none_or(|key| ownership.owner(key) != Some(SlotOwner::ShiftOwned))
+		});
 		let now = std::time::Instant::now();
 		let transition_snapshot = self.active_transition.clone();
 		let transition_done = transition_snapshot
 			.as_ref()
 			.map(|transition| transition.progress(now) >= 1.0)
 			.unwrap_or(false);
+		self.magnifier.tick(now);
+		let scenes: HashMap<_, _> = monitor_ids
+			.iter()
+			.map(|monitor_id| (*monitor_id, self.monitor_scene(*monitor_id)))
+			.collect();
+		let scanout_layers: HashMap<_, _> = scenes
+			.iter()
+			.filter_map(|(monitor_id, scene)| {
+				Some((*monitor_id, self.scanout_layers(*monitor_id, scene)?))
+			})
+			.collect();
+		self.sync_cursor()?;
+		let mut hdr_outputs = HashSet::new();
+		for mon in self.backend.outputs_mut() {
+			let monitor_id = self.tiles.logical_id(mon.context().id);
+			// The topmost session layer decides the output's dynamic range.
+			let shown = scenes
+				.get(&monitor_id)
+				.and_then(|scene| scene.layers().iter().rfind(|layer| layer.z < OVERLAY_Z))
+				.map(|layer| layer.session_id);
+			let hdr = self.hdr.sync(mon.connector_id(), monitor_id, shown);
+			mon.context_mut().set_hdr(hdr);
+			let transform =
+				Self::output_transform(&self.tiles, &self.output_transforms, mon.context().id);
+			mon.context_mut().transform = transform;
+			if hdr {
+				hdr_outputs.insert(mon.context().id);
+			}
+			self.vrr.sync(mon.connector_id(), monitor_id);
+		}
+		let animating = transition_snapshot.is_some() || self.magnifier.is_panning();
+		if self.commit_policy.take_scene_damage() || animating {
+			self.frame_damage.damage_all();
+		}
+		for mon in self.backend.outputs() {
+			let output_id = mon.context().id;
+			let offset = self
+				.tiles
+				.placement(output_id)
+				.map_or((0, 0), |placement| placement.offset);
+			// The cursor moves in logical pixels.
+			let matrix = mon.context().transform_matrix();
+			for rect in self.cursor.take_damage(output_id, offset) {
+				let (rect, _) = matrix.map_rect(skia_safe::Rect::from(rect));
+				self.frame_damage.damage_rect(output_id, rect.round_out());
+			}
+		}
+		let latency_modes: HashMap<_, _> = scenes
+			.iter()
+			.map(|(monitor_id, scene)| {
+				let sessions = scene.layers().iter().map(|layer| layer.session_id);
+				(*monitor_id, self.frame_pacing.monitor_mode(sessions))
+			})
+			.collect();
+		let mut latch_interval: Option<std::time::Duration> = None;
+		// Monitors with a frame still waiting to flip, which hold back the rest of their group.
+		let busy: HashSet<MonitorId> = self
+			.backend
+			.outputs()
+			.filter(|mon| !mon.can_render())
+			.map(|mon| self.tiles.logical_id(mon.context().id))
+			.collect();
+		let kept: HashSet<MonitorId> = self
+			.backend
+			.outputs()
+			.filter(|mon| self.keeps_frame(mon.connector_id(), mon.context().id))
+			.map(|mon| mon.context().id)
+			.collect();
 
-		for mon in self.drm.monitors_mut() {
-			if !mon.can_render() {
+		for mon in self.backend.outputs_mut() {
+			if !mon.can_render()
+				|| self
+					.flip_groups
+					.held(self.tiles.logical_id(mon.context().id), &busy)
+			{
+				continue;
+			}
+			let latency_mode = latency_modes
+				.get(&self.tiles.logical_id(mon.context().id))
+				.copied()
+				.unwrap_or_default();
+			let refresh_interval = frame_pacing::refresh_interval(mon.refresh_rate());
+			if self
+				.frame_pacing
+				.skip_output(mon.context().id, latency_mode, refresh_interval, now)
+			{
+				continue;
+			}
+			let output_id = mon.context().id;
+			if kept.contains(&output_id) {
+				continue;
+			}
+			if latency_mode == LatencyMode::Low {
+				latch_interval =
+					Some(latch_interval.map_or(refresh_interval, |interval| refresh_interval.min(interval)));
+			}
+			// HDR, rotated or flipped outputs and tiles transform the buffer on the way out, so
+			// they compose, and so do outputs drawing the cursor, being captured or being cast.
+			if let Some((key, overlays)) = scanout_layers.get(&self.tiles.logical_id(output_id))
+				&& !hdr_outputs.contains(&output_id)
+				&& mon.context().transform.is_normal()
+				&& !self.cursor.composed_on(output_id)
+				&& !self.captures.wants(output_id)
+				&& !self.screencasts.casting(self.tiles.logical_id(output_id))
+				&& self.tiles.placement(output_id).is_none()
+				&& let Some(buffer) = self.scanout.candidate(
+					*key,
+					mon.size(),
+					self
+						.plane_formats
+						.get(&output_id)
+						.and_then(Option::as_deref),
+				) && let Some(planes) = overlays
+				.iter()
+				.map(|(key, region)| {
+					Some(PlaneLayer {
+						buffer: self.scanout.overlay_candidate(*key, mon.size())?,
+						region: *region,
+					})
+				})
+				.collect::<Option<Vec<_>>>()
+				&& mon.scan_out(buffer, &planes)?
+			{
+				let shown = std::iter::once(*key).chain(overlays.iter().map(|(key, _)| *key));
+				self.scanout.drawn(output_id, shown.collect());
+				self.idle.drawn(output_id);
+				self.frame_damage.forget(output_id);
 				continue;
 			}
 			if let Err(e) = mon.make_current() {
-				warn!(monitor_id = %mon.context().id, "make_current failed: {e:?}");
+				warn!(monitor_id = %mon.context().id, "make_current failed: {e}");
 				continue;
 			}
+			self.gl_debug.attach();
+			self.scanout.drawn(output_id, Vec::new());
+			self.idle.drawn(output_id);
 
-			unsafe {
-				mon.gl().ClearColor(0.0, 0.0, 0.0, 1.0);
-				mon.gl().Clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
+			let placement = self.tiles.placement(mon.context().id);
+			let monitor_id = self.tiles.logical_id(mon.context().id);
+			let hidden = self.blanked || self.disabled_monitors.contains(&monitor_id);
+			let render_scale = self.render_resolutions.get(&monitor_id).copied();
+			// HDR and rescaled frames are resolved from a surface of their own, whose age isn't
+			// tracked.
+			let region = self.frame_damage.begin_frame(
+				output_id,
+				current_framebuffer_binding(&mon.context().gl),
+				mon.size(),
+				hidden || hdr_outputs.contains(&output_id) || render_scale.is_some(),
+			);
+			if region == Region::Full {
+				unsafe {
+					let gl = &mon.context().gl;
+					gl.ClearColor(0.0, 0.0, 0.0, 1.0);
+					gl.Clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
+				}
 			}
 
-			let monitor_id = mon.context().id;
-			let mode = mon.active_mode();
-			let (w, h) = (mode.size().0 as usize, mode.size().1 as usize);
+			if hidden {
+				self
+					.captures
+					.fail(monitor_id, "monitor is not showing sessions");
+				continue;
+			}
+			let (w, h) = (mon.size().0 as usize, mon.size().1 as usize);
 			let context = mon.context_mut();
 			let target_fbo = current_framebuffer_binding(&context.gl);
-			context.ensure_surface_target(&mut self.gr, w, h, target_fbo)?;
+			context.set_render_scale(render_scale.map_or(1.0, |scale| scale.factor() as f32));
+			context.ensure_surface_target(&mut self.skia, w, h, target_fbo)?;
+			// Only the damaged part is redrawn; the framebuffer keeps the rest of its last frame.
+			let clip_count = context.canvas().save_count();
+			if let Some(clip) = region.clip() {
+				context.canvas().save();
+				context.canvas().clip_irect(clip, None);
+				context.canvas().clear(skia_safe::Color::BLACK);
+			}
+			// Everything below is drawn in logical pixels, turned onto the output here.
+			let (output_width, output_height) = context.logical_size();
+			if !context.transform.is_normal() {
+				let matrix = context.transform_matrix();
+				context.canvas().save();
+				context.canvas().concat(&matrix);
+			}
+			let save_count = context.canvas().save_count();
+			// A tile draws the whole logical monitor shifted so only its own slice lands on it.
+			let (width, height) = match placement {
+				Some(placement) => {
+					let (x, y) = placement.offset;
+					context.canvas().translate((-x as f32, -y as f32));
+					let (w, h) = placement.logical_size;
+					(w as f32, h as f32)
+				}
+				None => (output_width, output_height),
+			};
+			color_filter::begin_filter_pass(context.canvas(), self.color_filters.filter_for(monitor_id));
+			self
+				.magnifier
+				.apply(context.canvas(), monitor_id, width, height);
 
 			let mut drew = false;
 			if let Some(transition) = transition_snapshot.as_ref()
 				&& let Some(animation) = self.animations.get(&transition.animation)
 			{
-				let old_key = self
-					.ownership
-					.current_slot_key_for_session(monitor_id, transition.from_session_id);
-				let new_key = self
-					.ownership
-					.current_slot_key_for_session(monitor_id, transition.to_session_id);
-				let old_image = old_key
-					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
-				let new_image = new_key
-					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
+				let old_image = Self::session_image(
+					&mut self.slots,
+					&mut self.retired_slots,
+					&self.session_snapshots,
+					&self.ownership,
+					&mut self.skia,
+					monitor_id,
+					transition.from_session_id,
+				);
+				let new_image = Self::session_image(
+					&mut self.slots,
+					&mut self.retired_slots,
+					&self.session_snapshots,
+					&self.ownership,
+					&mut self.skia,
+					monitor_id,
+					transition.to_session_id,
+				);
 				match (old_image, new_image) {
 					(Some(old_image), Some(new_image)) => {
-						let width = context.width as f32;
-						let height = context.height as f32;
 						animation.draw(
 							context.canvas(),
 							&old_image,
@@ -89,54 +578,375 @@ impl RenderingLayer {
 						drew = true;
 					}
 					(_, Some(new_image)) => {
-						Self::draw_image_fullscreen(context, &new_image);
+						let sampling = self.session_sampling(transition.to_session_id);
+						compose_fullscreen(context.canvas(), &new_image, width, height, sampling);
 						drew = true;
 					}
 					_ => {}
 				}
 			}
 
-			if !drew {
-				let key = self.ownership.current_slot_key(monitor_id);
-				let image = key
-					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
+			// A running transition replaces the session layers; overlays stay on top of it.
+			let layers: Vec<_> = scenes
+				.get(&monitor_id)
+				.into_iter()
+				.flat_map(|scene| scene.layers())
+				.filter(|layer| !drew || layer.z >= OVERLAY_Z)
+				.map(|layer| {
+					let image = Self::session_image(
+						&mut self.slots,
+						&mut self.retired_slots,
+						&self.session_snapshots,
+						&self.ownership,
+						&mut self.skia,
+						monitor_id,
+						layer.session_id,
+					);
+					(layer, image)
+				})
+				.collect();
+			let covered = drew
+				|| layers.iter().any(|(layer, image)| {
+					image.as_ref().is_some_and(|image| image.is_opaque()) && layer.covers_output()
+				});
+			if !covered {
+				self
+					.wallpapers
+					.draw(context.canvas(), monitor_id, width, height);
+			}
+			for (layer, image) in &layers {
 				if let Some(image) = image {
-					Self::draw_image_fullscreen(context, &image);
+					layer.compose(context.canvas(), image, width, height);
 				}
 			}
 
-			context.flush(&mut self.gr);
+			context.canvas().restore_to_count(save_count);
+			let thumbnails: Vec<_> = self
+				.switcher
+				.sessions()
+				.map(|session_id| {
+					Self::session_image(
+						&mut self.slots,
+						&mut self.retired_slots,
+						&self.session_snapshots,
+						&self.ownership,
+						&mut self.skia,
+						monitor_id,
+						session_id,
+					)
+				})
+				.collect();
+			self
+				.switcher
+				.draw(context.canvas(), output_width, output_height, &thumbnails);
+			self
+				.recovery
+				.draw(context.canvas(), output_width, output_height);
+			self
+				.watermark
+				.draw(context.canvas(), output_width, output_height);
+			if self.cursor.composed_on(output_id) {
+				let offset = placement.map_or((0, 0), |placement| placement.offset);
+				self.cursor.draw(context.canvas(), offset);
+			}
+			context.canvas().restore_to_count(clip_count);
+			context.flush(&mut self.skia);
+			if self.captures.wants(output_id) {
+				let offset = placement.map_or((0, 0), |placement| placement.offset);
+				self.captures.read_output(
+					monitor_id,
+					output_id,
+					offset,
+					(w as u32, h as u32),
+					|dst, row_bytes| context.read_pixels(dst, row_bytes),
+				);
+			}
+			if self.screencasts.casting(monitor_id)
+				&& let Some(frame) = context.snapshot(&mut self.skia)
+			{
+				let offset = placement.map_or((0, 0), |placement| placement.offset);
+				self.screencasts.add_tile(monitor_id, offset, &frame);
+			}
+			if let Some(fence) = self.submit_fences.export(&context.gl) {
+				self.frame_fences.push(fence);
+			}
 		}
 
+		self.frame_pacing.set_latch_interval(latch_interval);
 		if transition_done {
-			self.active_transition = None;
+			self.end_transition();
 		}
 
 		Ok(())
 	}
 
+	/// Composes the virtual monitors whose session presented a new frame, at most at their
+	/// refresh rate, and reads the captures waiting on them back. Returns the monitors drawn
+	/// with their refresh rate.
+	#[tracing::instrument(skip_all)]
+	fn draw_virtual_monitors(&mut self, now: std::time::Instant) -> Vec<(MonitorId, u32)> {
+		let due = self.virtual_monitors.due(now);
+		if due.is_empty() {
+			return Vec::new();
+		}
+		if let Err(e) = self.virtual_monitors.make_current(&self.backend) {
+			warn!("make_current failed for virtual monitors: {e}");
+			return Vec::new();
+		}
+		let mut drawn = Vec::new();
+		for monitor_id in due {
+			let Some((session_id, source, mirrored)) = self
+				.virtual_monitors
+				.get(monitor_id)
+				.map(|target| (target.session_id, target.source(), target.mirror.is_some()))
+			else {
+				continue;
+			};
+			// Mirrors mostly scale down, where mipmaps keep the frame from aliasing.
+			let sampling = if mirrored {
+				SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear)
+			} else {
+				self.session_sampling(session_id)
+			};
+			let image = Self::session_image(
+				&mut self.slots,
+				&mut self.retired_slots,
+				&self.session_snapshots,
+				&self.ownership,
+				&mut self.skia,
+				source,
+				session_id,
+			);
+			let Some(target) = self.virtual_monitors.get_mut(monitor_id) else {
+				continue;
+			};
+			let (width, height) = target.size();
+			let refresh_rate = target.refresh_rate();
+			let frame_rect = image
+				.as_ref()
+				.map(|image| target.frame_rect((image.width(), image.height())));
+			let Some(surface) = target.surface(&mut self.skia) else {
+				warn!(%monitor_id, "failed to create the virtual monitor's surface");
+				self
+					.captures
+					.fail(monitor_id, "failed to compose the virtual monitor");
+				target.drawn(now);
+				continue;
+			};
+			let canvas = surface.canvas();
+			canvas.clear(skia_safe::Color::BLACK);
+			if let (Some(image), Some(rect)) = (&image, frame_rect) {
+				let mut paint = Paint::default();
+				paint.set_argb(255, 255, 255, 255);
+				canvas.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
+			}
+			// Waiting for the GPU here lets the buffers sampled be released without a fence.
+			self.skia.submit_and_wait();
+			if self.screencasts.casting(monitor_id) {
+				self
+					.screencasts
+					.add_tile(monitor_id, (0, 0), &surface.image_snapshot());
+			}
+			if self.captures.wants(monitor_id) {
+				let info = ImageInfo::new(
+					(width as i32, height as i32),
+					ColorType::BGRA8888,
+					AlphaType::Premul,
+					None,
+				);
+				self.captures.read_output(
+					monitor_id,
+					monitor_id,
+					(0, 0),
+					(width, height),
+					|dst, row_bytes| surface.read_pixels(&info, dst, row_bytes, (0, 0)),
+				);
+			}
+			target.drawn(now);
+			drawn.push((monitor_id, refresh_rate));
+		}
+		drawn
+	}
+
+	#[tracing::instrument(skip_all)]
 	pub(super) async fn render_and_commit(&mut self) -> Result<bool, RenderError> {
+		// The frame takes the newest pointer position along, due or not.
+		if self.cursor.resample(std::time::Instant::now()) {
+			self.sync_cursor_planes();
+		}
 		self.draw_ready_monitors()?;
+		let drawn_virtual = self.draw_virtual_monitors(std::time::Instant::now());
+		if self.virtual_monitors.any_damaged() {
+			self.commit_policy.damage();
+		}
+		self.draw_screencasts();
 
-		let page_flipped_monitors = self
-			.drm
-			.monitors()
+		let mut drawn_monitors = self
+			.backend
+			.outputs()
 			.filter(|m| m.was_drawn())
-			.map(|m| m.context().id)
+			.map(|m| (self.tiles.logical_id(m.context().id), m.refresh_rate()))
 			.collect::<Vec<_>>();
+		let mut seen = HashSet::new();
+		drawn_monitors.retain(|(monitor_id, _)| seen.insert(*monitor_id));
+		drawn_monitors.extend(drawn_virtual);
 
-		let swap_result = self.drm.swap_buffers_with_result()?;
-		let committed_any = !swap_result.committed_connectors.is_empty();
-		self
-			.process_deferred_releases(swap_result.render_fence)
-			.await;
+		// Idle outputs keeping their frame don't need a new one.
+		let mut all_outputs_drawn = true;
+		let mut kept = Vec::new();
+		for m in self.backend.outputs().filter(|m| !m.was_drawn()) {
+			if self.keeps_frame(m.connector_id(), m.context().id) {
+				kept.push(self.tiles.logical_id(m.context().id));
+			} else {
+				all_outputs_drawn = false;
+			}
+		}
+		let commit = self.backend.commit()?;
+		let committed_any = commit.committed_any;
+		if committed_any {
+			crate::startup::first_frame_committed();
+		}
+		self.commit_policy.committed(all_outputs_drawn);
+		let frame_fences = std::mem::take(&mut self.frame_fences);
+		let submit_fence;
+		let release_fence = if let Some(render_fence) = commit.render_fence {
+			// SAFETY: the backend keeps the render fence open until the next commit.
+			Some(unsafe { BorrowedFd::borrow_raw(render_fence) })
+		} else {
+			submit_fence = submit_fence::merge(frame_fences);
+			submit_fence.as_ref().map(AsFd::as_fd)
+		};
+		self.process_deferred_releases(release_fence).await;
+		self.scanout.committed();
+		let now = std::time::Instant::now();
+		for (monitor_id, refresh_hz) in &drawn_monitors {
+			self
+				.presentation_feedback
+				.committed(*monitor_id, *refresh_hz);
+			self.frame_stats.record_frame(*monitor_id, *refresh_hz, now);
+			if let Some(session_id) = self.ownership.current_session() {
+				self
+					.frame_stats
+					.record_refresh(*monitor_id, session_id, now);
+			}
+		}
+		for monitor_id in kept {
+			if !drawn_monitors.iter().any(|(drawn, _)| *drawn == monitor_id) {
+				self.frame_stats.keep_frame(monitor_id, now);
+			}
+		}
 		self
 			.emit_event(RenderEvt::PageFlip {
-				monitors: page_flipped_monitors,
+				monitors: drawn_monitors.into_iter().map(|(id, _)| id).collect(),
 			})
 			.await;
+		// Offscreen monitors have no flip to wait for.
+		self.emit_presentation_feedback().await;
+		self.emit_due_frame_stats(now).await;
+		self.emit_finished_captures().await;
 
 		Ok(committed_any)
 	}
+
+	/// Sends the frames the casts got since the last call, on the shared context.
+	fn draw_screencasts(&mut self) {
+		if !self.screencasts.has_work() {
+			return;
+		}
+		let gl = match self.virtual_monitors.make_current(&self.backend) {
+			Ok(gl) => gl,
+			Err(e) => {
+				warn!("make_current failed for screencasts: {e}");
+				return;
+			}
+		};
+		let proc_loader = self.backend.proc_loader();
+		self
+			.screencasts
+			.draw(&mut self.skia, &gl, &proc_loader, &self.submit_fences);
+	}
+
+	/// Reports the buffers of committed frames whose flip completed.
+	pub(super) async fn emit_presentation_feedback(&mut self) {
+		let flipping: HashSet<MonitorId> = self
+			.backend
+			.outputs()
+			.filter(|m| !m.can_render())
+			.map(|m| self.tiles.logical_id(m.context().id))
+			.collect();
+		let flipped: Vec<MonitorId> = self
+			.presentation_feedback
+			.pending()
+			.filter(|monitor_id| !flipping.contains(monitor_id))
+			.collect();
+		if flipped.is_empty() {
+			return;
+		}
+		let Some(timestamp_ns) = present_queue::monotonic_ns() else {
+			return;
+		};
+		for monitor_id in flipped {
+			for presented in self.presentation_feedback.flipped(monitor_id, timestamp_ns) {
+				self
+					.emit_event(RenderEvt::PresentationFeedback {
+						session_id: presented.key.session_id,
+						monitor_id: presented.key.monitor_id,
+						buffer: presented.key.buffer.into(),
+						timestamp_ns: presented.timestamp_ns,
+						sequence: presented.sequence,
+						refresh_ns: presented.refresh_ns,
+						frame_id: self.frame_ids.get(&presented.key).copied(),
+					})
+					.await;
+			}
+		}
+	}
+
+	pub(super) async fn emit_due_frame_stats(&mut self, now: std::time::Instant) {
+		for stats in self.frame_stats.take_due(now) {
+			self.emit_event(stats).await;
+		}
+		if let Some(stats) = self.resource_cache.housekeeping(&mut self.skia, now) {
+			self.emit_event(stats).await;
+		}
+	}
+}
+
+/// Sampling for a session's buffers. Mipmaps only kick in when Skia draws the buffer smaller
+/// than its size; it builds the chain on demand. Cubic filters never use mipmaps.
+pub(super) fn sampling_options(quality: RenderQualityPayload) -> SamplingOptions {
+	let filter = match quality.filter {
+		SamplingFilter::Nearest => FilterMode::Nearest,
+		SamplingFilter::Linear => FilterMode::Linear,
+		SamplingFilter::Mitchell => return CubicResampler::mitchell().into(),
+		SamplingFilter::CatmullRom => return CubicResampler::catmull_rom().into(),
+	};
+	let mipmap = if quality.mipmaps {
+		MipmapMode::Linear
+	} else {
+		MipmapMode::None
+	};
+	SamplingOptions::new(filter, mipmap)
+}
+
+/// Draws `image` stretched over a `width`x`height` target, the way a session buffer is
+/// composited onto a monitor when no transition is running.
+pub(super) fn compose_fullscreen(
+	canvas: &skia_safe::Canvas,
+	image: &skia_safe::Image,
+	width: f32,
+	height: f32,
+	sampling: SamplingOptions,
+) {
+	let rect = skia_safe::Rect::from_wh(width, height);
+	let mut paint = Paint::default();
+	paint.set_argb(255, 255, 255, 255);
+	// Opaque buffers replace what is below; translucent ones, premultiplied by the time they
+	// are sampled, blend over it.
+	paint.set_blend_mode(if image.is_opaque() {
+		BlendMode::Src
+	} else {
+		BlendMode::SrcOver
+	});
+	canvas.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
 }
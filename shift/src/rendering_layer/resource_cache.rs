@@ -0,0 +1,121 @@
+//! Budget and housekeeping for Skia's GPU resource cache.
+//!
+//! Skia keeps textures, render targets and programs around for reuse. Left at its default the
+//! cache only shrinks under pressure, so a long-running Shift slowly grows GPU memory as
+//! sessions come and go. The budget follows the device's memory (`SHIFT_GPU_CACHE_MB`
+//! overrides it), resources unused for a while are dropped periodically, and everything
+//! unlocked is purged when a session goes to sleep or the system runs short on memory.
+
+use std::time::{Duration, Instant};
+
+use super::{RenderEvt, skia_backend::SkiaContext};
+
+const MIB: u64 = 1024 * 1024;
+const MIN_BUDGET: u64 = 96 * MIB;
+const MAX_BUDGET: u64 = 768 * MIB;
+/// Cached resources unused for this long are released by the periodic cleanup.
+const UNUSED_RESOURCE_AGE: Duration = Duration::from_secs(30);
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An eighth of dedicated VRAM, or 1/32 of system RAM for GPUs sharing it.
+pub(super) fn budget_for(vram_bytes: Option<u64>, ram_bytes: Option<u64>) -> u64 {
+	let budget = match (vram_bytes, ram_bytes) {
+		(Some(vram), _) if vram > 0 => vram / 8,
+		(_, Some(ram)) => ram / 32,
+		_ => MIN_BUDGET,
+	};
+	budget.clamp(MIN_BUDGET, MAX_BUDGET)
+}
+
+fn vram_bytes() -> Option<u64> {
+	let entries = std::fs::read_dir("/sys/class/drm").ok()?;
+	entries
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| {
+			let name = entry.file_name();
+			let name = name.to_string_lossy();
+			name.starts_with("card") && !name.contains('-')
+		})
+		.filter_map(|entry| {
+			std::fs::read_to_string(entry.path().join("device/mem_info_vram_total")).ok()
+		})
+		.filter_map(|raw| raw.trim().parse::<u64>().ok())
+		.max()
+}
+
+fn ram_bytes() -> Option<u64> {
+	let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+	let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+	let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+	Some(kib * 1024)
+}
+
+#[derive(Debug)]
+pub(super) struct ResourceCache {
+	budget_bytes: u64,
+	last_housekeeping: Instant,
+}
+
+impl ResourceCache {
+	/// Picks the budget and applies it to `skia`.
+	pub fn configure(skia: &mut SkiaContext) -> Self {
+		let configured = std::env::var("SHIFT_GPU_CACHE_MB").ok().and_then(|raw| {
+			raw
+				.trim()
+				.parse::<u64>()
+				.inspect_err(|e| tracing::warn!(value = %raw, "invalid SHIFT_GPU_CACHE_MB: {e}"))
+				.ok()
+		});
+		let budget_bytes = match configured {
+			Some(mb) => mb * MIB,
+			None => budget_for(vram_bytes(), ram_bytes()),
+		};
+		tracing::info!(budget_mb = budget_bytes / MIB, "GPU resource cache budget");
+		skia.set_cache_budget(budget_bytes);
+		Self {
+			budget_bytes,
+			last_housekeeping: Instant::now(),
+		}
+	}
+
+	/// Releases every cached resource nothing currently holds.
+	pub fn purge(&self, skia: &mut SkiaContext) {
+		let before = skia.cache_usage().resource_bytes;
+		skia.purge_unlocked();
+		let after = skia.cache_usage().resource_bytes;
+		tracing::debug!(
+			freed_bytes = before.saturating_sub(after),
+			"purged GPU resource cache"
+		);
+	}
+
+	/// Drops long-unused resources and returns a stats report, at most every few seconds.
+	pub fn housekeeping(&mut self, skia: &mut SkiaContext, now: Instant) -> Option<RenderEvt> {
+		if now.saturating_duration_since(self.last_housekeeping) < HOUSEKEEPING_INTERVAL {
+			return None;
+		}
+		self.last_housekeeping = now;
+		skia.purge_unused(UNUSED_RESOURCE_AGE);
+		let usage = skia.cache_usage();
+		Some(RenderEvt::ResourceCacheStats {
+			resource_count: usage.resource_count,
+			resource_bytes: usage.resource_bytes,
+			purgeable_bytes: usage.purgeable_bytes,
+			budget_bytes: self.budget_bytes,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn budget_follows_device_memory_within_bounds() {
+		assert_eq!(budget_for(Some(2048 * MIB), None), 256 * MIB);
+		assert_eq!(budget_for(Some(24 * 1024 * MIB), None), MAX_BUDGET);
+		assert_eq!(budget_for(None, Some(8192 * MIB)), 256 * MIB);
+		assert_eq!(budget_for(None, Some(1024 * MIB)), MIN_BUDGET);
+		assert_eq!(budget_for(None, None), MIN_BUDGET);
+	}
+}
@@ -0,0 +1,324 @@
+//! Direct scanout of fullscreen session buffers, with overlay sessions on overlay planes.
+//!
+//! When an output shows one opaque, untransformed session buffer of exactly its mode size, in a
+//! format its primary plane takes, composing that buffer through Skia is a full-screen copy that
+//! buys nothing. The renderer offers such a buffer to the backend instead
+//! ([`BackendOutput::scan_out`](super::backend::BackendOutput::scan_out)), which flips to it
+//! directly: no copy, and no GPU pass between the session's commit and the flip. Overlay
+//! sessions above it that occupy a single region go along as [`PlaneLayer`]s, for the backend
+//! to put on overlay planes cropped to that region.
+//!
+//! Anything else drawn on the output falls back to composition: overlays with several regions
+//! or straight alpha (planes blend premultiplied alpha), wallpaper showing through, including
+//! through a translucent session, a session transition, the switcher, the recovery screen, the
+//! watermark, the magnifier, color filters and profile corrections, HDR composition, and tiles
+//! (a tile shows only a slice of the buffer). So does a backend that can't place every buffer,
+//! e.g. for lack of overlay planes.
+//!
+//! The display reads a scanned-out buffer until the next page flip replaces it, so its release
+//! waits for that flip instead of going out with the commit's render fence.
+//!
+//! `SHIFT_DIRECT_SCANOUT=0` turns it off.
+
+use std::{collections::HashMap, os::fd::OwnedFd};
+
+use skia_safe::IRect;
+use tab_protocol::FramebufferLinkPayload;
+
+use super::state::{DeferredRelease, SlotKey};
+use crate::monitor::MonitorId;
+
+#[derive(Debug)]
+pub struct ScanoutPlane {
+	pub fd: OwnedFd,
+	pub offset: u32,
+	pub stride: u32,
+}
+
+/// A linked session buffer, as handed to the backend for scanout.
+#[derive(Debug)]
+pub struct ScanoutBuffer {
+	pub width: u32,
+	pub height: u32,
+	pub fourcc: u32,
+	/// `None` when the client left the layout to the driver's default.
+	pub modifier: Option<u64>,
+	/// In plane order; several planes may share one buffer.
+	pub planes: Vec<ScanoutPlane>,
+}
+
+impl ScanoutBuffer {
+	/// Describes one buffer of a framebuffer link, duplicating its plane fds since the import
+	/// consumes them.
+	pub fn from_link(payload: &FramebufferLinkPayload, fds: &[OwnedFd]) -> Option<Self> {
+		let layouts = std::iter::once((payload.stride, payload.offset)).chain(
+			payload
+				.extra_planes
+				.iter()
+				.map(|plane| (plane.stride, plane.offset)),
+		);
+		let planes = fds
+			.iter()
+			.zip(layouts)
+			.map(|(fd, (stride, offset))| {
+				Some(ScanoutPlane {
+					fd: fd
+						.try_clone()
+						.inspect_err(|e| tracing::warn!("failed to duplicate dmabuf fd for scanout: {e}"))
+						.ok()?,
+					offset: u32::try_from(offset).ok()?,
+					stride: u32::try_from(stride).ok()?,
+				})
+			})
+			.collect::<Option<Vec<_>>>()?;
+		Some(Self {
+			width: u32::try_from(payload.width).ok()?,
+			height: u32::try_from(payload.height).ok()?,
+			fourcc: payload.fourcc as u32,
+			modifier: payload.explicit_modifier(),
+			planes,
+		})
+	}
+
+	/// Whether the buffer can replace a composed frame on an output of `output_size`.
+	/// `plane_formats` are the primary plane's formats, `None` when they couldn't be read, in
+	/// which case the backend has the last word.
+	fn fits(&self, output_size: (u32, u32), plane_formats: Option<&[u32]>) -> bool {
+		(self.width, self.height) == output_size
+			&& plane_formats.is_none_or(|formats| formats.contains(&self.fourcc))
+	}
+}
+
+/// An overlay session's buffer on an overlay plane. Overlay buffers are monitor-sized, so
+/// `region` is both the part of the buffer read and where it lands on the output.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneLayer<'a> {
+	pub buffer: &'a ScanoutBuffer,
+	pub region: IRect,
+}
+
+#[derive(Debug)]
+pub(super) struct DirectScanout {
+	enabled: bool,
+	buffers: HashMap<SlotKey, ScanoutBuffer>,
+	/// Buffers each output (connector-level id) scanned out at the last commit.
+	on_plane: HashMap<MonitorId, Vec<SlotKey>>,
+	/// Outputs drawn this frame, with the buffers put on their planes; empty when composed.
+	drawn: HashMap<MonitorId, Vec<SlotKey>>,
+	/// Releases of buffers still on a plane, and whether a commit already replaced them there.
+	/// Replaced ones go out after the next page flip.
+	held: Vec<(DeferredRelease, bool)>,
+}
+
+impl DirectScanout {
+	/// Reads `SHIFT_DIRECT_SCANOUT` (default on).
+	pub fn from_env() -> Self {
+		Self::new(crate::input_layer::env_bool("SHIFT_DIRECT_SCANOUT", true))
+	}
+
+	fn new(enabled: bool) -> Self {
+		Self {
+			enabled,
+			buffers: HashMap::new(),
+			on_plane: HashMap::new(),
+			drawn: HashMap::new(),
+			held: Vec::new(),
+		}
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// Keeps the buffer linked into `key` for scanout, replacing the previous link's.
+	pub fn link(&mut self, key: SlotKey, buffer: Option<ScanoutBuffer>) {
+		match buffer {
+			Some(buffer) if self.enabled => {
+				self.buffers.insert(key, buffer);
+			}
+			_ => {
+				self.buffers.remove(&key);
+			}
+		}
+	}
+
+	pub fn has_buffer(&self, key: SlotKey) -> bool {
+		self.buffers.contains_key(&key)
+	}
+
+	/// The buffer in `key` if it can be scanned out on an output of `output_size`.
+	pub fn candidate(
+		&self,
+		key: SlotKey,
+		output_size: (u32, u32),
+		plane_formats: Option<&[u32]>,
+	) -> Option<&ScanoutBuffer> {
+		self
+			.buffers
+			.get(&key)
+			.filter(|buffer| buffer.fits(output_size, plane_formats))
+	}
+
+	/// The overlay buffer in `key` if it can go on an overlay plane of an output of
+	/// `output_size`. Overlay plane formats aren't known, so the backend checks those.
+	pub fn overlay_candidate(&self, key: SlotKey, output_size: (u32, u32)) -> Option<&ScanoutBuffer> {
+		self
+			.buffers
+			.get(&key)
+			.filter(|buffer| buffer.fits(output_size, None))
+	}
+
+	/// Records what `output` shows after this frame: the buffers put on its planes, none when
+	/// the frame was composed.
+	pub fn drawn(&mut self, output: MonitorId, keys: Vec<SlotKey>) {
+		self.drawn.insert(output, keys);
+	}
+
+	fn is_on_plane(&self, key: SlotKey) -> bool {
+		self.on_plane.values().flatten().any(|shown| *shown == key)
+	}
+
+	/// Holds `release` if its buffer was on a plane at the last commit. Returns it otherwise.
+	pub fn hold(&mut self, release: DeferredRelease) -> Option<DeferredRelease> {
+		let key = SlotKey::new(release.monitor_id, release.session_id, release.buffer);
+		if self.is_on_plane(key) {
+			self.held.push((release, false));
+			None
+		} else {
+			Some(release)
+		}
+	}
+
+	/// Moves this frame's outputs onto the plane state once the frame was committed.
+	pub fn committed(&mut self) {
+		for (output, keys) in self.drawn.drain() {
+			if keys.is_empty() {
+				self.on_plane.remove(&output);
+			} else {
+				self.on_plane.insert(output, keys);
+			}
+		}
+		let mut held = std::mem::take(&mut self.held);
+		for (release, replaced) in &mut held {
+			let key = SlotKey::new(release.monitor_id, release.session_id, release.buffer);
+			*replaced |= !self.is_on_plane(key);
+		}
+		self.held = held;
+	}
+
+	/// Releases of buffers a committed frame took off the plane, once a page flip happened.
+	pub fn take_held(&mut self) -> Vec<DeferredRelease> {
+		let (replaced, still_shown): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+			.into_iter()
+			.partition(|(_, replaced)| *replaced);
+		self.held = still_shown;
+		replaced.into_iter().map(|(release, _)| release).collect()
+	}
+
+	pub fn retain(&mut self, keep: impl Fn(SlotKey) -> bool) {
+		self.buffers.retain(|key, _| keep(*key));
+		self.held.retain(|(release, _)| {
+			keep(SlotKey::new(
+				release.monitor_id,
+				release.session_id,
+				release.buffer,
+			))
+		});
+	}
+
+	pub fn remove_output(&mut self, output: MonitorId) {
+		self.on_plane.remove(&output);
+		self.drawn.remove(&output);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rendering_layer::state::BufferSlot;
+
+	const XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+	const ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+
+	fn buffer(width: u32, height: u32, fourcc: u32) -> ScanoutBuffer {
+		ScanoutBuffer {
+			width,
+			height,
+			fourcc,
+			modifier: None,
+			planes: Vec::new(),
+		}
+	}
+
+	fn release(buffer: BufferSlot) -> DeferredRelease {
+		DeferredRelease {
+			monitor_id: "mon_1".parse().expect("monitor id"),
+			session_id: "se_1".parse().expect("session id"),
+			buffer,
+		}
+	}
+
+	fn key(release: DeferredRelease) -> SlotKey {
+		SlotKey::new(release.monitor_id, release.session_id, release.buffer)
+	}
+
+	#[test]
+	fn buffers_must_match_the_mode_and_plane_format() {
+		let formats = [XRGB8888];
+		assert!(buffer(1920, 1080, XRGB8888).fits((1920, 1080), Some(&formats)));
+		assert!(!buffer(1280, 720, XRGB8888).fits((1920, 1080), Some(&formats)));
+		assert!(!buffer(1920, 1080, ARGB8888).fits((1920, 1080), Some(&formats)));
+		assert!(
+			buffer(1920, 1080, ARGB8888).fits((1920, 1080), None),
+			"unknown plane formats leave it to the backend"
+		);
+	}
+
+	#[test]
+	fn disabled_scanout_keeps_no_buffers() {
+		let mut scanout = DirectScanout::new(false);
+		let key = key(release(BufferSlot::Zero));
+		scanout.link(key, Some(buffer(1920, 1080, XRGB8888)));
+		assert!(!scanout.has_buffer(key));
+	}
+
+	#[test]
+	fn releases_wait_for_the_flip_that_replaces_the_buffer() {
+		let mut scanout = DirectScanout::new(true);
+		let output = "mon_1".parse().expect("monitor id");
+		let (first, second) = (release(BufferSlot::Zero), release(BufferSlot::One));
+		scanout.drawn(output, vec![key(first)]);
+		scanout.committed();
+		// Released before the output could take a new frame: the flip still shows it.
+		assert!(scanout.hold(first).is_none());
+		scanout.committed();
+		assert!(scanout.take_held().is_empty());
+		// The next frame scans out the other buffer; the first stays on screen until it flips.
+		scanout.drawn(output, vec![key(second)]);
+		scanout.committed();
+		assert_eq!(scanout.take_held(), vec![first]);
+		// Composing over it takes the second buffer off the plane after that commit.
+		scanout.drawn(output, Vec::new());
+		assert!(scanout.hold(second).is_none());
+		scanout.committed();
+		assert_eq!(scanout.take_held(), vec![second]);
+		assert_eq!(scanout.hold(first), Some(first));
+	}
+
+	#[test]
+	fn overlay_plane_buffers_are_held_like_the_primary() {
+		let mut scanout = DirectScanout::new(true);
+		let output = "mon_1".parse().expect("monitor id");
+		let primary = release(BufferSlot::Zero);
+		let overlay = DeferredRelease {
+			session_id: "se_2".parse().expect("session id"),
+			..release(BufferSlot::Zero)
+		};
+		scanout.drawn(output, vec![key(primary), key(overlay)]);
+		scanout.committed();
+		assert!(scanout.hold(overlay).is_none());
+		scanout.drawn(output, vec![key(primary)]);
+		scanout.committed();
+		assert_eq!(scanout.take_held(), vec![overlay]);
+	}
+}
@@ -0,0 +1,181 @@
+//! Per-monitor composition snapshot: an ordered stack of session layers.
+//!
+//! Outside of session transitions, each monitor is composed from a [`MonitorScene`]: the active
+//! session at z 0 and overlay sessions at [`OVERLAY_Z`], clipped to the regions they declared.
+//! Picture-in-picture and split-screen add layers with their own opacity, transform and z order
+//! instead of special-casing the draw loop.
+
+use skia_safe::{Canvas, ClipOp, Image, Matrix, Path, Rect, SamplingOptions};
+use tab_protocol::OverlayRegion;
+
+use super::render_core::{compose_fullscreen, sampling_options};
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+/// Overlay layers stay above session layers, including during session transitions.
+pub(super) const OVERLAY_Z: i32 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct SceneLayer {
+	pub session_id: SessionId,
+	/// `0.0..=1.0`, applied on top of the buffer's own alpha.
+	pub opacity: f32,
+	/// Maps the full-monitor rectangle to where the layer lands on the monitor.
+	pub transform: Matrix,
+	/// Higher values are drawn later, on top.
+	pub z: i32,
+	pub sampling: SamplingOptions,
+	/// Monitor-space rectangles the layer is restricted to; empty draws it everywhere.
+	pub clip: Vec<Rect>,
+	/// Set when the buffer is drawn at another scale than the monitor's: it is drawn at its own
+	/// size times this, from the top left, instead of filling the monitor.
+	pub scale: Option<f32>,
+}
+
+impl SceneLayer {
+	/// An opaque layer covering the whole monitor.
+	pub fn fullscreen(session_id: SessionId, z: i32) -> Self {
+		Self {
+			session_id,
+			opacity: 1.0,
+			transform: Matrix::new_identity(),
+			z,
+			sampling: sampling_options(Default::default()),
+			clip: Vec::new(),
+			scale: None,
+		}
+	}
+
+	/// Whether the layer hides everything below it.
+	pub fn covers_output(&self) -> bool {
+		self.opacity >= 1.0
+			&& self.transform.is_identity()
+			&& self.clip.is_empty()
+			&& self.scale.is_none()
+	}
+
+	/// Draws `image` for this layer onto a `width`x`height` monitor.
+	pub fn compose(&self, canvas: &Canvas, image: &Image, width: f32, height: f32) {
+		if self.opacity <= 0.0 {
+			return;
+		}
+		let save_count = canvas.save();
+		if !self.clip.is_empty() {
+			let mut path = Path::new();
+			for rect in &self.clip {
+				path.add_rect(rect, None);
+			}
+			canvas.clip_path(&path, ClipOp::Intersect, false);
+		}
+		if !(self.opacity >= 1.0 && self.transform.is_identity()) {
+			canvas.save_layer_alpha_f(None, self.opacity.min(1.0));
+			canvas.concat(&self.transform);
+		}
+		let (width, height) = match self.scale {
+			Some(ratio) => (image.width() as f32 * ratio, image.height() as f32 * ratio),
+			None => (width, height),
+		};
+		compose_fullscreen(canvas, image, width, height, self.sampling);
+		canvas.restore_to_count(save_count);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct MonitorScene {
+	layers: Vec<SceneLayer>,
+}
+
+impl MonitorScene {
+	/// Inserts `layer` above every existing layer with the same or lower z.
+	pub fn push(&mut self, layer: SceneLayer) {
+		let index = self
+			.layers
+			.partition_point(|existing| existing.z <= layer.z);
+		self.layers.insert(index, layer);
+	}
+
+	/// Layers bottom to top.
+	pub fn layers(&self) -> &[SceneLayer] {
+		&self.layers
+	}
+}
+
+/// Regions overlay sessions occupy, per monitor. Entries are kept in the order they were last
+/// updated, so the overlay that changed most recently is stacked on top.
+#[derive(Debug, Default)]
+pub(super) struct OverlayRegions {
+	entries: Vec<(SessionId, MonitorId, Vec<Rect>)>,
+}
+
+impl OverlayRegions {
+	pub fn set(&mut self, session_id: SessionId, monitor_id: MonitorId, regions: &[OverlayRegion]) {
+		self
+			.entries
+			.retain(|(session, monitor, _)| (*session, *monitor) != (session_id, monitor_id));
+		let rects: Vec<_> = regions
+			.iter()
+			.map(|r| Rect::from_xywh(r.x as f32, r.y as f32, r.width as f32, r.height as f32))
+			.filter(|r| !r.is_empty())
+			.collect();
+		if !rects.is_empty() {
+			self.entries.push((session_id, monitor_id, rects));
+		}
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self
+			.entries
+			.retain(|(session, _, _)| *session != session_id);
+	}
+
+	/// Overlay layers for `monitor_id`, bottom to top.
+	pub fn layers(&self, monitor_id: MonitorId) -> impl Iterator<Item = SceneLayer> + '_ {
+		self
+			.entries
+			.iter()
+			.filter(move |(_, monitor, _)| *monitor == monitor_id)
+			.map(|(session_id, _, rects)| SceneLayer {
+				clip: rects.clone(),
+				..SceneLayer::fullscreen(*session_id, OVERLAY_Z)
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	#[test]
+	fn layers_are_ordered_by_z_then_insertion() {
+		let mut scene = MonitorScene::default();
+		scene.push(SceneLayer::fullscreen(session(1), 0));
+		scene.push(SceneLayer::fullscreen(session(2), 10));
+		scene.push(SceneLayer::fullscreen(session(3), 0));
+		scene.push(SceneLayer::fullscreen(session(4), -5));
+		let order: Vec<_> = scene.layers().iter().map(|l| l.session_id).collect();
+		assert_eq!(order, [session(4), session(1), session(3), session(2)]);
+	}
+
+	#[test]
+	fn overlays_stack_by_last_update() {
+		let monitor: MonitorId = "mon_1".parse().expect("monitor id");
+		let region = OverlayRegion {
+			x: 0,
+			y: 0,
+			width: 10,
+			height: 10,
+		};
+		let mut overlays = OverlayRegions::default();
+		overlays.set(session(1), monitor, &[region]);
+		overlays.set(session(2), monitor, &[region]);
+		overlays.set(session(1), monitor, &[region]);
+		let order: Vec<_> = overlays.layers(monitor).map(|l| l.session_id).collect();
+		assert_eq!(order, [session(2), session(1)]);
+		overlays.set(session(1), monitor, &[]);
+		overlays.remove_session(session(2));
+		assert_eq!(overlays.layers(monitor).count(), 0);
+	}
+}
@@ -0,0 +1,281 @@
+//! Screencasts the server starts with [`RenderCmd::StartScreencast`].
+//!
+//! Each cast streams one logical monitor to a PipeWire node. Outputs hand the cast a snapshot of
+//! their framebuffer right after their flush, each tile at its offset within the monitor, and
+//! virtual monitors their surface. Once everything is drawn, the latest snapshots are drawn into
+//! a free stream buffer on the shared context and queued with the fence of that draw. Monitors
+//! being cast compose rather than scan a buffer out, like captures. A cast keeps the size its
+//! monitor had when it started; a paused cast sends one black frame and then nothing.
+//!
+//! The PipeWire side is behind the `screencast` feature. Without it every cast fails to start.
+//!
+//! [`RenderCmd::StartScreencast`]: crate::comms::server2render::RenderCmd::StartScreencast
+
+#[cfg(feature = "screencast")]
+mod pipewire;
+#[cfg(not(feature = "screencast"))]
+#[path = "unsupported.rs"]
+mod pipewire;
+
+use std::{
+	collections::HashMap,
+	ffi::c_void,
+	os::fd::{AsFd, OwnedFd},
+	sync::Arc,
+};
+
+use drm::buffer::DrmFourcc;
+use easydrm::gl;
+use skia_safe::{Color, ColorType, Image, Surface};
+
+use super::{
+	dmabuf_import::{DmaBufTexture, ImportParams, ImportPlane, SkiaDmaBufTexture},
+	skia_backend::SkiaContext,
+	submit_fence::SubmitFences,
+};
+use crate::{comms::render2server::RenderEvtTx, monitor::MonitorId};
+use pipewire::Service;
+
+/// A stream buffer's dmabuf, for the renderer to draw frames into.
+pub(super) struct StreamBuffer {
+	pub slot: u32,
+	pub fd: OwnedFd,
+	pub stride: u32,
+	pub offset: u32,
+	pub modifier: u64,
+}
+
+#[derive(Default)]
+pub(super) struct Screencasts {
+	/// Connected to PipeWire with the first cast.
+	service: Option<Service>,
+	casts: HashMap<u64, Cast>,
+}
+
+struct Cast {
+	/// Logical monitor streamed.
+	monitor_id: MonitorId,
+	size: (u32, u32),
+	paused: bool,
+	/// Latest snapshot of each tile, by its offset within the monitor.
+	tiles: HashMap<(i32, i32), Image>,
+	/// Set when there is a frame the stream hasn't been sent yet.
+	damaged: bool,
+	/// Stream buffers imported as render targets, by slot.
+	targets: HashMap<u32, CastTarget>,
+}
+
+struct CastTarget {
+	surface: Surface,
+	_texture: SkiaDmaBufTexture,
+}
+
+impl Screencasts {
+	/// Starts streaming `monitor_id`, `size` pixels large, at most at `refresh_rate`. Fails when
+	/// Shift can't talk to PipeWire; otherwise the PipeWire thread reports the outcome itself.
+	pub fn start(
+		&mut self,
+		cast_id: u64,
+		monitor_id: MonitorId,
+		size: (u32, u32),
+		refresh_rate: u32,
+		events: &RenderEvtTx,
+	) -> Result<(), Arc<str>> {
+		let service = match &mut self.service {
+			Some(service) => service,
+			None => self.service.insert(Service::spawn(events.clone())?),
+		};
+		service.start(cast_id, size, refresh_rate);
+		self.casts.insert(
+			cast_id,
+			Cast {
+				monitor_id,
+				size,
+				paused: false,
+				tiles: HashMap::new(),
+				damaged: false,
+				targets: HashMap::new(),
+			},
+		);
+		Ok(())
+	}
+
+	pub fn stop(&mut self, cast_id: u64) {
+		if self.casts.remove(&cast_id).is_some()
+			&& let Some(service) = &self.service
+		{
+			service.stop(cast_id);
+		}
+	}
+
+	/// Pauses a cast, sending black from now on, or resumes it with the monitor's next frame.
+	/// Returns the cast's monitor when that changed anything.
+	pub fn set_paused(&mut self, cast_id: u64, paused: bool) -> Option<MonitorId> {
+		let cast = self.casts.get_mut(&cast_id)?;
+		if cast.paused == paused {
+			return None;
+		}
+		cast.paused = paused;
+		cast.tiles.clear();
+		cast.damaged = paused;
+		Some(cast.monitor_id)
+	}
+
+	/// Whether frames of the logical monitor `monitor_id` are streamed, so its outputs compose.
+	pub fn casting(&self, monitor_id: MonitorId) -> bool {
+		self
+			.casts
+			.values()
+			.any(|cast| cast.monitor_id == monitor_id && !cast.paused)
+	}
+
+	/// Keeps `frame`, the part of `monitor_id` at `offset`, for the monitor's next cast frame.
+	pub fn add_tile(&mut self, monitor_id: MonitorId, offset: (i32, i32), frame: &Image) {
+		for cast in self.casts.values_mut() {
+			if cast.monitor_id == monitor_id && !cast.paused {
+				cast.tiles.insert(offset, frame.clone());
+				cast.damaged = true;
+			}
+		}
+	}
+
+	/// Whether [`Self::draw`] has something to do.
+	pub fn has_work(&self) -> bool {
+		!self.casts.is_empty()
+	}
+
+	/// Whether a cast has a frame to send and a buffer to send it in, or its stream ended, so
+	/// the renderer should draw even though nothing on screen changed.
+	pub fn waiting(&self) -> bool {
+		let Some(service) = &self.service else {
+			return false;
+		};
+		self
+			.casts
+			.iter()
+			.any(|(cast_id, cast)| (cast.damaged && service.ready(*cast_id)) || service.ended(*cast_id))
+	}
+
+	/// Imports new stream buffers, drops casts whose stream ended and draws the damaged ones
+	/// into a free buffer each. Expects the shared context current, with `gl` its functions.
+	pub fn draw(
+		&mut self,
+		skia: &mut SkiaContext,
+		gl: &gl::Gles2,
+		proc_loader: &dyn Fn(&str) -> *const c_void,
+		submit_fences: &SubmitFences,
+	) {
+		let Some(service) = &self.service else {
+			return;
+		};
+		self.casts.retain(|cast_id, _| {
+			let ended = service.ended(*cast_id);
+			if ended {
+				service.stop(*cast_id);
+			}
+			!ended
+		});
+		let mut drawn = Vec::new();
+		for (cast_id, cast) in &mut self.casts {
+			for buffer in service.take_buffers(*cast_id) {
+				let slot = buffer.slot;
+				match import(skia, gl, proc_loader, cast.size, buffer) {
+					Ok(target) => {
+						cast.targets.insert(slot, target);
+					}
+					Err(e) => tracing::warn!(cast_id, slot, "failed to import a screencast buffer: {e}"),
+				}
+			}
+			if !cast.damaged {
+				continue;
+			}
+			let Some(slot) = service.free_slot(*cast_id) else {
+				continue;
+			};
+			let Some(target) = cast.targets.get_mut(&slot) else {
+				continue;
+			};
+			let canvas = target.surface.canvas();
+			canvas.clear(Color::BLACK);
+			for (offset, image) in &cast.tiles {
+				canvas.draw_image(image, (offset.0 as f32, offset.1 as f32), None);
+			}
+			cast.damaged = false;
+			drawn.push((*cast_id, slot));
+		}
+		if drawn.is_empty() {
+			return;
+		}
+		skia.submit();
+		let fence = submit_fences.export(gl);
+		for (cast_id, slot) in drawn {
+			let fence = fence
+				.as_ref()
+				.and_then(|fence| fence.as_fd().try_clone_to_owned().ok());
+			service.queue(cast_id, slot, fence);
+		}
+	}
+}
+
+/// Imports a stream buffer as a surface to draw the cast's frames on.
+fn import(
+	skia: &mut SkiaContext,
+	gl: &gl::Gles2,
+	proc_loader: &dyn Fn(&str) -> *const c_void,
+	size: (u32, u32),
+	buffer: StreamBuffer,
+) -> Result<CastTarget, Arc<str>> {
+	let label = format!("screencast_buffer_{}", buffer.slot);
+	let params = ImportParams {
+		width: size.0 as i32,
+		height: size.1 as i32,
+		fourcc: DrmFourcc::Xrgb8888 as i32,
+		modifier: Some(buffer.modifier),
+		planes: vec![ImportPlane {
+			fd: buffer.fd,
+			offset: buffer.offset as i32,
+			stride: buffer.stride as i32,
+		}],
+	};
+	let texture = DmaBufTexture::import(gl, proc_loader, params)
+		.and_then(|texture| texture.to_skia(label))
+		.map_err(|e| Arc::<str>::from(e.to_string()))?;
+	let surface = skia
+		.wrap_texture_target(&texture.backend_texture, ColorType::RGBA8888)
+		.ok_or_else(|| Arc::<str>::from("the buffer can't be rendered to"))?;
+	Ok(CastTarget {
+		surface,
+		_texture: texture,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cast(monitor_id: MonitorId) -> Cast {
+		Cast {
+			monitor_id,
+			size: (640, 480),
+			paused: false,
+			tiles: HashMap::new(),
+			damaged: false,
+			targets: HashMap::new(),
+		}
+	}
+
+	#[test]
+	fn paused_casts_send_one_black_frame() {
+		let monitor_id = "mon_1".parse().expect("monitor id");
+		let mut casts = Screencasts::default();
+		casts.casts.insert(1, cast(monitor_id));
+		assert!(casts.casting(monitor_id));
+		assert_eq!(casts.set_paused(1, true), Some(monitor_id));
+		assert_eq!(casts.set_paused(1, true), None);
+		assert!(!casts.casting(monitor_id), "paused monitors may scan out");
+		let paused = &casts.casts[&1];
+		assert!(paused.damaged && paused.tiles.is_empty());
+		casts.set_paused(1, false);
+		assert!(!casts.casts[&1].damaged, "resumes with the next frame");
+	}
+}
@@ -0,0 +1,632 @@
+//! The PipeWire end of screencasts: a thread running a PipeWire main loop with one video source
+//! stream per cast.
+//!
+//! Shift allocates the streams' buffers itself, as linear XRGB8888 GBM buffer objects, and hands
+//! their dmabufs both to PipeWire and to the renderer, which draws frames into them. Buffers
+//! PipeWire gives back are dequeued into the cast's free list; the renderer fills one and queues
+//! it with the fence of its draw, which this thread waits for before sending the buffer on.
+
+use std::{
+	cell::RefCell,
+	collections::{HashMap, VecDeque},
+	ffi::c_void,
+	os::fd::{AsRawFd, OwnedFd},
+	rc::Rc,
+	sync::{Arc, Mutex, PoisonError, mpsc},
+	thread::JoinHandle,
+};
+
+use gbm::{BufferObject, BufferObjectFlags, Format};
+use pipewire as pw;
+use pw::{
+	properties::properties,
+	spa::{
+		buffer::{DataFlags, DataType},
+		param::{
+			ParamType,
+			format::{FormatProperties, MediaSubtype, MediaType},
+			video::VideoFormat,
+		},
+		pod::{ChoiceValue, Object, Pod, Property, PropertyFlags, Value, serialize::PodSerializer},
+		utils::{Choice, ChoiceEnum, ChoiceFlags, Direction, Fraction, Id, Rectangle, SpaTypes},
+	},
+	stream::{Stream, StreamFlags, StreamListener, StreamState},
+};
+
+use super::StreamBuffer;
+use crate::{
+	comms::render2server::{RenderEvt, RenderEvtTx},
+	drm_card::Card,
+};
+
+/// Buffers each stream cycles through: one shown by the consumer, one being drawn, one spare.
+const BUFFER_COUNT: usize = 3;
+/// How long to wait for the GPU to finish a frame, in milliseconds, before sending it anyway.
+const FENCE_TIMEOUT_MS: i32 = 100;
+
+type SharedCasts = Arc<Mutex<HashMap<u64, SharedCast>>>;
+
+pub struct Service {
+	commands: pw::channel::Sender<Command>,
+	shared: SharedCasts,
+	thread: Option<JoinHandle<()>>,
+}
+
+/// What the PipeWire thread and the renderer both see of a cast.
+#[derive(Default)]
+struct SharedCast {
+	/// Buffers PipeWire took since the renderer last looked.
+	added: Vec<StreamBuffer>,
+	/// Slots dequeued for the renderer to fill.
+	free: VecDeque<u32>,
+}
+
+enum Command {
+	Start {
+		cast_id: u64,
+		size: (u32, u32),
+		refresh_rate: u32,
+	},
+	Stop {
+		cast_id: u64,
+	},
+	Frame {
+		cast_id: u64,
+		slot: u32,
+		fence: Option<OwnedFd>,
+	},
+	Quit,
+}
+
+impl Service {
+	/// Connects to the PipeWire daemon on a new thread. Fails when there is none to talk to or
+	/// no DRM card to allocate buffers on.
+	pub fn spawn(events: RenderEvtTx) -> Result<Self, Arc<str>> {
+		let (commands, receiver) = pw::channel::channel();
+		let shared = SharedCasts::default();
+		let (ready_tx, ready_rx) = mpsc::channel();
+		let thread = std::thread::Builder::new()
+			.name("shift-pipewire".into())
+			.spawn({
+				let shared = Arc::clone(&shared);
+				move || {
+					if let Err(reason) = run(receiver, shared, events, &ready_tx) {
+						let _ = ready_tx.send(Err(reason));
+					}
+				}
+			})
+			.map_err(|e| Arc::from(format!("failed to spawn the PipeWire thread: {e}")))?;
+		match ready_rx.recv() {
+			Ok(Ok(())) => Ok(Self {
+				commands,
+				shared,
+				thread: Some(thread),
+			}),
+			Ok(Err(reason)) => {
+				let _ = thread.join();
+				Err(reason)
+			}
+			Err(_) => Err("the PipeWire thread exited".into()),
+		}
+	}
+
+	pub fn start(&self, cast_id: u64, size: (u32, u32), refresh_rate: u32) {
+		self.lock().insert(cast_id, SharedCast::default());
+		self.send(Command::Start {
+			cast_id,
+			size,
+			refresh_rate,
+		});
+	}
+
+	pub fn stop(&self, cast_id: u64) {
+		self.lock().remove(&cast_id);
+		self.send(Command::Stop { cast_id });
+	}
+
+	/// Whether `cast_id`'s stream failed, or never started.
+	pub fn ended(&self, cast_id: u64) -> bool {
+		!self.lock().contains_key(&cast_id)
+	}
+
+	/// Whether `cast_id` has buffers to import or fill, or ended.
+	pub fn ready(&self, cast_id: u64) -> bool {
+		self
+			.lock()
+			.get(&cast_id)
+			.is_none_or(|cast| !cast.added.is_empty() || !cast.free.is_empty())
+	}
+
+	/// Buffers added to `cast_id`'s stream since the last call.
+	pub fn take_buffers(&self, cast_id: u64) -> Vec<StreamBuffer> {
+		self
+			.lock()
+			.get_mut(&cast_id)
+			.map(|cast| std::mem::take(&mut cast.added))
+			.unwrap_or_default()
+	}
+
+	/// A buffer of `cast_id` free to draw the next frame into.
+	pub fn free_slot(&self, cast_id: u64) -> Option<u32> {
+		self.lock().get_mut(&cast_id)?.free.pop_front()
+	}
+
+	/// Sends the frame drawn into `slot` once `fence` signals.
+	pub fn queue(&self, cast_id: u64, slot: u32, fence: Option<OwnedFd>) {
+		self.send(Command::Frame {
+			cast_id,
+			slot,
+			fence,
+		});
+	}
+
+	fn send(&self, command: Command) {
+		if self.commands.send(command).is_err() {
+			tracing::warn!("the PipeWire thread is gone");
+		}
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, SharedCast>> {
+		self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Drop for Service {
+	fn drop(&mut self) {
+		let _ = self.commands.send(Command::Quit);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+struct CastStream {
+	// Unhooked before the stream it listens to is destroyed.
+	_listener: StreamListener<CastState>,
+	stream: Stream,
+	/// Dequeued buffers by slot, waiting for the renderer to fill them.
+	dequeued: Rc<RefCell<HashMap<u32, *mut pw::sys::pw_buffer>>>,
+}
+
+/// State of one stream's callbacks.
+struct CastState {
+	cast_id: u64,
+	height: u32,
+	/// Buffer objects the stream's buffers point at, in slot order.
+	buffer_objects: Vec<BufferObject<()>>,
+	/// Dmabufs handed to PipeWire, kept open while it uses them.
+	fds: Vec<OwnedFd>,
+	dequeued: Rc<RefCell<HashMap<u32, *mut pw::sys::pw_buffer>>>,
+	shared: SharedCasts,
+	events: RenderEvtTx,
+	started: bool,
+}
+
+fn run(
+	receiver: pw::channel::Receiver<Command>,
+	shared: SharedCasts,
+	events: RenderEvtTx,
+	ready: &mpsc::Sender<Result<(), Arc<str>>>,
+) -> Result<(), Arc<str>> {
+	let pw_error = |e: pw::Error| Arc::<str>::from(format!("PipeWire is unavailable: {e}"));
+	pw::init();
+	let main_loop = pw::main_loop::MainLoop::new(None).map_err(pw_error)?;
+	let context = pw::context::Context::new(&main_loop).map_err(pw_error)?;
+	let core = context.connect(None).map_err(pw_error)?;
+	let (_, card) = Card::open_all()
+		.into_iter()
+		.next()
+		.ok_or_else(|| Arc::<str>::from("no DRM card to allocate screencast buffers on"))?;
+	let gbm =
+		gbm::Device::new(card).map_err(|e| Arc::<str>::from(format!("failed to open GBM: {e}")))?;
+	let streams = Rc::new(RefCell::new(HashMap::<u64, CastStream>::new()));
+	let weak_loop = main_loop.downgrade();
+	let _receiver = receiver.attach(main_loop.loop_(), move |command| match command {
+		Command::Start {
+			cast_id,
+			size,
+			refresh_rate,
+		} => {
+			let result = start_stream(&core, &gbm, &shared, &events, cast_id, size, refresh_rate);
+			match result {
+				Ok(stream) => {
+					streams.borrow_mut().insert(cast_id, stream);
+				}
+				Err(reason) => {
+					shared
+						.lock()
+						.unwrap_or_else(PoisonError::into_inner)
+						.remove(&cast_id);
+					let _ = events.blocking_send(RenderEvt::ScreencastEnded { cast_id, reason });
+				}
+			}
+		}
+		Command::Stop { cast_id } => {
+			streams.borrow_mut().remove(&cast_id);
+		}
+		Command::Frame {
+			cast_id,
+			slot,
+			fence,
+		} => {
+			if let Some(cast) = streams.borrow().get(&cast_id) {
+				if let Some(fence) = fence {
+					wait_for(&fence);
+				}
+				cast.send(slot);
+			}
+		}
+		Command::Quit => {
+			streams.borrow_mut().clear();
+			if let Some(main_loop) = weak_loop.upgrade() {
+				main_loop.quit();
+			}
+		}
+	});
+	let _ = ready.send(Ok(()));
+	main_loop.run();
+	Ok(())
+}
+
+fn start_stream(
+	core: &pw::core::Core,
+	gbm: &gbm::Device<Card>,
+	shared: &SharedCasts,
+	events: &RenderEvtTx,
+	cast_id: u64,
+	size: (u32, u32),
+	refresh_rate: u32,
+) -> Result<CastStream, Arc<str>> {
+	let buffer_objects = (0..BUFFER_COUNT)
+		.map(|_| {
+			gbm.create_buffer_object::<()>(
+				size.0,
+				size.1,
+				Format::Xrgb8888,
+				BufferObjectFlags::RENDERING | BufferObjectFlags::LINEAR,
+			)
+		})
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| Arc::<str>::from(format!("failed to allocate screencast buffers: {e}")))?;
+	let modifier = u64::from(buffer_objects[0].modifier());
+	let buffers = buffers_param(size.1, buffer_objects[0].stride());
+	let pw_error = |e: pw::Error| Arc::<str>::from(format!("failed to create the stream: {e}"));
+	let stream = Stream::new(
+		core,
+		"shift-screencast",
+		properties! {
+			*pw::keys::MEDIA_CLASS => "Video/Source",
+			*pw::keys::NODE_NAME => "shift-screencast",
+		},
+	)
+	.map_err(pw_error)?;
+	let dequeued = Rc::new(RefCell::new(HashMap::new()));
+	let state = CastState {
+		cast_id,
+		height: size.1,
+		buffer_objects,
+		fds: Vec::new(),
+		dequeued: Rc::clone(&dequeued),
+		shared: Arc::clone(shared),
+		events: events.clone(),
+		started: false,
+	};
+	let listener = stream
+		.add_local_listener_with_user_data(state)
+		.state_changed(|stream, state, _, new| match new {
+			StreamState::Paused if !state.started => {
+				state.started = true;
+				let node_id = stream.node_id();
+				let _ = state.events.blocking_send(RenderEvt::ScreencastStarted {
+					cast_id: state.cast_id,
+					node_id,
+				});
+			}
+			StreamState::Streaming => state.reclaim(stream),
+			StreamState::Error(reason) => {
+				// The renderer notices the cast is gone and stops it.
+				state
+					.shared
+					.lock()
+					.unwrap_or_else(PoisonError::into_inner)
+					.remove(&state.cast_id);
+				let _ = state.events.blocking_send(RenderEvt::ScreencastEnded {
+					cast_id: state.cast_id,
+					reason: reason.into(),
+				});
+			}
+			_ => {}
+		})
+		.param_changed(move |stream, _, id, param| {
+			if id != ParamType::Format.as_raw() || param.is_none() {
+				return;
+			}
+			let Some(buffers) = Pod::from_bytes(&buffers) else {
+				return;
+			};
+			if let Err(e) = stream.update_params(&mut [buffers]) {
+				tracing::warn!("failed to negotiate screencast buffers: {e}");
+			}
+		})
+		// SAFETY: PipeWire hands the callbacks buffers of this stream.
+		.add_buffer(|_, state, buffer| unsafe { state.attach(buffer) })
+		.remove_buffer(|_, state, buffer| unsafe { state.detach(buffer) })
+		.process(|stream, state| state.reclaim(stream))
+		.register()
+		.map_err(pw_error)?;
+	let format = format_param(size, refresh_rate, modifier);
+	let format = Pod::from_bytes(&format).ok_or_else(|| Arc::<str>::from("invalid stream format"))?;
+	stream
+		.connect(
+			Direction::Output,
+			None,
+			StreamFlags::DRIVER | StreamFlags::ALLOC_BUFFERS,
+			&mut [format],
+		)
+		.map_err(pw_error)?;
+	Ok(CastStream {
+		_listener: listener,
+		stream,
+		dequeued,
+	})
+}
+
+impl Drop for CastStream {
+	/// Hands the buffers back before their dmabufs close.
+	fn drop(&mut self) {
+		if let Err(e) = self.stream.disconnect() {
+			tracing::warn!("failed to disconnect the screencast stream: {e}");
+		}
+	}
+}
+
+impl CastStream {
+	/// Queues the buffer in `slot`, filled by the renderer, and has the graph pick it up.
+	fn send(&self, slot: u32) {
+		let Some(buffer) = self.dequeued.borrow_mut().remove(&slot) else {
+			return;
+		};
+		// SAFETY: the buffer was dequeued from this stream and not queued since.
+		unsafe {
+			let spa_buffer = (*buffer).buffer;
+			if !spa_buffer.is_null() && (*spa_buffer).n_datas > 0 {
+				let data = &mut *(*spa_buffer).datas;
+				if !data.chunk.is_null() {
+					(*data.chunk).size = data.maxsize;
+					(*data.chunk).flags = 0;
+				}
+			}
+			self.stream.queue_raw_buffer(buffer);
+		}
+		if let Err(e) = self.stream.trigger_process() {
+			tracing::debug!("failed to trigger the screencast graph: {e}");
+		}
+	}
+}
+
+impl CastState {
+	/// Points a buffer PipeWire just allocated at the next unused buffer object, and offers its
+	/// dmabuf to the renderer.
+	///
+	/// # Safety
+	///
+	/// `buffer` must be a buffer of the stream, as passed to `add_buffer`.
+	unsafe fn attach(&mut self, buffer: *mut pw::sys::pw_buffer) {
+		let slot = self.fds.len() as u32;
+		let Some(buffer_object) = self.buffer_objects.get(slot as usize) else {
+			tracing::warn!(
+				cast_id = self.cast_id,
+				"PipeWire asked for more buffers than negotiated"
+			);
+			return;
+		};
+		let fds = buffer_object
+			.fd()
+			.ok()
+			.and_then(|fd| Some((fd.try_clone().ok()?, fd)));
+		let Some((renderer_fd, fd)) = fds else {
+			tracing::warn!(
+				cast_id = self.cast_id,
+				"failed to export a screencast buffer"
+			);
+			return;
+		};
+		let (stride, offset) = (buffer_object.stride(), buffer_object.offset(0));
+		// SAFETY: `buffer` is valid per the caller; PipeWire allocated its data and chunk.
+		unsafe {
+			let spa_buffer = (*buffer).buffer;
+			if spa_buffer.is_null() || (*spa_buffer).n_datas == 0 {
+				return;
+			}
+			let data = &mut *(*spa_buffer).datas;
+			data.type_ = DataType::DmaBuf.as_raw();
+			data.flags = DataFlags::READABLE.bits();
+			data.fd = fd.as_raw_fd() as i64;
+			data.mapoffset = 0;
+			data.maxsize = stride * self.height;
+			data.data = std::ptr::null_mut();
+			if !data.chunk.is_null() {
+				(*data.chunk).offset = offset;
+				(*data.chunk).stride = stride as i32;
+				(*data.chunk).size = data.maxsize;
+			}
+			// Slots are stored one-based, so buffers left unattached read as null.
+			(*buffer).user_data = (slot as usize + 1) as *mut c_void;
+		}
+		self.fds.push(fd);
+		let modifier = u64::from(buffer_object.modifier());
+		if let Some(cast) = self
+			.shared
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&self.cast_id)
+		{
+			cast.added.push(StreamBuffer {
+				slot,
+				fd: renderer_fd,
+				stride,
+				offset,
+				modifier,
+			});
+		}
+	}
+
+	/// Forgets a buffer PipeWire is about to free.
+	///
+	/// # Safety
+	///
+	/// `buffer` must be a buffer of the stream, as passed to `remove_buffer`.
+	unsafe fn detach(&mut self, buffer: *mut pw::sys::pw_buffer) {
+		// SAFETY: valid per the caller.
+		let Some(slot) = (unsafe { slot_of(buffer) }) else {
+			return;
+		};
+		self.dequeued.borrow_mut().remove(&slot);
+		if let Some(cast) = self
+			.shared
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&self.cast_id)
+		{
+			cast.free.retain(|free| *free != slot);
+		}
+	}
+
+	/// Dequeues every buffer consumers gave back, for the renderer to fill.
+	fn reclaim(&mut self, stream: &pw::stream::StreamRef) {
+		let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+		let Some(cast) = shared.get_mut(&self.cast_id) else {
+			return;
+		};
+		loop {
+			// SAFETY: dequeuing hands out a buffer of the stream, or null when there is none.
+			let buffer = unsafe { stream.dequeue_raw_buffer() };
+			if buffer.is_null() {
+				break;
+			}
+			// SAFETY: the buffer came from the stream just now.
+			let Some(slot) = (unsafe { slot_of(buffer) }) else {
+				// SAFETY: as above; unattached buffers carry no frames, so hand them back.
+				unsafe { stream.queue_raw_buffer(buffer) };
+				break;
+			};
+			self.dequeued.borrow_mut().insert(slot, buffer);
+			cast.free.push_back(slot);
+		}
+	}
+}
+
+/// Slot `attach` stored in a buffer.
+///
+/// # Safety
+///
+/// `buffer` must point to a live `pw_buffer`.
+unsafe fn slot_of(buffer: *mut pw::sys::pw_buffer) -> Option<u32> {
+	// SAFETY: valid per the caller.
+	let slot = unsafe { (*buffer).user_data } as usize;
+	slot.checked_sub(1).map(|slot| slot as u32)
+}
+
+/// Blocks until the sync file `fence` signals, or gives up after [`FENCE_TIMEOUT_MS`].
+fn wait_for(fence: &OwnedFd) {
+	let mut poll_fd = libc::pollfd {
+		fd: fence.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	// SAFETY: `poll_fd` is one valid pollfd for the duration of the call.
+	if unsafe { libc::poll(&mut poll_fd, 1, FENCE_TIMEOUT_MS) } <= 0 {
+		tracing::debug!("screencast frame fence didn't signal in time");
+	}
+}
+
+/// The one format streams offer: BGRx (XRGB8888 in DRM terms) dmabufs of the monitor's size,
+/// at most at its refresh rate.
+fn format_param(size: (u32, u32), refresh_rate: u32, modifier: u64) -> Vec<u8> {
+	let property = |key: FormatProperties, value| Property::new(key.as_raw(), value);
+	let mut modifier = property(
+		FormatProperties::VideoModifier,
+		Value::Long(modifier as i64),
+	);
+	modifier.flags = PropertyFlags::MANDATORY;
+	let refresh = Fraction {
+		num: refresh_rate.max(1),
+		denom: 1,
+	};
+	serialize(Object {
+		type_: SpaTypes::ObjectParamFormat.as_raw(),
+		id: ParamType::EnumFormat.as_raw(),
+		properties: vec![
+			property(
+				FormatProperties::MediaType,
+				Value::Id(Id(MediaType::Video.as_raw())),
+			),
+			property(
+				FormatProperties::MediaSubtype,
+				Value::Id(Id(MediaSubtype::Raw.as_raw())),
+			),
+			property(
+				FormatProperties::VideoFormat,
+				Value::Id(Id(VideoFormat::BGRx.as_raw())),
+			),
+			modifier,
+			property(
+				FormatProperties::VideoSize,
+				Value::Rectangle(Rectangle {
+					width: size.0,
+					height: size.1,
+				}),
+			),
+			// Frames come as the monitor is drawn, not at a fixed rate.
+			property(
+				FormatProperties::VideoFramerate,
+				Value::Fraction(Fraction { num: 0, denom: 1 }),
+			),
+			property(
+				FormatProperties::VideoMaxFramerate,
+				Value::Choice(ChoiceValue::Fraction(Choice(
+					ChoiceFlags::empty(),
+					ChoiceEnum::Range {
+						default: refresh,
+						min: Fraction { num: 1, denom: 1 },
+						max: refresh,
+					},
+				))),
+			),
+		],
+	})
+}
+
+/// Buffers of one dmabuf plane each, the way [`CastState::attach`] fills them in.
+fn buffers_param(height: u32, stride: u32) -> Vec<u8> {
+	let property = |key, value| Property::new(key, value);
+	serialize(Object {
+		type_: SpaTypes::ObjectParamBuffers.as_raw(),
+		id: ParamType::Buffers.as_raw(),
+		properties: vec![
+			property(
+				pw::spa::sys::SPA_PARAM_BUFFERS_buffers,
+				Value::Int(BUFFER_COUNT as i32),
+			),
+			property(pw::spa::sys::SPA_PARAM_BUFFERS_blocks, Value::Int(1)),
+			property(
+				pw::spa::sys::SPA_PARAM_BUFFERS_size,
+				Value::Int((stride * height) as i32),
+			),
+			property(
+				pw::spa::sys::SPA_PARAM_BUFFERS_stride,
+				Value::Int(stride as i32),
+			),
+			property(
+				pw::spa::sys::SPA_PARAM_BUFFERS_dataType,
+				Value::Int(1 << DataType::DmaBuf.as_raw()),
+			),
+		],
+	})
+}
+
+fn serialize(object: Object) -> Vec<u8> {
+	PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(object))
+		.map(|(cursor, _)| cursor.into_inner())
+		.unwrap_or_default()
+}
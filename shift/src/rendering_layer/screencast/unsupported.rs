@@ -0,0 +1,43 @@
+//! Stands in for the PipeWire service in builds without the `screencast` feature.
+
+use std::{os::fd::OwnedFd, sync::Arc};
+
+use super::StreamBuffer;
+use crate::comms::render2server::RenderEvtTx;
+
+/// Never exists: spawning it always fails.
+pub enum Service {}
+
+impl Service {
+	pub fn spawn(_events: RenderEvtTx) -> Result<Self, Arc<str>> {
+		Err("Shift was built without the screencast feature".into())
+	}
+
+	pub fn start(&self, _cast_id: u64, _size: (u32, u32), _refresh_rate: u32) {
+		match *self {}
+	}
+
+	pub fn stop(&self, _cast_id: u64) {
+		match *self {}
+	}
+
+	pub fn ended(&self, _cast_id: u64) -> bool {
+		match *self {}
+	}
+
+	pub fn ready(&self, _cast_id: u64) -> bool {
+		match *self {}
+	}
+
+	pub fn take_buffers(&self, _cast_id: u64) -> Vec<StreamBuffer> {
+		match *self {}
+	}
+
+	pub fn free_slot(&self, _cast_id: u64) -> Option<u32> {
+		match *self {}
+	}
+
+	pub fn queue(&self, _cast_id: u64, _slot: u32, _fence: Option<OwnedFd>) {
+		match *self {}
+	}
+}
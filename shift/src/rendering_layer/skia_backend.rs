@@ -0,0 +1,211 @@
+//! The Skia backend the renderer draws with. Everything the rendering layer does with Skia's
+//! GPU context (wrapping output framebuffers, creating offscreen targets, sampling imported
+//! textures, submitting work and managing the resource cache) goes through [`SkiaContext`], so
+//! which backend it is doesn't leak into the rest of the layer.
+//!
+//! `SHIFT_SKIA_BACKEND` picks the backend: `ganesh` (the default) is Skia's OpenGL ES backend,
+//! `graphite` its Dawn based one, for drivers where Ganesh's GL path underperforms. Graphite
+//! needs Skia built with it, which the rust-skia bindings Shift builds against don't offer yet;
+//! until they do, asking for it logs a warning and Ganesh is used.
+
+use std::{ffi::c_void, time::Duration};
+
+use skia_safe::{
+	AlphaType, ColorType, Image, ImageInfo, Surface,
+	gpu::{self, PurgeResourceOptions, gl::FramebufferInfo},
+};
+
+use super::RenderError;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum SkiaBackendKind {
+	#[default]
+	Ganesh,
+	Graphite,
+}
+
+impl SkiaBackendKind {
+	pub fn from_env() -> Self {
+		std::env::var("SHIFT_SKIA_BACKEND")
+			.map(|value| Self::parse(&value))
+			.unwrap_or_default()
+	}
+
+	fn parse(value: &str) -> Self {
+		match value.trim().to_ascii_lowercase().as_str() {
+			"" | "ganesh" | "gl" => SkiaBackendKind::Ganesh,
+			"graphite" | "dawn" => SkiaBackendKind::Graphite,
+			other => {
+				tracing::warn!(backend = other, "unknown SHIFT_SKIA_BACKEND, using ganesh");
+				SkiaBackendKind::Ganesh
+			}
+		}
+	}
+}
+
+/// What Skia's resource cache holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CacheUsage {
+	pub resource_count: u64,
+	pub resource_bytes: u64,
+	pub purgeable_bytes: u64,
+}
+
+/// Skia's GPU context on the shared GL context.
+pub(super) struct SkiaContext {
+	gr: gpu::DirectContext,
+}
+
+impl SkiaContext {
+	/// Creates the context on the current GL context, whose functions `proc_loader` resolves.
+	pub fn open(proc_loader: &dyn Fn(&str) -> *const c_void) -> Result<Self, RenderError> {
+		if SkiaBackendKind::from_env() == SkiaBackendKind::Graphite {
+			tracing::warn!("Skia was built without Graphite, using ganesh");
+		}
+		let interface =
+			gpu::gl::Interface::new_load_with(|s| proc_loader(s)).ok_or(RenderError::SkiaGlInterface)?;
+		let gr =
+			gpu::direct_contexts::make_gl(interface, None).ok_or(RenderError::SkiaDirectContext)?;
+		Ok(Self { gr })
+	}
+
+	/// A surface drawing into the GL framebuffer `fbo`, allocated as `color_type`.
+	pub fn wrap_framebuffer(
+		&mut self,
+		fbo: u32,
+		size: (i32, i32),
+		color_type: ColorType,
+	) -> Option<Surface> {
+		let fb_info = FramebufferInfo {
+			fboid: fbo,
+			format: gl_format(color_type).into(),
+			protected: gpu::Protected::No,
+		};
+		let backend_rt = gpu::backend_render_targets::make_gl(
+			size, 0, // samples
+			8, // stencil
+			fb_info,
+		);
+		gpu::surfaces::wrap_backend_render_target(
+			&mut self.gr,
+			&backend_rt,
+			gpu::SurfaceOrigin::TopLeft,
+			color_type,
+			None,
+			None,
+		)
+	}
+
+	/// An offscreen surface Skia allocates and caches.
+	pub fn render_target(&mut self, info: &ImageInfo) -> Option<Surface> {
+		gpu::surfaces::render_target(
+			&mut self.gr,
+			gpu::Budgeted::Yes,
+			info,
+			None,
+			gpu::SurfaceOrigin::TopLeft,
+			None,
+			false,
+			None,
+		)
+	}
+
+	/// A surface drawing into `texture`.
+	pub fn wrap_texture_target(
+		&mut self,
+		texture: &gpu::BackendTexture,
+		color_type: ColorType,
+	) -> Option<Surface> {
+		gpu::surfaces::wrap_backend_texture(
+			&mut self.gr,
+			texture,
+			gpu::SurfaceOrigin::TopLeft,
+			None,
+			color_type,
+			None,
+			None,
+		)
+	}
+
+	/// An image sampling `texture`, which stays owned by the caller.
+	pub fn texture_image(
+		&mut self,
+		texture: &gpu::BackendTexture,
+		color_type: ColorType,
+		alpha_type: AlphaType,
+	) -> Option<Image> {
+		Image::from_texture(
+			&mut self.gr,
+			texture,
+			gpu::SurfaceOrigin::TopLeft,
+			color_type,
+			alpha_type,
+			None,
+		)
+	}
+
+	/// Hands recorded work to the GPU, so fences exported afterwards cover it.
+	pub fn submit(&mut self) {
+		self.gr.flush_and_submit();
+	}
+
+	/// Like [`Self::submit`], then waits for the GPU to finish it.
+	pub fn submit_and_wait(&mut self) {
+		self.gr.flush_submit_and_sync_cpu();
+	}
+
+	/// Stops issuing GPU calls, for when the device is gone.
+	pub fn abandon(&mut self) {
+		self.gr.abandon();
+	}
+
+	pub fn set_cache_budget(&mut self, bytes: u64) {
+		self.gr.set_resource_cache_limit(bytes as usize);
+	}
+
+	pub fn cache_usage(&self) -> CacheUsage {
+		let usage = self.gr.resource_cache_usage();
+		CacheUsage {
+			resource_count: usage.resource_count as u64,
+			resource_bytes: usage.resource_bytes as u64,
+			purgeable_bytes: self.gr.resource_cache_purgeable_bytes() as u64,
+		}
+	}
+
+	/// Releases every cached resource nothing uses right now.
+	pub fn purge_unlocked(&mut self) {
+		self
+			.gr
+			.purge_unlocked_resources(PurgeResourceOptions::AllResources);
+	}
+
+	/// Releases cached resources unused for `age`.
+	pub fn purge_unused(&mut self, age: Duration) {
+		self.gr.perform_deferred_cleanup(age, None);
+	}
+}
+
+/// The GL format backing framebuffers and textures Skia sees as `color_type`.
+pub(super) fn gl_format(color_type: ColorType) -> gpu::gl::Format {
+	match color_type {
+		ColorType::RGBA1010102 => gpu::gl::Format::RGB10_A2,
+		ColorType::RGBAF16 => gpu::gl::Format::RGBA16F,
+		_ => gpu::gl::Format::RGBA8,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn backend_names_parse() {
+		assert_eq!(
+			SkiaBackendKind::parse("Graphite"),
+			SkiaBackendKind::Graphite
+		);
+		assert_eq!(SkiaBackendKind::parse(" ganesh "), SkiaBackendKind::Ganesh);
+		assert_eq!(SkiaBackendKind::parse("vulkan"), SkiaBackendKind::Ganesh);
+		assert_eq!(gl_format(ColorType::RGBAF16), gpu::gl::Format::RGBA16F);
+	}
+}
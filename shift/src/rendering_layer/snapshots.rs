@@ -0,0 +1,151 @@
+//! Last frames of sleeping sessions.
+//!
+//! When a session goes to sleep, the frame it shows on each monitor is copied, so switching
+//! back to it and the switcher's thumbnails have a frame to show even when its buffers went
+//! away in the meantime (a relink it can't answer while asleep, a monitor resize) and before it
+//! renders again. A copy is dropped as soon as the session presents a newer frame there.
+//!
+//! `SHIFT_SLEEP_SNAPSHOTS` says where copies are kept: `gpu` (the default) as offscreen
+//! textures, `cpu` in system memory, uploaded again when drawn, for GPUs short on memory, or
+//! `off`. The software renderer always keeps a CPU copy of every session's frame, so it needs
+//! none of this.
+
+use std::collections::HashMap;
+
+use skia_safe::{AlphaType, BlendMode, Data, Image, Paint, images};
+
+use super::skia_backend::SkiaContext;
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum SnapshotMode {
+	#[default]
+	Gpu,
+	Cpu,
+	Off,
+}
+
+impl SnapshotMode {
+	fn from_env() -> Self {
+		std::env::var("SHIFT_SLEEP_SNAPSHOTS")
+			.map(|value| Self::parse(&value))
+			.unwrap_or_default()
+	}
+
+	fn parse(value: &str) -> Self {
+		match value.trim().to_ascii_lowercase().as_str() {
+			"" | "gpu" => SnapshotMode::Gpu,
+			"cpu" => SnapshotMode::Cpu,
+			"off" | "0" => SnapshotMode::Off,
+			other => {
+				tracing::warn!(mode = other, "unknown SHIFT_SLEEP_SNAPSHOTS, using gpu");
+				SnapshotMode::Gpu
+			}
+		}
+	}
+}
+
+pub(super) struct SessionSnapshots {
+	mode: SnapshotMode,
+	frames: HashMap<(MonitorId, SessionId), Image>,
+}
+
+impl SessionSnapshots {
+	/// Reads `SHIFT_SLEEP_SNAPSHOTS` (default `gpu`).
+	pub fn from_env() -> Self {
+		Self {
+			mode: SnapshotMode::from_env(),
+			frames: HashMap::new(),
+		}
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.mode != SnapshotMode::Off
+	}
+
+	/// Keeps a copy of `image`, the frame `session_id` shows on `monitor_id`.
+	pub fn take(
+		&mut self,
+		skia: &mut SkiaContext,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		image: &Image,
+	) {
+		if !self.enabled() {
+			return;
+		}
+		match copy(skia, image, self.mode == SnapshotMode::Cpu) {
+			Some(copy) => {
+				self.frames.insert((monitor_id, session_id), copy);
+			}
+			None => {
+				tracing::warn!(%monitor_id, %session_id, "failed to copy the last frame of a session");
+			}
+		}
+	}
+
+	pub fn get(&self, monitor_id: MonitorId, session_id: SessionId) -> Option<Image> {
+		self.frames.get(&(monitor_id, session_id)).cloned()
+	}
+
+	/// `session_id` presented a newer frame on `monitor_id`.
+	pub fn presented(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self.frames.remove(&(monitor_id, session_id));
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self
+			.frames
+			.retain(|(snapshot_monitor, _), _| *snapshot_monitor != monitor_id);
+	}
+
+	pub fn remove_session(&mut self, session_id: SessionId) {
+		self
+			.frames
+			.retain(|(_, snapshot_session), _| *snapshot_session != session_id);
+	}
+
+	pub fn clear(&mut self) {
+		self.frames.clear();
+	}
+}
+
+/// Draws `image` into an offscreen target, and reads that back into system memory when
+/// `in_memory`, so the copy no longer depends on the session's buffer.
+fn copy(skia: &mut SkiaContext, image: &Image, in_memory: bool) -> Option<Image> {
+	let mut info = image.image_info();
+	// GPU targets can't hold straight alpha; the copy is premultiplied instead.
+	if info.alpha_type() == AlphaType::Unpremul {
+		info = info.with_alpha_type(AlphaType::Premul);
+	}
+	let mut surface = skia.render_target(&info)?;
+	let mut paint = Paint::default();
+	paint.set_blend_mode(BlendMode::Src);
+	surface.canvas().draw_image(image, (0, 0), Some(&paint));
+	let copy = if in_memory {
+		let row_bytes = info.min_row_bytes();
+		let mut pixels = vec![0u8; row_bytes * info.height() as usize];
+		if !surface.read_pixels(&info, &mut pixels, row_bytes, (0, 0)) {
+			return None;
+		}
+		images::raster_from_data(&info, Data::new_copy(&pixels), row_bytes)
+	} else {
+		Some(surface.image_snapshot())
+	};
+	// The session may drop its buffer as soon as it is asleep.
+	skia.submit();
+	copy
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn snapshot_modes_parse() {
+		assert_eq!(SnapshotMode::parse("CPU"), SnapshotMode::Cpu);
+		assert_eq!(SnapshotMode::parse(" off "), SnapshotMode::Off);
+		assert_eq!(SnapshotMode::parse(""), SnapshotMode::Gpu);
+		assert_eq!(SnapshotMode::parse("vram"), SnapshotMode::Gpu);
+	}
+}
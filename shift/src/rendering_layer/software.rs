@@ -0,0 +1,645 @@
+//! Rendering without a GPU, for machines where no EGL context can be made, such as VMs without
+//! 3D acceleration or early boot.
+//!
+//! [`SoftwareDisplay`] drives the connected outputs of a card by itself with the legacy
+//! modesetting ioctls: each gets its preferred mode, or the one `set_monitor_mode` picks, and
+//! two dumb buffers of its size, and frames are
+//! composed with Skia's raster backend and flipped on at the next vblank. Frames are copied out
+//! of client buffers when they are swapped, through a CPU mapping, so only buffers a CPU can
+//! read show up: memfds and linear dma-bufs, one plane each, in the formats whose memory layout
+//! [`pixel_formats`](super::pixel_formats) gives (8888 and 2101010 RGB, `RG16`, `XB4H` and
+//! `AB4H`). The cursor, output transforms and monitor scales are composed too.
+//! [`HeadlessRenderer`] keeps the buffer bookkeeping, as without a display.
+//!
+//! Shift falls back to it when the GPU renderer fails to start, or right away with
+//! `SHIFT_SOFTWARE_RENDERING=1`.
+//!
+//! [`HeadlessRenderer`]: super::headless::HeadlessRenderer
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	os::fd::{AsFd, OwnedFd},
+};
+
+use drm::{
+	buffer::{Buffer as _, DrmFourcc},
+	control::{
+		Device as ControlDevice, Mode, ModeTypeFlags, PageFlipFlags, connector, crtc,
+		dumbbuffer::DumbBuffer, framebuffer,
+	},
+};
+use skia_safe::{AlphaType, Color, ColorType, Data, ImageInfo, Paint, Rect, images, surfaces};
+use tab_protocol::{FramebufferLinkPayload, MonitorScale, OutputTransform};
+
+use super::{
+	buffer_hash::read_mapped,
+	cursor,
+	formats::FALLBACK_FORMATS,
+	modes,
+	monitor_scale::buffer_ratio,
+	pixel_formats,
+	state::{BufferSlot, SlotKey},
+	surface_cache::output_matrix,
+};
+use crate::{
+	comms::server2render::CursorImage,
+	drm_card::Card,
+	monitor::{Monitor as ServerLayerMonitor, MonitorId},
+	sessions::SessionId,
+};
+
+pub struct SoftwareDisplay {
+	card: Card,
+	outputs: Vec<Output>,
+	/// Buffers sessions linked on the outputs.
+	buffers: HashMap<SlotKey, LinkedBuffer>,
+	/// Frame each session last swapped on each monitor.
+	frames: HashMap<(MonitorId, SessionId), Frame>,
+	cursor: Option<CursorImage>,
+	/// Monitor the pointer is on, and its hotspot position in monitor pixels.
+	pointer: Option<(MonitorId, (f64, f64))>,
+}
+
+struct Output {
+	monitor: ServerLayerMonitor,
+	connector: connector::Handle,
+	crtc: crtc::Handle,
+	/// Modes the connector offers.
+	modes: Vec<Mode>,
+	/// Frames are composed here, then copied into the back buffer.
+	staging: Vec<u8>,
+	buffers: [(DumbBuffer, framebuffer::Handle); 2],
+	/// Index of the buffer on screen.
+	front: usize,
+	/// Set when the output needs a new frame.
+	damaged: bool,
+	/// How frames are rotated or flipped onto the output.
+	transform: OutputTransform,
+	scale: MonitorScale,
+}
+
+struct LinkedBuffer {
+	fd: OwnedFd,
+	layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+	width: i32,
+	height: i32,
+	stride: usize,
+	offset: usize,
+	color_type: ColorType,
+	alpha_type: AlphaType,
+	/// Scale the session draws at, when it declared one.
+	scale: Option<MonitorScale>,
+}
+
+impl Layout {
+	/// How a link's buffers are read; `Err` carries the link failure reason.
+	fn of(payload: &FramebufferLinkPayload) -> Result<Self, &'static str> {
+		if payload
+			.explicit_modifier()
+			.is_some_and(|modifier| modifier != FramebufferLinkPayload::MOD_LINEAR)
+		{
+			return Err("unsupported_modifier");
+		}
+		let Some(format) = pixel_formats::lookup(payload.fourcc as u32) else {
+			return Err("unsupported_format");
+		};
+		match format.memory {
+			Some(color_type)
+				if payload.extra_planes.is_empty()
+					&& payload.width > 0
+					&& payload.height > 0
+					&& payload.offset >= 0
+					&& payload.stride > 0
+					&& payload.stride as usize >= payload.width as usize * color_type.bytes_per_pixel() =>
+			{
+				Ok(Self {
+					width: payload.width,
+					height: payload.height,
+					stride: payload.stride as usize,
+					offset: payload.offset as usize,
+					color_type,
+					alpha_type: format.alpha_type(payload.alpha_mode),
+					scale: payload.scale,
+				})
+			}
+			_ => Err("import_failed"),
+		}
+	}
+
+	/// Bytes from the first pixel to the last one.
+	fn len(&self) -> usize {
+		self.stride * (self.height as usize - 1)
+			+ self.width as usize * self.color_type.bytes_per_pixel()
+	}
+
+	fn info(&self) -> ImageInfo {
+		ImageInfo::new(
+			(self.width, self.height),
+			self.color_type,
+			self.alpha_type,
+			None,
+		)
+	}
+}
+
+struct Frame {
+	layout: Layout,
+	/// The frame from its first pixel, [`Layout::len`] bytes.
+	pixels: Vec<u8>,
+}
+
+impl SoftwareDisplay {
+	/// Sets up every connected output of the first card that has one.
+	pub fn open() -> io::Result<Self> {
+		let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no DRM card with a display");
+		for (path, card) in Card::open_all() {
+			// Only the DRM master may set modes; a failure shows up as soon as one is set.
+			let _ = card.acquire_master_lock();
+			match Self::open_card(card) {
+				Ok(display) if !display.outputs.is_empty() => {
+					tracing::info!(
+						card = %path.display(),
+						outputs = display.outputs.len(),
+						"rendering in software"
+					);
+					return Ok(display);
+				}
+				Ok(_) => {}
+				Err(e) => {
+					tracing::warn!(card = %path.display(), "can't render in software on this card: {e}");
+					last_error = e;
+				}
+			}
+		}
+		Err(last_error)
+	}
+
+	fn open_card(card: Card) -> io::Result<Self> {
+		let mut display = Self {
+			card,
+			outputs: Vec::new(),
+			buffers: HashMap::new(),
+			frames: HashMap::new(),
+			cursor: None,
+			pointer: None,
+		};
+		let resources = display.card.resource_handles()?;
+		let mut taken = HashSet::new();
+		for &handle in resources.connectors() {
+			let info = display.card.get_connector(handle, true)?;
+			if info.state() != connector::State::Connected {
+				continue;
+			}
+			let Some(&mode) = info
+				.modes()
+				.iter()
+				.find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+				.or_else(|| info.modes().first())
+			else {
+				continue;
+			};
+			let current = info
+				.current_encoder()
+				.and_then(|encoder| display.card.get_encoder(encoder).ok())
+				.and_then(|encoder| encoder.crtc());
+			let Some(crtc) = current.filter(|crtc| !taken.contains(crtc)).or_else(|| {
+				info
+					.encoders()
+					.iter()
+					.filter_map(|encoder| display.card.get_encoder(*encoder).ok())
+					.flat_map(|encoder| resources.filter_crtcs(encoder.possible_crtcs()))
+					.find(|crtc| !taken.contains(crtc))
+			}) else {
+				tracing::warn!(connector = ?handle, "no free CRTC for the connector");
+				continue;
+			};
+			let (width, height) = mode.size();
+			let size = (width.into(), height.into());
+			let front = display.create_buffer(size)?;
+			let buffers = match display.create_buffer(size) {
+				Ok(back) => [front, back],
+				Err(e) => {
+					display.destroy_buffers(&[front]);
+					return Err(e);
+				}
+			};
+			if let Err(e) = display
+				.card
+				.set_crtc(crtc, Some(buffers[0].1), (0, 0), &[handle], Some(mode))
+			{
+				display.destroy_buffers(&buffers);
+				return Err(e);
+			}
+			taken.insert(crtc);
+			let connector_id = u32::from(handle);
+			let name = format!("{}-{}", info.interface().as_str(), info.interface_id());
+			let edid = display.card.connector_blob(connector_id, b"EDID");
+			let id = crate::monitor::unique_id(&name, edid.as_deref(), |id| {
+				display.outputs.iter().any(|output| output.monitor.id == id)
+			});
+			display.outputs.push(Output {
+				monitor: ServerLayerMonitor {
+					id,
+					width: width.into(),
+					height: height.into(),
+					refresh_rate: mode.vrefresh().max(1),
+					connector_id,
+					name,
+					primary: false,
+					formats: FALLBACK_FORMATS.to_vec(),
+					scanout_formats: Vec::new(),
+					format_modifiers: Vec::new(),
+					hdr_eotfs: Vec::new(),
+					vrr_capable: false,
+					offscreen: false,
+					transform: Default::default(),
+					scale: Default::default(),
+					modes: modes::describe(info.modes(), modes::key(&mode)),
+					position: Default::default(),
+				},
+				connector: handle,
+				crtc,
+				modes: info.modes().to_vec(),
+				staging: vec![0; usize::from(width) * usize::from(height) * 4],
+				buffers,
+				front: 0,
+				damaged: true,
+				transform: OutputTransform::Normal,
+				scale: MonitorScale::ONE,
+			});
+		}
+		Ok(display)
+	}
+
+	fn create_buffer(&self, size: (u32, u32)) -> io::Result<(DumbBuffer, framebuffer::Handle)> {
+		let buffer = self
+			.card
+			.create_dumb_buffer(size, DrmFourcc::Xrgb8888, 32)?;
+		match self.card.add_framebuffer(&buffer, 24, 32) {
+			Ok(framebuffer) => Ok((buffer, framebuffer)),
+			Err(e) => {
+				let _ = self.card.destroy_dumb_buffer(buffer);
+				Err(e)
+			}
+		}
+	}
+
+	fn destroy_buffers(&self, buffers: &[(DumbBuffer, framebuffer::Handle)]) {
+		for &(buffer, framebuffer) in buffers {
+			let _ = self.card.destroy_framebuffer(framebuffer);
+			let _ = self.card.destroy_dumb_buffer(buffer);
+		}
+	}
+
+	pub(super) fn monitors(&self) -> Vec<ServerLayerMonitor> {
+		self
+			.outputs
+			.iter()
+			.map(|output| output.monitor.clone())
+			.collect()
+	}
+
+	/// Whether `monitor_id` is one of the display's outputs.
+	pub(super) fn shows(&self, monitor_id: MonitorId) -> bool {
+		self
+			.outputs
+			.iter()
+			.any(|output| output.monitor.id == monitor_id)
+	}
+
+	/// Keeps the buffers of a link to copy frames out of, after [`Self::unlink`] dropped the
+	/// previous ones. Fails with the reason to reject swaps with when a CPU can't read them.
+	pub(super) fn link(
+		&mut self,
+		session_id: SessionId,
+		payload: &FramebufferLinkPayload,
+		dma_bufs: [Vec<OwnedFd>; 2],
+	) -> Result<(), &'static str> {
+		let monitor_id = payload.monitor_id;
+		let layout = Layout::of(payload)?;
+		let mut linked = Vec::new();
+		for (index, fds) in dma_bufs.into_iter().enumerate() {
+			let (Some(slot), Ok([fd])) = (BufferSlot::from_index(index), <[OwnedFd; 1]>::try_from(fds))
+			else {
+				return Err("import_failed");
+			};
+			linked.push((SlotKey::new(monitor_id, session_id, slot), fd));
+		}
+		for (key, fd) in linked {
+			self.buffers.insert(key, LinkedBuffer { fd, layout });
+		}
+		Ok(())
+	}
+
+	/// Forgets the buffers and frame of a session on a monitor.
+	pub(super) fn unlink(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self
+			.buffers
+			.retain(|key, _| (key.monitor_id, key.session_id) != (monitor_id, session_id));
+		if self.frames.remove(&(monitor_id, session_id)).is_some() {
+			self.damage(monitor_id);
+		}
+	}
+
+	pub(super) fn remove_session(&mut self, session_id: SessionId) {
+		self.buffers.retain(|key, _| key.session_id != session_id);
+		self.frames.retain(|(_, session), _| *session != session_id);
+		self.damage_all();
+	}
+
+	/// Copies the frame out of the swapped buffer `key`. A failure keeps the previous frame.
+	pub(super) fn latch(&mut self, key: SlotKey) {
+		let Some(buffer) = self.buffers.get(&key) else {
+			return;
+		};
+		let layout = buffer.layout;
+		let range = layout.offset..layout.offset + layout.len();
+		let pixels = match read_mapped(buffer.fd.as_fd(), |bytes| {
+			bytes.get(range).map(<[u8]>::to_vec)
+		}) {
+			Ok(Some(Some(pixels))) => pixels,
+			Ok(_) => {
+				tracing::warn!(?key, "swapped buffer is smaller than its link says");
+				return;
+			}
+			Err(e) => {
+				tracing::warn!(?key, "failed to map a swapped buffer: {e}");
+				return;
+			}
+		};
+		self
+			.frames
+			.insert((key.monitor_id, key.session_id), Frame { layout, pixels });
+		self.damage(key.monitor_id);
+	}
+
+	pub(super) fn set_cursor(&mut self, monitor_id: MonitorId, image: Option<CursorImage>) {
+		self.cursor = image;
+		let (x, y) = self.pointer.map_or((0.0, 0.0), |(_, position)| position);
+		self.move_cursor(monitor_id, x, y);
+	}
+
+	pub(super) fn move_cursor(&mut self, monitor_id: MonitorId, x: f64, y: f64) {
+		if let Some((previous, _)) = self.pointer.replace((monitor_id, (x, y))) {
+			self.damage(previous);
+		}
+		self.damage(monitor_id);
+	}
+
+	pub(super) fn set_transform(&mut self, monitor_id: MonitorId, transform: OutputTransform) {
+		if let Some(output) = self
+			.outputs
+			.iter_mut()
+			.find(|output| output.monitor.id == monitor_id)
+		{
+			output.transform = transform;
+			output.damaged = true;
+		}
+	}
+
+	/// Switches `monitor_id` to the mode of its connector's that is `width`x`height` at `refresh`
+	/// Hz, with new buffers of its size. Returns the monitor in its new mode.
+	pub(super) fn set_mode(
+		&mut self,
+		monitor_id: MonitorId,
+		width: i32,
+		height: i32,
+		refresh: u32,
+	) -> Result<ServerLayerMonitor, &'static str> {
+		let Some(index) = self
+			.outputs
+			.iter()
+			.position(|output| output.monitor.id == monitor_id)
+		else {
+			return Err("unknown_monitor");
+		};
+		let Some(mode) = modes::find(&self.outputs[index].modes, width, height, refresh) else {
+			return Err("invalid_mode");
+		};
+		let (mode_width, mode_height) = mode.size();
+		let size = (mode_width.into(), mode_height.into());
+		let buffers = self
+			.create_buffer(size)
+			.and_then(|front| match self.create_buffer(size) {
+				Ok(back) => Ok([front, back]),
+				Err(e) => {
+					self.destroy_buffers(&[front]);
+					Err(e)
+				}
+			});
+		let buffers = match buffers {
+			Ok(buffers) => buffers,
+			Err(e) => {
+				tracing::warn!(%monitor_id, "no buffers for the new mode: {e}");
+				return Err("mode_set_failed");
+			}
+		};
+		let output = &self.outputs[index];
+		if let Err(e) = self.card.set_crtc(
+			output.crtc,
+			Some(buffers[0].1),
+			(0, 0),
+			&[output.connector],
+			Some(mode),
+		) {
+			tracing::warn!(%monitor_id, "software modeset failed: {e}");
+			self.destroy_buffers(&buffers);
+			return Err("mode_set_failed");
+		}
+		self.destroy_buffers(&output.buffers);
+		let output = &mut self.outputs[index];
+		output.buffers = buffers;
+		output.front = 0;
+		output.staging = vec![0; usize::from(mode_width) * usize::from(mode_height) * 4];
+		output.damaged = true;
+		output.monitor.width = mode_width.into();
+		output.monitor.height = mode_height.into();
+		output.monitor.refresh_rate = mode.vrefresh().max(1);
+		output.monitor.modes = modes::describe(&output.modes, modes::key(&mode));
+		Ok(output.monitor.clone())
+	}
+
+	pub(super) fn set_scale(&mut self, monitor_id: MonitorId, scale: MonitorScale) {
+		if let Some(output) = self
+			.outputs
+			.iter_mut()
+			.find(|output| output.monitor.id == monitor_id)
+		{
+			output.scale = scale;
+			output.damaged = true;
+		}
+	}
+
+	/// Something every output shows changed, e.g. the current session.
+	pub(super) fn damage_all(&mut self) {
+		for output in &mut self.outputs {
+			output.damaged = true;
+		}
+	}
+
+	fn damage(&mut self, monitor_id: MonitorId) {
+		for output in &mut self.outputs {
+			if output.monitor.id == monitor_id {
+				output.damaged = true;
+			}
+		}
+	}
+
+	/// Composes and flips every damaged output, showing `session_id`'s frames. Outputs whose
+	/// previous flip is still pending try again at the next call.
+	pub(super) fn present(&mut self, session_id: Option<SessionId>) {
+		for output in &mut self.outputs {
+			if !output.damaged {
+				continue;
+			}
+			let monitor_id = output.monitor.id;
+			let frame = session_id.and_then(|session_id| self.frames.get(&(monitor_id, session_id)));
+			let cursor = self
+				.pointer
+				.filter(|(pointer, _)| *pointer == monitor_id)
+				.and_then(|(_, position)| Some((self.cursor.as_ref()?, position)));
+			if let Err(e) = output.compose(frame, cursor) {
+				tracing::warn!(%monitor_id, "failed to compose a software frame: {e}");
+				output.damaged = false;
+				continue;
+			}
+			match output.flip(&self.card) {
+				Ok(()) => output.damaged = false,
+				// The previous flip hasn't happened yet.
+				Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {}
+				Err(e) => {
+					tracing::warn!(%monitor_id, "software page flip failed: {e}");
+					output.damaged = false;
+				}
+			}
+		}
+	}
+}
+
+impl Output {
+	/// Draws the next frame into the staging pixels.
+	fn compose(
+		&mut self,
+		frame: Option<&Frame>,
+		cursor: Option<(&CursorImage, (f64, f64))>,
+	) -> io::Result<()> {
+		let (width, height) = (self.monitor.width, self.monitor.height);
+		let info = ImageInfo::new_n32((width, height), AlphaType::Premul, None);
+		let row_bytes = width as usize * 4;
+		let mut surface = surfaces::wrap_pixels(&info, &mut self.staging, row_bytes, None)
+			.ok_or_else(|| io::Error::other("no raster surface for the output"))?;
+		let canvas = surface.canvas();
+		canvas.clear(Color::BLACK);
+		// Frames and the cursor are placed in the monitor's logical pixels.
+		let (width, height) = if self.transform.swaps_axes() {
+			(height, width)
+		} else {
+			(width, height)
+		};
+		canvas.concat(&output_matrix(
+			self.transform,
+			(width as f32, height as f32),
+		));
+		if let Some(frame) = frame {
+			// SAFETY: the image only lives until the draw below, while `frame` is borrowed.
+			let data = unsafe { Data::new_bytes(&frame.pixels) };
+			if let Some(image) = images::raster_from_data(&frame.layout.info(), data, frame.layout.stride)
+			{
+				let bounds = match buffer_ratio(self.scale, frame.layout.scale) {
+					Some(ratio) => Rect::from_wh(
+						frame.layout.width as f32 * ratio,
+						frame.layout.height as f32 * ratio,
+					),
+					None => Rect::from_iwh(width, height),
+				};
+				canvas.draw_image_rect(&image, None, bounds, &Paint::default());
+			}
+		}
+		if let Some((image, position)) = cursor
+			&& let Some(raster) = cursor::raster_image(image)
+		{
+			let (x, y) = cursor::top_left(image, position, (0, 0));
+			canvas.draw_image(&raster, (x as f32, y as f32), None);
+		}
+		Ok(())
+	}
+
+	/// Copies the staging pixels into the back buffer and flips to it.
+	fn flip(&mut self, card: &Card) -> io::Result<()> {
+		let back = 1 - self.front;
+		let row = self.monitor.width as usize * 4;
+		let (buffer, framebuffer) = &mut self.buffers[back];
+		let pitch = buffer.pitch() as usize;
+		let mut mapping = card.map_dumb_buffer(buffer)?;
+		for (y, line) in self.staging.chunks_exact(row).enumerate() {
+			mapping[y * pitch..y * pitch + row].copy_from_slice(line);
+		}
+		drop(mapping);
+		card.page_flip(self.crtc, *framebuffer, PageFlipFlags::empty(), None)?;
+		self.front = back;
+		Ok(())
+	}
+}
+
+impl Drop for SoftwareDisplay {
+	fn drop(&mut self) {
+		for output in &self.outputs {
+			self.destroy_buffers(&output.buffers);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn payload(fourcc: DrmFourcc, stride: i32) -> FramebufferLinkPayload {
+		FramebufferLinkPayload {
+			monitor_id: "mon_1".parse().expect("monitor id"),
+			width: 640,
+			height: 480,
+			stride,
+			offset: 0,
+			fourcc: fourcc as i32,
+			hdr_metadata: None,
+			extra_planes: Vec::new(),
+			modifier: None,
+			scale: None,
+			alpha_mode: None,
+		}
+	}
+
+	#[test]
+	fn only_linear_rgb_buffers_are_copied() {
+		let layout = Layout::of(&payload(DrmFourcc::Xbgr8888, 2560)).expect("layout");
+		assert_eq!(layout.color_type, ColorType::RGBA8888);
+		assert_eq!(layout.len(), 2560 * 479 + 640 * 4);
+		assert_eq!(
+			Layout::of(&payload(DrmFourcc::Argb8888, 2560)).map(|layout| layout.color_type),
+			Ok(ColorType::BGRA8888)
+		);
+		assert_eq!(
+			Layout::of(&payload(DrmFourcc::Nv12, 640)),
+			Err("import_failed")
+		);
+		assert_eq!(
+			Layout::of(&payload(DrmFourcc::Xrgb8888, 640)),
+			Err("import_failed"),
+			"stride shorter than a row"
+		);
+		let tiled = FramebufferLinkPayload {
+			modifier: Some(0x0100_0000_0000_0001),
+			..payload(DrmFourcc::Xrgb8888, 2560)
+		};
+		assert_eq!(Layout::of(&tiled), Err("unsupported_modifier"));
+		assert_eq!(
+			Layout::of(&payload(DrmFourcc::C8, 640)),
+			Err("unsupported_format")
+		);
+	}
+}
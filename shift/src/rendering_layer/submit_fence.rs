@@ -0,0 +1,101 @@
+//! Explicit fences for the composition work Skia submits.
+//!
+//! After each monitor's Skia work is submitted, an `EGL_ANDROID_native_fence_sync` fence is
+//! exported as a sync file. The frame's fences are merged into one that signals once every
+//! output finished sampling session buffers. It is handed back with released buffers whenever
+//! EasyDRM's commit didn't produce a render fence, so clients never depend on the driver's
+//! implicit sync to know when they can draw again.
+
+use std::{
+	ffi::c_void,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use easydrm::gl;
+
+use super::egl;
+
+/// `SYNC_IOC_MERGE`: `_IOWR('>', 3, struct sync_merge_data)`.
+const SYNC_IOC_MERGE: u32 = 0xC030_3E03;
+
+#[repr(C)]
+struct SyncMergeData {
+	name: [u8; 32],
+	fd2: i32,
+	fence: i32,
+	flags: u32,
+	pad: u32,
+}
+
+pub(super) struct SubmitFences {
+	egl: egl::Egl,
+	supported: bool,
+}
+
+impl SubmitFences {
+	pub fn load(resolver: impl Fn(&str) -> *const c_void) -> Self {
+		let egl = egl::Egl::load_with(|name| resolver(name));
+		let supported = egl.CreateSyncKHR.is_loaded()
+			&& egl.DestroySyncKHR.is_loaded()
+			&& egl.DupNativeFenceFDANDROID.is_loaded();
+		if !supported {
+			tracing::warn!("EGL_ANDROID_native_fence_sync unavailable, relying on implicit sync");
+		}
+		Self { egl, supported }
+	}
+
+	/// Exports a sync file that signals once everything submitted on the current context has
+	/// executed. Call right after Skia submitted the monitor's work.
+	pub fn export(&self, gl: &gl::Gles2) -> Option<OwnedFd> {
+		if !self.supported {
+			return None;
+		}
+		let display = unsafe { self.egl.GetCurrentDisplay() };
+		if display.is_null() {
+			return None;
+		}
+		let attrs = [egl::NONE as i32];
+		let sync = unsafe {
+			self
+				.egl
+				.CreateSyncKHR(display, egl::SYNC_NATIVE_FENCE_ANDROID, attrs.as_ptr())
+		};
+		if sync.is_null() {
+			tracing::warn!(
+				error = unsafe { self.egl.GetError() },
+				"eglCreateSyncKHR failed"
+			);
+			return None;
+		}
+		// The fence only gets an fd once its command reached the GPU queue.
+		unsafe { gl.Flush() };
+		let fd = unsafe { self.egl.DupNativeFenceFDANDROID(display, sync) };
+		unsafe { self.egl.DestroySyncKHR(display, sync) };
+		(fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) })
+	}
+}
+
+/// Merges sync files into one that signals when all of them have.
+pub(super) fn merge(fences: Vec<OwnedFd>) -> Option<OwnedFd> {
+	let mut fences = fences.into_iter();
+	let mut merged = fences.next()?;
+	for fence in fences {
+		let mut data = SyncMergeData {
+			name: [0; 32],
+			fd2: fence.as_raw_fd(),
+			fence: -1,
+			flags: 0,
+			pad: 0,
+		};
+		data.name[..12].copy_from_slice(b"shift-render");
+		let result = unsafe { libc::ioctl(merged.as_raw_fd(), SYNC_IOC_MERGE as _, &mut data) };
+		if result < 0 || data.fence < 0 {
+			tracing::warn!("SYNC_IOC_MERGE failed: {}", std::io::Error::last_os_error());
+			// Waiting on the newest fence still covers work submitted before it on the same queue.
+			merged = fence;
+			continue;
+		}
+		merged = unsafe { OwnedFd::from_raw_fd(data.fence) };
+	}
+	Some(merged)
+}
@@ -1,13 +1,24 @@
 use std::collections::HashMap;
 
-use easydrm::{Monitor, MonitorContextCreationRequest, gl};
+use easydrm::{MonitorContextCreationRequest, gl};
 use skia_safe::{
-	self as skia, FilterMode, MipmapMode, Paint, SamplingOptions, gpu, gpu::gl::FramebufferInfo,
+	self as skia, AlphaType, BlendMode, ColorType, CubicResampler, FilterMode, ImageInfo, MipmapMode,
+	Paint, SamplingOptions,
 };
 
-use crate::monitor::{Monitor as ServerLayerMonitor, MonitorId};
+use tab_protocol::OutputTransform;
 
-use super::{RenderError, dmabuf_import::SkiaDmaBufTexture};
+use crate::monitor::{self, Monitor as ServerLayerMonitor, MonitorId};
+
+use super::{
+	RenderError, backend::BackendOutput, cursor::CursorPlane, dmabuf_import::SkiaDmaBufTexture,
+	formats::XRGB2101010, skia_backend::SkiaContext,
+};
+
+/// Lowest resolution monitors are composed at, relative to their mode's.
+const MIN_RENDER_SCALE: f32 = 0.5;
+/// Highest resolution monitors are composed at, for 2x2 supersampling.
+const MAX_RENDER_SCALE: f32 = 2.0;
 
 pub struct MonitorRenderState {
 	pub surfaces_by_fbo: HashMap<i32, skia::Surface>,
@@ -15,7 +26,25 @@ pub struct MonitorRenderState {
 	pub height: usize,
 	pub target_fbo: i32,
 	pub gl: gl::Gles2,
+	/// The monitor's stable id once the renderer read its connector, `runtime_id` until then.
 	pub id: MonitorId,
+	/// Random id the output was created with; internal, never sent to the server.
+	pub runtime_id: MonitorId,
+	/// Set while an HDR session is shown. Frames are then composed in half float, so PQ content
+	/// keeps its precision through blending.
+	hdr: bool,
+	/// Resolution frames are composed at, relative to the output's.
+	render_scale: f32,
+	/// Where frames are composed while [`Self::hdr`] is set or [`Self::render_scale`] isn't 1,
+	/// resolved into the output framebuffer on flush.
+	offscreen: Option<skia::Surface>,
+	/// Fourcc chosen for the monitor's framebuffers from its primary plane's formats.
+	output_format: u32,
+	/// Set once the framebuffer came up shallower than `output_format`, to warn only once.
+	depth_mismatch_reported: bool,
+	pub cursor_plane: CursorPlane,
+	/// How the monitor's content is rotated or flipped onto the output; set every frame.
+	pub transform: OutputTransform,
 }
 
 impl MonitorRenderState {
@@ -23,20 +52,29 @@ impl MonitorRenderState {
 	pub fn new(req: &MonitorContextCreationRequest<'_>) -> Result<Self, RenderError> {
 		let target_fbo = current_framebuffer_binding(req.gl);
 
+		let runtime_id = MonitorId::from_raw(rand::random());
 		Ok(Self {
 			surfaces_by_fbo: HashMap::new(),
 			width: req.width,
 			height: req.height,
 			target_fbo,
 			gl: req.gl.clone(),
-			id: MonitorId::rand(),
+			id: runtime_id,
+			runtime_id,
+			hdr: false,
+			render_scale: 1.0,
+			offscreen: None,
+			output_format: 0,
+			depth_mismatch_reported: false,
+			cursor_plane: CursorPlane::default(),
+			transform: OutputTransform::Normal,
 		})
 	}
 
 	#[tracing::instrument(skip_all, fields(width = width, height = height, fbo = fbo))]
 	pub fn ensure_surface_target(
 		&mut self,
-		gr: &mut gpu::DirectContext,
+		skia: &mut SkiaContext,
 		width: usize,
 		height: usize,
 		fbo: i32,
@@ -44,19 +82,104 @@ impl MonitorRenderState {
 		let size_changed = self.width != width || self.height != height;
 		if size_changed {
 			self.surfaces_by_fbo.clear();
+			self.offscreen = None;
 			self.width = width;
 			self.height = height;
 		}
 		self.target_fbo = fbo;
 		if !self.surfaces_by_fbo.contains_key(&fbo) {
+			let color = framebuffer_color(&self.gl);
+			if self.output_format == XRGB2101010
+				&& color == FramebufferColor::Rgba8888
+				&& !std::mem::replace(&mut self.depth_mismatch_reported, true)
+			{
+				tracing::warn!(
+					monitor_id = %self.id,
+					"monitor supports 10-bit output but EasyDRM allocated an 8-bit framebuffer"
+				);
+			}
 			self
 				.surfaces_by_fbo
-				.insert(fbo, skia_surface_for_fbo(gr, width, height, fbo)?);
+				.insert(fbo, skia_surface_for_fbo(skia, width, height, fbo, color)?);
+		}
+		if (self.hdr || self.render_scale != 1.0) && self.offscreen.is_none() {
+			let target = self.surfaces_by_fbo[&fbo].image_info();
+			let color_type = if self.hdr {
+				ColorType::RGBAF16
+			} else {
+				target.color_type()
+			};
+			let (render_width, render_height) = self.render_size();
+			let info = ImageInfo::new(
+				(render_width, render_height),
+				color_type,
+				AlphaType::Premul,
+				None,
+			);
+			let mut surface = skia.render_target(&info).ok_or(RenderError::SkiaSurface)?;
+			// Frames keep being drawn in output pixels; this base transform outlives every
+			// frame's saves and restores.
+			surface.canvas().scale((
+				render_width as f32 / width as f32,
+				render_height as f32 / height as f32,
+			));
+			self.offscreen = Some(surface);
 		}
 		Ok(())
 	}
 
+	/// Records the framebuffer format picked for this monitor. Surfaces always follow what the
+	/// framebuffer was actually allocated with.
+	pub fn set_output_format(&mut self, fourcc: u32) {
+		if self.output_format != fourcc {
+			self.output_format = fourcc;
+			self.depth_mismatch_reported = false;
+		}
+	}
+
+	/// Switches composition to half float while the shown session is HDR.
+	pub fn set_hdr(&mut self, hdr: bool) {
+		if self.hdr != hdr {
+			self.hdr = hdr;
+			self.offscreen = None;
+		}
+	}
+
+	/// Composes frames at `scale` times the output's resolution, within
+	/// [`MIN_RENDER_SCALE`] and [`MAX_RENDER_SCALE`].
+	pub fn set_render_scale(&mut self, scale: f32) {
+		let scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+		if self.render_scale != scale {
+			self.render_scale = scale;
+			self.offscreen = None;
+		}
+	}
+
+	/// Size frames are composed at.
+	fn render_size(&self) -> (i32, i32) {
+		let scaled = |length: usize| ((length as f32 * self.render_scale).round() as i32).max(1);
+		(scaled(self.width), scaled(self.height))
+	}
+
+	/// Size of the monitor as its sessions see it, i.e. with [`Self::transform`] undone.
+	pub fn logical_size(&self) -> (f32, f32) {
+		let (width, height) = (self.width as f32, self.height as f32);
+		if self.transform.swaps_axes() {
+			(height, width)
+		} else {
+			(width, height)
+		}
+	}
+
+	/// Maps logical pixels onto the output.
+	pub fn transform_matrix(&self) -> skia::Matrix {
+		output_matrix(self.transform, self.logical_size())
+	}
+
 	pub fn canvas(&mut self) -> &skia::Canvas {
+		if let Some(surface) = &mut self.offscreen {
+			return surface.canvas();
+		}
 		self
 			.surfaces_by_fbo
 			.get_mut(&self.target_fbo)
@@ -64,68 +187,166 @@ impl MonitorRenderState {
 			.canvas()
 	}
 
-	pub fn flush(&mut self, gr: &mut gpu::DirectContext) {
-		gr.flush(None);
+	#[tracing::instrument(skip_all, name = "skia_flush", fields(monitor_id = %self.id))]
+	/// Flushes and submits the monitor's Skia work so a fence exported afterwards covers it.
+	pub fn flush(&mut self, skia: &mut SkiaContext) {
+		if let Some(offscreen) = &mut self.offscreen
+			&& let Some(target) = self.surfaces_by_fbo.get_mut(&self.target_fbo)
+		{
+			let frame = offscreen.image_snapshot();
+			let mut paint = Paint::default();
+			paint.set_blend_mode(BlendMode::Src);
+			// Dithering hides the banding of quantizing half float down to 8 bits.
+			paint.set_dither(self.hdr && target.image_info().color_type() == ColorType::RGBA8888);
+			// Supersampled frames are filtered down with a cubic; others only need linear
+			// filtering, or none at the output's resolution.
+			let sampling = if self.render_scale > 1.0 {
+				SamplingOptions::from(CubicResampler::mitchell())
+			} else if self.render_scale < 1.0 {
+				SamplingOptions::new(FilterMode::Linear, MipmapMode::None)
+			} else {
+				SamplingOptions::default()
+			};
+			let bounds = skia::Rect::from_wh(self.width as f32, self.height as f32);
+			target
+				.canvas()
+				.draw_image_rect_with_sampling_options(&frame, None, bounds, sampling, &paint);
+		}
+		skia.submit();
 	}
 
-	pub fn get_server_layer_monitor(monitor: &Monitor<Self>) -> ServerLayerMonitor {
+	/// Reads the output framebuffer back as BGRA8888 into `dst`, rows `row_bytes` apart. Call
+	/// after [`Self::flush`], so HDR frames are resolved into it.
+	pub fn read_pixels(&mut self, dst: &mut [u8], row_bytes: usize) -> bool {
+		let info = ImageInfo::new(
+			(self.width as i32, self.height as i32),
+			ColorType::BGRA8888,
+			AlphaType::Premul,
+			None,
+		);
+		self
+			.surfaces_by_fbo
+			.get_mut(&self.target_fbo)
+			.is_some_and(|surface| surface.read_pixels(&info, dst, row_bytes, (0, 0)))
+	}
+
+	/// Copies the output framebuffer into an image other contexts can draw. Call after
+	/// [`Self::flush`]; the copy is submitted right away, as the framebuffer only exists on this
+	/// monitor's context.
+	pub fn snapshot(&mut self, skia: &mut SkiaContext) -> Option<skia::Image> {
+		let image = self
+			.surfaces_by_fbo
+			.get_mut(&self.target_fbo)?
+			.image_snapshot();
+		skia.submit();
+		Some(image)
+	}
+
+	pub fn get_server_layer_monitor(monitor: &impl BackendOutput) -> ServerLayerMonitor {
 		crate::monitor::Monitor {
 			height: monitor.size().1 as _,
 			width: monitor.size().0 as _,
 			id: monitor.context().id,
-			name: format!("Monitor {}", u32::from(monitor.connector_id())),
-			refresh_rate: monitor.active_mode().vrefresh(),
+			name: format!("Monitor {}", monitor.connector_id()),
+			refresh_rate: monitor.refresh_rate(),
+			connector_id: monitor.connector_id(),
+			primary: false,
+			formats: Vec::new(),
+			scanout_formats: Vec::new(),
+			format_modifiers: Vec::new(),
+			hdr_eotfs: Vec::new(),
+			vrr_capable: false,
+			offscreen: false,
+			transform: Default::default(),
+			scale: Default::default(),
+			modes: Vec::new(),
+			position: Default::default(),
 		}
 	}
 
 	#[tracing::instrument(skip_all, fields(monitor_id = %self.id))]
 	pub fn draw_texture(
 		&mut self,
-		gr: &mut gpu::DirectContext,
+		skia: &mut SkiaContext,
 		texture: &mut SkiaDmaBufTexture,
 	) -> Result<(), RenderError> {
-		let Some(image) = texture.image(gr) else {
+		let Some(image) = texture.image(skia) else {
 			return Err(RenderError::SkiaSurface);
 		};
-		let rect = skia::Rect::from_wh(self.width as f32, self.height as f32);
+		let (width, height) = self.logical_size();
+		let rect = skia::Rect::from_wh(width, height);
+		let matrix = self.transform_matrix();
 		let sampling = SamplingOptions::new(FilterMode::Nearest, MipmapMode::Nearest);
 		let mut paint = Paint::default();
 		paint.set_argb(255, 255, 255, 255);
-		self
-			.canvas()
-			.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
+		let canvas = self.canvas();
+		canvas.save();
+		canvas.concat(&matrix);
+		canvas.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
+		canvas.restore();
 		Ok(())
 	}
 }
 
+/// Maps a monitor's logical pixels, `logical_size` large, onto its output under `transform`.
+pub(super) fn output_matrix(transform: OutputTransform, logical_size: (f32, f32)) -> skia::Matrix {
+	let [scale_x, skew_x, trans_x, skew_y, scale_y, trans_y] =
+		monitor::to_output(transform, logical_size);
+	skia::Matrix::new_all(
+		scale_x, skew_x, trans_x, skew_y, scale_y, trans_y, 0.0, 0.0, 1.0,
+	)
+}
+
+/// Color layout of the output framebuffer, which EasyDRM picks when it allocates the scanout
+/// buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FramebufferColor {
+	Rgba8888,
+	Rgba1010102,
+	RgbaF16,
+}
+
+impl FramebufferColor {
+	fn from_red_bits(bits: i32) -> Self {
+		match bits {
+			16.. => FramebufferColor::RgbaF16,
+			10 => FramebufferColor::Rgba1010102,
+			_ => FramebufferColor::Rgba8888,
+		}
+	}
+
+	fn color_type(self) -> ColorType {
+		match self {
+			FramebufferColor::Rgba8888 => ColorType::RGBA8888,
+			FramebufferColor::Rgba1010102 => ColorType::RGBA1010102,
+			FramebufferColor::RgbaF16 => ColorType::RGBAF16,
+		}
+	}
+}
+
+/// Reads the bit depth of the bound framebuffer.
+fn framebuffer_color(gl: &gl::Gles2) -> FramebufferColor {
+	let mut red_bits: i32 = 0;
+	unsafe {
+		gl.GetIntegerv(gl::RED_SIZE, &mut red_bits);
+	}
+	FramebufferColor::from_red_bits(red_bits)
+}
+
 fn skia_surface_for_fbo(
-	gr: &mut gpu::DirectContext,
+	skia: &mut SkiaContext,
 	width: usize,
 	height: usize,
 	fbo: i32,
+	color: FramebufferColor,
 ) -> Result<skia::Surface, RenderError> {
-	let fb_info = FramebufferInfo {
-		fboid: fbo as u32,
-		format: gpu::gl::Format::RGBA8.into(),
-		protected: gpu::Protected::No,
-	};
-
-	let backend_rt = gpu::backend_render_targets::make_gl(
-		(width as i32, height as i32),
-		0, // samples
-		8, // stencil
-		fb_info,
-	);
-
-	gpu::surfaces::wrap_backend_render_target(
-		gr,
-		&backend_rt,
-		gpu::SurfaceOrigin::TopLeft,
-		skia::ColorType::RGBA8888,
-		None,
-		None,
-	)
-	.ok_or(RenderError::SkiaSurface)
+	skia
+		.wrap_framebuffer(
+			fbo as u32,
+			(width as i32, height as i32),
+			color.color_type(),
+		)
+		.ok_or(RenderError::SkiaSurface)
 }
 
 pub fn current_framebuffer_binding(gl: &gl::Gles2) -> i32 {
@@ -0,0 +1,125 @@
+//! Built-in session switcher: a row of session thumbnails with their names, drawn centered on
+//! every output above the composed sessions but below the watermark.
+
+use skia_safe::{
+	Canvas, ClipOp, Color, FilterMode, Font, FontMgr, FontStyle, Image, MipmapMode, Paint,
+	PaintStyle, RRect, Rect, SamplingOptions,
+};
+
+use crate::comms::server2render::SwitcherView;
+use crate::sessions::SessionId;
+
+#[derive(Default)]
+pub(super) struct SessionSwitcher {
+	view: Option<SwitcherView>,
+	/// Resolved lazily; `None` inside means no system font could be loaded.
+	font_mgr: Option<Option<FontMgr>>,
+}
+
+impl SessionSwitcher {
+	pub fn set(&mut self, view: Option<SwitcherView>) {
+		self.view = view.filter(|view| !view.entries.is_empty());
+	}
+
+	pub fn is_visible(&self) -> bool {
+		self.view.is_some()
+	}
+
+	/// Sessions whose thumbnails are needed, in display order.
+	pub fn sessions(&self) -> impl Iterator<Item = SessionId> + '_ {
+		self
+			.view
+			.iter()
+			.flat_map(|view| view.entries.iter().map(|entry| entry.session_id))
+	}
+
+	/// `thumbnails` holds the latest frame of each listed session on this monitor, in the order
+	/// of [`Self::sessions`].
+	pub fn draw(&mut self, canvas: &Canvas, width: f32, height: f32, thumbnails: &[Option<Image>]) {
+		let Some(view) = self.view.as_ref() else {
+			return;
+		};
+		let count = view.entries.len() as f32;
+		let gap = (width * 0.015).max(8.0);
+		let thumb_width = ((width - gap * (count + 3.0)) / count).min(width * 0.2);
+		let thumb_height = thumb_width * height / width;
+		let label_size = (thumb_height * 0.12).clamp(12.0, 28.0);
+		let panel_width = thumb_width * count + gap * (count + 1.0);
+		let panel_height = thumb_height + label_size * 2.0 + gap * 2.0;
+		let panel = Rect::from_xywh(
+			(width - panel_width) * 0.5,
+			(height - panel_height) * 0.5,
+			panel_width,
+			panel_height,
+		);
+
+		let mut background = Paint::default();
+		background.set_anti_alias(true);
+		background.set_color(Color::from_argb(200, 20, 20, 24));
+		canvas.draw_rrect(RRect::new_rect_xy(panel, gap, gap), &background);
+
+		let font = self
+			.font_mgr
+			.get_or_insert_with(|| {
+				let font_mgr = FontMgr::new();
+				if font_mgr.count_families() == 0 {
+					tracing::warn!("no system fonts available, switcher shows no names");
+					return None;
+				}
+				Some(font_mgr)
+			})
+			.as_ref()
+			.and_then(|mgr| mgr.legacy_make_typeface(None, FontStyle::normal()))
+			.map(|typeface| Font::from_typeface(typeface, label_size));
+
+		let mut placeholder = Paint::default();
+		placeholder.set_color(Color::from_argb(255, 48, 48, 56));
+		let mut selection = Paint::default();
+		selection.set_anti_alias(true);
+		selection.set_style(PaintStyle::Stroke);
+		selection.set_stroke_width((gap * 0.4).max(3.0));
+		selection.set_color(Color::from_argb(255, 90, 150, 255));
+		let mut text = Paint::default();
+		text.set_anti_alias(true);
+		text.set_color(Color::from_argb(230, 255, 255, 255));
+
+		for (index, entry) in view.entries.iter().enumerate() {
+			let left = panel.left + gap + index as f32 * (thumb_width + gap);
+			let rect = Rect::from_xywh(left, panel.top + gap, thumb_width, thumb_height);
+			match thumbnails.get(index).and_then(Option::as_ref) {
+				Some(image) => {
+					canvas.draw_image_rect_with_sampling_options(
+						image,
+						None,
+						rect,
+						SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+						&Paint::default(),
+					);
+				}
+				None => {
+					canvas.draw_rect(rect, &placeholder);
+				}
+			}
+			if index == view.selected {
+				canvas.draw_rect(rect.with_outset((gap * 0.25, gap * 0.25)), &selection);
+			}
+			if let Some(font) = font.as_ref() {
+				let (name_width, _) = font.measure_str(&entry.name, None);
+				let x = left + ((thumb_width - name_width) * 0.5).max(0.0);
+				canvas.save();
+				canvas.clip_rect(
+					Rect::from_xywh(left, rect.bottom, thumb_width, label_size * 2.0),
+					ClipOp::Intersect,
+					true,
+				);
+				canvas.draw_str(
+					&entry.name,
+					(x, rect.bottom + label_size * 1.4),
+					font,
+					&text,
+				);
+				canvas.restore();
+			}
+		}
+	}
+}
@@ -0,0 +1,169 @@
+//! What a sync file waits on, from `SYNC_IOC_FILE_INFO`, for reporting fences that don't
+//! signal. Each fence names the driver and timeline it came from, which is what a GPU driver
+//! bug report needs.
+
+use std::{
+	fmt, io,
+	os::fd::{AsRawFd, BorrowedFd},
+};
+
+/// `SYNC_IOC_FILE_INFO`: `_IOWR('>', 4, struct sync_file_info)`.
+const SYNC_IOC_FILE_INFO: u32 = 0xC038_3E04;
+
+/// Fences a report lists at most; merged sync files can hold many.
+const MAX_FENCES: u32 = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawFileInfo {
+	name: [u8; 32],
+	status: i32,
+	flags: u32,
+	num_fences: u32,
+	pad: u32,
+	sync_fence_info: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawFenceInfo {
+	obj_name: [u8; 32],
+	driver_name: [u8; 32],
+	status: i32,
+	flags: u32,
+	timestamp_ns: u64,
+}
+
+/// Whether a fence signaled; negative kernel statuses are errors the fence signaled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FenceStatus {
+	Active,
+	Signaled,
+	Error(i32),
+}
+
+impl FenceStatus {
+	fn from_raw(status: i32) -> Self {
+		match status {
+			0 => FenceStatus::Active,
+			1.. => FenceStatus::Signaled,
+			error => FenceStatus::Error(-error),
+		}
+	}
+}
+
+impl fmt::Display for FenceStatus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FenceStatus::Active => f.write_str("active"),
+			FenceStatus::Signaled => f.write_str("signaled"),
+			FenceStatus::Error(errno) => write!(f, "error {}", io::Error::from_raw_os_error(*errno)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FenceInfo {
+	pub driver: String,
+	pub timeline: String,
+	pub status: FenceStatus,
+	/// `CLOCK_MONOTONIC` time the fence signaled at; 0 while active.
+	pub timestamp_ns: u64,
+}
+
+impl fmt::Display for FenceInfo {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}/{} {}", self.driver, self.timeline, self.status)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SyncFileInfo {
+	pub name: String,
+	pub status: FenceStatus,
+	/// Fences the sync file holds, e.g. several after a merge.
+	pub fence_count: u32,
+	/// The first [`MAX_FENCES`] of them.
+	pub fences: Vec<FenceInfo>,
+}
+
+/// Queries the sync file `fd`. Fails for fds that aren't sync files.
+pub(super) fn info(fd: BorrowedFd<'_>) -> io::Result<SyncFileInfo> {
+	let mut raw = RawFileInfo {
+		name: [0; 32],
+		status: 0,
+		flags: 0,
+		num_fences: 0,
+		pad: 0,
+		sync_fence_info: 0,
+	};
+	// The first call only counts the fences.
+	ioctl(fd, &mut raw)?;
+	let fence_count = raw.num_fences;
+	let mut fences = vec![
+		RawFenceInfo {
+			obj_name: [0; 32],
+			driver_name: [0; 32],
+			status: 0,
+			flags: 0,
+			timestamp_ns: 0,
+		};
+		fence_count as usize
+	];
+	// The kernel refuses room for fewer fences than the file holds.
+	if !fences.is_empty() {
+		raw.sync_fence_info = fences.as_mut_ptr() as u64;
+		ioctl(fd, &mut raw)?;
+		fences.truncate(raw.num_fences.min(MAX_FENCES) as usize);
+	}
+	Ok(SyncFileInfo {
+		name: c_string(&raw.name),
+		status: FenceStatus::from_raw(raw.status),
+		fence_count,
+		fences: fences
+			.iter()
+			.map(|fence| FenceInfo {
+				driver: c_string(&fence.driver_name),
+				timeline: c_string(&fence.obj_name),
+				status: FenceStatus::from_raw(fence.status),
+				timestamp_ns: fence.timestamp_ns,
+			})
+			.collect(),
+	})
+}
+
+fn ioctl(fd: BorrowedFd<'_>, raw: &mut RawFileInfo) -> io::Result<()> {
+	let result = unsafe { libc::ioctl(fd.as_raw_fd(), SYNC_IOC_FILE_INFO as _, raw as *mut _) };
+	if result < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn c_string(bytes: &[u8]) -> String {
+	let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::{fd::AsFd, unix::net::UnixStream};
+
+	use super::*;
+
+	#[test]
+	fn layouts_match_the_kernel() {
+		assert_eq!(std::mem::size_of::<RawFileInfo>(), 56);
+		assert_eq!(std::mem::size_of::<RawFenceInfo>(), 80);
+		assert_eq!(
+			FenceStatus::from_raw(-libc::ETIMEDOUT),
+			FenceStatus::Error(libc::ETIMEDOUT)
+		);
+	}
+
+	#[test]
+	fn other_fds_are_not_sync_files() {
+		let (socket, _peer) = UnixStream::pair().expect("socket pair");
+		assert!(info(socket.as_fd()).is_err());
+	}
+}
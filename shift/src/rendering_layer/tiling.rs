@@ -0,0 +1,200 @@
+//! Tiled displays: panels (typically 5K/8K, often behind a DP-MST dock) driven through several
+//! connectors, each scanning out one tile of the picture.
+//!
+//! The kernel describes the arrangement in each connector's `TILE` property. Once every tile of
+//! a group is connected at its native tile mode, the connectors are announced to the server as
+//! a single logical monitor with the combined geometry, identified by the top-left tile. Sessions
+//! render one buffer for it and every tile composes its own slice of that buffer. Incomplete
+//! groups (a dock still bringing up its streams) stay separate monitors until the last tile
+//! shows up.
+
+use std::collections::HashMap;
+
+use crate::{drm_card, monitor::MonitorId};
+
+/// One connector's entry from the kernel `TILE` property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct TileInfo {
+	pub group_id: u32,
+	pub num_h: u32,
+	pub num_v: u32,
+	pub loc_h: u32,
+	pub loc_v: u32,
+	pub tile_width: u32,
+	pub tile_height: u32,
+}
+
+/// Parses the `group:flags:num_h:num_v:loc_h:loc_v:width:height` blob.
+pub(super) fn parse_tile_blob(blob: &[u8]) -> Option<TileInfo> {
+	let text = std::str::from_utf8(blob).ok()?.trim_end_matches('\0');
+	let fields = text
+		.split(':')
+		.map(|field| field.trim().parse::<u32>().ok())
+		.collect::<Option<Vec<_>>>()?;
+	let [
+		group_id,
+		_flags,
+		num_h,
+		num_v,
+		loc_h,
+		loc_v,
+		tile_width,
+		tile_height,
+	] = fields[..]
+	else {
+		return None;
+	};
+	if num_h == 0 || num_v == 0 || loc_h >= num_h || loc_v >= num_v {
+		return None;
+	}
+	Some(TileInfo {
+		group_id,
+		num_h,
+		num_v,
+		loc_h,
+		loc_v,
+		tile_width,
+		tile_height,
+	})
+}
+
+/// Reads the `TILE` property of `connector_id`, if it has one.
+pub(super) fn read_tile_info(connector_id: u32) -> Option<TileInfo> {
+	parse_tile_blob(&drm_card::connector_blob(connector_id, b"TILE")?)
+}
+
+/// Where a connector's tile sits inside its logical monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct TilePlacement {
+	/// The top-left tile's monitor, which stands for the whole group.
+	pub logical_id: MonitorId,
+	pub offset: (i32, i32),
+	pub logical_size: (i32, i32),
+}
+
+#[derive(Debug, Default)]
+pub(super) struct TileLayout {
+	placements: HashMap<MonitorId, TilePlacement>,
+}
+
+impl TileLayout {
+	/// Groups the tiles of connected monitors. Only complete groups are kept.
+	pub fn build(tiles: impl IntoIterator<Item = (MonitorId, TileInfo)>) -> Self {
+		let mut groups: HashMap<u32, Vec<(MonitorId, TileInfo)>> = HashMap::new();
+		for (monitor_id, tile) in tiles {
+			groups
+				.entry(tile.group_id)
+				.or_default()
+				.push((monitor_id, tile));
+		}
+		let mut placements = HashMap::new();
+		for tiles in groups.values() {
+			let (num_h, num_v) = (tiles[0].1.num_h, tiles[0].1.num_v);
+			let mut grid = HashMap::new();
+			for (monitor_id, tile) in tiles {
+				if (tile.num_h, tile.num_v) != (num_h, num_v) {
+					continue;
+				}
+				grid.insert((tile.loc_h, tile.loc_v), (*monitor_id, *tile));
+			}
+			if grid.len() != (num_h * num_v) as usize {
+				continue;
+			}
+			let width_of = |h: u32, v: u32| grid[&(h, v)].1.tile_width as i32;
+			let height_of = |h: u32, v: u32| grid[&(h, v)].1.tile_height as i32;
+			let logical_id = grid[&(0, 0)].0;
+			let logical_size = (
+				(0..num_h).map(|h| width_of(h, 0)).sum(),
+				(0..num_v).map(|v| height_of(0, v)).sum(),
+			);
+			for ((loc_h, loc_v), (monitor_id, _)) in &grid {
+				let offset = (
+					(0..*loc_h).map(|h| width_of(h, *loc_v)).sum(),
+					(0..*loc_v).map(|v| height_of(*loc_h, v)).sum(),
+				);
+				placements.insert(
+					*monitor_id,
+					TilePlacement {
+						logical_id,
+						offset,
+						logical_size,
+					},
+				);
+			}
+		}
+		Self { placements }
+	}
+
+	pub fn placement(&self, monitor_id: MonitorId) -> Option<TilePlacement> {
+		self.placements.get(&monitor_id).copied()
+	}
+
+	/// The monitor sessions render for when composing `monitor_id`.
+	pub fn logical_id(&self, monitor_id: MonitorId) -> MonitorId {
+		self
+			.placement(monitor_id)
+			.map_or(monitor_id, |placement| placement.logical_id)
+	}
+
+	/// `true` for tiles other than the top-left one, which are hidden from the server.
+	pub fn is_secondary_tile(&self, monitor_id: MonitorId) -> bool {
+		self.logical_id(monitor_id) != monitor_id
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_kernel_tile_blob() {
+		assert_eq!(
+			parse_tile_blob(b"7:1:2:1:1:0:2560:2880\0"),
+			Some(TileInfo {
+				group_id: 7,
+				num_h: 2,
+				num_v: 1,
+				loc_h: 1,
+				loc_v: 0,
+				tile_width: 2560,
+				tile_height: 2880,
+			})
+		);
+		assert_eq!(parse_tile_blob(b"7:1:2:1:2:0:2560:2880"), None);
+		assert_eq!(parse_tile_blob(b"garbage"), None);
+	}
+
+	#[test]
+	fn groups_only_complete_tile_sets() {
+		let tile = |loc_h| TileInfo {
+			group_id: 1,
+			num_h: 2,
+			num_v: 1,
+			loc_h,
+			loc_v: 0,
+			tile_width: 2560,
+			tile_height: 2880,
+		};
+		let partial = TileLayout::build([(MonitorId::from_raw(2), tile(1))]);
+		assert_eq!(partial.placement(MonitorId::from_raw(2)), None);
+
+		let layout = TileLayout::build([
+			(MonitorId::from_raw(2), tile(1)),
+			(MonitorId::from_raw(1), tile(0)),
+		]);
+		assert_eq!(
+			layout.placement(MonitorId::from_raw(2)),
+			Some(TilePlacement {
+				logical_id: MonitorId::from_raw(1),
+				offset: (2560, 0),
+				logical_size: (5120, 2880),
+			})
+		);
+		assert!(!layout.is_secondary_tile(MonitorId::from_raw(1)));
+		assert!(layout.is_secondary_tile(MonitorId::from_raw(2)));
+		assert_eq!(
+			layout.logical_id(MonitorId::from_raw(3)),
+			MonitorId::from_raw(3)
+		);
+	}
+}
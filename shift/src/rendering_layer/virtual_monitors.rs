@@ -0,0 +1,275 @@
+//! Virtual monitors the server adds with [`RenderCmd::AddVirtualMonitor`].
+//!
+//! Each is an offscreen surface of the monitor's size, composed from its session's buffers
+//! when the session presented something new, at most at the monitor's refresh rate. Nothing is
+//! scanned out: the frames exist for captures, read back right after composing. Buffers are
+//! imported and composed on the device's shared context, the one Skia was created on.
+//!
+//! A mirror takes no buffers: it shows the frame its session last presented on the source
+//! monitor, scaled to the mirror's size, and is damaged whenever that frame changes.
+//!
+//! [`RenderCmd::AddVirtualMonitor`]: crate::comms::server2render::RenderCmd::AddVirtualMonitor
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use easydrm::gl;
+use skia_safe::{Rect, Surface};
+use tab_protocol::MirrorScaling;
+
+use super::{
+	backend::{BackendError, RenderBackend},
+	skia_backend::SkiaContext,
+};
+use crate::{
+	monitor::{Monitor as ServerLayerMonitor, MonitorId},
+	sessions::SessionId,
+};
+
+#[derive(Default)]
+pub(super) struct VirtualMonitors {
+	monitors: HashMap<MonitorId, VirtualMonitor>,
+	/// GL functions of the shared context, loaded on first use.
+	gl: Option<gl::Gles2>,
+}
+
+pub(super) struct VirtualMonitor {
+	pub monitor: ServerLayerMonitor,
+	pub session_id: SessionId,
+	/// Monitor whose frames of the session a mirror shows.
+	pub mirror: Option<MonitorId>,
+	pub scaling: MirrorScaling,
+	surface: Option<Surface>,
+	/// Set when the session presented since the last frame was composed.
+	damaged: bool,
+	last_drawn: Option<Instant>,
+}
+
+impl VirtualMonitors {
+	/// Adds `monitor`, replacing a virtual monitor with the same id.
+	pub fn add(
+		&mut self,
+		monitor: ServerLayerMonitor,
+		session_id: SessionId,
+		mirror: Option<MonitorId>,
+		scaling: MirrorScaling,
+	) {
+		self.monitors.insert(
+			monitor.id,
+			VirtualMonitor {
+				monitor,
+				session_id,
+				mirror,
+				scaling,
+				surface: None,
+				damaged: true,
+				last_drawn: None,
+			},
+		);
+	}
+
+	pub fn remove(&mut self, monitor_id: MonitorId) -> bool {
+		self.monitors.remove(&monitor_id).is_some()
+	}
+
+	pub fn contains(&self, monitor_id: MonitorId) -> bool {
+		self.monitors.contains_key(&monitor_id)
+	}
+
+	pub fn get(&self, monitor_id: MonitorId) -> Option<&VirtualMonitor> {
+		self.monitors.get(&monitor_id)
+	}
+
+	pub fn get_mut(&mut self, monitor_id: MonitorId) -> Option<&mut VirtualMonitor> {
+		self.monitors.get_mut(&monitor_id)
+	}
+
+	/// Asks for a new frame of `monitor_id`, if it is a virtual monitor.
+	pub fn damage(&mut self, monitor_id: MonitorId) {
+		if let Some(target) = self.monitors.get_mut(&monitor_id) {
+			target.damaged = true;
+		}
+	}
+
+	/// `session_id` showed a new frame on `monitor_id`: damages it and the mirrors of it.
+	pub fn presented(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self.damage(monitor_id);
+		for target in self.monitors.values_mut() {
+			if target.mirror == Some(monitor_id) && target.session_id == session_id {
+				target.damaged = true;
+			}
+		}
+	}
+
+	/// Whether a monitor waits for its next refresh to compose a new frame.
+	pub fn any_damaged(&self) -> bool {
+		self.monitors.values().any(|target| target.damaged)
+	}
+
+	/// Damaged monitors whose refresh interval passed since their last frame.
+	pub fn due(&self, now: Instant) -> Vec<MonitorId> {
+		self
+			.monitors
+			.iter()
+			.filter(|(_, target)| target.damaged && target.is_due(now))
+			.map(|(monitor_id, _)| *monitor_id)
+			.collect()
+	}
+
+	/// Makes the shared context current and returns its GL functions.
+	pub fn make_current(&mut self, backend: &impl RenderBackend) -> Result<gl::Gles2, BackendError> {
+		backend.make_current()?;
+		let gl = self.gl.get_or_insert_with(|| {
+			let proc_loader = backend.proc_loader();
+			gl::Gles2::load_with(|name| proc_loader(name))
+		});
+		Ok(gl.clone())
+	}
+}
+
+impl VirtualMonitor {
+	pub fn size(&self) -> (u32, u32) {
+		(self.monitor.width as u32, self.monitor.height as u32)
+	}
+
+	pub fn refresh_rate(&self) -> u32 {
+		self.monitor.refresh_rate
+	}
+
+	/// Monitor whose buffers of the session are composed here: the source of a mirror, or
+	/// the virtual monitor itself.
+	pub fn source(&self) -> MonitorId {
+		self.mirror.unwrap_or(self.monitor.id)
+	}
+
+	/// Where a frame of `image_size` lands; frames of the monitor's own buffers fill it.
+	pub fn frame_rect(&self, image_size: (i32, i32)) -> Rect {
+		let (width, height) = self.size();
+		let scaling = match self.mirror {
+			Some(_) => self.scaling,
+			None => MirrorScaling::Stretch,
+		};
+		frame_rect(image_size, (width as f32, height as f32), scaling)
+	}
+
+	fn is_due(&self, now: Instant) -> bool {
+		let interval = Duration::from_secs(1) / self.monitor.refresh_rate.max(1);
+		self
+			.last_drawn
+			.is_none_or(|drawn| now.saturating_duration_since(drawn) >= interval)
+	}
+
+	/// The surface frames are composed into, created on first use.
+	pub fn surface(&mut self, skia: &mut SkiaContext) -> Option<&mut Surface> {
+		if self.surface.is_none() {
+			let (width, height) = self.size();
+			let info = skia_safe::ImageInfo::new_n32_premul((width as i32, height as i32), None);
+			self.surface = skia.render_target(&info);
+		}
+		self.surface.as_mut()
+	}
+
+	pub fn drawn(&mut self, now: Instant) {
+		self.damaged = false;
+		self.last_drawn = Some(now);
+	}
+}
+
+/// Rect a `source`-sized frame is drawn to on a `target`-sized surface; with `Fill` it
+/// overflows the surface, which crops it.
+fn frame_rect(source: (i32, i32), target: (f32, f32), scaling: MirrorScaling) -> Rect {
+	let (width, height) = target;
+	if source.0 <= 0 || source.1 <= 0 || scaling == MirrorScaling::Stretch {
+		return Rect::from_wh(width, height);
+	}
+	let (x_scale, y_scale) = (width / source.0 as f32, height / source.1 as f32);
+	let scale = match scaling {
+		MirrorScaling::Fill => x_scale.max(y_scale),
+		_ => x_scale.min(y_scale),
+	};
+	let (scaled_width, scaled_height) = (source.0 as f32 * scale, source.1 as f32 * scale);
+	Rect::from_xywh(
+		(width - scaled_width) / 2.0,
+		(height - scaled_height) / 2.0,
+		scaled_width,
+		scaled_height,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monitor(refresh_rate: u32) -> ServerLayerMonitor {
+		ServerLayerMonitor {
+			width: 640,
+			height: 480,
+			refresh_rate,
+			offscreen: true,
+			..ServerLayerMonitor::fixture("mon_1", "VIRTUAL-1", 0)
+		}
+	}
+
+	#[test]
+	fn frames_follow_damage_at_the_refresh_rate() {
+		let mut monitors = VirtualMonitors::default();
+		let target = monitor(50);
+		let monitor_id = target.id;
+		monitors.add(target, SessionId::from_raw(1), None, MirrorScaling::Fit);
+		let start = Instant::now();
+		assert_eq!(
+			monitors.due(start),
+			vec![monitor_id],
+			"new monitors draw once"
+		);
+		monitors.get_mut(monitor_id).expect("monitor").drawn(start);
+		assert!(monitors.due(start + Duration::from_secs(1)).is_empty());
+
+		monitors.damage(monitor_id);
+		assert!(monitors.due(start + Duration::from_millis(10)).is_empty());
+		assert!(monitors.any_damaged(), "waits for the next refresh");
+		assert_eq!(
+			monitors.due(start + Duration::from_millis(20)),
+			vec![monitor_id]
+		);
+	}
+
+	#[test]
+	fn mirrors_follow_their_source() {
+		let mut monitors = VirtualMonitors::default();
+		let target = monitor(60);
+		let (mirror_id, source) = (target.id, "mon_2".parse().expect("monitor id"));
+		let session_id = SessionId::from_raw(1);
+		monitors.add(target, session_id, Some(source), MirrorScaling::Fit);
+		monitors
+			.get_mut(mirror_id)
+			.expect("monitor")
+			.drawn(Instant::now());
+		monitors.presented(source, SessionId::from_raw(2));
+		assert!(!monitors.any_damaged(), "other sessions don't show here");
+		monitors.presented(source, session_id);
+		assert!(monitors.any_damaged());
+		assert_eq!(monitors.get(mirror_id).expect("monitor").source(), source);
+	}
+
+	#[test]
+	fn scaling_keeps_the_aspect_ratio_unless_stretched() {
+		let target = (640.0, 480.0);
+		assert_eq!(
+			frame_rect((1920, 1080), target, MirrorScaling::Fit),
+			Rect::from_xywh(0.0, 60.0, 640.0, 360.0)
+		);
+		let fill = frame_rect((1920, 1080), target, MirrorScaling::Fill);
+		assert!((fill.height() - 480.0).abs() < 0.01 && fill.width() > 640.0);
+		assert!(
+			(fill.left + fill.right - 640.0).abs() < 0.01,
+			"cropped evenly"
+		);
+		assert_eq!(
+			frame_rect((1920, 1080), target, MirrorScaling::Stretch),
+			Rect::from_wh(640.0, 480.0)
+		);
+	}
+}
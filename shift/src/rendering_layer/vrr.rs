@@ -0,0 +1,196 @@
+//! Variable refresh rate (adaptive sync).
+//!
+//! An output can run with VRR when its connector reports `vrr_capable`. With VRR on, the CRTC's
+//! `VRR_ENABLED` property is set and the panel holds each frame until the next flip instead of
+//! refreshing at a fixed rate. The renderer then only flips such an output when something on
+//! it changed (see [`super::idle`]) or an animation is running, so flips follow the sessions'
+//! commits within the panel's range.
+//!
+//! `SHIFT_VRR=1` turns VRR on for every capable output; admins toggle it per monitor with
+//! `set_vrr`. Like `HDR_OUTPUT_METADATA`, the property is set directly on the card, which only
+//! the DRM master may do; a refusal is logged and not retried until the wanted state changes.
+
+use std::collections::HashMap;
+
+use drm::control::{Device as ControlDevice, connector};
+
+use crate::{drm_card::Card, monitor::MonitorId};
+
+#[derive(Debug)]
+pub(super) struct VrrOutputs {
+	default_enabled: bool,
+	/// Per-monitor choices that take precedence over `default_enabled`.
+	overrides: HashMap<MonitorId, bool>,
+	/// Whether each connector reports `vrr_capable`.
+	capable: HashMap<u32, bool>,
+	/// Last `VRR_ENABLED` value written per connector.
+	applied: HashMap<u32, bool>,
+}
+
+impl VrrOutputs {
+	/// Reads `SHIFT_VRR` (default off).
+	pub fn from_env() -> Self {
+		Self::new(crate::input_layer::env_bool("SHIFT_VRR", false))
+	}
+
+	fn new(default_enabled: bool) -> Self {
+		Self {
+			default_enabled,
+			overrides: HashMap::new(),
+			capable: HashMap::new(),
+			applied: HashMap::new(),
+		}
+	}
+
+	/// Turns VRR on or off for `monitor_id`, or for every monitor when `None`, which also drops
+	/// the per-monitor choices.
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, enabled: bool) {
+		match monitor_id {
+			Some(monitor_id) => {
+				self.overrides.insert(monitor_id, enabled);
+			}
+			None => {
+				self.default_enabled = enabled;
+				self.overrides.clear();
+			}
+		}
+	}
+
+	/// Reads whether `connector_id` is VRR-capable, once per connector.
+	pub fn probe(&mut self, connector_id: u32) {
+		self.capable.entry(connector_id).or_insert_with(|| {
+			let capable = read_capable(connector_id);
+			if capable {
+				tracing::info!(connector_id, "VRR-capable output");
+			}
+			capable
+		});
+	}
+
+	pub fn capable(&self, connector_id: u32) -> bool {
+		self.capable.get(&connector_id).copied().unwrap_or(false)
+	}
+
+	pub fn retain_connectors(&mut self, connected: impl Fn(u32) -> bool) {
+		self
+			.capable
+			.retain(|connector_id, _| connected(*connector_id));
+	}
+
+	fn wanted(&self, connector_id: u32, monitor_id: MonitorId) -> bool {
+		self.capable(connector_id)
+			&& self
+				.overrides
+				.get(&monitor_id)
+				.copied()
+				.unwrap_or(self.default_enabled)
+	}
+
+	/// Brings `connector_id`'s CRTC in line with the choice for `monitor_id`. Returns whether
+	/// the output now runs with VRR.
+	pub fn sync(&mut self, connector_id: u32, monitor_id: MonitorId) -> bool {
+		let wanted = self.wanted(connector_id, monitor_id);
+		match self.applied.get(&connector_id) {
+			Some(applied) if *applied == wanted => return wanted,
+			// Never touched: leave whatever the output was brought up with.
+			None if !wanted => return false,
+			_ => {}
+		}
+		if let Err(e) = write_vrr_enabled(connector_id, wanted) {
+			tracing::warn!(connector_id, "failed to set VRR_ENABLED: {e}");
+		}
+		// Recorded even on failure so a refused property isn't retried every frame.
+		self.applied.insert(connector_id, wanted);
+		wanted
+	}
+
+	/// Whether any output ran with VRR at its last sync.
+	pub fn any_enabled(&self) -> bool {
+		self.applied.values().any(|enabled| *enabled)
+	}
+
+	/// Whether `connector_id` runs with VRR, so it holds its frame while idle.
+	pub fn enabled(&self, connector_id: u32) -> bool {
+		self.applied.get(&connector_id).copied().unwrap_or(false)
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.overrides.remove(&monitor_id);
+	}
+}
+
+/// Whether `connector_id` reports `vrr_capable`.
+fn read_capable(connector_id: u32) -> bool {
+	let Some(handle) = drm::control::from_u32::<connector::Handle>(connector_id) else {
+		return false;
+	};
+	Card::open_all().into_iter().any(|(_, card)| {
+		let Ok(properties) = card.get_properties(handle) else {
+			return false;
+		};
+		let (ids, values) = properties.as_props_and_values();
+		ids.iter().zip(values).any(|(id, value)| {
+			*value != 0
+				&& card
+					.get_property(*id)
+					.is_ok_and(|info| info.name().to_bytes() == b"vrr_capable")
+		})
+	})
+}
+
+/// Sets `VRR_ENABLED` on the CRTC currently driving `connector_id`.
+fn write_vrr_enabled(connector_id: u32, enabled: bool) -> std::io::Result<()> {
+	let not_found = || {
+		std::io::Error::new(
+			std::io::ErrorKind::NotFound,
+			"no CRTC with VRR_ENABLED driving the connector",
+		)
+	};
+	let handle: connector::Handle = drm::control::from_u32(connector_id).ok_or_else(not_found)?;
+	for (_, card) in Card::open_all() {
+		let Some(crtc) = card
+			.get_connector(handle, false)
+			.ok()
+			.and_then(|info| info.current_encoder())
+			.and_then(|encoder| card.get_encoder(encoder).ok())
+			.and_then(|encoder| encoder.crtc())
+		else {
+			continue;
+		};
+		let Ok(properties) = card.get_properties(crtc) else {
+			continue;
+		};
+		let (ids, _) = properties.as_props_and_values();
+		let Some(property_id) = ids.iter().copied().find(|id| {
+			card
+				.get_property(*id)
+				.is_ok_and(|info| info.name().to_bytes() == b"VRR_ENABLED")
+		}) else {
+			continue;
+		};
+		return card.set_property(crtc, property_id, u64::from(enabled));
+	}
+	Err(not_found())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn per_monitor_choice_overrides_the_default() {
+		let mut vrr = VrrOutputs::new(false);
+		vrr.capable.insert(1, true);
+		vrr.capable.insert(2, false);
+		assert!(!vrr.wanted(1, MonitorId::from_raw(1)));
+		vrr.set(Some(MonitorId::from_raw(1)), true);
+		vrr.set(Some(MonitorId::from_raw(2)), true);
+		assert!(vrr.wanted(1, MonitorId::from_raw(1)));
+		assert!(
+			!vrr.wanted(2, MonitorId::from_raw(2)),
+			"incapable outputs never run with VRR"
+		);
+		vrr.set(None, false);
+		assert!(!vrr.wanted(1, MonitorId::from_raw(1)));
+	}
+}
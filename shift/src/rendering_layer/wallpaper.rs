@@ -0,0 +1,163 @@
+//! Per-monitor wallpaper drawn where no session frame covers the output: before the first
+//! session links buffers, while a session is still starting and behind translucent layers.
+//!
+//! `SHIFT_WALLPAPER` sets the default for every monitor, either as a `#rrggbb` color or as the
+//! path of an image file; outputs stay black when it is unset. Admins override it per monitor
+//! with `set_wallpaper`. Images are scaled to cover the output and cropped around the center.
+
+use std::collections::HashMap;
+
+use skia_safe::{
+	Canvas, Color, Data, FilterMode, Image, MipmapMode, Paint, Rect, SamplingOptions,
+	canvas::SrcRectConstraint,
+};
+
+use crate::comms::server2render::WallpaperSource;
+use crate::monitor::MonitorId;
+
+enum Background {
+	Color(Color),
+	Image(Image),
+}
+
+impl Background {
+	/// `None` when `source` is an image Skia can't decode.
+	fn load(source: &WallpaperSource) -> Option<Self> {
+		match source {
+			WallpaperSource::Color([r, g, b]) => Some(Self::Color(Color::from_rgb(*r, *g, *b))),
+			WallpaperSource::Image(bytes) => Image::from_encoded(Data::new_copy(bytes)).map(Self::Image),
+		}
+	}
+}
+
+pub(super) struct Wallpapers {
+	default: Background,
+	per_monitor: HashMap<MonitorId, Background>,
+}
+
+impl Default for Wallpapers {
+	fn default() -> Self {
+		Self {
+			default: Background::Color(Color::BLACK),
+			per_monitor: HashMap::new(),
+		}
+	}
+}
+
+impl Wallpapers {
+	pub fn from_env() -> Self {
+		let mut wallpapers = Self::default();
+		let Ok(value) = std::env::var("SHIFT_WALLPAPER") else {
+			return wallpapers;
+		};
+		let value = value.trim();
+		let source = if value.starts_with('#') {
+			let Some(rgb) = parse_hex_color(value) else {
+				tracing::warn!(value, "ignoring SHIFT_WALLPAPER: not a #rrggbb color");
+				return wallpapers;
+			};
+			WallpaperSource::Color(rgb)
+		} else {
+			match std::fs::read(value) {
+				Ok(bytes) => WallpaperSource::Image(bytes.into()),
+				Err(e) => {
+					tracing::warn!(path = value, "ignoring SHIFT_WALLPAPER: {e}");
+					return wallpapers;
+				}
+			}
+		};
+		match Background::load(&source) {
+			Some(background) => wallpapers.default = background,
+			None => tracing::warn!(path = value, "ignoring SHIFT_WALLPAPER: unsupported image"),
+		}
+		wallpapers
+	}
+
+	/// Changes the wallpaper of one monitor, or of every monitor when `None`. Undecodable
+	/// images leave the current wallpaper in place.
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, source: &WallpaperSource) {
+		let Some(background) = Background::load(source) else {
+			tracing::warn!(
+				?monitor_id,
+				"ignoring wallpaper image that can't be decoded"
+			);
+			return;
+		};
+		match monitor_id {
+			Some(monitor_id) => {
+				self.per_monitor.insert(monitor_id, background);
+			}
+			None => {
+				self.default = background;
+				self.per_monitor.clear();
+			}
+		}
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.per_monitor.remove(&monitor_id);
+	}
+
+	pub fn draw(&self, canvas: &Canvas, monitor_id: MonitorId, width: f32, height: f32) {
+		match self.per_monitor.get(&monitor_id).unwrap_or(&self.default) {
+			Background::Color(color) => {
+				canvas.draw_color(*color, None);
+			}
+			Background::Image(image) => {
+				let src = cover_crop(image.width() as f32, image.height() as f32, width, height);
+				canvas.draw_image_rect_with_sampling_options(
+					image,
+					Some((&src, SrcRectConstraint::Fast)),
+					Rect::from_wh(width, height),
+					SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+					&Paint::default(),
+				);
+			}
+		}
+	}
+}
+
+/// `#rrggbb` as `[r, g, b]`.
+fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+	let hex = value.strip_prefix('#')?;
+	if hex.len() != 6 || !hex.is_ascii() {
+		return None;
+	}
+	let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+	Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// The centered part of an `image_width`x`image_height` image with the output's aspect ratio.
+fn cover_crop(image_width: f32, image_height: f32, width: f32, height: f32) -> Rect {
+	let scale = (width / image_width).max(height / image_height);
+	let (crop_width, crop_height) = (width / scale, height / scale);
+	Rect::from_xywh(
+		(image_width - crop_width) * 0.5,
+		(image_height - crop_height) * 0.5,
+		crop_width,
+		crop_height,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_hex_colors() {
+		assert_eq!(parse_hex_color("#1e2a3B"), Some([0x1e, 0x2a, 0x3b]));
+		assert_eq!(parse_hex_color("1e2a3b"), None);
+		assert_eq!(parse_hex_color("#1e2a3"), None);
+		assert_eq!(parse_hex_color("#1e2a3g"), None);
+	}
+
+	#[test]
+	fn cover_crop_keeps_the_center_at_the_output_aspect() {
+		// 4:3 image on a 16:9 output: full width, trimmed top and bottom.
+		let crop = cover_crop(1600.0, 1200.0, 1920.0, 1080.0);
+		assert_eq!(crop, Rect::from_xywh(0.0, 150.0, 1600.0, 900.0));
+		// Portrait image on a square output: full width, trimmed top and bottom.
+		let crop = cover_crop(1000.0, 2000.0, 1000.0, 1000.0);
+		assert_eq!(crop, Rect::from_xywh(0.0, 500.0, 1000.0, 1000.0));
+	}
+}
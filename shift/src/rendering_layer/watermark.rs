@@ -0,0 +1,74 @@
+//! Identity watermark stamped in a corner of every output, on top of all other composition
+//! passes so neither the magnifier nor color filters affect it.
+
+use skia_safe::{Canvas, Color, Font, FontMgr, FontStyle, Paint, RRect, Rect};
+
+use crate::comms::server2render::ScreenCorner;
+
+#[derive(Default)]
+pub(super) struct Watermark {
+	text: Option<String>,
+	corner: ScreenCorner,
+	/// Resolved lazily; `None` inside means no system font could be loaded.
+	font_mgr: Option<Option<FontMgr>>,
+}
+
+impl Watermark {
+	pub fn set(&mut self, text: Option<String>, corner: ScreenCorner) {
+		self.text = text.filter(|text| !text.is_empty());
+		self.corner = corner;
+	}
+
+	pub fn is_visible(&self) -> bool {
+		self.text.is_some()
+	}
+
+	pub fn draw(&mut self, canvas: &Canvas, width: f32, height: f32) {
+		let Some(text) = self.text.as_deref() else {
+			return;
+		};
+		let font_mgr = self.font_mgr.get_or_insert_with(|| {
+			let font_mgr = FontMgr::new();
+			if font_mgr.count_families() == 0 {
+				tracing::warn!("no system fonts available, watermark disabled");
+				return None;
+			}
+			Some(font_mgr)
+		});
+		let Some(typeface) = font_mgr
+			.as_ref()
+			.and_then(|mgr| mgr.legacy_make_typeface(None, FontStyle::bold()))
+		else {
+			return;
+		};
+		let size = (height * 0.02).clamp(12.0, 48.0);
+		let font = Font::from_typeface(typeface, size);
+		let (text_width, bounds) = font.measure_str(text, None);
+		let padding = size * 0.5;
+		let margin = size;
+		let box_width = text_width + padding * 2.0;
+		let box_height = bounds.height() + padding * 2.0;
+		let (left, top) = match self.corner {
+			ScreenCorner::TopLeft => (margin, margin),
+			ScreenCorner::TopRight => (width - margin - box_width, margin),
+			ScreenCorner::BottomLeft => (margin, height - margin - box_height),
+			ScreenCorner::BottomRight => (width - margin - box_width, height - margin - box_height),
+		};
+
+		let mut background = Paint::default();
+		background.set_anti_alias(true);
+		background.set_color(Color::from_argb(150, 0, 0, 0));
+		let rect = Rect::from_xywh(left, top, box_width, box_height);
+		canvas.draw_rrect(RRect::new_rect_xy(rect, padding, padding), &background);
+
+		let mut foreground = Paint::default();
+		foreground.set_anti_alias(true);
+		foreground.set_color(Color::from_argb(220, 255, 255, 255));
+		canvas.draw_str(
+			text,
+			(left + padding, top + padding - bounds.top),
+			&font,
+			&foreground,
+		);
+	}
+}
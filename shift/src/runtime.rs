@@ -0,0 +1,369 @@
+//! Builds Shift's server, rendering and input layers and connects them, so Shift can run
+//! inside another process or a test as well as from the `shift` binary.
+
+use thiserror::Error;
+
+use crate::{
+	comms::{
+		channel::{self, ChannelConfig, ChannelKind},
+		input2server::InputEvtTx,
+	},
+	frame_trace::FrameTraceHandle,
+	gpu,
+	input_layer::{InputError, InputLayer, channels::Channels as InputChannels},
+	log_tail::LogTailHandle,
+	realtime,
+	rendering_layer::{
+		DrmBackend, HeadlessMonitor, HeadlessRenderer, RenderingLayer, SoftwareDisplay, VblankHandle,
+		channels::{Channels as RenderChannels, RenderingEnd},
+	},
+	server_layer::{BindError, ListenSocket, ShiftServer},
+	startup,
+};
+
+/// What the rendering and input layers run on.
+#[derive(Debug, Clone, Default)]
+pub enum Backend {
+	/// KMS on the first usable DRM device, input from libinput. Without a usable GPU, or with
+	/// `SHIFT_SOFTWARE_RENDERING=1`, frames are composed on the CPU instead.
+	#[default]
+	Drm,
+	/// Virtual monitors that accept swaps and flip on a timer, or when a test steps them with
+	/// [`ShiftRuntimeBuilder::vblank`], without a GPU or display; see
+	/// [`HeadlessRenderer`]. Input is off unless enabled with [`ShiftRuntimeBuilder::input`].
+	Headless { monitors: Vec<HeadlessMonitor> },
+}
+
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+	#[error("invalid socket configuration: {0}")]
+	Sockets(String),
+	#[error("failed to bind ShiftServer at {addresses}: {source}")]
+	Bind {
+		addresses: String,
+		#[source]
+		source: BindError,
+	},
+	#[error("{kind} channel capacity must be positive")]
+	ChannelCapacity { kind: &'static str },
+	#[error("{kind} channel can't use the {policy} overflow policy")]
+	ChannelPolicy {
+		kind: &'static str,
+		policy: &'static str,
+	},
+	#[error("failed to spawn rendering thread: {0}")]
+	RenderThread(std::io::Error),
+	#[error("failed to init rendering layer: {0}")]
+	RenderInit(String),
+}
+
+/// Configures a [`ShiftRuntime`]. Anything left unset comes from the environment, like the
+/// `shift` binary.
+pub struct ShiftRuntimeBuilder {
+	backend: Backend,
+	sockets: Option<Vec<ListenSocket>>,
+	channels: Vec<(ChannelKind, ChannelConfig)>,
+	frame_trace: Option<FrameTraceHandle>,
+	log_tail: Option<LogTailHandle>,
+	initial_session: bool,
+	input: Option<bool>,
+	gpu: Option<String>,
+	vblank: Option<VblankHandle>,
+}
+
+impl Default for ShiftRuntimeBuilder {
+	fn default() -> Self {
+		Self {
+			backend: Backend::default(),
+			sockets: None,
+			channels: Vec::new(),
+			frame_trace: None,
+			log_tail: None,
+			initial_session: true,
+			input: None,
+			gpu: None,
+			vblank: None,
+		}
+	}
+}
+
+impl ShiftRuntimeBuilder {
+	pub fn backend(mut self, backend: Backend) -> Self {
+		self.backend = backend;
+		self
+	}
+
+	/// Listen on `socket`, in addition to other sockets added here. Without any, the sockets
+	/// come from `SHIFT_SOCKETS`/`SHIFT_SOCKET`.
+	pub fn socket(mut self, socket: ListenSocket) -> Self {
+		self.sockets.get_or_insert_with(Vec::new).push(socket);
+		self
+	}
+
+	/// Overrides `SHIFT_<KIND>_CHANNEL_CAPACITY` and `SHIFT_<KIND>_CHANNEL_OVERFLOW`. Channel
+	/// configuration is process-wide, so only the first runtime of a process can change it.
+	pub fn channel(mut self, kind: ChannelKind, config: ChannelConfig) -> Self {
+		self.channels.push((kind, config));
+		self
+	}
+
+	/// Handle admins use to capture frame traces; install its layer to record anything.
+	pub fn frame_trace(mut self, frame_trace: FrameTraceHandle) -> Self {
+		self.frame_trace = Some(frame_trace);
+		self
+	}
+
+	/// Handle the recovery screen shows logs from; install its layer to fill it.
+	pub fn log_tail(mut self, log_tail: LogTailHandle) -> Self {
+		self.log_tail = Some(log_tail);
+		self
+	}
+
+	/// Whether to start the admin session right away (the default) or leave the server
+	/// without sessions until a client connects.
+	pub fn initial_session(mut self, enabled: bool) -> Self {
+		self.initial_session = enabled;
+		self
+	}
+
+	/// Whether to read libinput devices. Defaults to on with [`Backend::Drm`] and off with
+	/// [`Backend::Headless`].
+	pub fn input(mut self, enabled: bool) -> Self {
+		self.input = Some(enabled);
+		self
+	}
+
+	/// Overrides `SHIFT_GPU`: the GPU clients render on, by card or render node path or by
+	/// driver name. Like channels, only the first runtime of a process can change it.
+	pub fn gpu(mut self, selector: impl Into<String>) -> Self {
+		self.gpu = Some(selector.into());
+		self
+	}
+
+	/// Refreshes the [`Backend::Headless`] monitors only when `handle` steps them, for tests
+	/// that check which buffers were presented and released. Ignored with [`Backend::Drm`].
+	pub fn vblank(mut self, handle: VblankHandle) -> Self {
+		self.vblank = Some(handle);
+		self
+	}
+
+	/// Binds the sockets and starts every layer. The server doesn't accept clients until
+	/// [`ShiftRuntime::run`].
+	pub async fn build(self) -> Result<ShiftRuntime, RuntimeError> {
+		if let Some((kind, _)) = self
+			.channels
+			.iter()
+			.find(|(_, config)| config.capacity == 0)
+		{
+			return Err(RuntimeError::ChannelCapacity { kind: kind.name() });
+		}
+		if let Some((kind, config)) = self
+			.channels
+			.iter()
+			.find(|(kind, config)| !kind.allows(config.policy))
+		{
+			return Err(RuntimeError::ChannelPolicy {
+				kind: kind.name(),
+				policy: config.policy.as_str(),
+			});
+		}
+		if !self.channels.is_empty() && !channel::configure(&self.channels) {
+			tracing::warn!("channels already exist, keeping their configuration");
+		}
+		if let Some(selector) = self.gpu
+			&& !gpu::configure(selector)
+		{
+			tracing::warn!("a GPU was already selected, keeping it");
+		}
+		let sockets = match self.sockets {
+			Some(sockets) => sockets,
+			None => ListenSocket::from_env().map_err(RuntimeError::Sockets)?,
+		};
+		let addresses = sockets
+			.iter()
+			.map(|socket| socket.addr.to_string())
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		// ---- create inter-layer channels ----
+		let (server_render_channels, rendering_render_channels) = RenderChannels::new().split();
+		let (server_input_channels, input_layer_channels) = InputChannels::new().split();
+
+		// ---- create server ----
+		let server_phase = startup::phase("server_bind");
+		let mut server = ShiftServer::bind(
+			sockets,
+			server_render_channels,
+			server_input_channels.into_parts(),
+			self.frame_trace.unwrap_or_default(),
+			self.log_tail.unwrap_or_default(),
+		)
+		.await
+		.map_err(|source| RuntimeError::Bind {
+			addresses: addresses.clone(),
+			source,
+		})?;
+		drop(server_phase);
+		tracing::info!("starting ShiftServer on {addresses}");
+
+		// ---- overlap the slow parts of startup ----
+		// The admin client and libinput device enumeration make progress while the GPU comes up;
+		// the client's connection waits in the listen backlog until the server loop runs.
+		if self.initial_session {
+			server.add_initial_session();
+		}
+		let input_enabled = self.input.unwrap_or(matches!(self.backend, Backend::Drm));
+		let input = if input_enabled {
+			Input::Libinput(tokio::spawn(InputLayer::init(input_layer_channels).run()))
+		} else {
+			// Keep the channel open; the server treats a closed one as a dead input layer.
+			Input::Disabled(input_layer_channels.into_parts())
+		};
+
+		// ---- create rendering ----
+		let rendering = match self.backend {
+			Backend::Drm => Rendering::Thread(spawn_drm_renderer(rendering_render_channels).await?),
+			Backend::Headless { monitors } => {
+				let mut renderer = HeadlessRenderer::new(rendering_render_channels, &monitors);
+				if let Some(handle) = &self.vblank {
+					renderer = renderer.manual_vblank(handle);
+				}
+				Rendering::Task(tokio::spawn(renderer.run()))
+			}
+		};
+
+		Ok(ShiftRuntime {
+			server,
+			rendering,
+			input,
+		})
+	}
+}
+
+enum Rendering {
+	Thread(std::thread::JoinHandle<()>),
+	Task(tokio::task::JoinHandle<()>),
+}
+
+enum Input {
+	Libinput(tokio::task::JoinHandle<Result<(), InputError>>),
+	Disabled(InputEvtTx),
+}
+
+/// A connected set of layers; see [`ShiftRuntime::builder`].
+pub struct ShiftRuntime {
+	server: ShiftServer,
+	rendering: Rendering,
+	input: Input,
+}
+
+impl ShiftRuntime {
+	pub fn builder() -> ShiftRuntimeBuilder {
+		ShiftRuntimeBuilder::default()
+	}
+
+	/// Serves clients until the server shuts down, then waits for the other layers.
+	pub async fn run(self) {
+		let Self {
+			server,
+			rendering,
+			input,
+		} = self;
+		let rendering = async move {
+			let finished = match rendering {
+				Rendering::Thread(thread) => {
+					matches!(
+						tokio::task::spawn_blocking(move || thread.join()).await,
+						Ok(Ok(()))
+					)
+				}
+				Rendering::Task(task) => task.await.is_ok(),
+			};
+			if !finished {
+				tracing::error!("rendering thread panicked");
+			}
+		};
+		let (input_task, _input_events) = match input {
+			Input::Libinput(task) => (Some(task), None),
+			Input::Disabled(events) => (None, Some(events)),
+		};
+		let input = async move {
+			let Some(task) = input_task else {
+				return;
+			};
+			match task.await {
+				Ok(Err(e)) => tracing::error!("input layer ended with error: {e}"),
+				Err(e) => tracing::error!("input task failed: {e}"),
+				Ok(Ok(())) => {}
+			}
+		};
+		tokio::join!(server.start(), rendering, input);
+	}
+}
+
+/// The renderer gets its own thread and runtime so its scheduling policy and affinity do not
+/// leak onto the server loop. Returns once the GPU is up.
+async fn spawn_drm_renderer(
+	channels: RenderingEnd,
+) -> Result<std::thread::JoinHandle<()>, RuntimeError> {
+	let (init_tx, init_rx) = tokio::sync::oneshot::channel();
+	let render_tuning = realtime::ThreadTuning::from_env("RENDER");
+	let render_thread = std::thread::Builder::new()
+		.name("shift-render".into())
+		.spawn(move || {
+			render_tuning.apply_to_current_thread("render");
+			let runtime = match tokio::runtime::Builder::new_current_thread()
+				.enable_all()
+				.build()
+			{
+				Ok(runtime) => runtime,
+				Err(e) => {
+					let _ = init_tx.send(Err(format!("render runtime: {e}")));
+					return;
+				}
+			};
+			runtime.block_on(async move {
+				let device = {
+					let _phase = startup::phase("render_init");
+					if crate::input_layer::env_bool("SHIFT_SOFTWARE_RENDERING", false) {
+						Err("SHIFT_SOFTWARE_RENDERING is set".to_string())
+					} else {
+						RenderingLayer::<DrmBackend>::open().map_err(|e| e.to_string())
+					}
+				};
+				match device {
+					Ok(device) => {
+						let _ = init_tx.send(Ok(()));
+						let rendering = RenderingLayer::start(device, channels);
+						if let Err(e) = rendering.run().await {
+							tracing::error!("rendering thread ended with error: {e}");
+						}
+					}
+					Err(gpu_error) => {
+						tracing::warn!("not rendering on the GPU ({gpu_error}), falling back to software");
+						let display = {
+							let _phase = startup::phase("software_render_init");
+							SoftwareDisplay::open()
+						};
+						match display {
+							Ok(display) => {
+								let _ = init_tx.send(Ok(()));
+								HeadlessRenderer::software(channels, display).run().await;
+							}
+							Err(e) => {
+								let _ = init_tx.send(Err(format!("{gpu_error}; software rendering: {e}")));
+							}
+						}
+					}
+				}
+			});
+		})
+		.map_err(RuntimeError::RenderThread)?;
+	match init_rx.await {
+		Ok(Ok(())) => Ok(render_thread),
+		Ok(Err(e)) => Err(RuntimeError::RenderInit(e)),
+		Err(_) => Err(RuntimeError::RenderInit(
+			"rendering thread exited during init".into(),
+		)),
+	}
+}
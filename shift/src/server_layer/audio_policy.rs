@@ -0,0 +1,279 @@
+//! Optional audio policy for sessions that told Shift which sound server clients they own
+//! (`audio_clients`).
+//!
+//! `SHIFT_AUDIO_POLICY` picks the policy: `off` (default), `mute_sleeping` mutes a session's
+//! playback streams while it sleeps, and `mute_background` mutes every session but the active
+//! one. Streams are muted through `pactl` (`SHIFT_AUDIO_PACTL`), which talks to PulseAudio and
+//! to PipeWire through pipewire-pulse alike. Streams a session opens while muted are only
+//! caught on its next state change.
+//!
+//! A session may only claim clients whose `application.process.id` is its own process or one
+//! of its descendants.
+
+use std::{
+	collections::HashMap,
+	io,
+	process::{Command, Output},
+};
+
+use crate::sessions::SessionId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum AudioPolicyMode {
+	MuteSleeping,
+	MuteBackground,
+}
+
+#[derive(Debug)]
+struct Linked {
+	clients: Vec<u32>,
+	muted: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct AudioPolicy {
+	mode: Option<AudioPolicyMode>,
+	pactl: String,
+	sessions: HashMap<SessionId, Linked>,
+}
+
+impl AudioPolicy {
+	pub fn from_env() -> Self {
+		let mode = match std::env::var("SHIFT_AUDIO_POLICY") {
+			Ok(v) => parse_mode(&v).unwrap_or_else(|| {
+				tracing::warn!(value = v, "unknown SHIFT_AUDIO_POLICY, leaving audio alone");
+				None
+			}),
+			Err(_) => None,
+		};
+		Self {
+			mode,
+			pactl: std::env::var("SHIFT_AUDIO_PACTL").unwrap_or_else(|_| "pactl".into()),
+			sessions: HashMap::new(),
+		}
+	}
+
+	/// Replaces the clients of `session_id`, whose client process is `session_pid`. Clients
+	/// dropped from the list are unmuted. Does nothing while the policy is off.
+	pub fn link(
+		&mut self,
+		session_id: SessionId,
+		session_pid: Option<libc::pid_t>,
+		clients: Vec<u32>,
+	) -> Result<(), String> {
+		if self.mode.is_none() {
+			return Ok(());
+		}
+		if !clients.is_empty() {
+			let session_pid =
+				session_pid.ok_or("the session's process is unknown, so it can't own audio clients")?;
+			let owners = self
+				.client_pids()
+				.map_err(|e| format!("cannot list audio clients: {e}"))?;
+			if let Some(foreign) = clients.iter().find(|client| {
+				!owners
+					.get(client)
+					.is_some_and(|pid| descends_from(*pid, session_pid))
+			}) {
+				return Err(format!(
+					"audio client {foreign} does not belong to the session"
+				));
+			}
+		}
+		if let Some(previous) = self.sessions.remove(&session_id)
+			&& previous.muted
+		{
+			let dropped = previous
+				.clients
+				.into_iter()
+				.filter(|client| !clients.contains(client));
+			self.set_muted(dropped, false);
+		}
+		if !clients.is_empty() {
+			self.sessions.insert(
+				session_id,
+				Linked {
+					clients,
+					muted: false,
+				},
+			);
+		}
+		Ok(())
+	}
+
+	/// The session is gone, and its audio clients with it.
+	pub fn forget(&mut self, session_id: SessionId) {
+		self.sessions.remove(&session_id);
+	}
+
+	/// Mutes or unmutes linked sessions to match their state; call whenever sessions wake up,
+	/// go to sleep or the active session changes.
+	pub fn sync(&mut self, is_awake: impl Fn(SessionId) -> bool, active: Option<SessionId>) {
+		let Some(mode) = self.mode else {
+			return;
+		};
+		let mut changes = Vec::new();
+		for (&session_id, linked) in &mut self.sessions {
+			let muted = should_mute(mode, is_awake(session_id), active == Some(session_id));
+			if muted != linked.muted {
+				linked.muted = muted;
+				changes.push((linked.clients.clone(), muted));
+			}
+		}
+		for (clients, muted) in changes {
+			self.set_muted(clients, muted);
+		}
+	}
+
+	fn set_muted(&self, clients: impl IntoIterator<Item = u32>, muted: bool) {
+		let clients: Vec<u32> = clients.into_iter().collect();
+		if clients.is_empty() {
+			return;
+		}
+		let listing = match self.pactl(&["list", "short", "sink-inputs"]) {
+			Ok(listing) => listing,
+			Err(e) => {
+				tracing::warn!("cannot list audio streams: {e}");
+				return;
+			}
+		};
+		for stream in streams_of(&listing, &clients) {
+			let flag = if muted { "1" } else { "0" };
+			if let Err(e) = self.pactl(&["set-sink-input-mute", &stream.to_string(), flag]) {
+				tracing::warn!(stream, muted, "failed to change audio stream mute: {e}");
+			}
+		}
+		tracing::debug!(?clients, muted, "applied audio policy");
+	}
+
+	fn client_pids(&self) -> io::Result<HashMap<u32, libc::pid_t>> {
+		Ok(parse_client_pids(&self.pactl(&["list", "clients"])?))
+	}
+
+	fn pactl(&self, args: &[&str]) -> io::Result<String> {
+		let Output { status, stdout, .. } = Command::new(&self.pactl)
+			.args(args)
+			.env("LC_ALL", "C")
+			.output()?;
+		if !status.success() {
+			return Err(io::Error::other(format!(
+				"{} exited with {status}",
+				self.pactl
+			)));
+		}
+		Ok(String::from_utf8_lossy(&stdout).into_owned())
+	}
+}
+
+fn should_mute(mode: AudioPolicyMode, awake: bool, active: bool) -> bool {
+	match mode {
+		AudioPolicyMode::MuteSleeping => !awake,
+		AudioPolicyMode::MuteBackground => !active,
+	}
+}
+
+fn parse_mode(value: &str) -> Option<Option<AudioPolicyMode>> {
+	match value.trim().to_ascii_lowercase().as_str() {
+		"off" | "" => Some(None),
+		"mute_sleeping" => Some(Some(AudioPolicyMode::MuteSleeping)),
+		"mute_background" => Some(Some(AudioPolicyMode::MuteBackground)),
+		_ => None,
+	}
+}
+
+/// Client index to `application.process.id` from `pactl list clients`.
+fn parse_client_pids(listing: &str) -> HashMap<u32, libc::pid_t> {
+	let mut pids = HashMap::new();
+	let mut client = None;
+	for line in listing.lines() {
+		if let Some(index) = line.strip_prefix("Client #") {
+			client = index.trim().parse::<u32>().ok();
+		} else if let Some(pid) = line.trim().strip_prefix("application.process.id = ")
+			&& let Some(client) = client
+			&& let Ok(pid) = pid.trim_matches('"').parse()
+		{
+			pids.insert(client, pid);
+		}
+	}
+	pids
+}
+
+/// Sink input indices owned by `clients` in `pactl list short sink-inputs`, whose columns are
+/// index, sink, client, driver and sample spec.
+fn streams_of(listing: &str, clients: &[u32]) -> Vec<u32> {
+	listing
+		.lines()
+		.filter_map(|line| {
+			let mut columns = line.split('\t');
+			let index = columns.next()?.parse().ok()?;
+			let client: u32 = columns.nth(1)?.parse().ok()?;
+			clients.contains(&client).then_some(index)
+		})
+		.collect()
+}
+
+fn descends_from(mut pid: libc::pid_t, ancestor: libc::pid_t) -> bool {
+	while pid > 1 {
+		if pid == ancestor {
+			return true;
+		}
+		let Some(parent) = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+			.ok()
+			.and_then(|stat| parse_ppid(&stat))
+		else {
+			return false;
+		};
+		pid = parent;
+	}
+	false
+}
+
+/// The parent pid from `/proc/<pid>/stat`; the command name before it may contain spaces and
+/// parentheses, so fields are counted from the last `)`.
+fn parse_ppid(stat: &str) -> Option<libc::pid_t> {
+	let (_, fields) = stat.rsplit_once(')')?;
+	fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_modes() {
+		assert_eq!(parse_mode("off"), Some(None));
+		assert_eq!(
+			parse_mode("Mute_Sleeping"),
+			Some(Some(AudioPolicyMode::MuteSleeping))
+		);
+		assert_eq!(
+			parse_mode("mute_background"),
+			Some(Some(AudioPolicyMode::MuteBackground))
+		);
+		assert_eq!(parse_mode("mute"), None);
+	}
+
+	#[test]
+	fn mutes_according_to_mode() {
+		assert!(should_mute(AudioPolicyMode::MuteSleeping, false, false));
+		assert!(!should_mute(AudioPolicyMode::MuteSleeping, true, false));
+		assert!(should_mute(AudioPolicyMode::MuteBackground, true, false));
+		assert!(!should_mute(AudioPolicyMode::MuteBackground, true, true));
+	}
+
+	#[test]
+	fn parses_pactl_listings() {
+		let clients = "Client #7\n\tDriver: protocol-native.c\n\tProperties:\n\t\tapplication.name = \"mpv\"\n\t\tapplication.process.id = \"4242\"\nClient #9\n\tProperties:\n\t\tapplication.name = \"daemon\"\n";
+		assert_eq!(parse_client_pids(clients), HashMap::from([(7, 4242)]));
+
+		let streams = "31\t2\t7\tprotocol-native.c\ts16le 2ch 48000Hz\n32\t2\t9\tprotocol-native.c\tfloat32le 2ch 48000Hz\n";
+		assert_eq!(streams_of(streams, &[7]), [31]);
+		assert!(streams_of(streams, &[1]).is_empty());
+	}
+
+	#[test]
+	fn parses_parent_pid_after_command_name() {
+		assert_eq!(parse_ppid("123 (my (odd) app) S 45 123 123 0"), Some(45));
+		assert_eq!(parse_ppid("garbage"), None);
+	}
+}
@@ -0,0 +1,285 @@
+//! Deprioritises the processes behind sleeping sessions.
+//!
+//! `SHIFT_BACKGROUND_SESSIONS` picks what happens to a session's client process (found through
+//! the socket's peer credentials) when the session goes to sleep: `off` (default), `nice[:N]`
+//! raises the niceness of every thread to `N` (10 by default), and `freeze` freezes the
+//! process's cgroup v2 group. A process serving several clients is only throttled while the
+//! sessions of all of them sleep, and restored as soon as one wakes up or the last sleeping
+//! one disconnects. Groups containing Shift itself are never frozen; those sessions fall back
+//! to `nice`.
+
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+};
+
+use crate::client_layer::client::ClientId;
+
+const DEFAULT_BACKGROUND_NICE: i32 = 10;
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BackgroundPolicy {
+	Off,
+	Nice(i32),
+	Freeze,
+}
+
+#[derive(Debug)]
+enum Throttled {
+	/// Original niceness per thread id.
+	Niced(Vec<(libc::pid_t, i32)>),
+	Frozen(PathBuf),
+}
+
+#[derive(Debug)]
+pub(super) struct BackgroundThrottle {
+	policy: BackgroundPolicy,
+	/// Authenticated clients of each process, and whether their session sleeps.
+	clients: HashMap<libc::pid_t, HashMap<ClientId, bool>>,
+	throttled: HashMap<libc::pid_t, Throttled>,
+}
+
+impl BackgroundThrottle {
+	pub fn from_env() -> Self {
+		let policy = match std::env::var("SHIFT_BACKGROUND_SESSIONS") {
+			Ok(v) => parse_policy(&v).unwrap_or_else(|| {
+				tracing::warn!(
+					value = v,
+					"unknown SHIFT_BACKGROUND_SESSIONS, leaving sessions alone"
+				);
+				BackgroundPolicy::Off
+			}),
+			Err(_) => BackgroundPolicy::Off,
+		};
+		Self::new(policy)
+	}
+
+	fn new(policy: BackgroundPolicy) -> Self {
+		Self {
+			policy,
+			clients: HashMap::new(),
+			throttled: HashMap::new(),
+		}
+	}
+
+	/// The session of `client_id`, connected from `pid`, went to sleep or woke up.
+	pub fn set_asleep(&mut self, pid: libc::pid_t, client_id: ClientId, asleep: bool) {
+		if self.policy == BackgroundPolicy::Off {
+			return;
+		}
+		self
+			.clients
+			.entry(pid)
+			.or_default()
+			.insert(client_id, asleep);
+		self.sync(pid);
+	}
+
+	/// `client_id`, connected from `pid`, disconnected.
+	pub fn forget(&mut self, pid: libc::pid_t, client_id: ClientId) {
+		if let Some(clients) = self.clients.get_mut(&pid) {
+			clients.remove(&client_id);
+			if clients.is_empty() {
+				self.clients.remove(&pid);
+			}
+		}
+		self.sync(pid);
+	}
+
+	/// Restores `pid` right away, e.g. so a frozen process can be killed.
+	pub fn release(&mut self, pid: libc::pid_t) {
+		self.restore(pid);
+	}
+
+	/// Throttles `pid` while it has clients and all of their sessions sleep.
+	fn sync(&mut self, pid: libc::pid_t) {
+		let asleep = self
+			.clients
+			.get(&pid)
+			.is_some_and(|clients| clients.values().all(|asleep| *asleep));
+		if asleep {
+			self.throttle(pid);
+		} else {
+			self.restore(pid);
+		}
+	}
+
+	fn throttle(&mut self, pid: libc::pid_t) {
+		if self.throttled.contains_key(&pid) || pid == std::process::id() as libc::pid_t {
+			return;
+		}
+		let throttled = match self.policy {
+			BackgroundPolicy::Off => return,
+			BackgroundPolicy::Freeze => match freeze(pid) {
+				Ok(group) => Throttled::Frozen(group),
+				Err(e) => {
+					tracing::warn!(pid, "cannot freeze session process, renicing instead: {e}");
+					Throttled::Niced(renice(pid, DEFAULT_BACKGROUND_NICE))
+				}
+			},
+			BackgroundPolicy::Nice(nice) => Throttled::Niced(renice(pid, nice)),
+		};
+		tracing::debug!(pid, ?throttled, "session process moved to background");
+		self.throttled.insert(pid, throttled);
+	}
+
+	fn restore(&mut self, pid: libc::pid_t) {
+		match self.throttled.remove(&pid) {
+			Some(Throttled::Frozen(group)) => {
+				if let Err(e) = write_freeze(&group, false) {
+					tracing::warn!(pid, group = %group.display(), "failed to thaw session process: {e}");
+				}
+			}
+			Some(Throttled::Niced(threads)) => {
+				let mut failed = None;
+				for (tid, nice) in threads {
+					// SAFETY: setpriority on a thread id.
+					if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) } != 0 {
+						let e = io::Error::last_os_error();
+						// ESRCH only means the thread exited.
+						if e.raw_os_error() != Some(libc::ESRCH) {
+							failed = Some(e);
+						}
+					}
+				}
+				// Lowering the niceness again takes CAP_SYS_NICE.
+				if let Some(e) = failed {
+					tracing::warn!(pid, "failed to restore session process priority: {e}");
+				}
+			}
+			None => {}
+		}
+	}
+}
+
+/// Niceness is per thread on Linux, so every task of the process is adjusted.
+fn renice(pid: libc::pid_t, nice: i32) -> Vec<(libc::pid_t, i32)> {
+	let tasks = std::fs::read_dir(format!("/proc/{pid}/task"))
+		.into_iter()
+		.flatten()
+		.flatten()
+		.filter_map(|entry| entry.file_name().to_str()?.parse::<libc::pid_t>().ok());
+	let mut saved = Vec::new();
+	for tid in tasks {
+		// SAFETY: plain priority syscalls; errno is reset so -1 can be told apart from errors.
+		unsafe {
+			*libc::__errno_location() = 0;
+			let old = libc::getpriority(libc::PRIO_PROCESS, tid as libc::id_t);
+			if old == -1 && *libc::__errno_location() != 0 {
+				continue;
+			}
+			if libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice.max(old)) == 0 {
+				saved.push((tid, old));
+			}
+		}
+	}
+	saved
+}
+
+fn freeze(pid: libc::pid_t) -> io::Result<PathBuf> {
+	let group = cgroup_of(&format!("/proc/{pid}/cgroup"))?;
+	if cgroup_of("/proc/self/cgroup")?.starts_with(&group) {
+		return Err(io::Error::other(
+			"freezing the session's cgroup would freeze Shift",
+		));
+	}
+	write_freeze(&group, true)?;
+	Ok(group)
+}
+
+fn cgroup_of(proc_file: &str) -> io::Result<PathBuf> {
+	let contents = std::fs::read_to_string(proc_file)?;
+	let relative = parse_unified_cgroup(&contents)
+		.ok_or_else(|| io::Error::other("process is not in a cgroup v2 hierarchy"))?;
+	Ok(Path::new(CGROUP_ROOT).join(relative.trim_start_matches('/')))
+}
+
+fn write_freeze(group: &Path, frozen: bool) -> io::Result<()> {
+	std::fs::write(group.join("cgroup.freeze"), if frozen { "1" } else { "0" })
+}
+
+fn parse_unified_cgroup(contents: &str) -> Option<&str> {
+	contents
+		.lines()
+		.find_map(|line| line.strip_prefix("0::"))
+		.map(str::trim)
+}
+
+fn parse_policy(value: &str) -> Option<BackgroundPolicy> {
+	let value = value.trim().to_ascii_lowercase();
+	match value.split_once(':') {
+		Some(("nice", n)) => {
+			let nice: i32 = n.trim().parse().ok()?;
+			(0..=19)
+				.contains(&nice)
+				.then_some(BackgroundPolicy::Nice(nice))
+		}
+		Some(_) => None,
+		None => match value.as_str() {
+			"off" | "" => Some(BackgroundPolicy::Off),
+			"nice" => Some(BackgroundPolicy::Nice(DEFAULT_BACKGROUND_NICE)),
+			"freeze" => Some(BackgroundPolicy::Freeze),
+			_ => None,
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_policies() {
+		assert_eq!(parse_policy("off"), Some(BackgroundPolicy::Off));
+		assert_eq!(
+			parse_policy("nice"),
+			Some(BackgroundPolicy::Nice(DEFAULT_BACKGROUND_NICE))
+		);
+		assert_eq!(parse_policy("nice:5"), Some(BackgroundPolicy::Nice(5)));
+		assert_eq!(parse_policy("nice:-5"), None);
+		assert_eq!(parse_policy("Freeze"), Some(BackgroundPolicy::Freeze));
+		assert_eq!(parse_policy("pause"), None);
+	}
+
+	#[test]
+	fn throttles_only_while_every_client_sleeps() {
+		let mut child = std::process::Command::new("sleep")
+			.arg("5")
+			.spawn()
+			.expect("spawn sleep");
+		let pid = child.id() as libc::pid_t;
+		let (first, second) = (ClientId::rand(), ClientId::rand());
+		let mut background = BackgroundThrottle::new(BackgroundPolicy::Nice(DEFAULT_BACKGROUND_NICE));
+
+		background.set_asleep(pid, first, true);
+		background.set_asleep(pid, second, false);
+		assert!(
+			!background.throttled.contains_key(&pid),
+			"one session is awake"
+		);
+		background.set_asleep(pid, second, true);
+		assert!(background.throttled.contains_key(&pid));
+		background.forget(pid, first);
+		assert!(
+			background.throttled.contains_key(&pid),
+			"the other session still sleeps"
+		);
+		background.forget(pid, second);
+		assert!(!background.throttled.contains_key(&pid));
+
+		let _ = child.kill();
+		let _ = child.wait();
+	}
+
+	#[test]
+	fn finds_unified_cgroup() {
+		let contents = "12:cpu:/legacy\n0::/user.slice/app-session.scope\n";
+		assert_eq!(
+			parse_unified_cgroup(contents),
+			Some("/user.slice/app-session.scope")
+		);
+		assert_eq!(parse_unified_cgroup("3:memory:/x\n"), None);
+	}
+}
@@ -0,0 +1,72 @@
+//! Raw DRM properties of the monitors' connectors, for admin diagnostic tools that can't open
+//! the DRM node themselves (`get_connector_properties`).
+
+use std::collections::BTreeMap;
+
+use base64::Engine;
+use drm::control::{Device as ControlDevice, connector, property};
+use tab_protocol::ConnectorProperty;
+
+use crate::drm_card::Card;
+
+/// Every property of `connector_id` by name, or `None` when no card has the connector.
+pub(super) fn read(connector_id: u32) -> Option<BTreeMap<String, ConnectorProperty>> {
+	let handle: connector::Handle = drm::control::from_u32(connector_id)?;
+	Card::open_all().into_iter().find_map(|(_, card)| {
+		let properties = card.get_properties(handle).ok()?;
+		let (ids, values) = properties.as_props_and_values();
+		Some(
+			ids
+				.iter()
+				.zip(values)
+				.filter_map(|(id, value)| {
+					let info = card.get_property(*id).ok()?;
+					let name = info.name().to_string_lossy().into_owned();
+					Some((name, convert(&card, &info, *value)?))
+				})
+				.collect(),
+		)
+	})
+}
+
+fn convert(card: &Card, info: &property::Info, raw: u64) -> Option<ConnectorProperty> {
+	Some(match info.value_type() {
+		property::ValueType::Boolean => ConnectorProperty::Bool { value: raw != 0 },
+		property::ValueType::UnsignedRange(min, max) => ConnectorProperty::Range {
+			value: raw,
+			min,
+			max,
+		},
+		property::ValueType::SignedRange(min, max) => ConnectorProperty::SignedRange {
+			value: raw as i64,
+			min,
+			max,
+		},
+		property::ValueType::Enum(values) => {
+			let name = |entry: &property::EnumValue| entry.name().to_string_lossy().into_owned();
+			ConnectorProperty::Enum {
+				value: values
+					.get_value_from_raw_value(raw)
+					.map_or_else(|| raw.to_string(), name),
+				values: values.values().1.iter().map(name).collect(),
+			}
+		}
+		property::ValueType::Bitmask => ConnectorProperty::Bitmask { value: raw },
+		property::ValueType::Blob => ConnectorProperty::Blob {
+			data: match raw {
+				0 => String::new(),
+				blob => {
+					base64::engine::general_purpose::STANDARD.encode(card.get_property_blob(blob).ok()?)
+				}
+			},
+		},
+		property::ValueType::Object
+		| property::ValueType::CRTC
+		| property::ValueType::Connector
+		| property::ValueType::Encoder
+		| property::ValueType::Framebuffer
+		| property::ValueType::Plane
+		| property::ValueType::Property => ConnectorProperty::Object { id: raw as u32 },
+		property::ValueType::Unknown => return None,
+	})
+}
@@ -0,0 +1,107 @@
+//! Pointer cursors sessions hand to Shift with `set_cursor`.
+//!
+//! Each session keeps its own image. The current session's is shown on the pointer monitor and
+//! moved by Shift as pointer events come in, at the position [`Overlays`](super::overlay::Overlays)
+//! reconstructs, so cursor motion doesn't wait for the session to draw a frame.
+
+use std::{
+	collections::HashMap,
+	fs::File,
+	io,
+	os::{fd::OwnedFd, unix::fs::FileExt},
+};
+
+use tab_protocol::CursorImageInfo;
+
+use crate::{comms::server2render::CursorImage, sessions::SessionId};
+
+#[derive(Debug, Default)]
+pub(super) struct Cursors {
+	images: HashMap<SessionId, CursorImage>,
+}
+
+impl Cursors {
+	/// Replaces `session_id`'s cursor; `None` hides it.
+	pub fn set(&mut self, session_id: SessionId, image: Option<CursorImage>) {
+		match image {
+			Some(image) => {
+				self.images.insert(session_id, image);
+			}
+			None => {
+				self.images.remove(&session_id);
+			}
+		}
+	}
+
+	pub fn image(&self, session_id: SessionId) -> Option<&CursorImage> {
+		self.images.get(&session_id)
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.images.remove(&session_id);
+	}
+}
+
+/// Copies the image `info` describes out of the memfd `pixels`, dropping the rows' padding.
+pub(super) fn read_image(info: &CursorImageInfo, pixels: OwnedFd) -> io::Result<CursorImage> {
+	let file = File::from(pixels);
+	let row = info.width as usize * 4;
+	let mut data = vec![0; row * info.height as usize];
+	for (y, line) in data.chunks_exact_mut(row).enumerate() {
+		file.read_exact_at(line, y as u64 * u64::from(info.stride))?;
+	}
+	Ok(CursorImage {
+		width: info.width,
+		height: info.height,
+		hotspot: (info.hotspot_x, info.hotspot_y),
+		pixels: data.into(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::fd::FromRawFd;
+
+	use super::*;
+
+	fn memfd(contents: &[u8]) -> OwnedFd {
+		// SAFETY: memfd_create returns a new fd we own, or -1 which the assert rejects.
+		let fd = unsafe { libc::memfd_create(c"cursor".as_ptr(), 0) };
+		assert!(fd >= 0, "memfd_create failed");
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+		let file = File::from(fd.try_clone().expect("dup memfd"));
+		file.write_all_at(contents, 0).expect("write memfd");
+		fd
+	}
+
+	#[test]
+	fn rows_are_packed_without_their_padding() {
+		let info = CursorImageInfo {
+			width: 2,
+			height: 2,
+			stride: 12,
+			hotspot_x: 1,
+			hotspot_y: 0,
+		};
+		let mut contents = Vec::new();
+		contents.extend_from_slice(&[1; 8]);
+		contents.extend_from_slice(&[0xee; 4]);
+		contents.extend_from_slice(&[2; 8]);
+		let image = read_image(&info, memfd(&contents)).expect("image");
+		assert_eq!(image.hotspot, (1, 0));
+		assert_eq!(&image.pixels[..8], &[1; 8]);
+		assert_eq!(&image.pixels[8..], &[2; 8]);
+	}
+
+	#[test]
+	fn short_memfds_are_rejected() {
+		let info = CursorImageInfo {
+			width: 4,
+			height: 4,
+			stride: 16,
+			hotspot_x: 0,
+			hotspot_y: 0,
+		};
+		assert!(read_image(&info, memfd(&[0; 32])).is_err());
+	}
+}
@@ -0,0 +1,99 @@
+//! Monitors that flip together, from `SHIFT_FLIP_GROUPS`: comma separated groups of
+//! `+`-joined monitors, each named or given by connector id as in `SHIFT_PRIMARY_MONITOR`, e.g.
+//! `DP-1+DP-2+DP-3,HDMI-A-1+HDMI-A-2` for two video walls. The renderer holds the frames of a
+//! group's monitors until every one of them can take one, and commits them together.
+
+use std::collections::HashMap;
+
+use crate::monitor::{Monitor, MonitorId};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct FlipGroups {
+	groups: Vec<Vec<String>>,
+}
+
+impl FlipGroups {
+	pub fn from_env() -> Self {
+		std::env::var("SHIFT_FLIP_GROUPS")
+			.map(|value| Self::parse(&value))
+			.unwrap_or_default()
+	}
+
+	fn parse(value: &str) -> Self {
+		let groups = value
+			.split(',')
+			.map(|group| {
+				group
+					.split('+')
+					.map(str::trim)
+					.filter(|member| !member.is_empty())
+					.map(str::to_string)
+					.collect::<Vec<_>>()
+			})
+			.filter(|group| {
+				if group.len() < 2 {
+					if !group.is_empty() {
+						tracing::warn!(?group, "SHIFT_FLIP_GROUPS group needs two monitors or more");
+					}
+					return false;
+				}
+				true
+			})
+			.collect();
+		Self { groups }
+	}
+
+	/// The configured groups among `monitors`, sorted, leaving out the monitors that aren't
+	/// connected and groups with fewer than two left.
+	pub fn resolve(&self, monitors: &HashMap<MonitorId, Monitor>) -> Vec<Vec<MonitorId>> {
+		self
+			.groups
+			.iter()
+			.map(|group| {
+				let mut members: Vec<_> = monitors
+					.values()
+					.filter(|monitor| {
+						group
+							.iter()
+							.any(|member| *member == monitor.name || *member == monitor.connector_id.to_string())
+					})
+					.map(|monitor| monitor.id)
+					.collect();
+				members.sort();
+				members
+			})
+			.filter(|members| members.len() >= 2)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn groups_keep_their_connected_monitors() {
+		let groups = FlipGroups::parse("DP-1 + DP-2 + 52, HDMI-A-1+HDMI-A-2, eDP-1");
+		assert_eq!(groups.groups.len(), 2);
+		let monitors: HashMap<_, _> = [
+			Monitor::fixture("mon_1", "DP-1", 50),
+			Monitor::fixture("mon_2", "DP-2", 51),
+			Monitor::fixture("mon_3", "DP-3", 52),
+			Monitor::fixture("mon_4", "HDMI-A-1", 60),
+		]
+		.into_iter()
+		.map(|monitor| (monitor.id, monitor))
+		.collect();
+		let ids = |ids: &[&str]| {
+			ids
+				.iter()
+				.map(|id| id.parse().expect("monitor id"))
+				.collect::<Vec<MonitorId>>()
+		};
+		assert_eq!(
+			groups.resolve(&monitors),
+			vec![ids(&["mon_1", "mon_2", "mon_3"])],
+			"a group with one connected monitor is no group"
+		);
+	}
+}
@@ -0,0 +1,182 @@
+//! What becomes the current session when the client owning it disconnects.
+//!
+//! `SHIFT_HANDOVER` picks the successor: `recent` (default) switches to the most recently
+//! current session still connected, `splash` shows the splash screen, and `default:NAME`
+//! switches to the session named `NAME`, or to the most recent one while it isn't connected.
+//! Only sessions the switcher offers are eligible, so overlays never become current. The switch
+//! plays `SHIFT_HANDOVER_ANIMATION` (`blur` by default) over `SHIFT_HANDOVER_TRANSITION_MS`
+//! (300 by default, `0` switches instantly) from the departed session's last frame.
+
+use std::time::Duration;
+
+use crate::{comms::server2render::SessionTransition, sessions::SessionId};
+
+const DEFAULT_ANIMATION: &str = "blur";
+const DEFAULT_TRANSITION: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum HandoverPolicy {
+	Recent,
+	Splash,
+	/// Display name of the session to fall back to.
+	Default(String),
+}
+
+#[derive(Debug)]
+pub(super) struct Handover {
+	policy: HandoverPolicy,
+	animation: String,
+	duration: Duration,
+	/// Sessions in the order they were last current, most recent last.
+	recent: Vec<SessionId>,
+}
+
+impl Handover {
+	pub fn from_env() -> Self {
+		let policy = match std::env::var("SHIFT_HANDOVER") {
+			Ok(v) => parse_policy(&v).unwrap_or_else(|| {
+				tracing::warn!(
+					value = v,
+					"unknown SHIFT_HANDOVER, handing over to the most recent session"
+				);
+				HandoverPolicy::Recent
+			}),
+			Err(_) => HandoverPolicy::Recent,
+		};
+		let animation = std::env::var("SHIFT_HANDOVER_ANIMATION")
+			.ok()
+			.map(|v| v.trim().to_string())
+			.filter(|v| !v.is_empty())
+			.unwrap_or_else(|| DEFAULT_ANIMATION.to_string());
+		let duration = std::env::var("SHIFT_HANDOVER_TRANSITION_MS")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.map(Duration::from_millis)
+			.unwrap_or(DEFAULT_TRANSITION);
+		Self::new(policy, animation, duration)
+	}
+
+	fn new(policy: HandoverPolicy, animation: String, duration: Duration) -> Self {
+		Self {
+			policy,
+			animation,
+			duration,
+			recent: Vec::new(),
+		}
+	}
+
+	/// `session_id` became the current session.
+	pub fn activated(&mut self, session_id: Option<SessionId>) {
+		if let Some(session_id) = session_id {
+			self.recent.retain(|recent| *recent != session_id);
+			self.recent.push(session_id);
+		}
+	}
+
+	pub fn forget(&mut self, session_id: SessionId) {
+		self.recent.retain(|recent| *recent != session_id);
+	}
+
+	/// The session to make current now that the current one is gone, out of the switchable
+	/// `candidates` with their display names. `None` shows the splash screen.
+	pub fn successor(&self, candidates: &[(SessionId, &str)]) -> Option<SessionId> {
+		let by_name = |name: &str| {
+			candidates
+				.iter()
+				.find(|(_, candidate)| *candidate == name)
+				.map(|(id, _)| *id)
+		};
+		let most_recent = || {
+			self
+				.recent
+				.iter()
+				.rev()
+				.find(|recent| candidates.iter().any(|(id, _)| id == *recent))
+				.copied()
+		};
+		match &self.policy {
+			HandoverPolicy::Splash => None,
+			HandoverPolicy::Recent => most_recent(),
+			HandoverPolicy::Default(name) => by_name(name).or_else(most_recent),
+		}
+	}
+
+	/// The transition from the departed session's last frame to its successor.
+	pub fn transition(&self, from_session_id: SessionId) -> Option<SessionTransition> {
+		(!self.duration.is_zero()).then(|| SessionTransition {
+			from_session_id,
+			animation: self.animation.clone(),
+			duration: self.duration,
+		})
+	}
+}
+
+fn parse_policy(value: &str) -> Option<HandoverPolicy> {
+	match value.trim() {
+		"recent" => Some(HandoverPolicy::Recent),
+		"splash" => Some(HandoverPolicy::Splash),
+		other => other
+			.strip_prefix("default:")
+			.map(str::trim)
+			.filter(|name| !name.is_empty())
+			.map(|name| HandoverPolicy::Default(name.to_string())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	fn with_policy(policy: HandoverPolicy) -> Handover {
+		Handover::new(policy, DEFAULT_ANIMATION.into(), DEFAULT_TRANSITION)
+	}
+
+	#[test]
+	fn parses_policies() {
+		assert_eq!(parse_policy("recent"), Some(HandoverPolicy::Recent));
+		assert_eq!(parse_policy(" splash "), Some(HandoverPolicy::Splash));
+		assert_eq!(
+			parse_policy("default:Desktop"),
+			Some(HandoverPolicy::Default("Desktop".into()))
+		);
+		assert_eq!(parse_policy("default:"), None);
+		assert_eq!(parse_policy("nope"), None);
+	}
+
+	#[test]
+	fn hands_over_to_the_most_recent_connected_session() {
+		let mut handover = with_policy(HandoverPolicy::Recent);
+		for n in [1, 2, 3, 2] {
+			handover.activated(Some(session(n)));
+		}
+		handover.forget(session(2));
+		let candidates = [(session(1), "one"), (session(3), "three")];
+		assert_eq!(handover.successor(&candidates), Some(session(3)));
+		assert_eq!(handover.successor(&candidates[..1]), Some(session(1)));
+		assert_eq!(handover.successor(&[]), None);
+	}
+
+	#[test]
+	fn the_default_session_wins_while_connected() {
+		let mut handover = with_policy(HandoverPolicy::Default("Desktop".into()));
+		handover.activated(Some(session(1)));
+		handover.activated(Some(session(2)));
+		let candidates = [(session(1), "Desktop"), (session(2), "Game")];
+		assert_eq!(handover.successor(&candidates), Some(session(1)));
+		assert_eq!(handover.successor(&candidates[1..]), Some(session(2)));
+		assert_eq!(
+			with_policy(HandoverPolicy::Splash).successor(&candidates),
+			None
+		);
+	}
+
+	#[test]
+	fn zero_duration_switches_instantly() {
+		let handover = Handover::new(HandoverPolicy::Recent, "blur".into(), Duration::ZERO);
+		assert!(handover.transition(session(1)).is_none());
+	}
+}
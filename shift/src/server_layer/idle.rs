@@ -0,0 +1,140 @@
+//! Idle policy: blanks the outputs after a period without input unless the active session
+//! holds an idle inhibitor (e.g. during video playback).
+//!
+//! Inhibitors belong to sessions and only count while the session is awake; the server drops
+//! them when a session goes to sleep or disconnects, so a forgotten inhibitor can't keep the
+//! screens on forever.
+
+use std::{collections::HashSet, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::sessions::SessionId;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Change of the idle state the server has to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum IdleTransition {
+	Idle,
+	Active,
+}
+
+#[derive(Debug)]
+pub(super) struct IdleManager {
+	/// `None` disables idling entirely.
+	timeout: Option<Duration>,
+	last_activity: Instant,
+	inhibitors: HashSet<SessionId>,
+	idle: bool,
+}
+
+impl IdleManager {
+	pub fn new(timeout: Option<Duration>, now: Instant) -> Self {
+		Self {
+			timeout,
+			last_activity: now,
+			inhibitors: HashSet::new(),
+			idle: false,
+		}
+	}
+
+	/// Reads `SHIFT_IDLE_TIMEOUT_SECS` (default 600, `0` disables idling).
+	pub fn from_env(now: Instant) -> Self {
+		let timeout = match std::env::var("SHIFT_IDLE_TIMEOUT_SECS") {
+			Ok(raw) => match raw.trim().parse::<u64>() {
+				Ok(0) => None,
+				Ok(secs) => Some(Duration::from_secs(secs)),
+				Err(e) => {
+					tracing::warn!(value = %raw, "invalid SHIFT_IDLE_TIMEOUT_SECS: {e}");
+					Some(DEFAULT_TIMEOUT)
+				}
+			},
+			Err(_) => Some(DEFAULT_TIMEOUT),
+		};
+		Self::new(timeout, now)
+	}
+
+	pub fn is_idle(&self) -> bool {
+		self.idle
+	}
+
+	pub fn is_inhibited(&self) -> bool {
+		!self.inhibitors.is_empty()
+	}
+
+	/// Records user activity. Returns [`IdleTransition::Active`] when this wakes the outputs.
+	pub fn record_activity(&mut self, now: Instant) -> Option<IdleTransition> {
+		self.last_activity = now;
+		std::mem::take(&mut self.idle).then_some(IdleTransition::Active)
+	}
+
+	/// Returns `true` when the set of inhibitors changed.
+	pub fn set_inhibit(&mut self, session_id: SessionId, inhibit: bool, now: Instant) -> bool {
+		let changed = if inhibit {
+			self.inhibitors.insert(session_id)
+		} else {
+			self.inhibitors.remove(&session_id)
+		};
+		if changed && !inhibit && self.inhibitors.is_empty() {
+			// The idle countdown starts when the last inhibitor goes away.
+			self.last_activity = self.last_activity.max(now);
+		}
+		changed
+	}
+
+	/// Drops the inhibitor of a session that went to sleep or disconnected.
+	pub fn release(&mut self, session_id: SessionId, now: Instant) -> bool {
+		self.set_inhibit(session_id, false, now)
+	}
+
+	/// Checks the timeout; call periodically.
+	pub fn poll(&mut self, now: Instant) -> Option<IdleTransition> {
+		let timeout = self.timeout?;
+		if self.idle || self.is_inhibited() {
+			return None;
+		}
+		if now.saturating_duration_since(self.last_activity) < timeout {
+			return None;
+		}
+		self.idle = true;
+		Some(IdleTransition::Idle)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn goes_idle_after_timeout_and_wakes_on_activity() {
+		let start = Instant::now();
+		let mut idle = IdleManager::new(Some(Duration::from_secs(60)), start);
+		assert_eq!(idle.poll(start + Duration::from_secs(59)), None);
+		assert_eq!(
+			idle.poll(start + Duration::from_secs(60)),
+			Some(IdleTransition::Idle)
+		);
+		assert_eq!(idle.poll(start + Duration::from_secs(61)), None);
+		assert_eq!(
+			idle.record_activity(start + Duration::from_secs(62)),
+			Some(IdleTransition::Active)
+		);
+		assert_eq!(idle.record_activity(start + Duration::from_secs(63)), None);
+	}
+
+	#[test]
+	fn inhibitor_holds_off_idle_until_released() {
+		let start = Instant::now();
+		let session: SessionId = "se_1".parse().expect("session id");
+		let mut idle = IdleManager::new(Some(Duration::from_secs(60)), start);
+		assert!(idle.set_inhibit(session, true, start));
+		assert_eq!(idle.poll(start + Duration::from_secs(600)), None);
+		assert!(idle.release(session, start + Duration::from_secs(600)));
+		assert_eq!(idle.poll(start + Duration::from_secs(630)), None);
+		assert_eq!(
+			idle.poll(start + Duration::from_secs(660)),
+			Some(IdleTransition::Idle)
+		);
+	}
+}
@@ -0,0 +1,277 @@
+//! The monitor layout: where each monitor sits on the desktop they show parts of, and where the
+//! pointer is on it.
+//!
+//! Admins place monitors with `set_monitor_position`, which monitor profiles keep; the others
+//! are lined up left to right by connector id, top-aligned, right of the placed ones. A monitor
+//! spans its size as sessions see it, so a rotated one stands upright in the layout. Virtual
+//! monitors aren't part of it.
+//!
+//! Sessions draw their own cursors, so the pointer is tracked here. Relative motion moves it
+//! over to the monitor next to the edge it leaves by and stops it at the layout's outer edges;
+//! absolute pointers map onto the box around every monitor, and a point between monitors moves
+//! to the closest one.
+
+use std::collections::HashMap;
+
+use tab_protocol::{MonitorPlacement, MonitorPosition};
+
+use crate::monitor::{Monitor, MonitorId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+	x: i32,
+	y: i32,
+	width: i32,
+	height: i32,
+}
+
+impl Rect {
+	fn contains(&self, (x, y): (f64, f64)) -> bool {
+		let (left, top) = (self.x as f64, self.y as f64);
+		x >= left && y >= top && x < left + self.width as f64 && y < top + self.height as f64
+	}
+
+	/// The point of the rectangle closest to `(x, y)`.
+	fn clamp(&self, (x, y): (f64, f64)) -> (f64, f64) {
+		let (left, top) = (self.x as f64, self.y as f64);
+		(
+			x.clamp(left, left + (self.width - 1).max(0) as f64),
+			y.clamp(top, top + (self.height - 1).max(0) as f64),
+		)
+	}
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MonitorLayout {
+	/// Positions admins set, kept while their monitor is away.
+	placed: HashMap<MonitorId, MonitorPosition>,
+	/// Every laid out monitor, as of the last `arrange`.
+	rects: Vec<(MonitorId, Rect)>,
+	/// Pointer position on the desktop, `None` until there is a monitor to put it on.
+	pointer: Option<(f64, f64)>,
+}
+
+impl MonitorLayout {
+	/// Places `monitor_id` at `position`, or leaves it to automatic placement when `None`.
+	/// Takes effect with the next `arrange`.
+	pub fn set_position(&mut self, monitor_id: MonitorId, position: Option<MonitorPosition>) {
+		match position {
+			Some(position) => self.placed.insert(monitor_id, position),
+			None => self.placed.remove(&monitor_id),
+		};
+	}
+
+	/// The position an admin set for `monitor_id`, if any.
+	pub fn position(&self, monitor_id: MonitorId) -> Option<MonitorPosition> {
+		self.placed.get(&monitor_id).copied()
+	}
+
+	/// Forgets the position set for an unplugged monitor.
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.placed.remove(&monitor_id);
+	}
+
+	/// Lays `monitors` out and updates their `position`. A pointer left outside every monitor
+	/// goes to the middle of `home`, or of any monitor.
+	pub fn arrange(&mut self, monitors: &mut HashMap<MonitorId, Monitor>, home: Option<MonitorId>) {
+		let mut laid_out: Vec<&mut Monitor> = monitors
+			.values_mut()
+			.filter(|monitor| !monitor.offscreen)
+			.collect();
+		laid_out.sort_by_key(|monitor| (!self.placed.contains_key(&monitor.id), monitor.connector_id));
+		let mut next_x = 0;
+		self.rects.clear();
+		for monitor in laid_out {
+			let position = match self.placed.get(&monitor.id) {
+				Some(position) => *position,
+				None => MonitorPosition { x: next_x, y: 0 },
+			};
+			next_x = next_x.max(position.x + monitor.width);
+			monitor.position = position;
+			self.rects.push((
+				monitor.id,
+				Rect {
+					x: position.x,
+					y: position.y,
+					width: monitor.width,
+					height: monitor.height,
+				},
+			));
+		}
+		if self.pointer().is_none() {
+			let home = self
+				.rects
+				.iter()
+				.find(|(monitor_id, _)| Some(*monitor_id) == home)
+				.or(self.rects.first());
+			if let Some((_, rect)) = home {
+				self.pointer = Some((
+					(rect.x + rect.width / 2) as f64,
+					(rect.y + rect.height / 2) as f64,
+				));
+			}
+		}
+	}
+
+	/// Number of laid out monitors.
+	pub fn monitor_count(&self) -> usize {
+		self.rects.len()
+	}
+
+	/// Every laid out monitor's position.
+	pub fn placements(&self) -> Vec<MonitorPlacement> {
+		self
+			.rects
+			.iter()
+			.map(|(monitor_id, rect)| MonitorPlacement {
+				monitor_id: *monitor_id,
+				position: MonitorPosition {
+					x: rect.x,
+					y: rect.y,
+				},
+			})
+			.collect()
+	}
+
+	/// Monitor the pointer is on, with the pointer's position in pixels on it.
+	pub fn pointer(&self) -> Option<(MonitorId, (f64, f64))> {
+		let (x, y) = self.pointer?;
+		self
+			.rects
+			.iter()
+			.find(|(_, rect)| rect.contains((x, y)))
+			.map(|(monitor_id, rect)| (*monitor_id, (x - rect.x as f64, y - rect.y as f64)))
+	}
+
+	/// Moves the pointer by `(dx, dy)` pixels, across to the monitor it lands on.
+	pub fn move_pointer(&mut self, dx: f64, dy: f64) -> Option<(MonitorId, (f64, f64))> {
+		let (x, y) = self.pointer?;
+		self.place((x + dx, y + dy))
+	}
+
+	/// Moves the pointer to `(x, y)`, each in `0..=1` across the box around every monitor.
+	pub fn warp_pointer(&mut self, x: f64, y: f64) -> Option<(MonitorId, (f64, f64))> {
+		let left = self.rects.iter().map(|(_, rect)| rect.x).min()?;
+		let top = self.rects.iter().map(|(_, rect)| rect.y).min()?;
+		let right = self
+			.rects
+			.iter()
+			.map(|(_, rect)| rect.x + rect.width)
+			.max()?;
+		let bottom = self
+			.rects
+			.iter()
+			.map(|(_, rect)| rect.y + rect.height)
+			.max()?;
+		self.place((
+			left as f64 + x.clamp(0.0, 1.0) * (right - left) as f64,
+			top as f64 + y.clamp(0.0, 1.0) * (bottom - top) as f64,
+		))
+	}
+
+	/// Warps the pointer to `(x, y)` on `monitor_id`, in fractions of its size, keeping it on
+	/// that monitor. `None` if the monitor isn't laid out.
+	pub fn warp_pointer_onto(
+		&mut self,
+		monitor_id: MonitorId,
+		x: f64,
+		y: f64,
+	) -> Option<(MonitorId, (f64, f64))> {
+		let (_, rect) = self.rects.iter().find(|(id, _)| *id == monitor_id)?;
+		self.pointer = Some(rect.clamp((
+			rect.x as f64 + x * rect.width as f64,
+			rect.y as f64 + y * rect.height as f64,
+		)));
+		self.pointer()
+	}
+
+	fn place(&mut self, point: (f64, f64)) -> Option<(MonitorId, (f64, f64))> {
+		let closest = self
+			.rects
+			.iter()
+			.map(|(_, rect)| rect.clamp(point))
+			.min_by(|a, b| distance(*a, point).total_cmp(&distance(*b, point)))?;
+		self.pointer = Some(closest);
+		self.pointer()
+	}
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+	(a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monitor(id: &str, connector_id: u32, width: i32, height: i32) -> Monitor {
+		Monitor {
+			width,
+			height,
+			..Monitor::fixture(id, &format!("DP-{connector_id}"), connector_id)
+		}
+	}
+
+	fn id(id: &str) -> MonitorId {
+		id.parse().expect("monitor id")
+	}
+
+	#[test]
+	fn the_pointer_crosses_to_the_neighbouring_monitor() {
+		let mut monitors: HashMap<_, _> = [
+			monitor("mon_1", 40, 1920, 1080),
+			monitor("mon_2", 41, 1280, 1024),
+			monitor("mon_3", 42, 1080, 1920),
+		]
+		.into_iter()
+		.map(|monitor| (monitor.id, monitor))
+		.collect();
+		let mut layout = MonitorLayout::default();
+		layout.set_position(id("mon_3"), Some(MonitorPosition { x: -1080, y: -400 }));
+		layout.arrange(&mut monitors, Some(id("mon_2")));
+		assert_eq!(
+			monitors[&id("mon_1")].position,
+			MonitorPosition { x: 0, y: 0 }
+		);
+		assert_eq!(
+			monitors[&id("mon_2")].position,
+			MonitorPosition { x: 1920, y: 0 }
+		);
+		assert_eq!(layout.pointer(), Some((id("mon_2"), (640.0, 512.0))));
+
+		assert_eq!(
+			layout.move_pointer(-700.0, 0.0),
+			Some((id("mon_1"), (1860.0, 512.0)))
+		);
+		assert_eq!(
+			layout.move_pointer(0.0, 1000.0),
+			Some((id("mon_1"), (1860.0, 1079.0))),
+			"the pointer stops at the layout's edge"
+		);
+		assert_eq!(
+			layout.move_pointer(-2000.0, 0.0),
+			Some((id("mon_3"), (940.0, 1479.0)))
+		);
+		assert_eq!(
+			layout.warp_pointer(1.0, 0.0),
+			Some((id("mon_2"), (1279.0, 0.0))),
+			"points between monitors go to the closest one"
+		);
+
+		monitors.remove(&id("mon_2"));
+		layout.arrange(&mut monitors, Some(id("mon_2")));
+		assert_eq!(
+			layout.pointer().map(|(monitor_id, _)| monitor_id),
+			Some(id("mon_3"))
+		);
+		assert_eq!(
+			layout.warp_pointer_onto(id("mon_1"), 0.5, 0.25),
+			Some((id("mon_1"), (960.0, 270.0)))
+		);
+		assert_eq!(
+			layout.warp_pointer_onto(id("mon_1"), 1.5, -1.0),
+			Some((id("mon_1"), (1919.0, 0.0))),
+			"warps onto a monitor stay on it"
+		);
+	}
+}
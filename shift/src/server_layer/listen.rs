@@ -0,0 +1,388 @@
+//! Where Shift listens for Tab clients.
+//!
+//! Without a socket file, Shift listens on a single socket: the first one handed over by
+//! systemd socket activation (`LISTEN_PID`/`LISTEN_FDS`), else the one `SHIFT_SOCKET` names
+//! (`@name` meaning an abstract socket), else `/tmp/shift.sock`.
+//!
+//! `SHIFT_SOCKETS` names a JSON file listing several sockets instead, each restricting the roles
+//! that may authenticate through it and the permissions available through it:
+//!
+//! ```json
+//! { "sockets": [
+//!   { "address": "/run/shift/admin.sock", "mode": "0700", "roles": ["admin"] },
+//!   { "systemd": "shift-sessions", "roles": ["session", "overlay"],
+//!     "permissions": ["present", "idle_inhibit", "own_metadata", "overlay_regions"] }
+//! ] }
+//! ```
+//!
+//! `systemd` picks an activated socket by its `FileDescriptorName=`. `mode` only applies to
+//! socket paths (default `7777`); abstract sockets can't be protected by file permissions.
+//!
+//! A socket file left behind by a crashed instance is replaced, but never one a running instance
+//! still accepts connections on.
+
+use std::{
+	fmt, io,
+	os::{
+		fd::{AsRawFd, FromRawFd, OwnedFd},
+		linux::net::SocketAddrExt,
+		unix::{
+			fs::{FileTypeExt, PermissionsExt},
+			net::{SocketAddr, UnixListener, UnixStream},
+		},
+	},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use serde::Deserialize;
+use tab_protocol::SessionRole;
+
+use crate::sessions::{
+	Role,
+	permissions::{Permission, SocketRestrictions},
+};
+
+/// The first fd systemd passes, see `sd_listen_fds(3)`.
+const SD_LISTEN_FDS_START: i32 = 3;
+const DEFAULT_MODE: u32 = 0o7777;
+
+#[derive(Debug)]
+pub enum ListenAddr {
+	Path(PathBuf),
+	Abstract(String),
+	/// A listening socket inherited from systemd.
+	Inherited(OwnedFd),
+}
+
+/// A socket to listen on and what clients connecting through it may do.
+#[derive(Debug)]
+pub struct ListenSocket {
+	pub addr: ListenAddr,
+	mode: u32,
+	pub restrictions: SocketRestrictions,
+}
+
+/// A bound [`ListenSocket`].
+#[derive(Debug)]
+pub(super) struct Listener {
+	pub listener: UnixListener,
+	/// The address clients connect to, in `SHIFT_SOCKET` form.
+	pub address: Option<String>,
+	pub restrictions: Arc<SocketRestrictions>,
+}
+
+#[derive(Deserialize)]
+struct SocketFile {
+	sockets: Vec<SocketEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SocketEntry {
+	address: Option<String>,
+	systemd: Option<String>,
+	mode: Option<String>,
+	roles: Option<Vec<SessionRole>>,
+	permissions: Option<Vec<String>>,
+}
+
+impl ListenSocket {
+	/// An unrestricted socket at `address`, in `SHIFT_SOCKET` form.
+	pub fn new(address: &str) -> Self {
+		Self {
+			addr: ListenAddr::parse(address),
+			mode: DEFAULT_MODE,
+			restrictions: SocketRestrictions::default(),
+		}
+	}
+
+	/// The sockets to listen on. Fails rather than falling back to an unrestricted socket when
+	/// `SHIFT_SOCKETS` can't be used.
+	pub fn from_env() -> Result<Vec<Self>, String> {
+		let mut activated = activated_sockets();
+		let Some(path) = std::env::var_os("SHIFT_SOCKETS").filter(|v| !v.is_empty()) else {
+			if activated.len() > 1 {
+				tracing::warn!(
+					count = activated.len(),
+					"systemd passed several sockets, listening on the first only"
+				);
+			}
+			let addr = match activated.into_iter().next() {
+				Some((_, fd)) => ListenAddr::Inherited(fd),
+				None => std::env::var("SHIFT_SOCKET")
+					.ok()
+					.filter(|v| !v.is_empty())
+					.map_or_else(
+						|| ListenAddr::Path(tab_protocol::DEFAULT_SOCKET_PATH.into()),
+						|v| ListenAddr::parse(&v),
+					),
+			};
+			return Ok(vec![Self {
+				addr,
+				mode: DEFAULT_MODE,
+				restrictions: SocketRestrictions::default(),
+			}]);
+		};
+		let path = PathBuf::from(path);
+		let bytes = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+		let file: SocketFile =
+			serde_json::from_slice(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+		if file.sockets.is_empty() {
+			return Err(format!("{}: no sockets configured", path.display()));
+		}
+		let sockets = file
+			.sockets
+			.into_iter()
+			.map(|entry| Self::from_entry(entry, &mut activated))
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| format!("{}: {e}", path.display()))?;
+		for (name, _) in activated {
+			tracing::warn!(
+				name,
+				"closing a systemd socket that SHIFT_SOCKETS doesn't use"
+			);
+		}
+		Ok(sockets)
+	}
+
+	fn from_entry(
+		entry: SocketEntry,
+		activated: &mut Vec<(String, OwnedFd)>,
+	) -> Result<Self, String> {
+		let addr = match (entry.address, entry.systemd) {
+			(Some(address), None) => ListenAddr::parse(&address),
+			(None, Some(name)) => {
+				let index = activated
+					.iter()
+					.position(|(activated, _)| *activated == name)
+					.ok_or_else(|| format!("systemd did not pass a socket named {name:?}"))?;
+				ListenAddr::Inherited(activated.remove(index).1)
+			}
+			_ => return Err("each socket needs exactly one of `address` and `systemd`".into()),
+		};
+		let mode = match entry.mode {
+			Some(_) if !matches!(addr, ListenAddr::Path(_)) => {
+				return Err(format!("{addr}: `mode` only applies to socket paths"));
+			}
+			Some(mode) => u32::from_str_radix(&mode, 8)
+				.ok()
+				.filter(|mode| *mode <= 0o7777)
+				.ok_or_else(|| format!("{addr}: invalid mode {mode:?}"))?,
+			None => DEFAULT_MODE,
+		};
+		let permissions = entry
+			.permissions
+			.map(|names| {
+				names
+					.iter()
+					.map(|name| {
+						Permission::from_name(name)
+							.ok_or_else(|| format!("{addr}: unknown permission {name:?}"))
+					})
+					.collect::<Result<Vec<_>, _>>()
+			})
+			.transpose()?;
+		let roles = entry
+			.roles
+			.map(|roles| {
+				roles
+					.into_iter()
+					.map(|role| Role::try_from(role).map_err(|()| format!("{addr}: unknown role")))
+					.collect::<Result<Vec<_>, _>>()
+			})
+			.transpose()?;
+		Ok(Self {
+			addr,
+			mode,
+			restrictions: SocketRestrictions { roles, permissions },
+		})
+	}
+
+	pub(super) fn bind(self) -> io::Result<Listener> {
+		let listener = match self.addr {
+			ListenAddr::Path(path) => {
+				claim_path(&path)?;
+				let listener = UnixListener::bind(&path)?;
+				std::fs::set_permissions(&path, std::fs::Permissions::from_mode(self.mode))?;
+				listener
+			}
+			ListenAddr::Abstract(name) => {
+				UnixListener::bind_addr(&SocketAddr::from_abstract_name(name.as_bytes())?)?
+			}
+			ListenAddr::Inherited(fd) => UnixListener::from(fd),
+		};
+		let address = listener
+			.local_addr()
+			.ok()
+			.and_then(|addr| client_address(&addr));
+		Ok(Listener {
+			listener,
+			address,
+			restrictions: Arc::new(self.restrictions),
+		})
+	}
+}
+
+impl ListenAddr {
+	fn parse(value: &str) -> Self {
+		match value.strip_prefix('@') {
+			Some(name) => Self::Abstract(name.to_string()),
+			None => Self::Path(value.into()),
+		}
+	}
+}
+
+impl fmt::Display for ListenAddr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Path(path) => write!(f, "{}", path.display()),
+			Self::Abstract(name) => write!(f, "@{name}"),
+			Self::Inherited(fd) => write!(f, "systemd socket (fd {})", fd.as_raw_fd()),
+		}
+	}
+}
+
+/// The sockets systemd passed to us, with their `LISTEN_FDNAMES` names.
+fn activated_sockets() -> Vec<(String, OwnedFd)> {
+	let Some(count) = activation_count(
+		std::env::var("LISTEN_PID").ok().as_deref(),
+		std::env::var("LISTEN_FDS").ok().as_deref(),
+		std::process::id(),
+	) else {
+		return Vec::new();
+	};
+	let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+	let mut names = names.split(':');
+	(0..count)
+		.map(|i| {
+			let fd = SD_LISTEN_FDS_START + i;
+			// SAFETY: systemd passed this fd to us (LISTEN_PID matched) and nothing else owns it.
+			let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+			// Sessions Shift launches must not inherit the listening socket.
+			unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) };
+			(names.next().unwrap_or_default().to_string(), fd)
+		})
+		.collect()
+}
+
+/// How many fds systemd passed, if it activated us.
+fn activation_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Option<i32> {
+	if listen_pid?.trim().parse::<u32>().ok()? != pid {
+		return None;
+	}
+	listen_fds?.trim().parse().ok().filter(|count| *count >= 1)
+}
+
+fn client_address(addr: &SocketAddr) -> Option<String> {
+	if let Some(path) = addr.as_pathname() {
+		return Some(path.display().to_string());
+	}
+	addr
+		.as_abstract_name()
+		.map(|name| format!("@{}", String::from_utf8_lossy(name)))
+}
+
+/// Makes room for a socket at `path`: removes a stale socket nobody listens on, and refuses a
+/// live socket or a file that isn't a socket.
+fn claim_path(path: &Path) -> io::Result<()> {
+	let metadata = match std::fs::symlink_metadata(path) {
+		Ok(metadata) => metadata,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e),
+	};
+	if !metadata.file_type().is_socket() {
+		return Err(io::Error::new(
+			io::ErrorKind::AlreadyExists,
+			format!("{} exists and is not a socket", path.display()),
+		));
+	}
+	match UnixStream::connect(path) {
+		Ok(_) => Err(io::Error::new(
+			io::ErrorKind::AddrInUse,
+			format!("another Shift instance is listening on {}", path.display()),
+		)),
+		Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+			tracing::info!(path = %path.display(), "removing stale socket");
+			std::fs::remove_file(path)
+		}
+		Err(e) => Err(e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(json: &str) -> Result<ListenSocket, String> {
+		ListenSocket::from_entry(serde_json::from_str(json).unwrap(), &mut Vec::new())
+	}
+
+	#[test]
+	fn activation_requires_matching_pid() {
+		assert_eq!(activation_count(Some("42"), Some("1"), 42), Some(1));
+		assert_eq!(activation_count(Some("42"), Some("2"), 42), Some(2));
+		assert_eq!(activation_count(Some("41"), Some("1"), 42), None);
+		assert_eq!(activation_count(Some("42"), Some("0"), 42), None);
+		assert_eq!(activation_count(None, Some("1"), 42), None);
+		assert_eq!(activation_count(Some("42"), None, 42), None);
+	}
+
+	#[test]
+	fn parses_socket_names() {
+		let ListenAddr::Abstract(name) = ListenAddr::parse("@shift") else {
+			panic!("expected an abstract name");
+		};
+		assert_eq!(name, "shift");
+		let ListenAddr::Path(path) = ListenAddr::parse("/run/shift.sock") else {
+			panic!("expected a path");
+		};
+		assert_eq!(path, Path::new("/run/shift.sock"));
+	}
+
+	#[test]
+	fn parses_socket_entries() {
+		let admin = entry(
+			r#"{"address": "/run/shift/admin.sock", "mode": "0700", "roles": ["admin"], "permissions": ["session_management"]}"#,
+		)
+		.unwrap();
+		assert_eq!(admin.mode, 0o700);
+		assert_eq!(
+			admin.restrictions,
+			SocketRestrictions {
+				roles: Some(vec![Role::Admin]),
+				permissions: Some(vec![Permission::SessionManagement]),
+			}
+		);
+		let open = entry(r#"{"address": "@shift"}"#).unwrap();
+		assert_eq!(open.restrictions, SocketRestrictions::default());
+
+		assert!(entry(r#"{"address": "@shift", "mode": "0700"}"#).is_err());
+		assert!(entry(r#"{"address": "/a", "mode": "rwx"}"#).is_err());
+		assert!(entry(r#"{"address": "/a", "permissions": ["root"]}"#).is_err());
+		assert!(entry(r#"{"systemd": "missing"}"#).is_err());
+		assert!(entry(r#"{}"#).is_err());
+	}
+
+	#[test]
+	fn claims_stale_sockets_only() {
+		let dir = std::env::temp_dir().join(format!("shift-listen-test-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("stale.sock");
+		drop(UnixListener::bind(&path).unwrap());
+		claim_path(&path).unwrap();
+		assert!(!path.exists());
+
+		let live = UnixListener::bind(&path).unwrap();
+		assert_eq!(
+			claim_path(&path).unwrap_err().kind(),
+			io::ErrorKind::AddrInUse
+		);
+		drop(live);
+
+		let file = dir.join("file");
+		std::fs::write(&file, b"").unwrap();
+		assert!(claim_path(&file).is_err());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,178 @@
+//! Optional liveness requirement for sessions: an awake session that sends nothing (not even a
+//! `ping`) for too long is marked unresponsive, which admins see in `session_state`, and can
+//! be killed after a further grace period.
+//!
+//! Only awake sessions are watched. Sleeping sessions may be frozen, so their clock restarts
+//! when they wake up. Admin sessions are never tracked.
+
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::sessions::SessionId;
+
+/// What the server has to do after [`Liveness::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LivenessEvent {
+	Unresponsive(SessionId),
+	Kill(SessionId),
+}
+
+#[derive(Debug)]
+struct Tracked {
+	last_seen: Instant,
+	watched: bool,
+	unresponsive_since: Option<Instant>,
+	killed: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct Liveness {
+	/// `None` disables the requirement.
+	timeout: Option<Duration>,
+	/// How long a session may stay unresponsive before it is killed; `None` never kills.
+	kill_after: Option<Duration>,
+	sessions: HashMap<SessionId, Tracked>,
+}
+
+impl Liveness {
+	pub fn new(timeout: Option<Duration>, kill_after: Option<Duration>) -> Self {
+		Self {
+			timeout,
+			kill_after,
+			sessions: HashMap::new(),
+		}
+	}
+
+	/// Reads `SHIFT_SESSION_LIVENESS_SECS` and `SHIFT_UNRESPONSIVE_KILL_SECS`. Both are off when
+	/// unset or `0`.
+	pub fn from_env() -> Self {
+		let secs = |name: &str| {
+			std::env::var(name)
+				.ok()
+				.and_then(|v| v.trim().parse::<u64>().ok())
+				.filter(|secs| *secs > 0)
+				.map(Duration::from_secs)
+		};
+		Self::new(
+			secs("SHIFT_SESSION_LIVENESS_SECS"),
+			secs("SHIFT_UNRESPONSIVE_KILL_SECS"),
+		)
+	}
+
+	pub fn track(&mut self, session_id: SessionId, watched: bool, now: Instant) {
+		if self.timeout.is_none() {
+			return;
+		}
+		self.sessions.insert(
+			session_id,
+			Tracked {
+				last_seen: now,
+				watched,
+				unresponsive_since: None,
+				killed: false,
+			},
+		);
+	}
+
+	pub fn forget(&mut self, session_id: SessionId) {
+		self.sessions.remove(&session_id);
+	}
+
+	/// Records a message from the session. Returns `true` when it was unresponsive until now.
+	pub fn seen(&mut self, session_id: SessionId, now: Instant) -> bool {
+		let Some(tracked) = self.sessions.get_mut(&session_id) else {
+			return false;
+		};
+		tracked.last_seen = now;
+		tracked.killed = false;
+		tracked.unresponsive_since.take().is_some()
+	}
+
+	/// Starts or stops watching a session as it wakes up or goes to sleep.
+	pub fn set_watched(&mut self, session_id: SessionId, watched: bool, now: Instant) {
+		let Some(tracked) = self.sessions.get_mut(&session_id) else {
+			return;
+		};
+		if watched && !tracked.watched {
+			tracked.last_seen = now;
+		}
+		tracked.watched = watched;
+	}
+
+	pub fn is_unresponsive(&self, session_id: SessionId) -> bool {
+		self
+			.sessions
+			.get(&session_id)
+			.is_some_and(|tracked| tracked.unresponsive_since.is_some())
+	}
+
+	/// Checks the deadlines; call periodically.
+	pub fn poll(&mut self, now: Instant) -> Vec<LivenessEvent> {
+		let Some(timeout) = self.timeout else {
+			return Vec::new();
+		};
+		let mut events = Vec::new();
+		for (&session_id, tracked) in &mut self.sessions {
+			match tracked.unresponsive_since {
+				None if tracked.watched && now.saturating_duration_since(tracked.last_seen) >= timeout => {
+					tracked.unresponsive_since = Some(now);
+					events.push(LivenessEvent::Unresponsive(session_id));
+				}
+				Some(since)
+					if !tracked.killed
+						&& self
+							.kill_after
+							.is_some_and(|grace| now.saturating_duration_since(since) >= grace) =>
+				{
+					tracked.killed = true;
+					events.push(LivenessEvent::Kill(session_id));
+				}
+				_ => {}
+			}
+		}
+		events.sort_by_key(|event| match event {
+			LivenessEvent::Unresponsive(id) | LivenessEvent::Kill(id) => id.to_string(),
+		});
+		events
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn silent_awake_sessions_become_unresponsive_then_get_killed() {
+		let start = Instant::now();
+		let secs = |n| start + Duration::from_secs(n);
+		let awake: SessionId = "se_1".parse().expect("session id");
+		let asleep: SessionId = "se_2".parse().expect("session id");
+		let mut liveness = Liveness::new(Some(Duration::from_secs(10)), Some(Duration::from_secs(5)));
+		liveness.track(awake, true, start);
+		liveness.track(asleep, false, start);
+		assert!(liveness.poll(secs(9)).is_empty());
+		assert_eq!(
+			liveness.poll(secs(10)),
+			[LivenessEvent::Unresponsive(awake)]
+		);
+		assert!(liveness.is_unresponsive(awake));
+		assert!(liveness.poll(secs(14)).is_empty());
+		assert_eq!(liveness.poll(secs(15)), [LivenessEvent::Kill(awake)]);
+		assert!(liveness.poll(secs(16)).is_empty());
+		assert!(liveness.seen(awake, secs(16)));
+		assert!(!liveness.seen(awake, secs(17)));
+
+		// Waking up restarts the clock instead of counting the time spent asleep.
+		liveness.set_watched(asleep, true, secs(100));
+		liveness.seen(awake, secs(100));
+		assert!(liveness.poll(secs(109)).is_empty());
+		assert_eq!(
+			liveness.poll(secs(110)),
+			[
+				LivenessEvent::Unresponsive(awake),
+				LivenessEvent::Unresponsive(asleep)
+			]
+		);
+	}
+}
@@ -0,0 +1,184 @@
+//! Server-side state of the accessibility magnifier: zoom factor, the pointer focus it follows
+//! and the built-in `Super+=` / `Super+-` / `Super+0` key combos.
+//!
+//! Sessions draw their own cursors, so the focus point is reconstructed here from raw pointer
+//! motion in normalized monitor coordinates. The renderer eases towards it for smooth panning.
+
+use std::collections::HashSet;
+
+use tab_protocol::{InputEventPayload, KeyState};
+
+use crate::monitor::MonitorId;
+
+const MIN_FACTOR: f32 = 1.0;
+const MAX_FACTOR: f32 = 16.0;
+const KEY_STEP: f32 = 1.25;
+
+// Linux input event codes.
+const KEY_0: u32 = 11;
+const KEY_MINUS: u32 = 12;
+const KEY_EQUAL: u32 = 13;
+const KEY_LEFTMETA: u32 = 125;
+const KEY_RIGHTMETA: u32 = 126;
+
+/// What the server should do after feeding an input event to the magnifier.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct MagnifierInput {
+	/// The event was a magnifier key combo and must not reach the session.
+	pub consumed: bool,
+	/// The zoom factor changed.
+	pub factor_changed: bool,
+	/// The focus point moved while zoomed in.
+	pub focus_changed: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct Magnifier {
+	monitor_id: Option<MonitorId>,
+	factor: f32,
+	focus: (f64, f64),
+	keybindings: bool,
+	meta_held: u8,
+	/// Keys whose press was consumed, so their release is swallowed too.
+	consumed_keys: HashSet<u32>,
+}
+
+impl Magnifier {
+	pub fn new(keybindings: bool) -> Self {
+		Self {
+			monitor_id: None,
+			factor: MIN_FACTOR,
+			focus: (0.5, 0.5),
+			keybindings,
+			meta_held: 0,
+			consumed_keys: HashSet::new(),
+		}
+	}
+
+	pub fn monitor_id(&self) -> Option<MonitorId> {
+		self.monitor_id
+	}
+
+	pub fn factor(&self) -> f32 {
+		self.factor
+	}
+
+	/// Normalized `(x, y)` focus point on the magnified monitor.
+	pub fn focus(&self) -> (f32, f32) {
+		(self.focus.0 as f32, self.focus.1 as f32)
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.factor > MIN_FACTOR
+	}
+
+	pub fn set(&mut self, monitor_id: Option<MonitorId>, factor: f32) {
+		self.monitor_id = monitor_id;
+		self.factor = factor.clamp(MIN_FACTOR, MAX_FACTOR);
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		if self.monitor_id == Some(monitor_id) {
+			self.monitor_id = None;
+		}
+	}
+
+	/// `monitor_size` is the size in pixels of the monitor the focus point lives on, used to
+	/// convert relative pointer motion.
+	pub fn handle_input(
+		&mut self,
+		event: &InputEventPayload,
+		monitor_size: Option<(f64, f64)>,
+	) -> MagnifierInput {
+		let mut result = MagnifierInput::default();
+		match event {
+			InputEventPayload::PointerMotion { dx, dy, .. } => {
+				let Some((width, height)) = monitor_size.filter(|(w, h)| *w > 0.0 && *h > 0.0) else {
+					return result;
+				};
+				self.focus = (
+					(self.focus.0 + dx / width).clamp(0.0, 1.0),
+					(self.focus.1 + dy / height).clamp(0.0, 1.0),
+				);
+				result.focus_changed = self.is_active();
+			}
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				..
+			} => {
+				self.focus = (
+					(x_transformed / 65535.0).clamp(0.0, 1.0),
+					(y_transformed / 65535.0).clamp(0.0, 1.0),
+				);
+				result.focus_changed = self.is_active();
+			}
+			InputEventPayload::Key { key, state, .. } if self.keybindings => {
+				let pressed = matches!(state, KeyState::Pressed);
+				if matches!(*key, KEY_LEFTMETA | KEY_RIGHTMETA) {
+					self.meta_held = if pressed {
+						self.meta_held.saturating_add(1)
+					} else {
+						self.meta_held.saturating_sub(1)
+					};
+					return result;
+				}
+				if !pressed {
+					result.consumed = self.consumed_keys.remove(key);
+					return result;
+				}
+				if self.meta_held == 0 {
+					return result;
+				}
+				let factor = match *key {
+					KEY_EQUAL => self.factor * KEY_STEP,
+					KEY_MINUS => self.factor / KEY_STEP,
+					KEY_0 => MIN_FACTOR,
+					_ => return result,
+				}
+				.clamp(MIN_FACTOR, MAX_FACTOR);
+				self.consumed_keys.insert(*key);
+				result.consumed = true;
+				result.factor_changed = factor != self.factor;
+				self.factor = factor;
+			}
+			_ => {}
+		}
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(key: u32, state: KeyState) -> InputEventPayload {
+		InputEventPayload::Key {
+			device: 0,
+			time_usec: 0,
+			key,
+			state,
+		}
+	}
+
+	#[test]
+	fn super_equal_zooms_in_and_swallows_the_combo() {
+		let mut magnifier = Magnifier::new(true);
+		let meta = magnifier.handle_input(&key(KEY_LEFTMETA, KeyState::Pressed), None);
+		assert!(!meta.consumed);
+		let press = magnifier.handle_input(&key(KEY_EQUAL, KeyState::Pressed), None);
+		assert!(press.consumed && press.factor_changed);
+		assert!(magnifier.factor() > MIN_FACTOR);
+		magnifier.handle_input(&key(KEY_LEFTMETA, KeyState::Released), None);
+		let release = magnifier.handle_input(&key(KEY_EQUAL, KeyState::Released), None);
+		assert!(
+			release.consumed,
+			"release of a consumed press must be swallowed"
+		);
+		let plain = magnifier.handle_input(&key(KEY_EQUAL, KeyState::Pressed), None);
+		assert!(
+			!plain.consumed,
+			"without Super the key belongs to the session"
+		);
+	}
+}
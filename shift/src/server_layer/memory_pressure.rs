@@ -0,0 +1,151 @@
+//! Reaction to memory pressure.
+//!
+//! Shift registers a PSI trigger on its cgroup's `memory.pressure`, or on the system-wide
+//! `/proc/pressure/memory` outside a cgroup v2 hierarchy, and is woken when tasks stall on
+//! memory. What it can spare is then released: Skia's cached GPU resources and the copies of
+//! sleeping sessions' last frames, and with `SHIFT_MEMORY_PRESSURE=release` the buffers of
+//! sleeping sessions too, which are told with `release_buffers` so they can free them.
+//! `SHIFT_MEMORY_PRESSURE=off` ignores pressure; `purge` (the default) releases only Shift's
+//! own copies.
+
+use std::{
+	fs::{File, OpenOptions},
+	io::Write,
+	path::PathBuf,
+	time::Duration,
+};
+
+use tokio::{
+	io::{Interest, unix::AsyncFd},
+	time::Instant,
+};
+
+/// Fires when some task stalled on memory for 200ms within 2s; unprivileged triggers need a
+/// window that is a multiple of 2s.
+const TRIGGER: &str = "some 200000 2000000";
+/// Pressure is acted on at most this often; releasing again right away finds nothing new.
+const COOLDOWN: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum PressurePolicy {
+	Off,
+	#[default]
+	Purge,
+	Release,
+}
+
+impl PressurePolicy {
+	fn parse(value: &str) -> Self {
+		match value.trim().to_ascii_lowercase().as_str() {
+			"off" | "0" => PressurePolicy::Off,
+			"" | "purge" => PressurePolicy::Purge,
+			"release" => PressurePolicy::Release,
+			other => {
+				tracing::warn!(policy = other, "unknown SHIFT_MEMORY_PRESSURE, using purge");
+				PressurePolicy::Purge
+			}
+		}
+	}
+}
+
+#[derive(Debug)]
+pub(super) struct MemoryPressure {
+	policy: PressurePolicy,
+	trigger: Option<AsyncFd<File>>,
+	last_event: Option<Instant>,
+}
+
+impl MemoryPressure {
+	/// Reads `SHIFT_MEMORY_PRESSURE` and registers the trigger, unless it is `off`.
+	pub fn from_env() -> Self {
+		let policy = std::env::var("SHIFT_MEMORY_PRESSURE")
+			.map(|value| PressurePolicy::parse(&value))
+			.unwrap_or_default();
+		let trigger = (policy != PressurePolicy::Off)
+			.then(|| {
+				open_trigger()
+					.inspect_err(|e| tracing::info!("memory pressure not monitored: {e}"))
+					.ok()
+			})
+			.flatten();
+		Self {
+			policy,
+			trigger,
+			last_event: None,
+		}
+	}
+
+	/// Whether sleeping sessions are asked to release their buffers.
+	pub fn releases_buffers(&self) -> bool {
+		self.policy == PressurePolicy::Release
+	}
+
+	/// Waits for the next pressure event; never returns when pressure isn't monitored.
+	pub async fn wait(&mut self) {
+		loop {
+			let Some(trigger) = &self.trigger else {
+				return std::future::pending().await;
+			};
+			match trigger.ready(Interest::PRIORITY).await {
+				Ok(mut guard) => guard.clear_ready(),
+				Err(e) => {
+					tracing::warn!("memory pressure trigger failed, no longer monitoring: {e}");
+					self.trigger = None;
+					continue;
+				}
+			}
+			let now = Instant::now();
+			if self
+				.last_event
+				.is_none_or(|last| now.duration_since(last) >= COOLDOWN)
+			{
+				self.last_event = Some(now);
+				return;
+			}
+		}
+	}
+}
+
+fn open_trigger() -> std::io::Result<AsyncFd<File>> {
+	let path = std::fs::read_to_string("/proc/self/cgroup")
+		.ok()
+		.and_then(|cgroup| cgroup_pressure_path(&cgroup))
+		.filter(|path| path.exists())
+		.unwrap_or_else(|| PathBuf::from("/proc/pressure/memory"));
+	let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+	file.write_all(TRIGGER.as_bytes())?;
+	tracing::debug!(path = %path.display(), "monitoring memory pressure");
+	AsyncFd::with_interest(file, Interest::PRIORITY)
+}
+
+/// `memory.pressure` of the cgroup v2 entry (`0::<path>`) in `/proc/self/cgroup`.
+fn cgroup_pressure_path(cgroup: &str) -> Option<PathBuf> {
+	let path = cgroup.lines().find_map(|line| line.strip_prefix("0::"))?;
+	let mut pressure = PathBuf::from("/sys/fs/cgroup");
+	pressure.push(path.trim().trim_start_matches('/'));
+	pressure.push("memory.pressure");
+	Some(pressure)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn policy_and_cgroup_path_parse() {
+		assert_eq!(PressurePolicy::parse("Release"), PressurePolicy::Release);
+		assert_eq!(PressurePolicy::parse("off"), PressurePolicy::Off);
+		assert_eq!(PressurePolicy::parse("sometimes"), PressurePolicy::Purge);
+		assert_eq!(
+			cgroup_pressure_path("1:name=systemd:/init.scope\n0::/user.slice/session-2.scope\n"),
+			Some(PathBuf::from(
+				"/sys/fs/cgroup/user.slice/session-2.scope/memory.pressure"
+			))
+		);
+		assert_eq!(
+			cgroup_pressure_path("0::/\n"),
+			Some(PathBuf::from("/sys/fs/cgroup/memory.pressure"))
+		);
+		assert_eq!(cgroup_pressure_path("1:cpu:/legacy\n"), None);
+	}
+}
@@ -1,4 +1,29 @@
+mod audio_policy;
+mod background;
+mod connector_properties;
+mod cursor;
+mod flip_groups;
+mod handover;
+mod idle;
+mod layout;
+mod listen;
+mod liveness;
+mod magnifier;
+mod memory_pressure;
+mod monitor_profiles;
+mod monitor_scales;
+mod overlay;
+mod primary_monitor;
+mod recording;
+mod recovery;
+mod resize;
+mod sandbox;
+mod screencasts;
 mod server;
+mod switcher;
+mod virtual_monitors;
+mod watermark;
 
+pub use listen::ListenSocket;
 pub use server::BindError;
 pub use server::ShiftServer;
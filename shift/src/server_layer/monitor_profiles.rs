@@ -0,0 +1,263 @@
+//! Monitor configuration profiles keyed by the set of connected monitors.
+//!
+//! Monitors are identified by their EDID (manufacturer, product code and serial number), so a
+//! profile follows the physical screens across ports and reboots. When admins save a profile,
+//! Shift stores which monitors are enabled, which one is primary, and their display adjustments,
+//! transforms, scales and layout positions for the current combination of monitors. The profile
+//! is reapplied whenever that exact combination is connected again, e.g. when docking or
+//! undocking a laptop.
+//!
+//! Profiles are kept as JSON in `SHIFT_MONITOR_PROFILES` (`/var/lib/shift/monitor-profiles.json`
+//! by default); an empty value disables them.
+
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	io,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tab_protocol::{MonitorPosition, MonitorScale, OutputTransform};
+
+use crate::{drm_card::read_edid, monitor::MonitorId};
+
+const DEFAULT_PATH: &str = "/var/lib/shift/monitor-profiles.json";
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// Software brightness/contrast/gamma, as set with `display_adjust`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(super) struct DisplayAdjustment {
+	pub brightness: f32,
+	pub contrast: f32,
+	pub gamma: f32,
+}
+
+impl DisplayAdjustment {
+	pub const IDENTITY: Self = Self {
+		brightness: 0.0,
+		contrast: 1.0,
+		gamma: 1.0,
+	};
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct MonitorSettings {
+	pub enabled: bool,
+	#[serde(default)]
+	pub primary: bool,
+	#[serde(default)]
+	pub adjustment: Option<DisplayAdjustment>,
+	/// Set for monitors that aren't upright, as with `set_output_transform`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub transform: Option<OutputTransform>,
+	/// Set for monitors scaled with `set_monitor_scale`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub scale: Option<MonitorScale>,
+	/// Set for monitors placed with `set_monitor_position`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub position: Option<MonitorPosition>,
+}
+
+/// Settings per monitor identity; the keys are the topology the profile applies to.
+pub(super) type Profile = BTreeMap<String, MonitorSettings>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfileFile {
+	profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MonitorProfiles {
+	/// `None` when profiles are disabled.
+	path: Option<PathBuf>,
+	profiles: Vec<Profile>,
+}
+
+impl MonitorProfiles {
+	pub fn from_env() -> Self {
+		let path = match std::env::var("SHIFT_MONITOR_PROFILES") {
+			Ok(value) if value.trim().is_empty() => return Self::default(),
+			Ok(value) => PathBuf::from(value.trim()),
+			Err(_) => PathBuf::from(DEFAULT_PATH),
+		};
+		let profiles = match std::fs::read(&path) {
+			Ok(bytes) => match serde_json::from_slice::<ProfileFile>(&bytes) {
+				Ok(file) => file.profiles,
+				Err(e) => {
+					tracing::warn!(path = %path.display(), "ignoring unreadable monitor profiles: {e}");
+					Vec::new()
+				}
+			},
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+			Err(e) => {
+				tracing::warn!(path = %path.display(), "failed to read monitor profiles: {e}");
+				Vec::new()
+			}
+		};
+		Self {
+			path: Some(path),
+			profiles,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.path.is_some()
+	}
+
+	/// The profile saved for exactly this set of monitor identities.
+	pub fn find(&self, topology: &BTreeSet<String>) -> Option<&Profile> {
+		self
+			.profiles
+			.iter()
+			.find(|profile| profile.keys().eq(topology.iter()))
+	}
+
+	/// Stores `profile`, replacing the one saved for the same topology.
+	pub fn save(&mut self, profile: Profile) -> io::Result<()> {
+		self
+			.profiles
+			.retain(|saved| !saved.keys().eq(profile.keys()));
+		self.profiles.push(profile);
+		self.persist()
+	}
+
+	/// Forgets the profile of `topology`; `false` when there was none.
+	pub fn delete(&mut self, topology: &BTreeSet<String>) -> io::Result<bool> {
+		let before = self.profiles.len();
+		self
+			.profiles
+			.retain(|profile| !profile.keys().eq(topology.iter()));
+		if self.profiles.len() == before {
+			return Ok(false);
+		}
+		self.persist().map(|()| true)
+	}
+
+	fn persist(&self) -> io::Result<()> {
+		let Some(path) = self.path.as_deref() else {
+			return Ok(());
+		};
+		let file = ProfileFile {
+			profiles: self.profiles.clone(),
+		};
+		write_atomically(path, &serde_json::to_vec_pretty(&file)?)
+	}
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	let tmp = path.with_extension("json.tmp");
+	std::fs::write(&tmp, contents)?;
+	std::fs::rename(&tmp, path)
+}
+
+/// `MFG-PRODUCT-SERIAL` from an EDID base block, e.g. `DEL-a0c4-4c4f4a30`.
+pub(super) fn edid_identity(edid: &[u8]) -> Option<String> {
+	if edid.len() < 16 || edid[..8] != EDID_HEADER {
+		return None;
+	}
+	let vendor = u16::from_be_bytes([edid[8], edid[9]]);
+	let manufacturer: String = [10, 5, 0]
+		.iter()
+		.map(|shift| char::from(b'@' + ((vendor >> shift) & 0x1f) as u8))
+		.collect();
+	let product = u16::from_le_bytes([edid[10], edid[11]]);
+	let serial = u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]);
+	Some(format!("{manufacturer}-{product:04x}-{serial:08x}"))
+}
+
+/// Identity of the monitor on `connector_id`: its EDID when the kernel exposes one, otherwise
+/// the connector itself.
+pub(super) fn read_identity(connector_id: u32) -> String {
+	read_edid(connector_id)
+		.and_then(|edid| edid_identity(&edid))
+		.unwrap_or_else(|| format!("connector-{connector_id}"))
+}
+
+/// Keys for the connected monitors, given `(monitor, connector id, identity)`. Identical
+/// monitors without serial numbers share an identity, so those get their connector appended.
+pub(super) fn topology_keys(
+	monitors: impl IntoIterator<Item = (MonitorId, u32, String)>,
+) -> HashMap<MonitorId, String> {
+	let monitors: Vec<_> = monitors.into_iter().collect();
+	let mut counts: HashMap<&str, usize> = HashMap::new();
+	for (_, _, identity) in &monitors {
+		*counts.entry(identity.as_str()).or_default() += 1;
+	}
+	monitors
+		.iter()
+		.map(|(monitor_id, connector_id, identity)| {
+			let key = if counts[identity.as_str()] > 1 {
+				format!("{identity}@{connector_id}")
+			} else {
+				identity.clone()
+			};
+			(*monitor_id, key)
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn settings(enabled: bool) -> MonitorSettings {
+		MonitorSettings {
+			enabled,
+			primary: false,
+			adjustment: None,
+			transform: None,
+			scale: None,
+			position: None,
+		}
+	}
+
+	#[test]
+	fn decodes_edid_vendor_product_and_serial() {
+		let mut edid = EDID_HEADER.to_vec();
+		// "DEL", product 0xa0c4, serial 0x4c4f4a30.
+		edid.extend_from_slice(&[0x10, 0xac, 0xc4, 0xa0, 0x30, 0x4a, 0x4f, 0x4c]);
+		assert_eq!(edid_identity(&edid).as_deref(), Some("DEL-a0c4-4c4f4a30"));
+		assert_eq!(edid_identity(&edid[1..]), None);
+	}
+
+	#[test]
+	fn duplicate_identities_are_told_apart_by_connector() {
+		let keys = topology_keys([
+			(MonitorId::from_raw(1), 40, "AAA-0001-00000000".to_string()),
+			(MonitorId::from_raw(2), 41, "AAA-0001-00000000".to_string()),
+			(MonitorId::from_raw(3), 42, "BBB-0002-00000001".to_string()),
+		]);
+		assert_eq!(keys[&MonitorId::from_raw(1)], "AAA-0001-00000000@40");
+		assert_eq!(keys[&MonitorId::from_raw(2)], "AAA-0001-00000000@41");
+		assert_eq!(keys[&MonitorId::from_raw(3)], "BBB-0002-00000001");
+	}
+
+	#[test]
+	fn profiles_match_the_exact_topology() {
+		let mut profiles = MonitorProfiles::default();
+		let docked: Profile = [
+			("a".to_string(), settings(false)),
+			("b".into(), settings(true)),
+		]
+		.into_iter()
+		.collect();
+		profiles.save(docked.clone()).expect("in-memory save");
+		let topology = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect();
+		assert_eq!(profiles.find(&topology(&["b", "a"])), Some(&docked));
+		assert_eq!(profiles.find(&topology(&["a"])), None);
+		assert!(
+			!profiles
+				.delete(&topology(&["a"]))
+				.expect("in-memory delete")
+		);
+		assert!(
+			profiles
+				.delete(&topology(&["a", "b"]))
+				.expect("in-memory delete")
+		);
+		assert_eq!(profiles.find(&topology(&["a", "b"])), None);
+	}
+}
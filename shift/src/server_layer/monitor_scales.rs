@@ -0,0 +1,85 @@
+//! Scale factors from `SHIFT_MONITOR_SCALE`: comma separated `<monitor>=<factor>` entries, a
+//! monitor being named or given by connector id as in `SHIFT_PRIMARY_MONITOR`, and optionally a
+//! bare `<factor>` for all other monitors, e.g. `eDP-1=1.5,1`. Scales set with
+//! `set_monitor_scale` or restored from a monitor profile take precedence.
+//!
+//! `SHIFT_RENDER_RESOLUTION` takes the same form, for the resolution monitors are composed at.
+
+use tab_protocol::MonitorScale;
+
+use crate::monitor::Monitor;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct ConfiguredScales {
+	monitors: Vec<(String, MonitorScale)>,
+	fallback: Option<MonitorScale>,
+}
+
+impl ConfiguredScales {
+	pub fn from_env() -> Self {
+		Self::from_var("SHIFT_MONITOR_SCALE")
+	}
+
+	/// Scales from the environment variable `name`.
+	pub fn from_var(name: &str) -> Self {
+		std::env::var(name)
+			.map(|value| Self::parse(name, &value))
+			.unwrap_or_default()
+	}
+
+	fn parse(name: &str, value: &str) -> Self {
+		let mut scales = Self::default();
+		for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+			let (monitor, factor) = match entry.rsplit_once('=') {
+				Some((monitor, factor)) => (Some(monitor.trim()), factor.trim()),
+				None => (None, entry),
+			};
+			let Some(factor) = factor.parse::<f64>().ok().filter(|f| *f > 0.0) else {
+				tracing::warn!(entry, "invalid {name} entry");
+				continue;
+			};
+			let scale = MonitorScale::from_factor(factor);
+			match monitor {
+				Some(monitor) => scales.monitors.push((monitor.to_string(), scale)),
+				None => scales.fallback = Some(scale),
+			}
+		}
+		scales
+	}
+
+	/// The configured scale of `monitor`; 1x when nothing applies.
+	pub fn scale_for(&self, monitor: &Monitor) -> MonitorScale {
+		self
+			.monitors
+			.iter()
+			.find(|(key, _)| *key == monitor.name || *key == monitor.connector_id.to_string())
+			.map(|(_, scale)| *scale)
+			.or(self.fallback)
+			.unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monitor(name: &str, connector_id: u32) -> Monitor {
+		Monitor {
+			width: 2880,
+			height: 1800,
+			..Monitor::fixture("mon_1", name, connector_id)
+		}
+	}
+
+	#[test]
+	fn named_monitors_override_the_fallback() {
+		let scales = ConfiguredScales::parse("SHIFT_MONITOR_SCALE", "eDP-1=1.5, 77=2.25,bogus=x, 1.25");
+		assert_eq!(scales.scale_for(&monitor("eDP-1", 40)), MonitorScale(180));
+		assert_eq!(scales.scale_for(&monitor("DP-2", 77)), MonitorScale(270));
+		assert_eq!(scales.scale_for(&monitor("DP-3", 41)), MonitorScale(150));
+		// Out of range factors are clamped.
+		let scales = ConfiguredScales::parse("SHIFT_MONITOR_SCALE", "eDP-1=10");
+		assert_eq!(scales.scale_for(&monitor("eDP-1", 40)), MonitorScale::MAX);
+		assert_eq!(scales.scale_for(&monitor("DP-3", 41)), MonitorScale::ONE);
+	}
+}
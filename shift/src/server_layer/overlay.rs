@@ -0,0 +1,258 @@
+//! Regions claimed by overlay sessions and the input routing they imply.
+//!
+//! Overlays (notification daemons, OSDs, status bars) are composited above the active session
+//! inside the rectangles they declare per monitor. Pointer and touch input landing inside those
+//! rectangles goes to the overlay instead of the active session; keyboard input always stays with
+//! the active session.
+//!
+//! Sessions draw their own cursors, so the server tracks the pointer across the monitor layout
+//! and hands its position here before routing pointer events. A button press starts an implicit
+//! grab: the pointer keeps going to whoever received the press until every button is
+//! released. Touch contacts stick to whoever received their `TouchDown`.
+
+use std::collections::HashMap;
+
+use tab_protocol::{ButtonState, InputEventPayload, OverlayRegion};
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+#[derive(Debug, Default)]
+pub(super) struct Overlays {
+	/// Declared regions, in update order; later entries are stacked on top.
+	regions: Vec<(SessionId, MonitorId, Vec<OverlayRegion>)>,
+	/// Pointer position in pixels on the pointer monitor.
+	pointer: (f64, f64),
+	buttons_held: u32,
+	/// Receiver of the pointer while buttons are held; `None` inside means the active session.
+	pointer_grab: Option<Option<SessionId>>,
+	touch_targets: HashMap<i32, Option<SessionId>>,
+	last_touch_target: Option<SessionId>,
+}
+
+impl Overlays {
+	pub fn set_regions(
+		&mut self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		regions: Vec<OverlayRegion>,
+	) {
+		self
+			.regions
+			.retain(|(session, monitor, _)| (*session, *monitor) != (session_id, monitor_id));
+		if !regions.is_empty() {
+			self.regions.push((session_id, monitor_id, regions));
+		}
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self
+			.regions
+			.retain(|(session, _, _)| *session != session_id);
+		if self.pointer_grab == Some(Some(session_id)) {
+			self.pointer_grab = Some(None);
+		}
+		self
+			.touch_targets
+			.retain(|_, target| *target != Some(session_id));
+		if self.last_touch_target == Some(session_id) {
+			self.last_touch_target = None;
+		}
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self
+			.regions
+			.retain(|(_, monitor, _)| *monitor != monitor_id);
+	}
+
+	/// Pointer position in pixels on the pointer monitor.
+	pub fn pointer(&self) -> (f64, f64) {
+		self.pointer
+	}
+
+	/// The pointer moved to `position`, in pixels on the monitor it is on.
+	pub fn set_pointer(&mut self, position: (f64, f64)) {
+		self.pointer = position;
+	}
+
+	/// Topmost overlay covering `(x, y)` on `monitor_id`.
+	pub fn hit(&self, monitor_id: MonitorId, x: f64, y: f64) -> Option<SessionId> {
+		self
+			.regions
+			.iter()
+			.rev()
+			.filter(|(_, monitor, _)| *monitor == monitor_id)
+			.find(|(_, _, regions)| regions.iter().any(|r| contains(r, x, y)))
+			.map(|(session, _, _)| *session)
+	}
+
+	/// Decides which overlay receives `event`, or `None` for the active session. `monitor` is the
+	/// monitor the pointer is on for pointer events, or the one touchscreens map to for touch
+	/// events, with its size in pixels.
+	pub fn route(
+		&mut self,
+		event: &InputEventPayload,
+		monitor: Option<(MonitorId, (f64, f64))>,
+	) -> Option<SessionId> {
+		let Some((monitor_id, (width, height))) = monitor.filter(|(_, (w, h))| *w > 0.0 && *h > 0.0)
+		else {
+			return None;
+		};
+		match event {
+			InputEventPayload::PointerMotion { .. }
+			| InputEventPayload::PointerMotionAbsolute { .. }
+			| InputEventPayload::PointerAxis { .. } => self.pointer_target(monitor_id),
+			InputEventPayload::PointerButton { state, .. } => {
+				let target = self.pointer_target(monitor_id);
+				match state {
+					ButtonState::Pressed => {
+						if self.buttons_held == 0 {
+							self.pointer_grab = Some(target);
+						}
+						self.buttons_held += 1;
+					}
+					ButtonState::Released => {
+						self.buttons_held = self.buttons_held.saturating_sub(1);
+						if self.buttons_held == 0 {
+							self.pointer_grab = None;
+						}
+					}
+				}
+				target
+			}
+			InputEventPayload::TouchDown { contact, .. } => {
+				let target = self.hit(
+					monitor_id,
+					(contact.x_transformed / 65535.0).clamp(0.0, 1.0) * width,
+					(contact.y_transformed / 65535.0).clamp(0.0, 1.0) * height,
+				);
+				self.touch_targets.insert(contact.id, target);
+				self.last_touch_target = target;
+				target
+			}
+			InputEventPayload::TouchMotion { contact, .. } => {
+				let target = self.touch_targets.get(&contact.id).copied().flatten();
+				self.last_touch_target = target;
+				target
+			}
+			InputEventPayload::TouchUp { contact_id, .. } => {
+				let target = self.touch_targets.remove(contact_id).flatten();
+				self.last_touch_target = target;
+				target
+			}
+			InputEventPayload::TouchFrame { .. } => self.last_touch_target,
+			InputEventPayload::TouchCancel { .. } => {
+				self.touch_targets.clear();
+				self.last_touch_target.take()
+			}
+			_ => None,
+		}
+	}
+
+	fn pointer_target(&self, monitor_id: MonitorId) -> Option<SessionId> {
+		match self.pointer_grab {
+			Some(grab) => grab,
+			None => self.hit(monitor_id, self.pointer.0, self.pointer.1),
+		}
+	}
+}
+
+fn contains(region: &OverlayRegion, x: f64, y: f64) -> bool {
+	let (left, top) = (region.x as f64, region.y as f64);
+	x >= left && y >= top && x < left + region.width as f64 && y < top + region.height as f64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	fn monitor() -> MonitorId {
+		"mon_1".parse().expect("monitor id")
+	}
+
+	/// Motion that brought the pointer to `(x, y)`.
+	fn motion_to(overlays: &mut Overlays, x: f64, y: f64) -> InputEventPayload {
+		overlays.set_pointer((x, y));
+		InputEventPayload::PointerMotion {
+			device: 0,
+			time_usec: 0,
+			x,
+			y,
+			dx: 0.0,
+			dy: 0.0,
+			unaccel_dx: 0.0,
+			unaccel_dy: 0.0,
+		}
+	}
+
+	fn button(state: ButtonState) -> InputEventPayload {
+		InputEventPayload::PointerButton {
+			device: 0,
+			time_usec: 0,
+			button: 272,
+			state,
+		}
+	}
+
+	fn status_bar() -> Overlays {
+		let mut overlays = Overlays::default();
+		overlays.set_regions(
+			session(2),
+			monitor(),
+			vec![OverlayRegion {
+				x: 0,
+				y: 0,
+				width: 1920,
+				height: 32,
+			}],
+		);
+		overlays
+	}
+
+	#[test]
+	fn pointer_inside_a_region_goes_to_the_overlay() {
+		let mut overlays = status_bar();
+		let screen = Some((monitor(), (1920.0, 1080.0)));
+		let motion = motion_to(&mut overlays, 100.0, 10.0);
+		assert_eq!(overlays.route(&motion, screen), Some(session(2)));
+		let motion = motion_to(&mut overlays, 100.0, 110.0);
+		assert_eq!(overlays.route(&motion, screen), None);
+		let key = InputEventPayload::Key {
+			device: 0,
+			time_usec: 0,
+			key: 30,
+			state: tab_protocol::KeyState::Pressed,
+		};
+		let motion = motion_to(&mut overlays, 100.0, 10.0);
+		overlays.route(&motion, screen);
+		assert_eq!(
+			overlays.route(&key, screen),
+			None,
+			"keys stay with the active session"
+		);
+	}
+
+	#[test]
+	fn button_press_grabs_the_pointer_until_release() {
+		let mut overlays = status_bar();
+		let screen = Some((monitor(), (1920.0, 1080.0)));
+		let motion = motion_to(&mut overlays, 100.0, 10.0);
+		overlays.route(&motion, screen);
+		assert_eq!(
+			overlays.route(&button(ButtonState::Pressed), screen),
+			Some(session(2))
+		);
+		let motion = motion_to(&mut overlays, 100.0, 510.0);
+		assert_eq!(overlays.route(&motion, screen), Some(session(2)));
+		assert_eq!(
+			overlays.route(&button(ButtonState::Released), screen),
+			Some(session(2))
+		);
+		let motion = motion_to(&mut overlays, 100.0, 511.0);
+		assert_eq!(overlays.route(&motion, screen), None);
+	}
+}
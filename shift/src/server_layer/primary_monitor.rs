@@ -0,0 +1,60 @@
+//! Choice of the primary monitor: where single-output clients (launchers, lockscreens) should
+//! appear and where server overlays go by default.
+
+use std::collections::HashMap;
+
+use crate::monitor::{Monitor, MonitorId};
+
+/// Keeps `current` while it is connected. Otherwise prefers the monitor matching `configured`
+/// (`SHIFT_PRIMARY_MONITOR`, a monitor name or connector id), then the lowest connector id.
+pub(super) fn pick_primary(
+	monitors: &HashMap<MonitorId, Monitor>,
+	configured: Option<&str>,
+	current: Option<MonitorId>,
+) -> Option<MonitorId> {
+	if let Some(current) = current.filter(|id| monitors.contains_key(id)) {
+		return Some(current);
+	}
+	if let Some(configured) = configured
+		&& let Some(monitor) = monitors
+			.values()
+			.find(|monitor| monitor.name == configured || monitor.connector_id.to_string() == configured)
+	{
+		return Some(monitor.id);
+	}
+	monitors
+		.values()
+		.min_by_key(|monitor| monitor.connector_id)
+		.map(|monitor| monitor.id)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monitor(id: &str, connector_id: u32) -> Monitor {
+		Monitor::fixture(id, &format!("Monitor {connector_id}"), connector_id)
+	}
+
+	#[test]
+	fn prefers_current_then_configured_then_lowest_connector() {
+		let monitors: HashMap<_, _> = [monitor("mon_1", 40), monitor("mon_2", 33)]
+			.into_iter()
+			.map(|monitor| (monitor.id, monitor))
+			.collect();
+		let mon_1: MonitorId = "mon_1".parse().unwrap();
+		let mon_2: MonitorId = "mon_2".parse().unwrap();
+		let gone: MonitorId = "mon_9".parse().unwrap();
+		assert_eq!(pick_primary(&monitors, None, None), Some(mon_2));
+		assert_eq!(
+			pick_primary(&monitors, Some("Monitor 40"), None),
+			Some(mon_1)
+		);
+		assert_eq!(pick_primary(&monitors, Some("40"), Some(gone)), Some(mon_1));
+		assert_eq!(
+			pick_primary(&monitors, Some("40"), Some(mon_2)),
+			Some(mon_2)
+		);
+		assert_eq!(pick_primary(&HashMap::new(), None, None), None);
+	}
+}
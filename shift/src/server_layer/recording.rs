@@ -0,0 +1,218 @@
+//! Hardware-encoded recording of a monitor's composited output.
+//!
+//! Frames are captured with ffmpeg's `kmsgrab` device, which exports the framebuffer currently
+//! scanned out on a CRTC as a dmabuf. That is exactly the buffer the renderer just composited,
+//! so nothing is read back on the CPU: with VA-API the dmabuf is mapped straight into the
+//! encoder, with V4L2 mem2mem it is downloaded once into the encoder's input queue.
+//!
+//! Capture runs at the monitor's refresh rate and ffmpeg enforces the duration and size caps
+//! itself, so a forgotten recording stops on its own.
+
+use std::{
+	path::{Path, PathBuf},
+	process::{Child, Command, Stdio},
+	time::Duration,
+};
+
+use drm::control::{Device as ControlDevice, connector, crtc};
+use thiserror::Error;
+
+use crate::{drm_card::Card, monitor::Monitor};
+
+const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_MAX_SIZE_MB: u64 = 4096;
+/// How long to wait for ffmpeg to finalize the container after being interrupted.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+	#[error("monitor is already being recorded")]
+	AlreadyRecording,
+	#[error("monitor is not being recorded")]
+	NotRecording,
+	#[error("no DRM device drives connector {0}")]
+	CrtcNotFound(u32),
+	#[error("failed to start encoder: {0}")]
+	Spawn(std::io::Error),
+}
+
+/// Hardware encoder used for the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoder {
+	Vaapi,
+	V4l2M2m,
+}
+
+impl Encoder {
+	/// Reads `SHIFT_RECORD_ENCODER` (`vaapi`, the default, or `v4l2m2m`).
+	pub fn from_env() -> Self {
+		match std::env::var("SHIFT_RECORD_ENCODER").as_deref() {
+			Ok("v4l2m2m") => Self::V4l2M2m,
+			Ok("vaapi") | Err(_) => Self::Vaapi,
+			Ok(other) => {
+				tracing::warn!(value = other, "unknown SHIFT_RECORD_ENCODER, using vaapi");
+				Self::Vaapi
+			}
+		}
+	}
+
+	fn filter_and_codec(self) -> [&'static str; 4] {
+		match self {
+			Self::Vaapi => [
+				"-vf",
+				"hwmap=derive_device=vaapi,scale_vaapi=format=nv12",
+				"-c:v",
+				"h264_vaapi",
+			],
+			Self::V4l2M2m => ["-vf", "hwdownload,format=bgr0", "-c:v", "h264_v4l2m2m"],
+		}
+	}
+}
+
+/// Parameters of a new recording; `None` fields take the defaults.
+#[derive(Debug, Default)]
+pub struct RecordingRequest {
+	pub path: Option<PathBuf>,
+	pub max_duration: Option<Duration>,
+	pub max_size_mb: Option<u64>,
+}
+
+pub fn default_recording_path(monitor: &Monitor) -> PathBuf {
+	PathBuf::from(format!(
+		"/tmp/shift-recording-{}-{}.mp4",
+		monitor.id,
+		chrono::Local::now().format("%Y%m%d-%H%M%S")
+	))
+}
+
+/// A running ffmpeg capture of one monitor.
+#[derive(Debug)]
+pub struct Recording {
+	child: Child,
+	path: PathBuf,
+}
+
+impl Recording {
+	pub fn start(
+		monitor: &Monitor,
+		request: RecordingRequest,
+		encoder: Encoder,
+	) -> Result<Self, RecordingError> {
+		let (card, crtc) = find_crtc(monitor)?;
+		let path = request
+			.path
+			.unwrap_or_else(|| default_recording_path(monitor));
+		let max_duration = request.max_duration.unwrap_or(DEFAULT_MAX_DURATION);
+		let max_bytes = request.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024;
+		let framerate = monitor.refresh_rate.max(1);
+		let ffmpeg = std::env::var("SHIFT_FFMPEG").unwrap_or_else(|_| "ffmpeg".into());
+
+		let child = Command::new(ffmpeg)
+			.args(["-hide_banner", "-loglevel", "warning", "-y"])
+			.arg("-device")
+			.arg(&card)
+			.args(["-f", "kmsgrab"])
+			.args(["-crtc_id", &u32::from(crtc).to_string()])
+			.args(["-framerate", &framerate.to_string()])
+			.args(["-i", "-"])
+			.args(encoder.filter_and_codec())
+			.args(["-t", &max_duration.as_secs_f64().to_string()])
+			.args(["-fs", &max_bytes.to_string()])
+			.arg(&path)
+			.stdin(Stdio::null())
+			.spawn()
+			.map_err(RecordingError::Spawn)?;
+		tracing::info!(
+			monitor_id = %monitor.id,
+			card = %card.display(),
+			crtc = u32::from(crtc),
+			path = %path.display(),
+			?encoder,
+			"screen recording started"
+		);
+		Ok(Self { child, path })
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Whether ffmpeg is still capturing; it exits by itself once a safeguard limit is hit.
+	pub fn is_running(&mut self) -> bool {
+		matches!(self.child.try_wait(), Ok(None))
+	}
+
+	/// Interrupts ffmpeg so it finalizes the file, and waits on a blocking thread for it to
+	/// exit, returning the written path.
+	pub async fn stop(self) -> PathBuf {
+		let path = self.path.clone();
+		tokio::task::spawn_blocking(move || self.finish())
+			.await
+			.unwrap_or(path)
+	}
+
+	fn finish(mut self) -> PathBuf {
+		self.interrupt();
+		let deadline = std::time::Instant::now() + STOP_TIMEOUT;
+		loop {
+			match self.child.try_wait() {
+				Ok(Some(_)) => break,
+				Ok(None) if std::time::Instant::now() < deadline => {
+					std::thread::sleep(Duration::from_millis(20));
+				}
+				_ => {
+					tracing::warn!(path = %self.path.display(), "encoder did not exit, killing it");
+					let _ = self.child.kill();
+					let _ = self.child.wait();
+					break;
+				}
+			}
+		}
+		std::mem::take(&mut self.path)
+	}
+
+	fn interrupt(&self) {
+		unsafe {
+			libc::kill(self.child.id() as libc::pid_t, libc::SIGINT);
+		}
+	}
+}
+
+impl Drop for Recording {
+	fn drop(&mut self) {
+		if self.is_running() {
+			self.interrupt();
+		}
+	}
+}
+
+/// Finds the card and CRTC currently driving `monitor`'s connector.
+fn find_crtc(monitor: &Monitor) -> Result<(PathBuf, crtc::Handle), RecordingError> {
+	let not_found = || RecordingError::CrtcNotFound(monitor.connector_id);
+	let handle: connector::Handle =
+		drm::control::from_u32(monitor.connector_id).ok_or_else(not_found)?;
+	for (path, card) in Card::open_all() {
+		let Some(crtc) = card
+			.get_connector(handle, false)
+			.ok()
+			.and_then(|info| info.current_encoder())
+			.and_then(|encoder| card.get_encoder(encoder).ok())
+			.and_then(|encoder| encoder.crtc())
+		else {
+			continue;
+		};
+		// Connector ids are only unique per card; make sure the mode matches the monitor.
+		let matches = card
+			.get_crtc(crtc)
+			.ok()
+			.and_then(|info| info.mode())
+			.is_some_and(|mode| {
+				let (width, height) = mode.size();
+				i32::from(width) == monitor.width && i32::from(height) == monitor.height
+			});
+		if matches {
+			return Ok((path, crtc));
+		}
+	}
+	Err(not_found())
+}
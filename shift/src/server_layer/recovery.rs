@@ -0,0 +1,217 @@
+//! Safe mode: a built-in recovery screen shown when the admin session keeps crashing.
+//!
+//! Shift supervises the admin process it launches. A failed exit (non-zero status or a signal)
+//! restarts it, unless it already failed `SHIFT_RECOVERY_CRASH_LIMIT` times (3 by default, `0`
+//! disables safe mode) within `SHIFT_RECOVERY_WINDOW_SECS` (60 by default). Then Shift stops
+//! restarting it and the renderer draws a keyboard-driven menu instead of the sessions: restart
+//! the admin session, view the most recent log lines, or shut down through
+//! `SHIFT_RECOVERY_SHUTDOWN_CMD` (`systemctl poweroff` by default).
+
+use std::{
+	collections::VecDeque,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::{InputEventPayload, KeyState};
+
+use crate::comms::server2render::RecoveryScreen;
+
+const DEFAULT_CRASH_LIMIT: usize = 3;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_SHUTDOWN_CMD: &str = "systemctl poweroff";
+/// Log lines handed to the renderer; it shows as many as fit, newest at the bottom.
+pub(super) const LOG_LINES: usize = 60;
+
+// Linux input event codes.
+const KEY_ESC: u32 = 1;
+const KEY_ENTER: u32 = 28;
+const KEY_UP: u32 = 103;
+const KEY_DOWN: u32 = 108;
+const KEY_KPENTER: u32 = 96;
+
+#[derive(Debug)]
+pub(super) struct CrashLoop {
+	limit: usize,
+	window: Duration,
+	failures: VecDeque<Instant>,
+}
+
+impl CrashLoop {
+	pub fn from_env() -> Self {
+		let limit = std::env::var("SHIFT_RECOVERY_CRASH_LIMIT")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.unwrap_or(DEFAULT_CRASH_LIMIT);
+		let window = std::env::var("SHIFT_RECOVERY_WINDOW_SECS")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.map(Duration::from_secs)
+			.unwrap_or(DEFAULT_WINDOW);
+		Self::new(limit, window)
+	}
+
+	fn new(limit: usize, window: Duration) -> Self {
+		Self {
+			limit,
+			window,
+			failures: VecDeque::new(),
+		}
+	}
+
+	/// Records a failed exit; `true` once the failures within the window reach the limit.
+	pub fn record_failure(&mut self, now: Instant) -> bool {
+		while self
+			.failures
+			.front()
+			.is_some_and(|failed_at| now.duration_since(*failed_at) > self.window)
+		{
+			self.failures.pop_front();
+		}
+		self.failures.push_back(now);
+		self.limit > 0 && self.failures.len() >= self.limit
+	}
+
+	pub fn reset(&mut self) {
+		self.failures.clear();
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RecoveryAction {
+	Restart,
+	ShowLogs,
+	Shutdown,
+}
+
+const ACTIONS: [(RecoveryAction, &str); 3] = [
+	(RecoveryAction::Restart, "Restart session"),
+	(RecoveryAction::ShowLogs, "View logs"),
+	(RecoveryAction::Shutdown, "Shut down"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MenuInput {
+	/// Not a menu key; nothing changed.
+	Ignored,
+	/// The selection or view changed and the screen must be redrawn.
+	Redraw,
+	Activate(RecoveryAction),
+}
+
+#[derive(Debug)]
+pub(super) struct RecoveryMenu {
+	reason: String,
+	selected: usize,
+	showing_logs: bool,
+}
+
+impl RecoveryMenu {
+	pub fn new(reason: String) -> Self {
+		Self {
+			reason,
+			selected: 0,
+			showing_logs: false,
+		}
+	}
+
+	pub fn show_logs(&mut self, show: bool) {
+		self.showing_logs = show;
+	}
+
+	pub fn handle_input(&mut self, event: &InputEventPayload) -> MenuInput {
+		let InputEventPayload::Key {
+			key,
+			state: KeyState::Pressed,
+			..
+		} = event
+		else {
+			return MenuInput::Ignored;
+		};
+		if self.showing_logs {
+			return match *key {
+				KEY_ESC | KEY_ENTER | KEY_KPENTER => {
+					self.showing_logs = false;
+					MenuInput::Redraw
+				}
+				_ => MenuInput::Ignored,
+			};
+		}
+		match *key {
+			KEY_UP => {
+				self.selected = (self.selected + ACTIONS.len() - 1) % ACTIONS.len();
+				MenuInput::Redraw
+			}
+			KEY_DOWN => {
+				self.selected = (self.selected + 1) % ACTIONS.len();
+				MenuInput::Redraw
+			}
+			KEY_ENTER | KEY_KPENTER => MenuInput::Activate(ACTIONS[self.selected].0),
+			_ => MenuInput::Ignored,
+		}
+	}
+
+	pub fn screen(&self, logs: impl FnOnce() -> Vec<String>) -> RecoveryScreen {
+		RecoveryScreen {
+			title: "Shift recovery".into(),
+			message: self.reason.clone(),
+			items: ACTIONS.iter().map(|(_, label)| label.to_string()).collect(),
+			selected: self.selected,
+			log_lines: self.showing_logs.then(logs),
+		}
+	}
+}
+
+pub(super) fn shutdown_command() -> String {
+	std::env::var("SHIFT_RECOVERY_SHUTDOWN_CMD")
+		.ok()
+		.map(|v| v.trim().to_string())
+		.filter(|v| !v.is_empty())
+		.unwrap_or_else(|| DEFAULT_SHUTDOWN_CMD.into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn press(key: u32) -> InputEventPayload {
+		InputEventPayload::Key {
+			device: 0,
+			time_usec: 0,
+			key,
+			state: KeyState::Pressed,
+		}
+	}
+
+	#[test]
+	fn crash_loop_needs_failures_within_the_window() {
+		let start = Instant::now();
+		let mut crashes = CrashLoop::new(3, Duration::from_secs(60));
+		assert!(!crashes.record_failure(start));
+		assert!(!crashes.record_failure(start + Duration::from_secs(10)));
+		assert!(!crashes.record_failure(start + Duration::from_secs(100)));
+		assert!(!crashes.record_failure(start + Duration::from_secs(110)));
+		assert!(crashes.record_failure(start + Duration::from_secs(120)));
+		let mut disabled = CrashLoop::new(0, Duration::from_secs(60));
+		assert!((0..10).all(|_| !disabled.record_failure(start)));
+	}
+
+	#[test]
+	fn menu_wraps_and_activates_the_selection() {
+		let mut menu = RecoveryMenu::new("crashed".into());
+		assert_eq!(menu.handle_input(&press(KEY_UP)), MenuInput::Redraw);
+		assert_eq!(
+			menu.handle_input(&press(KEY_ENTER)),
+			MenuInput::Activate(RecoveryAction::Shutdown)
+		);
+		menu.handle_input(&press(KEY_DOWN));
+		menu.handle_input(&press(KEY_DOWN));
+		assert_eq!(
+			menu.handle_input(&press(KEY_ENTER)),
+			MenuInput::Activate(RecoveryAction::ShowLogs)
+		);
+		menu.show_logs(true);
+		assert!(menu.screen(|| vec!["line".into()]).log_lines.is_some());
+		assert_eq!(menu.handle_input(&press(KEY_ESC)), MenuInput::Redraw);
+		assert!(menu.screen(Vec::new).log_lines.is_none());
+	}
+}
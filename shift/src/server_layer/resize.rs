@@ -0,0 +1,125 @@
+//! Swapchain renegotiation after a monitor changes geometry in place.
+//!
+//! When a monitor's mode changes, every client receives `monitor_resized` and each session with
+//! buffers linked on that monitor gets `SHIFT_RESIZE_DEADLINE_MS` (2000 by default) to link
+//! buffers of the new size. Until it does, the renderer keeps scaling its last frame. A session
+//! that misses the deadline has its stale buffers dropped and is told with a
+//! `resize_deadline_expired` error.
+
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+const DEFAULT_DEADLINE: Duration = Duration::from_millis(2000);
+
+#[derive(Debug)]
+pub(super) struct PendingResizes {
+	deadline: Duration,
+	pending: HashMap<(SessionId, MonitorId), Instant>,
+}
+
+impl PendingResizes {
+	pub fn from_env() -> Self {
+		let deadline = std::env::var("SHIFT_RESIZE_DEADLINE_MS")
+			.ok()
+			.and_then(|v| v.trim().parse().ok())
+			.map(Duration::from_millis)
+			.unwrap_or(DEFAULT_DEADLINE);
+		Self::new(deadline)
+	}
+
+	fn new(deadline: Duration) -> Self {
+		Self {
+			deadline,
+			pending: HashMap::new(),
+		}
+	}
+
+	/// How long sessions get to relink after a resize.
+	pub fn deadline(&self) -> Duration {
+		self.deadline
+	}
+
+	/// Starts waiting for `sessions` to relink on `monitor_id`. A resize while one is already
+	/// pending restarts its deadline.
+	pub fn start(
+		&mut self,
+		monitor_id: MonitorId,
+		sessions: impl IntoIterator<Item = SessionId>,
+		now: Instant,
+	) {
+		let expires_at = now + self.deadline;
+		for session_id in sessions {
+			self.pending.insert((session_id, monitor_id), expires_at);
+		}
+	}
+
+	/// The session linked new buffers on `monitor_id`.
+	pub fn linked(&mut self, session_id: SessionId, monitor_id: MonitorId) {
+		self.pending.remove(&(session_id, monitor_id));
+	}
+
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self.pending.values().min().copied()
+	}
+
+	/// Removes and returns the sessions whose deadline passed.
+	pub fn take_expired(&mut self, now: Instant) -> Vec<(SessionId, MonitorId)> {
+		let expired: Vec<_> = self
+			.pending
+			.iter()
+			.filter(|(_, expires_at)| **expires_at <= now)
+			.map(|(key, _)| *key)
+			.collect();
+		for key in &expired {
+			self.pending.remove(key);
+		}
+		expired
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self
+			.pending
+			.retain(|(session, _), _| *session != session_id);
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self
+			.pending
+			.retain(|(_, monitor), _| *monitor != monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	#[test]
+	fn sessions_that_relink_never_expire() {
+		let monitor: MonitorId = "mon_1".parse().expect("monitor id");
+		let start = Instant::now();
+		let mut resizes = PendingResizes::new(Duration::from_millis(500));
+		resizes.start(monitor, [session(1), session(2)], start);
+		assert_eq!(
+			resizes.next_deadline(),
+			Some(start + Duration::from_millis(500))
+		);
+		resizes.linked(session(1), monitor);
+		assert!(
+			resizes
+				.take_expired(start + Duration::from_millis(499))
+				.is_empty()
+		);
+		assert_eq!(
+			resizes.take_expired(start + Duration::from_millis(500)),
+			vec![(session(2), monitor)]
+		);
+		assert_eq!(resizes.next_deadline(), None);
+	}
+}
@@ -0,0 +1,222 @@
+//! Privilege reduction for the session processes Shift launches itself.
+//!
+//! Configured per role: `SHIFT_SANDBOX_SESSION` (on by default) and `SHIFT_SANDBOX_ADMIN` (off
+//! by default, the admin shell is trusted) enable the sandbox. A sandboxed process:
+//! - inherits no fds besides stdio,
+//! - runs with `PR_SET_NO_NEW_PRIVS`,
+//! - switches to `SHIFT_SANDBOX_UID`/`SHIFT_SANDBOX_GID` when set, keeping only the
+//!   supplementary groups listed in `SHIFT_SANDBOX_GROUPS` (comma separated gids),
+//! - enters a fresh user namespace when `SHIFT_SANDBOX_USERNS` is set.
+//!
+//! Sessions only need their render node (`/dev/dri/renderD*`) to allocate buffers, so the
+//! configured identity should not be able to open the primary `/dev/dri/card*` nodes. That is
+//! checked when the sandbox is loaded and reported as a warning.
+
+use std::{
+	io,
+	os::unix::{fs::MetadataExt, process::CommandExt},
+	process::Command,
+};
+
+use crate::{input_layer::env_bool, sessions::Role};
+
+/// `CLOSE_RANGE_CLOEXEC` from `linux/close_range.h`.
+const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct SandboxPolicy {
+	pub enabled: bool,
+	pub uid: Option<libc::uid_t>,
+	pub gid: Option<libc::gid_t>,
+	pub groups: Vec<libc::gid_t>,
+	pub user_namespace: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct SandboxConfig {
+	pub admin: SandboxPolicy,
+	pub session: SandboxPolicy,
+}
+
+impl SandboxConfig {
+	pub fn from_env() -> Self {
+		let identity = SandboxPolicy {
+			enabled: false,
+			uid: env_id("SHIFT_SANDBOX_UID"),
+			gid: env_id("SHIFT_SANDBOX_GID"),
+			groups: std::env::var("SHIFT_SANDBOX_GROUPS")
+				.map(|v| parse_groups(&v))
+				.unwrap_or_default(),
+			user_namespace: env_bool("SHIFT_SANDBOX_USERNS", false),
+		};
+		let config = Self {
+			admin: SandboxPolicy {
+				enabled: env_bool("SHIFT_SANDBOX_ADMIN", false),
+				..identity.clone()
+			},
+			session: SandboxPolicy {
+				enabled: env_bool("SHIFT_SANDBOX_SESSION", true),
+				..identity
+			},
+		};
+		for (role, policy) in [
+			(Role::Admin, &config.admin),
+			(Role::Normal, &config.session),
+		] {
+			if policy.enabled && policy.can_open_card_nodes() {
+				tracing::warn!(
+					?role,
+					"sandboxed sessions can still open /dev/dri/card* nodes; set SHIFT_SANDBOX_UID/GID/GROUPS to an identity without access"
+				);
+			}
+		}
+		config
+	}
+
+	pub fn policy(&self, role: Role) -> &SandboxPolicy {
+		match role {
+			Role::Admin => &self.admin,
+			Role::Normal | Role::Overlay => &self.session,
+		}
+	}
+
+	/// Arranges for `cmd` to run under the policy configured for `role`.
+	pub fn apply(&self, cmd: &mut Command, role: Role) {
+		let policy = self.policy(role).clone();
+		if !policy.enabled {
+			return;
+		}
+		// SAFETY: the closure runs between fork and exec and only makes raw syscalls.
+		unsafe {
+			cmd.pre_exec(move || policy.enter());
+		}
+	}
+}
+
+impl SandboxPolicy {
+	/// Runs in the forked child, before exec.
+	fn enter(&self) -> io::Result<()> {
+		// SAFETY: plain syscalls on the child's own process state.
+		unsafe {
+			if libc::syscall(
+				libc::SYS_close_range,
+				3,
+				libc::c_uint::MAX,
+				CLOSE_RANGE_CLOEXEC,
+			) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+			if (self.uid.is_some() || self.gid.is_some())
+				&& libc::setgroups(self.groups.len(), self.groups.as_ptr()) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+			if let Some(gid) = self.gid
+				&& libc::setresgid(gid, gid, gid) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+			if let Some(uid) = self.uid
+				&& libc::setresuid(uid, uid, uid) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+			// Last: ids are unmapped inside the new namespace, so nothing above works after it.
+			if self.user_namespace && libc::unshare(libc::CLONE_NEWUSER) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok(())
+	}
+
+	/// Whether the identity the process ends up with may open a primary DRM node.
+	fn can_open_card_nodes(&self) -> bool {
+		// SAFETY: getuid/getgid cannot fail.
+		let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+		let uid = self.uid.unwrap_or(uid);
+		let gid = self.gid.unwrap_or(gid);
+		if self.user_namespace {
+			// Unmapped ids only get the "other" permission bits.
+			return card_nodes().any(|meta| grants_rw(meta.mode(), false, false));
+		}
+		if uid == 0 {
+			return true;
+		}
+		card_nodes().any(|meta| {
+			grants_rw(
+				meta.mode(),
+				meta.uid() == uid,
+				meta.gid() == gid || self.groups.contains(&meta.gid()),
+			)
+		})
+	}
+}
+
+fn card_nodes() -> impl Iterator<Item = std::fs::Metadata> {
+	std::fs::read_dir("/dev/dri")
+		.into_iter()
+		.flatten()
+		.flatten()
+		.filter(|entry| entry.file_name().to_string_lossy().starts_with("card"))
+		.filter_map(|entry| entry.metadata().ok())
+}
+
+fn grants_rw(mode: u32, owner: bool, group: bool) -> bool {
+	let bits = if owner {
+		mode >> 6
+	} else if group {
+		mode >> 3
+	} else {
+		mode
+	};
+	bits & 0o6 == 0o6
+}
+
+fn env_id(name: &str) -> Option<u32> {
+	let value = std::env::var(name).ok()?;
+	match value.trim().parse() {
+		Ok(id) => Some(id),
+		Err(_) => {
+			tracing::warn!(name, value, "ignoring invalid id");
+			None
+		}
+	}
+}
+
+fn parse_groups(value: &str) -> Vec<libc::gid_t> {
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|g| !g.is_empty())
+		.filter_map(|g| match g.parse() {
+			Ok(gid) => Some(gid),
+			Err(_) => {
+				tracing::warn!(group = g, "ignoring invalid SHIFT_SANDBOX_GROUPS entry");
+				None
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_group_lists() {
+		assert_eq!(parse_groups("44, 105,,x"), vec![44, 105]);
+		assert!(parse_groups("").is_empty());
+	}
+
+	#[test]
+	fn card_node_permissions() {
+		// crw-rw---- root:video
+		assert!(grants_rw(0o660, false, true));
+		assert!(!grants_rw(0o660, false, false));
+		assert!(grants_rw(0o666, false, false));
+	}
+}
@@ -0,0 +1,169 @@
+//! PipeWire screencasts clients start with `screencast_start`.
+//!
+//! The renderer streams a monitor's composed frames to a PipeWire node the client hands to
+//! whatever consumes it, e.g. a video call. A monitor shows the current session, so a normal
+//! session casting a physical monitor would share other sessions' contents once it is switched
+//! away from; its casts are paused (sending black frames) until it is current again. Virtual
+//! monitors only ever show their own session, and admins may see everything.
+
+use std::collections::HashMap;
+
+use crate::{
+	client_layer::client::ClientId,
+	monitor::MonitorId,
+	sessions::{Role, SessionId},
+};
+
+#[derive(Debug, Default)]
+pub(super) struct Screencasts {
+	casts: HashMap<u64, Screencast>,
+	next_id: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Screencast {
+	pub client_id: ClientId,
+	pub session_id: SessionId,
+	pub monitor_id: MonitorId,
+	/// Whether the monitor shows only the session's own frames.
+	pub private: bool,
+	/// Whether the renderer reported the stream's node yet.
+	pub started: bool,
+	pub paused: bool,
+	role: Role,
+}
+
+impl Screencasts {
+	/// Registers a cast and returns the id the renderer knows it by. `private` marks monitors
+	/// that show only `session_id`'s own frames.
+	pub fn start(
+		&mut self,
+		client_id: ClientId,
+		session_id: SessionId,
+		role: Role,
+		monitor_id: MonitorId,
+		private: bool,
+	) -> u64 {
+		self.next_id += 1;
+		self.casts.insert(
+			self.next_id,
+			Screencast {
+				client_id,
+				session_id,
+				monitor_id,
+				private,
+				started: false,
+				paused: false,
+				role,
+			},
+		);
+		self.next_id
+	}
+
+	/// The cast `client_id` runs of `monitor_id`.
+	pub fn find(&self, client_id: ClientId, monitor_id: MonitorId) -> Option<u64> {
+		self
+			.casts
+			.iter()
+			.find(|(_, cast)| cast.client_id == client_id && cast.monitor_id == monitor_id)
+			.map(|(cast_id, _)| *cast_id)
+	}
+
+	pub fn get_mut(&mut self, cast_id: u64) -> Option<&mut Screencast> {
+		self.casts.get_mut(&cast_id)
+	}
+
+	pub fn remove(&mut self, cast_id: u64) -> Option<Screencast> {
+		self.casts.remove(&cast_id)
+	}
+
+	/// Removes and returns the casts matching `filter`.
+	pub fn remove_where(
+		&mut self,
+		mut filter: impl FnMut(&Screencast) -> bool,
+	) -> Vec<(u64, Screencast)> {
+		let ids: Vec<_> = self
+			.casts
+			.iter()
+			.filter(|(_, cast)| filter(cast))
+			.map(|(cast_id, _)| *cast_id)
+			.collect();
+		ids
+			.into_iter()
+			.filter_map(|cast_id| Some((cast_id, self.casts.remove(&cast_id)?)))
+			.collect()
+	}
+
+	/// Casts whose pause state changes now that `current` is the current session.
+	pub fn sync_paused(&mut self, current: Option<SessionId>) -> Vec<(u64, bool)> {
+		let mut changed = Vec::new();
+		for (cast_id, cast) in &mut self.casts {
+			let paused = cast.should_pause(current);
+			if cast.paused != paused {
+				cast.paused = paused;
+				changed.push((*cast_id, paused));
+			}
+		}
+		changed
+	}
+}
+
+impl Screencast {
+	fn should_pause(&self, current: Option<SessionId>) -> bool {
+		self.role == Role::Normal && !self.private && current != Some(self.session_id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn normal_sessions_cast_only_while_current() {
+		let (own, other) = (SessionId::from_raw(1), SessionId::from_raw(2));
+		let client_id = ClientId::rand();
+		let (monitor, virtual_monitor) = (
+			"mon_1".parse().expect("monitor id"),
+			"mon_2".parse().expect("monitor id"),
+		);
+		let mut casts = Screencasts::default();
+		let shared = casts.start(client_id, own, Role::Normal, monitor, false);
+		casts.start(client_id, own, Role::Normal, virtual_monitor, true);
+		casts.start(ClientId::rand(), other, Role::Admin, monitor, false);
+		assert!(casts.sync_paused(Some(own)).is_empty());
+		assert_eq!(casts.sync_paused(Some(other)), vec![(shared, true)]);
+		assert!(casts.sync_paused(None).is_empty(), "already paused");
+		assert_eq!(casts.sync_paused(Some(own)), vec![(shared, false)]);
+		assert_eq!(casts.find(client_id, monitor), Some(shared));
+	}
+
+	#[test]
+	fn casts_are_removed_by_owner_or_monitor() {
+		let client_id = ClientId::rand();
+		let monitor = "mon_1".parse().expect("monitor id");
+		let mut casts = Screencasts::default();
+		let first = casts.start(
+			client_id,
+			SessionId::from_raw(1),
+			Role::Admin,
+			monitor,
+			false,
+		);
+		let second = casts.start(
+			ClientId::rand(),
+			SessionId::from_raw(2),
+			Role::Admin,
+			monitor,
+			false,
+		);
+		assert_ne!(first, second);
+		let removed = casts.remove_where(|cast| cast.client_id == client_id);
+		assert_eq!(removed.len(), 1);
+		assert_eq!(removed[0].0, first);
+		assert_eq!(
+			casts.remove_where(|cast| cast.monitor_id == monitor).len(),
+			1
+		);
+		assert!(casts.remove(second).is_none());
+	}
+}
@@ -1,11 +1,9 @@
 use std::{
 	collections::{HashMap, HashSet},
-	fs::Permissions,
 	future::pending,
 	io,
-	os::unix::fs::PermissionsExt,
-	path::{Path, PathBuf},
-	process::Command,
+	path::PathBuf,
+	process::{Child, Command},
 	sync::Arc,
 	time::Duration,
 };
@@ -20,8 +18,32 @@ use tokio::{
 	task::JoinHandle as TokioJoinHandle,
 	time::Instant,
 };
-use tracing::error;
+use tracing::{Instrument, error};
 
+use super::audio_policy::AudioPolicy;
+use super::background::BackgroundThrottle;
+use super::connector_properties;
+use super::cursor::{self, Cursors};
+use super::flip_groups::FlipGroups;
+use super::handover::Handover;
+use super::idle::{IdleManager, IdleTransition};
+use super::layout::MonitorLayout;
+use super::listen::{ListenSocket, Listener};
+use super::liveness::{Liveness, LivenessEvent};
+use super::magnifier::Magnifier;
+use super::memory_pressure::MemoryPressure;
+use super::monitor_profiles::{self, DisplayAdjustment, MonitorProfiles, MonitorSettings, Profile};
+use super::monitor_scales::ConfiguredScales;
+use super::overlay::Overlays;
+use super::primary_monitor::pick_primary;
+use super::recording::{self, Recording, RecordingError, RecordingRequest};
+use super::recovery::{self, CrashLoop, MenuInput, RecoveryAction, RecoveryMenu};
+use super::resize::PendingResizes;
+use super::sandbox::SandboxConfig;
+use super::screencasts::{Screencast, Screencasts};
+use super::switcher::Switcher;
+use super::virtual_monitors::{VirtualMonitor, VirtualMonitors};
+use super::watermark::WatermarkConfig;
 use crate::auth::error::Error as AuthError;
 use crate::{
 	auth::Token,
@@ -30,17 +52,34 @@ use crate::{
 		client_view::{self, ClientView},
 	},
 	comms::{
+		channel::{self, ChannelSnapshot},
 		client2server::C2SMsg,
 		input2server::{InputEvt, InputEvtRx},
-		render2server::{RenderEvt, RenderEvtRx},
+		render2server::{CapturedFrame, RenderEvt, RenderEvtRx},
 		server2client::BufferRelease,
-		server2render::{RenderCmd, RenderCmdTx, SessionTransition},
+		server2render::{
+			ColorProfileSource, RenderCmd, RenderCmdTx, SessionTransition, SwitcherEntry, SwitcherView,
+			WallpaperSource,
+		},
 	},
-	monitor::{Monitor, MonitorId},
-	rendering_layer::channels::ServerEnd as RenderServerChannels,
-	sessions::{PendingSession, Role, Session, SessionId},
+	frame_trace::{self, FrameTraceHandle},
+	input_layer::env_bool,
+	log_tail::LogTailHandle,
+	monitor::{self, Monitor, MonitorId},
+	rendering_layer::{DisplayProfile, channels::ServerEnd as RenderServerChannels},
+	sessions::{
+		PendingSession, Role, Session, SessionId, metadata,
+		permissions::{self, Permission, SocketRestrictions},
+	},
+};
+use tab_protocol::{
+	ChannelStats, ChannelStatsPayload, ConnectorProperties, ConnectorPropertiesPayload,
+	FrameStatsPayload, InputEventPayload, LatencyMode, MonitorPlacement, MonitorProfileAction,
+	MonitorScale, NightLightSchedule, OutputTransform, PresentationFeedbackPayload,
+	RenderCacheStatsPayload, RenderQualityPayload, ScreenshotReadyPayload, SessionFrameStatsPayload,
+	SessionInfo, SessionLifecycle, SessionMetadataPayload, SessionUpdatePayload,
+	VirtualMonitorCreatePayload, VirtualMonitorCreatedPayload,
 };
-use tab_protocol::{InputEventPayload, SessionInfo, SessionLifecycle, SessionRole};
 
 #[derive(Debug, Clone, Copy)]
 struct PendingFlip {
@@ -65,6 +104,10 @@ enum BufferOwner {
 struct ConnectedClient {
 	client_view: ClientView,
 	join_handle: TokioJoinHandle<()>,
+	/// Process on the other end of the socket, from `SO_PEERCRED`.
+	peer_pid: Option<libc::pid_t>,
+	/// Restrictions of the socket the client connected through.
+	socket_restrictions: Arc<SocketRestrictions>,
 }
 impl Drop for ConnectedClient {
 	fn drop(&mut self) {
@@ -72,7 +115,11 @@ impl Drop for ConnectedClient {
 	}
 }
 pub struct ShiftServer {
-	listener: Option<UnixListener>,
+	/// Taken by the server loop.
+	listeners: Vec<(UnixListener, Arc<SocketRestrictions>)>,
+	/// Addresses passed to launched sessions as `SHIFT_SOCKET`, picking the first socket that
+	/// admits their role.
+	exported_sockets: Vec<(String, Arc<SocketRestrictions>)>,
 	current_session: Option<SessionId>,
 	pending_sessions: HashMap<Token, PendingSession>,
 	active_sessions: HashMap<SessionId, Arc<Session>>,
@@ -84,9 +131,39 @@ pub struct ShiftServer {
 	render_events: RenderEvtRx,
 	input_events: InputEvtRx,
 	monitors: HashMap<MonitorId, Monitor>,
+	/// Connected monitors an admin disabled; hidden from clients until re-enabled.
+	disabled_monitors: HashMap<MonitorId, Monitor>,
+	primary_monitor: Option<MonitorId>,
+	/// `SHIFT_PRIMARY_MONITOR`: monitor name or connector id preferred as primary.
+	configured_primary_monitor: Option<String>,
+	monitor_profiles: MonitorProfiles,
+	/// EDID identity of each connected monitor, read once when it shows up.
+	monitor_identities: HashMap<MonitorId, String>,
+	/// Non-identity display adjustments, saved into monitor profiles.
+	display_adjustments: HashMap<MonitorId, DisplayAdjustment>,
+	/// Monitors that aren't drawn upright, saved into monitor profiles. Kept across device
+	/// losses, as a monitor on its side is unusable without.
+	output_transforms: HashMap<MonitorId, OutputTransform>,
+	/// Scales set with `set_monitor_scale` or by a monitor profile, saved into monitor profiles.
+	/// Kept across device losses like `output_transforms`.
+	monitor_scales: HashMap<MonitorId, MonitorScale>,
+	/// `SHIFT_MONITOR_SCALE`, for monitors without an entry in `monitor_scales`.
+	configured_scales: ConfiguredScales,
+	/// `SHIFT_RENDER_RESOLUTION`: the resolution monitors are composed at, relative to their mode.
+	render_resolutions: ConfiguredScales,
+	/// `SHIFT_FLIP_GROUPS`, and the groups of connected monitors the renderer was last given.
+	flip_groups: FlipGroups,
+	sent_flip_groups: Vec<Vec<MonitorId>>,
+	/// Where monitors sit on the desktop and the pointer with them, and the positions clients
+	/// were last told.
+	layout: MonitorLayout,
+	sent_layout: Vec<MonitorPlacement>,
 	pending_buffer_requests: Vec<PendingBufferRequest>,
 	waiting_flip: Vec<PendingFlip>,
 	front_buffers: HashMap<(SessionId, MonitorId), tab_protocol::BufferIndex>,
+	/// Acked buffers the renderer hasn't shown yet. Each session may queue one per monitor on
+	/// top of the one being shown.
+	queued_buffers: HashMap<(SessionId, MonitorId), tab_protocol::BufferIndex>,
 	buffer_ownership: HashMap<(SessionId, MonitorId, tab_protocol::BufferIndex), BufferOwner>,
 	swap_buffers_received: u64,
 	frame_done_emitted: u64,
@@ -96,6 +173,65 @@ pub struct ShiftServer {
 	debug_second_session_id: Option<SessionId>,
 	debug_auto_switch_interval: Option<Duration>,
 	pending_input_motion: Option<(SessionId, InputEventPayload)>,
+	frame_trace: FrameTraceHandle,
+	forward_frame_stats: bool,
+	/// Channel counters as of the last check, to report only when they grow.
+	channel_stats: [ChannelSnapshot; 3],
+	/// Sends sessions their own frame stats for windows in which they dropped frames.
+	notify_frame_drops: bool,
+	render_device_lost: bool,
+	sandbox: SandboxConfig,
+	background: BackgroundThrottle,
+	handover: Handover,
+	magnifier: Magnifier,
+	switcher: Switcher,
+	recordings: HashMap<MonitorId, Recording>,
+	recording_encoder: recording::Encoder,
+	/// Admin clients waiting for a capture of each monitor, answered by `RenderEvt::Captured`.
+	screenshots: HashMap<MonitorId, Vec<ClientId>>,
+	/// Admin clients waiting for a mode change of each monitor, answered by
+	/// `RenderEvt::MonitorModeChanged`.
+	mode_requests: HashMap<MonitorId, Vec<ClientId>>,
+	/// Offscreen monitors admins created for sessions; kept apart from `monitors`.
+	virtual_monitors: VirtualMonitors,
+	/// PipeWire streams of monitors clients asked for.
+	screencasts: Screencasts,
+	/// Admin client currently driving input remotely, and the monitor it controls.
+	remote_control: Option<(ClientId, MonitorId)>,
+	/// Screencast of the controlled monitor started for the controller along with remote
+	/// control, stopped with it.
+	remote_control_cast: Option<u64>,
+	idle: IdleManager,
+	liveness: Liveness,
+	memory_pressure: MemoryPressure,
+	audio_policy: AudioPolicy,
+	watermark: WatermarkConfig,
+	/// Last watermark text sent to the renderer.
+	watermark_text: Option<Arc<str>>,
+	/// Non-default sampling requested by sessions, replayed to a recovered renderer.
+	render_quality: HashMap<SessionId, RenderQualityPayload>,
+	/// Non-default latency modes requested by sessions, replayed to a recovered renderer.
+	latency_modes: HashMap<SessionId, LatencyMode>,
+	overlays: Overlays,
+	cursors: Cursors,
+	/// Sessions that still have to link buffers for a resized monitor.
+	resizes: PendingResizes,
+	/// Wallpaper an admin set for every monitor, replayed to a recovered renderer.
+	wallpaper: Option<WallpaperSource>,
+	/// Color profile an admin set for every monitor, replayed to a recovered renderer.
+	color_profile: Option<ColorProfileSource>,
+	/// VRR choice an admin made for every monitor, replayed to a recovered renderer.
+	vrr: Option<bool>,
+	/// Night light an admin set for every monitor, replayed to a recovered renderer.
+	color_temperature: Option<(u32, Option<NightLightSchedule>)>,
+	/// Admin client process Shift launched, supervised for crash loops.
+	admin_process: Option<Child>,
+	/// Token of the admin session waiting for that process to authenticate.
+	admin_token: Option<Token>,
+	admin_crashes: CrashLoop,
+	/// Set while the built-in recovery screen replaces the sessions.
+	recovery: Option<RecoveryMenu>,
+	log_tail: LogTailHandle,
 }
 #[derive(Error, Debug)]
 pub enum BindError {
@@ -103,15 +239,28 @@ pub enum BindError {
 	IOError(#[from] std::io::Error),
 }
 impl ShiftServer {
-	#[tracing::instrument(level= "info", skip(path), fields(path = ?path.as_ref().display()))]
+	#[tracing::instrument(level= "info", skip(sockets, frame_trace, log_tail), fields(sockets = sockets.len()))]
 	pub async fn bind(
-		path: impl AsRef<Path>,
+		sockets: Vec<ListenSocket>,
 		render_channels: RenderServerChannels,
 		input_events: InputEvtRx,
+		frame_trace: FrameTraceHandle,
+		log_tail: LogTailHandle,
 	) -> Result<Self, BindError> {
-		std::fs::remove_file(&path).ok();
-		let listener = UnixListener::bind(&path)?;
-		std::fs::set_permissions(&path, Permissions::from_mode(0o7777)).ok();
+		let mut listeners = Vec::with_capacity(sockets.len());
+		let mut exported_sockets = Vec::new();
+		for socket in sockets {
+			let Listener {
+				listener,
+				address,
+				restrictions,
+			} = socket.bind()?;
+			listener.set_nonblocking(true)?;
+			if let Some(address) = address {
+				exported_sockets.push((address, Arc::clone(&restrictions)));
+			}
+			listeners.push((UnixListener::from_std(listener)?, restrictions));
+		}
 		let (render_events, render_commands) = render_channels.into_parts();
 		let debug_second_session_cmd = std::env::var("SHIFT_DEBUG_SECOND_SESSION_CMD")
 			.ok()
@@ -131,7 +280,8 @@ impl ShiftServer {
 				}
 			});
 		Ok(Self {
-			listener: Some(listener),
+			listeners,
+			exported_sockets,
 			current_session: Default::default(),
 			pending_sessions: Default::default(),
 			active_sessions: Default::default(),
@@ -143,9 +293,27 @@ impl ShiftServer {
 			render_events,
 			input_events,
 			monitors: Default::default(),
+			disabled_monitors: Default::default(),
+			primary_monitor: None,
+			configured_primary_monitor: std::env::var("SHIFT_PRIMARY_MONITOR")
+				.ok()
+				.map(|v| v.trim().to_string())
+				.filter(|v| !v.is_empty()),
+			monitor_profiles: MonitorProfiles::from_env(),
+			monitor_identities: Default::default(),
+			display_adjustments: Default::default(),
+			output_transforms: Default::default(),
+			monitor_scales: Default::default(),
+			configured_scales: ConfiguredScales::from_env(),
+			render_resolutions: ConfiguredScales::from_var("SHIFT_RENDER_RESOLUTION"),
+			flip_groups: FlipGroups::from_env(),
+			sent_flip_groups: Vec::new(),
+			layout: MonitorLayout::default(),
+			sent_layout: Vec::new(),
 			pending_buffer_requests: Default::default(),
 			waiting_flip: Default::default(),
 			front_buffers: Default::default(),
+			queued_buffers: Default::default(),
 			buffer_ownership: Default::default(),
 			swap_buffers_received: 0,
 			frame_done_emitted: 0,
@@ -155,6 +323,44 @@ impl ShiftServer {
 			debug_second_session_id: None,
 			debug_auto_switch_interval,
 			pending_input_motion: None,
+			frame_trace,
+			forward_frame_stats: env_bool("SHIFT_FORWARD_FRAME_STATS", false),
+			channel_stats: channel::snapshot(),
+			notify_frame_drops: env_bool("SHIFT_NOTIFY_FRAME_DROPS", false),
+			magnifier: Magnifier::new(env_bool("SHIFT_MAGNIFIER_KEYBINDINGS", true)),
+			switcher: Switcher::new(env_bool("SHIFT_SWITCHER_KEYBINDINGS", true)),
+			recordings: Default::default(),
+			recording_encoder: recording::Encoder::from_env(),
+			screenshots: HashMap::new(),
+			mode_requests: HashMap::new(),
+			virtual_monitors: VirtualMonitors::default(),
+			screencasts: Screencasts::default(),
+			remote_control: None,
+			remote_control_cast: None,
+			idle: IdleManager::from_env(Instant::now()),
+			liveness: Liveness::from_env(),
+			memory_pressure: MemoryPressure::from_env(),
+			audio_policy: AudioPolicy::from_env(),
+			watermark: WatermarkConfig::from_env(),
+			watermark_text: None,
+			render_quality: HashMap::new(),
+			latency_modes: HashMap::new(),
+			overlays: Overlays::default(),
+			cursors: Cursors::default(),
+			resizes: PendingResizes::from_env(),
+			wallpaper: None,
+			color_profile: None,
+			vrr: None,
+			color_temperature: None,
+			admin_process: None,
+			admin_token: None,
+			admin_crashes: CrashLoop::from_env(),
+			recovery: None,
+			log_tail,
+			render_device_lost: false,
+			sandbox: SandboxConfig::from_env(),
+			background: BackgroundThrottle::from_env(),
+			handover: Handover::from_env(),
 		})
 	}
 
@@ -174,6 +380,8 @@ impl ShiftServer {
 		let mut cmd = Command::new(shell);
 		cmd.args(["-c", &cmdline]);
 		cmd.env("SHIFT_SESSION_TOKEN", token.to_string());
+		self.export_socket_address(&mut cmd, Role::Normal);
+		self.sandbox.apply(&mut cmd, Role::Normal);
 		match cmd.spawn() {
 			Ok(child) => {
 				self.debug_second_session_id = Some(session_id);
@@ -235,6 +443,22 @@ impl ShiftServer {
 	}
 
 	async fn notify_session_awake_change(&mut self, session_id: SessionId, awake: bool) {
+		self.liveness.set_watched(session_id, awake, Instant::now());
+		self.sync_audio_policy();
+		if !awake && self.idle.release(session_id, Instant::now()) {
+			tracing::debug!(%session_id, "idle inhibitor released by sleep");
+		}
+		if !awake
+			&& let Err(e) = self
+				.render_commands
+				.send(RenderCmd::SnapshotSession { session_id })
+				.await
+		{
+			tracing::error!("failed to ask renderer to snapshot a sleeping session: {e}");
+		}
+		if !awake && let Err(e) = self.render_commands.send(RenderCmd::PurgeResources).await {
+			tracing::error!("failed to ask renderer to purge resources: {e}");
+		}
 		let target_clients = self
 			.connected_clients
 			.iter()
@@ -242,6 +466,16 @@ impl ShiftServer {
 				(client.client_view.authenticated_session() == Some(session_id)).then_some(*id)
 			})
 			.collect::<Vec<_>>();
+		let target_pids = target_clients
+			.iter()
+			.filter_map(|id| Some((*id, self.connected_clients.get(id)?.peer_pid?)))
+			.collect::<Vec<_>>();
+		// Thaw before telling the client it is awake; throttle only once it heard it sleeps.
+		if awake {
+			for &(client_id, pid) in &target_pids {
+				self.background.set_asleep(pid, client_id, false);
+			}
+		}
 		for id in target_clients {
 			let Some(client) = self.connected_clients.get_mut(&id) else {
 				continue;
@@ -255,6 +489,11 @@ impl ShiftServer {
 				tracing::warn!(%id, %session_id, awake, "failed to notify session awake state");
 			}
 		}
+		if !awake {
+			for (client_id, pid) in target_pids {
+				self.background.set_asleep(pid, client_id, true);
+			}
+		}
 	}
 
 	async fn prune_expired_awake_sessions(&mut self) {
@@ -310,6 +549,28 @@ impl ShiftServer {
 		}
 	}
 
+	/// Sessions shown right now: the active one, every overlay and those with a virtual
+	/// monitor, which is being captured or streamed.
+	fn foreground_sessions(&self) -> Vec<SessionId> {
+		let mut sessions: Vec<_> = self
+			.current_session
+			.into_iter()
+			.chain(
+				self
+					.active_sessions
+					.values()
+					.filter(|session| session.role() == Role::Overlay)
+					.map(|session| session.id()),
+			)
+			.collect();
+		for session_id in self.virtual_monitors.sessions() {
+			if !sessions.contains(&session_id) {
+				sessions.push(session_id);
+			}
+		}
+		sessions
+	}
+
 	async fn keep_session_awake_for(&mut self, session_id: SessionId, duration: Duration) {
 		if duration.is_zero() {
 			return;
@@ -328,15 +589,14 @@ impl ShiftServer {
 		self.awake_sessions.contains(&session_id)
 	}
 
-	fn session_info_from(session: &Session) -> SessionInfo {
+	fn session_info(&self, session: &Session) -> SessionInfo {
 		SessionInfo {
-			id: session.id().to_string(),
-			role: match session.role() {
-				Role::Admin => SessionRole::Admin,
-				Role::Normal => SessionRole::Session,
-			},
+			id: session.id(),
+			role: session.role().into(),
 			display_name: Some(session.display_name().to_string()),
-			state: if session.ready() {
+			state: if self.liveness.is_unresponsive(session.id()) {
+				SessionLifecycle::Unresponsive
+			} else if session.ready() {
 				SessionLifecycle::Occupied
 			} else {
 				SessionLifecycle::Loading
@@ -344,9 +604,8 @@ impl ShiftServer {
 		}
 	}
 
-	async fn notify_admins_session_state(&mut self, session: &Session) {
-		let info = Self::session_info_from(session);
-		let admin_client_ids = self
+	fn admin_client_ids(&self) -> Vec<ClientId> {
+		self
 			.connected_clients
 			.iter()
 			.filter_map(|(id, client)| {
@@ -354,8 +613,123 @@ impl ShiftServer {
 				let session = self.active_sessions.get(&session_id)?;
 				(session.role() == Role::Admin).then_some(*id)
 			})
-			.collect::<Vec<_>>();
-		for id in admin_client_ids {
+			.collect()
+	}
+
+	/// Replies with the metadata of `session_id`, or of the client's own session. Only admins may
+	/// read other sessions' metadata.
+	async fn send_session_metadata(&mut self, client_id: ClientId, session_id: Option<SessionId>) {
+		let Some(client) = self.connected_clients.get_mut(&client_id) else {
+			return;
+		};
+		let Some(own_session) = client.client_view.authenticated_session() else {
+			return;
+		};
+		let target = session_id.unwrap_or(own_session);
+		let own_role = self
+			.active_sessions
+			.get(&own_session)
+			.map(|session| session.role());
+		if target != own_session
+			&& !permissions::check(
+				client_id,
+				own_role.map(|role| (own_session, role)),
+				&client.socket_restrictions,
+				Permission::SessionManagement,
+			) {
+			client
+				.client_view
+				.notify_permission_denied(Permission::SessionManagement)
+				.await;
+			return;
+		}
+		let Some(session) = self.active_sessions.get(&target) else {
+			client
+				.client_view
+				.notify_error("unknown_session".into(), None, false)
+				.await;
+			return;
+		};
+		let payload = SessionMetadataPayload {
+			session_id: target,
+			metadata: session.metadata().clone(),
+		};
+		if !client.client_view.notify_session_metadata(payload).await {
+			tracing::warn!(%client_id, session_id = %target, "failed to send session metadata");
+		}
+	}
+
+	/// Replies with the connector properties of `monitor_id`, or of every monitor. Monitors
+	/// whose connector can't be read are left out.
+	async fn send_connector_properties(
+		&mut self,
+		client_id: ClientId,
+		monitor_id: Option<MonitorId>,
+	) {
+		let mut monitors: Vec<_> = self
+			.monitors
+			.values()
+			.filter(|monitor| monitor_id.is_none_or(|id| id == monitor.id))
+			.filter_map(|monitor| {
+				Some(ConnectorProperties {
+					monitor_id: monitor.id,
+					connector: monitor.name.clone(),
+					properties: connector_properties::read(monitor.connector_id)?,
+				})
+			})
+			.collect();
+		monitors.sort_by(|a, b| a.connector.cmp(&b.connector));
+		let Some(client) = self.connected_clients.get_mut(&client_id) else {
+			return;
+		};
+		if !client
+			.client_view
+			.notify_connector_properties(ConnectorPropertiesPayload { monitors })
+			.await
+		{
+			tracing::warn!(%client_id, "failed to send connector properties");
+		}
+	}
+
+	/// Relabels the client's own session and tells admins, so switchers show the new name.
+	async fn update_session_label(&mut self, client_id: ClientId, update: SessionUpdatePayload) {
+		let Some(session) = self
+			.connected_clients
+			.get(&client_id)
+			.and_then(|client| client.client_view.authenticated_session())
+			.and_then(|session_id| self.active_sessions.get(&session_id))
+			.map(Arc::clone)
+		else {
+			return;
+		};
+		if let Err(e) = metadata::validate_update(&update) {
+			if let Some(client) = self.connected_clients.get_mut(&client_id) {
+				client
+					.client_view
+					.notify_error(
+						"invalid_session_update".into(),
+						Some(Arc::<str>::from(e)),
+						false,
+					)
+					.await;
+			}
+			return;
+		}
+		let updated = Arc::new(session.with_label(update.display_name.as_deref(), update.attributes));
+		self
+			.active_sessions
+			.insert(updated.id(), Arc::clone(&updated));
+		tracing::info!(session_id = %updated.id(), display_name = updated.display_name(), "session relabeled");
+		self.notify_admins_session_state(&updated).await;
+		self.refresh_watermark(false).await;
+		if self.switcher.view().is_some() {
+			self.send_switcher_view().await;
+		}
+	}
+
+	async fn notify_admins_session_state(&mut self, session: &Session) {
+		let info = self.session_info(session);
+		for id in self.admin_client_ids() {
 			let Some(client) = self.connected_clients.get_mut(&id) else {
 				continue;
 			};
@@ -367,6 +741,38 @@ impl ShiftServer {
 
 	#[tracing::instrument(level= "info", skip(self), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
 	pub fn add_initial_session(&mut self) -> Token {
+		match self.spawn_admin_session() {
+			Ok(token) => token,
+			Err((token, e)) => {
+				tracing::error!("failed to start admin session process: {e}");
+				self.recovery = Some(RecoveryMenu::new(format!(
+					"The admin session could not be started: {e}"
+				)));
+				token
+			}
+		}
+	}
+
+	/// Lets a launched client find a socket that admits its role.
+	fn export_socket_address(&self, cmd: &mut Command, role: Role) {
+		match self
+			.exported_sockets
+			.iter()
+			.find(|(_, restrictions)| restrictions.admits(role))
+		{
+			Some((address, _)) => {
+				cmd.env("SHIFT_SOCKET", address);
+			}
+			None => tracing::warn!(?role, "no named socket admits the launched client's role"),
+		}
+		for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+			cmd.env_remove(var);
+		}
+	}
+
+	/// Creates an admin session and launches its client (`ADMIN_LAUNCH_CMD`, else tibs when
+	/// installed). On failure the session is dropped again and its token returned with the error.
+	fn spawn_admin_session(&mut self) -> Result<Token, (Token, io::Error)> {
 		let (token, session) = PendingSession::admin(Some("Admin".into()));
 		let id = session.id();
 		self.pending_sessions.insert(token.clone(), session);
@@ -388,21 +794,123 @@ impl ShiftServer {
 		if let Some(cmd) = admin_command.as_mut() {
 			cmd.env("SHIFT_SESSION_TOKEN", token.to_string());
 			cmd.env("HOME", "/tmp");
-			if let Err(e) = cmd.spawn() {
-				panic!("Failed to start admin session process: {e}");
+			self.export_socket_address(cmd, Role::Admin);
+			self.sandbox.apply(cmd, Role::Admin);
+			match cmd.spawn() {
+				Ok(child) => {
+					tracing::info!(pid = child.id(), "launched admin session process");
+					self.admin_process = Some(child);
+					self.admin_token = Some(token.clone());
+				}
+				Err(e) => {
+					self.pending_sessions.remove(&token);
+					return Err((token, e));
+				}
 			}
 		}
-		tracing::info!(?token, %id, "added initial admin session");
-		token
+		tracing::info!(?token, %id, "added admin session");
+		Ok(token)
+	}
+
+	/// Restarts the admin process after a failed exit, or enters recovery when it keeps failing.
+	async fn poll_admin_process(&mut self) {
+		let Some(child) = self.admin_process.as_mut() else {
+			return;
+		};
+		let status = match child.try_wait() {
+			Ok(Some(status)) => status,
+			Ok(None) => return,
+			Err(e) => {
+				tracing::warn!("failed to poll admin session process: {e}");
+				return;
+			}
+		};
+		self.admin_process = None;
+		if let Some(token) = self.admin_token.take() {
+			self.pending_sessions.remove(&token);
+		}
+		if status.success() {
+			tracing::info!("admin session process exited");
+			return;
+		}
+		tracing::error!(%status, "admin session process failed");
+		if self.admin_crashes.record_failure(Instant::now()) {
+			self
+				.enter_recovery(format!(
+					"The admin session keeps crashing (last exit: {status})."
+				))
+				.await;
+			return;
+		}
+		if let Err((_, e)) = self.spawn_admin_session() {
+			self
+				.enter_recovery(format!("The admin session could not be restarted: {e}"))
+				.await;
+		}
+	}
+
+	async fn enter_recovery(&mut self, reason: String) {
+		tracing::warn!(%reason, "entering recovery mode");
+		self.recovery = Some(RecoveryMenu::new(reason));
+		self.send_recovery_screen().await;
 	}
-	pub async fn start(mut self) {
-		self.add_initial_session();
 
-		let listener = self.listener.take().unwrap();
+	async fn send_recovery_screen(&mut self) {
+		let screen = self
+			.recovery
+			.as_ref()
+			.map(|menu| menu.screen(|| self.log_tail.recent(recovery::LOG_LINES)));
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetRecoveryScreen { screen })
+			.await
+		{
+			tracing::error!("failed to forward recovery screen to renderer: {e}");
+		}
+	}
+
+	async fn handle_recovery_input(&mut self, input: MenuInput) {
+		match input {
+			MenuInput::Ignored => {}
+			MenuInput::Redraw => self.send_recovery_screen().await,
+			MenuInput::Activate(RecoveryAction::ShowLogs) => {
+				if let Some(menu) = self.recovery.as_mut() {
+					menu.show_logs(true);
+				}
+				self.send_recovery_screen().await;
+			}
+			MenuInput::Activate(RecoveryAction::Restart) => {
+				tracing::info!("restarting admin session from recovery");
+				self.recovery = None;
+				self.admin_crashes.reset();
+				self.send_recovery_screen().await;
+				if let Err((_, e)) = self.spawn_admin_session() {
+					self
+						.enter_recovery(format!("The admin session could not be restarted: {e}"))
+						.await;
+				}
+			}
+			MenuInput::Activate(RecoveryAction::Shutdown) => {
+				let cmdline = recovery::shutdown_command();
+				tracing::warn!(cmd = cmdline, "shutting down from recovery");
+				let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
+				if let Err(e) = Command::new(shell).args(["-c", &cmdline]).spawn() {
+					tracing::error!("failed to run SHIFT_RECOVERY_SHUTDOWN_CMD: {e}");
+				}
+			}
+		}
+	}
+	/// Runs the server loop. Call [`Self::add_initial_session`] first.
+	pub async fn start(mut self) {
+		let listeners = std::mem::take(&mut self.listeners);
 		let mut stats_tick = tokio::time::interval(std::time::Duration::from_secs(1));
 		let mut debug_auto_switch_tick = self.debug_auto_switch_interval.map(tokio::time::interval);
 		let mut input_flush_tick = tokio::time::interval(std::time::Duration::from_millis(4));
+		if self.recovery.is_some() {
+			self.send_recovery_screen().await;
+		}
 		loop {
+			let resize_deadline = self.resizes.next_deadline();
 			let span = tracing::trace_span!(
 				"server_loop",
 				connected_clients = self.connected_clients.len(),
@@ -414,9 +922,17 @@ impl ShiftServer {
 			let _span = span.enter();
 			tokio::select! {
 					client_message = Self::read_clients_messages(&mut self.connected_clients) => self.handle_client_message(client_message.0, client_message.1).await,
-					accept_result = listener.accept() => self.handle_accept(accept_result).await,
+					(restrictions, accept_result) = Self::accept_any(&listeners) => self.handle_accept(restrictions, accept_result).await,
 						_ = stats_tick.tick() => {
 								self.prune_expired_awake_sessions().await;
+								self.poll_admin_process().await;
+								if let Some(transition) = self.idle.poll(Instant::now()) {
+									self.apply_idle_transition(transition).await;
+								}
+								for event in self.liveness.poll(Instant::now()) {
+									self.apply_liveness_event(event).await;
+								}
+								self.report_channel_stats().await;
 								if self.swap_buffers_received > 0 || self.frame_done_emitted > 0 {
 									tracing::trace!(
 											swap_buffers_received = self.swap_buffers_received,
@@ -445,6 +961,17 @@ impl ShiftServer {
 					_ = input_flush_tick.tick() => {
 						self.flush_pending_input_motion().await;
 					}
+					_ = self.memory_pressure.wait() => {
+						self.handle_memory_pressure().await;
+					}
+					_ = async {
+						match resize_deadline {
+							Some(deadline) => tokio::time::sleep_until(deadline).await,
+							None => pending::<()>().await,
+						}
+					} => {
+						self.expire_resizes().await;
+					}
 					_ = async {
 						if let Some(tick) = &mut debug_auto_switch_tick {
 							tick.tick().await;
@@ -460,11 +987,39 @@ impl ShiftServer {
 
 	#[tracing::instrument(level= "trace", skip(self), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
 	async fn handle_client_message(&mut self, client_id: ClientId, message: C2SMsg) {
+		if let Some(session_id) = self
+			.connected_clients
+			.get(&client_id)
+			.and_then(|client| client.client_view.authenticated_session())
+			&& self.liveness.seen(session_id, Instant::now())
+			&& let Some(session) = self.active_sessions.get(&session_id).map(Arc::clone)
+		{
+			tracing::info!(%session_id, "session responsive again");
+			self.notify_admins_session_state(&session).await;
+		}
 		match message {
 			C2SMsg::Shutdown => {
 				self.disconnect_client(client_id).await;
 			}
+			C2SMsg::Alive => {}
 			C2SMsg::Auth(token) => {
+				// A token stays valid when used through a socket that doesn't admit its role.
+				if let Some(pending_session) = self.pending_sessions.get(&token)
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.socket_restrictions.admits(pending_session.role())
+				{
+					tracing::warn!(
+						target: "audit",
+						%client_id,
+						role = ?pending_session.role(),
+						"authentication refused by socket restrictions"
+					);
+					client
+						.client_view
+						.notify_auth_error(AuthError::RoleNotAdmitted)
+						.await;
+					return;
+				}
 				let Some(pending_session) = self.pending_sessions.remove(&token) else {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
@@ -495,9 +1050,10 @@ impl ShiftServer {
 					.insert(session.id(), Arc::clone(&session));
 				if session.role() == Role::Normal && !session.ready() {
 					self.loading_sessions.insert(session.id());
-					self
-						.set_awake_sessions(self.current_session.into_iter())
-						.await;
+					self.set_awake_sessions(self.foreground_sessions()).await;
+				}
+				if session.role() == Role::Overlay && !self.render_device_lost && !self.idle.is_idle() {
+					self.set_awake_sessions(self.foreground_sessions()).await;
 				}
 				if session.role() == Role::Admin {
 					self.debug_admin_session_id.get_or_insert(session.id());
@@ -512,6 +1068,18 @@ impl ShiftServer {
 				} else if let Some(client) = self.connected_clients.get_mut(&client_id) {
 					client.client_view.notify_session_sleep(session.id()).await;
 				}
+				if let Some(pid) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.peer_pid)
+				{
+					let asleep = !self.awake_sessions.contains(&session.id());
+					self.background.set_asleep(pid, client_id, asleep);
+				}
+				if session.role() != Role::Admin {
+					let awake = self.awake_sessions.contains(&session.id());
+					self.liveness.track(session.id(), awake, Instant::now());
+				}
 				if let Some(active_session_id) = self.current_session {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
@@ -524,8 +1092,8 @@ impl ShiftServer {
 					let session_infos = self
 						.active_sessions
 						.values()
-						.filter(|s| s.role() == Role::Normal)
-						.map(|s| Self::session_info_from(s))
+						.filter(|s| s.role() != Role::Admin)
+						.map(|s| self.session_info(s))
 						.collect::<Vec<_>>();
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						for info in session_infos {
@@ -533,43 +1101,55 @@ impl ShiftServer {
 						}
 					}
 				}
-				if session.role() == Role::Normal {
+				if let Some((_, monitor_id)) = self.remote_control
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+				{
+					client
+						.client_view
+						.notify_remote_control_state(Some(monitor_id))
+						.await;
+				}
+				if session.role() != Role::Admin {
 					self.notify_admins_session_state(&session).await;
 				}
 			}
 			C2SMsg::CreateSession(req) => {
+				if !self
+					.require_permission(client_id, Permission::SessionManagement)
+					.await
+				{
+					return;
+				}
 				let mut remove_client = false;
 				{
 					let Some(connected_client) = self.connected_clients.get_mut(&client_id) else {
 						tracing::warn!("tried handling message from a non-existing client");
 						return;
 					};
-					let client_session = connected_client
-						.client_view
-						.authenticated_session()
-						.and_then(|s| self.active_sessions.get(&s))
-						.map(Arc::clone);
-					let Some(client_session) = client_session else {
+					let Ok(role) = Role::try_from(req.role) else {
 						connected_client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(
+								"invalid_payload".into(),
+								Some(Arc::<str>::from("unknown session role")),
+								false,
+							)
 							.await;
 						return;
 					};
-					if client_session.role() != Role::Admin {
+					if let Err(e) = metadata::validate(&req.metadata) {
 						connected_client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(
+								"invalid_session_metadata".into(),
+								Some(Arc::<str>::from(e)),
+								false,
+							)
 							.await;
 						return;
 					}
-					let (token, pending_session) = PendingSession::new(
-						req.display_name.map(Arc::from),
-						match req.role {
-							tab_protocol::SessionRole::Admin => Role::Admin,
-							tab_protocol::SessionRole::Session => Role::Normal,
-						},
-					);
+					let (token, pending_session) = PendingSession::new(req.display_name.map(Arc::from), role);
+					let pending_session = pending_session.with_metadata(req.metadata);
 					self
 						.pending_sessions
 						.insert(token.clone(), pending_session.clone());
@@ -587,56 +1167,39 @@ impl ShiftServer {
 				}
 			}
 			C2SMsg::SwitchSession(payload) => {
-				let target_session = match payload.session_id.parse::<SessionId>() {
-					Ok(session_id) => session_id,
-					Err(e) => {
-						if let Some(client) = self.connected_clients.get_mut(&client_id) {
-							client
-								.client_view
-								.notify_error(
-									"invalid_session_id".into(),
-									Some(Arc::<str>::from(e.to_string())),
-									false,
-								)
-								.await;
-						}
-						return;
-					}
-				};
-				let Some(connected_client) = self.connected_clients.get(&client_id) else {
-					tracing::warn!("tried handling message from a non-existing client");
-					return;
-				};
-				let requester_session = connected_client
-					.client_view
-					.authenticated_session()
-					.and_then(|s| self.active_sessions.get(&s))
-					.map(Arc::clone);
-				let Some(requester_session) = requester_session else {
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_error("forbidden".into(), None, false)
-							.await;
-					}
+				if !self
+					.require_permission(client_id, Permission::SessionManagement)
+					.await
+				{
 					return;
-				};
-				if requester_session.role() != Role::Admin {
+				}
+				let target_session = payload.session_id;
+				if !self.active_sessions.contains_key(&target_session) {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(
+								"unknown_session".into(),
+								Some(Arc::<str>::from("target session is not active")),
+								false,
+							)
 							.await;
 					}
 					return;
 				}
-				if !self.active_sessions.contains_key(&target_session) {
+				if self
+					.active_sessions
+					.get(&target_session)
+					.is_some_and(|target| target.role() == Role::Overlay)
+				{
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
 							.notify_error(
-								"unknown_session".into(),
-								Some(Arc::<str>::from("target session is not active")),
+								"invalid_transition".into(),
+								Some(Arc::<str>::from(
+									"overlay sessions are shown on top and cannot become active",
+								)),
 								false,
 							)
 							.await;
@@ -696,7 +1259,7 @@ impl ShiftServer {
 					}
 					return;
 				};
-				if payload.session_id != requester_session_id.to_string() {
+				if payload.session_id != requester_session_id {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
@@ -720,14 +1283,14 @@ impl ShiftServer {
 					}
 					return;
 				};
-				if existing.role() == Role::Admin {
+				if existing.role() != Role::Normal {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
 							.notify_error(
 								"invalid_transition".into(),
 								Some(Arc::<str>::from(
-									"admin session does not use loading/ready lifecycle",
+									"only normal sessions use the loading/ready lifecycle",
 								)),
 								false,
 							)
@@ -745,14 +1308,14 @@ impl ShiftServer {
 					.insert(requester_session_id, Arc::clone(&ready_session));
 				self.loading_sessions.remove(&requester_session_id);
 				self.notify_admins_session_state(&ready_session).await;
-				self
-					.set_awake_sessions(self.current_session.into_iter())
-					.await;
+				self.set_awake_sessions(self.foreground_sessions()).await;
 			}
 			C2SMsg::BufferRequest {
 				monitor_id,
 				buffer,
 				acquire_fence,
+				present_at,
+				frame_id,
 			} => {
 				let Some(connected_client) = self.connected_clients.get(&client_id) else {
 					tracing::warn!("tried handling message from a non-existing client");
@@ -772,6 +1335,12 @@ impl ShiftServer {
 					}
 					return;
 				};
+				if self
+					.reject_foreign_virtual_monitor(client_id, client_session.id(), monitor_id)
+					.await
+				{
+					return;
+				}
 				if !self.is_session_awake(client_session.id()).await {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
@@ -823,21 +1392,35 @@ impl ShiftServer {
 					}
 					return;
 				}
-				if self.pending_buffer_requests.iter().any(|pending| {
+				let inflight = if self.pending_buffer_requests.iter().any(|pending| {
 					pending.session_id == client_session.id() && pending.monitor_id == monitor_id
 				}) {
+					Some("monitor already has an in-flight buffer request")
+				} else if self
+					.queued_buffers
+					.contains_key(&(client_session.id(), monitor_id))
+				{
+					Some("monitor already has a buffer waiting to be shown")
+				} else {
+					None
+				};
+				if let Some(detail) = inflight {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error(
-								"buffer_request_inflight".into(),
-								Some("monitor already has an in-flight buffer request".into()),
-								false,
-							)
+							.notify_error("buffer_request_inflight".into(), Some(detail.into()), false)
 							.await;
 					}
 					return;
 				}
+				// The renderer handles the swap inside this span.
+				let span = tracing::debug_span!(
+					"swap_buffers",
+					%frame_id,
+					client.id = %client_id,
+					session.id = %client_session.id(),
+					%monitor_id,
+				);
 				if let Err(e) = self
 					.render_commands
 					.send(RenderCmd::SwapBuffers {
@@ -845,7 +1428,10 @@ impl ShiftServer {
 						buffer,
 						session_id: client_session.id(),
 						acquire_fence,
+						present_at,
+						frame_id,
 					})
+					.instrument(span)
 					.await
 				{
 					tracing::error!("failed to forward SwapBuffers to renderer: {e}");
@@ -864,7 +1450,7 @@ impl ShiftServer {
 				}
 			}
 			C2SMsg::FramebufferLink { payload, dma_bufs } => {
-				let monitor_id_raw = payload.monitor_id.clone();
+				let monitor_id = payload.monitor_id;
 				let session_id = {
 					let Some(client) = self.connected_clients.get_mut(&client_id) else {
 						tracing::warn!("tried handling message from a non-existing client");
@@ -879,6 +1465,12 @@ impl ShiftServer {
 					};
 					session_id
 				};
+				if self
+					.reject_foreign_virtual_monitor(client_id, session_id, monitor_id)
+					.await
+				{
+					return;
+				}
 				if let Err(e) = self
 					.render_commands
 					.send(RenderCmd::FramebufferLink {
@@ -895,9 +1487,6 @@ impl ShiftServer {
 						client.client_view.notify_error(code, detail, true).await;
 					}
 				} else {
-					let Ok(monitor_id) = monitor_id_raw.parse::<MonitorId>() else {
-						return;
-					};
 					self.waiting_flip.retain(|pending| {
 						!(pending.session_id == session_id && pending.monitor_id == monitor_id)
 					});
@@ -905,6 +1494,8 @@ impl ShiftServer {
 						!(pending.session_id == session_id && pending.monitor_id == monitor_id)
 					});
 					self.front_buffers.remove(&(session_id, monitor_id));
+					self.queued_buffers.remove(&(session_id, monitor_id));
+					self.resizes.linked(session_id, monitor_id);
 					self.buffer_ownership.insert(
 						(session_id, monitor_id, tab_protocol::BufferIndex::Zero),
 						BufferOwner::Client,
@@ -915,157 +1506,2356 @@ impl ShiftServer {
 					);
 				}
 			}
-		}
-	}
-	async fn handle_render_event(&mut self, event: RenderEvt) {
-		match event {
-			RenderEvt::Started { monitors } => {
-				self.monitors = monitors.into_iter().map(|m| (m.id, m)).collect();
-			}
-			RenderEvt::MonitorOnline { monitor } => {
-				tracing::info!(?monitor, "renderer reports monitor online");
-				self.broadcast_monitor_added(&monitor).await;
-				self.monitors.insert(monitor.id, monitor);
-			}
-			RenderEvt::MonitorOffline { monitor_id } => {
-				tracing::info!(%monitor_id, "renderer reports monitor offline");
-				if let Some(monitor) = self.monitors.remove(&monitor_id) {
-					self.broadcast_monitor_removed(&monitor).await;
+			C2SMsg::ColorFilter(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Accessibility)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				if let Err(e) = self
+					.render_commands
+					.send(RenderCmd::SetColorFilter {
+						monitor_id,
+						mode: payload.mode,
+					})
+					.await
+				{
+					tracing::error!("failed to forward color filter to renderer: {e}");
 				}
-				self
-					.waiting_flip
-					.retain(|pending| pending.monitor_id != monitor_id);
-				self
-					.pending_buffer_requests
-					.retain(|pending| pending.monitor_id != monitor_id);
-				self.front_buffers.retain(|(_, mon), _| *mon != monitor_id);
-				self
-					.buffer_ownership
-					.retain(|(_, mon, _), _| *mon != monitor_id);
 			}
-			RenderEvt::BufferRequestAck {
-				session_id,
-				monitor_id,
-				buffer,
-			} => {
-				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
-					pending.session_id == session_id
-						&& pending.monitor_id == monitor_id
-						&& pending.buffer == buffer
-				}) else {
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "renderer acked unknown pending request");
+			C2SMsg::Magnifier(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Accessibility)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
 					return;
 				};
-				let pending = self.pending_buffer_requests.remove(pos);
-				self
-					.buffer_ownership
-					.insert((session_id, monitor_id, buffer), BufferOwner::Shift);
-				self.swap_buffers_received = self.swap_buffers_received.saturating_add(1);
-
-				let mut should_disconnect = false;
-				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
-					if !client
+				self.magnifier.set(monitor_id, payload.factor);
+				self.send_magnifier_state().await;
+			}
+			C2SMsg::DisplayAdjust(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				let adjustment = DisplayAdjustment {
+					brightness: payload.brightness,
+					contrast: payload.contrast,
+					gamma: payload.gamma,
+				};
+				self.send_display_adjustment(monitor_id, adjustment).await;
+			}
+			C2SMsg::ScreenRecord(payload) => {
+				if !self
+					.require_permission(client_id, Permission::ScreenCapture)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				// Recordings that hit their duration or size cap have already finished.
+				if self
+					.recordings
+					.get_mut(&monitor_id)
+					.is_some_and(|recording| !recording.is_running())
+					&& let Some(finished) = self.recordings.remove(&monitor_id)
+				{
+					tracing::info!(path = %finished.path().display(), "screen recording finished");
+				}
+				let result = if payload.enabled {
+					if self.recordings.contains_key(&monitor_id) {
+						Err(RecordingError::AlreadyRecording)
+					} else {
+						let request = RecordingRequest {
+							path: payload.path.map(PathBuf::from),
+							max_duration: payload.max_duration_secs.map(Duration::from_secs),
+							max_size_mb: payload.max_size_mb,
+						};
+						Recording::start(&self.monitors[&monitor_id], request, self.recording_encoder).map(
+							|recording| {
+								self.recordings.insert(monitor_id, recording);
+							},
+						)
+					}
+				} else {
+					self
+						.recordings
+						.remove(&monitor_id)
+						.ok_or(RecordingError::NotRecording)
+						.map(|recording| {
+							// ffmpeg may take seconds to finalize the file; don't hold up the loop.
+							tokio::spawn(async move {
+								let path = recording.stop().await;
+								tracing::info!(path = %path.display(), "screen recording written");
+							});
+						})
+				};
+				if let Err(e) = result
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+				{
+					client
 						.client_view
-						.notify_buffer_request_ack(monitor_id, buffer)
+						.notify_error(
+							"recording_failed".into(),
+							Some(Arc::<str>::from(e.to_string())),
+							false,
+						)
+						.await;
+				}
+			}
+			C2SMsg::Screenshot(payload) => {
+				if !self
+					.require_permission(client_id, Permission::ScreenCapture)
+					.await
+				{
+					return;
+				}
+				let monitor_id = payload.monitor_id;
+				if !self.virtual_monitors.contains(monitor_id)
+					&& self
+						.resolve_optional_monitor(client_id, Some(monitor_id))
 						.await
-					{
-						should_disconnect = true;
+						.is_none()
+				{
+					return;
+				}
+				// Requests for a monitor already being captured share its frame.
+				let waiting = self.screenshots.entry(monitor_id).or_default();
+				if !waiting.contains(&client_id) {
+					waiting.push(client_id);
+				}
+				if waiting.len() == 1
+					&& let Err(e) = self
+						.render_commands
+						.send(RenderCmd::Capture { monitor_id })
+						.await
+				{
+					tracing::error!("failed to forward screenshot to renderer: {e}");
+				}
+			}
+			C2SMsg::VirtualMonitorCreate(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				self.create_virtual_monitor(client_id, payload).await;
+			}
+			C2SMsg::VirtualMonitorRemove(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(virtual_monitor) = self.virtual_monitors.remove(payload.monitor_id) else {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"unknown_monitor".into(),
+								Some(Arc::<str>::from(payload.monitor_id.to_string())),
+								false,
+							)
+							.await;
 					}
+					return;
+				};
+				self.remove_virtual_monitor(virtual_monitor).await;
+			}
+			C2SMsg::ScreencastStart(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Screencast)
+					.await
+				{
+					return;
 				}
-				if should_disconnect {
-					self.disconnect_client(pending.client_id).await;
+				self.start_screencast(client_id, payload.monitor_id).await;
+			}
+			C2SMsg::ScreencastStop(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Screencast)
+					.await
+				{
+					return;
+				}
+				let monitor_id = payload.monitor_id;
+				let Some(cast_id) = self.screencasts.find(client_id, monitor_id) else {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"screencast_inactive".into(),
+								Some(Arc::<str>::from(monitor_id.to_string())),
+								false,
+							)
+							.await;
+					}
+					return;
+				};
+				if let Some(cast) = self.screencasts.remove(cast_id) {
+					self.stop_screencast(cast_id, cast, Some("requested")).await;
 				}
 			}
-			RenderEvt::BufferRequestRejected {
-				session_id,
-				monitor_id,
-				buffer,
-				reason,
-			} => {
-				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
-					pending.session_id == session_id
-						&& pending.monitor_id == monitor_id
-						&& pending.buffer == buffer
-				}) else {
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, %reason, "renderer rejected unknown pending request");
+			C2SMsg::RemoteControl(payload) => {
+				if !self
+					.require_permission(client_id, Permission::InputInjection)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
 					return;
 				};
-				let pending = self.pending_buffer_requests.remove(pos);
-				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
+				let holder = self.remote_control.map(|(holder, _)| holder);
+				let error = match (payload.enabled, holder) {
+					(true, Some(holder)) if holder != client_id => Some("remote_control_busy"),
+					(true, _) => {
+						self.set_remote_control(Some((client_id, monitor_id))).await;
+						None
+					}
+					(false, Some(holder)) if holder == client_id => {
+						self.set_remote_control(None).await;
+						None
+					}
+					(false, _) => Some("remote_control_inactive"),
+				};
+				if let Some(code) = error
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+				{
 					client
 						.client_view
-						.notify_error("buffer_request_rejected".into(), Some(reason), false)
+						.notify_error(code.into(), None, false)
 						.await;
 				}
 			}
-			RenderEvt::BufferConsumed {
-				session_id,
-				monitor_id,
-				buffer,
-				release_fence,
-			} => {
-				self
-					.buffer_ownership
-					.insert((session_id, monitor_id, buffer), BufferOwner::Client);
-				let Some((_id, client)) = self
-					.connected_clients
-					.iter_mut()
-					.find(|(_, c)| c.client_view.authenticated_session() == Some(session_id))
-				else {
-					return;
-				};
-				if !client
-					.client_view
-					.notify_buffer_release(vec![BufferRelease {
-						monitor_id,
-						buffer,
-						release_fence,
-					}])
-					.await
-				{
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "failed to send early buffer_release");
-				} else {
-					self.frame_done_emitted = self.frame_done_emitted.saturating_add(1);
+			C2SMsg::InputInject(mut event) => {
+				if !self
+					.require_permission(client_id, Permission::InputInjection)
+					.await
+				{
+					return;
+				}
+				if self.remote_control.map(|(holder, _)| holder) != Some(client_id) {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error("remote_control_inactive".into(), None, false)
+							.await;
+					}
+					return;
+				}
+				if let Some((_, monitor_id)) = self.remote_control {
+					self.place_injected_input(monitor_id, &mut event);
+				}
+				self.route_input_event(event).await;
+			}
+			C2SMsg::MonitorEnable(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let monitor_id = payload.monitor_id;
+				if !self.monitors.contains_key(&monitor_id)
+					&& !self.disabled_monitors.contains_key(&monitor_id)
+				{
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"unknown_monitor".into(),
+								Some(Arc::<str>::from(monitor_id.to_string())),
+								false,
+							)
+							.await;
+					}
+					return;
+				}
+				if payload.enabled {
+					self.enable_monitor(monitor_id).await;
+				} else {
+					self.disable_monitor(monitor_id).await;
+				}
+			}
+			C2SMsg::GetSessionMetadata(payload) => {
+				self
+					.send_session_metadata(client_id, payload.session_id)
+					.await;
+			}
+			C2SMsg::GetConnectorProperties(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Diagnostics)
+					.await
+				{
+					return;
+				}
+				let Some(target) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				self.send_connector_properties(client_id, target).await;
+			}
+			C2SMsg::SessionUpdate(payload) => {
+				if !self
+					.require_permission(client_id, Permission::OwnMetadata)
+					.await
+				{
+					return;
+				}
+				self.update_session_label(client_id, payload).await;
+			}
+			C2SMsg::AudioClients(payload) => {
+				if !self
+					.require_permission(client_id, Permission::OwnMetadata)
+					.await
+				{
+					return;
+				}
+				let Some((session_id, peer_pid)) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| Some((client.client_view.authenticated_session()?, client.peer_pid)))
+				else {
+					return;
+				};
+				if let Err(e) = self
+					.audio_policy
+					.link(session_id, peer_pid, payload.client_ids)
+				{
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"invalid_audio_clients".into(),
+								Some(Arc::<str>::from(e)),
+								false,
+							)
+							.await;
+					}
+					return;
+				}
+				self.sync_audio_policy();
+			}
+			C2SMsg::OverlayRegions(payload) => {
+				if !self
+					.require_permission(client_id, Permission::OverlayRegions)
+					.await
+				{
+					return;
+				}
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				self
+					.overlays
+					.set_regions(session_id, monitor_id, payload.regions.clone());
+				if let Err(e) = self
+					.render_commands
+					.send(RenderCmd::SetOverlayRegions {
+						session_id,
+						monitor_id,
+						regions: payload.regions,
+					})
+					.await
+				{
+					tracing::error!("failed to forward overlay regions to renderer: {e}");
+				}
+			}
+			C2SMsg::MonitorProfile(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				self.handle_monitor_profile(client_id, payload.action).await;
+			}
+			C2SMsg::SetWallpaper(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				self
+					.handle_set_wallpaper(client_id, monitor_id, payload.wallpaper)
+					.await;
+			}
+			C2SMsg::SetColorProfile(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				self
+					.handle_set_color_profile(client_id, monitor_id, payload.profile)
+					.await;
+			}
+			C2SMsg::SetVrr(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				if monitor_id.is_none() {
+					self.vrr = Some(payload.enabled);
+				}
+				self.send_vrr(monitor_id, payload.enabled).await;
+			}
+			C2SMsg::SetColorTemperature(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(monitor_id) = self
+					.resolve_optional_monitor(client_id, payload.monitor_id)
+					.await
+				else {
+					return;
+				};
+				if monitor_id.is_none() {
+					self.color_temperature = Some((payload.kelvin, payload.schedule));
+				}
+				self
+					.send_color_temperature(monitor_id, payload.kelvin, payload.schedule)
+					.await;
+			}
+			C2SMsg::SetOutputTransform(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				self
+					.send_output_transform(monitor_id, payload.transform)
+					.await;
+			}
+			C2SMsg::SetMonitorScale(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				self
+					.send_monitor_scale(monitor_id, Some(payload.scale.clamped()))
+					.await;
+			}
+			C2SMsg::SetMonitorMode(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				let requested = (payload.width, payload.height, payload.refresh_rate);
+				let offered = self.monitors.get(&monitor_id).is_some_and(|monitor| {
+					monitor
+						.modes
+						.iter()
+						.any(|mode| (mode.width, mode.height, mode.refresh_rate) == requested)
+				});
+				if !offered {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"invalid_mode".into(),
+								Some(Arc::<str>::from(format!(
+									"{}x{}@{}",
+									payload.width, payload.height, payload.refresh_rate
+								))),
+								false,
+							)
+							.await;
+					}
+					return;
+				}
+				let waiting = self.mode_requests.entry(monitor_id).or_default();
+				if !waiting.contains(&client_id) {
+					waiting.push(client_id);
+				}
+				if let Err(e) = self
+					.render_commands
+					.send(RenderCmd::SetMode {
+						monitor_id,
+						width: payload.width,
+						height: payload.height,
+						refresh: payload.refresh_rate,
+					})
+					.await
+				{
+					tracing::error!("failed to forward monitor mode to renderer: {e}");
+				}
+			}
+			C2SMsg::SetMonitorPosition(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				tracing::info!(%monitor_id, position = ?payload.position, "monitor position set");
+				self.layout.set_position(monitor_id, payload.position);
+				self.sync_layout().await;
+			}
+			C2SMsg::SetPrimaryMonitor(payload) => {
+				if !self
+					.require_permission(client_id, Permission::DisplayConfiguration)
+					.await
+				{
+					return;
+				}
+				let Some(Some(monitor_id)) = self
+					.resolve_optional_monitor(client_id, Some(payload.monitor_id))
+					.await
+				else {
+					return;
+				};
+				if self.update_primary_monitor(Some(monitor_id)) {
+					self.broadcast_primary_monitor().await;
+				}
+			}
+			C2SMsg::IdleInhibit(payload) => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				if payload.inhibit && self.current_session != Some(session_id) {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								"idle_inhibit_rejected".into(),
+								Some(Arc::<str>::from("only the active session may inhibit idle")),
+								false,
+							)
+							.await;
+					}
+					return;
+				}
+				if self
+					.idle
+					.set_inhibit(session_id, payload.inhibit, Instant::now())
+				{
+					tracing::info!(%session_id, inhibit = payload.inhibit, "idle inhibitor changed");
+				}
+			}
+			C2SMsg::RenderQuality(quality) => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				if quality == RenderQualityPayload::default() {
+					self.render_quality.remove(&session_id);
+				} else {
+					self.render_quality.insert(session_id, quality);
+				}
+				self.send_render_quality(session_id, quality).await;
+			}
+			C2SMsg::SetCursor { payload, pixels } => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				let image = match (payload.image, pixels) {
+					(Some(info), Some(pixels)) => match cursor::read_image(&info, pixels) {
+						Ok(image) => Some(image),
+						Err(e) => {
+							if let Some(client) = self.connected_clients.get_mut(&client_id) {
+								client
+									.client_view
+									.notify_error(
+										"invalid_cursor".into(),
+										Some(Arc::<str>::from(format!(
+											"failed to read cursor pixels: {e}"
+										))),
+										false,
+									)
+									.await;
+							}
+							return;
+						}
+					},
+					_ => None,
+				};
+				self.cursors.set(session_id, image);
+				if self.current_session == Some(session_id) {
+					self.send_cursor().await;
+				}
+			}
+			C2SMsg::LatencyMode(payload) => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				if payload.mode == LatencyMode::default() {
+					self.latency_modes.remove(&session_id);
+				} else {
+					self.latency_modes.insert(session_id, payload.mode);
+				}
+				self.send_latency_mode(session_id, payload.mode).await;
+			}
+			C2SMsg::FrameTrace(payload) => {
+				if !self
+					.require_permission(client_id, Permission::Diagnostics)
+					.await
+				{
+					return;
+				}
+				let result = if payload.enabled {
+					let path = payload
+						.path
+						.map(PathBuf::from)
+						.unwrap_or_else(frame_trace::default_trace_path);
+					self.frame_trace.start(&path).map(|()| {
+						tracing::info!(path = %path.display(), "frame trace capture started");
+					})
+				} else {
+					self.frame_trace.stop().await.map(|path| {
+						tracing::info!(path = %path.display(), "frame trace written");
+					})
+				};
+				if let Err(e) = result
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+				{
+					client
+						.client_view
+						.notify_error(
+							"frame_trace_failed".into(),
+							Some(Arc::<str>::from(e.to_string())),
+							false,
+						)
+						.await;
+				}
+			}
+		}
+	}
+	/// Returns whether `client_id` is authenticated as an admin, notifying it with `forbidden`
+	/// otherwise.
+	/// Checks `permission` against the client's session role, telling the client when it is
+	/// denied.
+	async fn require_permission(&mut self, client_id: ClientId, permission: Permission) -> bool {
+		let granted = self.has_permission(client_id, permission);
+		if !granted && let Some(client) = self.connected_clients.get_mut(&client_id) {
+			client
+				.client_view
+				.notify_permission_denied(permission)
+				.await;
+		}
+		granted
+	}
+	/// Whether `client_id` may use `permission`, without telling it.
+	fn has_permission(&self, client_id: ClientId, permission: Permission) -> bool {
+		let Some(client) = self.connected_clients.get(&client_id) else {
+			return false;
+		};
+		let session = client
+			.client_view
+			.authenticated_session()
+			.and_then(|s| self.active_sessions.get(&s))
+			.map(|session| (session.id(), session.role()));
+		permissions::check(client_id, session, &client.socket_restrictions, permission)
+	}
+	/// Checks an optional monitor id argument of an admin command. Returns `None` after notifying
+	/// the client when the monitor is not connected.
+	async fn resolve_optional_monitor(
+		&mut self,
+		client_id: ClientId,
+		monitor_id: Option<MonitorId>,
+	) -> Option<Option<MonitorId>> {
+		let Some(monitor_id) = monitor_id else {
+			return Some(None);
+		};
+		if self.monitors.contains_key(&monitor_id) {
+			return Some(Some(monitor_id));
+		}
+		if let Some(client) = self.connected_clients.get_mut(&client_id) {
+			client
+				.client_view
+				.notify_error(
+					"unknown_monitor".into(),
+					Some(Arc::<str>::from(monitor_id.to_string())),
+					false,
+				)
+				.await;
+		}
+		None
+	}
+
+	/// Blanks the outputs and puts sessions to sleep when going idle, and undoes both on
+	/// activity.
+	async fn apply_liveness_event(&mut self, event: LivenessEvent) {
+		match event {
+			LivenessEvent::Unresponsive(session_id) => {
+				let Some(session) = self.active_sessions.get(&session_id).map(Arc::clone) else {
+					self.liveness.forget(session_id);
+					return;
+				};
+				tracing::warn!(%session_id, display_name = session.display_name(), "session unresponsive");
+				self.notify_admins_session_state(&session).await;
+			}
+			LivenessEvent::Kill(session_id) => {
+				let clients = self
+					.connected_clients
+					.iter()
+					.filter(|(_, client)| client.client_view.authenticated_session() == Some(session_id))
+					.map(|(id, client)| (*id, client.peer_pid))
+					.collect::<Vec<_>>();
+				tracing::warn!(%session_id, "killing unresponsive session");
+				for (client_id, pid) in clients {
+					if let Some(pid) = pid.filter(|pid| *pid != std::process::id() as libc::pid_t) {
+						// A frozen process only dies once thawed.
+						self.background.release(pid);
+						if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+							tracing::warn!(
+								%session_id,
+								pid,
+								"failed to kill unresponsive session: {}",
+								io::Error::last_os_error()
+							);
+						}
+					}
+					self.disconnect_client(client_id).await;
+				}
+			}
+		}
+	}
+
+	async fn apply_idle_transition(&mut self, transition: IdleTransition) {
+		tracing::info!(?transition, "idle state changed");
+		match transition {
+			IdleTransition::Idle => {
+				self.send_blanked(true).await;
+				self.set_awake_sessions(std::iter::empty()).await;
+			}
+			IdleTransition::Active => {
+				self.send_blanked(false).await;
+				if !self.render_device_lost {
+					self.set_awake_sessions(self.foreground_sessions()).await;
+				}
+			}
+		}
+	}
+
+	async fn send_render_quality(&mut self, session_id: SessionId, quality: RenderQualityPayload) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetRenderQuality {
+				session_id,
+				quality,
+			})
+			.await
+		{
+			tracing::error!("failed to forward render quality to renderer: {e}");
+		}
+	}
+
+	async fn send_latency_mode(&mut self, session_id: SessionId, mode: LatencyMode) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetLatencyMode { session_id, mode })
+			.await
+		{
+			tracing::error!("failed to forward latency mode to renderer: {e}");
+		}
+	}
+
+	async fn send_blanked(&mut self, blanked: bool) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetBlanked { blanked })
+			.await
+		{
+			tracing::error!("failed to forward blanking to renderer: {e}");
+		}
+	}
+
+	/// Sends the watermark for the current session and remote control state to the renderer
+	/// when it changed, or unconditionally with `force` (e.g. for a fresh renderer).
+	async fn refresh_watermark(&mut self, force: bool) {
+		let active = self
+			.current_session
+			.and_then(|session_id| self.active_sessions.get(&session_id))
+			.map(|session| (session.role(), session.display_name()));
+		let text = self
+			.watermark
+			.text_for(active, self.remote_control.is_some());
+		if !force && text == self.watermark_text {
+			return;
+		}
+		self.watermark_text = text.clone();
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetWatermark {
+				text: text.map(|text| text.to_string()),
+				corner: self.watermark.corner,
+			})
+			.await
+		{
+			tracing::error!("failed to forward watermark to renderer: {e}");
+		}
+	}
+
+	/// Updates the remote control holder and tells every authenticated client, so sessions can
+	/// show that someone else is driving their input. A controller allowed to screencast gets
+	/// a cast of the controlled monitor to see what it drives, unless it already casts it.
+	async fn set_remote_control(&mut self, remote_control: Option<(ClientId, MonitorId)>) {
+		if self.remote_control == remote_control {
+			return;
+		}
+		self.remote_control = remote_control;
+		let monitor_id = remote_control.map(|(_, monitor_id)| monitor_id);
+		tracing::info!(?remote_control, "remote control state changed");
+		if let Some(cast_id) = self.remote_control_cast.take()
+			&& let Some(cast) = self.screencasts.remove(cast_id)
+		{
+			self
+				.stop_screencast(cast_id, cast, Some("remote control ended"))
+				.await;
+		}
+		if let Some((client_id, monitor_id)) = remote_control
+			&& self.has_permission(client_id, Permission::Screencast)
+			&& self.screencasts.find(client_id, monitor_id).is_none()
+		{
+			self.start_screencast(client_id, monitor_id).await;
+			self.remote_control_cast = self.screencasts.find(client_id, monitor_id);
+		}
+		self.refresh_watermark(false).await;
+		for (id, client) in self.connected_clients.iter_mut() {
+			if client.client_view.authenticated_session().is_some()
+				&& !client
+					.client_view
+					.notify_remote_control_state(monitor_id)
+					.await
+			{
+				tracing::warn!(%id, "failed to notify remote control state");
+			}
+		}
+	}
+
+	async fn send_magnifier_state(&mut self) {
+		let (x, y) = self.magnifier.focus();
+		for cmd in [
+			RenderCmd::SetMagnifier {
+				monitor_id: self.magnifier.monitor_id(),
+				factor: self.magnifier.factor(),
+			},
+			RenderCmd::MagnifierFocus { x, y },
+		] {
+			if let Err(e) = self.render_commands.send(cmd).await {
+				tracing::error!("failed to forward magnifier state to renderer: {e}");
+				return;
+			}
+		}
+	}
+
+	/// Sessions the built-in switcher offers, the active one first.
+	fn switchable_sessions(
+		sessions: &HashMap<SessionId, Arc<Session>>,
+		current: Option<SessionId>,
+	) -> Vec<SessionId> {
+		let mut switchable: Vec<_> = sessions
+			.values()
+			.filter(|session| match session.role() {
+				Role::Admin => true,
+				Role::Normal => session.ready(),
+				Role::Overlay => false,
+			})
+			.collect();
+		switchable
+			.sort_by_key(|session| (session.display_name().to_string(), session.id().to_string()));
+		let mut ids: Vec<_> = switchable.into_iter().map(|session| session.id()).collect();
+		if let Some(index) = ids.iter().position(|id| Some(*id) == current) {
+			ids.rotate_left(index);
+		}
+		ids
+	}
+
+	async fn send_switcher_view(&mut self) {
+		let view = self
+			.switcher
+			.view()
+			.map(|(sessions, selected)| SwitcherView {
+				entries: sessions
+					.iter()
+					.map(|session_id| SwitcherEntry {
+						session_id: *session_id,
+						name: self
+							.active_sessions
+							.get(session_id)
+							.map(|session| session.display_name().to_string())
+							.unwrap_or_else(|| session_id.to_string()),
+					})
+					.collect(),
+				selected,
+			});
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetSessionSwitcher { view })
+			.await
+		{
+			tracing::error!("failed to forward session switcher to renderer: {e}");
+		}
+	}
+
+	/// Monitor the pointer is on, with its size.
+	fn pointer_monitor(&self) -> Option<(MonitorId, (f64, f64))> {
+		let (monitor_id, _) = self.layout.pointer()?;
+		let monitor = self.monitors.get(&monitor_id)?;
+		Some((monitor.id, (monitor.width as f64, monitor.height as f64)))
+	}
+
+	/// Monitor touchscreens map to: the primary one, or any.
+	fn touch_monitor(&self) -> Option<(MonitorId, (f64, f64))> {
+		let monitor = match self.primary_monitor {
+			Some(monitor_id) => self.monitors.get(&monitor_id),
+			None => self.monitors.values().next(),
+		}?;
+		Some((monitor.id, (monitor.width as f64, monitor.height as f64)))
+	}
+
+	fn magnifier_monitor_size(&self) -> Option<(f64, f64)> {
+		let monitor = match self.magnifier.monitor_id() {
+			Some(monitor_id) => self.monitors.get(&monitor_id),
+			None => self.monitors.values().next(),
+		}?;
+		Some((monitor.width as f64, monitor.height as f64))
+	}
+
+	#[tracing::instrument(level = "trace", skip_all, fields(frame_id = event.frame_id().map(tracing::field::display)))]
+	async fn handle_render_event(&mut self, event: RenderEvt) {
+		match event {
+			RenderEvt::Started { monitors } => {
+				self.monitors = monitors
+					.into_iter()
+					.map(|m| (m.id, self.configured(m)))
+					.collect();
+				let scaled: Vec<_> = self.monitors.values().cloned().collect();
+				for monitor in &scaled {
+					self.sync_render_scale(monitor).await;
+					self.sync_render_resolution(monitor).await;
+				}
+				self.apply_monitor_profile().await;
+				self.sync_flip_groups().await;
+				self.sync_layout().await;
+			}
+			RenderEvt::MonitorOnline { monitor } => {
+				tracing::info!(?monitor, "renderer reports monitor online");
+				self.add_monitor(monitor).await;
+				self.apply_monitor_profile().await;
+				self.sync_flip_groups().await;
+				self.sync_layout().await;
+			}
+			RenderEvt::MonitorOffline { monitor_id } => {
+				tracing::info!(%monitor_id, "renderer reports monitor offline");
+				self.forget_monitor(monitor_id).await;
+				self.monitor_identities.remove(&monitor_id);
+				self.display_adjustments.remove(&monitor_id);
+				self.output_transforms.remove(&monitor_id);
+				self.monitor_scales.remove(&monitor_id);
+				self.layout.forget_monitor(monitor_id);
+				self.apply_monitor_profile().await;
+				self.sync_flip_groups().await;
+				self.sync_layout().await;
+			}
+			RenderEvt::MonitorResized { monitor } => {
+				tracing::info!(?monitor, "renderer reports monitor resized");
+				self.resize_monitor(monitor).await;
+			}
+			RenderEvt::MonitorModeChanged { monitor_id, result } => {
+				let waiting = self.mode_requests.remove(&monitor_id).unwrap_or_default();
+				match result {
+					Ok(monitor) => self.change_monitor_mode(monitor).await,
+					Err(reason) => {
+						tracing::warn!(%monitor_id, %reason, "renderer failed to change monitor mode");
+						for client_id in waiting {
+							if let Some(client) = self.connected_clients.get_mut(&client_id) {
+								client
+									.client_view
+									.notify_error("mode_set_failed".into(), Some(Arc::clone(&reason)), false)
+									.await;
+							}
+						}
+					}
+				}
+			}
+			RenderEvt::DeviceLost { reason } => {
+				tracing::warn!(%reason, "renderer lost its DRM device, putting sessions to sleep");
+				self.render_device_lost = true;
+				let monitor_ids = self.monitors.keys().copied().collect::<Vec<_>>();
+				for monitor_id in monitor_ids {
+					self.forget_monitor(monitor_id).await;
+				}
+				let virtual_monitors: Vec<_> = self.virtual_monitors.iter().cloned().collect();
+				for virtual_monitor in &virtual_monitors {
+					self.retire_virtual_monitor(virtual_monitor).await;
+				}
+				// Monitor ids don't survive the device, so disabled ones come back enabled.
+				self.disabled_monitors.clear();
+				self.sent_flip_groups.clear();
+				self.monitor_identities.clear();
+				self.display_adjustments.clear();
+				self.awake_until.clear();
+				let asleep = self.awake_sessions.drain().collect::<Vec<_>>();
+				for session_id in asleep {
+					self.notify_session_awake_change(session_id, false).await;
+				}
+			}
+			RenderEvt::DeviceRestored { monitors } => {
+				tracing::info!(count = monitors.len(), "renderer recovered its DRM device");
+				self.render_device_lost = false;
+				for monitor in monitors {
+					self.add_monitor(monitor).await;
+				}
+				let transforms: Vec<_> = self
+					.output_transforms
+					.iter()
+					.filter(|(monitor_id, _)| self.monitors.contains_key(monitor_id))
+					.map(|(monitor_id, transform)| (*monitor_id, *transform))
+					.collect();
+				for (monitor_id, transform) in transforms {
+					self.send_output_transform(monitor_id, transform).await;
+				}
+				self.apply_monitor_profile().await;
+				self.sync_flip_groups().await;
+				self.sync_layout().await;
+				// Virtual monitors keep their ids; their sessions relink as for a new monitor.
+				let virtual_monitors: Vec<_> = self.virtual_monitors.iter().cloned().collect();
+				for virtual_monitor in &virtual_monitors {
+					self.announce_virtual_monitor(virtual_monitor).await;
+				}
+				// The new renderer starts without an active session; replay it and wake it up.
+				self.update_active_session(self.current_session, None).await;
+				if self.magnifier.is_active() {
+					self.send_magnifier_state().await;
+				}
+				if self.idle.is_idle() {
+					self.send_blanked(true).await;
+				}
+				self.refresh_watermark(true).await;
+				if let Some(wallpaper) = self.wallpaper.clone() {
+					self.send_wallpaper(None, wallpaper).await;
+				}
+				if let Some(color_profile) = self.color_profile.clone() {
+					self.send_color_profile(None, color_profile).await;
+				}
+				if let Some(enabled) = self.vrr {
+					self.send_vrr(None, enabled).await;
+				}
+				if let Some((kelvin, schedule)) = self.color_temperature {
+					self.send_color_temperature(None, kelvin, schedule).await;
+				}
+				if self.recovery.is_some() {
+					self.send_recovery_screen().await;
+				}
+				if self.switcher.view().is_some() {
+					self.send_switcher_view().await;
+				}
+				let render_quality: Vec<_> = self
+					.render_quality
+					.iter()
+					.map(|(session_id, quality)| (*session_id, *quality))
+					.collect();
+				for (session_id, quality) in render_quality {
+					self.send_render_quality(session_id, quality).await;
+				}
+				let latency_modes: Vec<_> = self
+					.latency_modes
+					.iter()
+					.map(|(session_id, mode)| (*session_id, *mode))
+					.collect();
+				for (session_id, mode) in latency_modes {
+					self.send_latency_mode(session_id, mode).await;
+				}
+			}
+			RenderEvt::BufferRequestAck {
+				session_id,
+				monitor_id,
+				buffer,
+				frame_id,
+				deferred,
+			} => {
+				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
+					pending.session_id == session_id
+						&& pending.monitor_id == monitor_id
+						&& pending.buffer == buffer
+				}) else {
+					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "renderer acked unknown pending request");
+					return;
+				};
+				let pending = self.pending_buffer_requests.remove(pos);
+				self
+					.buffer_ownership
+					.insert((session_id, monitor_id, buffer), BufferOwner::Shift);
+				if deferred {
+					self.queued_buffers.insert((session_id, monitor_id), buffer);
+				}
+				self.swap_buffers_received = self.swap_buffers_received.saturating_add(1);
+
+				let mut should_disconnect = false;
+				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
+					if !client
+						.client_view
+						.notify_buffer_request_ack(monitor_id, buffer, frame_id)
+						.await
+					{
+						should_disconnect = true;
+					}
+				}
+				if should_disconnect {
+					self.disconnect_client(pending.client_id).await;
+				}
+			}
+			RenderEvt::BufferRequestRejected {
+				session_id,
+				monitor_id,
+				buffer,
+				reason,
+				..
+			} => {
+				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
+					pending.session_id == session_id
+						&& pending.monitor_id == monitor_id
+						&& pending.buffer == buffer
+				}) else {
+					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, %reason, "renderer rejected unknown pending request");
+					return;
+				};
+				let pending = self.pending_buffer_requests.remove(pos);
+				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
+					client
+						.client_view
+						.notify_error("buffer_request_rejected".into(), Some(reason), false)
+						.await;
+				}
+			}
+			RenderEvt::BufferLatched {
+				session_id,
+				monitor_id,
+				buffer,
+				..
+			} => {
+				if self.queued_buffers.get(&(session_id, monitor_id)) == Some(&buffer) {
+					self.queued_buffers.remove(&(session_id, monitor_id));
+				}
+			}
+			RenderEvt::BufferConsumed {
+				session_id,
+				monitor_id,
+				buffer,
+				release_fence,
+				content_hash,
+				frame_id,
+			} => {
+				self
+					.buffer_ownership
+					.insert((session_id, monitor_id, buffer), BufferOwner::Client);
+				// A queued buffer is released unshown when its surface is torn down.
+				if self.queued_buffers.get(&(session_id, monitor_id)) == Some(&buffer) {
+					self.queued_buffers.remove(&(session_id, monitor_id));
+				}
+				let Some((_id, client)) = self
+					.connected_clients
+					.iter_mut()
+					.find(|(_, c)| c.client_view.authenticated_session() == Some(session_id))
+				else {
+					return;
+				};
+				if !client
+					.client_view
+					.notify_buffer_release(vec![BufferRelease {
+						monitor_id,
+						buffer,
+						release_fence,
+						content_hash,
+						frame_id,
+					}])
+					.await
+				{
+					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "failed to send early buffer_release");
+				} else {
+					self.frame_done_emitted = self.frame_done_emitted.saturating_add(1);
+				}
+			}
+			RenderEvt::PresentationFeedback {
+				session_id,
+				monitor_id,
+				buffer,
+				timestamp_ns,
+				sequence,
+				refresh_ns,
+				..
+			} => {
+				let Some(client) = self
+					.connected_clients
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(session_id))
+				else {
+					return;
+				};
+				client
+					.client_view
+					.notify_presentation_feedback(PresentationFeedbackPayload {
+						monitor_id,
+						buffer,
+						timestamp_ns,
+						sequence,
+						refresh_ns,
+					})
+					.await;
+			}
+			RenderEvt::Captured { monitor_id, result } => match result {
+				Ok(frame) => self.send_screenshot(monitor_id, frame).await,
+				Err(reason) => self.fail_screenshots(monitor_id, reason).await,
+			},
+			RenderEvt::ScreencastStarted { cast_id, node_id } => {
+				let Some(cast) = self.screencasts.get_mut(cast_id) else {
+					return;
+				};
+				cast.started = true;
+				let (client_id, monitor_id) = (cast.client_id, cast.monitor_id);
+				tracing::info!(%monitor_id, %client_id, node_id, "screencast started");
+				if let Some(client) = self.connected_clients.get_mut(&client_id) {
+					client
+						.client_view
+						.notify_screencast_started(monitor_id, node_id)
+						.await;
+				}
+			}
+			RenderEvt::ScreencastEnded { cast_id, reason } => {
+				let Some(cast) = self.screencasts.remove(cast_id) else {
+					return;
+				};
+				tracing::warn!(monitor_id = %cast.monitor_id, %reason, "screencast ended");
+				if let Some(client) = self.connected_clients.get_mut(&cast.client_id) {
+					if cast.started {
+						client
+							.client_view
+							.notify_screencast_stopped(cast.monitor_id, &reason)
+							.await;
+					} else {
+						client
+							.client_view
+							.notify_error("screencast_failed".into(), Some(reason), false)
+							.await;
+					}
+				}
+			}
+			RenderEvt::FatalError { reason } => {
+				tracing::error!(?reason, "renderer fatal error");
+				// TODO: Shutdown server
+			}
+			RenderEvt::PageFlip { monitors } => {
+				let _ = monitors;
+			}
+			RenderEvt::FrameStats {
+				monitor_id,
+				fps,
+				avg_frame_ms,
+				p99_frame_ms,
+				missed_vblanks,
+				skipped_commits,
+			} => {
+				if missed_vblanks > 0 {
+					tracing::debug!(%monitor_id, fps, p99_frame_ms, missed_vblanks, "frame stats");
+				}
+				let stats = FrameStatsPayload {
+					monitor_id,
+					fps,
+					avg_frame_ms,
+					p99_frame_ms,
+					missed_vblanks,
+					skipped_commits,
+				};
+				if self.forward_frame_stats {
+					for id in self.admin_client_ids() {
+						if let Some(client) = self.connected_clients.get_mut(&id) {
+							client.client_view.notify_frame_stats(stats.clone()).await;
+						}
+					}
+				}
+			}
+			RenderEvt::SessionFrameStats {
+				session_id,
+				monitor_id,
+				presented_frames,
+				missed_refreshes,
+				longest_miss_streak,
+				stale_presents,
+			} => {
+				let dropped = missed_refreshes > 0 || stale_presents > 0;
+				if dropped {
+					tracing::debug!(%session_id, %monitor_id, presented_frames, missed_refreshes, longest_miss_streak, stale_presents, "session frame drops");
+				}
+				let stats = SessionFrameStatsPayload {
+					session_id,
+					monitor_id,
+					presented_frames,
+					missed_refreshes,
+					longest_miss_streak,
+					stale_presents,
+				};
+				let mut recipients = if self.forward_frame_stats {
+					self.admin_client_ids()
+				} else {
+					Vec::new()
+				};
+				if self.notify_frame_drops
+					&& dropped
+					&& let Some((id, _)) = self
+						.connected_clients
+						.iter()
+						.find(|(_, c)| c.client_view.authenticated_session() == Some(session_id))
+					&& !recipients.contains(id)
+				{
+					recipients.push(*id);
+				}
+				for id in recipients {
+					if let Some(client) = self.connected_clients.get_mut(&id) {
+						client
+							.client_view
+							.notify_session_frame_stats(stats.clone())
+							.await;
+					}
+				}
+			}
+			RenderEvt::ResourceCacheStats {
+				resource_count,
+				resource_bytes,
+				purgeable_bytes,
+				budget_bytes,
+			} => {
+				tracing::trace!(
+					resource_count,
+					resource_bytes,
+					budget_bytes,
+					"GPU resource cache"
+				);
+				let stats = RenderCacheStatsPayload {
+					resource_count,
+					resource_bytes,
+					purgeable_bytes,
+					budget_bytes,
+				};
+				if self.forward_frame_stats {
+					for id in self.admin_client_ids() {
+						if let Some(client) = self.connected_clients.get_mut(&id) {
+							client
+								.client_view
+								.notify_render_cache_stats(stats.clone())
+								.await;
+						}
+					}
+				}
+			}
+		}
+	}
+
+	/// Logs channel overflows since the last check and forwards the counters to admins.
+	async fn report_channel_stats(&mut self) {
+		let current = channel::snapshot();
+		let previous = std::mem::replace(&mut self.channel_stats, current);
+		if current == previous {
+			return;
+		}
+		for (now, before) in current.iter().zip(&previous) {
+			if now.saturated > before.saturated {
+				tracing::warn!(
+					channel = now.kind.name(),
+					capacity = now.config.capacity,
+					overflow = now.config.policy.as_str(),
+					saturated = now.saturated - before.saturated,
+					dropped = now.dropped - before.dropped,
+					coalesced = now.coalesced - before.coalesced,
+					"channel full"
+				);
+			}
+		}
+		if !self.forward_frame_stats {
+			return;
+		}
+		let stats = ChannelStatsPayload {
+			channels: current
+				.iter()
+				.map(|snapshot| ChannelStats {
+					kind: snapshot.kind.name().into(),
+					capacity: snapshot.config.capacity as u64,
+					overflow: snapshot.config.policy.as_str().into(),
+					saturated: snapshot.saturated,
+					dropped: snapshot.dropped,
+					coalesced: snapshot.coalesced,
+				})
+				.collect(),
+		};
+		for id in self.admin_client_ids() {
+			if let Some(client) = self.connected_clients.get_mut(&id) {
+				client.client_view.notify_channel_stats(stats.clone()).await;
+			}
+		}
+	}
+
+	/// Stops composing `monitor_id` and tells clients it is gone, keeping it around so it can
+	/// be re-enabled.
+	async fn disable_monitor(&mut self, monitor_id: MonitorId) {
+		let Some(monitor) = self.monitors.get(&monitor_id).cloned() else {
+			return;
+		};
+		tracing::info!(%monitor_id, "disabling monitor");
+		self.forget_monitor(monitor_id).await;
+		self.disabled_monitors.insert(monitor_id, monitor);
+		self.send_monitor_enabled(monitor_id, false).await;
+	}
+
+	async fn enable_monitor(&mut self, monitor_id: MonitorId) {
+		let Some(monitor) = self.disabled_monitors.remove(&monitor_id) else {
+			return;
+		};
+		tracing::info!(%monitor_id, "enabling monitor");
+		self.send_monitor_enabled(monitor_id, true).await;
+		self.add_monitor(monitor).await;
+	}
+
+	/// Registers a monitor that became available and announces it, along with the primary
+	/// monitor if that changed as a result.
+	async fn add_monitor(&mut self, monitor: Monitor) {
+		let mut monitor = self.configured(monitor);
+		monitor.primary = false;
+		let monitor_id = monitor.id;
+		self.sync_render_scale(&monitor).await;
+		self.sync_render_resolution(&monitor).await;
+		self.monitors.insert(monitor_id, monitor);
+		let primary_changed = self.update_primary_monitor(None);
+		self
+			.layout
+			.arrange(&mut self.monitors, self.primary_monitor);
+		let monitor = self.monitors[&monitor_id].clone();
+		self.broadcast_monitor_added(&monitor).await;
+		if primary_changed {
+			self.broadcast_primary_monitor().await;
+		}
+		self.sync_layout().await;
+	}
+
+	/// Re-evaluates the primary monitor, preferring `requested`, and updates the `primary` flags.
+	/// Returns whether the primary monitor changed.
+	fn update_primary_monitor(&mut self, requested: Option<MonitorId>) -> bool {
+		let primary = pick_primary(
+			&self.monitors,
+			self.configured_primary_monitor.as_deref(),
+			requested.or(self.primary_monitor),
+		);
+		if primary == self.primary_monitor {
+			return false;
+		}
+		self.primary_monitor = primary;
+		for monitor in self.monitors.values_mut() {
+			monitor.primary = Some(monitor.id) == primary;
+		}
+		tracing::info!(monitor_id = ?primary, "primary monitor changed");
+		true
+	}
+
+	async fn send_display_adjustment(
+		&mut self,
+		monitor_id: MonitorId,
+		adjustment: DisplayAdjustment,
+	) {
+		if adjustment == DisplayAdjustment::IDENTITY {
+			self.display_adjustments.remove(&monitor_id);
+		} else {
+			self.display_adjustments.insert(monitor_id, adjustment);
+		}
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetDisplayAdjustment {
+				monitor_id,
+				brightness: adjustment.brightness,
+				contrast: adjustment.contrast,
+				gamma: adjustment.gamma,
+			})
+			.await
+		{
+			tracing::error!("failed to forward display adjustment to renderer: {e}");
+		}
+	}
+
+	/// Profile keys of every connected monitor, disabled ones included.
+	fn monitor_topology(&mut self) -> HashMap<MonitorId, String> {
+		let connected: Vec<(MonitorId, u32)> = self
+			.monitors
+			.values()
+			.chain(self.disabled_monitors.values())
+			.map(|monitor| (monitor.id, monitor.connector_id))
+			.collect();
+		let identities = connected.into_iter().map(|(monitor_id, connector_id)| {
+			let identity = self
+				.monitor_identities
+				.entry(monitor_id)
+				.or_insert_with(|| monitor_profiles::read_identity(connector_id))
+				.clone();
+			(monitor_id, connector_id, identity)
+		});
+		monitor_profiles::topology_keys(identities)
+	}
+
+	/// Reapplies the saved profile of the connected monitors, if there is one.
+	async fn apply_monitor_profile(&mut self) {
+		if !self.monitor_profiles.is_enabled() {
+			return;
+		}
+		let topology = self.monitor_topology();
+		let keys = topology.values().cloned().collect();
+		let Some(profile) = self.monitor_profiles.find(&keys).cloned() else {
+			return;
+		};
+		tracing::info!(monitors = ?keys, "applying monitor profile");
+		let mut primary = None;
+		for (monitor_id, key) in topology {
+			let Some(settings) = profile.get(&key) else {
+				continue;
+			};
+			let adjustment = settings.adjustment.unwrap_or(DisplayAdjustment::IDENTITY);
+			let current = self
+				.display_adjustments
+				.get(&monitor_id)
+				.copied()
+				.unwrap_or(DisplayAdjustment::IDENTITY);
+			if adjustment != current {
+				self.send_display_adjustment(monitor_id, adjustment).await;
+			}
+			let transform = settings.transform.unwrap_or_default();
+			if self
+				.output_transforms
+				.get(&monitor_id)
+				.copied()
+				.unwrap_or_default()
+				!= transform
+			{
+				self.send_output_transform(monitor_id, transform).await;
+			}
+			if self.monitor_scales.get(&monitor_id).copied() != settings.scale {
+				self.send_monitor_scale(monitor_id, settings.scale).await;
+			}
+			self.layout.set_position(monitor_id, settings.position);
+			if settings.enabled {
+				self.enable_monitor(monitor_id).await;
+			} else {
+				self.disable_monitor(monitor_id).await;
+			}
+			if settings.primary {
+				primary = Some(monitor_id);
+			}
+		}
+		if let Some(primary) = primary.filter(|id| self.monitors.contains_key(id))
+			&& self.update_primary_monitor(Some(primary))
+		{
+			self.broadcast_primary_monitor().await;
+		}
+	}
+
+	async fn handle_set_wallpaper(
+		&mut self,
+		client_id: ClientId,
+		monitor_id: Option<MonitorId>,
+		wallpaper: tab_protocol::Wallpaper,
+	) {
+		let source = match wallpaper {
+			tab_protocol::Wallpaper::Color { rgb } => Ok(WallpaperSource::Color(rgb)),
+			tab_protocol::Wallpaper::Image { path } => match std::fs::read(&path) {
+				Ok(bytes)
+					if skia_safe::Image::from_encoded(skia_safe::Data::new_copy(&bytes)).is_some() =>
+				{
+					Ok(WallpaperSource::Image(bytes.into()))
+				}
+				Ok(_) => Err(("wallpaper_unsupported", path)),
+				Err(e) => {
+					tracing::warn!(%path, "failed to read wallpaper: {e}");
+					Err(("wallpaper_io", format!("{path}: {e}")))
+				}
+			},
+		};
+		match source {
+			Ok(source) => {
+				if monitor_id.is_none() {
+					self.wallpaper = Some(source.clone());
+				}
+				self.send_wallpaper(monitor_id, source).await;
+			}
+			Err((code, message)) => {
+				if let Some(client) = self.connected_clients.get_mut(&client_id) {
+					client
+						.client_view
+						.notify_error(code.into(), Some(message.into()), false)
+						.await;
+				}
+			}
+		}
+	}
+
+	async fn send_wallpaper(&mut self, monitor_id: Option<MonitorId>, wallpaper: WallpaperSource) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetWallpaper {
+				monitor_id,
+				wallpaper,
+			})
+			.await
+		{
+			tracing::error!("failed to forward wallpaper to renderer: {e}");
+		}
+	}
+
+	async fn handle_set_color_profile(
+		&mut self,
+		client_id: ClientId,
+		monitor_id: Option<MonitorId>,
+		profile: tab_protocol::ColorProfile,
+	) {
+		let source = match profile {
+			tab_protocol::ColorProfile::None => Ok(ColorProfileSource::None),
+			tab_protocol::ColorProfile::Edid => Ok(ColorProfileSource::Edid),
+			tab_protocol::ColorProfile::Icc { path } => match std::fs::read(&path) {
+				Ok(bytes) => match DisplayProfile::from_icc(&bytes) {
+					Ok(_) => Ok(ColorProfileSource::Icc(bytes.into())),
+					Err(e) => Err(("color_profile_unsupported", format!("{path}: {e}"))),
+				},
+				Err(e) => {
+					tracing::warn!(%path, "failed to read color profile: {e}");
+					Err(("color_profile_io", format!("{path}: {e}")))
+				}
+			},
+		};
+		match source {
+			Ok(source) => {
+				if monitor_id.is_none() {
+					self.color_profile = Some(source.clone());
+				}
+				self.send_color_profile(monitor_id, source).await;
+			}
+			Err((code, message)) => {
+				if let Some(client) = self.connected_clients.get_mut(&client_id) {
+					client
+						.client_view
+						.notify_error(code.into(), Some(message.into()), false)
+						.await;
+				}
+			}
+		}
+	}
+
+	async fn send_color_profile(
+		&mut self,
+		monitor_id: Option<MonitorId>,
+		source: ColorProfileSource,
+	) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetColorProfile { monitor_id, source })
+			.await
+		{
+			tracing::error!("failed to forward color profile to renderer: {e}");
+		}
+	}
+
+	async fn send_vrr(&mut self, monitor_id: Option<MonitorId>, enabled: bool) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetVrr {
+				monitor_id,
+				enabled,
+			})
+			.await
+		{
+			tracing::error!("failed to forward VRR setting to renderer: {e}");
+		}
+	}
+
+	async fn send_color_temperature(
+		&mut self,
+		monitor_id: Option<MonitorId>,
+		kelvin: u32,
+		schedule: Option<NightLightSchedule>,
+	) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetColorTemperature {
+				monitor_id,
+				kelvin,
+				schedule,
+			})
+			.await
+		{
+			tracing::error!("failed to forward color temperature to renderer: {e}");
+		}
+	}
+
+	/// Rotates or flips `monitor_id`, and resizes it for its sessions when that turns it on its
+	/// side.
+	async fn send_output_transform(&mut self, monitor_id: MonitorId, transform: OutputTransform) {
+		if transform.is_normal() {
+			self.output_transforms.remove(&monitor_id);
+		} else {
+			self.output_transforms.insert(monitor_id, transform);
+		}
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetOutputTransform {
+				monitor_id,
+				transform,
+			})
+			.await
+		{
+			tracing::error!("failed to forward output transform to renderer: {e}");
+		}
+		let known = self
+			.monitors
+			.get(&monitor_id)
+			.or_else(|| self.disabled_monitors.get(&monitor_id))
+			.filter(|monitor| monitor.transform != transform)
+			.cloned();
+		if let Some(monitor) = known {
+			tracing::info!(%monitor_id, ?transform, "monitor transform changed");
+			self.resize_monitor(monitor).await;
+		}
+	}
+
+	/// Sets the scale of `monitor_id`, or goes back to its `SHIFT_MONITOR_SCALE` one when
+	/// `None`, and resizes it for its sessions when that changes it.
+	async fn send_monitor_scale(&mut self, monitor_id: MonitorId, scale: Option<MonitorScale>) {
+		match scale {
+			Some(scale) => self.monitor_scales.insert(monitor_id, scale),
+			None => self.monitor_scales.remove(&monitor_id),
+		};
+		let known = self
+			.monitors
+			.get(&monitor_id)
+			.or_else(|| self.disabled_monitors.get(&monitor_id))
+			.cloned();
+		let Some(monitor) = known else {
+			return;
+		};
+		let scale = self.monitor_scale(&monitor);
+		self.send_render_scale(monitor_id, scale).await;
+		if monitor.scale != scale {
+			tracing::info!(%monitor_id, scale = scale.factor(), "monitor scale changed");
+			self.resize_monitor(monitor).await;
+		}
+	}
+
+	/// Tells the renderer the scale of `monitor`, which it forgets with the monitor, unless it
+	/// is 1x.
+	async fn sync_render_scale(&mut self, monitor: &Monitor) {
+		if !monitor.scale.is_one() {
+			self.send_render_scale(monitor.id, monitor.scale).await;
+		}
+	}
+
+	async fn send_render_scale(&mut self, monitor_id: MonitorId, scale: MonitorScale) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetMonitorScale { monitor_id, scale })
+			.await
+		{
+			tracing::error!("failed to forward monitor scale to renderer: {e}");
+		}
+	}
+
+	/// Tells the renderer the resolution `monitor` is composed at when `SHIFT_RENDER_RESOLUTION`
+	/// gives it one other than its mode's.
+	async fn sync_render_resolution(&mut self, monitor: &Monitor) {
+		let scale = self.render_resolutions.scale_for(monitor);
+		if scale.is_one() {
+			return;
+		}
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetRenderResolution {
+				monitor_id: monitor.id,
+				scale,
+			})
+			.await
+		{
+			tracing::error!("failed to forward render resolution to renderer: {e}");
+		}
+	}
+
+	/// Tells the renderer which connected monitors flip together, when that changed.
+	async fn sync_flip_groups(&mut self) {
+		let groups = self.flip_groups.resolve(&self.monitors);
+		if groups == self.sent_flip_groups {
+			return;
+		}
+		tracing::info!(?groups, "monitor flip groups changed");
+		self.sent_flip_groups = groups.clone();
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetFlipGroups { groups })
+			.await
+		{
+			tracing::error!("failed to forward flip groups to renderer: {e}");
+		}
+	}
+
+	/// Lays out the connected monitors, moving the pointer off monitors that went away, and
+	/// tells clients where monitors are when that changed.
+	async fn sync_layout(&mut self) {
+		self
+			.layout
+			.arrange(&mut self.monitors, self.primary_monitor);
+		if let Some((_, position)) = self.layout.pointer() {
+			self.overlays.set_pointer(position);
+		}
+		self.move_cursor().await;
+		let placements = self.layout.placements();
+		if placements == self.sent_layout {
+			return;
+		}
+		tracing::info!(?placements, "monitor layout changed");
+		self.sent_layout = placements.clone();
+		for (id, client) in self.connected_clients.iter_mut() {
+			if !client
+				.client_view
+				.notify_monitor_layout(placements.clone())
+				.await
+			{
+				tracing::warn!(%id, "failed to notify monitor layout");
+			}
+		}
+	}
+
+	fn monitor_scale(&self, monitor: &Monitor) -> MonitorScale {
+		self
+			.monitor_scales
+			.get(&monitor.id)
+			.copied()
+			.unwrap_or_else(|| self.configured_scales.scale_for(monitor))
+	}
+
+	/// `monitor` sized, oriented and scaled as configured.
+	fn configured(&self, monitor: Monitor) -> Monitor {
+		let transform = self
+			.output_transforms
+			.get(&monitor.id)
+			.copied()
+			.unwrap_or_default();
+		let mut monitor = monitor::oriented(monitor, transform);
+		monitor.scale = self.monitor_scale(&monitor);
+		monitor
+	}
+
+	/// Hands `frame` to every client waiting for a capture of `monitor_id`.
+	async fn send_screenshot(&mut self, monitor_id: MonitorId, frame: CapturedFrame) {
+		let waiting = self.screenshots.remove(&monitor_id).unwrap_or_default();
+		for client_id in waiting {
+			let Some(client) = self.connected_clients.get_mut(&client_id) else {
+				continue;
+			};
+			let pixels = match frame.pixels.try_clone() {
+				Ok(pixels) => pixels,
+				Err(e) => {
+					tracing::warn!(%monitor_id, "failed to duplicate screenshot fd: {e}");
+					continue;
+				}
+			};
+			let payload = ScreenshotReadyPayload {
+				monitor_id,
+				width: frame.width,
+				height: frame.height,
+				stride: frame.stride,
+			};
+			client.client_view.notify_screenshot(payload, pixels).await;
+		}
+	}
+
+	async fn fail_screenshots(&mut self, monitor_id: MonitorId, reason: Arc<str>) {
+		let waiting = self.screenshots.remove(&monitor_id).unwrap_or_default();
+		for client_id in waiting {
+			if let Some(client) = self.connected_clients.get_mut(&client_id) {
+				client
+					.client_view
+					.notify_error("screenshot_failed".into(), Some(Arc::clone(&reason)), false)
+					.await;
+			}
+		}
+	}
+
+	/// Rejects `session_id` presenting on a mirror or on a virtual monitor of another session
+	/// with `forbidden`. Returns whether it did.
+	async fn reject_foreign_virtual_monitor(
+		&mut self,
+		client_id: ClientId,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+	) -> bool {
+		let Some(virtual_monitor) = self.virtual_monitors.get(monitor_id) else {
+			return false;
+		};
+		let reason = if virtual_monitor.mirror.is_some() {
+			"mirrors show their source and take no buffers"
+		} else if virtual_monitor.session_id != session_id {
+			"virtual monitor belongs to another session"
+		} else {
+			return false;
+		};
+		if let Some(client) = self.connected_clients.get_mut(&client_id) {
+			client
+				.client_view
+				.notify_error("forbidden".into(), Some(reason.into()), false)
+				.await;
+		}
+		true
+	}
+
+	async fn create_virtual_monitor(
+		&mut self,
+		client_id: ClientId,
+		request: VirtualMonitorCreatePayload,
+	) {
+		let error = if let Err(reason) = request.validate() {
+			Some(("invalid_virtual_monitor", reason.to_string()))
+		} else if !self.active_sessions.contains_key(&request.session_id) {
+			Some(("unknown_session", request.session_id.to_string()))
+		} else if let Some(source) = request.mirror
+			&& !self.monitors.contains_key(&source)
+		{
+			Some(("unknown_monitor", source.to_string()))
+		} else {
+			None
+		};
+		if let Some((code, detail)) = error {
+			if let Some(client) = self.connected_clients.get_mut(&client_id) {
+				client
+					.client_view
+					.notify_error(code.into(), Some(Arc::<str>::from(detail)), false)
+					.await;
+			}
+			return;
+		}
+		// Every physical monitor reports the device's importable formats. Mirrors import nothing.
+		let formats = self
+			.monitors
+			.values()
+			.next()
+			.filter(|_| request.mirror.is_none())
+			.map(|monitor| (monitor.formats.clone(), monitor.format_modifiers.clone()))
+			.unwrap_or_default();
+		let virtual_monitor = self.virtual_monitors.create(&request, formats).clone();
+		let monitor = &virtual_monitor.monitor;
+		tracing::info!(
+			monitor_id = %monitor.id,
+			session_id = %request.session_id,
+			width = monitor.width,
+			height = monitor.height,
+			refresh_rate = monitor.refresh_rate,
+			mirror = ?request.mirror,
+			"virtual monitor created"
+		);
+		let payload = VirtualMonitorCreatedPayload {
+			session_id: request.session_id,
+			monitor: monitor.to_protocol_info(),
+		};
+		// A lost renderer gets it with the other monitors once it recovers.
+		if !self.render_device_lost {
+			self.announce_virtual_monitor(&virtual_monitor).await;
+			if !self.idle.is_idle() {
+				self.set_awake_sessions(self.foreground_sessions()).await;
+			}
+		}
+		if let Some(client) = self.connected_clients.get_mut(&client_id) {
+			client
+				.client_view
+				.notify_virtual_monitor_created(payload)
+				.await;
+		}
+	}
+
+	/// Has the renderer compose `virtual_monitor` and tells its session about it, unless it is
+	/// a mirror.
+	async fn announce_virtual_monitor(&mut self, virtual_monitor: &VirtualMonitor) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::AddVirtualMonitor {
+				monitor: virtual_monitor.monitor.clone(),
+				session_id: virtual_monitor.session_id,
+				mirror: virtual_monitor.mirror,
+				scaling: virtual_monitor.scaling,
+			})
+			.await
+		{
+			tracing::error!("failed to forward virtual monitor to renderer: {e}");
+		}
+		if virtual_monitor.mirror.is_some() {
+			return;
+		}
+		for client in self.connected_clients.values_mut() {
+			if client.client_view.authenticated_session() == Some(virtual_monitor.session_id)
+				&& !client
+					.client_view
+					.notify_monitor_added(virtual_monitor.monitor.clone())
+					.await
+			{
+				tracing::warn!(monitor_id = %virtual_monitor.monitor.id, "failed to notify virtual monitor added");
+			}
+		}
+	}
+
+	/// Drops what refers to `virtual_monitor` once it is gone, or its renderer is.
+	async fn retire_virtual_monitor(&mut self, virtual_monitor: &VirtualMonitor) {
+		let monitor = &virtual_monitor.monitor;
+		let name: Arc<str> = monitor.name.clone().into();
+		for client in self.connected_clients.values_mut() {
+			if virtual_monitor.mirror.is_none()
+				&& client.client_view.authenticated_session() == Some(virtual_monitor.session_id)
+				&& !client
+					.client_view
+					.notify_monitor_removed(monitor.id, Arc::clone(&name))
+					.await
+			{
+				tracing::warn!(monitor_id = %monitor.id, "failed to notify virtual monitor removed");
+			}
+		}
+		self.forget_monitor(monitor.id).await;
+	}
+
+	async fn remove_virtual_monitor(&mut self, virtual_monitor: VirtualMonitor) {
+		let monitor_id = virtual_monitor.monitor.id;
+		tracing::info!(%monitor_id, session_id = %virtual_monitor.session_id, "virtual monitor removed");
+		if !self.render_device_lost
+			&& let Err(e) = self
+				.render_commands
+				.send(RenderCmd::RemoveVirtualMonitor { monitor_id })
+				.await
+		{
+			tracing::error!("failed to remove virtual monitor from renderer: {e}");
+		}
+		self.retire_virtual_monitor(&virtual_monitor).await;
+		if !self.render_device_lost && !self.idle.is_idle() {
+			self.set_awake_sessions(self.foreground_sessions()).await;
+		}
+	}
+
+	async fn start_screencast(&mut self, client_id: ClientId, monitor_id: MonitorId) {
+		let Some((session_id, role)) = self
+			.connected_clients
+			.get(&client_id)
+			.and_then(|client| client.client_view.authenticated_session())
+			.and_then(|session_id| self.active_sessions.get(&session_id))
+			.map(|session| (session.id(), session.role()))
+		else {
+			return;
+		};
+		let private = match self.virtual_monitors.session_of(monitor_id) {
+			Some(owner) => owner == session_id,
+			None => {
+				if self
+					.resolve_optional_monitor(client_id, Some(monitor_id))
+					.await
+					.is_none()
+				{
+					return;
+				}
+				false
+			}
+		};
+		let error = if self.virtual_monitors.contains(monitor_id) && !private && role != Role::Admin {
+			Some(("forbidden", "virtual monitor belongs to another session"))
+		} else if self.render_device_lost {
+			Some(("screencast_failed", "no display device"))
+		} else if self.screencasts.find(client_id, monitor_id).is_some() {
+			Some(("screencast_failed", "monitor is already being cast"))
+		} else {
+			None
+		};
+		if let Some((code, reason)) = error {
+			if let Some(client) = self.connected_clients.get_mut(&client_id) {
+				client
+					.client_view
+					.notify_error(code.into(), Some(reason.into()), false)
+					.await;
+			}
+			return;
+		}
+		let cast_id = self
+			.screencasts
+			.start(client_id, session_id, role, monitor_id, private);
+		tracing::info!(%monitor_id, %session_id, cast_id, "starting screencast");
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::StartScreencast {
+				cast_id,
+				monitor_id,
+			})
+			.await
+		{
+			tracing::error!("failed to forward screencast to renderer: {e}");
+		}
+		self.sync_screencast_pauses().await;
+	}
+
+	/// Tears `cast` down on the renderer and tells its client `reason`, unless it is gone.
+	async fn stop_screencast(&mut self, cast_id: u64, cast: Screencast, reason: Option<&str>) {
+		tracing::info!(monitor_id = %cast.monitor_id, cast_id, reason, "screencast stopped");
+		if !self.render_device_lost
+			&& let Err(e) = self
+				.render_commands
+				.send(RenderCmd::StopScreencast { cast_id })
+				.await
+		{
+			tracing::error!("failed to stop screencast on renderer: {e}");
+		}
+		if let Some(reason) = reason
+			&& let Some(client) = self.connected_clients.get_mut(&cast.client_id)
+		{
+			client
+				.client_view
+				.notify_screencast_stopped(cast.monitor_id, reason)
+				.await;
+		}
+	}
+
+	/// Pauses the casts that would show sessions other than their own, and resumes the rest.
+	async fn sync_screencast_pauses(&mut self) {
+		for (cast_id, paused) in self.screencasts.sync_paused(self.current_session) {
+			if let Err(e) = self
+				.render_commands
+				.send(RenderCmd::SetScreencastPaused { cast_id, paused })
+				.await
+			{
+				tracing::error!("failed to pause screencast on renderer: {e}");
+			}
+		}
+	}
+
+	async fn handle_monitor_profile(&mut self, client_id: ClientId, action: MonitorProfileAction) {
+		let error = if !self.monitor_profiles.is_enabled() {
+			Some(("monitor_profiles_disabled", None))
+		} else {
+			let topology = self.monitor_topology();
+			let result = match action {
+				MonitorProfileAction::Save => {
+					let profile: Profile = topology
+						.iter()
+						.map(|(monitor_id, key)| {
+							let settings = MonitorSettings {
+								enabled: self.monitors.contains_key(monitor_id),
+								primary: self.primary_monitor == Some(*monitor_id),
+								adjustment: self.display_adjustments.get(monitor_id).copied(),
+								transform: self.output_transforms.get(monitor_id).copied(),
+								scale: self.monitor_scales.get(monitor_id).copied(),
+								position: self.layout.position(*monitor_id),
+							};
+							(key.clone(), settings)
+						})
+						.collect();
+					tracing::info!(monitors = ?profile.keys(), "saving monitor profile");
+					self.monitor_profiles.save(profile).map(|()| true)
+				}
+				MonitorProfileAction::Delete => {
+					let keys = topology.into_values().collect();
+					tracing::info!(monitors = ?keys, "deleting monitor profile");
+					self.monitor_profiles.delete(&keys)
+				}
+			};
+			match result {
+				Ok(true) => None,
+				Ok(false) => Some(("no_monitor_profile", None)),
+				Err(e) => {
+					tracing::warn!("failed to store monitor profiles: {e}");
+					Some(("monitor_profile_io", Some(Arc::<str>::from(e.to_string()))))
 				}
 			}
-			RenderEvt::FatalError { reason } => {
-				tracing::error!(?reason, "renderer fatal error");
-				// TODO: Shutdown server
+		};
+		if let Some((code, message)) = error
+			&& let Some(client) = self.connected_clients.get_mut(&client_id)
+		{
+			client
+				.client_view
+				.notify_error(code.into(), message, false)
+				.await;
+		}
+	}
+
+	async fn broadcast_primary_monitor(&mut self) {
+		let monitor_id = self.primary_monitor;
+		for (id, client) in self.connected_clients.iter_mut() {
+			if !client.client_view.notify_primary_monitor(monitor_id).await {
+				tracing::warn!(%id, "failed to notify primary monitor");
 			}
-			RenderEvt::PageFlip { monitors } => {
-				let _ = monitors;
+		}
+	}
+
+	async fn send_monitor_enabled(&mut self, monitor_id: MonitorId, enabled: bool) {
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetMonitorEnabled {
+				monitor_id,
+				enabled,
+			})
+			.await
+		{
+			tracing::error!("failed to forward monitor enable state to renderer: {e}");
+		}
+	}
+
+	async fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.disabled_monitors.remove(&monitor_id);
+		if let Some(monitor) = self.monitors.remove(&monitor_id) {
+			self.broadcast_monitor_removed(&monitor).await;
+			if self.update_primary_monitor(None) {
+				self.broadcast_primary_monitor().await;
 			}
 		}
+		self.magnifier.forget_monitor(monitor_id);
+		self.overlays.forget_monitor(monitor_id);
+		self.resizes.forget_monitor(monitor_id);
+		self.mode_requests.remove(&monitor_id);
+		if let Some(recording) = self.recordings.remove(&monitor_id) {
+			tokio::spawn(async move {
+				let path = recording.stop().await;
+				tracing::info!(path = %path.display(), "screen recording stopped with its monitor");
+			});
+		}
+		self
+			.fail_screenshots(monitor_id, "monitor disconnected".into())
+			.await;
+		for (cast_id, cast) in self
+			.screencasts
+			.remove_where(|cast| cast.monitor_id == monitor_id)
+		{
+			self
+				.stop_screencast(cast_id, cast, Some("monitor removed"))
+				.await;
+		}
+		if self
+			.remote_control
+			.is_some_and(|(_, mon)| mon == monitor_id)
+		{
+			self.set_remote_control(None).await;
+		}
+		self
+			.waiting_flip
+			.retain(|pending| pending.monitor_id != monitor_id);
+		self
+			.pending_buffer_requests
+			.retain(|pending| pending.monitor_id != monitor_id);
+		self.front_buffers.retain(|(_, mon), _| *mon != monitor_id);
+		self.queued_buffers.retain(|(_, mon), _| *mon != monitor_id);
+		self
+			.buffer_ownership
+			.retain(|(_, mon, _), _| *mon != monitor_id);
+		self.sync_layout().await;
 	}
 
 	async fn handle_input_event(&mut self, event: InputEvt) {
 		match event {
-			InputEvt::Event(input_event) => {
-				let Some(active_session_id) = self.current_session else {
-					return;
-				};
-				if Self::is_coalescable_motion(&input_event) {
-					match self.pending_input_motion.as_ref() {
-						Some((pending_session, pending_event))
-							if *pending_session == active_session_id
-								&& Self::same_motion_kind(pending_event, &input_event) =>
-						{
-							self.pending_input_motion = Some((active_session_id, input_event));
-						}
-						Some(_) => {
-							self.flush_pending_input_motion().await;
-							self.pending_input_motion = Some((active_session_id, input_event));
-						}
-						None => {
-							self.pending_input_motion = Some((active_session_id, input_event));
-						}
-					}
-				} else {
-					self.flush_pending_input_motion().await;
-					self
-						.forward_input_event_to_session(active_session_id, input_event)
-						.await;
-				}
+			InputEvt::Event(mut input_event) => {
+				self.orient_input(&mut input_event);
+				self.place_pointer(&mut input_event);
+				self.route_input_event(input_event).await;
 			}
 			InputEvt::FatalError { reason } => {
 				tracing::error!(%reason, "input layer fatal error");
@@ -1073,6 +3863,188 @@ impl ShiftServer {
 		}
 	}
 
+	/// Hands a placed input event to the recovery screen, the switcher, the magnifier, an
+	/// overlay or the current session, whichever takes it.
+	async fn route_input_event(&mut self, mut input_event: InputEventPayload) {
+		if let Some(transition) = self.idle.record_activity(Instant::now()) {
+			self.apply_idle_transition(transition).await;
+		}
+		// The recovery screen owns every input event while it is shown.
+		if let Some(menu) = self.recovery.as_mut() {
+			let input = menu.handle_input(&input_event);
+			self.handle_recovery_input(input).await;
+			return;
+		}
+		let switcher_input = self.switcher.handle_input(&input_event, || {
+			Self::switchable_sessions(&self.active_sessions, self.current_session)
+		});
+		if switcher_input.changed {
+			self.send_switcher_view().await;
+		}
+		if let Some(target) = switcher_input.commit
+			&& self.current_session != Some(target)
+			&& self.active_sessions.contains_key(&target)
+		{
+			tracing::info!(%target, "switching session from the built-in switcher");
+			self.update_active_session(Some(target), None).await;
+		}
+		if switcher_input.consumed {
+			return;
+		}
+		let magnifier_size = self.magnifier_monitor_size();
+		let magnifier_input = self.magnifier.handle_input(&input_event, magnifier_size);
+		if magnifier_input.factor_changed {
+			self.send_magnifier_state().await;
+		} else if magnifier_input.focus_changed {
+			let (x, y) = self.magnifier.focus();
+			if let Err(e) = self
+				.render_commands
+				.send(RenderCmd::MagnifierFocus { x, y })
+				.await
+			{
+				tracing::error!("failed to forward magnifier focus to renderer: {e}");
+			}
+		}
+		if magnifier_input.consumed {
+			return;
+		}
+		let routed_monitor = match input_event {
+			InputEventPayload::TouchDown { .. }
+			| InputEventPayload::TouchMotion { .. }
+			| InputEventPayload::TouchUp { .. }
+			| InputEventPayload::TouchFrame { .. }
+			| InputEventPayload::TouchCancel { .. } => self.touch_monitor(),
+			_ => self.pointer_monitor(),
+		};
+		let overlay = self.overlays.route(&input_event, routed_monitor);
+		if Self::is_coalescable_motion(&input_event) {
+			self.move_cursor().await;
+		}
+		// Overlays cannot track the pointer from deltas alone, so they get its position.
+		if overlay.is_some()
+			&& let InputEventPayload::PointerMotion { x, y, .. } = &mut input_event
+		{
+			(*x, *y) = self.overlays.pointer();
+		}
+		let Some(target_session_id) = overlay.or(self.current_session) else {
+			return;
+		};
+		if Self::is_coalescable_motion(&input_event) {
+			match self.pending_input_motion.as_ref() {
+				Some((pending_session, pending_event))
+					if *pending_session == target_session_id
+						&& Self::same_motion_kind(pending_event, &input_event) =>
+				{
+					self.pending_input_motion = Some((target_session_id, input_event));
+				}
+				Some(_) => {
+					self.flush_pending_input_motion().await;
+					self.pending_input_motion = Some((target_session_id, input_event));
+				}
+				None => {
+					self.pending_input_motion = Some((target_session_id, input_event));
+				}
+			}
+		} else {
+			self.flush_pending_input_motion().await;
+			self
+				.forward_input_event_to_session(target_session_id, input_event)
+				.await;
+		}
+	}
+
+	/// Maps touch positions from the panel onto the touch monitor as its sessions see it, i.e.
+	/// undoes its transform. Absolute pointer positions are too while the layout holds a single
+	/// monitor; across several they map onto the layout as it is, see `place_pointer`.
+	fn orient_input(&self, event: &mut InputEventPayload) {
+		let (monitor, x, y) = match event {
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				..
+			} if self.layout.monitor_count() == 1 => (self.pointer_monitor(), x_transformed, y_transformed),
+			InputEventPayload::TouchDown { contact, .. }
+			| InputEventPayload::TouchMotion { contact, .. } => (
+				self.touch_monitor(),
+				&mut contact.x_transformed,
+				&mut contact.y_transformed,
+			),
+			_ => return,
+		};
+		let Some(transform) = monitor
+			.and_then(|(monitor_id, _)| self.monitors.get(&monitor_id))
+			.map(|monitor| monitor.transform)
+			.filter(|transform| !transform.is_normal())
+		else {
+			return;
+		};
+		// The input layer normalizes absolute positions to `0..=65535`.
+		let (logical_x, logical_y) = monitor::input_to_logical(transform, *x / 65535.0, *y / 65535.0);
+		(*x, *y) = (logical_x * 65535.0, logical_y * 65535.0);
+	}
+
+	/// Moves the pointer across the monitor layout, and maps absolute pointer positions onto the
+	/// monitor the pointer lands on, as its sessions see it.
+	fn place_pointer(&mut self, event: &mut InputEventPayload) {
+		let placed = match event {
+			InputEventPayload::PointerMotion { dx, dy, .. } => self.layout.move_pointer(*dx, *dy),
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				..
+			} => {
+				let placed = self
+					.layout
+					.warp_pointer(*x_transformed / 65535.0, *y_transformed / 65535.0);
+				if let Some((monitor_id, (x, y))) = placed
+					&& let Some(monitor) = self
+						.monitors
+						.get(&monitor_id)
+						.filter(|monitor| monitor.width > 0 && monitor.height > 0)
+				{
+					*x_transformed = x / monitor.width as f64 * 65535.0;
+					*y_transformed = y / monitor.height as f64 * 65535.0;
+				}
+				placed
+			}
+			_ => return,
+		};
+		if let Some((_, position)) = placed {
+			self.overlays.set_pointer(position);
+		}
+	}
+
+	/// Places an injected event like a local one, except that absolute pointer positions are
+	/// relative to the controlled monitor `monitor_id` and stay on it.
+	fn place_injected_input(&mut self, monitor_id: MonitorId, event: &mut InputEventPayload) {
+		let InputEventPayload::PointerMotionAbsolute {
+			x_transformed,
+			y_transformed,
+			..
+		} = event
+		else {
+			self.orient_input(event);
+			self.place_pointer(event);
+			return;
+		};
+		let Some((_, (x, y))) = self.layout.warp_pointer_onto(
+			monitor_id,
+			*x_transformed / 65535.0,
+			*y_transformed / 65535.0,
+		) else {
+			return;
+		};
+		if let Some(monitor) = self
+			.monitors
+			.get(&monitor_id)
+			.filter(|monitor| monitor.width > 0 && monitor.height > 0)
+		{
+			*x_transformed = x / monitor.width as f64 * 65535.0;
+			*y_transformed = y / monitor.height as f64 * 65535.0;
+		}
+		self.overlays.set_pointer((x, y));
+	}
+
 	fn is_coalescable_motion(event: &InputEventPayload) -> bool {
 		matches!(
 			event,
@@ -1097,7 +4069,11 @@ impl ShiftServer {
 		let Some((session_id, event)) = self.pending_input_motion.take() else {
 			return;
 		};
-		if self.current_session != Some(session_id) {
+		let is_overlay = self
+			.active_sessions
+			.get(&session_id)
+			.is_some_and(|session| session.role() == Role::Overlay);
+		if self.current_session != Some(session_id) && !is_overlay {
 			return;
 		}
 		if self.has_inflight_buffer_request_for_session(session_id) {
@@ -1150,8 +4126,26 @@ impl ShiftServer {
 		}
 		select_all(futures).await.0
 	}
-	#[tracing::instrument(level= "info", skip(self, accept_result), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
-	async fn handle_accept(&mut self, accept_result: io::Result<(UnixStream, SocketAddr)>) {
+	async fn accept_any(
+		listeners: &[(UnixListener, Arc<SocketRestrictions>)],
+	) -> (
+		Arc<SocketRestrictions>,
+		io::Result<(UnixStream, SocketAddr)>,
+	) {
+		let futures = listeners
+			.iter()
+			.map(|(listener, restrictions)| {
+				Box::pin(async move { (Arc::clone(restrictions), listener.accept().await) })
+			})
+			.collect::<Vec<_>>();
+		select_all(futures).await.0
+	}
+	#[tracing::instrument(level= "info", skip(self, restrictions, accept_result), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
+	async fn handle_accept(
+		&mut self,
+		restrictions: Arc<SocketRestrictions>,
+		accept_result: io::Result<(UnixStream, SocketAddr)>,
+	) {
 		match accept_result {
 			Ok((client_socket, _ip)) => {
 				macro_rules! or_continue {
@@ -1166,6 +4160,7 @@ impl ShiftServer {
                     };
                 }
 
+				let peer_pid = client_socket.peer_cred().ok().and_then(|cred| cred.pid());
 				let hellopkt = TabMessageFrame::hello("shift 0.1.0-alpha");
 				let client_async_fd = or_continue!(
 					client_socket.into_std().and_then(AsyncFd::new),
@@ -1176,8 +4171,11 @@ impl ShiftServer {
 					hellopkt.send_frame_to_async_fd(&client_async_fd).await,
 					"failed to send hello packet: {}"
 				);
-				let (new_client, mut new_client_view) =
-					Client::wrap_socket(client_async_fd, self.monitors.values().cloned().collect());
+				let (new_client, mut new_client_view) = Client::wrap_socket(
+					client_async_fd,
+					self.monitors.values().cloned().collect(),
+					Arc::clone(&restrictions),
+				);
 				let client_id = new_client_view.id();
 
 				self.connected_clients.insert(
@@ -1185,6 +4183,8 @@ impl ShiftServer {
 					ConnectedClient {
 						client_view: new_client_view,
 						join_handle: new_client.spawn().await,
+						peer_pid,
+						socket_restrictions: restrictions,
 					},
 				);
 				tracing::info!(%client_id, "client successfully connected");
@@ -1195,6 +4195,155 @@ impl ShiftServer {
 		}
 	}
 
+	/// Takes the new geometry of a connected monitor and gives the sessions with buffers on it
+	/// until the resize deadline to link new ones.
+	async fn resize_monitor(&mut self, monitor: Monitor) {
+		let mut monitor = self.configured(monitor);
+		let monitor_id = monitor.id;
+		if let Some(disabled) = self.disabled_monitors.get_mut(&monitor_id) {
+			*disabled = monitor;
+			return;
+		}
+		let Some(known) = self.monitors.get_mut(&monitor_id) else {
+			self.add_monitor(monitor).await;
+			return;
+		};
+		monitor.primary = known.primary;
+		monitor.position = known.position;
+		*known = monitor.clone();
+		let sessions: HashSet<SessionId> = self
+			.buffer_ownership
+			.keys()
+			.filter(|(_, mon, _)| *mon == monitor_id)
+			.map(|(session_id, _, _)| *session_id)
+			.collect();
+		self.resizes.start(monitor_id, sessions, Instant::now());
+		let deadline = self.resizes.deadline();
+		for (id, client) in self.connected_clients.iter_mut() {
+			if !client
+				.client_view
+				.notify_monitor_resized(monitor.clone(), deadline)
+				.await
+			{
+				tracing::warn!(%id, "failed to notify monitor resized");
+			}
+		}
+		self.sync_layout().await;
+	}
+
+	/// Records the new mode of `monitor`, already resized for its sessions when its size
+	/// changed, and tells every client.
+	async fn change_monitor_mode(&mut self, monitor: Monitor) {
+		let mut monitor = self.configured(monitor);
+		let monitor_id = monitor.id;
+		tracing::info!(
+			%monitor_id,
+			width = monitor.width,
+			height = monitor.height,
+			refresh_rate = monitor.refresh_rate,
+			"monitor mode changed"
+		);
+		if let Some(disabled) = self.disabled_monitors.get_mut(&monitor_id) {
+			*disabled = monitor;
+			return;
+		}
+		let Some(known) = self.monitors.get_mut(&monitor_id) else {
+			return;
+		};
+		monitor.primary = known.primary;
+		monitor.position = known.position;
+		*known = monitor.clone();
+		for (id, client) in self.connected_clients.iter_mut() {
+			if !client
+				.client_view
+				.notify_monitor_mode_changed(monitor.clone())
+				.await
+			{
+				tracing::warn!(%id, "failed to notify monitor mode changed");
+			}
+		}
+		self.sync_layout().await;
+	}
+
+	/// Drops the stale buffers of sessions that didn't relink in time after a resize.
+	async fn expire_resizes(&mut self) {
+		for (session_id, monitor_id) in self.resizes.take_expired(Instant::now()) {
+			tracing::warn!(%session_id, %monitor_id, "session missed the resize deadline, dropping its buffers");
+			self.drop_session_buffers(session_id, monitor_id).await;
+			if let Some(client) = self
+				.connected_clients
+				.values_mut()
+				.find(|client| client.client_view.authenticated_session() == Some(session_id))
+			{
+				client
+					.client_view
+					.notify_error(
+						"resize_deadline_expired".into(),
+						Some(Arc::<str>::from(monitor_id.to_string())),
+						false,
+					)
+					.await;
+			}
+		}
+	}
+
+	/// Forgets the buffers `session_id` linked on `monitor_id`, here and in the renderer.
+	async fn drop_session_buffers(&mut self, session_id: SessionId, monitor_id: MonitorId) {
+		self
+			.waiting_flip
+			.retain(|pending| (pending.session_id, pending.monitor_id) != (session_id, monitor_id));
+		self
+			.pending_buffer_requests
+			.retain(|pending| (pending.session_id, pending.monitor_id) != (session_id, monitor_id));
+		self.front_buffers.remove(&(session_id, monitor_id));
+		self.queued_buffers.remove(&(session_id, monitor_id));
+		self
+			.buffer_ownership
+			.retain(|(sess, mon, _), _| (*sess, *mon) != (session_id, monitor_id));
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::DropSessionBuffers {
+				session_id,
+				monitor_id,
+			})
+			.await
+		{
+			tracing::error!("failed to drop stale buffers in renderer: {e}");
+		}
+	}
+
+	async fn handle_memory_pressure(&mut self) {
+		tracing::info!("memory pressure, releasing caches");
+		if let Err(e) = self.render_commands.send(RenderCmd::MemoryPressure).await {
+			tracing::error!("failed to ask renderer to release memory: {e}");
+		}
+		if !self.memory_pressure.releases_buffers() {
+			return;
+		}
+		let linked: HashSet<(SessionId, MonitorId)> = self
+			.buffer_ownership
+			.keys()
+			.map(|(session_id, monitor_id, _)| (*session_id, *monitor_id))
+			.filter(|(session_id, _)| {
+				self.active_sessions.contains_key(session_id) && !self.awake_sessions.contains(session_id)
+			})
+			.collect();
+		for &(session_id, monitor_id) in &linked {
+			self.drop_session_buffers(session_id, monitor_id).await;
+		}
+		let released: HashSet<SessionId> = linked.iter().map(|(session_id, _)| *session_id).collect();
+		for session_id in released {
+			tracing::info!(%session_id, "asking sleeping session to release its buffers");
+			if let Some(client) = self
+				.connected_clients
+				.values_mut()
+				.find(|client| client.client_view.authenticated_session() == Some(session_id))
+			{
+				client.client_view.notify_release_buffers(session_id).await;
+			}
+		}
+	}
+
 	async fn broadcast_monitor_added(&mut self, monitor: &crate::monitor::Monitor) {
 		for (id, client) in self.connected_clients.iter_mut() {
 			if !client
@@ -1224,11 +4373,47 @@ impl ShiftServer {
 		let Some(client) = self.connected_clients.remove(&client_id) else {
 			return;
 		};
+		for waiting in self
+			.screenshots
+			.values_mut()
+			.chain(self.mode_requests.values_mut())
+		{
+			waiting.retain(|id| *id != client_id);
+		}
+		for (cast_id, cast) in self
+			.screencasts
+			.remove_where(|cast| cast.client_id == client_id)
+		{
+			self.stop_screencast(cast_id, cast, None).await;
+		}
+		if let Some(pid) = client.peer_pid {
+			self.background.forget(pid, client_id);
+		}
+		if self
+			.remote_control
+			.is_some_and(|(holder, _)| holder == client_id)
+		{
+			self.set_remote_control(None).await;
+		}
 		if let Some(session_id) = client.client_view.authenticated_session() {
+			self.idle.release(session_id, Instant::now());
+			self.liveness.forget(session_id);
+			self.audio_policy.forget(session_id);
+			self.render_quality.remove(&session_id);
+			self.latency_modes.remove(&session_id);
+			self.overlays.forget_session(session_id);
+			self.cursors.forget_session(session_id);
+			self.resizes.forget_session(session_id);
+			if self.switcher.forget_session(session_id) {
+				self.send_switcher_view().await;
+			}
 			self.active_sessions.remove(&session_id);
 			self.loading_sessions.remove(&session_id);
 			self.awake_sessions.remove(&session_id);
 			self.awake_until.remove(&session_id);
+			for virtual_monitor in self.virtual_monitors.remove_session(session_id) {
+				self.remove_virtual_monitor(virtual_monitor).await;
+			}
 			self
 				.pending_buffer_requests
 				.retain(|pending| pending.client_id != client_id && pending.session_id != session_id);
@@ -1238,9 +4423,18 @@ impl ShiftServer {
 			self
 				.front_buffers
 				.retain(|(sess, _), _| *sess != session_id);
+			self
+				.queued_buffers
+				.retain(|(sess, _), _| *sess != session_id);
 			self
 				.buffer_ownership
 				.retain(|(sess, _, _), _| *sess != session_id);
+			self.handover.forget(session_id);
+			// Hand over before the renderer drops the session, so the transition can start from
+			// its last frame.
+			if self.current_session == Some(session_id) {
+				self.hand_over_current_session(session_id).await;
+			}
 			if let Err(e) = self
 				.render_commands
 				.send(RenderCmd::SessionRemoved { session_id })
@@ -1248,12 +4442,22 @@ impl ShiftServer {
 			{
 				tracing::error!("failed to notify renderer about session removal: {e}");
 			}
-			if self.current_session == Some(session_id) {
-				self.update_active_session(None, None).await;
-			}
 		}
 	}
 
+	/// Picks the current session after `departed`, the current one, went away.
+	async fn hand_over_current_session(&mut self, departed: SessionId) {
+		let switchable = Self::switchable_sessions(&self.active_sessions, None);
+		let candidates: Vec<_> = switchable
+			.iter()
+			.filter_map(|id| Some((*id, self.active_sessions.get(id)?.display_name())))
+			.collect();
+		let next = self.handover.successor(&candidates);
+		let transition = next.and_then(|_| self.handover.transition(departed));
+		tracing::info!(%departed, next = ?next, "current session disconnected, handing over");
+		self.update_active_session(next, transition).await;
+	}
+
 	async fn update_active_session(
 		&mut self,
 		next: Option<SessionId>,
@@ -1261,8 +4465,12 @@ impl ShiftServer {
 	) {
 		self.pending_input_motion = None;
 		self.current_session = next;
+		self.handover.activated(next);
 		self.prune_expired_awake_sessions().await;
-		self.set_awake_sessions(next.into_iter()).await;
+		// Sessions stay asleep while there is no device to present on or the outputs are idle.
+		if !self.render_device_lost && !self.idle.is_idle() {
+			self.set_awake_sessions(self.foreground_sessions()).await;
+		}
 		if let Some(active_session_id) = next {
 			let target_clients = self
 				.connected_clients
@@ -1288,5 +4496,57 @@ impl ShiftServer {
 		{
 			tracing::error!("failed to notify renderer about active session change: {e}");
 		}
+		self.sync_screencast_pauses().await;
+		self.sync_audio_policy();
+		self.refresh_watermark(false).await;
+		self.send_cursor().await;
+	}
+
+	/// Shows the current session's cursor at the pointer, or hides it.
+	async fn send_cursor(&mut self) {
+		let Some((monitor_id, _)) = self.pointer_monitor() else {
+			return;
+		};
+		let image = self
+			.current_session
+			.and_then(|session_id| self.cursors.image(session_id))
+			.cloned();
+		let visible = image.is_some();
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::SetCursor { monitor_id, image })
+			.await
+		{
+			tracing::error!("failed to forward cursor to renderer: {e}");
+		}
+		if visible {
+			self.move_cursor().await;
+		}
+	}
+
+	/// Moves the current session's cursor to the pointer.
+	async fn move_cursor(&mut self) {
+		let shown = self
+			.current_session
+			.is_some_and(|session_id| self.cursors.image(session_id).is_some());
+		let Some((monitor_id, _)) = self.pointer_monitor().filter(|_| shown) else {
+			return;
+		};
+		let (x, y) = self.overlays.pointer();
+		if let Err(e) = self
+			.render_commands
+			.send(RenderCmd::MoveCursor { monitor_id, x, y })
+			.await
+		{
+			tracing::error!("failed to forward cursor position to renderer: {e}");
+		}
+	}
+
+	fn sync_audio_policy(&mut self) {
+		let awake_sessions = &self.awake_sessions;
+		self.audio_policy.sync(
+			|session_id| awake_sessions.contains(&session_id),
+			self.current_session,
+		);
 	}
 }
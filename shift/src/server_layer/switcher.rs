@@ -0,0 +1,212 @@
+//! Built-in session switcher, for systems without an admin shell to switch sessions.
+//!
+//! `Super+Tab` opens it over the active session with the next switchable session selected;
+//! further `Tab` presses (`Shift+Tab` backwards) move the selection while `Super` is held, and
+//! releasing `Super` switches to it. `Esc` closes it without switching. The renderer draws the
+//! list with live thumbnails. `SHIFT_SWITCHER_KEYBINDINGS=0` disables the combo.
+
+use std::collections::HashSet;
+
+use tab_protocol::{InputEventPayload, KeyState};
+
+use crate::sessions::SessionId;
+
+// Linux input event codes.
+const KEY_ESC: u32 = 1;
+const KEY_TAB: u32 = 15;
+const KEY_LEFTSHIFT: u32 = 42;
+const KEY_RIGHTSHIFT: u32 = 54;
+const KEY_LEFTMETA: u32 = 125;
+const KEY_RIGHTMETA: u32 = 126;
+
+/// What the server should do after feeding an input event to the switcher.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SwitcherInput {
+	/// The event drove the switcher and must not reach the session.
+	pub consumed: bool,
+	/// The switcher opened, closed or moved its selection.
+	pub changed: bool,
+	/// Session to switch to now that the switcher closed.
+	pub commit: Option<SessionId>,
+}
+
+#[derive(Debug)]
+struct Open {
+	entries: Vec<SessionId>,
+	selected: usize,
+}
+
+#[derive(Debug)]
+pub(super) struct Switcher {
+	keybindings: bool,
+	meta_held: u8,
+	shift_held: u8,
+	/// Keys whose press was consumed, so their release is swallowed too.
+	consumed_keys: HashSet<u32>,
+	open: Option<Open>,
+}
+
+impl Switcher {
+	pub fn new(keybindings: bool) -> Self {
+		Self {
+			keybindings,
+			meta_held: 0,
+			shift_held: 0,
+			consumed_keys: HashSet::new(),
+			open: None,
+		}
+	}
+
+	/// Sessions listed while open, and the selected index.
+	pub fn view(&self) -> Option<(&[SessionId], usize)> {
+		self
+			.open
+			.as_ref()
+			.map(|open| (open.entries.as_slice(), open.selected))
+	}
+
+	/// Drops a session that went away; `true` when the open switcher changed.
+	pub fn forget_session(&mut self, session_id: SessionId) -> bool {
+		let Some(open) = self.open.as_mut() else {
+			return false;
+		};
+		let Some(index) = open.entries.iter().position(|id| *id == session_id) else {
+			return false;
+		};
+		open.entries.remove(index);
+		if open.entries.len() < 2 {
+			self.open = None;
+		} else if open.selected >= open.entries.len() {
+			open.selected = 0;
+		}
+		true
+	}
+
+	/// `candidates` lists the switchable sessions, the active one first; it is only called when
+	/// the switcher opens.
+	pub fn handle_input(
+		&mut self,
+		event: &InputEventPayload,
+		candidates: impl FnOnce() -> Vec<SessionId>,
+	) -> SwitcherInput {
+		let mut result = SwitcherInput::default();
+		let InputEventPayload::Key { key, state, .. } = event else {
+			return result;
+		};
+		if !self.keybindings {
+			return result;
+		}
+		let pressed = matches!(state, KeyState::Pressed);
+		let held = match *key {
+			KEY_LEFTMETA | KEY_RIGHTMETA => Some(&mut self.meta_held),
+			KEY_LEFTSHIFT | KEY_RIGHTSHIFT => Some(&mut self.shift_held),
+			_ => None,
+		};
+		if let Some(held) = held {
+			*held = if pressed {
+				held.saturating_add(1)
+			} else {
+				held.saturating_sub(1)
+			};
+			if self.meta_held == 0
+				&& let Some(open) = self.open.take()
+			{
+				result.changed = true;
+				result.commit = open.entries.get(open.selected).copied();
+			}
+			return result;
+		}
+		if !pressed {
+			result.consumed = self.consumed_keys.remove(key);
+			return result;
+		}
+		match *key {
+			KEY_TAB if self.meta_held > 0 => {
+				let backwards = self.shift_held > 0;
+				match self.open.as_mut() {
+					Some(open) => {
+						let len = open.entries.len();
+						open.selected = if backwards {
+							(open.selected + len - 1) % len
+						} else {
+							(open.selected + 1) % len
+						};
+					}
+					None => {
+						let entries = candidates();
+						if entries.len() < 2 {
+							self.consumed_keys.insert(*key);
+							result.consumed = true;
+							return result;
+						}
+						let selected = if backwards { entries.len() - 1 } else { 1 };
+						self.open = Some(Open { entries, selected });
+					}
+				}
+				result.changed = true;
+			}
+			KEY_ESC if self.open.is_some() => {
+				self.open = None;
+				result.changed = true;
+			}
+			_ => return result,
+		}
+		self.consumed_keys.insert(*key);
+		result.consumed = true;
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(key: u32, state: KeyState) -> InputEventPayload {
+		InputEventPayload::Key {
+			device: 0,
+			time_usec: 0,
+			key,
+			state,
+		}
+	}
+
+	fn session(n: u32) -> SessionId {
+		format!("se_{n}").parse().expect("session id")
+	}
+
+	#[test]
+	fn super_tab_cycles_and_commits_on_super_release() {
+		let mut switcher = Switcher::new(true);
+		let sessions = || vec![session(1), session(2), session(3)];
+		switcher.handle_input(&key(KEY_LEFTMETA, KeyState::Pressed), sessions);
+		let open = switcher.handle_input(&key(KEY_TAB, KeyState::Pressed), sessions);
+		assert!(open.consumed && open.changed);
+		assert_eq!(switcher.view().map(|(_, selected)| selected), Some(1));
+		let release = switcher.handle_input(&key(KEY_TAB, KeyState::Released), sessions);
+		assert!(
+			release.consumed,
+			"release of a consumed press must be swallowed"
+		);
+		switcher.handle_input(&key(KEY_TAB, KeyState::Pressed), sessions);
+		let commit = switcher.handle_input(&key(KEY_LEFTMETA, KeyState::Released), sessions);
+		assert_eq!(commit.commit, Some(session(3)));
+		assert!(switcher.view().is_none());
+	}
+
+	#[test]
+	fn escape_cancels_and_plain_tab_passes_through() {
+		let mut switcher = Switcher::new(true);
+		let sessions = || vec![session(1), session(2)];
+		let plain = switcher.handle_input(&key(KEY_TAB, KeyState::Pressed), sessions);
+		assert!(!plain.consumed);
+		switcher.handle_input(&key(KEY_RIGHTMETA, KeyState::Pressed), sessions);
+		switcher.handle_input(&key(KEY_TAB, KeyState::Pressed), sessions);
+		assert!(
+			switcher
+				.handle_input(&key(KEY_ESC, KeyState::Pressed), sessions)
+				.consumed
+		);
+		let release = switcher.handle_input(&key(KEY_RIGHTMETA, KeyState::Released), sessions);
+		assert_eq!(release.commit, None);
+	}
+}
@@ -0,0 +1,186 @@
+//! Virtual monitors admins create with `virtual_monitor_create`.
+//!
+//! A virtual monitor belongs to one session, the only client told about it. The renderer
+//! composes what that session presents there offscreen, for screenshots and streaming; nothing
+//! is scanned out. Virtual monitors stay out of `ShiftServer::monitors`, so they never become
+//! primary or part of a monitor profile, and they go away with their session.
+//!
+//! A mirror is a virtual monitor showing what its session presents on a physical monitor,
+//! scaled to the mirror's own size and paced at its own rate, e.g. to stream a session at
+//! 720p while it keeps drawing at the panel's resolution. The session isn't told about mirrors
+//! and links no buffers to them.
+
+use std::collections::HashMap;
+
+use tab_protocol::{FormatModifiers, MirrorScaling, VirtualMonitorCreatePayload};
+
+use crate::{
+	monitor::{Monitor, MonitorId},
+	sessions::SessionId,
+};
+
+#[derive(Debug, Default)]
+pub(super) struct VirtualMonitors {
+	monitors: HashMap<MonitorId, VirtualMonitor>,
+	/// Monitors created so far, numbering their names.
+	created: u32,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct VirtualMonitor {
+	pub monitor: Monitor,
+	pub session_id: SessionId,
+	/// Physical monitor a mirror shows the session's frames of.
+	pub mirror: Option<MonitorId>,
+	pub scaling: MirrorScaling,
+}
+
+impl VirtualMonitors {
+	/// Adds a monitor for a validated request. It takes the `formats` the renderer imports, as
+	/// reported with the physical monitors.
+	pub fn create(
+		&mut self,
+		request: &VirtualMonitorCreatePayload,
+		formats: (Vec<u32>, Vec<FormatModifiers>),
+	) -> &VirtualMonitor {
+		self.created += 1;
+		let id = loop {
+			let id = MonitorId::from_raw(rand::random());
+			if !self.monitors.contains_key(&id) {
+				break id;
+			}
+		};
+		let (formats, format_modifiers) = formats;
+		let monitor = Monitor {
+			id,
+			width: request.width as i32,
+			height: request.height as i32,
+			refresh_rate: request.refresh_rate(),
+			connector_id: 0,
+			name: format!("VIRTUAL-{}", self.created),
+			primary: false,
+			formats,
+			scanout_formats: Vec::new(),
+			format_modifiers,
+			hdr_eotfs: Vec::new(),
+			vrr_capable: false,
+			offscreen: true,
+			transform: Default::default(),
+			scale: Default::default(),
+			modes: Vec::new(),
+			position: Default::default(),
+		};
+		self.monitors.entry(id).or_insert(VirtualMonitor {
+			monitor,
+			session_id: request.session_id,
+			mirror: request.mirror,
+			scaling: request.scaling,
+		})
+	}
+
+	pub fn remove(&mut self, monitor_id: MonitorId) -> Option<VirtualMonitor> {
+		self.monitors.remove(&monitor_id)
+	}
+
+	pub fn contains(&self, monitor_id: MonitorId) -> bool {
+		self.monitors.contains_key(&monitor_id)
+	}
+
+	pub fn get(&self, monitor_id: MonitorId) -> Option<&VirtualMonitor> {
+		self.monitors.get(&monitor_id)
+	}
+
+	/// The session `monitor_id` belongs to, if it is a virtual monitor.
+	pub fn session_of(&self, monitor_id: MonitorId) -> Option<SessionId> {
+		self
+			.monitors
+			.get(&monitor_id)
+			.map(|virtual_monitor| virtual_monitor.session_id)
+	}
+
+	/// Sessions with at least one virtual monitor.
+	pub fn sessions(&self) -> impl Iterator<Item = SessionId> {
+		let mut sessions: Vec<_> = self
+			.monitors
+			.values()
+			.map(|virtual_monitor| virtual_monitor.session_id)
+			.collect();
+		sessions.sort_unstable();
+		sessions.dedup();
+		sessions.into_iter()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &VirtualMonitor> {
+		self.monitors.values()
+	}
+
+	/// Removes and returns the monitors of `session_id`.
+	pub fn remove_session(&mut self, session_id: SessionId) -> Vec<VirtualMonitor> {
+		let ids: Vec<_> = self
+			.monitors
+			.iter()
+			.filter(|(_, virtual_monitor)| virtual_monitor.session_id == session_id)
+			.map(|(id, _)| *id)
+			.collect();
+		ids
+			.into_iter()
+			.filter_map(|id| self.monitors.remove(&id))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn request(session_id: SessionId) -> VirtualMonitorCreatePayload {
+		VirtualMonitorCreatePayload {
+			session_id,
+			width: 1280,
+			height: 720,
+			refresh_rate: None,
+			mirror: None,
+			scaling: MirrorScaling::Fit,
+		}
+	}
+
+	#[test]
+	fn created_monitors_are_offscreen_and_distinct() {
+		let session_id = SessionId::from_raw(1);
+		let mut monitors = VirtualMonitors::default();
+		let first = monitors
+			.create(&request(session_id), (vec![1, 2], Vec::new()))
+			.monitor
+			.clone();
+		let second = monitors
+			.create(&request(session_id), Default::default())
+			.monitor
+			.clone();
+		assert_ne!(first.id, second.id);
+		assert_ne!(first.name, second.name);
+		assert!(first.offscreen && !first.primary);
+		assert!(first.scanout_formats.is_empty(), "never scanned out");
+		assert_eq!(first.formats, vec![1, 2]);
+		assert_eq!(
+			(first.width, first.height, first.refresh_rate),
+			(1280, 720, 60)
+		);
+		assert_eq!(monitors.session_of(first.id), Some(session_id));
+		assert_eq!(monitors.sessions().count(), 1);
+	}
+
+	#[test]
+	fn monitors_go_away_with_their_session() {
+		let (kept, removed) = (SessionId::from_raw(1), SessionId::from_raw(2));
+		let mut monitors = VirtualMonitors::default();
+		let kept_id = monitors
+			.create(&request(kept), Default::default())
+			.monitor
+			.id;
+		monitors.create(&request(removed), Default::default());
+		monitors.create(&request(removed), Default::default());
+		assert_eq!(monitors.remove_session(removed).len(), 2);
+		assert!(monitors.contains(kept_id));
+		assert_eq!(monitors.sessions().collect::<Vec<_>>(), vec![kept]);
+	}
+}
@@ -0,0 +1,86 @@
+//! Which identity watermark the renderer should stamp on the outputs.
+//!
+//! Configured per role: `SHIFT_WATERMARK_ADMIN` and `SHIFT_WATERMARK_SESSION` stamp the active
+//! session's display name (both off by default), `SHIFT_WATERMARK_REMOTE_CONTROL` (on by
+//! default) replaces it with a banner while an admin controls input remotely, and
+//! `SHIFT_WATERMARK_CORNER` picks the corner (`bottom-right` by default).
+
+use std::sync::Arc;
+
+use crate::{comms::server2render::ScreenCorner, input_layer::env_bool, sessions::Role};
+
+pub(super) const REMOTE_CONTROL_BANNER: &str = "REMOTE CONTROL ACTIVE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct WatermarkConfig {
+	pub admin: bool,
+	pub session: bool,
+	pub remote_control: bool,
+	pub corner: ScreenCorner,
+}
+
+impl WatermarkConfig {
+	pub fn from_env() -> Self {
+		let corner = match std::env::var("SHIFT_WATERMARK_CORNER").as_deref() {
+			Ok("top-left") => ScreenCorner::TopLeft,
+			Ok("top-right") => ScreenCorner::TopRight,
+			Ok("bottom-left") => ScreenCorner::BottomLeft,
+			Ok("bottom-right") | Err(_) => ScreenCorner::BottomRight,
+			Ok(other) => {
+				tracing::warn!(
+					value = other,
+					"unknown SHIFT_WATERMARK_CORNER, using bottom-right"
+				);
+				ScreenCorner::BottomRight
+			}
+		};
+		Self {
+			admin: env_bool("SHIFT_WATERMARK_ADMIN", false),
+			session: env_bool("SHIFT_WATERMARK_SESSION", false),
+			remote_control: env_bool("SHIFT_WATERMARK_REMOTE_CONTROL", true),
+			corner,
+		}
+	}
+
+	/// Text to stamp for the active session, if any.
+	pub fn text_for(
+		&self,
+		active: Option<(Role, &str)>,
+		remote_control_active: bool,
+	) -> Option<Arc<str>> {
+		if remote_control_active && self.remote_control {
+			return Some(REMOTE_CONTROL_BANNER.into());
+		}
+		let (role, display_name) = active?;
+		let enabled = match role {
+			Role::Admin => self.admin,
+			Role::Normal | Role::Overlay => self.session,
+		};
+		enabled.then(|| display_name.into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn remote_banner_overrides_per_role_name() {
+		let config = WatermarkConfig {
+			admin: false,
+			session: true,
+			remote_control: true,
+			corner: ScreenCorner::BottomRight,
+		};
+		assert_eq!(
+			config.text_for(Some((Role::Normal, "Desktop")), false),
+			Some("Desktop".into())
+		);
+		assert_eq!(config.text_for(Some((Role::Admin, "Shell")), false), None);
+		assert_eq!(
+			config.text_for(Some((Role::Admin, "Shell")), true),
+			Some(REMOTE_CONTROL_BANNER.into())
+		);
+		assert_eq!(config.text_for(None, false), None);
+	}
+}
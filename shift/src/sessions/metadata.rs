@@ -0,0 +1,103 @@
+//! Limits on the metadata an admin attaches to a session, so a session cannot be handed an
+//! environment its shell cannot apply, and on the labels sessions give themselves.
+
+use tab_protocol::{SessionMetadata, SessionUpdatePayload};
+
+const MAX_ENTRIES: usize = 128;
+const MAX_KEY_LEN: usize = 256;
+const MAX_VALUE_LEN: usize = 4096;
+const MAX_DISPLAY_NAME_LEN: usize = 256;
+
+pub fn validate(metadata: &SessionMetadata) -> Result<(), String> {
+	for (kind, map) in [
+		("attribute", &metadata.attributes),
+		("environment variable", &metadata.environment),
+	] {
+		if map.len() > MAX_ENTRIES {
+			return Err(format!("more than {MAX_ENTRIES} {kind}s"));
+		}
+		for (key, value) in map {
+			if key.is_empty() || key.len() > MAX_KEY_LEN {
+				return Err(format!("{kind} name must be 1..={MAX_KEY_LEN} bytes"));
+			}
+			if value.len() > MAX_VALUE_LEN {
+				return Err(format!(
+					"{kind} `{key}` is longer than {MAX_VALUE_LEN} bytes"
+				));
+			}
+		}
+	}
+	for (name, value) in &metadata.environment {
+		if !is_env_name(name) {
+			return Err(format!("`{name}` is not a valid environment variable name"));
+		}
+		if value.contains('\0') {
+			return Err(format!("environment variable `{name}` contains a NUL byte"));
+		}
+	}
+	Ok(())
+}
+
+/// Checks a session relabeling itself with `session_update`.
+pub fn validate_update(update: &SessionUpdatePayload) -> Result<(), String> {
+	if let Some(name) = &update.display_name {
+		validate_display_name(name)?;
+	}
+	if let Some(attributes) = &update.attributes {
+		validate(&SessionMetadata {
+			attributes: attributes.clone(),
+			..SessionMetadata::default()
+		})?;
+	}
+	Ok(())
+}
+
+/// Display names end up in switcher UIs and the watermark, so they must fit on one line.
+fn validate_display_name(name: &str) -> Result<(), String> {
+	if name.trim().is_empty() || name.len() > MAX_DISPLAY_NAME_LEN {
+		return Err(format!(
+			"display name must be 1..={MAX_DISPLAY_NAME_LEN} bytes and not blank"
+		));
+	}
+	if name.chars().any(char::is_control) {
+		return Err("display name contains control characters".into());
+	}
+	Ok(())
+}
+
+fn is_env_name(name: &str) -> bool {
+	let mut chars = name.chars();
+	chars
+		.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_bad_environment_names() {
+		let mut metadata = SessionMetadata::default();
+		metadata
+			.attributes
+			.insert("locale".into(), "pt_BR.UTF-8".into());
+		metadata
+			.environment
+			.insert("LANG".into(), "pt_BR.UTF-8".into());
+		assert!(validate(&metadata).is_ok());
+		metadata.environment.insert("1BAD".into(), String::new());
+		assert!(validate(&metadata).is_err());
+		metadata.environment.remove("1BAD");
+		metadata.environment.insert("A=B".into(), String::new());
+		assert!(validate(&metadata).is_err());
+	}
+	#[test]
+	fn display_names_stay_on_one_line() {
+		assert!(validate_display_name("Firefox — 3 tabs").is_ok());
+		assert!(validate_display_name("  ").is_err());
+		assert!(validate_display_name("two\nlines").is_err());
+		assert!(validate_display_name(&"x".repeat(MAX_DISPLAY_NAME_LEN + 1)).is_err());
+	}
+}
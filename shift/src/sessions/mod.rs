@@ -1,6 +1,8 @@
 use crate::define_id_type;
 pub use role::Role;
+pub mod metadata;
 mod pending_sessions;
+pub mod permissions;
 mod role;
 mod session;
 pub use pending_sessions::PendingSession;
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
+use tab_protocol::SessionMetadata;
 
 use crate::{auth::Token, sessions::Session};
 
@@ -12,6 +13,7 @@ pub struct PendingSession {
 	role: Role,
 	created_at: DateTime<Utc>,
 	display_name: Option<Arc<str>>,
+	metadata: Arc<SessionMetadata>,
 }
 impl PendingSession {
 	pub fn id(&self) -> SessionId {
@@ -29,14 +31,20 @@ impl PendingSession {
 		(
 			Token::generate().expect("getrandom to be available"),
 			Self {
-				id: SessionId::rand(),
+				id: SessionId::from_raw(rand::random()),
 				role,
 				created_at: Utc::now(),
 				display_name,
+				metadata: Default::default(),
 			},
 		)
 	}
 
+	pub fn with_metadata(mut self, metadata: SessionMetadata) -> Self {
+		self.metadata = Arc::new(metadata);
+		self
+	}
+
 	pub fn admin(display_name: Option<Arc<str>>) -> (Token, Self) {
 		Self::new(display_name, Role::Admin)
 	}
@@ -48,12 +56,13 @@ impl PendingSession {
 		Session {
 			id: self.id,
 			role: self.role,
-			ready: self.role == Role::Admin,
+			ready: self.role != Role::Normal,
 			display_name: self
 				.display_name
 				.as_ref()
 				.map(Arc::clone)
 				.unwrap_or_else(|| self.default_session_name().into()),
+			metadata: self.metadata,
 		}
 	}
 	pub fn default_session_name(&self) -> String {
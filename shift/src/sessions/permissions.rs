@@ -0,0 +1,298 @@
+//! Which protocol commands each session role may use.
+//!
+//! Every command that needs authentication maps to one [`Permission`]; [`allows`] is the single
+//! table deciding whether a role holds it. The client layer checks it before forwarding a
+//! command, and the server checks it again before acting. Denials are logged under the
+//! `audit` target.
+//!
+//! The socket a client connected through may narrow this further, see [`SocketRestrictions`].
+
+use std::fmt::Display;
+
+use tab_protocol::TabMessage;
+
+use super::{Role, SessionId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+	/// Link buffers, request flips and tune how they are sampled.
+	Present,
+	/// Keep the system from going idle while active.
+	IdleInhibit,
+	/// Read the metadata attached to the own session, relabel it and name its audio clients.
+	OwnMetadata,
+	/// Create sessions, switch between them and read their metadata.
+	SessionManagement,
+	/// Inject input and control monitors remotely.
+	InputInjection,
+	/// Record monitor contents.
+	ScreenCapture,
+	/// Share monitor contents with other programs as a PipeWire stream.
+	Screencast,
+	/// Enable monitors, pick the primary one and adjust their output.
+	DisplayConfiguration,
+	/// Color filters and the magnifier.
+	Accessibility,
+	/// Frame tracing and reading connector properties.
+	Diagnostics,
+	/// Declare the regions an overlay occupies on top of the active session.
+	OverlayRegions,
+}
+
+impl Permission {
+	pub const ALL: [Self; 11] = [
+		Self::Present,
+		Self::IdleInhibit,
+		Self::OwnMetadata,
+		Self::SessionManagement,
+		Self::InputInjection,
+		Self::ScreenCapture,
+		Self::Screencast,
+		Self::DisplayConfiguration,
+		Self::Accessibility,
+		Self::Diagnostics,
+		Self::OverlayRegions,
+	];
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|p| p.as_str() == name)
+	}
+
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Present => "present",
+			Self::IdleInhibit => "idle_inhibit",
+			Self::OwnMetadata => "own_metadata",
+			Self::SessionManagement => "session_management",
+			Self::InputInjection => "input_injection",
+			Self::ScreenCapture => "screen_capture",
+			Self::Screencast => "screencast",
+			Self::DisplayConfiguration => "display_configuration",
+			Self::Accessibility => "accessibility",
+			Self::Diagnostics => "diagnostics",
+			Self::OverlayRegions => "overlay_regions",
+		}
+	}
+}
+
+impl Display for Permission {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// The permission matrix.
+pub const fn allows(role: Role, permission: Permission) -> bool {
+	match role {
+		Role::Admin => !matches!(permission, Permission::OverlayRegions),
+		Role::Normal => matches!(
+			permission,
+			Permission::Present
+				| Permission::IdleInhibit
+				| Permission::OwnMetadata
+				| Permission::Screencast
+		),
+		Role::Overlay => matches!(
+			permission,
+			Permission::Present | Permission::OwnMetadata | Permission::OverlayRegions
+		),
+	}
+}
+
+/// What clients connecting through one of Shift's sockets may do. The default restricts nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketRestrictions {
+	/// Roles whose tokens the socket accepts, all when `None`.
+	pub roles: Option<Vec<Role>>,
+	/// Permissions usable through the socket, on top of the role's, all when `None`.
+	pub permissions: Option<Vec<Permission>>,
+}
+
+impl SocketRestrictions {
+	pub fn admits(&self, role: Role) -> bool {
+		self
+			.roles
+			.as_ref()
+			.is_none_or(|roles| roles.contains(&role))
+	}
+
+	pub fn allows(&self, permission: Permission) -> bool {
+		self
+			.permissions
+			.as_ref()
+			.is_none_or(|permissions| permissions.contains(&permission))
+	}
+}
+
+/// The permission a client command needs, or `None` for messages that are allowed before
+/// authentication or that clients never send.
+pub fn required_for(message: &TabMessage) -> Option<Permission> {
+	Some(match message {
+		TabMessage::FramebufferLink { .. }
+		| TabMessage::BufferRequest { .. }
+		| TabMessage::RenderQuality(_)
+		| TabMessage::LatencyMode(_)
+		| TabMessage::SetCursor { .. } => Permission::Present,
+		TabMessage::IdleInhibit(_) => Permission::IdleInhibit,
+		TabMessage::GetSessionMetadata(_)
+		| TabMessage::SessionUpdate(_)
+		| TabMessage::AudioClients(_) => Permission::OwnMetadata,
+		TabMessage::SessionCreate(_) | TabMessage::SessionSwitch(_) => Permission::SessionManagement,
+		TabMessage::InputInject(_) | TabMessage::RemoteControl(_) => Permission::InputInjection,
+		TabMessage::ScreenRecord(_) | TabMessage::Screenshot(_) => Permission::ScreenCapture,
+		TabMessage::ScreencastStart(_) | TabMessage::ScreencastStop(_) => Permission::Screencast,
+		TabMessage::DisplayAdjust(_)
+		| TabMessage::MonitorEnable(_)
+		| TabMessage::SetPrimaryMonitor(_)
+		| TabMessage::MonitorProfile(_)
+		| TabMessage::SetWallpaper(_)
+		| TabMessage::SetColorProfile(_)
+		| TabMessage::SetVrr(_)
+		| TabMessage::SetOutputTransform(_)
+		| TabMessage::SetMonitorScale(_)
+		| TabMessage::SetMonitorMode(_)
+		| TabMessage::SetMonitorPosition(_)
+		| TabMessage::SetColorTemperature(_)
+		| TabMessage::VirtualMonitorCreate(_)
+		| TabMessage::VirtualMonitorRemove(_) => Permission::DisplayConfiguration,
+		TabMessage::ColorFilter(_) | TabMessage::Magnifier(_) => Permission::Accessibility,
+		TabMessage::FrameTrace(_) | TabMessage::GetConnectorProperties(_) => Permission::Diagnostics,
+		TabMessage::OverlayRegions(_) => Permission::OverlayRegions,
+		// The handshake and keepalives come before there is a session to check.
+		TabMessage::Hello(_) | TabMessage::Auth(_) | TabMessage::Ping | TabMessage::Pong => {
+			return None;
+		}
+		// Every session announces readiness; the server ignores it from unauthenticated clients.
+		TabMessage::SessionReady(_) => return None,
+		// Sent by the server only; the client layer rejects them as unknown messages.
+		TabMessage::AuthOk(_)
+		| TabMessage::AuthError(_)
+		| TabMessage::BufferRequestAck(_)
+		| TabMessage::BufferRelease { .. }
+		| TabMessage::InputEvent(_)
+		| TabMessage::MonitorAdded(_)
+		| TabMessage::MonitorRemoved(_)
+		| TabMessage::SessionCreated(_)
+		| TabMessage::SessionState(_)
+		| TabMessage::SessionActive(_)
+		| TabMessage::SessionAwake(_)
+		| TabMessage::SessionSleep(_)
+		| TabMessage::ReleaseBuffers(_)
+		| TabMessage::Error(_)
+		| TabMessage::FrameStats(_)
+		| TabMessage::RemoteControlState(_)
+		| TabMessage::PrimaryMonitor(_)
+		| TabMessage::RenderCacheStats(_)
+		| TabMessage::SessionMetadata(_)
+		| TabMessage::MonitorResized(_)
+		| TabMessage::MonitorModeChanged(_)
+		| TabMessage::MonitorLayout(_)
+		| TabMessage::ConnectorProperties(_)
+		| TabMessage::BufferHash(_)
+		| TabMessage::SessionFrameStats(_)
+		| TabMessage::PresentationFeedback(_)
+		| TabMessage::ChannelStats(_)
+		| TabMessage::ScreenshotReady { .. }
+		| TabMessage::VirtualMonitorCreated(_)
+		| TabMessage::ScreencastStarted(_)
+		| TabMessage::ScreencastStopped(_)
+		| TabMessage::Unknown(_) => return None,
+	})
+}
+
+/// Checks `permission` for a client authenticated as `session` (if at all) that connected
+/// through a socket with `socket` restrictions, audit-logging denials.
+pub fn check(
+	client: impl Display,
+	session: Option<(SessionId, Role)>,
+	socket: &SocketRestrictions,
+	permission: Permission,
+) -> bool {
+	let granted =
+		session.is_some_and(|(_, role)| allows(role, permission)) && socket.allows(permission);
+	if !granted {
+		tracing::warn!(
+			target: "audit",
+			%client,
+			session = session.map(|(id, _)| id.to_string()),
+			role = ?session.map(|(_, role)| role),
+			socket_restricted = !socket.allows(permission),
+			%permission,
+			"permission denied"
+		);
+	}
+	granted
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sessions_cannot_use_admin_commands() {
+		for permission in [
+			Permission::SessionManagement,
+			Permission::InputInjection,
+			Permission::ScreenCapture,
+			Permission::DisplayConfiguration,
+			Permission::Accessibility,
+			Permission::Diagnostics,
+		] {
+			assert!(!allows(Role::Normal, permission), "{permission}");
+			assert!(allows(Role::Admin, permission), "{permission}");
+		}
+		assert!(allows(Role::Normal, Permission::Present));
+		assert!(allows(Role::Normal, Permission::Screencast));
+		assert!(!allows(Role::Overlay, Permission::Screencast));
+		assert!(!check(
+			"cl_test",
+			None,
+			&SocketRestrictions::default(),
+			Permission::Present
+		));
+	}
+
+	#[test]
+	fn overlays_only_present_in_their_regions() {
+		assert!(allows(Role::Overlay, Permission::Present));
+		assert!(allows(Role::Overlay, Permission::OverlayRegions));
+		assert!(!allows(Role::Overlay, Permission::IdleInhibit));
+		assert!(!allows(Role::Overlay, Permission::SessionManagement));
+		assert!(!allows(Role::Normal, Permission::OverlayRegions));
+		assert!(!allows(Role::Admin, Permission::OverlayRegions));
+	}
+
+	#[test]
+	fn sockets_narrow_role_permissions() {
+		let session = Some((SessionId::from_raw(rand::random()), Role::Admin));
+		let socket = SocketRestrictions {
+			roles: Some(vec![Role::Admin]),
+			permissions: Some(vec![Permission::Present, Permission::SessionManagement]),
+		};
+		assert!(socket.admits(Role::Admin));
+		assert!(!socket.admits(Role::Normal));
+		assert!(check(
+			"cl_test",
+			session,
+			&socket,
+			Permission::SessionManagement
+		));
+		assert!(!check(
+			"cl_test",
+			session,
+			&socket,
+			Permission::ScreenCapture
+		));
+		assert!(check(
+			"cl_test",
+			session,
+			&SocketRestrictions::default(),
+			Permission::ScreenCapture
+		));
+		assert_eq!(
+			Permission::from_name("screen_capture"),
+			Some(Permission::ScreenCapture)
+		);
+		assert_eq!(Permission::from_name("root"), None);
+	}
+}
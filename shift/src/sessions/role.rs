@@ -5,13 +5,20 @@ use tab_protocol::SessionRole;
 pub enum Role {
 	Normal = 0,
 	Admin = 1,
+	/// Composited above the active session in the regions it declares.
+	Overlay = 2,
 }
 
-impl From<SessionRole> for Role {
-	fn from(value: SessionRole) -> Self {
+/// Fails for [`SessionRole::Unknown`], a role this build doesn't implement.
+impl TryFrom<SessionRole> for Role {
+	type Error = ();
+
+	fn try_from(value: SessionRole) -> Result<Self, ()> {
 		match value {
-			SessionRole::Admin => Self::Admin,
-			SessionRole::Session => Self::Normal,
+			SessionRole::Admin => Ok(Self::Admin),
+			SessionRole::Session => Ok(Self::Normal),
+			SessionRole::Overlay => Ok(Self::Overlay),
+			SessionRole::Unknown => Err(()),
 		}
 	}
 }
@@ -21,6 +28,7 @@ impl From<Role> for SessionRole {
 		match value {
 			Role::Normal => Self::Session,
 			Role::Admin => Self::Admin,
+			Role::Overlay => Self::Overlay,
 		}
 	}
 }
@@ -1,8 +1,10 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use crate::{define_id_type, sessions::Role};
+use tab_protocol::SessionMetadata;
 
-define_id_type!(Session, "se_");
+pub use tab_protocol::SessionId;
+
+use crate::sessions::Role;
 
 #[derive(Clone, Debug)]
 pub struct Session {
@@ -10,6 +12,7 @@ pub struct Session {
 	pub(super) role: Role,
 	pub(super) ready: bool,
 	pub(super) display_name: Arc<str>,
+	pub(super) metadata: Arc<SessionMetadata>,
 }
 
 impl Session {
@@ -18,6 +21,23 @@ impl Session {
 		cloned.ready = ready;
 		cloned
 	}
+	/// A copy relabeled by the session itself; `None` keeps the current value.
+	pub fn with_label(
+		&self,
+		display_name: Option<&str>,
+		attributes: Option<BTreeMap<String, String>>,
+	) -> Self {
+		let mut cloned = self.clone();
+		if let Some(display_name) = display_name {
+			cloned.display_name = Arc::from(display_name);
+		}
+		if let Some(attributes) = attributes {
+			let mut metadata = SessionMetadata::clone(&self.metadata);
+			metadata.attributes = attributes;
+			cloned.metadata = Arc::new(metadata);
+		}
+		cloned
+	}
 	pub fn id(&self) -> SessionId {
 		self.id
 	}
@@ -30,4 +50,7 @@ impl Session {
 	pub fn display_name(&self) -> &str {
 		&self.display_name
 	}
+	pub fn metadata(&self) -> &SessionMetadata {
+		&self.metadata
+	}
 }
@@ -0,0 +1,56 @@
+//! Startup phase timing, so time-to-first-frame on boot can be read straight from the logs.
+
+use std::{
+	sync::{
+		OnceLock,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+static FIRST_FRAME: AtomicBool = AtomicBool::new(false);
+
+/// Call first thing in `main`; later phases are measured from here.
+pub fn mark_process_start() {
+	PROCESS_START.get_or_init(Instant::now);
+}
+
+pub fn since_start() -> Duration {
+	PROCESS_START.get_or_init(Instant::now).elapsed()
+}
+
+/// Logs how long a startup phase took once dropped.
+#[must_use]
+pub struct Phase {
+	name: &'static str,
+	started: Instant,
+}
+
+pub fn phase(name: &'static str) -> Phase {
+	Phase {
+		name,
+		started: Instant::now(),
+	}
+}
+
+impl Drop for Phase {
+	fn drop(&mut self) {
+		tracing::info!(
+			phase = self.name,
+			elapsed_ms = self.started.elapsed().as_secs_f64() * 1000.0,
+			since_start_ms = since_start().as_secs_f64() * 1000.0,
+			"startup phase done"
+		);
+	}
+}
+
+/// Records the first committed frame of the process; later calls do nothing.
+pub fn first_frame_committed() {
+	if !FIRST_FRAME.swap(true, Ordering::Relaxed) {
+		tracing::info!(
+			since_start_ms = since_start().as_secs_f64() * 1000.0,
+			"first frame committed"
+		);
+	}
+}
@@ -368,6 +368,9 @@ typedef struct {
 typedef enum {
     TAB_SESSION_ROLE_ADMIN = 0,
     TAB_SESSION_ROLE_SESSION = 1,
+    TAB_SESSION_ROLE_OVERLAY = 2,
+    /* A role added by a newer Shift. */
+    TAB_SESSION_ROLE_UNKNOWN = 3,
 } TabSessionRole;
 
 typedef enum {
@@ -375,6 +378,9 @@ typedef enum {
     TAB_SESSION_LIFECYCLE_LOADING = 1,
     TAB_SESSION_LIFECYCLE_OCCUPIED = 2,
     TAB_SESSION_LIFECYCLE_CONSUMED = 3,
+    TAB_SESSION_LIFECYCLE_UNRESPONSIVE = 4,
+    /* A state added by a newer Shift. */
+    TAB_SESSION_LIFECYCLE_UNKNOWN = 5,
 } TabSessionLifecycle;
 
 typedef struct {
@@ -405,6 +411,11 @@ typedef enum {
     TAB_EVENT_SESSION_AWAKE = 6,
     TAB_EVENT_SESSION_SLEEP = 7,
     TAB_EVENT_SESSION_ACTIVE = 8,
+    TAB_EVENT_MONITOR_RESIZED = 9,
+    /* A rejected buffer request was retried after relinking the swapchain. */
+    TAB_EVENT_BUFFER_RECOVERED = 10,
+    /* A buffer request was rejected for good, e.g. the monitor is gone. */
+    TAB_EVENT_BUFFER_FAILED = 11,
 } TabEventType;
 
 typedef struct {
@@ -418,9 +429,15 @@ typedef struct {
     const char *name;
 } TabMonitorRemoved;
 
+typedef struct {
+    const char *monitor_id;
+    const char *reason;
+} TabBufferFailed;
+
 typedef union {
     TabBufferRelease buffer_released;
     TabMonitorInfo monitor_added;
+    TabMonitorInfo monitor_resized;
     TabMonitorRemoved monitor_removed;
     TabSessionInfo session_state;
     const char *session_awake;
@@ -428,6 +445,8 @@ typedef union {
     const char *session_active;
     TabInputEvent input;
     const char *session_created_token;
+    const char *buffer_recovered;
+    TabBufferFailed buffer_failed;
 } TabEventData;
 
 typedef struct {
@@ -476,12 +495,18 @@ TabMonitorInfo tab_client_get_monitor_info(TabClientHandle *handle, const char *
 void tab_client_free_monitor_info(TabMonitorInfo *info);
 TabSessionInfo tab_client_get_session(TabClientHandle *handle);
 void tab_client_free_session_info(TabSessionInfo *session_info);
+/* Keeps the session from being marked unresponsive while it has nothing else to send. */
+bool tab_client_ping(TabClientHandle *handle);
 bool tab_client_send_ready(TabClientHandle *handle);
 bool tab_client_session_create(
     TabClientHandle *handle,
     TabSessionRole role,
     const char *display_name
 );
+/* Relabels the own session; admins see the new name in TAB_EVENT_SESSION_STATE. */
+bool tab_client_set_session_display_name(TabClientHandle *handle, const char *display_name);
+/* Names the PulseAudio/PipeWire clients the session plays through; count 0 unlinks them. */
+bool tab_client_set_audio_clients(TabClientHandle *handle, const uint32_t *client_ids, size_t count);
 bool tab_client_session_switch(
     TabClientHandle *handle,
     const char *session_id,
@@ -504,6 +529,16 @@ bool tab_client_request_buffer(
     const char *monitor_id,
     int acquire_fence_fd
 );
+bool tab_client_request_buffer_at(
+    TabClientHandle *handle,
+    const char *monitor_id,
+    int acquire_fence_fd,
+    uint64_t present_at_ns
+);
+
+/* Waits up to timeout_ms (forever when negative) for a sync file fence the caller keeps owning.
+ * Returns 1 once it signaled, 0 on timeout and -1 on error. */
+int tab_client_fence_wait(int fence_fd, int timeout_ms);
 
 int tab_client_get_swap_fd(TabClientHandle *handle);
 int tab_client_get_socket_fd(TabClientHandle *handle);
@@ -5,9 +5,13 @@ use std::{
 	collections::{HashMap, VecDeque},
 	env,
 	ffi::{CStr, CString},
-	os::raw::{c_char, c_int},
+	os::{
+		fd::{BorrowedFd, IntoRawFd},
+		raw::{c_char, c_int},
+	},
 	ptr,
 	rc::Rc,
+	str::FromStr,
 	time::Duration,
 };
 
@@ -18,10 +22,11 @@ use crate::{
 	events::{InputEvent, MonitorEvent, RenderEvent, SessionEvent},
 	monitor::MonitorState,
 	swapchain::TabSwapchain,
+	sync_fence::{self, SyncFence},
 };
 use tab_protocol::{
 	AxisOrientation, AxisPhase, AxisSource, BufferIndex, ButtonState, InputEventPayload, KeyState,
-	SwitchState, SwitchType, TipState,
+	MonitorId, SessionId, SwitchState, SwitchType, TipState,
 };
 
 #[repr(C)]
@@ -69,6 +74,13 @@ pub struct TabMonitorRemoved {
 	pub name: *mut c_char,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TabBufferFailed {
+	pub monitor_id: *mut c_char,
+	pub reason: *mut c_char,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub enum TabAcquireResult {
@@ -89,6 +101,9 @@ pub enum TabEventType {
 	TAB_EVENT_SESSION_AWAKE = 6,
 	TAB_EVENT_SESSION_SLEEP = 7,
 	TAB_EVENT_SESSION_ACTIVE = 8,
+	TAB_EVENT_MONITOR_RESIZED = 9,
+	TAB_EVENT_BUFFER_RECOVERED = 10,
+	TAB_EVENT_BUFFER_FAILED = 11,
 }
 
 #[repr(C)]
@@ -96,6 +111,8 @@ pub enum TabEventType {
 pub enum TabSessionRole {
 	TAB_SESSION_ROLE_ADMIN = 0,
 	TAB_SESSION_ROLE_SESSION = 1,
+	TAB_SESSION_ROLE_OVERLAY = 2,
+	TAB_SESSION_ROLE_UNKNOWN = 3,
 }
 
 #[repr(C)]
@@ -105,6 +122,8 @@ pub enum TabSessionLifecycle {
 	TAB_SESSION_LIFECYCLE_LOADING = 1,
 	TAB_SESSION_LIFECYCLE_OCCUPIED = 2,
 	TAB_SESSION_LIFECYCLE_CONSUMED = 3,
+	TAB_SESSION_LIFECYCLE_UNRESPONSIVE = 4,
+	TAB_SESSION_LIFECYCLE_UNKNOWN = 5,
 }
 
 #[repr(C)]
@@ -128,6 +147,7 @@ pub struct TabEvent {
 pub union TabEventData {
 	pub buffer_released: TabBufferRelease,
 	pub monitor_added: TabMonitorInfo,
+	pub monitor_resized: TabMonitorInfo,
 	pub monitor_removed: TabMonitorRemoved,
 	pub session_state: TabSessionInfo,
 	pub session_awake: *mut c_char,
@@ -135,6 +155,8 @@ pub union TabEventData {
 	pub session_active: *mut c_char,
 	pub input: TabInputEvent,
 	pub session_created_token: *mut c_char,
+	pub buffer_recovered: *mut c_char,
+	pub buffer_failed: TabBufferFailed,
 }
 
 #[repr(C)]
@@ -457,13 +479,22 @@ struct MonitorEntry {
 }
 
 enum PendingEvent {
-	BufferReleased(String, BufferIndex, Option<c_int>),
+	BufferReleased(MonitorId, BufferIndex, Option<SyncFence>),
 	MonitorAdded(MonitorState),
-	MonitorRemoved { monitor_id: String, name: String },
+	MonitorRemoved {
+		monitor_id: MonitorId,
+		name: String,
+	},
+	MonitorResized(MonitorState),
+	BufferRecovered(MonitorId),
+	BufferFailed {
+		monitor_id: MonitorId,
+		reason: String,
+	},
 	SessionState(tab_protocol::SessionInfo),
-	SessionActive(String),
-	SessionAwake(String),
-	SessionSleep(String),
+	SessionActive(SessionId),
+	SessionAwake(SessionId),
+	SessionSleep(SessionId),
 	SessionCreated(String),
 	Input(InputEventPayload),
 }
@@ -471,8 +502,8 @@ enum PendingEvent {
 pub struct TabClientHandle {
 	client: TabClient,
 	events: Rc<RefCell<VecDeque<PendingEvent>>>,
-	monitors: HashMap<String, MonitorEntry>,
-	monitor_order: Vec<String>,
+	monitors: HashMap<MonitorId, MonitorEntry>,
+	monitor_order: Vec<MonitorId>,
 	last_error: Option<CString>,
 }
 
@@ -488,10 +519,21 @@ impl TabClientHandle {
 					MonitorEvent::Added(state) => guard.push_back(PendingEvent::MonitorAdded(state.clone())),
 					MonitorEvent::Removed { monitor_id, name } => {
 						guard.push_back(PendingEvent::MonitorRemoved {
-							monitor_id: monitor_id.clone(),
+							monitor_id: *monitor_id,
 							name: name.clone(),
 						})
 					}
+					MonitorEvent::Resized { state, .. } => {
+						guard.push_back(PendingEvent::MonitorResized(state.clone()))
+					}
+					MonitorEvent::PrimaryChanged(_)
+					| MonitorEvent::ModeChanged(_)
+					| MonitorEvent::LayoutChanged(_)
+					| MonitorEvent::ConnectorProperties(_)
+					| MonitorEvent::Captured { .. }
+					| MonitorEvent::VirtualCreated(_)
+					| MonitorEvent::ScreencastStarted(_)
+					| MonitorEvent::ScreencastStopped(_) => {}
 				}
 			});
 		}
@@ -503,12 +545,29 @@ impl TabClientHandle {
 					RenderEvent::BufferReleased {
 						monitor_id,
 						buffer,
-						release_fence_fd,
+						release_fence,
 					} => guard.push_back(PendingEvent::BufferReleased(
-						monitor_id.clone(),
+						*monitor_id,
 						*buffer,
-						*release_fence_fd,
+						release_fence
+							.as_ref()
+							.and_then(|fence| fence.try_clone().ok()),
 					)),
+					RenderEvent::Recovered { monitor_id } => {
+						guard.push_back(PendingEvent::BufferRecovered(*monitor_id))
+					}
+					RenderEvent::Failed { monitor_id, reason } => {
+						guard.push_back(PendingEvent::BufferFailed {
+							monitor_id: *monitor_id,
+							reason: reason.clone(),
+						})
+					}
+					RenderEvent::FrameStats(_)
+					| RenderEvent::CacheStats(_)
+					| RenderEvent::SessionFrameStats(_)
+					| RenderEvent::PresentationFeedback(_)
+					| RenderEvent::ChannelStats(_)
+					| RenderEvent::BufferHash { .. } => {}
 				}
 			});
 		}
@@ -518,13 +577,13 @@ impl TabClientHandle {
 				let mut guard = q.borrow_mut();
 				match evt {
 					SessionEvent::Active(session_id) => {
-						guard.push_back(PendingEvent::SessionActive(session_id.clone()))
+						guard.push_back(PendingEvent::SessionActive(*session_id))
 					}
 					SessionEvent::Awake(session_id) => {
-						guard.push_back(PendingEvent::SessionAwake(session_id.clone()))
+						guard.push_back(PendingEvent::SessionAwake(*session_id))
 					}
 					SessionEvent::Sleep(session_id) => {
-						guard.push_back(PendingEvent::SessionSleep(session_id.clone()))
+						guard.push_back(PendingEvent::SessionSleep(*session_id))
 					}
 					SessionEvent::State(session) => {
 						guard.push_back(PendingEvent::SessionState(session.clone()))
@@ -532,6 +591,9 @@ impl TabClientHandle {
 					SessionEvent::Created { token, .. } => {
 						guard.push_back(PendingEvent::SessionCreated(token.clone()))
 					}
+					SessionEvent::ReleaseBuffers(_)
+					| SessionEvent::RemoteControl(_)
+					| SessionEvent::Metadata(_) => {}
 				}
 			});
 		}
@@ -553,13 +615,9 @@ impl TabClientHandle {
 			last_error: None,
 		};
 
-		let monitor_ids: Vec<String> = handle
-			.client
-			.monitors()
-			.map(|m| m.info.id.clone())
-			.collect();
+		let monitor_ids: Vec<MonitorId> = handle.client.monitors().map(|m| m.info.id).collect();
 		for id in monitor_ids {
-			if let Some(state) = handle.client.monitor(&id).cloned() {
+			if let Some(state) = handle.client.monitor(id).cloned() {
 				handle.insert_monitor(state)?;
 			}
 		}
@@ -568,12 +626,12 @@ impl TabClientHandle {
 	}
 
 	fn insert_monitor(&mut self, state: MonitorState) -> Result<(), TabClientError> {
-		let id = state.info.id.clone();
+		let id = state.info.id;
 		if self.monitors.contains_key(&id) {
 			return Ok(());
 		}
-		let swapchain = self.client.create_swapchain(&id)?;
-		self.monitor_order.push(id.clone());
+		let swapchain = self.client.create_swapchain(id)?;
+		self.monitor_order.push(id);
 		self.monitors.insert(
 			id,
 			MonitorEntry {
@@ -585,9 +643,20 @@ impl TabClientHandle {
 		Ok(())
 	}
 
-	fn remove_monitor(&mut self, id: &str) {
-		self.monitors.remove(id);
-		self.monitor_order.retain(|item| item != id);
+	/// Replaces the monitor's swapchain with one of its new size.
+	fn resize_monitor(&mut self, state: MonitorState) -> Result<(), TabClientError> {
+		let Some(entry) = self.monitors.get_mut(&state.info.id) else {
+			return self.insert_monitor(state);
+		};
+		entry.swapchain = self.client.create_swapchain(state.info.id)?;
+		entry.state = state;
+		entry.pending = None;
+		Ok(())
+	}
+
+	fn remove_monitor(&mut self, id: MonitorId) {
+		self.monitors.remove(&id);
+		self.monitor_order.retain(|item| *item != id);
 	}
 
 	fn record_error(&mut self, err: impl ToString) {
@@ -613,13 +682,18 @@ fn cstring_to_string(ptr: *const c_char) -> Option<String> {
 		.map(|s| s.to_string())
 }
 
+/// Parses a C string id; `None` for a null pointer or an id of the wrong kind.
+fn cstring_to_id<T: FromStr>(ptr: *const c_char) -> Option<T> {
+	cstring_to_string(ptr).and_then(|s| s.parse().ok())
+}
+
 fn resolve_token(token: *const c_char) -> Option<String> {
 	cstring_to_string(token).or_else(|| env::var("SHIFT_SESSION_TOKEN").ok())
 }
 
 fn monitor_info_to_c(state: &MonitorState) -> TabMonitorInfo {
 	TabMonitorInfo {
-		id: dup_string(&state.info.id),
+		id: dup_string(&state.info.id.to_string()),
 		width: state.info.width,
 		height: state.info.height,
 		refresh_rate: state.info.refresh_rate,
@@ -631,6 +705,8 @@ fn tab_session_role(role: tab_protocol::SessionRole) -> TabSessionRole {
 	match role {
 		tab_protocol::SessionRole::Admin => TabSessionRole::TAB_SESSION_ROLE_ADMIN,
 		tab_protocol::SessionRole::Session => TabSessionRole::TAB_SESSION_ROLE_SESSION,
+		tab_protocol::SessionRole::Overlay => TabSessionRole::TAB_SESSION_ROLE_OVERLAY,
+		tab_protocol::SessionRole::Unknown => TabSessionRole::TAB_SESSION_ROLE_UNKNOWN,
 	}
 }
 
@@ -640,12 +716,16 @@ fn tab_session_lifecycle(lifecycle: tab_protocol::SessionLifecycle) -> TabSessio
 		tab_protocol::SessionLifecycle::Loading => TabSessionLifecycle::TAB_SESSION_LIFECYCLE_LOADING,
 		tab_protocol::SessionLifecycle::Occupied => TabSessionLifecycle::TAB_SESSION_LIFECYCLE_OCCUPIED,
 		tab_protocol::SessionLifecycle::Consumed => TabSessionLifecycle::TAB_SESSION_LIFECYCLE_CONSUMED,
+		tab_protocol::SessionLifecycle::Unresponsive => {
+			TabSessionLifecycle::TAB_SESSION_LIFECYCLE_UNRESPONSIVE
+		}
+		tab_protocol::SessionLifecycle::Unknown => TabSessionLifecycle::TAB_SESSION_LIFECYCLE_UNKNOWN,
 	}
 }
 
 fn tab_session_info_to_c(session: &tab_protocol::SessionInfo) -> TabSessionInfo {
 	TabSessionInfo {
-		id: dup_string(&session.id),
+		id: dup_string(&session.id.to_string()),
 		role: tab_session_role(session.role),
 		display_name: session
 			.display_name
@@ -1172,6 +1252,7 @@ fn tab_input_from_payload(payload: &InputEventPayload) -> TabInputEvent {
 				},
 			},
 		},
+		InputEventPayload::Unknown => unreachable!("TabClient drops unknown input events"),
 	}
 }
 
@@ -1275,7 +1356,7 @@ pub unsafe extern "C" fn tab_client_get_monitor_id(
 		handle
 			.monitor_order
 			.get(index)
-			.map(|id| dup_string(id))
+			.map(|id| dup_string(&id.to_string()))
 			.unwrap_or(ptr::null_mut())
 	}
 }
@@ -1298,7 +1379,7 @@ pub unsafe extern "C" fn tab_client_get_monitor_info(
 				};
 			}
 		};
-		let id = match cstring_to_string(monitor_id) {
+		let id: MonitorId = match cstring_to_id(monitor_id) {
 			Some(id) => id,
 			None => {
 				return TabMonitorInfo {
@@ -1376,23 +1457,23 @@ pub unsafe extern "C" fn tab_client_next_event(
 			return false;
 		};
 		match evt {
-			PendingEvent::BufferReleased(monitor_id, buffer, release_fence_fd) => {
+			PendingEvent::BufferReleased(monitor_id, buffer, release_fence) => {
 				if let Some(entry) = handle.monitors.get_mut(&monitor_id) {
 					entry.swapchain.mark_released(buffer);
 				}
 				(*event).event_type = TabEventType::TAB_EVENT_BUFFER_RELEASED;
 				(*event).data.buffer_released = TabBufferRelease {
-					monitor_id: dup_string(&monitor_id),
+					monitor_id: dup_string(&monitor_id.to_string()),
 					buffer_index: buffer as u32,
-					release_fence_fd: release_fence_fd.unwrap_or(-1),
+					release_fence_fd: release_fence.map_or(-1, IntoRawFd::into_raw_fd),
 				};
 				true
 			}
 			PendingEvent::MonitorRemoved { monitor_id, name } => {
-				handle.remove_monitor(&monitor_id);
+				handle.remove_monitor(monitor_id);
 				(*event).event_type = TabEventType::TAB_EVENT_MONITOR_REMOVED;
 				(*event).data.monitor_removed = TabMonitorRemoved {
-					monitor_id: dup_string(&monitor_id),
+					monitor_id: dup_string(&monitor_id.to_string()),
 					name: dup_string(&name),
 				};
 				true
@@ -1412,19 +1493,46 @@ pub unsafe extern "C" fn tab_client_next_event(
 					true
 				}
 			}
+			PendingEvent::MonitorResized(state) => {
+				if let Err(err) = handle.resize_monitor(state.clone()) {
+					handle.record_error(err);
+					handle
+						.events
+						.borrow_mut()
+						.push_front(PendingEvent::MonitorResized(state));
+					false
+				} else {
+					(*event).event_type = TabEventType::TAB_EVENT_MONITOR_RESIZED;
+					(*event).data.monitor_resized = monitor_info_to_c(&state);
+					true
+				}
+			}
+			PendingEvent::BufferRecovered(monitor_id) => {
+				(*event).event_type = TabEventType::TAB_EVENT_BUFFER_RECOVERED;
+				(*event).data.buffer_recovered = dup_string(&monitor_id.to_string());
+				true
+			}
+			PendingEvent::BufferFailed { monitor_id, reason } => {
+				(*event).event_type = TabEventType::TAB_EVENT_BUFFER_FAILED;
+				(*event).data.buffer_failed = TabBufferFailed {
+					monitor_id: dup_string(&monitor_id.to_string()),
+					reason: dup_string(&reason),
+				};
+				true
+			}
 			PendingEvent::SessionAwake(session_id) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_AWAKE;
-				(*event).data.session_awake = dup_string(&session_id);
+				(*event).data.session_awake = dup_string(&session_id.to_string());
 				true
 			}
 			PendingEvent::SessionActive(session_id) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_ACTIVE;
-				(*event).data.session_active = dup_string(&session_id);
+				(*event).data.session_active = dup_string(&session_id.to_string());
 				true
 			}
 			PendingEvent::SessionSleep(session_id) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_SLEEP;
-				(*event).data.session_sleep = dup_string(&session_id);
+				(*event).data.session_sleep = dup_string(&session_id.to_string());
 				true
 			}
 			PendingEvent::SessionState(session) => {
@@ -1473,6 +1581,22 @@ pub unsafe extern "C" fn tab_client_free_event_strings(event: *mut TabEvent) {
 					(*event).data.monitor_removed.name = ptr::null_mut();
 				}
 			}
+			TabEventType::TAB_EVENT_BUFFER_RECOVERED => {
+				if !(*event).data.buffer_recovered.is_null() {
+					drop(CString::from_raw((*event).data.buffer_recovered));
+					(*event).data.buffer_recovered = ptr::null_mut();
+				}
+			}
+			TabEventType::TAB_EVENT_BUFFER_FAILED => {
+				if !(*event).data.buffer_failed.monitor_id.is_null() {
+					drop(CString::from_raw((*event).data.buffer_failed.monitor_id));
+					(*event).data.buffer_failed.monitor_id = ptr::null_mut();
+				}
+				if !(*event).data.buffer_failed.reason.is_null() {
+					drop(CString::from_raw((*event).data.buffer_failed.reason));
+					(*event).data.buffer_failed.reason = ptr::null_mut();
+				}
+			}
 			TabEventType::TAB_EVENT_SESSION_CREATED => {
 				if !(*event).data.session_created_token.is_null() {
 					drop(CString::from_raw((*event).data.session_created_token));
@@ -1511,6 +1635,10 @@ pub unsafe extern "C" fn tab_client_free_event_strings(event: *mut TabEvent) {
 				let mut info = (*event).data.monitor_added;
 				tab_client_free_monitor_info(&mut info as *mut _);
 			}
+			TabEventType::TAB_EVENT_MONITOR_RESIZED => {
+				let mut info = (*event).data.monitor_resized;
+				tab_client_free_monitor_info(&mut info as *mut _);
+			}
 			_ => {}
 		}
 	}
@@ -1527,7 +1655,7 @@ pub unsafe extern "C" fn tab_client_acquire_frame(
 			Some(h) => h,
 			None => return TabAcquireResult::TAB_ACQUIRE_ERROR,
 		};
-		let id = match cstring_to_string(monitor_id) {
+		let id: MonitorId = match cstring_to_id(monitor_id) {
 			Some(id) => id,
 			None => return TabAcquireResult::TAB_ACQUIRE_ERROR,
 		};
@@ -1563,13 +1691,34 @@ pub unsafe extern "C" fn tab_client_request_buffer(
 	handle: *mut TabClientHandle,
 	monitor_id: *const c_char,
 	acquire_fence_fd: c_int,
+) -> bool {
+	unsafe { request_buffer(handle, monitor_id, acquire_fence_fd, None) }
+}
+
+/// Like `tab_client_request_buffer`, but the frame isn't shown before `present_at_ns`
+/// (`CLOCK_MONOTONIC` nanoseconds).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_request_buffer_at(
+	handle: *mut TabClientHandle,
+	monitor_id: *const c_char,
+	acquire_fence_fd: c_int,
+	present_at_ns: u64,
+) -> bool {
+	unsafe { request_buffer(handle, monitor_id, acquire_fence_fd, Some(present_at_ns)) }
+}
+
+unsafe fn request_buffer(
+	handle: *mut TabClientHandle,
+	monitor_id: *const c_char,
+	acquire_fence_fd: c_int,
+	present_at: Option<u64>,
 ) -> bool {
 	unsafe {
 		let handle = match handle.as_mut() {
 			Some(h) => h,
 			None => return false,
 		};
-		let id = match cstring_to_string(monitor_id) {
+		let id: MonitorId = match cstring_to_id(monitor_id) {
 			Some(id) => id,
 			None => return false,
 		};
@@ -1581,12 +1730,25 @@ pub unsafe extern "C" fn tab_client_request_buffer(
 			Some(idx) => idx,
 			None => return false,
 		};
+		// The caller keeps owning `acquire_fence_fd`; send a duplicate.
 		let acquire_fence = if acquire_fence_fd >= 0 {
-			Some(acquire_fence_fd)
+			match BorrowedFd::borrow_raw(acquire_fence_fd).try_clone_to_owned() {
+				Ok(fd) => Some(SyncFence::from(fd)),
+				Err(err) => {
+					entry.swapchain.rollback();
+					handle.record_error(err);
+					return false;
+				}
+			}
 		} else {
 			None
 		};
-		if let Err(err) = handle.client.request_buffer(&id, buffer, acquire_fence) {
+		if let Err(err) = handle.client.present(
+			&mut entry.swapchain,
+			buffer,
+			acquire_fence.as_ref(),
+			present_at,
+		) {
 			let err_text = err.to_string();
 			let ownership_related = err_text.contains("ownership_violation")
 				|| err_text.contains("buffer_request_inflight")
@@ -1596,14 +1758,34 @@ pub unsafe extern "C" fn tab_client_request_buffer(
 			} else {
 				entry.swapchain.rollback();
 			}
+			// The monitor is gone; its swapchain is recreated when it is added again.
+			if matches!(&err, TabClientError::BufferRequestRejected(reason) if reason == "unknown_monitor")
+			{
+				handle.remove_monitor(id);
+			}
 			handle.record_error(err_text);
 			return false;
 		}
-		entry.swapchain.mark_busy(buffer);
 		true
 	}
 }
 
+/// Waits up to `timeout_ms` (forever when negative) for a sync file fence the caller keeps
+/// owning. Returns 1 once it signaled, 0 on timeout and -1 on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_fence_wait(fence_fd: c_int, timeout_ms: c_int) -> c_int {
+	if fence_fd < 0 {
+		return -1;
+	}
+	let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+	let fd = unsafe { BorrowedFd::borrow_raw(fence_fd) };
+	match sync_fence::wait_fd(fd, timeout) {
+		Ok(true) => 1,
+		Ok(false) => 0,
+		Err(_) => -1,
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_get_server_name(_handle: *mut TabClientHandle) -> *mut c_char {
 	ptr::null_mut()
@@ -1648,6 +1830,20 @@ pub unsafe extern "C" fn tab_client_free_session_info(info: *mut TabSessionInfo)
 	}
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_ping(handle: *mut TabClientHandle) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		if let Err(err) = handle.client.ping() {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_send_ready(handle: *mut TabClientHandle) -> bool {
 	unsafe {
@@ -1675,6 +1871,8 @@ pub unsafe extern "C" fn tab_client_session_create(
 		let role = match role {
 			TabSessionRole::TAB_SESSION_ROLE_ADMIN => tab_protocol::SessionRole::Admin,
 			TabSessionRole::TAB_SESSION_ROLE_SESSION => tab_protocol::SessionRole::Session,
+			TabSessionRole::TAB_SESSION_ROLE_OVERLAY => tab_protocol::SessionRole::Overlay,
+			TabSessionRole::TAB_SESSION_ROLE_UNKNOWN => tab_protocol::SessionRole::Unknown,
 		};
 		let display_name = cstring_to_string(display_name);
 		if let Err(err) = handle.client.create_session(role, display_name) {
@@ -1685,6 +1883,51 @@ pub unsafe extern "C" fn tab_client_session_create(
 	}
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_set_session_display_name(
+	handle: *mut TabClientHandle,
+	display_name: *const c_char,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let Some(display_name) = cstring_to_string(display_name) else {
+			return false;
+		};
+		if let Err(err) = handle.client.update_session(Some(&display_name), None) {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_set_audio_clients(
+	handle: *mut TabClientHandle,
+	client_ids: *const u32,
+	count: usize,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let client_ids = if count == 0 {
+			&[][..]
+		} else if client_ids.is_null() {
+			return false;
+		} else {
+			std::slice::from_raw_parts(client_ids, count)
+		};
+		if let Err(err) = handle.client.set_audio_clients(client_ids) {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_session_switch(
 	handle: *mut TabClientHandle,
@@ -1696,14 +1939,14 @@ pub unsafe extern "C" fn tab_client_session_switch(
 		let Some(handle) = handle.as_mut() else {
 			return false;
 		};
-		let Some(session_id) = cstring_to_string(session_id) else {
+		let Some(session_id) = cstring_to_id(session_id) else {
 			return false;
 		};
 		let animation = cstring_to_string(animation);
 		let duration = Duration::from_millis(duration_ms as u64);
 		if let Err(err) = handle
 			.client
-			.switch_session(&session_id, animation, duration)
+			.switch_session(session_id, animation, duration)
 		{
 			handle.record_error(err);
 			return false;
@@ -1,7 +1,5 @@
 use std::path::{Path, PathBuf};
 
-use tab_protocol::DEFAULT_SOCKET_PATH;
-
 /// Builder-style configuration for establishing a Tab connection.
 #[derive(Debug, Clone)]
 pub struct TabClientConfig {
@@ -13,7 +11,7 @@ pub struct TabClientConfig {
 impl TabClientConfig {
 	pub fn new(token: impl Into<String>) -> Self {
 		Self {
-			socket_path: PathBuf::from(DEFAULT_SOCKET_PATH),
+			socket_path: tab_protocol::default_socket_path(),
 			token: token.into(),
 			render_node: None,
 		}
@@ -0,0 +1,394 @@
+//! Presents dmabufs the client did not allocate, such as VA-API or V4L2 decoder surfaces,
+//! without copying them into a swapchain.
+//!
+//! Shift links two buffers per monitor at a time. [`DmabufPresenter`] keeps the last frames
+//! linked, so a decoder cycling through a small surface pool only relinks when it moves on to
+//! another surface. A surface may be reused once its id comes back from
+//! [`DmabufPresenter::handle_render_event`].
+
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::sync::Arc;
+
+use tab_protocol::{BufferIndex, FramebufferLinkPayload, FramebufferPlane, MonitorId};
+
+use crate::{RenderEvent, SyncFence, TabClient, TabClientError};
+
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+/// The buffer's layout is implied by the driver that allocated it.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DmabufPlane<'a> {
+	pub fd: BorrowedFd<'a>,
+	pub offset: u32,
+	pub stride: u32,
+}
+
+/// A decoded frame as exported by the decoder, e.g. from `vaExportSurfaceHandle`.
+#[derive(Debug, Clone, Copy)]
+pub struct DmabufFrame<'a> {
+	/// Chosen by the caller and handed back in [`ReleasedFrame`].
+	pub id: u64,
+	pub width: u32,
+	pub height: u32,
+	pub fourcc: u32,
+	pub modifier: u64,
+	pub planes: &'a [DmabufPlane<'a>],
+}
+
+/// Shift stopped reading the frame `id`. Wait for `release_fence`, when set, before the
+/// decoder writes to it again.
+#[derive(Debug, Clone)]
+pub struct ReleasedFrame {
+	pub id: u64,
+	pub release_fence: Option<Arc<SyncFence>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layout {
+	width: u32,
+	height: u32,
+	fourcc: u32,
+	modifier: u64,
+	/// `(offset, stride)` of each plane.
+	planes: Vec<(u32, u32)>,
+}
+
+/// `(st_dev, st_ino)` of a dmabuf; every fd exported for the same dmabuf shares it.
+type BufferKey = (libc::dev_t, libc::ino_t);
+
+#[derive(Debug)]
+struct Linked {
+	id: u64,
+	key: BufferKey,
+	fds: Vec<OwnedFd>,
+	busy: bool,
+}
+
+#[derive(Debug)]
+pub struct DmabufPresenter {
+	monitor_id: MonitorId,
+	layout: Option<Layout>,
+	slots: [Option<Linked>; 2],
+	/// Frames dropped by a relink while still on screen. Shift retires them once the next frame
+	/// shows, so they are released along with the next buffer release.
+	retired: Vec<u64>,
+}
+
+impl DmabufPresenter {
+	pub fn new(monitor_id: MonitorId) -> Self {
+		Self {
+			monitor_id,
+			layout: None,
+			slots: [None, None],
+			retired: Vec::new(),
+		}
+	}
+
+	pub fn monitor_id(&self) -> MonitorId {
+		self.monitor_id
+	}
+
+	/// Links `frame` if needed and asks Shift to show it once `acquire_fence` signals, no
+	/// earlier than `present_at` (see [`TabClient::request_buffer_at`]).
+	///
+	/// Frames may have up to [`FramebufferLinkPayload::MAX_PLANES`] planes, e.g. NV12 or P010
+	/// straight from a decoder; others fail with [`TabClientError::UnsupportedDmabuf`]. Tiled or
+	/// compressed frames need a modifier the monitor lists in
+	/// [`tab_protocol::MonitorInfo::format_modifiers`], or Shift rejects the request with
+	/// `unsupported_modifier`. Fails with [`TabClientError::BuffersBusy`] while Shift holds both
+	/// linked frames.
+	pub fn present(
+		&mut self,
+		client: &mut TabClient,
+		frame: &DmabufFrame<'_>,
+		acquire_fence: Option<&SyncFence>,
+		present_at: Option<u64>,
+	) -> Result<(), TabClientError> {
+		let planes = check_planes(frame)?;
+		let layout = Layout {
+			width: frame.width,
+			height: frame.height,
+			fourcc: frame.fourcc,
+			modifier: frame.modifier,
+			planes: planes
+				.iter()
+				.map(|plane| (plane.offset, plane.stride))
+				.collect(),
+		};
+		let key = buffer_key(planes[0].fd)?;
+		let linked = self
+			.slot_of(key)
+			.filter(|_| self.layout.as_ref() == Some(&layout));
+		let index = match linked {
+			Some(index)
+				if self.slots[index as usize]
+					.as_ref()
+					.is_some_and(|slot| slot.busy) =>
+			{
+				return Err(TabClientError::BuffersBusy);
+			}
+			Some(index) => index,
+			None => self.link(client, frame.id, planes, key, layout.clone())?,
+		};
+		let result = match client.request_buffer_at(self.monitor_id, index, acquire_fence, present_at) {
+			// Shift dropped our link, e.g. after a mode change; link from scratch once.
+			Err(TabClientError::BufferRequestRejected(reason)) if reason == "unlinked_buffer" => {
+				self.layout = None;
+				self.slots = [None, None];
+				let index = self.link(client, frame.id, planes, key, layout)?;
+				client
+					.request_buffer_at(self.monitor_id, index, acquire_fence, present_at)
+					.map(|()| index)
+			}
+			other => other.map(|()| index),
+		};
+		let index = result?;
+		if let Some(slot) = self.slots[index as usize].as_mut() {
+			slot.id = frame.id;
+			slot.busy = true;
+		}
+		Ok(())
+	}
+
+	/// Feed every [`RenderEvent`] through here; returns the frames that may be reused.
+	pub fn handle_render_event(&mut self, event: &RenderEvent) -> Vec<ReleasedFrame> {
+		let RenderEvent::BufferReleased {
+			monitor_id,
+			buffer,
+			release_fence,
+		} = event
+		else {
+			return Vec::new();
+		};
+		if *monitor_id != self.monitor_id {
+			return Vec::new();
+		}
+		let mut released: Vec<_> = self
+			.retired
+			.drain(..)
+			.map(|id| ReleasedFrame {
+				id,
+				release_fence: release_fence.clone(),
+			})
+			.collect();
+		if let Some(slot) = self.slots[*buffer as usize]
+			.as_mut()
+			.filter(|slot| slot.busy)
+		{
+			slot.busy = false;
+			released.push(ReleasedFrame {
+				id: slot.id,
+				release_fence: release_fence.clone(),
+			});
+		}
+		released
+	}
+
+	fn slot_of(&self, key: BufferKey) -> Option<BufferIndex> {
+		[BufferIndex::Zero, BufferIndex::One]
+			.into_iter()
+			.find(|index| {
+				self.slots[*index as usize]
+					.as_ref()
+					.is_some_and(|slot| slot.key == key)
+			})
+	}
+
+	/// Links the frame into a slot Shift doesn't hold, keeping the other slot when its layout
+	/// matches.
+	fn link(
+		&mut self,
+		client: &TabClient,
+		id: u64,
+		planes: &[DmabufPlane<'_>],
+		key: BufferKey,
+		layout: Layout,
+	) -> Result<BufferIndex, TabClientError> {
+		let index = [BufferIndex::Zero, BufferIndex::One]
+			.into_iter()
+			.find(|index| {
+				!self.slots[*index as usize]
+					.as_ref()
+					.is_some_and(|slot| slot.busy)
+			})
+			.ok_or(TabClientError::BuffersBusy)?;
+		let other = match index {
+			BufferIndex::Zero => BufferIndex::One,
+			BufferIndex::One => BufferIndex::Zero,
+		};
+		let fds = planes
+			.iter()
+			.map(|plane| plane.fd.try_clone_to_owned())
+			.collect::<Result<Vec<_>, _>>()?;
+		if self.layout.as_ref() != Some(&layout)
+			&& let Some(old) = self.slots[other as usize].take()
+			&& old.busy
+		{
+			self.retired.push(old.id);
+		}
+		// Linking resets both slots on Shift's side, so a frame on screen in the other slot
+		// is retired rather than released.
+		if let Some(kept) = self.slots[other as usize].as_mut()
+			&& kept.busy
+		{
+			kept.busy = false;
+			self.retired.push(kept.id);
+		}
+		let raw = |fds: &[OwnedFd]| fds.iter().map(|fd| fd.as_raw_fd()).collect::<Vec<_>>();
+		let other_fds = self.slots[other as usize]
+			.as_ref()
+			.map_or_else(|| raw(&fds), |slot| raw(&slot.fds));
+		let link_fds = match index {
+			BufferIndex::Zero => [raw(&fds), other_fds].concat(),
+			BufferIndex::One => [other_fds, raw(&fds)].concat(),
+		};
+		let (offset, stride) = layout.planes[0];
+		let payload = FramebufferLinkPayload {
+			monitor_id: self.monitor_id,
+			width: layout.width as i32,
+			height: layout.height as i32,
+			stride: stride as i32,
+			offset: offset as i32,
+			fourcc: layout.fourcc as i32,
+			hdr_metadata: None,
+			scale: None,
+			alpha_mode: None,
+			modifier: (layout.modifier != DRM_FORMAT_MOD_INVALID).then_some(layout.modifier),
+			extra_planes: layout.planes[1..]
+				.iter()
+				.map(|&(offset, stride)| FramebufferPlane {
+					stride: stride as i32,
+					offset: offset as i32,
+				})
+				.collect(),
+		};
+		client.link_framebuffers(payload, &link_fds)?;
+		self.layout = Some(layout);
+		self.slots[index as usize] = Some(Linked {
+			id,
+			key,
+			fds,
+			busy: false,
+		});
+		Ok(index)
+	}
+}
+
+fn check_planes<'a>(frame: &'a DmabufFrame<'a>) -> Result<&'a [DmabufPlane<'a>], TabClientError> {
+	if frame.planes.is_empty() || frame.planes.len() > FramebufferLinkPayload::MAX_PLANES {
+		return Err(TabClientError::UnsupportedDmabuf(format!(
+			"{} planes, frames with 1 to {} planes can be linked",
+			frame.planes.len(),
+			FramebufferLinkPayload::MAX_PLANES
+		)));
+	}
+	Ok(frame.planes)
+}
+
+fn buffer_key(fd: BorrowedFd<'_>) -> Result<BufferKey, TabClientError> {
+	let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+	if unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) } < 0 {
+		return Err(std::io::Error::last_os_error().into());
+	}
+	let stat = unsafe { stat.assume_init() };
+	Ok((stat.st_dev, stat.st_ino))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::fd::{AsFd, FromRawFd};
+
+	use super::*;
+
+	/// Stands in for a dmabuf; `fstat` treats both alike.
+	fn memfd() -> OwnedFd {
+		let fd = unsafe { libc::memfd_create(c"dmabuf".as_ptr(), libc::MFD_CLOEXEC) };
+		assert!(fd >= 0, "memfd_create: {}", std::io::Error::last_os_error());
+		unsafe { OwnedFd::from_raw_fd(fd) }
+	}
+
+	fn monitor(id: &str) -> MonitorId {
+		id.parse().expect("monitor id")
+	}
+
+	fn frame<'a>(planes: &'a [DmabufPlane<'a>]) -> DmabufFrame<'a> {
+		DmabufFrame {
+			id: 1,
+			width: 64,
+			height: 64,
+			fourcc: 0,
+			modifier: DRM_FORMAT_MOD_LINEAR,
+			planes,
+		}
+	}
+
+	fn linked(id: u64, busy: bool) -> Option<Linked> {
+		Some(Linked {
+			id,
+			key: (0, id as libc::ino_t),
+			fds: Vec::new(),
+			busy,
+		})
+	}
+
+	#[test]
+	fn plane_counts_are_checked() {
+		let file = memfd();
+		let plane = DmabufPlane {
+			fd: file.as_fd(),
+			offset: 0,
+			stride: 256,
+		};
+		assert!(check_planes(&frame(&[plane])).is_ok());
+		assert!(matches!(
+			check_planes(&frame(&[])),
+			Err(TabClientError::UnsupportedDmabuf(_))
+		));
+		let too_many = vec![plane; FramebufferLinkPayload::MAX_PLANES + 1];
+		assert!(matches!(
+			check_planes(&frame(&too_many)),
+			Err(TabClientError::UnsupportedDmabuf(_))
+		));
+	}
+
+	#[test]
+	fn exported_fds_of_one_buffer_share_a_key() {
+		let file = memfd();
+		let dup = file.try_clone().expect("dup");
+		let other = memfd();
+		let key = buffer_key(file.as_fd()).expect("fstat");
+		assert_eq!(key, buffer_key(dup.as_fd()).expect("fstat"));
+		assert_ne!(key, buffer_key(other.as_fd()).expect("fstat"));
+	}
+
+	#[test]
+	fn releases_return_the_frame_and_retired_ones() {
+		let mut presenter = DmabufPresenter::new(monitor("mon_1"));
+		presenter.slots = [linked(7, true), linked(8, false)];
+		presenter.retired.push(3);
+		let released = |monitor_id, buffer| RenderEvent::BufferReleased {
+			monitor_id,
+			buffer,
+			release_fence: None,
+		};
+
+		let ids = |frames: Vec<ReleasedFrame>| frames.into_iter().map(|f| f.id).collect::<Vec<_>>();
+		assert!(
+			ids(presenter.handle_render_event(&released(monitor("mon_2"), BufferIndex::Zero))).is_empty(),
+			"another monitor's release"
+		);
+		assert_eq!(
+			ids(presenter.handle_render_event(&released(monitor("mon_1"), BufferIndex::Zero))),
+			vec![3, 7]
+		);
+		assert!(
+			ids(presenter.handle_render_event(&released(monitor("mon_1"), BufferIndex::Zero))).is_empty(),
+			"released twice"
+		);
+		assert!(
+			ids(presenter.handle_render_event(&released(monitor("mon_1"), BufferIndex::One))).is_empty(),
+			"slot Shift didn't hold"
+		);
+	}
+}
@@ -27,7 +27,15 @@ pub enum TabClientError {
 	#[error("monitor has invalid dimensions")]
 	InvalidMonitorDimensions,
 	#[error("unknown monitor: {0}")]
-	UnknownMonitor(String),
+	UnknownMonitor(tab_protocol::MonitorId),
+	/// Shift refused a `buffer_request`; holds the reason, e.g. `unlinked_buffer`.
+	#[error("buffer request rejected: {0}")]
+	BufferRequestRejected(String),
+	/// Shift still holds both linked buffers; wait for a release and try again.
+	#[error("both linked buffers are still in use")]
+	BuffersBusy,
+	#[error("unsupported dma-buf: {0}")]
+	UnsupportedDmabuf(String),
 	#[error("failed to export dma-buf fd: {0}")]
 	BufferExport(#[from] InvalidFdError),
 }
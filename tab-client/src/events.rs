@@ -1,31 +1,116 @@
-use crate::MonitorState;
-use std::os::fd::RawFd;
-use tab_protocol::{BufferIndex, InputEventPayload, SessionInfo};
+use crate::{MonitorId, MonitorState, SessionId, SyncFence};
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use std::time::Duration;
+use tab_protocol::{
+	BufferIndex, ChannelStatsPayload, ConnectorPropertiesPayload, FrameStatsPayload,
+	InputEventPayload, MonitorPlacement, PresentationFeedbackPayload, RenderCacheStatsPayload,
+	ScreencastStartedPayload, ScreencastStoppedPayload, ScreenshotReadyPayload,
+	SessionFrameStatsPayload, SessionInfo, SessionMetadataPayload, VirtualMonitorCreatedPayload,
+};
 
 /// Monitor lifecycle event emitted to listeners.
 #[derive(Debug, Clone)]
 pub enum MonitorEvent {
 	Added(MonitorState),
-	Removed { monitor_id: String, name: String },
+	Removed {
+		monitor_id: MonitorId,
+		name: String,
+	},
+	/// The primary monitor changed; `None` when no monitor is connected.
+	PrimaryChanged(Option<MonitorId>),
+	/// The monitor changed size in place. Link a swapchain of the new size (e.g. with
+	/// [`crate::TabClient::create_swapchain`]) within `relink_deadline`; until then the last
+	/// frame is scaled.
+	Resized {
+		state: MonitorState,
+		relink_deadline: Duration,
+	},
+	/// An admin switched the monitor to another mode with
+	/// [`crate::TabClient::set_monitor_mode`]; a new size was announced with
+	/// [`MonitorEvent::Resized`] first.
+	ModeChanged(MonitorState),
+	/// Monitors moved in the layout, e.g. after [`crate::TabClient::set_monitor_position`];
+	/// `MonitorInfo::position` of the known monitors is already updated.
+	LayoutChanged(Vec<MonitorPlacement>),
+	/// Reply to [`crate::TabClient::request_connector_properties`].
+	ConnectorProperties(ConnectorPropertiesPayload),
+	/// Reply to [`crate::TabClient::capture_monitor`]: `pixels` is a memfd laid out as `info`
+	/// describes. Listeners share it, so clone it to keep it past the callback.
+	Captured {
+		info: ScreenshotReadyPayload,
+		pixels: Arc<OwnedFd>,
+	},
+	/// Reply to [`crate::TabClient::create_virtual_monitor`]. Only the session it was created
+	/// for is told about the monitor itself, with [`MonitorEvent::Added`].
+	VirtualCreated(VirtualMonitorCreatedPayload),
+	/// Reply to [`crate::TabClient::start_screencast`] with the stream's PipeWire node.
+	ScreencastStarted(ScreencastStartedPayload),
+	/// A cast ended, on request or because its monitor or stream went away.
+	ScreencastStopped(ScreencastStoppedPayload),
 }
 
 /// Rendering-related notifications.
 #[derive(Debug, Clone)]
 pub enum RenderEvent {
+	/// Shift is done with `buffer`. When `release_fence` is set, wait for it before writing the
+	/// buffer again; listeners share it, so clone it to keep it past the callback.
 	BufferReleased {
-		monitor_id: String,
+		monitor_id: MonitorId,
 		buffer: BufferIndex,
-		release_fence_fd: Option<RawFd>,
+		release_fence: Option<Arc<SyncFence>>,
+	},
+	/// A rejected [`crate::TabClient::present`] was recovered from by linking the swapchain
+	/// again and retrying.
+	Recovered { monitor_id: MonitorId },
+	/// A [`crate::TabClient::present`] was rejected for good, e.g. because the monitor is gone.
+	/// Drop the monitor's swapchain and create a new one once it is added again.
+	Failed {
+		monitor_id: MonitorId,
+		reason: String,
+	},
+	/// Periodic per-monitor frame pacing, only delivered to admin clients.
+	FrameStats(FrameStatsPayload),
+	/// Periodic GPU resource cache usage, only delivered to admin clients.
+	CacheStats(RenderCacheStatsPayload),
+	/// How a session kept up with a monitor's refresh. Admin clients get it for every session;
+	/// a session gets its own for windows it dropped frames in, if Shift runs with
+	/// `SHIFT_NOTIFY_FRAME_DROPS=1`.
+	SessionFrameStats(SessionFrameStatsPayload),
+	/// A buffer this session presented reached the screen, with the flip's time and sequence
+	/// and the monitor's refresh interval.
+	PresentationFeedback(PresentationFeedbackPayload),
+	/// Overflow counters of Shift's internal channels, delivered to admin clients whenever one
+	/// of them grew.
+	ChannelStats(ChannelStatsPayload),
+	/// Sampled hash of a buffer's contents, sent right before its [`RenderEvent::BufferReleased`]
+	/// while Shift runs with `SHIFT_DEBUG_BUFFER_HASH=1`. Compare it with
+	/// [`tab_protocol::buffer_hash::sample_hash`] over what was drawn into the buffer.
+	BufferHash {
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		hash: u64,
 	},
 }
 
 #[derive(Debug, Clone)]
 pub enum SessionEvent {
-	Active(String),
-	Awake(String),
-	Sleep(String),
+	Active(SessionId),
+	Awake(SessionId),
+	Sleep(SessionId),
+	/// Shift is short on memory and dropped the buffers this sleeping session linked; they can be
+	/// freed, and new ones have to be linked before presenting again.
+	ReleaseBuffers(SessionId),
 	State(SessionInfo),
-	Created { session: SessionInfo, token: String },
+	Created {
+		session: SessionInfo,
+		token: String,
+	},
+	/// An admin client started (`Some(monitor_id)`) or stopped (`None`) remotely controlling
+	/// input; sessions should show an indicator while it is active.
+	RemoteControl(Option<MonitorId>),
+	/// Reply to [`crate::TabClient::request_session_metadata`].
+	Metadata(SessionMetadataPayload),
 }
 
 #[derive(Debug, Clone)]
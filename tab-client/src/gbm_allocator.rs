@@ -51,9 +51,14 @@ pub struct GbmAllocator {
 }
 
 impl GbmAllocator {
-	pub fn new(configured_node: Option<&Path>) -> Result<Self, TabClientError> {
+	/// Opens `configured_node`, else `TAB_CLIENT_RENDER_NODE`, else the node Shift advertised,
+	/// else the first default node that can allocate.
+	pub fn new(
+		configured_node: Option<&Path>,
+		advertised_node: Option<&Path>,
+	) -> Result<Self, TabClientError> {
 		let mut last_error = None;
-		for candidate in Self::render_node_candidates(configured_node) {
+		for candidate in Self::render_node_candidates(configured_node, advertised_node) {
 			match OpenOptions::new().read(true).write(true).open(&candidate) {
 				Ok(file) => match Device::new(file) {
 					Ok(device) => {
@@ -108,39 +113,65 @@ impl GbmAllocator {
 			u32::try_from(monitor.info.width).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
 		let height =
 			u32::try_from(monitor.info.height).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
+		let format = self.format_for(monitor);
 		let bo0 = self
 			.device
-			.create_buffer_object::<()>(width, height, self.format, self.preferred_usage)
+			.create_buffer_object::<()>(width, height, format, self.preferred_usage)
 			.or_else(|_| {
 				self
 					.device
-					.create_buffer_object::<()>(width, height, self.format, self.fallback_usage)
+					.create_buffer_object::<()>(width, height, format, self.fallback_usage)
 			})?;
 		let bo1 = self
 			.device
-			.create_buffer_object::<()>(width, height, self.format, self.preferred_usage)
+			.create_buffer_object::<()>(width, height, format, self.preferred_usage)
 			.or_else(|_| {
 				self
 					.device
-					.create_buffer_object::<()>(width, height, self.format, self.fallback_usage)
+					.create_buffer_object::<()>(width, height, format, self.fallback_usage)
 			})?;
 		let buffers = [
 			TabBuffer::new(BufferIndex::Zero, bo0),
 			TabBuffer::new(BufferIndex::One, bo1),
 		];
-		Ok(TabSwapchain::new(monitor.info.id.clone(), buffers))
+		Ok(TabSwapchain::new(monitor.info.id, buffers))
 	}
 
-	fn render_node_candidates(configured: Option<&Path>) -> Vec<PathBuf> {
+	/// The default format, unless the monitor lists formats without it; then the first listed
+	/// format GBM can allocate.
+	fn format_for(&self, monitor: &MonitorState) -> Format {
+		let offered = &monitor.info.formats;
+		if offered.is_empty() || offered.contains(&(self.format as u32)) {
+			return self.format;
+		}
+		offered
+			.iter()
+			.filter_map(|fourcc| Format::try_from(*fourcc).ok())
+			.find(|format| {
+				self
+					.device
+					.is_format_supported(*format, self.preferred_usage)
+			})
+			.unwrap_or(self.format)
+	}
+
+	fn render_node_candidates(configured: Option<&Path>, advertised: Option<&Path>) -> Vec<PathBuf> {
 		if let Some(path) = configured {
 			vec![path.to_path_buf()]
 		} else if let Ok(env) = std::env::var("TAB_CLIENT_RENDER_NODE") {
 			vec![PathBuf::from(env)]
 		} else {
-			DEFAULT_RENDER_NODES
-				.iter()
-				.chain(DEFAULT_PRIMARY_NODES.iter())
-				.map(|p| PathBuf::from(p))
+			// The advertised node may not be accessible from the client's sandbox; the defaults
+			// follow it.
+			advertised
+				.map(Path::to_path_buf)
+				.into_iter()
+				.chain(
+					DEFAULT_RENDER_NODES
+						.iter()
+						.chain(DEFAULT_PRIMARY_NODES.iter())
+						.map(PathBuf::from),
+				)
 				.collect()
 		}
 	}
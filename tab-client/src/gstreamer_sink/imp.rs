@@ -0,0 +1,574 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::os::fd::RawFd;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, LazyLock, Mutex};
+use std::thread::JoinHandle;
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_base::subclass::prelude::*;
+use gst_video::subclass::prelude::*;
+use gst_video::{VideoFormat, VideoInfo};
+use tab_protocol::{BufferIndex, MonitorId};
+
+use super::pool::{PooledBuffer, SwapchainPool};
+use crate::{RenderEvent, SyncFence, TabBuffer, TabClient, TabClientConfig, TabSwapchain};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+	gst::DebugCategory::new(
+		"tabclientsink",
+		gst::DebugColorFlags::empty(),
+		Some("Shift tab-client video sink"),
+	)
+});
+
+const DRM_FORMAT_XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+const DRM_FORMAT_ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+/// How long the dispatch thread sleeps in `poll` before checking whether it should stop.
+const DISPATCH_POLL_MS: libc::c_int = 100;
+
+#[derive(Debug, Default, Clone)]
+struct Settings {
+	monitor_id: Option<String>,
+	token: Option<String>,
+	socket_path: Option<String>,
+}
+
+enum Pending {
+	Released(BufferIndex, Option<Arc<SyncFence>>),
+	Failed(String),
+}
+
+struct Connection {
+	client: TabClient,
+	swapchain: TabSwapchain,
+	format: VideoFormat,
+	events: Rc<RefCell<VecDeque<Pending>>>,
+	/// Pooled frames Shift still shows, kept alive so they only return to upstream once released.
+	held: [Option<gst::Buffer>; 2],
+}
+
+// SAFETY: the `Rc`s are only shared with the listener owned by `client`, so they always move
+// between threads together, and the connection is only used under the element's mutex.
+unsafe impl Send for Connection {}
+
+impl Connection {
+	fn open(settings: &Settings) -> Result<Self, String> {
+		let token = settings
+			.token
+			.clone()
+			.or_else(|| std::env::var("SHIFT_SESSION_TOKEN").ok())
+			.ok_or("no token set and SHIFT_SESSION_TOKEN is missing")?;
+		let mut config = TabClientConfig::new(token);
+		if let Some(path) = &settings.socket_path {
+			config = config.socket_path(path);
+		}
+		let mut client = TabClient::connect(config).map_err(|err| err.to_string())?;
+		let monitor_id = match &settings.monitor_id {
+			Some(id) => id
+				.parse::<MonitorId>()
+				.map_err(|err| format!("invalid monitor-id {id:?}: {err}"))?,
+			None => client
+				.monitors()
+				.find(|monitor| monitor.info.primary)
+				.or_else(|| client.monitors().next())
+				.map(|monitor| monitor.info.id)
+				.ok_or("Shift reported no monitors")?,
+		};
+		let swapchain = client
+			.create_swapchain(monitor_id)
+			.map_err(|err| err.to_string())?;
+		let format = match swapchain.buffers[0].fourcc() as u32 {
+			DRM_FORMAT_XRGB8888 => VideoFormat::Bgrx,
+			DRM_FORMAT_ARGB8888 => VideoFormat::Bgra,
+			other => {
+				return Err(format!(
+					"monitor {monitor_id} needs unsupported fourcc {other:#x}"
+				));
+			}
+		};
+
+		let events = Rc::new(RefCell::new(VecDeque::new()));
+		let queue = Rc::clone(&events);
+		client.on_render_event(move |event| match event {
+			RenderEvent::BufferReleased {
+				monitor_id: released_on,
+				buffer,
+				release_fence,
+			} if *released_on == monitor_id => {
+				queue
+					.borrow_mut()
+					.push_back(Pending::Released(*buffer, release_fence.clone()));
+			}
+			RenderEvent::Failed {
+				monitor_id: failed_on,
+				reason,
+			} if *failed_on == monitor_id => {
+				queue
+					.borrow_mut()
+					.push_back(Pending::Failed(reason.clone()));
+			}
+			_ => {}
+		});
+		Ok(Self {
+			client,
+			swapchain,
+			format,
+			events,
+			held: [None, None],
+		})
+	}
+
+	fn size(&self) -> (u32, u32) {
+		let buffer = &self.swapchain.buffers[0];
+		(buffer.width() as u32, buffer.height() as u32)
+	}
+
+	fn caps(&self) -> gst::Caps {
+		let (width, height) = self.size();
+		let system = gst_video::VideoCapsBuilder::new()
+			.format(self.format)
+			.width(width as i32)
+			.height(height as i32)
+			.build();
+		let mut caps = gst_video::VideoCapsBuilder::new()
+			.features([gst_allocators::CAPS_FEATURE_MEMORY_DMABUF])
+			.format(self.format)
+			.width(width as i32)
+			.height(height as i32)
+			.build();
+		caps.merge(system);
+		caps
+	}
+
+	fn pooled_buffers(&self) -> [PooledBuffer; 2] {
+		self
+			.swapchain
+			.buffers
+			.each_ref()
+			.map(|buffer| PooledBuffer {
+				fd: buffer.fd(),
+				size: buffer.stride() as usize * buffer.height() as usize,
+				offset: buffer.offset() as usize,
+				stride: buffer.stride(),
+			})
+	}
+
+	/// The swapchain buffer backing `buffer`, when it came from our pool.
+	fn own_index(&self, buffer: &gst::BufferRef) -> Option<BufferIndex> {
+		if buffer.n_memory() != 1 {
+			return None;
+		}
+		let fd = buffer
+			.peek_memory(0)
+			.downcast_memory_ref::<gst_allocators::DmaBufMemory>()?
+			.fd();
+		self
+			.swapchain
+			.buffers
+			.iter()
+			.find(|own| own.fd() == fd)
+			.map(|own| own.index)
+	}
+
+	fn dispatch(&mut self) -> Result<(), String> {
+		self
+			.client
+			.dispatch_events()
+			.map_err(|err| err.to_string())?;
+		let drained: Vec<_> = self.events.borrow_mut().drain(..).collect();
+		for event in drained {
+			match event {
+				Pending::Released(buffer, fence) => {
+					if let Some(fence) = fence
+						&& let Err(err) = fence.wait(None)
+					{
+						gst::warning!(CAT, "failed to wait for a release fence: {err}");
+					}
+					self.swapchain.mark_released(buffer);
+					self.held[buffer as usize] = None;
+				}
+				Pending::Failed(reason) => return Err(reason),
+			}
+		}
+		Ok(())
+	}
+
+	/// Shows a frame that upstream wrote into our pool.
+	fn present_pooled(&mut self, index: BufferIndex, buffer: &gst::Buffer) -> Result<(), String> {
+		self
+			.client
+			.present(&mut self.swapchain, index, None, None)
+			.map_err(|err| err.to_string())?;
+		self.held[index as usize] = Some(buffer.clone());
+		Ok(())
+	}
+
+	/// Copies a frame into the next free buffer. Returns `false` when Shift holds both.
+	fn present_copy(&mut self, buffer: &gst::Buffer, info: &VideoInfo) -> Result<bool, String> {
+		let Some((_, index)) = self.swapchain.acquire_next() else {
+			return Ok(false);
+		};
+		let copied = copy_frame(buffer, info, &mut self.swapchain.buffers[index as usize]);
+		if let Err(err) = copied {
+			self.swapchain.rollback();
+			return Err(err);
+		}
+		self
+			.client
+			.present(&mut self.swapchain, index, None, None)
+			.map_err(|err| err.to_string())?;
+		Ok(true)
+	}
+}
+
+fn copy_frame(
+	buffer: &gst::Buffer,
+	info: &VideoInfo,
+	target: &mut TabBuffer,
+) -> Result<(), String> {
+	let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer.as_ref(), info)
+		.map_err(|err| err.to_string())?;
+	let src = frame.plane_data(0).map_err(|err| err.to_string())?;
+	let src_stride = frame.plane_stride()[0] as usize;
+	let row_len = info.width() as usize * 4;
+	let height = info.height() as usize;
+	target
+		.write_with(|dst, stride| {
+			let stride = stride as usize;
+			for y in 0..height {
+				dst[y * stride..][..row_len].copy_from_slice(&src[y * src_stride..][..row_len]);
+			}
+		})
+		.map_err(|err| err.to_string())
+}
+
+#[derive(Default)]
+struct State {
+	connection: Option<Connection>,
+	info: Option<VideoInfo>,
+	pool: Option<SwapchainPool>,
+	flushing: bool,
+	error: Option<String>,
+}
+
+/// Shared with the dispatch thread, which wakes `released` whenever Shift gives a buffer back.
+#[derive(Default)]
+struct Shared {
+	state: Mutex<State>,
+	released: Condvar,
+}
+
+struct Dispatcher {
+	stop: Arc<AtomicBool>,
+	thread: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct TabClientSink {
+	settings: Mutex<Settings>,
+	shared: Arc<Shared>,
+	dispatcher: Mutex<Option<Dispatcher>>,
+}
+
+fn dispatch_loop(shared: Arc<Shared>, socket: RawFd, stop: Arc<AtomicBool>) {
+	while !stop.load(Ordering::Relaxed) {
+		let mut fds = [libc::pollfd {
+			fd: socket,
+			events: libc::POLLIN,
+			revents: 0,
+		}];
+		if unsafe { libc::poll(fds.as_mut_ptr(), 1, DISPATCH_POLL_MS) } <= 0 {
+			continue;
+		}
+		let mut state = shared.state.lock().unwrap();
+		let Some(connection) = state.connection.as_mut() else {
+			break;
+		};
+		let result = connection.dispatch();
+		let failed = result.is_err();
+		if let Err(err) = result {
+			state.error = Some(err);
+		}
+		drop(state);
+		shared.released.notify_all();
+		if failed {
+			break;
+		}
+	}
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for TabClientSink {
+	const NAME: &'static str = "GstTabClientSink";
+	type Type = super::TabClientSink;
+	type ParentType = gst_video::VideoSink;
+}
+
+impl ObjectImpl for TabClientSink {
+	fn properties() -> &'static [glib::ParamSpec] {
+		static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+			vec![
+				glib::ParamSpecString::builder("monitor-id")
+					.nick("Monitor ID")
+					.blurb("Monitor to show frames on; the primary monitor when unset")
+					.mutable_ready()
+					.build(),
+				glib::ParamSpecString::builder("token")
+					.nick("Session token")
+					.blurb("Token to authenticate with; SHIFT_SESSION_TOKEN when unset")
+					.mutable_ready()
+					.build(),
+				glib::ParamSpecString::builder("socket-path")
+					.nick("Socket path")
+					.blurb("Shift socket to connect to; the default socket when unset")
+					.mutable_ready()
+					.build(),
+			]
+		});
+		PROPERTIES.as_ref()
+	}
+
+	fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+		let mut settings = self.settings.lock().unwrap();
+		let value = value
+			.get::<Option<String>>()
+			.expect("type checked upstream");
+		match pspec.name() {
+			"monitor-id" => settings.monitor_id = value,
+			"token" => settings.token = value,
+			"socket-path" => settings.socket_path = value,
+			name => unreachable!("unknown property {name}"),
+		}
+	}
+
+	fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+		let settings = self.settings.lock().unwrap();
+		match pspec.name() {
+			"monitor-id" => settings.monitor_id.to_value(),
+			"token" => settings.token.to_value(),
+			"socket-path" => settings.socket_path.to_value(),
+			name => unreachable!("unknown property {name}"),
+		}
+	}
+}
+
+impl GstObjectImpl for TabClientSink {}
+
+impl ElementImpl for TabClientSink {
+	fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+		static METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+			gst::subclass::ElementMetadata::new(
+				"Shift video sink",
+				"Sink/Video",
+				"Shows video frames on a Shift monitor through tab-client",
+				"ardOS developers",
+			)
+		});
+		Some(&*METADATA)
+	}
+
+	fn pad_templates() -> &'static [gst::PadTemplate] {
+		static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+			let formats = [VideoFormat::Bgrx, VideoFormat::Bgra];
+			let mut caps = gst_video::VideoCapsBuilder::new()
+				.features([gst_allocators::CAPS_FEATURE_MEMORY_DMABUF])
+				.format_list(formats)
+				.build();
+			caps.merge(
+				gst_video::VideoCapsBuilder::new()
+					.format_list(formats)
+					.build(),
+			);
+			vec![
+				gst::PadTemplate::new(
+					"sink",
+					gst::PadDirection::Sink,
+					gst::PadPresence::Always,
+					&caps,
+				)
+				.expect("valid pad template"),
+			]
+		});
+		PAD_TEMPLATES.as_ref()
+	}
+}
+
+impl BaseSinkImpl for TabClientSink {
+	fn start(&self) -> Result<(), gst::ErrorMessage> {
+		let settings = self.settings.lock().unwrap().clone();
+		let connection = Connection::open(&settings).map_err(|err| {
+			gst::error_msg!(
+				gst::ResourceError::OpenWrite,
+				["Failed to set up the Shift connection: {err}"]
+			)
+		})?;
+		let socket = connection.client.socket_fd();
+		gst::info!(
+			CAT,
+			imp = self,
+			"showing frames on monitor {}",
+			connection.swapchain.monitor_id
+		);
+		*self.shared.state.lock().unwrap() = State {
+			connection: Some(connection),
+			..State::default()
+		};
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread = std::thread::Builder::new()
+			.name("tabclientsink".into())
+			.spawn({
+				let shared = Arc::clone(&self.shared);
+				let stop = Arc::clone(&stop);
+				move || dispatch_loop(shared, socket, stop)
+			})
+			.map_err(|err| {
+				gst::error_msg!(
+					gst::ResourceError::Failed,
+					["Failed to start the dispatch thread: {err}"]
+				)
+			})?;
+		*self.dispatcher.lock().unwrap() = Some(Dispatcher { stop, thread });
+		Ok(())
+	}
+
+	fn stop(&self) -> Result<(), gst::ErrorMessage> {
+		if let Some(dispatcher) = self.dispatcher.lock().unwrap().take() {
+			dispatcher.stop.store(true, Ordering::Relaxed);
+			let _ = dispatcher.thread.join();
+		}
+		let mut state = std::mem::take(&mut *self.shared.state.lock().unwrap());
+		// Pooled buffers wrap the swapchain's fds, so retire them before the swapchain.
+		if let Some(connection) = state.connection.as_mut() {
+			connection.held = [None, None];
+		}
+		if let Some(pool) = state.pool.take() {
+			let _ = pool.set_active(false);
+		}
+		drop(state);
+		Ok(())
+	}
+
+	fn caps(&self, filter: Option<&gst::Caps>) -> Option<gst::Caps> {
+		let state = self.shared.state.lock().unwrap();
+		let caps = match &state.connection {
+			Some(connection) => connection.caps(),
+			None => self.obj().sink_pad().pad_template_caps(),
+		};
+		Some(match filter {
+			Some(filter) => filter.intersect_with_mode(&caps, gst::CapsIntersectMode::First),
+			None => caps,
+		})
+	}
+
+	fn set_caps(&self, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
+		let info = VideoInfo::from_caps(caps)
+			.map_err(|_| gst::loggable_error!(CAT, "Failed to parse caps {caps}"))?;
+		let mut state = self.shared.state.lock().unwrap();
+		let Some(connection) = &state.connection else {
+			return Err(gst::loggable_error!(CAT, "Caps set before start"));
+		};
+		if (info.width(), info.height()) != connection.size() {
+			return Err(gst::loggable_error!(
+				CAT,
+				"Frames are {}x{} but the monitor is {:?}",
+				info.width(),
+				info.height(),
+				connection.size()
+			));
+		}
+		state.info = Some(info);
+		Ok(())
+	}
+
+	fn propose_allocation(
+		&self,
+		query: &mut gst::query::Allocation,
+	) -> Result<(), gst::LoggableError> {
+		query.add_allocation_meta::<gst_video::VideoMeta>(None);
+		let (Some(caps), true) = query.get_owned() else {
+			return Ok(());
+		};
+		let info = VideoInfo::from_caps(&caps)
+			.map_err(|_| gst::loggable_error!(CAT, "Failed to parse caps {caps}"))?;
+		let mut state = self.shared.state.lock().unwrap();
+		let State {
+			connection: Some(connection),
+			pool,
+			..
+		} = &mut *state
+		else {
+			return Ok(());
+		};
+		if (info.width(), info.height()) != connection.size() || info.format() != connection.format {
+			return Ok(());
+		}
+		let buffers = connection.pooled_buffers();
+		let pool = pool.get_or_insert_with(|| {
+			SwapchainPool::new(connection.format, info.width(), info.height(), buffers)
+		});
+		let size = buffers[0].size as u32;
+		let mut config = pool.config();
+		config.set_params(Some(&caps), size, 2, 2);
+		if pool.set_config(config).is_err() {
+			gst::warning!(CAT, imp = self, "Failed to configure the swapchain pool");
+			return Ok(());
+		}
+		query.add_allocation_pool(Some(&*pool), size, 2, 2);
+		Ok(())
+	}
+
+	fn unlock(&self) -> Result<(), gst::ErrorMessage> {
+		self.shared.state.lock().unwrap().flushing = true;
+		self.shared.released.notify_all();
+		Ok(())
+	}
+
+	fn unlock_stop(&self) -> Result<(), gst::ErrorMessage> {
+		self.shared.state.lock().unwrap().flushing = false;
+		Ok(())
+	}
+}
+
+impl VideoSinkImpl for TabClientSink {
+	fn show_frame(&self, buffer: &gst::Buffer) -> Result<gst::FlowSuccess, gst::FlowError> {
+		let mut state = self.shared.state.lock().unwrap();
+		loop {
+			if let Some(err) = state.error.take() {
+				gst::element_imp_error!(
+					self,
+					gst::StreamError::Failed,
+					["Shift stopped showing frames: {err}"]
+				);
+				return Err(gst::FlowError::Error);
+			}
+			if state.flushing {
+				return Err(gst::FlowError::Flushing);
+			}
+			let State {
+				connection, info, ..
+			} = &mut *state;
+			let connection = connection.as_mut().ok_or(gst::FlowError::Flushing)?;
+			let info = info.as_ref().ok_or(gst::FlowError::NotNegotiated)?;
+			let shown = match connection.own_index(buffer) {
+				Some(index) => connection.present_pooled(index, buffer).map(|()| true),
+				None => connection.present_copy(buffer, info),
+			};
+			match shown {
+				Ok(true) => return Ok(gst::FlowSuccess::Ok),
+				Ok(false) => state = self.shared.released.wait(state).unwrap(),
+				Err(err) => {
+					gst::element_imp_error!(
+						self,
+						gst::StreamError::Failed,
+						["Failed to present a frame: {err}"]
+					);
+					return Err(gst::FlowError::Error);
+				}
+			}
+		}
+	}
+}
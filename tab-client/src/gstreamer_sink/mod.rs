@@ -0,0 +1,38 @@
+//! `tabclientsink`, a GStreamer video sink that shows frames on a Shift monitor.
+//!
+//! The sink connects as its own session (the `token` property, or `SHIFT_SESSION_TOKEN`),
+//! creates a swapchain for `monitor-id` (the primary monitor by default) and only accepts
+//! frames of that monitor's size, so put a `videoscale` in front of it when needed.
+//!
+//! It offers upstream a two-buffer pool backed by the swapchain's dmabufs. Decoders and
+//! converters that use it write straight into the buffers Shift scans out, and a buffer only
+//! returns to the pool once Shift released it. Frames from any other memory are copied into the
+//! next free swapchain buffer instead.
+//!
+//! ```ignore
+//! gstreamer::init()?;
+//! tab_client::gstreamer_sink::register(None)?;
+//! let pipeline = gstreamer::parse::launch("filesrc location=clip.mkv ! decodebin ! videoconvert ! tabclientsink")?;
+//! ```
+
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+mod pool;
+
+glib::wrapper! {
+	pub struct TabClientSink(ObjectSubclass<imp::TabClientSink>)
+		@extends gst_video::VideoSink, gst_base::BaseSink, gst::Element, gst::Object;
+}
+
+/// Registers `tabclientsink` with `plugin`, or as a static element when `None`. Call after
+/// `gst::init`.
+pub fn register(plugin: Option<&gst::Plugin>) -> Result<(), glib::BoolError> {
+	gst::Element::register(
+		plugin,
+		"tabclientsink",
+		gst::Rank::NONE,
+		TabClientSink::static_type(),
+	)
+}
@@ -0,0 +1,110 @@
+//! A buffer pool handing out the two dmabufs of a swapchain.
+
+use std::os::fd::RawFd;
+
+use gst::glib;
+use gst::subclass::prelude::*;
+use gst_video::VideoFormat;
+
+glib::wrapper! {
+	pub struct SwapchainPool(ObjectSubclass<imp::SwapchainPool>)
+		@extends gst::BufferPool, gst::Object;
+}
+
+/// Layout of one swapchain buffer. The fd stays owned by the swapchain, which must outlive
+/// every buffer of the pool.
+#[derive(Debug, Clone, Copy)]
+pub struct PooledBuffer {
+	pub fd: RawFd,
+	pub size: usize,
+	pub offset: usize,
+	pub stride: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+	format: VideoFormat,
+	width: u32,
+	height: u32,
+	buffers: [PooledBuffer; 2],
+}
+
+impl SwapchainPool {
+	pub fn new(format: VideoFormat, width: u32, height: u32, buffers: [PooledBuffer; 2]) -> Self {
+		let pool: Self = glib::Object::new();
+		*pool.imp().layout.lock().unwrap() = Some(Layout {
+			format,
+			width,
+			height,
+			buffers,
+		});
+		pool
+	}
+}
+
+mod imp {
+	use std::sync::Mutex;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use gst_allocators::{DmaBufAllocator, FdMemoryFlags};
+
+	use super::*;
+
+	#[derive(Default)]
+	pub struct SwapchainPool {
+		pub(super) layout: Mutex<Option<Layout>>,
+		allocated: AtomicUsize,
+	}
+
+	#[glib::object_subclass]
+	impl ObjectSubclass for SwapchainPool {
+		const NAME: &'static str = "TabClientSwapchainPool";
+		type Type = super::SwapchainPool;
+		type ParentType = gst::BufferPool;
+	}
+
+	impl ObjectImpl for SwapchainPool {}
+
+	impl GstObjectImpl for SwapchainPool {}
+
+	impl BufferPoolImpl for SwapchainPool {
+		fn alloc_buffer(
+			&self,
+			_params: Option<&gst::BufferPoolAcquireParams>,
+		) -> Result<gst::Buffer, gst::FlowError> {
+			let layout = self
+				.layout
+				.lock()
+				.unwrap()
+				.ok_or(gst::FlowError::NotNegotiated)?;
+			let slot = self.allocated.fetch_add(1, Ordering::SeqCst);
+			let plane = layout.buffers.get(slot).ok_or(gst::FlowError::Error)?;
+			// SAFETY: the fd stays open for as long as the swapchain lives, see `PooledBuffer`.
+			let memory = unsafe {
+				DmaBufAllocator::new().alloc_with_flags(plane.fd, plane.size, FdMemoryFlags::DONT_CLOSE)
+			}
+			.map_err(|_| gst::FlowError::Error)?;
+			let mut buffer = gst::Buffer::new();
+			{
+				let buffer = buffer.get_mut().expect("new buffer is writable");
+				buffer.append_memory(memory);
+				gst_video::VideoMeta::add_full(
+					buffer,
+					gst_video::VideoFrameFlags::empty(),
+					layout.format,
+					layout.width,
+					layout.height,
+					&[plane.offset],
+					&[plane.stride],
+				)
+				.map_err(|_| gst::FlowError::Error)?;
+			}
+			Ok(buffer)
+		}
+
+		fn stop(&self) -> bool {
+			self.allocated.store(0, Ordering::SeqCst);
+			self.parent_stop()
+		}
+	}
+}
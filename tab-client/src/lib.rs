@@ -2,33 +2,56 @@
 
 mod c_bindings;
 mod config;
+mod dmabuf_presenter;
 mod error;
 mod events;
 mod gbm_allocator;
+#[cfg(feature = "gstreamer")]
+pub mod gstreamer_sink;
 mod monitor;
 mod swapchain;
+mod sync_fence;
 
 pub use config::TabClientConfig;
+pub use dmabuf_presenter::{
+	DRM_FORMAT_MOD_INVALID, DRM_FORMAT_MOD_LINEAR, DmabufFrame, DmabufPlane, DmabufPresenter,
+	ReleasedFrame,
+};
 pub use error::TabClientError;
 pub use events::{InputEvent, MonitorEvent, RenderEvent, SessionEvent};
-pub use monitor::{MonitorId, MonitorState};
+pub use monitor::MonitorState;
 pub use swapchain::{TabBuffer, TabSwapchain};
+pub use sync_fence::SyncFence;
+pub use tab_protocol::{MonitorId, SessionId};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::os::{
-	fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd, RawFd},
+	fd::{AsRawFd, BorrowedFd, OwnedFd, RawFd},
 	unix::net::UnixStream,
 };
+use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use tab_protocol::message_frame::{TabMessageFrame, TabMessageFrameReader};
 use tab_protocol::message_header;
 use tab_protocol::{
-	AuthErrorPayload, AuthOkPayload, AuthPayload, BufferIndex, BufferReleasePayload,
-	BufferRequestAckPayload, InputEventPayload, MonitorInfo, SessionActivePayload,
-	SessionAwakePayload, SessionCreatePayload, SessionCreatedPayload, SessionInfo,
-	SessionReadyPayload, SessionRole, SessionSleepPayload, SessionStatePayload, SessionSwitchPayload,
-	TabMessage,
+	AudioClientsPayload, AuthErrorPayload, AuthOkPayload, AuthPayload, BufferHashPayload,
+	BufferIndex, BufferReleasePayload, BufferRequestAckPayload, ColorFilterMode, ColorFilterPayload,
+	ColorProfile, CursorImageInfo, DisplayAdjustPayload, FrameTracePayload, FramebufferLinkPayload,
+	GetConnectorPropertiesPayload, GetSessionMetadataPayload, IdleInhibitPayload, InputEventPayload,
+	LatencyMode, LatencyModePayload, MagnifierPayload, MirrorScaling, MonitorEnablePayload,
+	MonitorInfo, MonitorPlacement, MonitorPosition, MonitorProfileAction, MonitorProfilePayload,
+	MonitorScale, NightLightSchedule, OutputTransform, OverlayRegion, OverlayRegionsPayload,
+	ReleaseBuffersPayload, RemoteControlPayload, RenderQualityPayload, SamplingFilter,
+	ScreenRecordPayload, ScreencastStartPayload, ScreencastStopPayload, ScreenshotPayload,
+	SessionActivePayload, SessionAwakePayload, SessionCreatePayload, SessionCreatedPayload,
+	SessionInfo, SessionMetadata, SessionReadyPayload, SessionRole, SessionSleepPayload,
+	SessionStatePayload, SessionSwitchPayload, SessionUpdatePayload, SetColorProfilePayload,
+	SetColorTemperaturePayload, SetCursorPayload, SetMonitorModePayload, SetMonitorPositionPayload,
+	SetMonitorScalePayload, SetOutputTransformPayload, SetPrimaryMonitorPayload, SetVrrPayload,
+	SetWallpaperPayload, TabMessage, VirtualMonitorCreatePayload, VirtualMonitorRemovePayload,
+	Wallpaper,
 };
 
 use crate::gbm_allocator::GbmAllocator;
@@ -38,6 +61,7 @@ pub struct TabClient {
 	socket: UnixStream,
 	reader: TabMessageFrameReader,
 	session: SessionInfo,
+	metadata: SessionMetadata,
 	monitors: HashMap<MonitorId, MonitorState>,
 	monitor_listeners: Vec<Box<dyn Fn(&MonitorEvent)>>,
 	render_listeners: Vec<Box<dyn Fn(&RenderEvent)>>,
@@ -57,7 +81,7 @@ impl TabClient {
 		let TabMessage::Hello(payload) = hello else {
 			return Err(TabClientError::Unexpected("expected hello"));
 		};
-		if payload.protocol != tab_protocol::PROTOCOL_VERSION {
+		if !tab_protocol::is_compatible(&payload.protocol) {
 			return Err(TabClientError::Unexpected("protocol mismatch"));
 		}
 		let auth_frame = TabMessageFrame::json(
@@ -71,14 +95,18 @@ impl TabClient {
 		let monitors = auth_ok
 			.monitors
 			.into_iter()
-			.map(|info| (info.id.clone(), MonitorState::new(info)))
+			.map(|info| (info.id, MonitorState::new(info)))
 			.collect();
-		let gbm = GbmAllocator::new(config.render_node_path())?;
+		let gbm = GbmAllocator::new(
+			config.render_node_path(),
+			auth_ok.render_node.as_deref().map(Path::new),
+		)?;
 		socket.set_nonblocking(true)?;
 		Ok(Self {
 			socket,
 			reader,
 			session: auth_ok.session,
+			metadata: auth_ok.metadata,
 			monitors,
 			monitor_listeners: Vec::new(),
 			render_listeners: Vec::new(),
@@ -92,12 +120,17 @@ impl TabClient {
 		&self.session
 	}
 
+	/// Attributes and environment the admin attached to this session.
+	pub fn session_metadata(&self) -> &SessionMetadata {
+		&self.metadata
+	}
+
 	pub fn monitors(&self) -> impl Iterator<Item = &MonitorState> {
 		self.monitors.values()
 	}
 
-	pub fn monitor(&self, id: &str) -> Option<&MonitorState> {
-		self.monitors.get(id)
+	pub fn monitor(&self, id: MonitorId) -> Option<&MonitorState> {
+		self.monitors.get(&id)
 	}
 
 	pub fn socket_fd(&self) -> RawFd {
@@ -112,45 +145,121 @@ impl TabClient {
 		self.gbm.drm_fd()
 	}
 
-	pub fn create_swapchain(&self, monitor_id: &str) -> Result<TabSwapchain, TabClientError> {
+	pub fn create_swapchain(&self, monitor_id: MonitorId) -> Result<TabSwapchain, TabClientError> {
 		let monitor = self
 			.monitors
-			.get(monitor_id)
-			.ok_or_else(|| TabClientError::UnknownMonitor(monitor_id.to_string()))?;
+			.get(&monitor_id)
+			.ok_or(TabClientError::UnknownMonitor(monitor_id))?;
 		let swapchain = self.gbm.create_swapchain(monitor)?;
 		self.framebuffer_link(&swapchain)?;
 		Ok(swapchain)
 	}
 
 	pub fn framebuffer_link(&self, swapchain: &TabSwapchain) -> Result<(), TabClientError> {
-		let payload = swapchain.framebuffer_link_payload();
+		self.link_framebuffers(
+			swapchain.framebuffer_link_payload(),
+			&swapchain.export_fds(),
+		)
+	}
+
+	pub(crate) fn link_framebuffers(
+		&self,
+		payload: FramebufferLinkPayload,
+		fds: &[RawFd],
+	) -> Result<(), TabClientError> {
 		let mut frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, payload);
-		let fds = swapchain.export_fds();
-		frame.fds = Vec::from(fds);
+		frame.fds = fds.to_vec();
 		frame.encode_and_send(&self.socket)?;
 		Ok(())
 	}
 
 	pub fn request_buffer(
 		&mut self,
-		monitor_id: &str,
+		monitor_id: MonitorId,
 		buffer: BufferIndex,
-		acquire_fence: Option<RawFd>,
+		acquire_fence: Option<&SyncFence>,
 	) -> Result<(), TabClientError> {
-		let payload = format!("{monitor_id} {}", buffer as u8);
+		self.request_buffer_at(monitor_id, buffer, acquire_fence, None)
+	}
+
+	/// Like [`Self::request_buffer`], but the buffer isn't shown before `present_at`, a
+	/// `CLOCK_MONOTONIC` time in nanoseconds. Lets video players schedule frames against their
+	/// audio clock; the buffer shows on the first vblank after both that time and the acquire
+	/// fence.
+	pub fn request_buffer_at(
+		&mut self,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		acquire_fence: Option<&SyncFence>,
+		present_at: Option<u64>,
+	) -> Result<(), TabClientError> {
+		let payload = match present_at {
+			Some(present_at) => format!("{monitor_id} {} {present_at}", buffer as u8),
+			None => format!("{monitor_id} {}", buffer as u8),
+		};
 		let frame = TabMessageFrame {
 			header: message_header::BUFFER_REQUEST.into(),
 			payload: Some(payload),
-			fds: acquire_fence.map_or_else(Vec::new, |fd| vec![fd]),
+			fds: acquire_fence.map_or_else(Vec::new, |fence| vec![fence.as_raw_fd()]),
 		};
 		frame.encode_and_send(&self.socket)?;
 		self.wait_for_buffer_request_ack(monitor_id, buffer)?;
 		Ok(())
 	}
 
+	/// Hands `buffer` of `swapchain` to Shift like [`Self::request_buffer_at`] and marks it busy
+	/// once accepted. Rejections are handled here: after `unlinked_buffer` the swapchain is
+	/// linked again and the request retried once, emitting [`RenderEvent::Recovered`]; any other
+	/// rejection, or a failed retry, emits [`RenderEvent::Failed`].
+	pub fn present(
+		&mut self,
+		swapchain: &mut TabSwapchain,
+		buffer: BufferIndex,
+		acquire_fence: Option<&SyncFence>,
+		present_at: Option<u64>,
+	) -> Result<(), TabClientError> {
+		let monitor_id = swapchain.monitor_id;
+		let result = match self.request_buffer_at(monitor_id, buffer, acquire_fence, present_at) {
+			Err(TabClientError::BufferRequestRejected(reason)) if reason == "unlinked_buffer" => {
+				let retried = self
+					.framebuffer_link(swapchain)
+					.and_then(|()| self.request_buffer_at(monitor_id, buffer, acquire_fence, present_at));
+				let event = match &retried {
+					Ok(()) => RenderEvent::Recovered { monitor_id },
+					Err(err) => RenderEvent::Failed {
+						monitor_id,
+						reason: err.to_string(),
+					},
+				};
+				self.emit_render_event(&event);
+				retried
+			}
+			Err(TabClientError::BufferRequestRejected(reason)) => {
+				self.emit_render_event(&RenderEvent::Failed {
+					monitor_id,
+					reason: reason.clone(),
+				});
+				Err(TabClientError::BufferRequestRejected(reason))
+			}
+			other => other,
+		};
+		if result.is_ok() {
+			swapchain.mark_busy(buffer);
+		}
+		result
+	}
+
+	/// Tells Shift the session is alive without doing anything else. Only needed by sessions
+	/// that can go quiet for longer than `SHIFT_SESSION_LIVENESS_SECS`; any other message
+	/// counts as well.
+	pub fn ping(&self) -> Result<(), TabClientError> {
+		TabMessageFrame::no_payload(message_header::PING).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
 	pub fn send_ready(&self) -> Result<(), TabClientError> {
 		let payload = SessionReadyPayload {
-			session_id: self.session.id.clone(),
+			session_id: self.session.id,
 		};
 		TabMessageFrame::json(message_header::SESSION_READY, payload).encode_and_send(&self.socket)?;
 		Ok(())
@@ -161,19 +270,64 @@ impl TabClient {
 		role: SessionRole,
 		display_name: Option<String>,
 	) -> Result<SessionCreatedPayload, TabClientError> {
-		let payload = SessionCreatePayload { role, display_name };
+		self.create_session_with_metadata(role, display_name, SessionMetadata::default())
+	}
+
+	/// Like [`Self::create_session`], attaching attributes and environment variables that the
+	/// session's client receives with `auth_ok`.
+	pub fn create_session_with_metadata(
+		&mut self,
+		role: SessionRole,
+		display_name: Option<String>,
+		metadata: SessionMetadata,
+	) -> Result<SessionCreatedPayload, TabClientError> {
+		let payload = SessionCreatePayload {
+			role,
+			display_name,
+			metadata,
+		};
 		TabMessageFrame::json(message_header::SESSION_CREATE, payload).encode_and_send(&self.socket)?;
 		self.wait_for_session_created()
 	}
 
+	/// Relabels this session, e.g. with the title of what it shows; admins receive the new name
+	/// as [`SessionEvent::State`]. `attributes` replaces the session's metadata attributes.
+	/// `None` keeps the current value. Invalid labels are answered with an
+	/// `invalid_session_update` error.
+	pub fn update_session(
+		&self,
+		display_name: Option<&str>,
+		attributes: Option<BTreeMap<String, String>>,
+	) -> Result<(), TabClientError> {
+		let payload = SessionUpdatePayload {
+			display_name: display_name.map(str::to_string),
+			attributes,
+		};
+		TabMessageFrame::json(message_header::SESSION_UPDATE, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Names the sound server clients (PulseAudio client indices, or PipeWire client ids
+	/// through pipewire-pulse) this session plays through, so Shift's audio policy can mute
+	/// them while the session sleeps or is in the background. Replaces the previous list.
+	/// Clients not owned by this session's process tree are answered with an
+	/// `invalid_audio_clients` error.
+	pub fn set_audio_clients(&self, client_ids: &[u32]) -> Result<(), TabClientError> {
+		let payload = AudioClientsPayload {
+			client_ids: client_ids.to_vec(),
+		};
+		TabMessageFrame::json(message_header::AUDIO_CLIENTS, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
 	pub fn switch_session(
 		&self,
-		session_id: &str,
+		session_id: SessionId,
 		animation: Option<String>,
 		duration: Duration,
 	) -> Result<(), TabClientError> {
 		let payload = SessionSwitchPayload {
-			session_id: session_id.to_string(),
+			session_id,
 			animation,
 			duration,
 		};
@@ -181,6 +335,432 @@ impl TabClient {
 		Ok(())
 	}
 
+	/// Sets the accessibility color filter of `monitor_id`, or of every monitor when `None`
+	/// (admin only).
+	pub fn set_color_filter(
+		&self,
+		monitor_id: Option<MonitorId>,
+		mode: ColorFilterMode,
+	) -> Result<(), TabClientError> {
+		let payload = ColorFilterPayload { monitor_id, mode };
+		TabMessageFrame::json(message_header::COLOR_FILTER, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Zooms around the pointer on `monitor_id`, or on every monitor when `None` (admin only).
+	/// A `factor` of `1.0` turns the magnifier off.
+	pub fn set_magnifier(
+		&self,
+		monitor_id: Option<MonitorId>,
+		factor: f32,
+	) -> Result<(), TabClientError> {
+		let payload = MagnifierPayload { monitor_id, factor };
+		TabMessageFrame::json(message_header::MAGNIFIER, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Sets software brightness (`-1.0..=1.0`), contrast (`0.0..=4.0`) and gamma (`0.1..=10.0`)
+	/// on `monitor_id` (admin only). `0.0, 1.0, 1.0` resets the monitor.
+	pub fn set_display_adjustment(
+		&self,
+		monitor_id: MonitorId,
+		brightness: f32,
+		contrast: f32,
+		gamma: f32,
+	) -> Result<(), TabClientError> {
+		let payload = DisplayAdjustPayload {
+			monitor_id,
+			brightness,
+			contrast,
+			gamma,
+		};
+		TabMessageFrame::json(message_header::DISPLAY_ADJUST, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Asks for a session's metadata (the own session when `session_id` is `None`); the reply
+	/// arrives as [`SessionEvent::Metadata`].
+	pub fn request_session_metadata(
+		&self,
+		session_id: Option<SessionId>,
+	) -> Result<(), TabClientError> {
+		let payload = GetSessionMetadataPayload { session_id };
+		TabMessageFrame::json(message_header::GET_SESSION_METADATA, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Asks for the DRM connector properties of `monitor_id`, or of every monitor (admin only);
+	/// the reply arrives as [`MonitorEvent::ConnectorProperties`].
+	pub fn request_connector_properties(
+		&self,
+		monitor_id: Option<MonitorId>,
+	) -> Result<(), TabClientError> {
+		let payload = GetConnectorPropertiesPayload { monitor_id };
+		TabMessageFrame::json(message_header::GET_CONNECTOR_PROPERTIES, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Makes `monitor_id` the primary monitor (admin only).
+	pub fn set_primary_monitor(&self, monitor_id: MonitorId) -> Result<(), TabClientError> {
+		let payload = SetPrimaryMonitorPayload { monitor_id };
+		TabMessageFrame::json(message_header::SET_PRIMARY_MONITOR, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Stops or resumes composing `monitor_id` (admin only). While disabled, the monitor is
+	/// reported as removed to every client.
+	pub fn set_monitor_enabled(
+		&self,
+		monitor_id: MonitorId,
+		enabled: bool,
+	) -> Result<(), TabClientError> {
+		let payload = MonitorEnablePayload {
+			monitor_id,
+			enabled,
+		};
+		TabMessageFrame::json(message_header::MONITOR_ENABLE, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Saves or deletes the profile of the currently connected monitors (admin only). A saved
+	/// profile is reapplied whenever the same monitors are connected again.
+	pub fn monitor_profile(&self, action: MonitorProfileAction) -> Result<(), TabClientError> {
+		let payload = MonitorProfilePayload { action };
+		TabMessageFrame::json(message_header::MONITOR_PROFILE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Sets the wallpaper shown where no session covers `monitor_id`, or every monitor when
+	/// `None` (admin only). Image paths are read by the server.
+	pub fn set_wallpaper(
+		&self,
+		monitor_id: Option<MonitorId>,
+		wallpaper: Wallpaper,
+	) -> Result<(), TabClientError> {
+		let payload = SetWallpaperPayload {
+			monitor_id,
+			wallpaper,
+		};
+		TabMessageFrame::json(message_header::SET_WALLPAPER, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Sets how colors are corrected for `monitor_id`, or every monitor when `None` (admin
+	/// only). ICC profile paths are read by the server.
+	pub fn set_color_profile(
+		&self,
+		monitor_id: Option<MonitorId>,
+		profile: ColorProfile,
+	) -> Result<(), TabClientError> {
+		let payload = SetColorProfilePayload {
+			monitor_id,
+			profile,
+		};
+		TabMessageFrame::json(message_header::SET_COLOR_PROFILE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Turns variable refresh rate on or off for `monitor_id`, or every monitor when `None`
+	/// (admin only). Monitors without `vrr_capable` are left alone.
+	pub fn set_vrr(
+		&self,
+		monitor_id: Option<MonitorId>,
+		enabled: bool,
+	) -> Result<(), TabClientError> {
+		let payload = SetVrrPayload {
+			monitor_id,
+			enabled,
+		};
+		TabMessageFrame::json(message_header::SET_VRR, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Rotates or flips `monitor_id` (admin only). Sessions on it get `monitor_resized` with
+	/// the new size.
+	pub fn set_output_transform(
+		&self,
+		monitor_id: MonitorId,
+		transform: OutputTransform,
+	) -> Result<(), TabClientError> {
+		let payload = SetOutputTransformPayload {
+			monitor_id,
+			transform,
+		};
+		TabMessageFrame::json(message_header::SET_OUTPUT_TRANSFORM, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Sets the scale factor of `monitor_id` (admin only). Sessions on it get `monitor_resized`
+	/// with the new `scale`.
+	pub fn set_monitor_scale(
+		&self,
+		monitor_id: MonitorId,
+		scale: MonitorScale,
+	) -> Result<(), TabClientError> {
+		let payload = SetMonitorScalePayload { monitor_id, scale };
+		TabMessageFrame::json(message_header::SET_MONITOR_SCALE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Switches `monitor_id` to one of the modes its [`MonitorInfo::modes`] lists (admin only).
+	/// Every client gets [`MonitorEvent::ModeChanged`] once it took effect, and `monitor_resized`
+	/// before it when the size changed; Shift replies `error` when it couldn't.
+	pub fn set_monitor_mode(
+		&self,
+		monitor_id: MonitorId,
+		width: i32,
+		height: i32,
+		refresh_rate: u32,
+	) -> Result<(), TabClientError> {
+		let payload = SetMonitorModePayload {
+			monitor_id,
+			width,
+			height,
+			refresh_rate,
+		};
+		TabMessageFrame::json(message_header::SET_MONITOR_MODE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Moves `monitor_id` to `position` in the monitor layout, or leaves its placement to Shift
+	/// when `None` (admin only). Every client gets [`MonitorEvent::LayoutChanged`] when monitors
+	/// moved.
+	pub fn set_monitor_position(
+		&self,
+		monitor_id: MonitorId,
+		position: Option<MonitorPosition>,
+	) -> Result<(), TabClientError> {
+		let payload = SetMonitorPositionPayload {
+			monitor_id,
+			position,
+		};
+		TabMessageFrame::json(message_header::SET_MONITOR_POSITION, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Warms the colors of `monitor_id`, or every monitor when `None`, to `kelvin` (admin only);
+	/// [`NightLightSchedule::NEUTRAL_KELVIN`] turns the night light off. With `schedule` it only
+	/// applies between sunset and sunrise.
+	pub fn set_color_temperature(
+		&self,
+		monitor_id: Option<MonitorId>,
+		kelvin: u32,
+		schedule: Option<NightLightSchedule>,
+	) -> Result<(), TabClientError> {
+		let payload = SetColorTemperaturePayload {
+			monitor_id,
+			kelvin,
+			schedule,
+		};
+		TabMessageFrame::json(message_header::SET_COLOR_TEMPERATURE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Shows `image` as this session's pointer cursor, its pixels read from the memfd `pixels`,
+	/// or hides the cursor when `None`. The server copies the pixels when it handles the message,
+	/// so write later images to a fresh memfd instead of reusing this one.
+	pub fn set_cursor(
+		&self,
+		image: Option<(CursorImageInfo, BorrowedFd<'_>)>,
+	) -> Result<(), TabClientError> {
+		let payload = SetCursorPayload {
+			image: image.map(|(info, _)| info),
+		};
+		let mut frame = TabMessageFrame::json(message_header::SET_CURSOR, payload);
+		frame.fds = image.map(|(_, fd)| fd.as_raw_fd()).into_iter().collect();
+		frame.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Keeps the outputs from idling while `inhibit` is set, e.g. during video playback. Only
+	/// honored for the active session; the server drops the inhibitor once the session sleeps.
+	pub fn set_idle_inhibit(&self, inhibit: bool) -> Result<(), TabClientError> {
+		let payload = IdleInhibitPayload { inhibit };
+		TabMessageFrame::json(message_header::IDLE_INHIBIT, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Replaces the regions this overlay session occupies on `monitor_id` (overlay role only).
+	/// The session is composited above the active session and receives pointer and touch input
+	/// only inside them; an empty list removes it from the monitor.
+	pub fn set_overlay_regions(
+		&self,
+		monitor_id: MonitorId,
+		regions: Vec<OverlayRegion>,
+	) -> Result<(), TabClientError> {
+		let payload = OverlayRegionsPayload {
+			monitor_id,
+			regions,
+		};
+		TabMessageFrame::json(message_header::OVERLAY_REGIONS, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Chooses how Shift samples this session's buffers when they don't match the monitor size.
+	pub fn set_render_quality(
+		&self,
+		filter: SamplingFilter,
+		mipmaps: bool,
+	) -> Result<(), TabClientError> {
+		let payload = RenderQualityPayload { filter, mipmaps };
+		TabMessageFrame::json(message_header::RENDER_QUALITY, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Hints how Shift should pace the monitors showing this session: `Low` composes as late as
+	/// possible before each vblank, `PowerSave` halves the refresh rate.
+	pub fn set_latency_mode(&self, mode: LatencyMode) -> Result<(), TabClientError> {
+		let payload = LatencyModePayload { mode };
+		TabMessageFrame::json(message_header::LATENCY_MODE, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Takes or releases remote control of `monitor_id` (admin only). Every session is told
+	/// while remote control is active.
+	pub fn set_remote_control(
+		&self,
+		monitor_id: MonitorId,
+		enabled: bool,
+	) -> Result<(), TabClientError> {
+		let payload = RemoteControlPayload {
+			monitor_id,
+			enabled,
+		};
+		TabMessageFrame::json(message_header::REMOTE_CONTROL, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Injects `event` as if it came from a local input device. Requires holding remote control.
+	pub fn inject_input(&self, event: &InputEventPayload) -> Result<(), TabClientError> {
+		TabMessageFrame::json(message_header::INPUT_INJECT, event).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Starts or stops recording `monitor_id` to a hardware-encoded video file (admin only).
+	/// When starting, `None` limits fall back to the server defaults (one hour, 4 GiB) and a
+	/// `None` path to a timestamped file under `/tmp`.
+	pub fn set_screen_recording(
+		&self,
+		monitor_id: MonitorId,
+		enabled: bool,
+		path: Option<String>,
+		max_duration_secs: Option<u64>,
+		max_size_mb: Option<u64>,
+	) -> Result<(), TabClientError> {
+		let payload = ScreenRecordPayload {
+			monitor_id,
+			enabled,
+			path,
+			max_duration_secs,
+			max_size_mb,
+		};
+		TabMessageFrame::json(message_header::SCREEN_RECORD, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Asks for a capture of what `monitor_id` shows next (admin only); the pixels arrive as
+	/// [`MonitorEvent::Captured`], or an error with code `screenshot_failed`.
+	pub fn capture_monitor(&self, monitor_id: MonitorId) -> Result<(), TabClientError> {
+		let payload = ScreenshotPayload { monitor_id };
+		TabMessageFrame::json(message_header::SCREENSHOT, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Streams `monitor_id` to a new PipeWire node, whose id arrives as
+	/// [`MonitorEvent::ScreencastStarted`], or an error with code `screencast_failed`.
+	pub fn start_screencast(&self, monitor_id: MonitorId) -> Result<(), TabClientError> {
+		let payload = ScreencastStartPayload { monitor_id };
+		TabMessageFrame::json(message_header::SCREENCAST_START, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Ends the cast of `monitor_id`, confirmed with [`MonitorEvent::ScreencastStopped`].
+	pub fn stop_screencast(&self, monitor_id: MonitorId) -> Result<(), TabClientError> {
+		let payload = ScreencastStopPayload { monitor_id };
+		TabMessageFrame::json(message_header::SCREENCAST_STOP, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Creates an offscreen monitor for `session_id` (admin only), composed at most
+	/// `refresh_rate` times per second and never scanned out. The reply arrives as
+	/// [`MonitorEvent::VirtualCreated`], or an error with code `invalid_virtual_monitor` or
+	/// `unknown_session`.
+	pub fn create_virtual_monitor(
+		&self,
+		session_id: SessionId,
+		width: u32,
+		height: u32,
+		refresh_rate: Option<u32>,
+	) -> Result<(), TabClientError> {
+		let payload = VirtualMonitorCreatePayload {
+			session_id,
+			width,
+			height,
+			refresh_rate,
+			mirror: None,
+			scaling: MirrorScaling::default(),
+		};
+		TabMessageFrame::json(message_header::VIRTUAL_MONITOR_CREATE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Mirrors what `session_id` shows on `source` into a new `width`x`height` virtual monitor
+	/// (admin only), e.g. to stream the session at a lower resolution or frame rate. The
+	/// session isn't told about the mirror; the reply arrives as
+	/// [`MonitorEvent::VirtualCreated`].
+	pub fn mirror_session(
+		&self,
+		session_id: SessionId,
+		source: MonitorId,
+		width: u32,
+		height: u32,
+		refresh_rate: Option<u32>,
+		scaling: MirrorScaling,
+	) -> Result<(), TabClientError> {
+		let payload = VirtualMonitorCreatePayload {
+			session_id,
+			width,
+			height,
+			refresh_rate,
+			mirror: Some(source),
+			scaling,
+		};
+		TabMessageFrame::json(message_header::VIRTUAL_MONITOR_CREATE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Removes a monitor made with [`Self::create_virtual_monitor`] (admin only).
+	pub fn remove_virtual_monitor(&self, monitor_id: MonitorId) -> Result<(), TabClientError> {
+		let payload = VirtualMonitorRemovePayload { monitor_id };
+		TabMessageFrame::json(message_header::VIRTUAL_MONITOR_REMOVE, payload)
+			.encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
+	/// Starts or stops a chrome-trace capture of the server's rendering spans (admin only).
+	/// When stopping, the trace is written to the path given at start, or a timestamped file
+	/// under `/tmp` if none was given.
+	pub fn set_frame_trace(&self, enabled: bool, path: Option<String>) -> Result<(), TabClientError> {
+		let payload = FrameTracePayload { enabled, path };
+		TabMessageFrame::json(message_header::FRAME_TRACE, payload).encode_and_send(&self.socket)?;
+		Ok(())
+	}
+
 	pub fn on_monitor_event<F>(&mut self, listener: F)
 	where
 		F: Fn(&MonitorEvent) + 'static,
@@ -212,10 +792,13 @@ impl TabClient {
 	pub fn dispatch_events(&mut self) -> Result<(), TabClientError> {
 		loop {
 			match self.reader.read_framed(&self.socket) {
-				Ok(frame) => {
-					let message = TabMessage::try_from(frame)?;
-					self.handle_message(message)?;
-				}
+				Ok(frame) => match TabMessage::try_from(frame) {
+					Ok(message) => self.handle_message(message)?,
+					// A payload a newer server changed beyond what this build can decode; skip
+					// it like a message we don't know.
+					Err(tab_protocol::ProtocolError::Json(_)) => {}
+					Err(other) => return Err(other.into()),
+				},
 				Err(tab_protocol::ProtocolError::WouldBlock) => break,
 				Err(other) => return Err(other.into()),
 			}
@@ -260,6 +843,18 @@ impl TabClient {
 			TabMessage::MonitorRemoved(payload) => {
 				self.handle_monitor_removed(payload.monitor_id, payload.name);
 			}
+			TabMessage::MonitorResized(payload) => {
+				self.handle_monitor_resized(
+					payload.monitor,
+					Duration::from_millis(payload.relink_deadline_ms),
+				);
+			}
+			TabMessage::MonitorModeChanged(payload) => {
+				self.handle_monitor_mode_changed(payload.monitor);
+			}
+			TabMessage::MonitorLayout(payload) => {
+				self.handle_monitor_layout(payload.monitors);
+			}
 			TabMessage::SessionCreated(payload) => {
 				self.handle_session_created(payload.session, payload.token);
 			}
@@ -275,6 +870,9 @@ impl TabClient {
 			TabMessage::SessionSleep(SessionSleepPayload { session_id }) => {
 				self.handle_session_sleep(session_id);
 			}
+			TabMessage::ReleaseBuffers(ReleaseBuffersPayload { session_id }) => {
+				self.handle_release_buffers(session_id);
+			}
 			TabMessage::SessionActive(SessionActivePayload { session_id }) => {
 				self.handle_session_active(session_id);
 			}
@@ -284,21 +882,165 @@ impl TabClient {
 			TabMessage::InputEvent(payload) => {
 				self.handle_input_event(payload);
 			}
+			TabMessage::PrimaryMonitor(payload) => {
+				self.handle_primary_monitor(payload.monitor_id);
+			}
+			TabMessage::RemoteControlState(payload) => {
+				let event = SessionEvent::RemoteControl(payload.monitor_id.filter(|_| payload.active));
+				for listener in &self.session_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::FrameStats(payload) => {
+				let event = RenderEvent::FrameStats(payload);
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::RenderCacheStats(payload) => {
+				let event = RenderEvent::CacheStats(payload);
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::SessionFrameStats(payload) => {
+				let event = RenderEvent::SessionFrameStats(payload);
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::PresentationFeedback(payload) => {
+				let event = RenderEvent::PresentationFeedback(payload);
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::ChannelStats(payload) => {
+				let event = RenderEvent::ChannelStats(payload);
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::BufferHash(BufferHashPayload {
+				monitor_id,
+				buffer,
+				hash,
+			}) => {
+				let event = RenderEvent::BufferHash {
+					monitor_id,
+					buffer,
+					hash,
+				};
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::ConnectorProperties(payload) => {
+				let event = MonitorEvent::ConnectorProperties(payload);
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::ScreenshotReady { payload, pixels } => {
+				let event = MonitorEvent::Captured {
+					info: payload,
+					pixels: Arc::new(pixels),
+				};
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::VirtualMonitorCreated(payload) => {
+				let event = MonitorEvent::VirtualCreated(payload);
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::ScreencastStarted(payload) => {
+				let event = MonitorEvent::ScreencastStarted(payload);
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::ScreencastStopped(payload) => {
+				let event = MonitorEvent::ScreencastStopped(payload);
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			TabMessage::SessionMetadata(payload) => {
+				if payload.session_id == self.session.id {
+					self.metadata = payload.metadata.clone();
+				}
+				let event = SessionEvent::Metadata(payload);
+				for listener in &self.session_listeners {
+					listener(&event);
+				}
+			}
 			_ => {}
 		}
 		Ok(())
 	}
 
+	fn emit_render_event(&self, event: &RenderEvent) {
+		for listener in &self.render_listeners {
+			listener(event);
+		}
+	}
+
 	fn handle_monitor_added(&mut self, info: MonitorInfo) {
 		let state = MonitorState::new(info);
-		self.monitors.insert(state.info.id.clone(), state.clone());
+		self.monitors.insert(state.info.id, state.clone());
 		let event = MonitorEvent::Added(state);
 		for listener in &self.monitor_listeners {
 			listener(&event);
 		}
 	}
 
-	fn handle_monitor_removed(&mut self, monitor_id: String, name: String) {
+	fn handle_monitor_resized(&mut self, info: MonitorInfo, relink_deadline: Duration) {
+		let state = MonitorState::new(info);
+		self.monitors.insert(state.info.id, state.clone());
+		let event = MonitorEvent::Resized {
+			state,
+			relink_deadline,
+		};
+		for listener in &self.monitor_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_monitor_mode_changed(&mut self, info: MonitorInfo) {
+		let state = MonitorState::new(info);
+		self.monitors.insert(state.info.id, state.clone());
+		let event = MonitorEvent::ModeChanged(state);
+		for listener in &self.monitor_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_monitor_layout(&mut self, placements: Vec<MonitorPlacement>) {
+		for placement in &placements {
+			if let Some(state) = self.monitors.get_mut(&placement.monitor_id) {
+				state.info.position = placement.position;
+			}
+		}
+		let event = MonitorEvent::LayoutChanged(placements);
+		for listener in &self.monitor_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_primary_monitor(&mut self, monitor_id: Option<MonitorId>) {
+		for (id, state) in self.monitors.iter_mut() {
+			state.info.primary = monitor_id == Some(*id);
+		}
+		let event = MonitorEvent::PrimaryChanged(monitor_id);
+		for listener in &self.monitor_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_monitor_removed(&mut self, monitor_id: MonitorId, name: String) {
 		self.monitors.remove(&monitor_id);
 		let event = MonitorEvent::Removed { monitor_id, name };
 		for listener in &self.monitor_listeners {
@@ -311,43 +1053,42 @@ impl TabClient {
 		payload: BufferReleasePayload,
 		release_fence: Option<OwnedFd>,
 	) {
-		let monitor_id = payload.monitor_id;
-		let buffer = payload.buffer;
-		for listener in &self.render_listeners {
-			let release_fence_fd = release_fence
-				.as_ref()
-				.and_then(|fd| fd.as_fd().try_clone_to_owned().ok())
-				.map(|fd| fd.into_raw_fd());
-			let event = RenderEvent::BufferReleased {
-				monitor_id: monitor_id.clone(),
-				buffer,
-				release_fence_fd,
-			};
-			listener(&event);
-		}
+		let event = RenderEvent::BufferReleased {
+			monitor_id: payload.monitor_id,
+			buffer: payload.buffer,
+			release_fence: release_fence.map(|fd| Arc::new(SyncFence::from(fd))),
+		};
+		self.emit_render_event(&event);
 	}
 
-	fn handle_session_awake(&mut self, session_id: String) {
+	fn handle_session_awake(&mut self, session_id: SessionId) {
 		let event = SessionEvent::Awake(session_id);
 		for listener in &self.session_listeners {
 			listener(&event);
 		}
 	}
 
-	fn handle_session_active(&mut self, session_id: String) {
+	fn handle_session_active(&mut self, session_id: SessionId) {
 		let event = SessionEvent::Active(session_id);
 		for listener in &self.session_listeners {
 			listener(&event);
 		}
 	}
 
-	fn handle_session_sleep(&mut self, session_id: String) {
+	fn handle_session_sleep(&mut self, session_id: SessionId) {
 		let event = SessionEvent::Sleep(session_id);
 		for listener in &self.session_listeners {
 			listener(&event);
 		}
 	}
 
+	fn handle_release_buffers(&mut self, session_id: SessionId) {
+		let event = SessionEvent::ReleaseBuffers(session_id);
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
 	fn handle_session_created(&mut self, session: SessionInfo, token: String) {
 		let event = SessionEvent::Created { session, token };
 		for listener in &self.session_listeners {
@@ -363,6 +1104,9 @@ impl TabClient {
 	}
 
 	fn handle_input_event(&mut self, payload: InputEventPayload) {
+		if matches!(payload, InputEventPayload::Unknown) {
+			return;
+		}
 		let event = InputEvent::Event(payload);
 		for listener in &self.input_listeners {
 			listener(&event);
@@ -371,7 +1115,7 @@ impl TabClient {
 
 	fn wait_for_buffer_request_ack(
 		&mut self,
-		monitor_id: &str,
+		monitor_id: MonitorId,
 		buffer: BufferIndex,
 	) -> Result<(), TabClientError> {
 		let deadline = Instant::now() + Self::BUFFER_REQUEST_ACK_TIMEOUT;
@@ -391,6 +1135,14 @@ impl TabClient {
 								return Ok(());
 							}
 						}
+						TabMessage::Error(err) if err.code == "buffer_request_rejected" => {
+							return Err(TabClientError::BufferRequestRejected(
+								err.message.unwrap_or(err.code),
+							));
+						}
+						TabMessage::Error(err) if err.code == "unknown_monitor" => {
+							return Err(TabClientError::BufferRequestRejected(err.code));
+						}
 						TabMessage::Error(err) => {
 							let details = err
 								.message
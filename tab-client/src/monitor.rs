@@ -1,7 +1,5 @@
 use tab_protocol::MonitorInfo;
 
-pub type MonitorId = String;
-
 #[derive(Debug, Clone)]
 pub struct MonitorState {
 	pub info: MonitorInfo,
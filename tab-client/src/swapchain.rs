@@ -1,7 +1,10 @@
+use std::io;
 use std::os::fd::{AsRawFd, OwnedFd, RawFd};
 
 use gbm::BufferObject;
-use tab_protocol::{BufferIndex, FramebufferLinkPayload};
+use tab_protocol::{
+	AlphaMode, BufferIndex, FramebufferLinkPayload, HdrMetadata, MonitorId, MonitorScale,
+};
 
 /// Metadata describing a DMA-BUF-backed buffer.
 #[derive(Debug)]
@@ -43,26 +46,42 @@ impl TabBuffer {
 	pub fn fd(&self) -> RawFd {
 		self.fd.as_raw_fd()
 	}
+
+	/// Maps the whole buffer for CPU writes. `f` gets the pixels and the row stride of the
+	/// mapping, which can differ from [`Self::stride`] when the buffer is tiled.
+	pub fn write_with<R>(&mut self, f: impl FnOnce(&mut [u8], u32) -> R) -> io::Result<R> {
+		let (width, height) = (self.bo.width(), self.bo.height());
+		self.bo.map_mut(0, 0, width, height, |mapping| {
+			let stride = mapping.stride();
+			f(mapping.buffer_mut(), stride)
+		})
+	}
 }
 
 /// Double-buffer swapchain model.
 #[derive(Debug)]
 pub struct TabSwapchain {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub buffers: [TabBuffer; 2],
 	current: BufferIndex,
 	last_acquired: Option<BufferIndex>,
 	busy: [bool; 2],
+	hdr_metadata: Option<HdrMetadata>,
+	scale: Option<MonitorScale>,
+	alpha_mode: Option<AlphaMode>,
 }
 
 impl TabSwapchain {
-	pub fn new(monitor_id: impl Into<String>, buffers: [TabBuffer; 2]) -> Self {
+	pub fn new(monitor_id: MonitorId, buffers: [TabBuffer; 2]) -> Self {
 		Self {
-			monitor_id: monitor_id.into(),
+			monitor_id,
 			buffers,
 			current: BufferIndex::Zero,
 			last_acquired: None,
 			busy: [false, false],
+			hdr_metadata: None,
+			scale: None,
+			alpha_mode: None,
 		}
 	}
 
@@ -101,15 +120,37 @@ impl TabSwapchain {
 		self.busy[idx as usize] = false;
 	}
 
+	/// Marks the buffers as holding HDR content. Takes effect on the next framebuffer link.
+	pub fn set_hdr_metadata(&mut self, metadata: Option<HdrMetadata>) {
+		self.hdr_metadata = metadata;
+	}
+
+	/// Declares the scale the buffers are drawn at, when it isn't the monitor's. Takes effect on
+	/// the next framebuffer link.
+	pub fn set_scale(&mut self, scale: Option<MonitorScale>) {
+		self.scale = scale;
+	}
+
+	/// Declares how the buffers' alpha is to be blended, for translucent overlays. Takes effect on
+	/// the next framebuffer link.
+	pub fn set_alpha_mode(&mut self, alpha_mode: Option<AlphaMode>) {
+		self.alpha_mode = alpha_mode;
+	}
+
 	pub fn framebuffer_link_payload(&self) -> FramebufferLinkPayload {
 		let buffer = &self.buffers[0];
 		FramebufferLinkPayload {
-			monitor_id: self.monitor_id.clone(),
+			monitor_id: self.monitor_id,
 			width: buffer.width(),
 			height: buffer.height(),
 			stride: buffer.stride(),
 			offset: buffer.offset(),
 			fourcc: buffer.fourcc(),
+			hdr_metadata: self.hdr_metadata,
+			extra_planes: Vec::new(),
+			modifier: None,
+			scale: self.scale,
+			alpha_mode: self.alpha_mode,
 		}
 	}
 
@@ -0,0 +1,128 @@
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+/// An owned `sync_file` fence, as used for acquire and release fences. The fd is closed on drop.
+#[derive(Debug)]
+pub struct SyncFence(OwnedFd);
+
+impl SyncFence {
+	/// Whether the fence signaled, without blocking.
+	pub fn is_signaled(&self) -> io::Result<bool> {
+		self.wait(Some(Duration::ZERO))
+	}
+
+	/// Blocks until the fence signals or `timeout` passes (`None` waits forever). Returns
+	/// `false` on timeout.
+	pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+		wait_fd(self.0.as_fd(), timeout)
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.0.try_clone().map(Self)
+	}
+
+	/// The fd to pass as `EGL_SYNC_NATIVE_FENCE_FD_ANDROID` when creating an
+	/// `EGL_SYNC_NATIVE_FENCE_ANDROID` sync. EGL owns it once `eglCreateSyncKHR` succeeds; close
+	/// it yourself if the call fails.
+	pub fn into_egl_native_fence_fd(self) -> RawFd {
+		self.0.into_raw_fd()
+	}
+
+	/// Takes ownership of an fd returned by `eglDupNativeFenceFDANDROID`.
+	/// `EGL_NO_NATIVE_FENCE_FD_ANDROID` (`-1`) gives `None`.
+	///
+	/// # Safety
+	/// `fd` must be `-1` or an open fd nothing else owns.
+	pub unsafe fn from_egl_native_fence_fd(fd: RawFd) -> Option<Self> {
+		(fd >= 0).then(|| Self(unsafe { OwnedFd::from_raw_fd(fd) }))
+	}
+
+	/// The fd to import with `VK_EXTERNAL_SEMAPHORE_HANDLE_TYPE_SYNC_FD_BIT` (or the fence
+	/// equivalent). Vulkan owns it once the import succeeds; close it yourself if it fails.
+	pub fn into_vulkan_sync_fd(self) -> RawFd {
+		self.0.into_raw_fd()
+	}
+
+	/// Takes ownership of a sync fd exported with `vkGetSemaphoreFdKHR`/`vkGetFenceFdKHR`.
+	/// Vulkan exports `-1` for payloads that already signaled, which gives `None`.
+	///
+	/// # Safety
+	/// `fd` must be `-1` or an open fd nothing else owns.
+	pub unsafe fn from_vulkan_sync_fd(fd: RawFd) -> Option<Self> {
+		(fd >= 0).then(|| Self(unsafe { OwnedFd::from_raw_fd(fd) }))
+	}
+}
+
+impl From<OwnedFd> for SyncFence {
+	fn from(fd: OwnedFd) -> Self {
+		Self(fd)
+	}
+}
+
+impl From<SyncFence> for OwnedFd {
+	fn from(fence: SyncFence) -> Self {
+		fence.0
+	}
+}
+
+impl AsFd for SyncFence {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.0.as_fd()
+	}
+}
+
+impl AsRawFd for SyncFence {
+	fn as_raw_fd(&self) -> RawFd {
+		self.0.as_raw_fd()
+	}
+}
+
+impl IntoRawFd for SyncFence {
+	fn into_raw_fd(self) -> RawFd {
+		self.0.into_raw_fd()
+	}
+}
+
+impl FromRawFd for SyncFence {
+	unsafe fn from_raw_fd(fd: RawFd) -> Self {
+		Self(unsafe { OwnedFd::from_raw_fd(fd) })
+	}
+}
+
+/// [`SyncFence::wait`] on a fence the caller keeps owning.
+pub(crate) fn wait_fd(fd: BorrowedFd<'_>, timeout: Option<Duration>) -> io::Result<bool> {
+	let deadline = timeout.map(|timeout| Instant::now() + timeout);
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	loop {
+		let timeout_ms = match deadline {
+			Some(deadline) => {
+				let remaining = deadline.saturating_duration_since(Instant::now());
+				// Round up so a sub-millisecond remainder doesn't turn into a busy loop.
+				remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+			}
+			None => -1,
+		};
+		let rc = unsafe { libc::poll(&mut pfd as *mut libc::pollfd, 1, timeout_ms) };
+		if rc > 0 {
+			if pfd.revents & libc::POLLNVAL != 0 {
+				return Err(io::Error::from_raw_os_error(libc::EBADF));
+			}
+			return Ok(pfd.revents & (libc::POLLIN | libc::POLLERR | libc::POLLHUP) != 0);
+		}
+		if rc == 0 {
+			if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+				return Ok(false);
+			}
+			continue;
+		}
+		let err = io::Error::last_os_error();
+		if err.kind() != io::ErrorKind::Interrupted {
+			return Err(err);
+		}
+	}
+}
@@ -0,0 +1,23 @@
+//! Arbitrary header and payload lines through `TabMessageFrame::from_lines`. The first byte
+//! picks where the input splits into the two lines.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tab_protocol::{TabMessage, testing::frame_from_lines};
+
+fuzz_target!(|data: &[u8]| {
+	let Some((&split, rest)) = data.split_first() else {
+		return;
+	};
+	let (header, payload) = rest.split_at(split as usize % (rest.len() + 1));
+	let Ok(frame) = frame_from_lines(header, payload) else {
+		return;
+	};
+	assert_eq!(frame.header.0.as_bytes(), header);
+	match &frame.payload {
+		Some(parsed) => assert_eq!(parsed.as_bytes(), payload),
+		None => assert_eq!(payload, b"\0\0\0\0"),
+	}
+	let _ = TabMessage::parse_message_frame(frame);
+});
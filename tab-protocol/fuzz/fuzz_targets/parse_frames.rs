@@ -0,0 +1,9 @@
+//! Arbitrary bytes through `TabMessageFrame::parse_from_bytes` and message parsing.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	tab_protocol::testing::check_bytes(data);
+});
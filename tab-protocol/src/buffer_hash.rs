@@ -0,0 +1,62 @@
+//! The content hash Shift reports in `buffer_hash` while `SHIFT_DEBUG_BUFFER_HASH` is set.
+//!
+//! It is 64-bit FNV-1a over [`SAMPLED_ROWS`] rows spread evenly over the buffer's first plane,
+//! each `stride` bytes starting at `offset + y * stride`. Row padding and, with tiled modifiers,
+//! the tiled layout are part of it, so clients hash their own mapping of the buffer with
+//! [`sample_hash`] to tell which of their frames Shift showed.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Rows hashed per buffer; buffers with fewer rows are hashed whole.
+pub const SAMPLED_ROWS: u32 = 16;
+
+/// Hashes the sampled rows of `mapping`, which starts at the beginning of the dmabuf. `None`
+/// when the rows don't fit in it.
+pub fn sample_hash(mapping: &[u8], height: u32, stride: u32, offset: u32) -> Option<u64> {
+	let mut hash = FNV_OFFSET_BASIS;
+	for y in sampled_rows(height) {
+		let start = offset as usize + y as usize * stride as usize;
+		let row = mapping.get(start..start + stride as usize)?;
+		for byte in row {
+			hash = (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME);
+		}
+	}
+	Some(hash)
+}
+
+fn sampled_rows(height: u32) -> impl Iterator<Item = u32> {
+	let count = SAMPLED_ROWS.min(height);
+	(0..count).map(move |i| (u64::from(i) * u64::from(height) / u64::from(count)) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn samples_rows_evenly() {
+		assert_eq!(sampled_rows(4).collect::<Vec<_>>(), [0, 1, 2, 3]);
+		let rows: Vec<_> = sampled_rows(1080).collect();
+		assert_eq!(rows.len(), SAMPLED_ROWS as usize);
+		assert_eq!((rows[0], rows[1], rows[15]), (0, 67, 1012));
+		assert_eq!(sampled_rows(0).count(), 0);
+	}
+
+	#[test]
+	fn hash_covers_sampled_rows_only() {
+		let (height, stride, offset) = (64u32, 8u32, 4u32);
+		let mut buffer = vec![0u8; (offset + height * stride) as usize];
+		let base = sample_hash(&buffer, height, stride, offset).unwrap();
+
+		// Row 4 is sampled, row 5 isn't.
+		buffer[(offset + 4 * stride) as usize] = 1;
+		let changed = sample_hash(&buffer, height, stride, offset).unwrap();
+		assert_ne!(changed, base);
+		buffer[(offset + 5 * stride + 3) as usize] = 1;
+		assert_eq!(sample_hash(&buffer, height, stride, offset), Some(changed));
+
+		assert_eq!(sample_hash(&buffer[..100], height, stride, offset), None);
+		assert_eq!(sample_hash(&[], 0, stride, offset), Some(FNV_OFFSET_BASIS));
+	}
+}
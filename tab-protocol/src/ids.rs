@@ -0,0 +1,111 @@
+//! Typed ids of protocol objects. On the wire an id is its prefix followed by a hex number
+//! (`mon_1f3a...`); each kind gets its own type so a session id can't be passed where a monitor
+//! id is expected.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IdParseError {
+	#[error("expected prefix '{expected}' but found {found:?}")]
+	InvalidPrefix {
+		expected: &'static str,
+		found: Option<String>,
+	},
+	#[error("invalid id: {0}")]
+	InvalidHex(std::num::ParseIntError),
+}
+
+macro_rules! define_id {
+	($(#[$meta:meta])* $name:ident, $prefix:literal) => {
+		$(#[$meta])*
+		#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+		pub struct $name(u64);
+
+		impl $name {
+			pub const PREFIX: &'static str = $prefix;
+
+			#[inline]
+			pub const fn from_raw(raw: u64) -> Self {
+				Self(raw)
+			}
+
+			#[inline]
+			pub const fn raw(self) -> u64 {
+				self.0
+			}
+		}
+
+		impl fmt::Display for $name {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				write!(f, concat!($prefix, "{:x}"), self.0)
+			}
+		}
+
+		impl FromStr for $name {
+			type Err = IdParseError;
+
+			fn from_str(s: &str) -> Result<Self, Self::Err> {
+				let Some(hex) = s.strip_prefix($prefix) else {
+					return Err(IdParseError::InvalidPrefix {
+						expected: $prefix,
+						found: s.split_once('_').map(|(prefix, _)| format!("{prefix}_")),
+					});
+				};
+				u64::from_str_radix(hex, 16)
+					.map(Self)
+					.map_err(IdParseError::InvalidHex)
+			}
+		}
+
+		impl Serialize for $name {
+			fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.collect_str(self)
+			}
+		}
+
+		impl<'de> Deserialize<'de> for $name {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+				s.parse().map_err(serde::de::Error::custom)
+			}
+		}
+	};
+}
+
+define_id!(
+	/// A monitor, as announced in `auth_ok` and `monitor_added`.
+	MonitorId,
+	"mon_"
+);
+define_id!(
+	/// A session, from `auth_ok` or `session_created`.
+	SessionId,
+	"se_"
+);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ids_round_trip_through_json() {
+		let id = MonitorId::from_raw(0x1f3a);
+		let json = serde_json::to_string(&id).unwrap();
+		assert_eq!(json, r#""mon_1f3a""#);
+		assert_eq!(serde_json::from_str::<MonitorId>(&json).unwrap(), id);
+	}
+
+	#[test]
+	fn ids_of_another_kind_are_rejected() {
+		let session = SessionId::from_raw(7).to_string();
+		assert_eq!(
+			session.parse::<MonitorId>(),
+			Err(IdParseError::InvalidPrefix {
+				expected: "mon_",
+				found: Some("se_".into()),
+			})
+		);
+	}
+}
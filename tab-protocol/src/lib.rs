@@ -5,17 +5,39 @@
 
 use serde::{Deserialize, Serialize};
 use std::{
-	os::fd::{FromRawFd, OwnedFd},
+	collections::BTreeMap,
+	os::fd::{FromRawFd, OwnedFd, RawFd},
 	str::FromStr,
 	time::Duration,
 };
 
+pub mod buffer_hash;
+mod ids;
 pub mod message_frame;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod unix_socket_utils;
+pub use ids::{IdParseError, MonitorId, SessionId};
 /// Default Unix domain socket for Tab connections.
 pub const DEFAULT_SOCKET_PATH: &str = "/tmp/shift.sock";
+/// The socket to connect to: `SHIFT_SOCKET` when set (Shift sets it for the sessions it
+/// launches), otherwise [`DEFAULT_SOCKET_PATH`]. A leading `@` names an abstract socket.
+pub fn default_socket_path() -> std::path::PathBuf {
+	std::env::var_os("SHIFT_SOCKET")
+		.filter(|v| !v.is_empty())
+		.map_or_else(|| DEFAULT_SOCKET_PATH.into(), Into::into)
+}
 /// Protocol identifier string expected in `hello` payloads. Used to check if the client and server are compatible.
 pub const PROTOCOL_VERSION: &str = const_str::concat!("tab/v", env!("CARGO_PKG_VERSION"));
+/// Whether a peer announcing `protocol` in its `hello` can talk to this build. Peers must share
+/// the major version; minor versions only add messages, optional fields and enum values, which
+/// older peers skip or decode as `Unknown`.
+pub fn is_compatible(protocol: &str) -> bool {
+	protocol
+		.strip_prefix("tab/v")
+		.and_then(|version| version.split('.').next())
+		== Some(env!("CARGO_PKG_VERSION_MAJOR"))
+}
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum BufferIndex {
@@ -42,7 +64,8 @@ pub enum TabMessage {
 	AuthError(AuthErrorPayload),
 	FramebufferLink {
 		payload: FramebufferLinkPayload,
-		dma_bufs: [OwnedFd; 2],
+		/// One fd per plane for each of the two buffers.
+		dma_bufs: [Vec<OwnedFd>; 2],
 	},
 	BufferRequest {
 		payload: BufferRequestPayload,
@@ -64,9 +87,67 @@ pub enum TabMessage {
 	SessionActive(SessionActivePayload),
 	SessionAwake(SessionAwakePayload),
 	SessionSleep(SessionSleepPayload),
+	ReleaseBuffers(ReleaseBuffersPayload),
 	Error(ErrorPayload),
 	Ping,
 	Pong,
+	FrameTrace(FrameTracePayload),
+	FrameStats(FrameStatsPayload),
+	ColorFilter(ColorFilterPayload),
+	Magnifier(MagnifierPayload),
+	DisplayAdjust(DisplayAdjustPayload),
+	ScreenRecord(ScreenRecordPayload),
+	RemoteControl(RemoteControlPayload),
+	InputInject(InputEventPayload),
+	RemoteControlState(RemoteControlStatePayload),
+	IdleInhibit(IdleInhibitPayload),
+	MonitorEnable(MonitorEnablePayload),
+	SetPrimaryMonitor(SetPrimaryMonitorPayload),
+	PrimaryMonitor(PrimaryMonitorPayload),
+	RenderQuality(RenderQualityPayload),
+	RenderCacheStats(RenderCacheStatsPayload),
+	GetSessionMetadata(GetSessionMetadataPayload),
+	SessionMetadata(SessionMetadataPayload),
+	OverlayRegions(OverlayRegionsPayload),
+	MonitorProfile(MonitorProfilePayload),
+	MonitorResized(MonitorResizedPayload),
+	MonitorModeChanged(MonitorModeChangedPayload),
+	MonitorLayout(MonitorLayoutPayload),
+	SetWallpaper(SetWallpaperPayload),
+	LatencyMode(LatencyModePayload),
+	SessionUpdate(SessionUpdatePayload),
+	AudioClients(AudioClientsPayload),
+	GetConnectorProperties(GetConnectorPropertiesPayload),
+	ConnectorProperties(ConnectorPropertiesPayload),
+	BufferHash(BufferHashPayload),
+	SessionFrameStats(SessionFrameStatsPayload),
+	PresentationFeedback(PresentationFeedbackPayload),
+	ChannelStats(ChannelStatsPayload),
+	SetColorProfile(SetColorProfilePayload),
+	SetVrr(SetVrrPayload),
+	SetOutputTransform(SetOutputTransformPayload),
+	SetMonitorScale(SetMonitorScalePayload),
+	SetMonitorMode(SetMonitorModePayload),
+	SetMonitorPosition(SetMonitorPositionPayload),
+	SetColorTemperature(SetColorTemperaturePayload),
+	SetCursor {
+		payload: SetCursorPayload,
+		/// Memfd holding the image's pixels; `None` when the cursor is hidden.
+		pixels: Option<OwnedFd>,
+	},
+	Screenshot(ScreenshotPayload),
+	ScreenshotReady {
+		payload: ScreenshotReadyPayload,
+		/// Memfd holding the captured pixels.
+		pixels: OwnedFd,
+	},
+	VirtualMonitorCreate(VirtualMonitorCreatePayload),
+	VirtualMonitorCreated(VirtualMonitorCreatedPayload),
+	VirtualMonitorRemove(VirtualMonitorRemovePayload),
+	ScreencastStart(ScreencastStartPayload),
+	ScreencastStarted(ScreencastStartedPayload),
+	ScreencastStop(ScreencastStopPayload),
+	ScreencastStopped(ScreencastStoppedPayload),
 	Unknown(TabMessageFrame),
 }
 impl TryFrom<TabMessageFrame> for TabMessage {
@@ -77,7 +158,8 @@ impl TryFrom<TabMessageFrame> for TabMessage {
 }
 
 impl TabMessage {
-	/// Parse the raw TabMessageFrame into a typed `TabMessage` variant.
+	/// Parse the raw TabMessageFrame into a typed `TabMessage` variant. The frame's fds are
+	/// owned by the message only on success; on error the caller still has to close them.
 	#[tracing::instrument(skip_all, fields(header = %msg.header.0))]
 	pub fn parse_message_frame(msg: TabMessageFrame) -> Result<Self, ProtocolError> {
 		let header = msg.header.0.as_str();
@@ -101,29 +183,47 @@ impl TabMessage {
 			}
 			message_header::FRAMEBUFFER_LINK => {
 				let payload: FramebufferLinkPayload = msg.expect_payload_json()?;
-				msg.expect_n_fds(2)?;
-				let dma_bufs = unsafe {
-					[
-						OwnedFd::from_raw_fd(msg.fds[0]),
-						OwnedFd::from_raw_fd(msg.fds[1]),
-					]
+				let planes = payload.plane_count();
+				if planes > FramebufferLinkPayload::MAX_PLANES {
+					return Err(ProtocolError::InvalidPayload(format!(
+						"framebuffer_link supports at most {} planes",
+						FramebufferLinkPayload::MAX_PLANES
+					)));
+				}
+				msg.expect_n_fds(2 * planes as u32)?;
+				let (first, second) = msg.fds.split_at(planes);
+				let own = |fds: &[RawFd]| -> Vec<OwnedFd> {
+					fds
+						.iter()
+						.map(|&fd| unsafe { OwnedFd::from_raw_fd(fd) })
+						.collect()
 				};
+				let dma_bufs = [own(first), own(second)];
 				Ok(TabMessage::FramebufferLink { payload, dma_bufs })
 			}
 			message_header::BUFFER_REQUEST => {
 				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_request" request requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
+				let err = || {
+					ProtocolError::InvalidPayload(
+						r#""buffer_request" request requires 2 or 3 arguments: <monitor_id> <0 or 1 (buffer index)> [present_at_ns]"#
+							.into(),
+					)
+				};
 				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
-				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
+				let (monitor_id, buffer_index_str, present_at) = match split[..] {
+					[monitor_id, buffer_index_str] => (monitor_id, buffer_index_str, None),
+					[monitor_id, buffer_index_str, present_at] => (
+						monitor_id,
+						buffer_index_str,
+						Some(present_at.parse().map_err(|_| err())?),
+					),
+					_ => return Err(err()),
 				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
+				let buffer_index = buffer_index_str.parse().map_err(|_| err())?;
 				let payload = BufferRequestPayload {
-					monitor_id: monitor_id.into(),
+					monitor_id: monitor_id.parse().map_err(|_| err())?,
 					buffer: buffer_index,
+					present_at,
 				};
 				let acquire_fence = match msg.fds.len() {
 					0 => None,
@@ -142,31 +242,37 @@ impl TabMessage {
 			}
 			message_header::BUFFER_REQUEST_ACK => {
 				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_request_ack" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
+				let err = || {
+					ProtocolError::InvalidPayload(
+						r#""buffer_request_ack" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
+							.into(),
+					)
+				};
 				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
 				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
+					return Err(err());
 				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
+				let monitor_id = monitor_id.parse().map_err(|_| err())?;
+				let buffer_index = buffer_index_str.parse().map_err(|_| err())?;
 				Ok(TabMessage::BufferRequestAck(BufferRequestAckPayload {
-					monitor_id: monitor_id.into(),
+					monitor_id,
 					buffer: buffer_index,
 				}))
 			}
 			message_header::BUFFER_RELEASE => {
 				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_release" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
+				let err = || {
+					ProtocolError::InvalidPayload(
+						r#""buffer_release" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
+							.into(),
+					)
+				};
 				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
 				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
+					return Err(err());
 				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
+				let monitor_id = monitor_id.parse().map_err(|_| err())?;
+				let buffer_index = buffer_index_str.parse().map_err(|_| err())?;
 				let release_fence = match msg.fds.len() {
 					0 => None,
 					1 => Some(unsafe { OwnedFd::from_raw_fd(msg.fds[0]) }),
@@ -179,7 +285,7 @@ impl TabMessage {
 				};
 				Ok(TabMessage::BufferRelease {
 					payload: BufferReleasePayload {
-						monitor_id: monitor_id.into(),
+						monitor_id,
 						buffer: buffer_index,
 					},
 					release_fence,
@@ -229,12 +335,224 @@ impl TabMessage {
 				let payload: SessionSleepPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionSleep(payload))
 			}
+			message_header::RELEASE_BUFFERS => {
+				let payload: ReleaseBuffersPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ReleaseBuffers(payload))
+			}
 			message_header::ERROR => {
 				let payload: ErrorPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::Error(payload))
 			}
 			message_header::PING => Ok(TabMessage::Ping),
 			message_header::PONG => Ok(TabMessage::Pong),
+			message_header::FRAME_TRACE => {
+				let payload: FrameTracePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FrameTrace(payload))
+			}
+			message_header::FRAME_STATS => {
+				let payload: FrameStatsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FrameStats(payload))
+			}
+			message_header::COLOR_FILTER => {
+				let payload: ColorFilterPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ColorFilter(payload))
+			}
+			message_header::MAGNIFIER => {
+				let payload: MagnifierPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Magnifier(payload))
+			}
+			message_header::DISPLAY_ADJUST => {
+				let payload: DisplayAdjustPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DisplayAdjust(payload))
+			}
+			message_header::SCREEN_RECORD => {
+				let payload: ScreenRecordPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ScreenRecord(payload))
+			}
+			message_header::REMOTE_CONTROL => {
+				let payload: RemoteControlPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::RemoteControl(payload))
+			}
+			message_header::INPUT_INJECT => {
+				let payload: InputEventPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::InputInject(payload))
+			}
+			message_header::REMOTE_CONTROL_STATE => {
+				let payload: RemoteControlStatePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::RemoteControlState(payload))
+			}
+			message_header::IDLE_INHIBIT => {
+				let payload: IdleInhibitPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::IdleInhibit(payload))
+			}
+			message_header::MONITOR_ENABLE => {
+				let payload: MonitorEnablePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorEnable(payload))
+			}
+			message_header::SET_PRIMARY_MONITOR => {
+				let payload: SetPrimaryMonitorPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetPrimaryMonitor(payload))
+			}
+			message_header::PRIMARY_MONITOR => {
+				let payload: PrimaryMonitorPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PrimaryMonitor(payload))
+			}
+			message_header::RENDER_QUALITY => {
+				let payload: RenderQualityPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::RenderQuality(payload))
+			}
+			message_header::RENDER_CACHE_STATS => {
+				let payload: RenderCacheStatsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::RenderCacheStats(payload))
+			}
+			message_header::GET_SESSION_METADATA => {
+				let payload: GetSessionMetadataPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::GetSessionMetadata(payload))
+			}
+			message_header::SESSION_METADATA => {
+				let payload: SessionMetadataPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionMetadata(payload))
+			}
+			message_header::OVERLAY_REGIONS => {
+				let payload: OverlayRegionsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::OverlayRegions(payload))
+			}
+			message_header::MONITOR_PROFILE => {
+				let payload: MonitorProfilePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorProfile(payload))
+			}
+			message_header::MONITOR_RESIZED => {
+				let payload: MonitorResizedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorResized(payload))
+			}
+			message_header::MONITOR_MODE_CHANGED => {
+				let payload: MonitorModeChangedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorModeChanged(payload))
+			}
+			message_header::MONITOR_LAYOUT => {
+				let payload: MonitorLayoutPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorLayout(payload))
+			}
+			message_header::SET_WALLPAPER => {
+				let payload: SetWallpaperPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetWallpaper(payload))
+			}
+			message_header::LATENCY_MODE => {
+				let payload: LatencyModePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::LatencyMode(payload))
+			}
+			message_header::SESSION_UPDATE => {
+				let payload: SessionUpdatePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionUpdate(payload))
+			}
+			message_header::AUDIO_CLIENTS => {
+				let payload: AudioClientsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::AudioClients(payload))
+			}
+			message_header::GET_CONNECTOR_PROPERTIES => {
+				let payload: GetConnectorPropertiesPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::GetConnectorProperties(payload))
+			}
+			message_header::CONNECTOR_PROPERTIES => {
+				let payload: ConnectorPropertiesPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ConnectorProperties(payload))
+			}
+			message_header::BUFFER_HASH => {
+				let payload: BufferHashPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::BufferHash(payload))
+			}
+			message_header::SESSION_FRAME_STATS => {
+				let payload: SessionFrameStatsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionFrameStats(payload))
+			}
+			message_header::PRESENTATION_FEEDBACK => {
+				let payload: PresentationFeedbackPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PresentationFeedback(payload))
+			}
+			message_header::CHANNEL_STATS => {
+				let payload: ChannelStatsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ChannelStats(payload))
+			}
+			message_header::SET_COLOR_PROFILE => {
+				let payload: SetColorProfilePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetColorProfile(payload))
+			}
+			message_header::SET_VRR => {
+				let payload: SetVrrPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetVrr(payload))
+			}
+			message_header::SET_OUTPUT_TRANSFORM => {
+				let payload: SetOutputTransformPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetOutputTransform(payload))
+			}
+			message_header::SET_MONITOR_SCALE => {
+				let payload: SetMonitorScalePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetMonitorScale(payload))
+			}
+			message_header::SET_MONITOR_MODE => {
+				let payload: SetMonitorModePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetMonitorMode(payload))
+			}
+			message_header::SET_MONITOR_POSITION => {
+				let payload: SetMonitorPositionPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetMonitorPosition(payload))
+			}
+			message_header::SET_COLOR_TEMPERATURE => {
+				let payload: SetColorTemperaturePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SetColorTemperature(payload))
+			}
+			message_header::SET_CURSOR => {
+				let payload: SetCursorPayload = msg.expect_payload_json()?;
+				if let Some(image) = &payload.image {
+					image
+						.validate()
+						.map_err(|e| ProtocolError::InvalidPayload(e.into()))?;
+				}
+				msg.expect_n_fds(u32::from(payload.image.is_some()))?;
+				let pixels = msg
+					.fds
+					.first()
+					.map(|&fd| unsafe { OwnedFd::from_raw_fd(fd) });
+				Ok(TabMessage::SetCursor { payload, pixels })
+			}
+			message_header::SCREENSHOT => {
+				let payload: ScreenshotPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Screenshot(payload))
+			}
+			message_header::SCREENSHOT_READY => {
+				let payload: ScreenshotReadyPayload = msg.expect_payload_json()?;
+				msg.expect_n_fds(1)?;
+				let pixels = unsafe { OwnedFd::from_raw_fd(msg.fds[0]) };
+				Ok(TabMessage::ScreenshotReady { payload, pixels })
+			}
+			message_header::VIRTUAL_MONITOR_CREATE => {
+				let payload: VirtualMonitorCreatePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::VirtualMonitorCreate(payload))
+			}
+			message_header::VIRTUAL_MONITOR_CREATED => {
+				let payload: VirtualMonitorCreatedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::VirtualMonitorCreated(payload))
+			}
+			message_header::VIRTUAL_MONITOR_REMOVE => {
+				let payload: VirtualMonitorRemovePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::VirtualMonitorRemove(payload))
+			}
+			message_header::SCREENCAST_START => {
+				let payload: ScreencastStartPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ScreencastStart(payload))
+			}
+			message_header::SCREENCAST_STARTED => {
+				let payload: ScreencastStartedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ScreencastStarted(payload))
+			}
+			message_header::SCREENCAST_STOP => {
+				let payload: ScreencastStopPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ScreencastStop(payload))
+			}
+			message_header::SCREENCAST_STOPPED => {
+				let payload: ScreencastStoppedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ScreencastStopped(payload))
+			}
 			_ => Ok(TabMessage::Unknown(msg)),
 		}
 	}
@@ -253,16 +571,174 @@ pub struct AuthPayload {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MonitorInfo {
-	pub id: String,
+	/// Stable across reconnects and restarts for the same screen on the same connector.
+	pub id: MonitorId,
+	/// Size sessions render at: the mode's size, with the axes swapped when `transform`
+	/// rotates by 90 or 270 degrees.
 	pub width: i32,
 	pub height: i32,
 	pub refresh_rate: i32,
 	pub name: String,
+	/// Where single-output clients should appear. Exactly one connected monitor is primary.
+	#[serde(default)]
+	pub primary: bool,
+	/// DRM fourccs Shift can import for this monitor, in the driver's order.
+	#[serde(default)]
+	pub formats: Vec<u32>,
+	/// The subset of `formats` the monitor can scan out without composition.
+	#[serde(default)]
+	pub scanout_formats: Vec<u32>,
+	/// Explicit DRM format modifiers Shift can import, per format. Formats missing here only
+	/// take linear or implicit (driver-chosen) layouts.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub format_modifiers: Vec<FormatModifiers>,
+	/// HDR transfer functions the monitor can display. Empty for SDR-only monitors, which
+	/// ignore `hdr_metadata` on linked buffers.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub hdr_eotfs: Vec<HdrEotf>,
+	/// Whether the monitor supports variable refresh rate. Admins turn it on with `set_vrr`.
+	#[serde(default)]
+	pub vrr_capable: bool,
+	/// Set on virtual monitors: offscreen targets an admin created for this session, captured
+	/// and streamed but never shown on a screen.
+	#[serde(default)]
+	pub offscreen: bool,
+	/// How the monitor is mounted, as set with `set_output_transform`. Shift rotates frames and
+	/// absolute input to match, so sessions only see `width` and `height`.
+	#[serde(default, skip_serializing_if = "OutputTransform::is_normal")]
+	pub transform: OutputTransform,
+	/// How much larger than at 1x sessions should draw, as set with `set_monitor_scale` or
+	/// `SHIFT_MONITOR_SCALE`. `width` and `height` stay in pixels; a session at 1.5x lays out
+	/// for `width / 1.5` points and renders at full size.
+	#[serde(default, skip_serializing_if = "MonitorScale::is_one")]
+	pub scale: MonitorScale,
+	/// Modes the connector offers, in its own orientation like the mode's size before
+	/// `transform`; admins pick one with `set_monitor_mode`. Empty for virtual monitors.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub modes: Vec<MonitorMode>,
+	/// Top-left corner of the monitor in the monitor layout, which spans `width` by `height`
+	/// from there. Always the origin for virtual monitors, which aren't part of it.
+	#[serde(default)]
+	pub position: MonitorPosition,
+}
+
+/// A point of the monitor layout, the desktop every monitor shows a part of, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MonitorPosition {
+	pub x: i32,
+	pub y: i32,
+}
+
+/// One of a monitor's display modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorMode {
+	pub width: i32,
+	pub height: i32,
+	/// In Hz.
+	pub refresh_rate: u32,
+	/// The mode the monitor asks for, which it starts in.
+	#[serde(default)]
+	pub preferred: bool,
+	/// The mode the monitor runs in.
+	#[serde(default)]
+	pub current: bool,
+}
+
+/// Rotation of a monitor's content, counter-clockwise, applied after an optional horizontal
+/// flip, like `wl_output.transform`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OutputTransform {
+	#[default]
+	#[serde(rename = "normal")]
+	Normal,
+	#[serde(rename = "90")]
+	Rotate90,
+	#[serde(rename = "180")]
+	Rotate180,
+	#[serde(rename = "270")]
+	Rotate270,
+	#[serde(rename = "flipped")]
+	Flipped,
+	#[serde(rename = "flipped_90")]
+	Flipped90,
+	#[serde(rename = "flipped_180")]
+	Flipped180,
+	#[serde(rename = "flipped_270")]
+	Flipped270,
+}
+
+impl OutputTransform {
+	pub fn is_normal(&self) -> bool {
+		*self == OutputTransform::Normal
+	}
+
+	/// Whether the monitor is on its side, so its width and height trade places.
+	pub fn swaps_axes(self) -> bool {
+		matches!(
+			self,
+			OutputTransform::Rotate90
+				| OutputTransform::Rotate270
+				| OutputTransform::Flipped90
+				| OutputTransform::Flipped270
+		)
+	}
+}
+
+/// A monitor's scale factor in 120ths, like `wp_fractional_scale_v1`, so 180 is 1.5x. Keeps
+/// [`MonitorInfo`] comparable and exact on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MonitorScale(pub u32);
+
+impl MonitorScale {
+	pub const ONE: Self = Self(120);
+	/// Smallest scale Shift applies, 0.5x.
+	pub const MIN: Self = Self(60);
+	/// Largest scale Shift applies, 4x.
+	pub const MAX: Self = Self(480);
+
+	/// The nearest scale to `factor`, within [`MIN`](Self::MIN) and [`MAX`](Self::MAX).
+	pub fn from_factor(factor: f64) -> Self {
+		if !factor.is_finite() {
+			return Self::ONE;
+		}
+		Self(
+			(factor * 120.0)
+				.round()
+				.clamp(Self::MIN.0 as f64, Self::MAX.0 as f64) as u32,
+		)
+	}
+
+	pub fn factor(self) -> f64 {
+		self.0 as f64 / 120.0
+	}
+
+	pub fn is_one(&self) -> bool {
+		*self == Self::ONE
+	}
+
+	/// `self`, within [`MIN`](Self::MIN) and [`MAX`](Self::MAX).
+	pub fn clamped(self) -> Self {
+		self.clamp(Self::MIN, Self::MAX)
+	}
+}
+
+impl Default for MonitorScale {
+	fn default() -> Self {
+		Self::ONE
+	}
+}
+
+/// DRM format modifiers, e.g. Intel Y-tiling or AMD DCC, usable with one fourcc.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatModifiers {
+	pub fourcc: u32,
+	pub modifiers: Vec<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionInfo {
-	pub id: String,
+	pub id: SessionId,
 	pub role: SessionRole,
 	pub display_name: Option<String>,
 	pub state: SessionLifecycle,
@@ -275,6 +751,11 @@ pub enum SessionLifecycle {
 	Loading,
 	Occupied,
 	Consumed,
+	/// Sent nothing within `SHIFT_SESSION_LIVENESS_SECS` while awake.
+	Unresponsive,
+	/// A state added by a newer peer.
+	#[serde(other)]
+	Unknown,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -282,12 +763,25 @@ pub enum SessionLifecycle {
 pub enum SessionRole {
 	Admin,
 	Session,
+	/// Trusted overlay (notifications, OSD, status bar): composited above the active session
+	/// in the regions it declares with `overlay_regions`, and only gets pointer and touch
+	/// input inside them.
+	Overlay,
+	/// A role added by a newer peer.
+	#[serde(other)]
+	Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AuthOkPayload {
 	pub session: SessionInfo,
 	pub monitors: Vec<MonitorInfo>,
+	/// What the admin attached when creating this session.
+	#[serde(default)]
+	pub metadata: SessionMetadata,
+	/// Render node of the GPU Shift wants buffers allocated on, e.g. `/dev/dri/renderD128`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub render_node: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -297,31 +791,131 @@ pub struct AuthErrorPayload {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FramebufferLinkPayload {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub width: i32,
 	pub height: i32,
 	pub stride: i32,
 	pub offset: i32,
 	pub fourcc: i32,
+	/// Set when the buffers hold HDR content; Shift forwards it to HDR-capable outputs while
+	/// this session is shown.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub hdr_metadata: Option<HdrMetadata>,
+	/// Planes after the first for multi-planar formats such as NV12, P010 or YUV420; `stride`
+	/// and `offset` describe the first plane. The frame carries one fd per plane for each buffer,
+	/// all planes of buffer 0 first.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub extra_planes: Vec<FramebufferPlane>,
+	/// DRM format modifier of both buffers; one the monitor lists in `format_modifiers`, or
+	/// [`FramebufferLinkPayload::MOD_LINEAR`]. Leave it out for buffers allocated without an
+	/// explicit modifier.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub modifier: Option<u64>,
+	/// Scale the buffers are drawn at. Shift resizes them by the monitor's `scale` over this one
+	/// when composing, anchored at the top left; leave it out when rendering at the monitor's
+	/// scale.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub scale: Option<MonitorScale>,
+	/// How the buffers' alpha channel is read when they are composed over other layers. Leave it
+	/// out to have the alpha channel ignored and the buffers shown opaque; formats without one
+	/// (`XR24`) are always opaque.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub alpha_mode: Option<AlphaMode>,
+}
+
+impl FramebufferLinkPayload {
+	pub const MAX_PLANES: usize = 3;
+	/// `DRM_FORMAT_MOD_LINEAR`.
+	pub const MOD_LINEAR: u64 = 0;
+	/// `DRM_FORMAT_MOD_INVALID`: the layout is implied by the allocating driver, same as no
+	/// modifier.
+	pub const MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+	/// The explicit modifier, if any.
+	pub fn explicit_modifier(&self) -> Option<u64> {
+		self
+			.modifier
+			.filter(|&modifier| modifier != Self::MOD_INVALID)
+	}
+
+	pub fn plane_count(&self) -> usize {
+		1 + self.extra_planes.len()
+	}
+}
+
+/// Layout of one plane of a linked buffer, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FramebufferPlane {
+	pub stride: i32,
+	pub offset: i32,
+}
+
+/// How the color channels of a buffer with alpha relate to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaMode {
+	/// Color channels are already multiplied by alpha, as GPUs blend.
+	Premultiplied,
+	/// Color channels are independent of alpha; Shift premultiplies them as it samples them.
+	Straight,
+}
+
+/// Transfer function of HDR content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HdrEotf {
+	/// SMPTE ST 2084.
+	Pq,
+	Hlg,
+}
+
+/// HDR static metadata (CTA-861-G, type 1) describing the content of linked buffers.
+///
+/// Chromaticities are CIE 1931 `[x, y]` in units of 0.00002; luminances are in cd/m², except
+/// `min_mastering_luminance` which is in units of 0.0001 cd/m².
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HdrMetadata {
+	pub eotf: HdrEotf,
+	/// Red, green and blue primaries of the mastering display.
+	#[serde(default)]
+	pub display_primaries: [[u16; 2]; 3],
+	#[serde(default)]
+	pub white_point: [u16; 2],
+	pub max_mastering_luminance: u16,
+	pub min_mastering_luminance: u16,
+	/// Maximum content light level.
+	pub max_cll: u16,
+	/// Maximum frame-average light level.
+	pub max_fall: u16,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BufferRequestPayload {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub buffer: BufferIndex,
+	/// Earliest `CLOCK_MONOTONIC` time, in nanoseconds, the buffer may be shown at.
+	pub present_at: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BufferRequestAckPayload {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub buffer: BufferIndex,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BufferReleasePayload {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub buffer: BufferIndex,
 }
+/// Sent right before the `buffer_release` of a buffer Shift showed, while
+/// `SHIFT_DEBUG_BUFFER_HASH` is set. `hash` is [`buffer_hash::sample_hash`] of its contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BufferHashPayload {
+	pub monitor_id: MonitorId,
+	pub buffer: BufferIndex,
+	pub hash: u64,
+}
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum InputEventPayload {
@@ -356,6 +950,8 @@ pub enum InputEventPayload {
 		delta: f64,
 		delta_discrete: Option<i32>,
 		source: AxisSource,
+		/// Missing from v1 peers, which only sent wheel-like scrolling.
+		#[serde(default)]
 		phase: AxisPhase,
 	},
 	Key {
@@ -488,6 +1084,9 @@ pub enum InputEventPayload {
 		time_usec: u64,
 		cancelled: bool,
 	},
+	/// An event kind added by a newer peer; receivers skip it.
+	#[serde(other)]
+	Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -574,9 +1173,10 @@ pub enum AxisSource {
 	WheelTilt,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AxisPhase {
 	Started,
+	#[default]
 	Moved,
 	Ended,
 	Cancelled,
@@ -601,21 +1201,169 @@ pub struct MonitorAddedPayload {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MonitorRemovedPayload {
-	pub monitor_id: String,
+	pub monitor_id: MonitorId,
 	pub name: String,
 }
 
+/// Sent when a monitor's geometry changes in place. Sessions showing on it should link
+/// buffers of the new size within `relink_deadline_ms`; until then their last frame is
+/// scaled to fit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorResizedPayload {
+	pub monitor: MonitorInfo,
+	pub relink_deadline_ms: u64,
+}
+
+/// Sent to every client when `set_monitor_mode` switched a monitor to another mode. A change of
+/// size is announced with `monitor_resized` first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorModeChangedPayload {
+	pub monitor: MonitorInfo,
+}
+
+/// Sent to every client when monitors moved in the layout, with the position of every monitor
+/// in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorLayoutPayload {
+	pub monitors: Vec<MonitorPlacement>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorPlacement {
+	pub monitor_id: MonitorId,
+	pub position: MonitorPosition,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionSwitchPayload {
-	pub session_id: String,
+	pub session_id: SessionId,
 	pub animation: Option<String>,
+	/// v1 admins sent a number of seconds instead, which is still accepted.
+	#[serde(deserialize_with = "duration_or_seconds")]
 	pub duration: Duration,
 }
 
+fn duration_or_seconds<'de, D: serde::Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Duration, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Repr {
+		Seconds(f64),
+		Duration(Duration),
+	}
+	match Repr::deserialize(deserializer)? {
+		Repr::Seconds(secs) => Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom),
+		Repr::Duration(duration) => Ok(duration),
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionCreatePayload {
 	pub role: SessionRole,
 	pub display_name: Option<String>,
+	#[serde(default)]
+	pub metadata: SessionMetadata,
+}
+
+/// Admin-provided configuration handed to a session's client after `auth_ok`: free-form
+/// attributes (user name, locale, DPI hints, ...) and environment variables for the shell.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+	#[serde(default)]
+	pub attributes: BTreeMap<String, String>,
+	#[serde(default)]
+	pub environment: BTreeMap<String, String>,
+}
+
+/// Asks for a session's metadata; `None` means the sender's own session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetSessionMetadataPayload {
+	#[serde(default)]
+	pub session_id: Option<SessionId>,
+}
+
+/// Relabels the sender's own session after creation; `None` fields stay unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionUpdatePayload {
+	#[serde(default)]
+	pub display_name: Option<String>,
+	/// Replaces the session's metadata attributes. The environment stays admin-controlled.
+	#[serde(default)]
+	pub attributes: Option<BTreeMap<String, String>>,
+}
+
+/// Sound server clients owned by the sender's session: PulseAudio client indices, which
+/// pipewire-pulse maps to PipeWire client ids. Replaces the previous list; empty unlinks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioClientsPayload {
+	pub client_ids: Vec<u32>,
+}
+
+/// Asks for the DRM properties of a monitor's connector, or of every monitor's when `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetConnectorPropertiesPayload {
+	#[serde(default)]
+	pub monitor_id: Option<MonitorId>,
+}
+
+/// Reply to `get_connector_properties`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectorPropertiesPayload {
+	pub monitors: Vec<ConnectorProperties>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectorProperties {
+	pub monitor_id: MonitorId,
+	/// The connector's name, e.g. `DP-1`.
+	pub connector: String,
+	/// Keyed by the kernel's property names, e.g. `link-status`, `max bpc`, `vrr_capable`.
+	pub properties: BTreeMap<String, ConnectorProperty>,
+}
+
+/// A connector property's current value, along with what it may be set to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConnectorProperty {
+	Bool {
+		value: bool,
+	},
+	Range {
+		value: u64,
+		min: u64,
+		max: u64,
+	},
+	SignedRange {
+		value: i64,
+		min: i64,
+		max: i64,
+	},
+	Enum {
+		value: String,
+		values: Vec<String>,
+	},
+	Bitmask {
+		value: u64,
+	},
+	/// Base64 of the blob, e.g. `EDID` or `HDR_OUTPUT_METADATA`; empty when unset.
+	Blob {
+		data: String,
+	},
+	/// The id of a DRM object such as a CRTC; 0 when unset.
+	Object {
+		id: u32,
+	},
+	/// A kind added by a newer peer.
+	#[serde(other)]
+	Unknown,
+}
+
+/// Reply to `get_session_metadata`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadataPayload {
+	pub session_id: SessionId,
+	pub metadata: SessionMetadata,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -626,7 +1374,7 @@ pub struct SessionCreatedPayload {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionReadyPayload {
-	pub session_id: String,
+	pub session_id: SessionId,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -636,23 +1384,558 @@ pub struct SessionStatePayload {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionActivePayload {
-	pub session_id: String,
+	pub session_id: SessionId,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionAwakePayload {
-	pub session_id: String,
+	pub session_id: SessionId,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionSleepPayload {
-	pub session_id: String,
+	pub session_id: SessionId,
+}
+
+/// Hint to a sleeping session that Shift is short on memory and dropped the buffers it linked,
+/// so it may free them. It has to link new ones before presenting again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseBuffersPayload {
+	pub session_id: SessionId,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ErrorPayload {
 	pub code: String,
 	pub message: Option<String>,
+	/// For `forbidden` errors, the permission the sender's role lacks.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub permission: Option<String>,
+}
+
+/// Admin request to start or stop a frame timeline capture.
+///
+/// When starting, `path` selects where the chrome-trace JSON is written once the capture is
+/// stopped; the server picks a file under `/tmp` when it is omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameTracePayload {
+	pub enabled: bool,
+	pub path: Option<String>,
+}
+
+/// Periodic frame pacing statistics for one monitor, sent to admin clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameStatsPayload {
+	pub monitor_id: MonitorId,
+	pub fps: f32,
+	pub avg_frame_ms: f32,
+	pub p99_frame_ms: f32,
+	pub missed_vblanks: u32,
+	/// Refresh periods Shift didn't commit because the output was static.
+	#[serde(default)]
+	pub skipped_commits: u32,
+}
+
+/// How one session kept up with a monitor's refresh over the last stats window. Sent to admin
+/// clients, and to the session itself when it dropped frames.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionFrameStatsPayload {
+	pub session_id: SessionId,
+	pub monitor_id: MonitorId,
+	/// New buffers that reached the screen.
+	pub presented_frames: u32,
+	/// Refreshes the session was shown on without a new buffer, counted only between two
+	/// presented frames close enough together that the session was still animating.
+	pub missed_refreshes: u32,
+	/// Most refreshes missed in a row.
+	pub longest_miss_streak: u32,
+	/// Requests for the buffer that was already on screen.
+	pub stale_presents: u32,
+}
+
+/// Sent to a session once a buffer it presented reached the screen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresentationFeedbackPayload {
+	pub monitor_id: MonitorId,
+	pub buffer: BufferIndex,
+	/// `CLOCK_MONOTONIC` time, in nanoseconds, of the flip that first showed the buffer.
+	pub timestamp_ns: u64,
+	/// Flips the monitor completed so far, counting this one.
+	pub sequence: u64,
+	/// Nominal time between two refreshes of the monitor, in nanoseconds.
+	pub refresh_ns: u64,
+}
+
+/// Configuration and overflow counters of one kind of Shift's internal channels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelStats {
+	/// `client`, `input` or `render`.
+	pub kind: String,
+	pub capacity: u64,
+	/// `block`, `drop-oldest` or `coalesce`.
+	pub overflow: String,
+	/// Sends that found their channel full, since Shift started.
+	pub saturated: u64,
+	/// Messages dropped to make room.
+	pub dropped: u64,
+	/// Messages merged into a queued one.
+	pub coalesced: u64,
+}
+
+/// Sent to admin clients whenever one of Shift's internal channels overflowed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelStatsPayload {
+	pub channels: Vec<ChannelStats>,
+}
+
+/// Periodic usage of the renderer's GPU resource cache, sent to admin clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderCacheStatsPayload {
+	pub resource_count: u64,
+	pub resource_bytes: u64,
+	pub purgeable_bytes: u64,
+	pub budget_bytes: u64,
+}
+
+/// Accessibility color filter applied to the composited output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorFilterMode {
+	#[default]
+	None,
+	Grayscale,
+	/// Daltonization for red-weak (protan) vision.
+	Protanopia,
+	/// Daltonization for green-weak (deutan) vision.
+	Deuteranopia,
+	/// Daltonization for blue-weak (tritan) vision.
+	Tritanopia,
+}
+
+/// Admin request to change the color filter of one monitor, or of all monitors when
+/// `monitor_id` is omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorFilterPayload {
+	pub monitor_id: Option<MonitorId>,
+	pub mode: ColorFilterMode,
+}
+
+/// Admin request to zoom around the pointer on one monitor, or on all monitors when
+/// `monitor_id` is omitted. A `factor` of `1.0` turns the magnifier off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MagnifierPayload {
+	pub monitor_id: Option<MonitorId>,
+	pub factor: f32,
+}
+
+/// Admin request to set software brightness/contrast/gamma on a monitor. The identity is
+/// `brightness: 0.0, contrast: 1.0, gamma: 1.0`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayAdjustPayload {
+	pub monitor_id: MonitorId,
+	pub brightness: f32,
+	pub contrast: f32,
+	pub gamma: f32,
+}
+
+/// Admin request to start or stop recording a monitor to a video file. `path`, the duration
+/// cap and the size cap are only read when starting; omitted values use server defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenRecordPayload {
+	pub monitor_id: MonitorId,
+	pub enabled: bool,
+	#[serde(default)]
+	pub path: Option<String>,
+	#[serde(default)]
+	pub max_duration_secs: Option<u64>,
+	#[serde(default)]
+	pub max_size_mb: Option<u64>,
+}
+
+/// Admin request to take or release remote control of a monitor. While held, the admin may
+/// inject input with `input_inject`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteControlPayload {
+	pub monitor_id: MonitorId,
+	pub enabled: bool,
+}
+
+/// Broadcast to every session whenever remote control starts or stops, so sessions can show
+/// an indicator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteControlStatePayload {
+	pub active: bool,
+	pub monitor_id: Option<MonitorId>,
+}
+
+/// Session request to keep the outputs from idling, e.g. during video playback. Only the
+/// active session may inhibit; the inhibitor is dropped when the session goes to sleep.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleInhibitPayload {
+	pub inhibit: bool,
+}
+
+/// Admin request to stop or resume composing a connected monitor. Disabled monitors are
+/// announced to clients as removed and come back as added.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorEnablePayload {
+	pub monitor_id: MonitorId,
+	pub enabled: bool,
+}
+
+/// What a `monitor_profile` request does with the profile of the connected monitors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorProfileAction {
+	/// Store the current enabled state, primary monitor and display adjustments.
+	Save,
+	/// Forget the stored profile; the current settings stay in effect.
+	Delete,
+}
+
+/// Admin request to save or delete the monitor profile of the currently connected monitors.
+/// Saved profiles are reapplied whenever the same set of monitors is connected again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorProfilePayload {
+	pub action: MonitorProfileAction,
+}
+
+/// What a monitor shows where no session has a frame to cover it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Wallpaper {
+	/// Solid `[r, g, b]` color.
+	Color { rgb: [u8; 3] },
+	/// Image file read by the server (PNG, JPEG, WebP, ...), scaled to cover the monitor.
+	Image { path: String },
+}
+
+/// Admin request to change the wallpaper of one monitor, or of all monitors when `monitor_id`
+/// is omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetWallpaperPayload {
+	pub monitor_id: Option<MonitorId>,
+	pub wallpaper: Wallpaper,
+}
+
+/// How Shift corrects colors for a monitor before scanout. Session content is assumed to be
+/// sRGB and is converted into the monitor's color space.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ColorProfile {
+	/// No correction: the monitor is treated as sRGB.
+	None,
+	/// Primaries, white point and gamma the monitor's EDID reports.
+	Edid,
+	/// ICC display profile read by the server; only matrix/TRC profiles are supported.
+	Icc { path: String },
+}
+
+/// Admin request to change the color profile of one monitor, or of all monitors when
+/// `monitor_id` is omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetColorProfilePayload {
+	pub monitor_id: Option<MonitorId>,
+	pub profile: ColorProfile,
+}
+
+/// Admin request to turn variable refresh rate on or off for one monitor, or for all monitors
+/// when `monitor_id` is omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetVrrPayload {
+	pub monitor_id: Option<MonitorId>,
+	pub enabled: bool,
+}
+
+/// Admin request to rotate or flip a monitor, e.g. one mounted in portrait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetOutputTransformPayload {
+	pub monitor_id: MonitorId,
+	pub transform: OutputTransform,
+}
+
+/// Admin request to change a monitor's scale factor; Shift clamps it to
+/// [`MonitorScale::MIN`]..=[`MonitorScale::MAX`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetMonitorScalePayload {
+	pub monitor_id: MonitorId,
+	pub scale: MonitorScale,
+}
+
+/// Admin request to switch a monitor to one of the [`MonitorInfo::modes`] it lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetMonitorModePayload {
+	pub monitor_id: MonitorId,
+	pub width: i32,
+	pub height: i32,
+	/// In Hz.
+	pub refresh_rate: u32,
+}
+
+/// Admin request to move a monitor to `position` in the layout, or to leave its placement to
+/// Shift when `position` is omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetMonitorPositionPayload {
+	pub monitor_id: MonitorId,
+	#[serde(default)]
+	pub position: Option<MonitorPosition>,
+}
+
+/// Admin request to warm one monitor's colors, or every monitor's when `monitor_id` is omitted.
+/// `kelvin` is clamped to [`NightLightSchedule::MIN_KELVIN`] and
+/// [`NightLightSchedule::NEUTRAL_KELVIN`], which leaves colors untouched. With a `schedule` the
+/// warmth only applies at night.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetColorTemperaturePayload {
+	pub monitor_id: Option<MonitorId>,
+	pub kelvin: u32,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub schedule: Option<NightLightSchedule>,
+}
+
+/// Local times, in minutes after midnight, between which a night light is on. Shift fades in
+/// after `sunset` and back out before `sunrise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NightLightSchedule {
+	pub sunset: u16,
+	pub sunrise: u16,
+}
+
+impl NightLightSchedule {
+	/// Color temperature of unaltered output.
+	pub const NEUTRAL_KELVIN: u32 = 6500;
+	/// Warmest color temperature Shift applies.
+	pub const MIN_KELVIN: u32 = 1000;
+}
+
+/// Layout of a cursor image: premultiplied ARGB8888 pixels (`B, G, R, A` in memory), `stride`
+/// bytes per row, from the start of the memfd sent along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorImageInfo {
+	pub width: u32,
+	pub height: u32,
+	pub stride: u32,
+	/// The pixel of the image at the pointer position.
+	pub hotspot_x: u32,
+	pub hotspot_y: u32,
+}
+
+impl CursorImageInfo {
+	/// Largest cursor Shift takes, in either dimension.
+	pub const MAX_SIZE: u32 = 256;
+
+	pub fn validate(&self) -> Result<(), &'static str> {
+		if self.width == 0 || self.height == 0 {
+			return Err("cursor image is empty");
+		}
+		if self.width > Self::MAX_SIZE || self.height > Self::MAX_SIZE {
+			return Err("cursor image is larger than 256x256");
+		}
+		if self.stride < self.width * 4 {
+			return Err("cursor stride is shorter than a row");
+		}
+		if self.hotspot_x >= self.width || self.hotspot_y >= self.height {
+			return Err("cursor hotspot is outside the image");
+		}
+		Ok(())
+	}
+}
+
+/// Session request to show `image` as its pointer cursor, moved by Shift at input rate, or to
+/// hide the cursor when `image` is omitted. Shift keeps one cursor per session and shows the
+/// current session's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetCursorPayload {
+	#[serde(default)]
+	pub image: Option<CursorImageInfo>,
+}
+
+/// Admin request to capture what `monitor_id` currently shows. Shift answers with
+/// `screenshot_ready`, or with a `screenshot_failed` error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenshotPayload {
+	pub monitor_id: MonitorId,
+}
+
+/// A captured frame: ARGB8888 pixels (`B, G, R, A` in memory, alpha opaque), `stride` bytes
+/// per row, from the start of the memfd sent along.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenshotReadyPayload {
+	pub monitor_id: MonitorId,
+	pub width: u32,
+	pub height: u32,
+	pub stride: u32,
+}
+
+/// Admin request to create a virtual monitor for `session_id`: an offscreen target only that
+/// session sees, composed like a monitor for screenshots and streaming but never scanned out.
+/// Shift answers with `virtual_monitor_created`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualMonitorCreatePayload {
+	pub session_id: SessionId,
+	pub width: u32,
+	pub height: u32,
+	/// Frames per second the monitor is composed at; 60 when omitted.
+	#[serde(default)]
+	pub refresh_rate: Option<u32>,
+	/// Mirror what the session shows on this monitor instead of taking buffers of its own.
+	/// The session isn't told about a mirror and keeps presenting at the source's size.
+	#[serde(default)]
+	pub mirror: Option<MonitorId>,
+	/// How a mirror fits the source's frames into its own size.
+	#[serde(default)]
+	pub scaling: MirrorScaling,
+}
+
+/// How a mirroring virtual monitor scales the source's frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorScaling {
+	/// Keep the aspect ratio and letterbox with black.
+	#[default]
+	Fit,
+	/// Keep the aspect ratio and crop what overflows.
+	Fill,
+	/// Stretch to the mirror's size.
+	Stretch,
+}
+
+impl VirtualMonitorCreatePayload {
+	/// Largest virtual monitor Shift creates, in either dimension.
+	pub const MAX_SIZE: u32 = 8192;
+	pub const DEFAULT_REFRESH_RATE: u32 = 60;
+	pub const MAX_REFRESH_RATE: u32 = 240;
+
+	pub fn refresh_rate(&self) -> u32 {
+		self.refresh_rate.unwrap_or(Self::DEFAULT_REFRESH_RATE)
+	}
+
+	pub fn validate(&self) -> Result<(), &'static str> {
+		if self.width == 0 || self.height == 0 {
+			return Err("virtual monitor is empty");
+		}
+		if self.width > Self::MAX_SIZE || self.height > Self::MAX_SIZE {
+			return Err("virtual monitor is larger than 8192x8192");
+		}
+		if !(1..=Self::MAX_REFRESH_RATE).contains(&self.refresh_rate()) {
+			return Err("virtual monitor refresh rate is outside 1-240");
+		}
+		Ok(())
+	}
+}
+
+/// Reply to `virtual_monitor_create`. Unless the monitor is a mirror, the session receives
+/// `monitor_added` for `monitor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualMonitorCreatedPayload {
+	pub session_id: SessionId,
+	pub monitor: MonitorInfo,
+}
+
+/// Admin request to remove a virtual monitor created with `virtual_monitor_create`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualMonitorRemovePayload {
+	pub monitor_id: MonitorId,
+}
+
+/// Request to share `monitor_id`'s composed frames as a PipeWire stream. Shift answers with
+/// `screencast_started`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreencastStartPayload {
+	pub monitor_id: MonitorId,
+}
+
+/// The screencast of `monitor_id` is available as PipeWire node `node_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreencastStartedPayload {
+	pub monitor_id: MonitorId,
+	pub node_id: u32,
+}
+
+/// Ends the sender's screencast of `monitor_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreencastStopPayload {
+	pub monitor_id: MonitorId,
+}
+
+/// The sender's screencast of `monitor_id` ended, on request or because `reason` happened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreencastStoppedPayload {
+	pub monitor_id: MonitorId,
+	pub reason: String,
+}
+
+/// A rectangle in monitor pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayRegion {
+	pub x: i32,
+	pub y: i32,
+	pub width: u32,
+	pub height: u32,
+}
+
+/// Overlay request replacing the regions it occupies on `monitor_id`; empty removes the
+/// overlay from that monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayRegionsPayload {
+	pub monitor_id: MonitorId,
+	pub regions: Vec<OverlayRegion>,
+}
+
+/// Admin request to make `monitor_id` the primary monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetPrimaryMonitorPayload {
+	pub monitor_id: MonitorId,
+}
+
+/// Broadcast when the primary monitor changes; `None` when no monitor is connected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimaryMonitorPayload {
+	pub monitor_id: Option<MonitorId>,
+}
+
+/// Filter Shift uses when a session's buffers are scaled to the monitor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingFilter {
+	/// Sharp pixels; exact for buffers at the monitor's native size.
+	#[default]
+	Nearest,
+	/// Bilinear; smooth for non-native buffers.
+	Linear,
+	/// Bicubic (Mitchell-Netravali); softer than `CatmullRom` but without ringing.
+	Mitchell,
+	/// Bicubic (Catmull-Rom); the sharpest choice for upscaling low-resolution video.
+	CatmullRom,
+}
+
+/// Session request for how Shift samples its buffers during composition.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderQualityPayload {
+	pub filter: SamplingFilter,
+	/// Sample from mipmaps when the buffer is drawn smaller than its size (thumbnails,
+	/// downscaled high-resolution buffers). Costs a mip chain rebuild per new frame.
+	#[serde(default)]
+	pub mipmaps: bool,
+}
+
+/// How Shift paces frames on the monitors showing a session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyMode {
+	/// Compose as late as possible before each vblank so the newest buffer makes it on screen.
+	Low,
+	/// Compose right after each page flip.
+	#[default]
+	Balanced,
+	/// Compose on every other vblank at most.
+	PowerSave,
+}
+
+/// Session hint for how its monitors are paced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyModePayload {
+	pub mode: LatencyMode,
 }
 
 pub use message_header::MessageHeader;
@@ -661,4 +1944,4 @@ pub mod message_header;
 mod error;
 pub use error::*;
 
-pub use crate::message_frame::{TabMessageFrame, TabMessageFrameReader};
+pub use crate::message_frame::{TabFrameEncoder, TabMessageFrame, TabMessageFrameReader};
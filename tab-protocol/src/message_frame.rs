@@ -34,7 +34,11 @@ impl TabMessageFrameReader {
 		self.pop_ready()
 	}
 	#[tracing::instrument(skip_all)]
-	fn feed_chunk(&mut self, bytes: &[u8], mut fds: Vec<RawFd>) -> Result<(), ProtocolError> {
+	pub(crate) fn feed_chunk(
+		&mut self,
+		bytes: &[u8],
+		mut fds: Vec<RawFd>,
+	) -> Result<(), ProtocolError> {
 		if !bytes.is_empty() {
 			self.pending_bytes.extend_from_slice(bytes);
 		}
@@ -130,33 +134,49 @@ fn recv_into_vec(stream: &impl AsRawFd) -> Result<(Vec<u8>, Vec<RawFd>), Protoco
 	let data = iov[0][..bytes].to_vec();
 	Ok((data, fds))
 }
+/// Sends the slices as one message, with `fds` attached.
+fn send_slices(stream: &impl AsRawFd, iov: &[IoSlice], fds: &[RawFd]) -> Result<(), ProtocolError> {
+	let rights = [ControlMessage::ScmRights(fds)];
+	let cmsg: &[ControlMessage] = if fds.is_empty() { &[] } else { &rights };
+	loop {
+		match sendmsg::<()>(stream.as_raw_fd(), iov, cmsg, MsgFlags::empty(), None) {
+			Err(Errno::EINTR) => continue,
+			Err(errno) if errno == Errno::EAGAIN || errno == Errno::EWOULDBLOCK => {
+				break Err(ProtocolError::WouldBlock);
+			}
+			Err(errno) => break Err(ProtocolError::Nix(errno)),
+			Ok(_) => break Ok(()),
+		}
+	}
+}
+
+/// Payload line of frames without a payload.
+const NO_PAYLOAD: &str = "\0\0\0\0";
+
 impl TabMessageFrame {
 	/// Write a framed TabMessageFrame to the provided stream using sendmsg/SCM_RIGHTS.
 	pub fn encode_and_send(&self, stream: &impl AsRawFd) -> Result<(), ProtocolError> {
-		let (encoded_header, encoded_payload) = self.serialize();
-		let encoded_header = format!("{encoded_header}\n");
-		let encoded_payload = format!("{encoded_payload}\n");
+		let (header_line, payload_line) = self.lines();
 		let iov = [
-			IoSlice::new(encoded_header.as_bytes()),
-			IoSlice::new(encoded_payload.as_bytes()),
+			IoSlice::new(header_line.as_bytes()),
+			IoSlice::new(b"\n"),
+			IoSlice::new(payload_line.as_bytes()),
+			IoSlice::new(b"\n"),
 		];
-		let cmsg = if self.fds.is_empty() {
-			vec![]
-		} else {
-			vec![ControlMessage::ScmRights(&self.fds)]
-		};
-		sendmsg::<()>(stream.as_raw_fd(), &iov, &cmsg, MsgFlags::empty(), None)?;
-		Ok(())
+		send_slices(stream, &iov, &self.fds)
 	}
 	pub fn serialize(&self) -> (String, String) {
+		let (header_line, payload_line) = self.lines();
+		(header_line.to_string(), payload_line.to_string())
+	}
+	fn lines(&self) -> (&str, &str) {
 		let header_line = self.header.0.trim_end();
 		let payload_line = self
 			.payload
 			.as_ref()
 			.map(|p| p.trim_end_matches('\n'))
-			.unwrap_or_else(|| "\0\0\0\0");
-
-		(header_line.to_string(), payload_line.to_string())
+			.unwrap_or(NO_PAYLOAD);
+		(header_line, payload_line)
 	}
 
 	/// Sends a message asynchronously
@@ -255,7 +275,7 @@ impl TabMessageFrame {
 		Ok(Some((frame, consumed)))
 	}
 
-	fn from_lines(
+	pub(crate) fn from_lines(
 		header_bytes: &[u8],
 		payload_bytes: &[u8],
 		fds: Vec<RawFd>,
@@ -264,7 +284,7 @@ impl TabMessageFrame {
 		let payload_str = String::from_utf8(payload_bytes.to_vec())?;
 		Ok(Self {
 			header: header.into(),
-			payload: if payload_str == "\0\0\0\0" {
+			payload: if payload_str == NO_PAYLOAD {
 				None
 			} else {
 				Some(payload_str)
@@ -273,3 +293,96 @@ impl TabMessageFrame {
 		})
 	}
 }
+
+/// Encodes frames into a buffer kept between sends, for messages sent at input rates: once the
+/// buffer has grown to the largest frame, encoding and sending one allocates nothing.
+#[derive(Debug, Default)]
+pub struct TabFrameEncoder {
+	buf: Vec<u8>,
+}
+
+impl TabFrameEncoder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Encodes a frame carrying `payload` as JSON, without fds. The bytes stay valid until the
+	/// next call.
+	pub fn encode_json(
+		&mut self,
+		header: &str,
+		payload: &impl Serialize,
+	) -> Result<&[u8], ProtocolError> {
+		self.buf.clear();
+		self.buf.extend_from_slice(header.trim_end().as_bytes());
+		self.buf.push(b'\n');
+		serde_json::to_writer(&mut self.buf, payload)?;
+		self.buf.push(b'\n');
+		Ok(&self.buf)
+	}
+
+	/// Encodes a JSON frame and writes it to the provided stream.
+	pub fn send_json(
+		&mut self,
+		stream: &impl AsRawFd,
+		header: &str,
+		payload: &impl Serialize,
+	) -> Result<(), ProtocolError> {
+		let bytes = self.encode_json(header, payload)?;
+		send_slices(stream, &[IoSlice::new(bytes)], &[])
+	}
+
+	/// Encodes a JSON frame and sends it asynchronously.
+	#[cfg(feature = "async")]
+	pub async fn send_json_to_async_fd<T: AsRawFd>(
+		&mut self,
+		fd: &tokio::io::unix::AsyncFd<T>,
+		header: &str,
+		payload: &impl Serialize,
+	) -> Result<(), ProtocolError> {
+		let bytes = self.encode_json(header, payload)?;
+		loop {
+			let mut guard = fd.writable().await?;
+			if let Ok(result) = guard.try_io(|_| match send_slices(fd, &[IoSlice::new(bytes)], &[]) {
+				Err(ProtocolError::WouldBlock) => Err(would_block_err()),
+				def => Ok(def),
+			}) {
+				break result?;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{ButtonState, InputEventPayload, message_header};
+
+	#[test]
+	fn encoded_frames_parse_like_json_frames() {
+		let mut encoder = TabFrameEncoder::new();
+		let event = InputEventPayload::PointerButton {
+			device: 3,
+			time_usec: 1_000,
+			button: 272,
+			state: ButtonState::Pressed,
+		};
+		let expected = TabMessageFrame::json(message_header::INPUT_EVENT, &event);
+		for _ in 0..2 {
+			let bytes = encoder
+				.encode_json(message_header::INPUT_EVENT, &event)
+				.expect("encode");
+			let (frame, used) = TabMessageFrame::parse_from_bytes(bytes, Vec::new())
+				.expect("parse")
+				.expect("complete frame");
+			assert_eq!(used, bytes.len());
+			assert_eq!(frame, expected);
+		}
+	}
+
+	#[test]
+	fn frames_without_payload_keep_their_marker() {
+		let frame = TabMessageFrame::no_payload("ping");
+		assert_eq!(frame.serialize(), ("ping".into(), NO_PAYLOAD.into()));
+	}
+}
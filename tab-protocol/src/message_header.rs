@@ -32,9 +32,59 @@ define_headers! {
 		SESSION_ACTIVE,
 		SESSION_AWAKE,
 		SESSION_SLEEP,
+		RELEASE_BUFFERS,
 		ERROR,
 		PING,
 		PONG,
+		FRAME_TRACE,
+		FRAME_STATS,
+		COLOR_FILTER,
+		MAGNIFIER,
+		DISPLAY_ADJUST,
+		SCREEN_RECORD,
+		REMOTE_CONTROL,
+		INPUT_INJECT,
+		REMOTE_CONTROL_STATE,
+		IDLE_INHIBIT,
+		MONITOR_ENABLE,
+		SET_PRIMARY_MONITOR,
+		PRIMARY_MONITOR,
+		RENDER_QUALITY,
+		RENDER_CACHE_STATS,
+		GET_SESSION_METADATA,
+		SESSION_METADATA,
+		OVERLAY_REGIONS,
+		MONITOR_PROFILE,
+		MONITOR_RESIZED,
+		MONITOR_MODE_CHANGED,
+		MONITOR_LAYOUT,
+		SET_WALLPAPER,
+		LATENCY_MODE,
+		SESSION_UPDATE,
+		AUDIO_CLIENTS,
+		GET_CONNECTOR_PROPERTIES,
+		CONNECTOR_PROPERTIES,
+		BUFFER_HASH,
+		SESSION_FRAME_STATS,
+		PRESENTATION_FEEDBACK,
+		CHANNEL_STATS,
+		SET_COLOR_PROFILE,
+		SET_VRR,
+		SET_OUTPUT_TRANSFORM,
+		SET_MONITOR_SCALE,
+		SET_MONITOR_MODE,
+		SET_MONITOR_POSITION,
+		SET_COLOR_TEMPERATURE,
+		SET_CURSOR,
+		SCREENSHOT,
+		SCREENSHOT_READY,
+		VIRTUAL_MONITOR_CREATE,
+		VIRTUAL_MONITOR_CREATED,
+		VIRTUAL_MONITOR_REMOVE,
+		SCREENCAST_START,
+		SCREENCAST_STARTED,
+		SCREENCAST_STOP,
+		SCREENCAST_STOPPED,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
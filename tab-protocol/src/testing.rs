@@ -0,0 +1,901 @@
+//! Conformance harness for the Tab framing and message parsing, behind the `testing` feature.
+//!
+//! [`Generate`] builds random values of every payload type, [`random_frame`] and [`corpus`]
+//! turn them into frames and wire bytes, and [`check_bytes`] runs arbitrary bytes through the
+//! parser while checking its invariants; the fuzz targets in `tab-protocol/fuzz` call it.
+//!
+//! Adding a field to a payload breaks the build here until its generator knows the field, and
+//! every message parsed from JSON has to be listed in `json_messages!`, so new fields and
+//! messages are covered by the round-trip tests below.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::*;
+
+/// Small deterministic generator (splitmix64), so failures reproduce from the seed alone.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Self(seed)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/// Uniform in `0..n`; `n` must not be zero.
+	pub fn below(&mut self, n: u64) -> u64 {
+		self.next_u64() % n
+	}
+
+	pub fn chance(&mut self) -> bool {
+		self.next_u64() & 1 == 1
+	}
+}
+
+/// Random values for property tests.
+pub trait Generate: Sized {
+	fn generate(rng: &mut Rng) -> Self;
+}
+
+macro_rules! generate_ints {
+	($($ty:ty),*) => {
+		$(impl Generate for $ty {
+			fn generate(rng: &mut Rng) -> Self {
+				// Bias towards the edges, where encodings tend to break.
+				match rng.below(4) {
+					0 => <$ty>::MIN,
+					1 => <$ty>::MAX,
+					_ => rng.next_u64() as $ty,
+				}
+			}
+		})*
+	};
+}
+generate_ints!(u8, u16, u32, u64, i32, i64);
+
+impl Generate for bool {
+	fn generate(rng: &mut Rng) -> Self {
+		rng.chance()
+	}
+}
+
+/// Quarters in a range JSON encodes and parses exactly.
+impl Generate for f64 {
+	fn generate(rng: &mut Rng) -> Self {
+		(rng.below(2_000_001) as f64 - 1_000_000.0) / 4.0
+	}
+}
+
+impl Generate for f32 {
+	fn generate(rng: &mut Rng) -> Self {
+		(rng.below(200_001) as f32 - 100_000.0) / 4.0
+	}
+}
+
+/// Includes characters the framing must survive: quotes, escapes, newlines and NULs.
+impl Generate for String {
+	fn generate(rng: &mut Rng) -> Self {
+		const CHARS: &[char] = &[
+			'a', 'Z', '0', ' ', '"', '\\', '\n', '\r', '\t', '\0', '/', '{', '}', 'é', '漢', '🦀',
+		];
+		let len = rng.below(16) as usize;
+		(0..len)
+			.map(|_| CHARS[rng.below(CHARS.len() as u64) as usize])
+			.collect()
+	}
+}
+
+impl<T: Generate> Generate for Option<T> {
+	fn generate(rng: &mut Rng) -> Self {
+		rng.chance().then(|| T::generate(rng))
+	}
+}
+
+impl<T: Generate> Generate for Vec<T> {
+	fn generate(rng: &mut Rng) -> Self {
+		let len = rng.below(5) as usize;
+		(0..len).map(|_| T::generate(rng)).collect()
+	}
+}
+
+impl<T: Generate, const N: usize> Generate for [T; N] {
+	fn generate(rng: &mut Rng) -> Self {
+		std::array::from_fn(|_| T::generate(rng))
+	}
+}
+
+impl<V: Generate> Generate for BTreeMap<String, V> {
+	fn generate(rng: &mut Rng) -> Self {
+		Vec::<(String, V)>::generate(rng).into_iter().collect()
+	}
+}
+
+impl<A: Generate, B: Generate> Generate for (A, B) {
+	fn generate(rng: &mut Rng) -> Self {
+		(A::generate(rng), B::generate(rng))
+	}
+}
+
+impl Generate for MonitorId {
+	fn generate(rng: &mut Rng) -> Self {
+		Self::from_raw(Generate::generate(rng))
+	}
+}
+
+impl Generate for SessionId {
+	fn generate(rng: &mut Rng) -> Self {
+		Self::from_raw(Generate::generate(rng))
+	}
+}
+
+impl Generate for MonitorScale {
+	fn generate(rng: &mut Rng) -> Self {
+		Self(Generate::generate(rng))
+	}
+}
+
+impl Generate for Duration {
+	fn generate(rng: &mut Rng) -> Self {
+		Duration::new(rng.below(1 << 40), rng.below(1_000_000_000) as u32)
+	}
+}
+
+macro_rules! generate_structs {
+	($($ty:ident { $($field:ident),* $(,)? })*) => {
+		$(impl Generate for $ty {
+			fn generate(rng: &mut Rng) -> Self {
+				Self { $($field: Generate::generate(rng)),* }
+			}
+		})*
+	};
+}
+
+macro_rules! generate_enums {
+	($($ty:ident { $($variant:ident $({ $($field:ident),* $(,)? })?),* $(,)? })*) => {
+		$(impl Generate for $ty {
+			fn generate(rng: &mut Rng) -> Self {
+				let variants: &[fn(&mut Rng) -> Self] = &[$(
+					|rng: &mut Rng| {
+						let _ = &rng;
+						Self::$variant $({ $($field: Generate::generate(rng)),* })?
+					}
+				),*];
+				variants[rng.below(variants.len() as u64) as usize](rng)
+			}
+		})*
+	};
+}
+
+generate_structs! {
+	HelloPayload { server, protocol }
+	AuthPayload { token }
+	MonitorInfo { id, width, height, refresh_rate, name, primary, formats, scanout_formats, format_modifiers, hdr_eotfs, vrr_capable, offscreen, transform, scale, modes, position }
+	MonitorPosition { x, y }
+	MonitorMode { width, height, refresh_rate, preferred, current }
+	FormatModifiers { fourcc, modifiers }
+	SessionInfo { id, role, display_name, state }
+	AuthOkPayload { session, monitors, metadata, render_node }
+	AuthErrorPayload { error }
+	FramebufferPlane { stride, offset }
+	HdrMetadata {
+		eotf,
+		display_primaries,
+		white_point,
+		max_mastering_luminance,
+		min_mastering_luminance,
+		max_cll,
+		max_fall,
+	}
+	TouchContact { id, x, y, x_transformed, y_transformed }
+	TabletTool { serial, tool_type, capability }
+	TabletToolCapability { pressure, distance, tilt, rotation, slider, wheel }
+	TabletToolAxes { x, y, pressure, distance, tilt_x, tilt_y, rotation, slider, wheel_delta, buttons }
+	MonitorAddedPayload { monitor }
+	MonitorRemovedPayload { monitor_id, name }
+	BufferRequestPayload { monitor_id, buffer, present_at }
+	BufferRequestAckPayload { monitor_id, buffer }
+	BufferReleasePayload { monitor_id, buffer }
+	MonitorResizedPayload { monitor, relink_deadline_ms }
+	MonitorModeChangedPayload { monitor }
+	MonitorLayoutPayload { monitors }
+	MonitorPlacement { monitor_id, position }
+	SessionSwitchPayload { session_id, animation, duration }
+	SessionCreatePayload { role, display_name, metadata }
+	SessionMetadata { attributes, environment }
+	GetSessionMetadataPayload { session_id }
+	SessionUpdatePayload { display_name, attributes }
+	AudioClientsPayload { client_ids }
+	GetConnectorPropertiesPayload { monitor_id }
+	ConnectorPropertiesPayload { monitors }
+	ConnectorProperties { monitor_id, connector, properties }
+	BufferHashPayload { monitor_id, buffer, hash }
+	SessionFrameStatsPayload { session_id, monitor_id, presented_frames, missed_refreshes, longest_miss_streak, stale_presents }
+	PresentationFeedbackPayload { monitor_id, buffer, timestamp_ns, sequence, refresh_ns }
+	SessionMetadataPayload { session_id, metadata }
+	SessionCreatedPayload { session, token }
+	SessionReadyPayload { session_id }
+	SessionStatePayload { session }
+	SessionActivePayload { session_id }
+	SessionAwakePayload { session_id }
+	SessionSleepPayload { session_id }
+	ReleaseBuffersPayload { session_id }
+	ErrorPayload { code, message, permission }
+	FrameTracePayload { enabled, path }
+	FrameStatsPayload { monitor_id, fps, avg_frame_ms, p99_frame_ms, missed_vblanks, skipped_commits }
+	RenderCacheStatsPayload { resource_count, resource_bytes, purgeable_bytes, budget_bytes }
+	ChannelStats { kind, capacity, overflow, saturated, dropped, coalesced }
+	ChannelStatsPayload { channels }
+	ColorFilterPayload { monitor_id, mode }
+	MagnifierPayload { monitor_id, factor }
+	DisplayAdjustPayload { monitor_id, brightness, contrast, gamma }
+	ScreenRecordPayload { monitor_id, enabled, path, max_duration_secs, max_size_mb }
+	RemoteControlPayload { monitor_id, enabled }
+	RemoteControlStatePayload { active, monitor_id }
+	IdleInhibitPayload { inhibit }
+	MonitorEnablePayload { monitor_id, enabled }
+	MonitorProfilePayload { action }
+	SetWallpaperPayload { monitor_id, wallpaper }
+	SetColorProfilePayload { monitor_id, profile }
+	SetVrrPayload { monitor_id, enabled }
+	SetOutputTransformPayload { monitor_id, transform }
+	SetMonitorScalePayload { monitor_id, scale }
+	SetMonitorModePayload { monitor_id, width, height, refresh_rate }
+	SetMonitorPositionPayload { monitor_id, position }
+	SetColorTemperaturePayload { monitor_id, kelvin, schedule }
+	NightLightSchedule { sunset, sunrise }
+	CursorImageInfo { width, height, stride, hotspot_x, hotspot_y }
+	SetCursorPayload { image }
+	ScreenshotPayload { monitor_id }
+	ScreenshotReadyPayload { monitor_id, width, height, stride }
+	VirtualMonitorCreatePayload { session_id, width, height, refresh_rate, mirror, scaling }
+	VirtualMonitorCreatedPayload { session_id, monitor }
+	VirtualMonitorRemovePayload { monitor_id }
+	ScreencastStartPayload { monitor_id }
+	ScreencastStartedPayload { monitor_id, node_id }
+	ScreencastStopPayload { monitor_id }
+	ScreencastStoppedPayload { monitor_id, reason }
+	OverlayRegion { x, y, width, height }
+	OverlayRegionsPayload { monitor_id, regions }
+	SetPrimaryMonitorPayload { monitor_id }
+	PrimaryMonitorPayload { monitor_id }
+	RenderQualityPayload { filter, mipmaps }
+	LatencyModePayload { mode }
+}
+
+generate_enums! {
+	SessionLifecycle { Pending, Loading, Occupied, Consumed, Unresponsive }
+	SessionRole { Admin, Session, Overlay }
+	HdrEotf { Pq, Hlg }
+	AlphaMode { Premultiplied, Straight }
+	BufferIndex { Zero, One }
+	ButtonState { Pressed, Released }
+	KeyState { Pressed, Released }
+	TipState { Down, Up }
+	TabletToolType { Pen, Eraser, Brush, Pencil, Airbrush, Finger, Mouse, Lens }
+	AxisOrientation { Vertical, Horizontal }
+	AxisSource { Wheel, Finger, Continuous, WheelTilt }
+	AxisPhase { Started, Moved, Ended, Cancelled }
+	SwitchType { Lid, TabletMode }
+	SwitchState { On, Off }
+	ColorFilterMode { None, Grayscale, Protanopia, Deuteranopia, Tritanopia }
+	MonitorProfileAction { Save, Delete }
+	Wallpaper { Color { rgb }, Image { path } }
+	ColorProfile { None, Edid, Icc { path } }
+	SamplingFilter { Nearest, Linear, Mitchell, CatmullRom }
+	LatencyMode { Low, Balanced, PowerSave }
+	MirrorScaling { Fit, Fill, Stretch }
+	OutputTransform { Normal, Rotate90, Rotate180, Rotate270, Flipped, Flipped90, Flipped180, Flipped270 }
+	ConnectorProperty {
+		Bool { value },
+		Range { value, min, max },
+		SignedRange { value, min, max },
+		Enum { value, values },
+		Bitmask { value },
+		Blob { data },
+		Object { id },
+	}
+	InputEventPayload {
+		PointerMotion { device, time_usec, x, y, dx, dy, unaccel_dx, unaccel_dy },
+		PointerMotionAbsolute { device, time_usec, x, y, x_transformed, y_transformed },
+		PointerButton { device, time_usec, button, state },
+		PointerAxis { device, time_usec, orientation, delta, delta_discrete, source, phase },
+		Key { device, time_usec, key, state },
+		TouchDown { device, time_usec, contact },
+		TouchUp { device, time_usec, contact_id },
+		TouchMotion { device, time_usec, contact },
+		TouchFrame { time_usec },
+		TouchCancel { time_usec },
+		TableToolProximity { device, time_usec, in_proximity, tool },
+		TabletToolAxis { device, time_usec, tool, axes },
+		TabletToolTip { device, time_usec, tool, state },
+		TabletToolButton { device, time_usec, tool, button, state },
+		TablePadButton { device, time_usec, button, state },
+		TablePadRing { device, time_usec, ring, position, source },
+		TablePadStrip { device, time_usec, strip, position, source },
+		SwitchToggle { device, time_usec, switch, state },
+		GestureSwipeBegin { device, time_usec, fingers },
+		GestureSwipeUpdate { device, time_usec, fingers, dx, dy },
+		GestureSwipeEnd { device, time_usec, cancelled },
+		GesturePinchBegin { device, time_usec, fingers },
+		GesturePinchUpdate { device, time_usec, fingers, dx, dy, scale, rotation },
+		GesturePinchEnd { device, time_usec, cancelled },
+		GestureHoldBegin { device, time_usec, fingers },
+		GestureHoldEnd { device, time_usec, cancelled },
+	}
+}
+
+/// Stays within the planes Shift accepts.
+impl Generate for FramebufferLinkPayload {
+	fn generate(rng: &mut Rng) -> Self {
+		let extra_planes = rng.below(Self::MAX_PLANES as u64) as usize;
+		Self {
+			monitor_id: Generate::generate(rng),
+			width: Generate::generate(rng),
+			height: Generate::generate(rng),
+			stride: Generate::generate(rng),
+			offset: Generate::generate(rng),
+			fourcc: Generate::generate(rng),
+			hdr_metadata: Generate::generate(rng),
+			extra_planes: (0..extra_planes)
+				.map(|_| FramebufferPlane::generate(rng))
+				.collect(),
+			modifier: Generate::generate(rng),
+			scale: Generate::generate(rng),
+			alpha_mode: Generate::generate(rng),
+		}
+	}
+}
+
+/// Every message whose payload is JSON, as `HEADER => Variant(Payload)`, handed to `$then!`.
+macro_rules! json_messages {
+	($then:ident) => {
+		$then! {
+			HELLO => Hello(HelloPayload),
+			AUTH => Auth(AuthPayload),
+			AUTH_OK => AuthOk(AuthOkPayload),
+			AUTH_ERROR => AuthError(AuthErrorPayload),
+			INPUT_EVENT => InputEvent(InputEventPayload),
+			MONITOR_ADDED => MonitorAdded(MonitorAddedPayload),
+			MONITOR_REMOVED => MonitorRemoved(MonitorRemovedPayload),
+			SESSION_SWITCH => SessionSwitch(SessionSwitchPayload),
+			SESSION_CREATE => SessionCreate(SessionCreatePayload),
+			SESSION_CREATED => SessionCreated(SessionCreatedPayload),
+			SESSION_READY => SessionReady(SessionReadyPayload),
+			SESSION_STATE => SessionState(SessionStatePayload),
+			SESSION_ACTIVE => SessionActive(SessionActivePayload),
+			SESSION_AWAKE => SessionAwake(SessionAwakePayload),
+			SESSION_SLEEP => SessionSleep(SessionSleepPayload),
+			RELEASE_BUFFERS => ReleaseBuffers(ReleaseBuffersPayload),
+			ERROR => Error(ErrorPayload),
+			FRAME_TRACE => FrameTrace(FrameTracePayload),
+			FRAME_STATS => FrameStats(FrameStatsPayload),
+			COLOR_FILTER => ColorFilter(ColorFilterPayload),
+			MAGNIFIER => Magnifier(MagnifierPayload),
+			DISPLAY_ADJUST => DisplayAdjust(DisplayAdjustPayload),
+			SCREEN_RECORD => ScreenRecord(ScreenRecordPayload),
+			REMOTE_CONTROL => RemoteControl(RemoteControlPayload),
+			INPUT_INJECT => InputInject(InputEventPayload),
+			REMOTE_CONTROL_STATE => RemoteControlState(RemoteControlStatePayload),
+			IDLE_INHIBIT => IdleInhibit(IdleInhibitPayload),
+			MONITOR_ENABLE => MonitorEnable(MonitorEnablePayload),
+			SET_PRIMARY_MONITOR => SetPrimaryMonitor(SetPrimaryMonitorPayload),
+			PRIMARY_MONITOR => PrimaryMonitor(PrimaryMonitorPayload),
+			RENDER_QUALITY => RenderQuality(RenderQualityPayload),
+			RENDER_CACHE_STATS => RenderCacheStats(RenderCacheStatsPayload),
+			GET_SESSION_METADATA => GetSessionMetadata(GetSessionMetadataPayload),
+			SESSION_METADATA => SessionMetadata(SessionMetadataPayload),
+			OVERLAY_REGIONS => OverlayRegions(OverlayRegionsPayload),
+			MONITOR_PROFILE => MonitorProfile(MonitorProfilePayload),
+			MONITOR_RESIZED => MonitorResized(MonitorResizedPayload),
+			MONITOR_MODE_CHANGED => MonitorModeChanged(MonitorModeChangedPayload),
+			MONITOR_LAYOUT => MonitorLayout(MonitorLayoutPayload),
+			SET_WALLPAPER => SetWallpaper(SetWallpaperPayload),
+			LATENCY_MODE => LatencyMode(LatencyModePayload),
+			SESSION_UPDATE => SessionUpdate(SessionUpdatePayload),
+			AUDIO_CLIENTS => AudioClients(AudioClientsPayload),
+			GET_CONNECTOR_PROPERTIES => GetConnectorProperties(GetConnectorPropertiesPayload),
+			CONNECTOR_PROPERTIES => ConnectorProperties(ConnectorPropertiesPayload),
+			BUFFER_HASH => BufferHash(BufferHashPayload),
+			SESSION_FRAME_STATS => SessionFrameStats(SessionFrameStatsPayload),
+			PRESENTATION_FEEDBACK => PresentationFeedback(PresentationFeedbackPayload),
+			CHANNEL_STATS => ChannelStats(ChannelStatsPayload),
+			SET_COLOR_PROFILE => SetColorProfile(SetColorProfilePayload),
+			SET_VRR => SetVrr(SetVrrPayload),
+			SET_OUTPUT_TRANSFORM => SetOutputTransform(SetOutputTransformPayload),
+			SET_MONITOR_SCALE => SetMonitorScale(SetMonitorScalePayload),
+			SET_MONITOR_MODE => SetMonitorMode(SetMonitorModePayload),
+			SET_MONITOR_POSITION => SetMonitorPosition(SetMonitorPositionPayload),
+			SET_COLOR_TEMPERATURE => SetColorTemperature(SetColorTemperaturePayload),
+			SCREENSHOT => Screenshot(ScreenshotPayload),
+			VIRTUAL_MONITOR_CREATE => VirtualMonitorCreate(VirtualMonitorCreatePayload),
+			VIRTUAL_MONITOR_CREATED => VirtualMonitorCreated(VirtualMonitorCreatedPayload),
+			VIRTUAL_MONITOR_REMOVE => VirtualMonitorRemove(VirtualMonitorRemovePayload),
+			SCREENCAST_START => ScreencastStart(ScreencastStartPayload),
+			SCREENCAST_STARTED => ScreencastStarted(ScreencastStartedPayload),
+			SCREENCAST_STOP => ScreencastStop(ScreencastStopPayload),
+			SCREENCAST_STOPPED => ScreencastStopped(ScreencastStoppedPayload),
+		}
+	};
+}
+
+macro_rules! json_frame_generators {
+	($($header:ident => $variant:ident($payload:ty)),* $(,)?) => {
+		fn json_frame_generators() -> Vec<fn(&mut Rng) -> TabMessageFrame> {
+			vec![$(
+				(|rng: &mut Rng| {
+					TabMessageFrame::json(message_header::$header, <$payload>::generate(rng))
+				}) as fn(&mut Rng) -> TabMessageFrame
+			),*]
+		}
+	};
+}
+json_messages!(json_frame_generators);
+
+/// The `buffer_request` frame a client sends for `payload`.
+pub fn buffer_request_frame(payload: &BufferRequestPayload) -> TabMessageFrame {
+	let mut line = format!("{} {}", payload.monitor_id, payload.buffer as u8);
+	if let Some(present_at) = payload.present_at {
+		line.push_str(&format!(" {present_at}"));
+	}
+	TabMessageFrame::raw(message_header::BUFFER_REQUEST, line)
+}
+
+/// A random well-formed frame of any message type, without fds. Messages that require fds
+/// (`framebuffer_link`, `set_cursor` with an image, `screenshot_ready`) are generated without
+/// them, so they fail to parse like a client forgetting its fds would.
+pub fn random_frame(rng: &mut Rng) -> TabMessageFrame {
+	let json = json_frame_generators();
+	match rng.below(json.len() as u64 + 8) as usize {
+		i if i < json.len() => json[i](rng),
+		i => match i - json.len() {
+			0 => TabMessageFrame::json(
+				message_header::FRAMEBUFFER_LINK,
+				FramebufferLinkPayload::generate(rng),
+			),
+			1 => buffer_request_frame(&BufferRequestPayload::generate(rng)),
+			2 => {
+				let ack = BufferRequestAckPayload::generate(rng);
+				let line = format!("{} {}", ack.monitor_id, ack.buffer as u8);
+				TabMessageFrame::raw(message_header::BUFFER_REQUEST_ACK, line)
+			}
+			3 => {
+				let release = BufferReleasePayload::generate(rng);
+				let line = format!("{} {}", release.monitor_id, release.buffer as u8);
+				TabMessageFrame::raw(message_header::BUFFER_RELEASE, line)
+			}
+			4 => TabMessageFrame::json(message_header::SET_CURSOR, SetCursorPayload::generate(rng)),
+			5 => TabMessageFrame::json(
+				message_header::SCREENSHOT_READY,
+				ScreenshotReadyPayload::generate(rng),
+			),
+			6 => TabMessageFrame::no_payload(message_header::PING),
+			_ => TabMessageFrame::no_payload(message_header::PONG),
+		},
+	}
+}
+
+/// The bytes `frame` occupies on the wire, fds aside.
+pub fn encode(frame: &TabMessageFrame) -> Vec<u8> {
+	let (header, payload) = frame.serialize();
+	format!("{header}\n{payload}\n").into_bytes()
+}
+
+/// `count` encoded random frames from `seed`, e.g. to seed a fuzzing corpus.
+pub fn corpus(seed: u64, count: usize) -> Vec<Vec<u8>> {
+	let mut rng = Rng::new(seed);
+	(0..count)
+		.map(|_| encode(&random_frame(&mut rng)))
+		.collect()
+}
+
+/// Damages `bytes` the way a confused or hostile peer might: flipped bytes, stray newlines,
+/// truncation and repeated spans.
+pub fn mutate(rng: &mut Rng, bytes: &mut Vec<u8>) {
+	for _ in 0..=rng.below(3) {
+		let len = bytes.len() as u64;
+		match rng.below(5) {
+			0 if len > 0 => {
+				let i = rng.below(len) as usize;
+				bytes[i] ^= 1 << rng.below(8);
+			}
+			1 => {
+				let i = rng.below(len + 1) as usize;
+				bytes.insert(i, b'\n');
+			}
+			2 if len > 0 => bytes.truncate(rng.below(len) as usize),
+			3 if len > 0 => {
+				let start = rng.below(len) as usize;
+				let end = start + rng.below(len - start as u64 + 1) as usize;
+				let tail = bytes.split_off(end);
+				bytes.extend_from_within(start..);
+				bytes.extend(tail);
+			}
+			_ => bytes.push(rng.next_u64() as u8),
+		}
+	}
+}
+
+/// Exposes the line parser to the fuzz targets.
+pub fn frame_from_lines(header: &[u8], payload: &[u8]) -> Result<TabMessageFrame, ProtocolError> {
+	TabMessageFrame::from_lines(header, payload, Vec::new())
+}
+
+/// Runs arbitrary bytes through framing and message parsing, as a reader would on a stream
+/// without fds. Panics only when an invariant breaks: every frame is taken from within the
+/// input, and encoding a parsed frame gives back bytes that parse to the same frame.
+pub fn check_bytes(bytes: &[u8]) {
+	let mut rest = bytes;
+	while let Ok(Some((frame, used))) = TabMessageFrame::parse_from_bytes(rest, Vec::new()) {
+		assert!(
+			used > 0 && used <= rest.len(),
+			"consumed {used} of {}",
+			rest.len()
+		);
+		rest = &rest[used..];
+		// Serializing trims trailing whitespace from headers; such frames can't round-trip.
+		if frame.header.0.trim_end() == frame.header.0 {
+			let encoded = encode(&frame);
+			let (reparsed, reused) = TabMessageFrame::parse_from_bytes(&encoded, Vec::new())
+				.expect("re-encoded frame is valid")
+				.expect("re-encoded frame is complete");
+			assert_eq!(reused, encoded.len());
+			assert_eq!(reparsed, frame);
+		}
+		let _ = TabMessage::parse_message_frame(frame);
+	}
+}
+
+/// Sends `frame` through encoding, framing and parsing.
+pub fn round_trip(frame: &TabMessageFrame) -> Result<TabMessage, ProtocolError> {
+	let bytes = encode(frame);
+	let (parsed, used) = TabMessageFrame::parse_from_bytes(&bytes, frame.fds.clone())?
+		.ok_or(ProtocolError::Truncated)?;
+	if used != bytes.len() {
+		return Err(ProtocolError::TrailingData);
+	}
+	TabMessage::parse_message_frame(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::fd::IntoRawFd;
+
+	use serde::Serialize;
+
+	use super::*;
+
+	const SEEDS: u64 = 256;
+
+	fn assert_json_round_trips<T>(header: &str, extract: fn(TabMessage) -> Option<T>)
+	where
+		T: Generate + Serialize + PartialEq + std::fmt::Debug,
+	{
+		for seed in 0..SEEDS {
+			let payload = T::generate(&mut Rng::new(seed));
+			let message = round_trip(&TabMessageFrame::json(header, &payload))
+				.unwrap_or_else(|e| panic!("{header} (seed {seed}) failed to parse: {e}"));
+			let parsed = extract(message)
+				.unwrap_or_else(|| panic!("{header} (seed {seed}) parsed as another message"));
+			assert_eq!(parsed, payload, "{header} (seed {seed})");
+		}
+	}
+
+	macro_rules! assert_all_json_round_trip {
+		($($header:ident => $variant:ident($payload:ty)),* $(,)?) => {
+			$(assert_json_round_trips::<$payload>(message_header::$header, |message| match message {
+				TabMessage::$variant(payload) => Some(payload),
+				_ => None,
+			});)*
+		};
+	}
+
+	#[test]
+	fn json_payloads_round_trip() {
+		json_messages!(assert_all_json_round_trip);
+	}
+
+	#[test]
+	fn framebuffer_link_round_trips_with_fds() {
+		for seed in 0..SEEDS {
+			let payload = FramebufferLinkPayload::generate(&mut Rng::new(seed));
+			let mut frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, &payload);
+			frame.fds = (0..2 * payload.plane_count())
+				.map(|_| std::fs::File::open("/dev/null").unwrap().into_raw_fd())
+				.collect();
+			let Ok(TabMessage::FramebufferLink {
+				payload: parsed,
+				dma_bufs,
+			}) = round_trip(&frame)
+			else {
+				panic!("framebuffer_link (seed {seed}) did not round-trip");
+			};
+			assert_eq!(parsed, payload);
+			assert!(
+				dma_bufs
+					.iter()
+					.all(|fds| fds.len() == payload.plane_count())
+			);
+		}
+		let mut payload = FramebufferLinkPayload::generate(&mut Rng::new(0));
+		payload.extra_planes = vec![FramebufferPlane {
+			stride: 1920,
+			offset: 1920 * 1080,
+		}];
+		let frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, &payload);
+		assert!(matches!(
+			round_trip(&frame),
+			Err(ProtocolError::ExpectedFds {
+				expected: 4,
+				found: 0
+			})
+		));
+		payload.extra_planes = vec![payload.extra_planes[0]; FramebufferLinkPayload::MAX_PLANES];
+		let frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, &payload);
+		assert!(matches!(
+			round_trip(&frame),
+			Err(ProtocolError::InvalidPayload(_))
+		));
+	}
+
+	#[test]
+	fn set_cursor_carries_pixels_only_with_an_image() {
+		let image = CursorImageInfo {
+			width: 24,
+			height: 24,
+			stride: 96,
+			hotspot_x: 4,
+			hotspot_y: 2,
+		};
+		let mut frame = TabMessageFrame::json(
+			message_header::SET_CURSOR,
+			SetCursorPayload { image: Some(image) },
+		);
+		frame.fds = vec![std::fs::File::open("/dev/null").unwrap().into_raw_fd()];
+		let Ok(TabMessage::SetCursor {
+			payload,
+			pixels: Some(_),
+		}) = round_trip(&frame)
+		else {
+			panic!("set_cursor with an image did not round-trip");
+		};
+		assert_eq!(payload.image, Some(image));
+		frame.fds.clear();
+		assert!(matches!(
+			round_trip(&frame),
+			Err(ProtocolError::ExpectedFds {
+				expected: 1,
+				found: 0
+			})
+		));
+		let hidden =
+			TabMessageFrame::json(message_header::SET_CURSOR, SetCursorPayload { image: None });
+		assert!(matches!(
+			round_trip(&hidden),
+			Ok(TabMessage::SetCursor { pixels: None, .. })
+		));
+		let oversized = TabMessageFrame::json(
+			message_header::SET_CURSOR,
+			SetCursorPayload {
+				image: Some(CursorImageInfo {
+					width: 512,
+					stride: 2048,
+					..image
+				}),
+			},
+		);
+		assert!(matches!(
+			round_trip(&oversized),
+			Err(ProtocolError::InvalidPayload(_))
+		));
+	}
+
+	#[test]
+	fn screenshots_arrive_with_their_pixels() {
+		let payload = ScreenshotReadyPayload::generate(&mut Rng::new(3));
+		let mut frame = TabMessageFrame::json(message_header::SCREENSHOT_READY, &payload);
+		assert!(matches!(
+			round_trip(&frame),
+			Err(ProtocolError::ExpectedFds {
+				expected: 1,
+				found: 0
+			})
+		));
+		frame.fds = vec![std::fs::File::open("/dev/null").unwrap().into_raw_fd()];
+		let Ok(TabMessage::ScreenshotReady {
+			payload: parsed, ..
+		}) = round_trip(&frame)
+		else {
+			panic!("screenshot_ready did not round-trip");
+		};
+		assert_eq!(parsed, payload);
+	}
+
+	#[test]
+	fn text_payloads_round_trip() {
+		for seed in 0..SEEDS {
+			let mut rng = Rng::new(seed);
+			let request = BufferRequestPayload::generate(&mut rng);
+			let Ok(TabMessage::BufferRequest {
+				payload,
+				acquire_fence: None,
+			}) = round_trip(&buffer_request_frame(&request))
+			else {
+				panic!("buffer_request (seed {seed}) did not round-trip");
+			};
+			assert_eq!(payload, request);
+
+			let ack = BufferRequestAckPayload::generate(&mut rng);
+			let frame = TabMessageFrame::raw(
+				message_header::BUFFER_REQUEST_ACK,
+				format!("{} {}", ack.monitor_id, ack.buffer as u8),
+			);
+			let Ok(TabMessage::BufferRequestAck(payload)) = round_trip(&frame) else {
+				panic!("buffer_request_ack (seed {seed}) did not round-trip");
+			};
+			assert_eq!(payload, ack);
+
+			let release = BufferReleasePayload::generate(&mut rng);
+			let frame = TabMessageFrame::raw(
+				message_header::BUFFER_RELEASE,
+				format!("{} {}", release.monitor_id, release.buffer as u8),
+			);
+			let Ok(TabMessage::BufferRelease {
+				payload,
+				release_fence: None,
+			}) = round_trip(&frame)
+			else {
+				panic!("buffer_release (seed {seed}) did not round-trip");
+			};
+			assert_eq!(payload, release);
+		}
+		assert!(matches!(
+			round_trip(&TabMessageFrame::no_payload(message_header::PING)),
+			Ok(TabMessage::Ping)
+		));
+	}
+
+	#[test]
+	fn unknown_headers_pass_through() {
+		let frame = TabMessageFrame::raw("from_the_future", "{\"anything\":1}");
+		let Ok(TabMessage::Unknown(parsed)) = round_trip(&frame) else {
+			panic!("unknown header was rejected");
+		};
+		assert_eq!(parsed, frame);
+	}
+
+	#[test]
+	fn frames_split_across_reads_reassemble() {
+		let frames: Vec<_> = corpus(7, 32);
+		let stream: Vec<u8> = frames.concat();
+		let mut rng = Rng::new(7);
+		let mut reader = TabMessageFrameReader::new();
+		let mut offset = 0;
+		while offset < stream.len() {
+			let end = (offset + 1 + rng.below(64) as usize).min(stream.len());
+			reader.feed_chunk(&stream[offset..end], Vec::new()).unwrap();
+			offset = end;
+		}
+		let parsed: Vec<_> = std::iter::from_fn(|| reader.try_pop_ready_frame())
+			.map(|frame| encode(&frame))
+			.collect();
+		assert_eq!(parsed, frames);
+	}
+
+	#[test]
+	fn mutated_frames_keep_parser_invariants() {
+		let mut rng = Rng::new(42);
+		for _ in 0..4096 {
+			let mut bytes = encode(&random_frame(&mut rng));
+			mutate(&mut rng, &mut bytes);
+			check_bytes(&bytes);
+		}
+	}
+
+	/// Parses a frame as a v1 peer sent it.
+	fn captured(header: &str, payload: &str) -> TabMessage {
+		round_trip(&TabMessageFrame::raw(header, payload))
+			.unwrap_or_else(|e| panic!("{header} {payload} failed to parse: {e}"))
+	}
+
+	#[test]
+	fn v1_payloads_still_parse() {
+		let TabMessage::Hello(hello) = captured(
+			message_header::HELLO,
+			r#"{"server":"Shift v0.1.0-alpha","protocol":"tab/v1.0.0"}"#,
+		) else {
+			panic!("hello parsed as another message");
+		};
+		assert_eq!(hello.protocol, "tab/v1.0.0");
+
+		let TabMessage::SessionCreate(create) = captured(
+			message_header::SESSION_CREATE,
+			r#"{"role":"session","display_name":"alice"}"#,
+		) else {
+			panic!("session_create parsed as another message");
+		};
+		assert_eq!(create.role, SessionRole::Session);
+		assert_eq!(create.metadata, SessionMetadata::default());
+
+		let TabMessage::SessionCreated(created) = captured(
+			message_header::SESSION_CREATED,
+			r#"{"session":{"id":"se_2a","role":"session","state":"pending"},"token":"ses_QneY60mnZsYt2c9RgK+1CA=="}"#,
+		) else {
+			panic!("session_created parsed as another message");
+		};
+		assert_eq!(created.session.display_name, None);
+		assert_eq!(created.session.state, SessionLifecycle::Pending);
+
+		let TabMessage::AuthOk(auth_ok) = captured(
+			message_header::AUTH_OK,
+			r#"{"session":{"id":"se_2a","role":"session","display_name":"alice","state":"loading"},"monitors":[{"id":"mon_1","width":1920,"height":1080,"refresh_rate":60,"name":"eDP-1"}]}"#,
+		) else {
+			panic!("auth_ok parsed as another message");
+		};
+		assert_eq!(auth_ok.metadata, SessionMetadata::default());
+		let [monitor] = auth_ok.monitors.as_slice() else {
+			panic!("auth_ok lost its monitor");
+		};
+		assert!(!monitor.primary && monitor.formats.is_empty());
+
+		let TabMessage::InputEvent(InputEventPayload::PointerAxis { phase, .. }) = captured(
+			message_header::INPUT_EVENT,
+			r#"{"kind":"pointer_axis","device":3,"time_usec":1000,"orientation":"Vertical","delta":15.0,"delta_discrete":1,"source":"Wheel"}"#,
+		) else {
+			panic!("pointer_axis parsed as another message");
+		};
+		assert_eq!(phase, AxisPhase::Moved);
+
+		let TabMessage::SessionSwitch(switch) = captured(
+			message_header::SESSION_SWITCH,
+			r#"{"session_id":"se_2a","animation":null,"duration":0.25}"#,
+		) else {
+			panic!("session_switch parsed as another message");
+		};
+		assert_eq!(switch.duration, Duration::from_millis(250));
+
+		let TabMessage::Error(error) = captured(
+			message_header::ERROR,
+			r#"{"code":"protocol_violation","message":"unexpected frame"}"#,
+		) else {
+			panic!("error parsed as another message");
+		};
+		assert_eq!(error.permission, None);
+	}
+
+	#[test]
+	fn newer_payloads_parse_with_unknown_values() {
+		let TabMessage::SessionState(state) = captured(
+			message_header::SESSION_STATE,
+			r#"{"session":{"id":"se_7","role":"kiosk","display_name":null,"state":"hibernating","since":12},"reason":"idle"}"#,
+		) else {
+			panic!("session_state parsed as another message");
+		};
+		assert_eq!(state.session.role, SessionRole::Unknown);
+		assert_eq!(state.session.state, SessionLifecycle::Unknown);
+
+		let TabMessage::InputEvent(event) = captured(
+			message_header::INPUT_EVENT,
+			r#"{"kind":"pointer_warp","device":1,"time_usec":5,"x":1.0,"y":2.0}"#,
+		) else {
+			panic!("input_event parsed as another message");
+		};
+		assert_eq!(event, InputEventPayload::Unknown);
+	}
+
+	#[test]
+	fn compatibility_follows_major_version() {
+		assert!(crate::is_compatible(crate::PROTOCOL_VERSION));
+		let major = env!("CARGO_PKG_VERSION_MAJOR");
+		assert!(crate::is_compatible(&format!("tab/v{major}.99.0")));
+		assert!(!crate::is_compatible(&format!("tab/v{major}1.0.0")));
+		assert!(!crate::is_compatible("tab/v1.0.0"));
+		assert!(!crate::is_compatible("wayland/v3.0.0"));
+	}
+}
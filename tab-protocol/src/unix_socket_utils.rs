@@ -2,6 +2,7 @@ use nix::sys::socket::{
 	AddressFamily, Backlog, SockFlag, SockType, UnixAddr, accept, bind, connect, listen, socket,
 };
 use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
 /// Bind a Unix seqpacket listener at the given path (removes any stale socket file).
@@ -27,7 +28,8 @@ pub fn accept_seqpacket(listener_fd: RawFd) -> Result<UnixStream, nix::Error> {
 	Ok(unsafe { UnixStream::from_raw_fd(fd.into_raw_fd()) })
 }
 
-/// Connect to a Unix seqpacket socket at the given path, returning it as a `UnixStream`.
+/// Connect to a Unix seqpacket socket at the given path, returning it as a `UnixStream`. A path
+/// starting with `@` names an abstract socket.
 pub fn connect_seqpacket(path: impl AsRef<Path>) -> Result<UnixStream, nix::Error> {
 	let fd = socket(
 		AddressFamily::Unix,
@@ -35,7 +37,11 @@ pub fn connect_seqpacket(path: impl AsRef<Path>) -> Result<UnixStream, nix::Erro
 		SockFlag::empty(),
 		None,
 	)?;
-	let addr = UnixAddr::new(path.as_ref())?;
+	let path = path.as_ref().as_os_str().as_bytes();
+	let addr = match path.strip_prefix(b"@") {
+		Some(name) => UnixAddr::new_abstract(name)?,
+		None => UnixAddr::new(path)?,
+	};
 	connect(fd.as_raw_fd(), &addr)?;
 	Ok(unsafe { UnixStream::from_raw_fd(fd.into_raw_fd()) })
 }
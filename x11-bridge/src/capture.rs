@@ -0,0 +1,149 @@
+//! Reads the X root window through MIT-SHM whenever the Damage extension reports a change.
+
+use std::{
+	io,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	ptr::NonNull,
+};
+
+use anyhow::{Context, bail};
+use x11rb::{
+	connection::Connection,
+	protocol::{
+		Event,
+		damage::{self, ConnectionExt as _, ReportLevel},
+		shm::{self, ConnectionExt as _},
+		xproto::{ImageFormat, Screen, Window},
+	},
+	rust_connection::RustConnection,
+};
+
+pub struct Capture {
+	root: Window,
+	width: u16,
+	height: u16,
+	segment: shm::Seg,
+	damage: damage::Damage,
+	pixels: NonNull<u8>,
+	len: usize,
+	dirty: bool,
+}
+
+impl Capture {
+	pub fn new(conn: &RustConnection, screen: &Screen) -> anyhow::Result<Self> {
+		if screen.root_depth != 24 {
+			bail!(
+				"X screen has depth {}, only 24 is supported",
+				screen.root_depth
+			);
+		}
+		let shm_version = conn
+			.shm_query_version()?
+			.reply()
+			.context("X server lacks MIT-SHM")?;
+		if (shm_version.major_version, shm_version.minor_version) < (1, 2) {
+			bail!("X server lacks MIT-SHM 1.2 fd passing");
+		}
+		conn
+			.damage_query_version(1, 1)?
+			.reply()
+			.context("X server lacks the Damage extension")?;
+
+		let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+		let len = usize::from(width) * usize::from(height) * 4;
+		let memfd = memfd(len)?;
+		let pixels = unsafe {
+			libc::mmap(
+				std::ptr::null_mut(),
+				len,
+				libc::PROT_READ,
+				libc::MAP_SHARED,
+				memfd.as_raw_fd(),
+				0,
+			)
+		};
+		if pixels == libc::MAP_FAILED {
+			return Err(io::Error::last_os_error()).context("failed to map the capture buffer");
+		}
+		let pixels = NonNull::new(pixels.cast::<u8>()).expect("mmap returned null");
+
+		let segment = conn.generate_id()?;
+		// The mapping outlives the fd, so the server can take it.
+		conn.shm_attach_fd(segment, memfd, false)?;
+		let damage = conn.generate_id()?;
+		conn.damage_create(damage, screen.root, ReportLevel::NON_EMPTY)?;
+		conn.flush()?;
+		Ok(Self {
+			root: screen.root,
+			width,
+			height,
+			segment,
+			damage,
+			pixels,
+			len,
+			dirty: true,
+		})
+	}
+
+	/// Whether the screen changed since the last [`Self::grab`].
+	pub fn is_dirty(&self) -> bool {
+		self.dirty
+	}
+
+	/// Returns `true` when `event` was damage on the root window.
+	pub fn handle_event(&mut self, conn: &RustConnection, event: &Event) -> anyhow::Result<bool> {
+		let Event::DamageNotify(notify) = event else {
+			return Ok(false);
+		};
+		if notify.damage != self.damage {
+			return Ok(false);
+		}
+		conn.damage_subtract(self.damage, x11rb::NONE, x11rb::NONE)?;
+		self.dirty = true;
+		Ok(true)
+	}
+
+	/// Copies the root window into the shared segment. Rows are `width * 4` bytes of
+	/// little-endian XRGB8888.
+	pub fn grab(&mut self, conn: &RustConnection) -> anyhow::Result<&[u8]> {
+		conn
+			.shm_get_image(
+				self.root,
+				0,
+				0,
+				self.width,
+				self.height,
+				!0,
+				ImageFormat::Z_PIXMAP.into(),
+				self.segment,
+				0,
+			)?
+			.reply()?;
+		self.dirty = false;
+		Ok(unsafe { std::slice::from_raw_parts(self.pixels.as_ptr(), self.len) })
+	}
+
+	pub fn size(&self) -> (u32, u32) {
+		(u32::from(self.width), u32::from(self.height))
+	}
+}
+
+impl Drop for Capture {
+	fn drop(&mut self) {
+		unsafe {
+			libc::munmap(self.pixels.as_ptr().cast(), self.len);
+		}
+	}
+}
+
+fn memfd(len: usize) -> anyhow::Result<OwnedFd> {
+	let fd = unsafe { libc::memfd_create(c"shift-x11-bridge".as_ptr(), libc::MFD_CLOEXEC) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error()).context("memfd_create failed");
+	}
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } < 0 {
+		return Err(io::Error::last_os_error()).context("failed to size the capture buffer");
+	}
+	Ok(fd)
+}
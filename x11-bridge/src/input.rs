@@ -0,0 +1,183 @@
+//! Replays Shift input on the nested X server through XTEST.
+//!
+//! Keys keep their evdev codes (X keycode = evdev + 8, as with the `evdev` XKB rules), pointer
+//! motion stays relative so X applies its own acceleration, and touch drives the pointer with
+//! the first contact.
+
+use tab_protocol::{AxisOrientation, ButtonState, InputEventPayload, KeyState};
+use x11rb::{
+	errors::ConnectionError,
+	protocol::{
+		xproto::{
+			BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+			MOTION_NOTIFY_EVENT, Window,
+		},
+		xtest::ConnectionExt as _,
+	},
+	rust_connection::RustConnection,
+};
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+/// Absolute coordinates arrive scaled to `0..=65535` of the output.
+const ABSOLUTE_RANGE: f64 = 65535.0;
+/// libinput reports wheel deltas in degrees; one detent is 15.
+const DEGREES_PER_DETENT: f64 = 15.0;
+
+pub struct InputForwarder {
+	root: Window,
+	width: f64,
+	height: f64,
+	/// Sub-pixel motion and partial wheel detents carried to the next event.
+	motion_remainder: (f64, f64),
+	scroll_remainder: (f64, f64),
+	touch_contact: Option<i32>,
+}
+
+impl InputForwarder {
+	pub fn new(root: Window, (width, height): (u32, u32)) -> Self {
+		Self {
+			root,
+			width: f64::from(width),
+			height: f64::from(height),
+			motion_remainder: (0.0, 0.0),
+			scroll_remainder: (0.0, 0.0),
+			touch_contact: None,
+		}
+	}
+
+	pub fn forward(
+		&mut self,
+		conn: &RustConnection,
+		event: &InputEventPayload,
+	) -> Result<(), ConnectionError> {
+		match event {
+			InputEventPayload::PointerMotion { dx, dy, .. } => {
+				let x = self.motion_remainder.0 + dx;
+				let y = self.motion_remainder.1 + dy;
+				self.motion_remainder = (x.fract(), y.fract());
+				if x.trunc() != 0.0 || y.trunc() != 0.0 {
+					conn.xtest_fake_input(
+						MOTION_NOTIFY_EVENT,
+						1,
+						x11rb::CURRENT_TIME,
+						x11rb::NONE,
+						x.trunc() as i16,
+						y.trunc() as i16,
+						0,
+					)?;
+				}
+			}
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				..
+			} => self.move_to(conn, *x_transformed, *y_transformed)?,
+			InputEventPayload::PointerButton { button, state, .. } => {
+				if let Some(button) = x_button(*button) {
+					self.button(conn, button, *state == ButtonState::Pressed)?;
+				}
+			}
+			InputEventPayload::PointerAxis {
+				orientation,
+				delta,
+				delta_discrete,
+				..
+			} => {
+				let remainder = match orientation {
+					AxisOrientation::Vertical => &mut self.scroll_remainder.1,
+					AxisOrientation::Horizontal => &mut self.scroll_remainder.0,
+				};
+				let detents = match delta_discrete {
+					Some(steps) => f64::from(*steps),
+					None => {
+						*remainder += delta / DEGREES_PER_DETENT;
+						let whole = remainder.trunc();
+						*remainder -= whole;
+						whole
+					}
+				};
+				let (negative, positive) = match orientation {
+					AxisOrientation::Vertical => (4, 5),
+					AxisOrientation::Horizontal => (6, 7),
+				};
+				let button = if detents < 0.0 { negative } else { positive };
+				for _ in 0..detents.abs() as u32 {
+					self.button(conn, button, true)?;
+					self.button(conn, button, false)?;
+				}
+			}
+			InputEventPayload::Key { key, state, .. } => {
+				let Some(keycode) = key.checked_add(8).and_then(|code| u8::try_from(code).ok()) else {
+					return Ok(());
+				};
+				let kind = match state {
+					KeyState::Pressed => KEY_PRESS_EVENT,
+					KeyState::Released => KEY_RELEASE_EVENT,
+				};
+				conn.xtest_fake_input(kind, keycode, x11rb::CURRENT_TIME, self.root, 0, 0, 0)?;
+			}
+			InputEventPayload::TouchDown { contact, .. } if self.touch_contact.is_none() => {
+				self.touch_contact = Some(contact.id);
+				self.move_to(conn, contact.x_transformed, contact.y_transformed)?;
+				self.button(conn, 1, true)?;
+			}
+			InputEventPayload::TouchMotion { contact, .. } if self.touch_contact == Some(contact.id) => {
+				self.move_to(conn, contact.x_transformed, contact.y_transformed)?;
+			}
+			InputEventPayload::TouchUp { contact_id, .. } if self.touch_contact == Some(*contact_id) => {
+				self.touch_contact = None;
+				self.button(conn, 1, false)?;
+			}
+			InputEventPayload::TouchCancel { .. } if self.touch_contact.take().is_some() => {
+				self.button(conn, 1, false)?;
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn move_to(&self, conn: &RustConnection, x: f64, y: f64) -> Result<(), ConnectionError> {
+		let x = (x / ABSOLUTE_RANGE).clamp(0.0, 1.0) * (self.width - 1.0);
+		let y = (y / ABSOLUTE_RANGE).clamp(0.0, 1.0) * (self.height - 1.0);
+		conn.xtest_fake_input(
+			MOTION_NOTIFY_EVENT,
+			0,
+			x11rb::CURRENT_TIME,
+			self.root,
+			x.round() as i16,
+			y.round() as i16,
+			0,
+		)?;
+		Ok(())
+	}
+
+	fn button(
+		&self,
+		conn: &RustConnection,
+		button: u8,
+		pressed: bool,
+	) -> Result<(), ConnectionError> {
+		let kind = if pressed {
+			BUTTON_PRESS_EVENT
+		} else {
+			BUTTON_RELEASE_EVENT
+		};
+		conn.xtest_fake_input(kind, button, x11rb::CURRENT_TIME, self.root, 0, 0, 0)?;
+		Ok(())
+	}
+}
+
+fn x_button(evdev: u32) -> Option<u8> {
+	Some(match evdev {
+		BTN_LEFT => 1,
+		BTN_MIDDLE => 2,
+		BTN_RIGHT => 3,
+		BTN_SIDE => 8,
+		BTN_EXTRA => 9,
+		_ => return None,
+	})
+}
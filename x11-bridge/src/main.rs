@@ -0,0 +1,238 @@
+//! Runs legacy X11 applications as a Shift session.
+//!
+//! The bridge starts a rootful nested X server sized like the primary monitor, copies its root
+//! window into the session's dmabuf swapchain whenever it is damaged, and replays the session's
+//! input on it through XTEST:
+//!
+//! ```text
+//! SHIFT_SESSION_TOKEN=... shift-x11-bridge -- xterm
+//! ```
+//!
+//! The bridge exits when the X server or the given command exits. Frames are copied on the CPU
+//! (MIT-SHM into a mapped buffer), which is fine for desktop applications but not for video.
+
+mod capture;
+mod input;
+mod xserver;
+
+use std::{
+	cell::RefCell,
+	collections::VecDeque,
+	os::fd::AsRawFd,
+	process::{Child, Command},
+	rc::Rc,
+	time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tab_client::{InputEvent, RenderEvent, SyncFence, TabClient, TabClientConfig, TabSwapchain};
+use tab_protocol::{BufferIndex, InputEventPayload};
+use tracing::{error, info, warn};
+use tracing_subscriber::{EnvFilter, fmt};
+use x11rb::{connection::Connection, protocol::xtest::ConnectionExt as _};
+
+use crate::{capture::Capture, input::InputForwarder, xserver::XServer};
+
+const DRM_FORMAT_XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+const DRM_FORMAT_ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+/// How often the bridge checks on the X server and the child when nothing else happens.
+const IDLE_POLL: Duration = Duration::from_millis(500);
+
+enum Pending {
+	Input(InputEventPayload),
+	Released(BufferIndex, Option<std::sync::Arc<SyncFence>>),
+	Failed(String),
+}
+
+fn main() {
+	fmt()
+		.with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+		.init();
+	if let Err(err) = run() {
+		error!(error = %format!("{err:#}"), "x11 bridge failed");
+		std::process::exit(1);
+	}
+}
+
+fn run() -> anyhow::Result<()> {
+	let command: Vec<String> = std::env::args()
+		.skip_while(|arg| arg != "--")
+		.skip(1)
+		.collect();
+	let token = std::env::var("SHIFT_SESSION_TOKEN").context("missing SHIFT_SESSION_TOKEN")?;
+	let mut client = TabClient::connect(TabClientConfig::new(token))?;
+
+	let monitor = client
+		.monitors()
+		.find(|monitor| monitor.info.primary)
+		.or_else(|| client.monitors().next())
+		.context("Shift reported no monitors")?
+		.info
+		.clone();
+	let mut swapchain = client.create_swapchain(monitor.id)?;
+	let fourcc = swapchain.buffers[0].fourcc() as u32;
+	if fourcc != DRM_FORMAT_XRGB8888 && fourcc != DRM_FORMAT_ARGB8888 {
+		bail!(
+			"monitor {} needs fourcc {fourcc:#x}, only XR24 and AR24 are supported",
+			monitor.id
+		);
+	}
+
+	let mut server = XServer::spawn(monitor.width as u32, monitor.height as u32)?;
+	let (conn, screen) =
+		x11rb::connect(Some(server.display())).context("failed to connect to the X server")?;
+	let screen = conn.setup().roots[screen].clone();
+	conn
+		.xtest_get_version(2, 2)?
+		.reply()
+		.context("X server lacks XTEST")?;
+	let mut capture = Capture::new(&conn, &screen)?;
+	let mut input = InputForwarder::new(screen.root, capture.size());
+	info!(display = server.display(), monitor = %monitor.id, "nested X server ready");
+
+	let mut child = match command.split_first() {
+		Some((program, args)) => Some(
+			Command::new(program)
+				.args(args)
+				.env("DISPLAY", server.display())
+				.env_remove("SHIFT_SESSION_TOKEN")
+				.spawn()
+				.with_context(|| format!("failed to start {program}"))?,
+		),
+		None => None,
+	};
+
+	let pending = Rc::new(RefCell::new(VecDeque::new()));
+	let queue = Rc::clone(&pending);
+	client.on_input_event(move |InputEvent::Event(event)| {
+		queue.borrow_mut().push_back(Pending::Input(event.clone()));
+	});
+	let queue = Rc::clone(&pending);
+	let monitor_id = monitor.id;
+	client.on_render_event(move |event| match event {
+		RenderEvent::BufferReleased {
+			monitor_id: released_on,
+			buffer,
+			release_fence,
+		} if *released_on == monitor_id => {
+			queue
+				.borrow_mut()
+				.push_back(Pending::Released(*buffer, release_fence.clone()));
+		}
+		RenderEvent::Failed {
+			monitor_id: failed_on,
+			reason,
+		} if *failed_on == monitor_id => {
+			queue
+				.borrow_mut()
+				.push_back(Pending::Failed(reason.clone()));
+		}
+		_ => {}
+	});
+
+	let mut ready = false;
+	let x_fd = conn.stream().as_raw_fd();
+	loop {
+		if server.exited() {
+			info!("X server exited");
+			return Ok(());
+		}
+		if let Some(status) = child.as_mut().map(Child::try_wait).transpose()?.flatten() {
+			info!(%status, "X client exited");
+			return Ok(());
+		}
+
+		if capture.is_dirty()
+			&& present_frame(&mut client, &mut swapchain, &conn, &mut capture)?
+			&& !ready
+		{
+			client.send_ready()?;
+			ready = true;
+		}
+		conn.flush()?;
+
+		let [tab_socket, drm] = client.poll_fds();
+		let mut fds = [tab_socket, drm, x_fd].map(|fd| libc::pollfd {
+			fd,
+			events: libc::POLLIN,
+			revents: 0,
+		});
+		let rc = unsafe {
+			libc::poll(
+				fds.as_mut_ptr(),
+				fds.len() as libc::nfds_t,
+				IDLE_POLL.as_millis() as libc::c_int,
+			)
+		};
+		if rc < 0 {
+			let err = std::io::Error::last_os_error();
+			if err.kind() == std::io::ErrorKind::Interrupted {
+				continue;
+			}
+			return Err(err).context("poll failed");
+		}
+
+		client.dispatch_events()?;
+		while let Some(event) = conn.poll_for_event()? {
+			capture.handle_event(&conn, &event)?;
+		}
+		let drained: Vec<_> = pending.borrow_mut().drain(..).collect();
+		for event in drained {
+			match event {
+				Pending::Input(event) => input.forward(&conn, &event)?,
+				Pending::Released(buffer, fence) => {
+					if let Some(fence) = fence
+						&& let Err(err) = fence.wait(None)
+					{
+						warn!(error = %err, "failed to wait for the release fence");
+					}
+					swapchain.mark_released(buffer);
+				}
+				Pending::Failed(reason) => bail!("monitor {} went away: {reason}", monitor.id),
+			}
+		}
+	}
+}
+
+/// Copies the X screen into the next free buffer and presents it. Returns `false` when both
+/// buffers are still held by Shift; the damage stays pending until one is released.
+fn present_frame(
+	client: &mut TabClient,
+	swapchain: &mut TabSwapchain,
+	conn: &x11rb::rust_connection::RustConnection,
+	capture: &mut Capture,
+) -> anyhow::Result<bool> {
+	let Some((_, index)) = swapchain.acquire_next() else {
+		return Ok(false);
+	};
+	let (width, height) = capture.size();
+	let row_len = width as usize * 4;
+	let pixels = capture.grab(conn)?;
+	let buffer = &mut swapchain.buffers[index as usize];
+	let opaque = buffer.fourcc() as u32 == DRM_FORMAT_ARGB8888;
+	let copied = buffer.write_with(|dst, stride| {
+		let stride = stride as usize;
+		for (y, src) in pixels
+			.chunks_exact(row_len)
+			.take(height as usize)
+			.enumerate()
+		{
+			let dst = &mut dst[y * stride..][..row_len];
+			dst.copy_from_slice(src);
+			// X leaves the padding byte of depth 24 undefined.
+			if opaque {
+				dst
+					.iter_mut()
+					.skip(3)
+					.step_by(4)
+					.for_each(|alpha| *alpha = 0xff);
+			}
+		}
+	});
+	if let Err(err) = copied {
+		swapchain.rollback();
+		return Err(err).context("failed to map the swapchain buffer");
+	}
+	client.present(swapchain, index, None, None)?;
+	Ok(true)
+}
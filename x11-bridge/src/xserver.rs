@@ -0,0 +1,105 @@
+//! Starts the nested X server and waits until it accepts connections.
+
+use std::{
+	fs::File,
+	io::{self, Read},
+	os::{
+		fd::{AsRawFd, FromRawFd, OwnedFd},
+		unix::process::CommandExt,
+	},
+	process::{Child, Command},
+};
+
+use anyhow::{Context, bail};
+
+/// The fd the X server writes its display number to once it is ready (`-displayfd`).
+const DISPLAY_FD: libc::c_int = 3;
+
+pub struct XServer {
+	child: Child,
+	display: String,
+}
+
+impl XServer {
+	/// Runs `SHIFT_X11_SERVER` (default `Xvfb`) as a rootful server of `width`x`height`, on the
+	/// first free display. Extra arguments come from `SHIFT_X11_SERVER_ARGS`.
+	pub fn spawn(width: u32, height: u32) -> anyhow::Result<Self> {
+		let program = std::env::var("SHIFT_X11_SERVER").unwrap_or_else(|_| "Xvfb".into());
+		let extra_args = std::env::var("SHIFT_X11_SERVER_ARGS").unwrap_or_default();
+		let (read_end, write_end) = pipe()?;
+		let write_fd = write_end.as_raw_fd();
+		let mut command = Command::new(&program);
+		command
+			.args(["-displayfd", &DISPLAY_FD.to_string()])
+			.args(["-screen", "0", &format!("{width}x{height}x24")])
+			.args([
+				"-nolisten",
+				"tcp",
+				"+extension",
+				"MIT-SHM",
+				"+extension",
+				"XTEST",
+			])
+			.args(extra_args.split_whitespace());
+		// SAFETY: only async-signal-safe calls between fork and exec.
+		unsafe {
+			command.pre_exec(move || {
+				// dup2 onto itself keeps `O_CLOEXEC`, so clear it by hand in that case.
+				let result = if write_fd == DISPLAY_FD {
+					libc::fcntl(DISPLAY_FD, libc::F_SETFD, 0)
+				} else {
+					libc::dup2(write_fd, DISPLAY_FD)
+				};
+				if result < 0 {
+					return Err(io::Error::last_os_error());
+				}
+				Ok(())
+			});
+		}
+		let child = command
+			.spawn()
+			.with_context(|| format!("failed to start {program}"))?;
+		drop(write_end);
+		let mut number = String::new();
+		File::from(read_end)
+			.read_to_string(&mut number)
+			.context("failed to read the X display number")?;
+		let number = number.trim();
+		if number.is_empty() {
+			bail!("{program} exited before reporting its display");
+		}
+		Ok(Self {
+			child,
+			display: format!(":{number}"),
+		})
+	}
+
+	pub fn display(&self) -> &str {
+		&self.display
+	}
+
+	/// `true` once the server process is gone.
+	pub fn exited(&mut self) -> bool {
+		!matches!(self.child.try_wait(), Ok(None))
+	}
+}
+
+impl Drop for XServer {
+	fn drop(&mut self) {
+		if self.exited() {
+			return;
+		}
+		unsafe {
+			libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM);
+		}
+		let _ = self.child.wait();
+	}
+}
+
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+	let mut fds = [0; 2];
+	if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}